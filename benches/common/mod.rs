@@ -0,0 +1,51 @@
+//! Synthetic activity builders shared by the benchmarks.
+
+// Each bench target uses only some of these helpers.
+#![allow(dead_code)]
+
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataRecord, encode_records, from_bytes};
+
+const FIXTURE: &str = "test/fixtures/activity.fit";
+
+/// Record counts used for the scaling benchmarks. The 1M-record size needs a
+/// few GB of memory and only runs when `RUSTYFIT_BENCH_LARGE` is set.
+pub fn record_counts() -> Vec<usize> {
+    let mut counts = vec![10_000, 100_000];
+    if std::env::var_os("RUSTYFIT_BENCH_LARGE").is_some() {
+        counts.push(1_000_000);
+    }
+    counts
+}
+
+/// Decoded records with the fixture's `Record` messages repeated until
+/// `record_count` of them exist; every other message is kept once.
+pub fn synthetic_records(record_count: usize) -> Vec<FitDataRecord> {
+    let bytes = std::fs::read(FIXTURE).expect("fixture should be present");
+    let fixture = from_bytes(&bytes).expect("fixture should decode");
+    let samples: Vec<&FitDataRecord> = fixture
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .collect();
+    assert!(!samples.is_empty(), "fixture has no record messages");
+
+    let mut records: Vec<FitDataRecord> = fixture
+        .iter()
+        .filter(|record| !matches!(record.kind(), MesgNum::Record))
+        .cloned()
+        .collect();
+    records.reserve(record_count);
+    records.extend(
+        samples
+            .iter()
+            .cycle()
+            .take(record_count)
+            .map(|&record| record.clone()),
+    );
+    records
+}
+
+/// [`synthetic_records`] encoded back to FIT bytes.
+pub fn synthetic_bytes(record_count: usize) -> Vec<u8> {
+    encode_records(&synthetic_records(record_count)).expect("synthetic records should encode")
+}
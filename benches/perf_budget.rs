@@ -0,0 +1,47 @@
+//! Performance budget check for CI: `cargo bench --bench perf_budget`.
+//!
+//! Processes a synthetic 100k-record file end to end and exits non-zero when
+//! the median of a few runs exceeds the budget. Override the budget with
+//! `RUSTYFIT_PERF_BUDGET_MS` on slower runners.
+
+mod common;
+
+use rustyfit::processing::{ProcessingOptions, process_fit_bytes};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const RECORD_COUNT: usize = 100_000;
+const RUNS: usize = 5;
+const DEFAULT_BUDGET_MS: u64 = 3_000;
+
+fn main() -> ExitCode {
+    let budget = Duration::from_millis(
+        std::env::var("RUSTYFIT_PERF_BUDGET_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_BUDGET_MS),
+    );
+    let bytes = common::synthetic_bytes(RECORD_COUNT);
+    let options = ProcessingOptions {
+        smooth_speed: true,
+        flatten_indoor: true,
+        ..ProcessingOptions::default()
+    };
+
+    let mut timings: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let started = Instant::now();
+            process_fit_bytes(&bytes, &options).expect("synthetic file should process");
+            started.elapsed()
+        })
+        .collect();
+    timings.sort();
+    let median = timings[RUNS / 2];
+
+    println!("process_fit_bytes on {RECORD_COUNT} records: median {median:?} (budget {budget:?})");
+    if median > budget {
+        eprintln!("performance budget exceeded");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
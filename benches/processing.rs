@@ -0,0 +1,55 @@
+//! Scaling benchmarks for parse, preprocess, re-encode, and the file CRC.
+//!
+//! Run with `cargo bench --bench processing`; set `RUSTYFIT_BENCH_LARGE=1` to
+//! include the 1M-record file.
+
+mod common;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use fitparser::encode_records;
+use rustyfit::processing::crc::fit_crc;
+use rustyfit::processing::preprocess::preprocess_fit;
+use rustyfit::processing::{ProcessingOptions, parse_fit};
+use std::hint::black_box;
+
+fn all_transforms() -> ProcessingOptions {
+    ProcessingOptions {
+        smooth_speed: true,
+        flatten_indoor: true,
+        strip_device_info: true,
+        ..ProcessingOptions::default()
+    }
+}
+
+fn processing_benches(c: &mut Criterion) {
+    let options = all_transforms();
+
+    for count in common::record_counts() {
+        let bytes = common::synthetic_bytes(count);
+        let records = parse_fit(&bytes).expect("synthetic bytes should decode");
+        let processed = preprocess_fit(&records, &options)
+            .expect("preprocessing succeeds")
+            .records;
+
+        let mut group = c.benchmark_group(format!("{count}_records"));
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(count as u64));
+
+        group.bench_function(BenchmarkId::new("parse", count), |b| {
+            b.iter(|| parse_fit(black_box(&bytes)))
+        });
+        group.bench_function(BenchmarkId::new("preprocess", count), |b| {
+            b.iter(|| preprocess_fit(black_box(&records), &options))
+        });
+        group.bench_function(BenchmarkId::new("encode", count), |b| {
+            b.iter(|| encode_records(black_box(&processed)))
+        });
+        group.bench_function(BenchmarkId::new("crc", count), |b| {
+            b.iter(|| fit_crc(black_box(&bytes)))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, processing_benches);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+/* C interface to the RustyFit processing engine.
+ * Build with `cargo build --release --no-default-features --features ffi`
+ * and link against the resulting `librustyfit` shared or static library. */
+#ifndef RUSTYFIT_H
+#define RUSTYFIT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes returned by rustyfit_process. */
+typedef uint32_t RustyFitStatus;
+#define RUSTYFIT_OK ((RustyFitStatus)0)
+#define RUSTYFIT_NULL_POINTER ((RustyFitStatus)1)
+#define RUSTYFIT_INVALID_OPTIONS ((RustyFitStatus)2)
+#define RUSTYFIT_PROCESSING_FAILED ((RustyFitStatus)3)
+#define RUSTYFIT_SUMMARY_FAILED ((RustyFitStatus)4)
+/* The library hit an internal error; `out` is left empty. */
+#define RUSTYFIT_PANICKED ((RustyFitStatus)5)
+
+typedef struct RustyFitOutput {
+  uint8_t *bytes;
+  size_t bytes_len;
+  /* NUL-terminated JSON object with `summary`, `devices`, and `warnings`. */
+  char *summary_json;
+} RustyFitOutput;
+
+/* Process `len` bytes of FIT data. `options_json` may be NULL for defaults,
+ * e.g. "{\"smooth_speed\": true}". Free a successful `out` with
+ * rustyfit_output_free. */
+RustyFitStatus rustyfit_process(const uint8_t *data, size_t len,
+                                const char *options_json, RustyFitOutput *out);
+
+void rustyfit_output_free(RustyFitOutput *out);
+
+/* Static description of a status code, including unknown ones; do not free. */
+const char *rustyfit_status_message(RustyFitStatus status);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RUSTYFIT_H */
@@ -0,0 +1,251 @@
+//! Optional accounts for shared deployments, each with its own upload and
+//! storage quota.
+//!
+//! Accounts are listed in a JSON file named by `RUSTYFIT_ACCOUNTS_FILE`:
+//!
+//! ```json
+//! [{ "id": "alice", "token": "…", "files_per_day": 50, "max_stored_bytes": 104857600 }]
+//! ```
+//!
+//! When the file is configured, processing endpoints require an account token
+//! (as a bearer token or the HTTP Basic password, like the admin token). Without
+//! it the server stays single-user and nothing is metered.
+
+use crate::admin::presented_token;
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Window over which `files_per_day` is counted.
+pub const QUOTA_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Limits for one account; a missing limit is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AccountQuota {
+    pub files_per_day: Option<usize>,
+    /// Processed bytes held in downloads at once.
+    pub max_stored_bytes: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct AccountEntry {
+    id: String,
+    token: String,
+    #[serde(flatten)]
+    quota: AccountQuota,
+}
+
+/// A signed-in account.
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub id: String,
+    token: blake3::Hash,
+    pub quota: AccountQuota,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AccountsError {
+    #[error("failed to read accounts {path}: {1}", path = .0.display())]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid accounts file {path}: {1}", path = .0.display())]
+    Invalid(PathBuf, serde_json::Error),
+    #[error("account {0} reuses another account's token")]
+    DuplicateToken(String),
+}
+
+/// Why an upload was refused.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum QuotaExceeded {
+    #[error("Daily limit of {limit} files reached")]
+    FilesPerDay { limit: usize, retry_after: Duration },
+    #[error("Stored downloads use {used} of {limit} bytes; wait for older downloads to expire")]
+    Storage { limit: u64, used: u64 },
+}
+
+/// Current usage of one account, shown on `/account`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountUsage {
+    pub account: String,
+    pub files_today: usize,
+    pub stored_bytes: u64,
+    #[serde(flatten)]
+    pub quota: AccountQuota,
+}
+
+/// Configured accounts plus the uploads each made within [`QUOTA_WINDOW`].
+#[derive(Clone, Default)]
+pub struct AccountStore {
+    accounts: Arc<Vec<Account>>,
+    uploads: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>,
+}
+
+impl fmt::Debug for AccountStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccountStore")
+            .field("accounts", &self.accounts.len())
+            .finish()
+    }
+}
+
+impl AccountStore {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let entries: Vec<AccountEntry> = serde_json::from_str(json)?;
+        let accounts = entries
+            .into_iter()
+            .map(|entry| Account {
+                id: entry.id,
+                token: blake3::hash(entry.token.as_bytes()),
+                quota: entry.quota,
+            })
+            .collect();
+        Ok(Self {
+            accounts: Arc::new(accounts),
+            uploads: Arc::default(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, AccountsError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|err| AccountsError::Io(path.to_path_buf(), err))?;
+        let store = Self::from_json(&json)
+            .map_err(|err| AccountsError::Invalid(path.to_path_buf(), err))?;
+        for (index, account) in store.accounts.iter().enumerate() {
+            if store.accounts[..index]
+                .iter()
+                .any(|other| other.token == account.token)
+            {
+                return Err(AccountsError::DuplicateToken(account.id.clone()));
+            }
+        }
+        Ok(store)
+    }
+
+    /// Whether accounts are configured, so processing requires one.
+    pub fn is_enabled(&self) -> bool {
+        !self.accounts.is_empty()
+    }
+
+    /// Account whose token the request presents.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Option<Account> {
+        let token = blake3::hash(presented_token(headers)?.as_bytes());
+        // `blake3::Hash` equality is constant time.
+        self.accounts
+            .iter()
+            .find(|account| account.token == token)
+            .cloned()
+    }
+
+    /// Count `files` new uploads against `account`, or refuse them all if that
+    /// would exceed its quota. `stored_bytes` is what its downloads hold now.
+    pub async fn reserve(
+        &self,
+        account: &Account,
+        files: usize,
+        stored_bytes: u64,
+    ) -> Result<(), QuotaExceeded> {
+        let storage_limit = account.quota.max_stored_bytes;
+        if let Some(limit) = storage_limit.filter(|&limit| stored_bytes >= limit) {
+            return Err(QuotaExceeded::Storage {
+                limit,
+                used: stored_bytes,
+            });
+        }
+
+        let mut uploads = self.uploads.lock().await;
+        let recent = uploads.entry(account.id.clone()).or_default();
+        while recent.front().is_some_and(|at| at.elapsed() > QUOTA_WINDOW) {
+            recent.pop_front();
+        }
+        let files_limit = account.quota.files_per_day;
+        if let Some(limit) = files_limit.filter(|&limit| recent.len() + files > limit) {
+            let retry_after = recent
+                .front()
+                .map(|oldest| QUOTA_WINDOW.saturating_sub(oldest.elapsed()))
+                .unwrap_or(QUOTA_WINDOW);
+            return Err(QuotaExceeded::FilesPerDay { limit, retry_after });
+        }
+        let now = Instant::now();
+        recent.extend(std::iter::repeat_n(now, files));
+        Ok(())
+    }
+
+    /// Give back `files` of `account`'s latest reservations, for uploads that
+    /// failed or were skipped, so only processed files count against it.
+    pub async fn refund(&self, account: &Account, files: usize) {
+        if let Some(recent) = self.uploads.lock().await.get_mut(&account.id) {
+            recent.truncate(recent.len().saturating_sub(files));
+        }
+    }
+
+    pub async fn usage(&self, account: &Account, stored_bytes: u64) -> AccountUsage {
+        let files_today = self
+            .uploads
+            .lock()
+            .await
+            .get(&account.id)
+            .map_or(0, |recent| {
+                recent
+                    .iter()
+                    .filter(|at| at.elapsed() <= QUOTA_WINDOW)
+                    .count()
+            });
+        AccountUsage {
+            account: account.id.clone(),
+            files_today,
+            stored_bytes,
+            quota: account.quota,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderValue, header};
+
+    const ACCOUNTS: &str = r#"[
+        { "id": "alice", "token": "alice-token", "files_per_day": 2, "max_stored_bytes": 100 },
+        { "id": "bob", "token": "bob-token" }
+    ]"#;
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn quotas_limit_files_per_day_and_stored_bytes() {
+        let store = AccountStore::from_json(ACCOUNTS).unwrap();
+        let alice = store.authenticate(&bearer("alice-token")).unwrap();
+        assert!(store.authenticate(&bearer("nobody")).is_none());
+
+        assert_eq!(store.reserve(&alice, 2, 0).await, Ok(()));
+        assert!(matches!(
+            store.reserve(&alice, 1, 0).await,
+            Err(QuotaExceeded::FilesPerDay { limit: 2, .. })
+        ));
+        assert_eq!(
+            store.reserve(&alice, 1, 100).await,
+            Err(QuotaExceeded::Storage {
+                limit: 100,
+                used: 100
+            })
+        );
+        assert_eq!(store.usage(&alice, 40).await.files_today, 2);
+        store.refund(&alice, 1).await;
+        assert_eq!(store.usage(&alice, 40).await.files_today, 1);
+        assert_eq!(store.reserve(&alice, 1, 0).await, Ok(()));
+
+        let bob = store.authenticate(&bearer("bob-token")).unwrap();
+        assert_eq!(store.reserve(&bob, 1_000, u64::MAX).await, Ok(()));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::cache::CacheMetrics;
+use crate::downloads::DownloadInfo;
+use crate::processing::{ExceededLimit, FitProcessError};
+use crate::telemetry::UsageTotals;
+use axum::http::{HeaderMap, header};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+/// Number of recent errors kept for the admin dashboard.
+pub const RECENT_ERROR_CAPACITY: usize = 50;
+
+/// Credential required for `/admin`, read from `RUSTYFIT_ADMIN_TOKEN`.
+#[derive(Clone)]
+pub struct AdminToken(blake3::Hash);
+
+impl AdminToken {
+    pub fn new(token: &str) -> Self {
+        Self(blake3::hash(token.as_bytes()))
+    }
+
+    /// `None` when the variable is unset or empty, which disables `/admin`.
+    pub fn from_env() -> Option<Self> {
+        std::env::var("RUSTYFIT_ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .map(|token| Self::new(&token))
+    }
+
+    /// Accept the token as a bearer token or as the HTTP Basic password, so
+    /// both scripts and browsers can sign in.
+    pub fn authorizes(&self, headers: &HeaderMap) -> bool {
+        // Comparing hashes keeps the check constant time.
+        presented_token(headers).is_some_and(|token| blake3::hash(token.as_bytes()) == self.0)
+    }
+}
+
+/// Token sent as `Authorization: Bearer <token>` or as the HTTP Basic password.
+pub(crate) fn presented_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())?;
+    if let Some(token) = value.strip_prefix("Bearer ") {
+        Some(token.trim().to_string())
+    } else {
+        value
+            .strip_prefix("Basic ")
+            .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|credentials| {
+                credentials
+                    .split_once(':')
+                    .map(|(_, password)| password.to_string())
+            })
+    }
+}
+
+/// Counts uploads that are currently being processed.
+#[derive(Clone, Default)]
+pub struct JobTracker {
+    active: Arc<AtomicUsize>,
+}
+
+/// Marks one job as active until dropped.
+pub struct JobGuard {
+    active: Arc<AtomicUsize>,
+}
+
+impl JobTracker {
+    pub fn start(&self) -> JobGuard {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        JobGuard {
+            active: self.active.clone(),
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A processing failure reported to a client.
+#[derive(Clone)]
+pub struct RecordedError {
+    pub kind: &'static str,
+    pub message: String,
+    pub at: Instant,
+}
+
+#[derive(Default)]
+struct ErrorLogInner {
+    counts: BTreeMap<&'static str, u64>,
+    recent: VecDeque<RecordedError>,
+}
+
+/// Totals per error kind plus the most recent failures.
+#[derive(Clone, Default)]
+pub struct ErrorLog {
+    inner: Arc<Mutex<ErrorLogInner>>,
+}
+
+impl ErrorLog {
+    pub async fn record(&self, error: &FitProcessError) {
+        let kind = error_kind(error);
+        let mut inner = self.inner.lock().await;
+        *inner.counts.entry(kind).or_default() += 1;
+        if inner.recent.len() >= RECENT_ERROR_CAPACITY {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(RecordedError {
+            kind,
+            message: error.to_string(),
+            at: Instant::now(),
+        });
+    }
+
+    /// Totals by kind and recent errors, newest first.
+    pub async fn snapshot(&self) -> (Vec<(&'static str, u64)>, Vec<RecordedError>) {
+        let inner = self.inner.lock().await;
+        (
+            inner
+                .counts
+                .iter()
+                .map(|(kind, count)| (*kind, *count))
+                .collect(),
+            inner.recent.iter().rev().cloned().collect(),
+        )
+    }
+}
+
+/// Everything shown on the `/admin` page.
+pub struct AdminOverview {
+    pub downloads: Vec<DownloadInfo>,
+    pub active_jobs: usize,
+    pub cache: CacheMetrics,
+    pub error_counts: Vec<(&'static str, u64)>,
+    pub recent_errors: Vec<RecordedError>,
+    /// Entries removed by the purge that redirected here, if any.
+    pub purged: Option<usize>,
+    /// Usage counted since startup; `None` unless the operator opted in.
+    pub usage: Option<UsageTotals>,
+}
+
+pub(crate) fn error_kind(error: &FitProcessError) -> &'static str {
+    match error {
+        FitProcessError::ParseError(_) => "parse_error",
+        FitProcessError::InvalidHeader(_) => "invalid_header",
+        FitProcessError::EncodeError(_) => "encode_error",
+        FitProcessError::UnsupportedFeature(_) => "unsupported_feature",
+        FitProcessError::LimitExceeded(ExceededLimit::Records { .. }) => "record_limit",
+        FitProcessError::LimitExceeded(ExceededLimit::DecodeTime { .. }) => "decode_time_limit",
+        FitProcessError::InvalidOption(_) => "invalid_option",
+        FitProcessError::Cancelled => "cancelled",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(authorization).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn token_is_accepted_as_bearer_or_basic_password() {
+        let token = AdminToken::new("s3cret");
+
+        assert!(token.authorizes(&headers("Bearer s3cret")));
+        let basic = STANDARD.encode("admin:s3cret");
+        assert!(token.authorizes(&headers(&format!("Basic {basic}"))));
+        assert!(!token.authorizes(&headers("Bearer wrong")));
+        assert!(!token.authorizes(&HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn error_log_counts_kinds_and_keeps_newest_first() {
+        let log = ErrorLog::default();
+        log.record(&FitProcessError::ParseError("first".into()))
+            .await;
+        log.record(&FitProcessError::ParseError("second".into()))
+            .await;
+
+        let (counts, recent) = log.snapshot().await;
+
+        assert_eq!(counts, vec![("parse_error", 2)]);
+        assert!(recent[0].message.contains("second"));
+    }
+}
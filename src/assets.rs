@@ -0,0 +1,108 @@
+//! Stylesheets and scripts served from the static directory under
+//! content-fingerprinted names, so they can be cached indefinitely.
+//!
+//! Fingerprints are taken from the files in the static directory the server
+//! serves, so a `--static-dir` with edited assets gets new URLs rather than
+//! stale copies cached under the old ones.
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// URL prefix the static directory is mounted at.
+pub const STATIC_PREFIX: &str = "/static";
+
+/// Assets the templates link to, embedded as the fallback when the static
+/// directory has not been read.
+const ASSETS: &[(&str, &[u8])] = &[
+    ("app.css", include_bytes!("../static/app.css")),
+    ("landing.js", include_bytes!("../static/landing.js")),
+];
+
+/// Hex characters of the content hash kept in fingerprinted names.
+const FINGERPRINT_LEN: usize = 16;
+
+/// Fingerprinted URL of every asset, set once.
+static URLS: OnceLock<Vec<(&'static str, String)>> = OnceLock::new();
+
+/// Fingerprint the assets as `static_dir` holds them. Called once when the
+/// server is built, before any page links them; assets the directory lacks
+/// keep the fingerprint of the embedded copy.
+pub fn fingerprint_static_dir(static_dir: &Path) {
+    let _ = URLS.set(fingerprinted_urls(|name, embedded| {
+        std::fs::read(static_dir.join(name)).unwrap_or_else(|_| embedded.to_vec())
+    }));
+}
+
+fn fingerprinted_urls(contents: impl Fn(&str, &[u8]) -> Vec<u8>) -> Vec<(&'static str, String)> {
+    ASSETS
+        .iter()
+        .map(|&(name, embedded)| {
+            let hash = blake3::hash(&contents(name, embedded)).to_hex();
+            let fingerprinted = match name.rsplit_once('.') {
+                Some((stem, ext)) => format!("{stem}.{}.{ext}", &hash[..FINGERPRINT_LEN]),
+                None => format!("{name}.{}", &hash[..FINGERPRINT_LEN]),
+            };
+            (name, format!("{STATIC_PREFIX}/{fingerprinted}"))
+        })
+        .collect()
+}
+
+/// URL of `name` with its content fingerprint, e.g. `/static/app.1f2e3d4c5b6a7988.css`.
+///
+/// Names not in [`ASSETS`] are linked without a fingerprint.
+pub fn asset_url(name: &str) -> String {
+    let urls = URLS.get_or_init(|| fingerprinted_urls(|_, embedded| embedded.to_vec()));
+    urls.iter()
+        .find(|(asset, _)| *asset == name)
+        .map(|(_, url)| url.clone())
+        .unwrap_or_else(|| format!("{STATIC_PREFIX}/{name}"))
+}
+
+/// Whether a fingerprinted `path` carries the current fingerprint of its
+/// file, rather than one from an older build or static directory.
+pub fn is_current_fingerprint(path: &str) -> bool {
+    let Some(stripped) = strip_fingerprint(path) else {
+        return false;
+    };
+    let file_name = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+    file_name(&asset_url(&file_name(&stripped))) == file_name(path)
+}
+
+/// Remove the fingerprint from a requested path, returning the on-disk path.
+///
+/// `None` means the path carries no fingerprint.
+pub fn strip_fingerprint(path: &str) -> Option<String> {
+    let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+    let (rest, ext) = file.rsplit_once('.')?;
+    let (stem, fingerprint) = rest.rsplit_once('.')?;
+    let is_fingerprint = fingerprint.len() == FINGERPRINT_LEN
+        && fingerprint.bytes().all(|byte| byte.is_ascii_hexdigit());
+    (is_fingerprint && !stem.is_empty()).then(|| format!("{dir}/{stem}.{ext}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprinted_urls_strip_back_to_the_file() {
+        let url = asset_url("app.css");
+
+        assert_ne!(url, "/static/app.css");
+        assert_eq!(strip_fingerprint(&url).as_deref(), Some("/static/app.css"));
+        assert_eq!(strip_fingerprint("/static/app.css"), None);
+        assert_eq!(strip_fingerprint("/static/app.min.css"), None);
+    }
+
+    #[test]
+    fn only_the_current_fingerprint_is_current() {
+        let url = asset_url("app.css");
+
+        assert!(is_current_fingerprint(&url));
+        assert!(is_current_fingerprint(
+            url.trim_start_matches(STATIC_PREFIX)
+        ));
+        assert!(!is_current_fingerprint("/static/app.0123456789abcdef.css"));
+        assert!(!is_current_fingerprint("/static/app.css"));
+    }
+}
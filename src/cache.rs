@@ -0,0 +1,313 @@
+use crate::processing::{ProcessedFit, ProcessingOptions};
+use fitparser::FitDataRecord;
+use moka::future::Cache;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How long a processed upload stays eligible for reuse.
+pub const UPLOAD_CACHE_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Default memory budget for cached uploads, in estimated bytes.
+pub const UPLOAD_CACHE_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Rough in-memory size of one decoded record and its display copy, used to
+/// weigh entries.
+const RECORD_WEIGHT_BYTES: usize = 512;
+
+/// Identical bytes processed with equivalent options for the same account.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    content_hash: [u8; 32],
+    /// [`ProcessingOptions::canonical`], so option sets that produce the same
+    /// output share an entry.
+    options: ProcessingOptions,
+    /// Keeps one account's upload from counting as a repeat of another's.
+    owner: Option<String>,
+}
+
+impl CacheKey {
+    fn new(content_hash: [u8; 32], options: &ProcessingOptions, owner: Option<&str>) -> Self {
+        Self {
+            content_hash,
+            options: options.canonical(),
+            owner: owner.map(str::to_string),
+        }
+    }
+}
+
+/// A previously processed upload.
+#[derive(Clone)]
+pub struct CachedUpload {
+    pub records: Arc<Vec<FitDataRecord>>,
+    pub processed: ProcessedFit,
+    /// Account that uploaded it, so its entries go when its data is deleted.
+    pub owner: Option<String>,
+}
+
+impl CachedUpload {
+    /// Estimated memory held by this entry.
+    fn weight(&self) -> u32 {
+        let bytes = self.processed.processed_bytes.len()
+            + self.records.len().saturating_mul(RECORD_WEIGHT_BYTES);
+        u32::try_from(bytes).unwrap_or(u32::MAX)
+    }
+}
+
+/// Hit/miss counters exposed at `/api/v1/cache/metrics`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    pub bypasses: u64,
+    pub entries: usize,
+    /// Estimated bytes held, out of `max_bytes`.
+    pub weighted_bytes: u64,
+    pub max_bytes: u64,
+}
+
+/// Recently processed uploads keyed by blake3 content hash, options and owner.
+///
+/// Backed by a concurrent cache, so lookups from parallel uploads don't
+/// serialise on a lock. Entries expire after [`UPLOAD_CACHE_TTL`] and the
+/// least recently used ones are evicted once the estimated size exceeds the
+/// memory budget.
+#[derive(Clone)]
+pub struct UploadCache {
+    entries: Cache<CacheKey, CachedUpload>,
+    max_bytes: u64,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+    bypasses: Arc<AtomicU64>,
+}
+
+impl Default for UploadCache {
+    fn default() -> Self {
+        Self::with_max_bytes(UPLOAD_CACHE_MAX_BYTES)
+    }
+}
+
+/// Content hash used as the cache key for an upload.
+pub fn content_hash(bytes: &[u8]) -> [u8; 32] {
+    *blake3::hash(bytes).as_bytes()
+}
+
+impl UploadCache {
+    pub fn with_max_bytes(max_bytes: u64) -> Self {
+        Self {
+            entries: Cache::builder()
+                .max_capacity(max_bytes)
+                .weigher(|_, upload: &CachedUpload| upload.weight())
+                .time_to_live(UPLOAD_CACHE_TTL)
+                .support_invalidation_closures()
+                .build(),
+            max_bytes,
+            hits: Arc::default(),
+            misses: Arc::default(),
+            bypasses: Arc::default(),
+        }
+    }
+
+    /// Budget from `RUSTYFIT_CACHE_MAX_BYTES`; `0` disables caching.
+    pub fn from_env() -> Self {
+        let max_bytes = std::env::var("RUSTYFIT_CACHE_MAX_BYTES")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(UPLOAD_CACHE_MAX_BYTES);
+        Self::with_max_bytes(max_bytes)
+    }
+
+    /// Look up a fresh entry `owner` uploaded, counting the lookup as a hit or
+    /// miss.
+    pub async fn get(
+        &self,
+        content_hash: [u8; 32],
+        options: &ProcessingOptions,
+        owner: Option<&str>,
+    ) -> Option<CachedUpload> {
+        let key = CacheKey::new(content_hash, options, owner);
+        let upload = self.entries.get(&key).await;
+        let counter = if upload.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        upload
+    }
+
+    /// Store a processed upload; eviction happens in the background.
+    pub async fn insert(
+        &self,
+        content_hash: [u8; 32],
+        options: &ProcessingOptions,
+        upload: CachedUpload,
+    ) {
+        let key = CacheKey::new(content_hash, options, upload.owner.as_deref());
+        self.entries.insert(key, upload).await;
+    }
+
+    /// Drop every entry `owner` uploaded.
+    pub async fn remove_owned(&self, owner: &str) {
+        let owner = owner.to_string();
+        // Only fails for caches built without invalidation closures.
+        let _ = self.entries.invalidate_entries_if(move |_, upload| {
+            upload.owner.as_deref() == Some(owner.as_str())
+        });
+        self.entries.run_pending_tasks().await;
+    }
+
+    /// Apply pending expirations now, returning how many entries were removed.
+    pub async fn purge_expired(&self) -> usize {
+        let before = self.entries.entry_count();
+        self.entries.run_pending_tasks().await;
+        before.saturating_sub(self.entries.entry_count()) as usize
+    }
+
+    /// Count an upload that skipped the cache on request.
+    pub fn record_bypass(&self) {
+        self.bypasses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub async fn metrics(&self) -> CacheMetrics {
+        self.entries.run_pending_tasks().await;
+        CacheMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            bypasses: self.bypasses.load(Ordering::Relaxed),
+            entries: self.entries.entry_count() as usize,
+            weighted_bytes: self.entries.weighted_size(),
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::WorkoutSummary;
+
+    fn upload() -> CachedUpload {
+        CachedUpload {
+            records: Arc::new(Vec::new()),
+            processed: ProcessedFit {
+                records: Vec::new(),
+                record_count: 0,
+                processed_bytes: vec![1, 2, 3],
+                summary: WorkoutSummary::default(),
+                devices: Vec::new(),
+                structure: Default::default(),
+                annotations: Default::default(),
+                speed_spikes: Vec::new(),
+                charts: Default::default(),
+                track: Vec::new(),
+                warnings: Vec::new(),
+                smoothing: None,
+                timings: Default::default(),
+            },
+            owner: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn hits_require_same_bytes_and_options() {
+        let cache = UploadCache::default();
+        let hash = content_hash(b"activity");
+        let options = ProcessingOptions::default();
+        cache.insert(hash, &options, upload()).await;
+
+        let smoothed = ProcessingOptions {
+            smooth_speed: true,
+            ..ProcessingOptions::default()
+        };
+        assert!(cache.get(hash, &options, None).await.is_some());
+        assert!(cache.get(hash, &smoothed, None).await.is_none());
+        assert!(
+            cache
+                .get(content_hash(b"other"), &options, None)
+                .await
+                .is_none()
+        );
+
+        let metrics = cache.metrics().await;
+        assert_eq!((metrics.hits, metrics.misses, metrics.entries), (1, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn owned_entries_can_be_removed() {
+        let cache = UploadCache::default();
+        let options = ProcessingOptions::default();
+        let owned = CachedUpload {
+            owner: Some("alice".to_string()),
+            ..upload()
+        };
+        cache.insert(content_hash(b"alice"), &options, owned).await;
+        cache
+            .insert(content_hash(b"anyone"), &options, upload())
+            .await;
+
+        cache.remove_owned("alice").await;
+
+        let alice = Some("alice");
+        assert!(
+            cache
+                .get(content_hash(b"alice"), &options, alice)
+                .await
+                .is_none()
+        );
+        assert!(
+            cache
+                .get(content_hash(b"anyone"), &options, None)
+                .await
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn hits_require_the_same_owner() {
+        let cache = UploadCache::default();
+        let hash = content_hash(b"activity");
+        let options = ProcessingOptions::default();
+        let owned = CachedUpload {
+            owner: Some("alice".to_string()),
+            ..upload()
+        };
+        cache.insert(hash, &options, owned).await;
+
+        assert!(cache.get(hash, &options, Some("alice")).await.is_some());
+        assert!(cache.get(hash, &options, Some("bob")).await.is_none());
+        assert!(cache.get(hash, &options, None).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn equivalent_options_share_an_entry() {
+        let cache = UploadCache::default();
+        let hash = content_hash(b"activity");
+        cache
+            .insert(hash, &ProcessingOptions::default(), upload())
+            .await;
+
+        let equivalent = ProcessingOptions {
+            resample_interval_seconds: Some(1),
+            gear: Some("   ".to_string()),
+            inject_derived_fields: true,
+            ..ProcessingOptions::default()
+        };
+
+        assert!(cache.get(hash, &equivalent, None).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn entries_over_the_memory_budget_are_evicted() {
+        let cache = UploadCache::with_max_bytes(4);
+        let options = ProcessingOptions::default();
+        for name in [b"first", b"other"] {
+            cache.insert(content_hash(name), &options, upload()).await;
+        }
+
+        let metrics = cache.metrics().await;
+        assert_eq!(metrics.entries, 1);
+        assert!(metrics.weighted_bytes <= 4);
+    }
+}
@@ -0,0 +1,147 @@
+//! Line charts of an activity drawn with plotters on the server, so the
+//! results page shows them without any client-side JavaScript.
+//!
+//! Plotters is built without its font features: the SVG backend writes labels
+//! as plain `<text>` set in the page's font, and the PDF report in
+//! [`crate::pdf`] draws the same charts through its own backend in the
+//! standard Helvetica, so no font files ship.
+
+use crate::locale::NumberLocale;
+use crate::processing::series::{ChartPoint, ChartSeries};
+use crate::templates::format_duration;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+const WIDTH: u32 = 600;
+const HEIGHT: u32 = 200;
+
+pub const SPEED_COLOR: RGBColor = RGBColor(0x25, 0x63, 0xeb);
+pub const HEART_RATE_COLOR: RGBColor = RGBColor(0xdc, 0x26, 0x26);
+pub const ELEVATION_COLOR: RGBColor = RGBColor(0x16, 0xa3, 0x4a);
+
+/// Speed, heart rate, and elevation charts of `series`, leaving out series
+/// with fewer than two points; `None` when none are left.
+pub fn render_series_charts(series: &ChartSeries, locale: NumberLocale) -> Option<String> {
+    let charts: String = [
+        ("Speed (km/h) over time", &series.speed, SPEED_COLOR),
+        (
+            "Heart rate (bpm) over time",
+            &series.heart_rate,
+            HEART_RATE_COLOR,
+        ),
+        (
+            "Elevation (m) over time",
+            &series.elevation,
+            ELEVATION_COLOR,
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, points, _)| points.len() >= 2)
+    .map(|(label, points, color)| line_chart(label, points, color, locale))
+    .collect();
+    (!charts.is_empty()).then_some(charts)
+}
+
+/// `points` as a captioned SVG figure, with the elapsed time along the x axis.
+pub fn line_chart(
+    label: &str,
+    points: &[ChartPoint],
+    color: RGBColor,
+    locale: NumberLocale,
+) -> String {
+    let points: Vec<(f64, f64)> = points
+        .iter()
+        .map(|point| (point.elapsed_seconds, point.value))
+        .collect();
+    let mut svg = String::new();
+    {
+        let area = SVGBackend::with_string(&mut svg, (WIDTH, HEIGHT)).into_drawing_area();
+        let elapsed = |seconds: &f64| format_duration(Some(*seconds));
+        // Drawing into a string cannot fail.
+        let _ =
+            draw_line_chart(&area, &points, color, &elapsed, locale).and_then(|()| area.present());
+    }
+    let svg = svg.replacen(
+        "<svg ",
+        &format!("<svg role=\"img\" aria-label=\"{label}\" "),
+        1,
+    );
+    format!("<figure class=\"curve-chart\"><figcaption>{label}</figcaption>{svg}</figure>")
+}
+
+/// Draw `points` as a line across `area`, with the value range labelled on
+/// the left and the x values described by `x_label`.
+pub fn draw_line_chart<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    points: &[(f64, f64)],
+    color: RGBColor,
+    x_label: &dyn Fn(&f64) -> String,
+    locale: NumberLocale,
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let end = points.last().map_or(1.0, |&(x, _)| x.max(1.0));
+    let (low, high) = points
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(low, high), &(_, y)| {
+            (low.min(y), high.max(y))
+        });
+    let high = high.max(low + 1.0);
+    let value = |value: &f64| locale.number(*value, 0);
+
+    let mut chart = ChartBuilder::on(area)
+        .margin(10)
+        .x_label_area_size(20)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0.0..end, low..high)?;
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_labels(4)
+        .y_labels(3)
+        .x_label_formatter(x_label)
+        .y_label_formatter(&value)
+        .label_style(("sans-serif", 10))
+        .draw()?;
+    chart.draw_series(LineSeries::new(
+        points.iter().copied(),
+        color.stroke_width(2),
+    ))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(values: &[f64]) -> Vec<ChartPoint> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, &value)| ChartPoint {
+                elapsed_seconds: index as f64 * 60.0,
+                value,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_series_with_a_line_are_charted() {
+        let series = ChartSeries {
+            speed: Vec::new(),
+            heart_rate: points(&[120.0, 150.0, 135.0]),
+            elevation: points(&[1_204.0]),
+        };
+
+        let charts = render_series_charts(&series, NumberLocale::default()).unwrap();
+        assert_eq!(charts.matches("<svg").count(), 1);
+        assert!(charts.contains("aria-label=\"Heart rate (bpm) over time\""));
+        assert!(
+            charts
+                .contains("stroke=\"#DC2626\" stroke-width=\"2\" points=\"50,169 319,10 589,90 \"")
+        );
+        assert!(charts.contains("\n1m 40s\n</text>"));
+        assert_eq!(
+            render_series_charts(&ChartSeries::default(), NumberLocale::default()),
+            None
+        );
+    }
+}
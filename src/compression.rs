@@ -0,0 +1,189 @@
+//! Compressed variants of processed and original downloads.
+//!
+//! Compression runs on a blocking thread and is streamed to the client in
+//! chunks as it is produced, so a large multisport file is never held twice
+//! in memory and the first bytes go out before the whole file is compressed.
+
+use axum::body::{Body, Bytes};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use futures_util::stream;
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use zip::CompressionMethod;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// Compressed bytes sent to the client at a time.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Chunks compressed ahead of the client before compression waits.
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+/// Format requested with `?compression=` on a download link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadCompression {
+    /// `processed.fit.gz`; also used for `Accept-Encoding: gzip`.
+    Gzip,
+    /// `processed.zip` holding `processed.fit`.
+    Zip,
+}
+
+impl DownloadCompression {
+    /// Attachment name of the compressed `{stem}.fit`.
+    pub fn file_name(self, stem: &str) -> String {
+        match self {
+            DownloadCompression::Gzip => format!("{stem}.fit.gz"),
+            DownloadCompression::Zip => format!("{stem}.zip"),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            DownloadCompression::Gzip => "application/gzip",
+            DownloadCompression::Zip => "application/zip",
+        }
+    }
+
+    /// Entity tag of this variant, derived from the uncompressed file's tag.
+    pub fn etag(self, etag: &str) -> String {
+        let suffix = match self {
+            DownloadCompression::Gzip => "gzip",
+            DownloadCompression::Zip => "zip",
+        };
+        format!("\"{}-{suffix}\"", etag.trim_matches('"'))
+    }
+}
+
+/// Response body streaming `bytes` compressed as `compression`; archives
+/// name the file `{stem}.fit`.
+pub fn compressed_body(
+    bytes: Arc<Vec<u8>>,
+    compression: DownloadCompression,
+    stem: &'static str,
+) -> Body {
+    let (sender, receiver) = mpsc::channel(CHUNKS_IN_FLIGHT);
+    tokio::task::spawn_blocking(move || {
+        let writer = ChunkWriter::new(sender.clone());
+        if let Err(err) = compress(&bytes, compression, &format!("{stem}.fit"), writer) {
+            // The client sees a truncated body; the receiver may already be gone.
+            let _ = sender.blocking_send(Err(err));
+        }
+    });
+    Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    }))
+}
+
+fn compress(
+    bytes: &[u8],
+    compression: DownloadCompression,
+    entry_name: &str,
+    writer: ChunkWriter,
+) -> io::Result<()> {
+    match compression {
+        DownloadCompression::Gzip => {
+            let mut encoder = GzEncoder::new(writer, Compression::default());
+            encoder.write_all(bytes)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        DownloadCompression::Zip => {
+            let mut archive = ZipWriter::new_stream(writer);
+            let options =
+                SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+            archive.start_file(entry_name, options)?;
+            archive.write_all(bytes)?;
+            archive.finish()?;
+            Ok(())
+        }
+    }
+}
+
+/// Blocking writer that forwards its output to a response body in chunks;
+/// the last partial chunk is sent when it is dropped.
+struct ChunkWriter {
+    sender: mpsc::Sender<io::Result<Bytes>>,
+    buffer: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new(sender: mpsc::Sender<io::Result<Bytes>>) -> Self {
+        Self {
+            sender,
+            buffer: Vec::with_capacity(CHUNK_BYTES),
+        }
+    }
+
+    fn send_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_BYTES));
+        self.sender
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "download was abandoned"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= CHUNK_BYTES {
+            self.send_buffer()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+impl Drop for ChunkWriter {
+    /// Send whatever the encoder wrote last; the finished encoder drops its
+    /// writer rather than flushing it.
+    fn drop(&mut self) {
+        let _ = self.send_buffer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use http_body_util::BodyExt;
+    use std::io::{Cursor, Read};
+
+    async fn collect(bytes: &[u8], compression: DownloadCompression) -> Vec<u8> {
+        let body = compressed_body(Arc::new(bytes.to_vec()), compression, "processed");
+        body.collect().await.unwrap().to_bytes().to_vec()
+    }
+
+    #[tokio::test]
+    async fn compressed_downloads_round_trip() {
+        let fit = std::fs::read("test/fixtures/activity.fit").unwrap();
+
+        let gzipped = collect(&fit, DownloadCompression::Gzip).await;
+        let mut unzipped = Vec::new();
+        GzDecoder::new(gzipped.as_slice())
+            .read_to_end(&mut unzipped)
+            .unwrap();
+        assert_eq!(unzipped, fit);
+
+        let zipped = collect(&fit, DownloadCompression::Zip).await;
+        let mut archive = zip::ZipArchive::new(Cursor::new(zipped)).unwrap();
+        let mut entry = archive.by_name("processed.fit").unwrap();
+        let mut extracted = Vec::new();
+        entry.read_to_end(&mut extracted).unwrap();
+        assert_eq!(extracted, fit);
+    }
+
+    #[test]
+    fn variants_have_their_own_entity_tags() {
+        assert_eq!(DownloadCompression::Gzip.etag("\"abc\""), "\"abc-gzip\"");
+        assert_eq!(DownloadCompression::Zip.etag("\"abc\""), "\"abc-zip\"");
+    }
+}
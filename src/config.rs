@@ -0,0 +1,266 @@
+//! Server settings chosen at startup by the binary or the environment.
+
+use crate::accounts::AccountStore;
+use crate::elevation::ElevationConfig;
+use crate::events::MqttConfig;
+use crate::fetch::FetchLimits;
+use crate::telemetry::UsageCounters;
+use crate::weather::WeatherConfig;
+use axum::http::{HeaderName, HeaderValue, header};
+use std::path::PathBuf;
+
+/// Runtime settings for the HTTP server.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Directory holding the `wasm-pack` bundle served under `/pkg`.
+    pub wasm_pkg_dir: PathBuf,
+    /// Directory holding stylesheets and scripts served under `/static`.
+    pub static_dir: PathBuf,
+    pub security_headers: SecurityHeaders,
+    /// How much of each processed file's statistics to log.
+    pub processing_log: ProcessingLogDetail,
+    /// Bounds on files fetched by `POST /api/v1/process-url`.
+    pub url_fetch: FetchLimits,
+    /// Broker receiving a message for every completed job.
+    pub job_events: Option<MqttConfig>,
+    /// Elevation API used by the `replace_elevation` option.
+    pub elevation: Option<ElevationConfig>,
+    /// Historical weather API used by the `enrich_weather` option.
+    pub weather: Option<WeatherConfig>,
+    /// Accounts allowed to process files; empty leaves the server open.
+    pub accounts: AccountStore,
+    /// Counters of files processed, options used, and errors for `/admin`,
+    /// when the operator enabled them.
+    pub telemetry: Option<UsageCounters>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            wasm_pkg_dir: PathBuf::from("pkg"),
+            static_dir: PathBuf::from("static"),
+            security_headers: SecurityHeaders::default(),
+            processing_log: ProcessingLogDetail::default(),
+            url_fetch: FetchLimits::default(),
+            job_events: None,
+            elevation: None,
+            weather: None,
+            accounts: AccountStore::default(),
+            telemetry: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Defaults with the security headers, `RUSTYFIT_PROCESSING_LOG`, URL
+    /// fetch limits, MQTT broker, elevation service, and weather provider
+    /// taken from the environment.
+    pub fn from_env() -> Self {
+        Self {
+            security_headers: SecurityHeaders::from_env(),
+            processing_log: std::env::var("RUSTYFIT_PROCESSING_LOG")
+                .ok()
+                .and_then(|name| ProcessingLogDetail::from_name(&name))
+                .unwrap_or_default(),
+            url_fetch: FetchLimits::from_env(),
+            job_events: MqttConfig::from_env(),
+            elevation: ElevationConfig::from_env(),
+            weather: WeatherConfig::from_env(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Detail level of the per-file processing statistics event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProcessingLogDetail {
+    /// No event is emitted.
+    Off,
+    /// Record count, sizes, cache use, and total duration.
+    #[default]
+    Summary,
+    /// Also the message-type histogram, options, warnings, and per-stage durations.
+    Full,
+}
+
+impl ProcessingLogDetail {
+    /// Parse `off`, `summary`, or `full`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "summary" => Some(Self::Summary),
+            "full" => Some(Self::Full),
+            _ => None,
+        }
+    }
+}
+
+/// Referrer policies accepted from `RUSTYFIT_REFERRER_POLICY`.
+const REFERRER_POLICIES: &[&str] = &[
+    "no-referrer",
+    "no-referrer-when-downgrade",
+    "origin",
+    "origin-when-cross-origin",
+    "same-origin",
+    "strict-origin",
+    "strict-origin-when-cross-origin",
+    "unsafe-url",
+];
+
+/// Headers added to every response.
+///
+/// Content is restricted to this origin by default; the source lists admit
+/// additional origins, e.g. a CDN hosting a map or chart library.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    pub script_sources: Vec<String>,
+    pub style_sources: Vec<String>,
+    pub img_sources: Vec<String>,
+    pub connect_sources: Vec<String>,
+    /// Origins allowed to embed pages in a frame; empty forbids framing.
+    pub frame_ancestors: Vec<String>,
+    pub referrer_policy: String,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            script_sources: Vec::new(),
+            style_sources: Vec::new(),
+            img_sources: Vec::new(),
+            connect_sources: Vec::new(),
+            frame_ancestors: Vec::new(),
+            referrer_policy: "strict-origin-when-cross-origin".to_string(),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// Extra sources from `RUSTYFIT_CSP_SCRIPT_SRC`, `RUSTYFIT_CSP_STYLE_SRC`,
+    /// `RUSTYFIT_CSP_IMG_SRC`, `RUSTYFIT_CSP_CONNECT_SRC`, and
+    /// `RUSTYFIT_FRAME_ANCESTORS` (space separated), plus
+    /// `RUSTYFIT_REFERRER_POLICY`.
+    pub fn from_env() -> Self {
+        let sources = |name: &str| {
+            std::env::var(name)
+                .map(|value| parse_sources(&value))
+                .unwrap_or_default()
+        };
+        let defaults = Self::default();
+        Self {
+            script_sources: sources("RUSTYFIT_CSP_SCRIPT_SRC"),
+            style_sources: sources("RUSTYFIT_CSP_STYLE_SRC"),
+            img_sources: sources("RUSTYFIT_CSP_IMG_SRC"),
+            connect_sources: sources("RUSTYFIT_CSP_CONNECT_SRC"),
+            frame_ancestors: sources("RUSTYFIT_FRAME_ANCESTORS"),
+            referrer_policy: std::env::var("RUSTYFIT_REFERRER_POLICY")
+                .ok()
+                .filter(|policy| REFERRER_POLICIES.contains(&policy.trim()))
+                .map(|policy| policy.trim().to_string())
+                .unwrap_or(defaults.referrer_policy),
+        }
+    }
+
+    /// Policy permitting this origin plus the configured sources. WebAssembly
+    /// compilation is allowed for the in-browser processing bundle.
+    pub fn content_security_policy(&self) -> String {
+        let directive = |name: &str, base: &str, extra: &[String]| {
+            let mut value = format!("{name} {base}");
+            for source in extra {
+                value.push(' ');
+                value.push_str(source);
+            }
+            value
+        };
+        let frame_ancestors = if self.frame_ancestors.is_empty() {
+            "frame-ancestors 'none'".to_string()
+        } else {
+            directive("frame-ancestors", "'self'", &self.frame_ancestors)
+        };
+        [
+            "default-src 'self'".to_string(),
+            directive(
+                "script-src",
+                "'self' 'wasm-unsafe-eval'",
+                &self.script_sources,
+            ),
+            directive("style-src", "'self'", &self.style_sources),
+            directive("img-src", "'self' data:", &self.img_sources),
+            directive("connect-src", "'self'", &self.connect_sources),
+            "object-src 'none'".to_string(),
+            "base-uri 'none'".to_string(),
+            frame_ancestors,
+        ]
+        .join("; ")
+    }
+
+    /// Header name/value pairs to set when a handler has not set them itself.
+    pub fn headers(&self) -> Vec<(HeaderName, HeaderValue)> {
+        let value = |text: String| {
+            HeaderValue::from_str(&text).expect("sources are filtered to header-safe text")
+        };
+        vec![
+            (
+                header::CONTENT_SECURITY_POLICY,
+                value(self.content_security_policy()),
+            ),
+            (
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            ),
+            (header::REFERRER_POLICY, value(self.referrer_policy.clone())),
+        ]
+    }
+}
+
+/// Split a space-separated source list, dropping entries that could break out
+/// of the directive or the header.
+fn parse_sources(value: &str) -> Vec<String> {
+    value
+        .split_whitespace()
+        .filter(|source| {
+            source
+                .bytes()
+                .all(|byte| byte.is_ascii_graphic() && byte != b';' && byte != b',')
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn processing_log_detail_parses_known_names() {
+        assert_eq!(
+            ProcessingLogDetail::from_name(" FULL "),
+            Some(ProcessingLogDetail::Full)
+        );
+        assert_eq!(
+            ProcessingLogDetail::from_name("off"),
+            Some(ProcessingLogDetail::Off)
+        );
+        assert_eq!(ProcessingLogDetail::from_name("verbose"), None);
+    }
+
+    #[test]
+    fn extra_sources_extend_the_policy() {
+        let headers = SecurityHeaders {
+            script_sources: parse_sources("https://cdn.example.com bad;source"),
+            frame_ancestors: vec!["https://embed.example.com".into()],
+            ..SecurityHeaders::default()
+        };
+
+        let policy = headers.content_security_policy();
+
+        assert!(policy.contains("script-src 'self' 'wasm-unsafe-eval' https://cdn.example.com;"));
+        assert!(!policy.contains("bad"));
+        assert!(policy.contains("frame-ancestors 'self' https://embed.example.com"));
+        assert!(
+            SecurityHeaders::default()
+                .content_security_policy()
+                .ends_with("frame-ancestors 'none'")
+        );
+    }
+}
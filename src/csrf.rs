@@ -0,0 +1,102 @@
+//! Double-submit cookie protection for state-changing browser requests.
+//!
+//! Every response to a client without a token cookie sets one. Protected
+//! routes then require the same token back in the `X-CSRF-Token` header
+//! (scripts) or a `csrf_token` query parameter (plain HTML forms), which a
+//! cross-site page cannot read.
+
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use uuid::Uuid;
+
+pub const CSRF_COOKIE: &str = "rustyfit_csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+pub const CSRF_QUERY_PARAM: &str = "csrf_token";
+
+/// The client's CSRF token, available to handlers as an `Extension` for
+/// embedding in rendered forms.
+#[derive(Debug, Clone)]
+pub struct CsrfToken(pub String);
+
+/// Make the client's token available to handlers, issuing a cookie when the
+/// client has none yet.
+pub async fn issue_csrf_cookie(mut request: Request, next: Next) -> Response {
+    let existing = cookie_token(request.headers());
+    let token = existing
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string());
+    request.extensions_mut().insert(CsrfToken(token.clone()));
+
+    let mut response = next.run(request).await;
+    if existing.is_none() {
+        // Not HttpOnly: the landing page script echoes it in a header.
+        let cookie = format!("{CSRF_COOKIE}={token}; Path=/; SameSite=Strict");
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+    response
+}
+
+/// Reject requests whose submitted token does not match the cookie.
+pub async fn require_csrf_token(request: Request, next: Next) -> Response {
+    let submitted = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| query_token(request.uri().query()));
+    let valid =
+        cookie_token(request.headers())
+            .zip(submitted)
+            .is_some_and(|(cookie, submitted)| {
+                // Compare digests so the check does not leak a matching prefix.
+                blake3::hash(cookie.as_bytes()) == blake3::hash(submitted.as_bytes())
+            });
+    if !valid {
+        return (StatusCode::FORBIDDEN, "Missing or invalid CSRF token").into_response();
+    }
+    next.run(request).await
+}
+
+fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == CSRF_COOKIE && !value.is_empty()).then(|| value.to_string())
+        })
+}
+
+fn query_token(query: Option<&str>) -> Option<String> {
+    query?.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name == CSRF_QUERY_PARAM && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_read_from_cookie_and_query() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; rustyfit_csrf=abc123"),
+        );
+
+        assert_eq!(cookie_token(&headers).as_deref(), Some("abc123"));
+        assert_eq!(
+            query_token(Some("window=7&csrf_token=abc123")).as_deref(),
+            Some("abc123")
+        );
+        assert_eq!(query_token(Some("csrf_token=")), None);
+    }
+}
@@ -0,0 +1,339 @@
+use crate::cache::content_hash;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// How long a processed file stays downloadable, so interrupted downloads can resume.
+pub const DOWNLOAD_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A processed file plus the validators used for conditional and range requests.
+#[derive(Clone)]
+pub struct StoredDownload {
+    pub bytes: Arc<Vec<u8>>,
+    /// Strong entity tag derived from the content, including quotes.
+    pub etag: String,
+    pub last_modified: SystemTime,
+    /// Account the file was processed for, when accounts are configured.
+    pub owner: Option<String>,
+    /// The upload the file was processed from, kept and expired with it.
+    original: Option<Box<StoredDownload>>,
+    stored_at: Instant,
+}
+
+impl StoredDownload {
+    fn new(bytes: Vec<u8>, owner: Option<String>) -> Self {
+        Self {
+            etag: entity_tag(&bytes),
+            bytes: Arc::new(bytes),
+            last_modified: SystemTime::now(),
+            owner,
+            original: None,
+            stored_at: Instant::now(),
+        }
+    }
+
+    /// The uploaded file, byte for byte, when it was kept.
+    pub fn original(&self) -> Option<&StoredDownload> {
+        self.original.as_deref()
+    }
+
+    /// Bytes held for this download, the original upload included.
+    fn size(&self) -> usize {
+        self.bytes.len() + self.original().map_or(0, |original| original.bytes.len())
+    }
+}
+
+/// Size and age of one stored download, for the admin dashboard.
+#[derive(Debug, Clone)]
+pub struct DownloadInfo {
+    pub id: String,
+    pub size: usize,
+    pub age: Duration,
+    pub expired: bool,
+}
+
+/// In-memory map from download ID to processed bytes.
+#[derive(Clone, Default)]
+pub struct DownloadStore {
+    downloads: Arc<Mutex<HashMap<String, StoredDownload>>>,
+}
+
+impl DownloadStore {
+    /// Store `bytes` under a new ID, dropping downloads older than [`DOWNLOAD_TTL`].
+    pub async fn insert(&self, bytes: Vec<u8>, owner: Option<String>) -> String {
+        self.store(StoredDownload::new(bytes, owner), None)
+            .await
+            .expect("unlimited stores always fit")
+    }
+
+    /// [`DownloadStore::insert`] that also keeps the `original` upload the
+    /// bytes were processed from, for as long as the processed file.
+    ///
+    /// With `max_owner_bytes`, the download is refused when it would take its
+    /// owner's live downloads past that many bytes, and the bytes they hold
+    /// already are returned instead. The check and the insert share one lock,
+    /// so concurrent uploads cannot overshoot the limit together.
+    pub async fn insert_with_original(
+        &self,
+        bytes: Vec<u8>,
+        original: Vec<u8>,
+        owner: Option<String>,
+        max_owner_bytes: Option<u64>,
+    ) -> Result<String, u64> {
+        let download = StoredDownload {
+            original: Some(Box::new(StoredDownload::new(original, owner.clone()))),
+            ..StoredDownload::new(bytes, owner)
+        };
+        self.store(download, max_owner_bytes).await
+    }
+
+    async fn store(
+        &self,
+        download: StoredDownload,
+        max_owner_bytes: Option<u64>,
+    ) -> Result<String, u64> {
+        let mut downloads = self.downloads.lock().await;
+        downloads.retain(|_, download| download.stored_at.elapsed() <= DOWNLOAD_TTL);
+        if let (Some(limit), Some(owner)) = (max_owner_bytes, download.owner.as_deref()) {
+            let used: u64 = downloads
+                .values()
+                .filter(|stored| stored.owner.as_deref() == Some(owner))
+                .map(|stored| stored.size() as u64)
+                .sum();
+            if used.saturating_add(download.size() as u64) > limit {
+                return Err(used);
+            }
+        }
+        let id = Uuid::new_v4().to_string();
+        downloads.insert(id.clone(), download);
+        Ok(id)
+    }
+
+    /// Swap the bytes behind a live download, e.g. after a record edit. The
+    /// download keeps its ID, owner, original, and expiry; returns false when
+    /// it is gone.
+    pub async fn replace(&self, id: &str, bytes: Vec<u8>) -> bool {
+        let mut downloads = self.downloads.lock().await;
+        let Some(download) = downloads
+            .get_mut(id)
+            .filter(|download| download.stored_at.elapsed() <= DOWNLOAD_TTL)
+        else {
+            return false;
+        };
+        download.etag = entity_tag(&bytes);
+        download.bytes = Arc::new(bytes);
+        download.last_modified = SystemTime::now();
+        true
+    }
+
+    /// Every stored download, oldest first, including expired ones not yet pruned.
+    pub async fn list(&self) -> Vec<DownloadInfo> {
+        let mut listed: Vec<DownloadInfo> = self
+            .downloads
+            .lock()
+            .await
+            .iter()
+            .map(|(id, download)| {
+                let age = download.stored_at.elapsed();
+                DownloadInfo {
+                    id: id.clone(),
+                    size: download.size(),
+                    age,
+                    expired: age > DOWNLOAD_TTL,
+                }
+            })
+            .collect();
+        listed.sort_by_key(|download| Reverse(download.age));
+        listed
+    }
+
+    /// Drop downloads older than [`DOWNLOAD_TTL`], returning how many were removed.
+    pub async fn purge_expired(&self) -> usize {
+        let mut downloads = self.downloads.lock().await;
+        let before = downloads.len();
+        downloads.retain(|_, download| download.stored_at.elapsed() <= DOWNLOAD_TTL);
+        before - downloads.len()
+    }
+
+    /// Bytes held by `owner`'s live downloads, counted against its storage quota.
+    pub async fn stored_bytes(&self, owner: &str) -> u64 {
+        self.downloads
+            .lock()
+            .await
+            .values()
+            .filter(|download| download.stored_at.elapsed() <= DOWNLOAD_TTL)
+            .filter(|download| download.owner.as_deref() == Some(owner))
+            .map(|download| download.size() as u64)
+            .sum()
+    }
+
+    /// `owner`'s live downloads by ID, oldest first.
+    pub async fn owned_by(&self, owner: &str) -> Vec<(String, StoredDownload)> {
+        let mut owned: Vec<(String, StoredDownload)> = self
+            .downloads
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, download)| download.stored_at.elapsed() <= DOWNLOAD_TTL)
+            .filter(|(_, download)| download.owner.as_deref() == Some(owner))
+            .map(|(id, download)| (id.clone(), download.clone()))
+            .collect();
+        owned.sort_by_key(|(_, download)| download.stored_at);
+        owned
+    }
+
+    /// Delete every download of `owner`, expired ones included, returning their IDs.
+    pub async fn remove_owned(&self, owner: &str) -> Vec<String> {
+        let mut downloads = self.downloads.lock().await;
+        let removed: Vec<String> = downloads
+            .iter()
+            .filter(|(_, download)| download.owner.as_deref() == Some(owner))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &removed {
+            downloads.remove(id);
+        }
+        removed
+    }
+
+    pub async fn get(&self, id: &str) -> Option<StoredDownload> {
+        self.downloads
+            .lock()
+            .await
+            .get(id)
+            .filter(|download| download.stored_at.elapsed() <= DOWNLOAD_TTL)
+            .cloned()
+    }
+}
+
+/// Strong entity tag derived from the content, including quotes.
+fn entity_tag(bytes: &[u8]) -> String {
+    let hash = content_hash(bytes);
+    format!(
+        "\"{}\"",
+        hash[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>()
+    )
+}
+
+/// Outcome of interpreting a `Range` header against a body of known length.
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    /// No usable range; serve the whole body.
+    Full,
+    Partial(Range<usize>),
+    Unsatisfiable,
+}
+
+/// Interpret a single `bytes=` range. Multiple ranges and unknown units fall
+/// back to the full body, which the HTTP spec allows.
+pub fn parse_range(header: Option<&str>, len: usize) -> RangeRequest {
+    let Some(spec) = header.and_then(|value| value.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<usize>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(suffix) => len.saturating_sub(suffix)..len,
+            Err(_) => return RangeRequest::Full,
+        },
+        (start, "") => match start.parse::<usize>() {
+            Ok(start) => start..len,
+            Err(_) => return RangeRequest::Full,
+        },
+        (start, end) => match (start.parse::<usize>(), end.parse::<usize>()) {
+            (Ok(start), Ok(end)) if start <= end => start..(end + 1).min(len),
+            _ => return RangeRequest::Full,
+        },
+    };
+
+    if range.start >= len {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn originals_are_kept_and_counted_with_their_download() {
+        let store = DownloadStore::default();
+        let id = store
+            .insert_with_original(vec![1, 2], vec![3, 4, 5], Some("alice".into()), None)
+            .await
+            .unwrap();
+        assert!(store.replace(&id, vec![6]).await);
+
+        let download = store.get(&id).await.unwrap();
+        assert_eq!(*download.bytes, vec![6]);
+        assert_eq!(*download.original().unwrap().bytes, vec![3, 4, 5]);
+        assert_eq!(store.stored_bytes("alice").await, 4);
+        assert_eq!(store.remove_owned("alice").await, vec![id.clone()]);
+        assert!(store.get(&id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn owners_are_refused_past_their_storage_limit() {
+        let store = DownloadStore::default();
+        let owner = || Some("alice".to_string());
+        store
+            .insert_with_original(vec![1; 4], vec![2; 4], owner(), Some(10))
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .insert_with_original(vec![1; 2], vec![2; 2], owner(), Some(10))
+                .await,
+            Err(8)
+        );
+        assert!(
+            store
+                .insert_with_original(vec![1; 2], vec![2; 2], Some("bob".into()), Some(10))
+                .await
+                .is_ok()
+        );
+        assert_eq!(store.stored_bytes("alice").await, 8);
+    }
+
+    #[test]
+    fn parses_single_byte_ranges() {
+        assert_eq!(
+            parse_range(Some("bytes=0-3"), 10),
+            RangeRequest::Partial(0..4)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=7-"), 10),
+            RangeRequest::Partial(7..10)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-4"), 10),
+            RangeRequest::Partial(6..10)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=5-50"), 10),
+            RangeRequest::Partial(5..10)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=10-"), 10),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range(Some("bytes=0-1,4-5"), 10), RangeRequest::Full);
+        assert_eq!(parse_range(None, 10), RangeRequest::Full);
+    }
+}
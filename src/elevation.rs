@@ -0,0 +1,333 @@
+//! Altitude lookups from an external digital elevation model, for devices
+//! whose barometer records nonsense.
+//!
+//! The service is any Open-Elevation compatible API: positions are posted to
+//! `RUSTYFIT_ELEVATION_URL` as `{"locations": [{"latitude", "longitude"}]}`
+//! in batches of [`ElevationConfig::batch_size`], and the answer's
+//! `results[].elevation` are read back in order. A lookup spaces its
+//! requests at least [`ElevationConfig::min_interval`] apart without holding
+//! up other uploads' lookups, each request gives up after
+//! [`REQUEST_TIMEOUT`], and elevations are cached by position rounded to
+//! about a metre, so repeated routes are looked up once.
+//! Lookups need the `elevation` feature; without it a configured service is
+//! reported once at startup and lookups fail.
+
+use crate::processing::anonymize::{SEMICIRCLES_TO_DEGREES, record_semicircles};
+use crate::processing::{HashableF64, ProcessingOptions};
+use fitparser::FitDataRecord;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Positions sent per request when `RUSTYFIT_ELEVATION_BATCH_SIZE` is unset.
+pub const DEFAULT_ELEVATION_BATCH_SIZE: usize = 100;
+
+/// Spacing between requests when `RUSTYFIT_ELEVATION_MIN_INTERVAL_MS` is unset.
+pub const DEFAULT_ELEVATION_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest wait for the service to accept a connection.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest wait for one request, answer included.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Elevation API to look altitudes up from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElevationConfig {
+    /// Lookup endpoint, e.g. `https://api.open-elevation.com/api/v1/lookup`.
+    pub url: String,
+    pub batch_size: usize,
+    /// Shortest time between the start of two requests.
+    pub min_interval: Duration,
+}
+
+impl ElevationConfig {
+    /// Service from `RUSTYFIT_ELEVATION_URL`, with
+    /// `RUSTYFIT_ELEVATION_BATCH_SIZE` and `RUSTYFIT_ELEVATION_MIN_INTERVAL_MS`;
+    /// `None` when no URL is set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        let number = |name: &str| var(name)?.trim().parse::<u64>().ok();
+        Some(Self {
+            url: var("RUSTYFIT_ELEVATION_URL")?.trim().to_string(),
+            batch_size: number("RUSTYFIT_ELEVATION_BATCH_SIZE")
+                .filter(|&size| size > 0)
+                .map_or(DEFAULT_ELEVATION_BATCH_SIZE, |size| size as usize),
+            min_interval: number("RUSTYFIT_ELEVATION_MIN_INTERVAL_MS")
+                .map_or(DEFAULT_ELEVATION_MIN_INTERVAL, Duration::from_millis),
+        })
+    }
+}
+
+/// Why a lookup returned no altitudes.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ElevationError {
+    /// No service is configured, or the server was built without the
+    /// `elevation` feature.
+    #[error("no elevation service is configured")]
+    Unavailable,
+    #[error("elevation request failed: {0}")]
+    Request(String),
+    /// The service answered with something other than one elevation per
+    /// position.
+    #[error("unexpected elevation response: {0}")]
+    Response(String),
+}
+
+/// Client for the configured elevation service; every lookup fails with
+/// [`ElevationError::Unavailable`] when none is configured.
+#[derive(Clone, Default)]
+pub struct ElevationService {
+    #[cfg(feature = "elevation")]
+    client: Option<dem::DemClient>,
+}
+
+impl fmt::Debug for ElevationService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ElevationService")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+impl ElevationService {
+    pub fn connect(config: Option<&ElevationConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        #[cfg(feature = "elevation")]
+        {
+            Self {
+                client: Some(dem::DemClient::new(config.clone())),
+            }
+        }
+        #[cfg(not(feature = "elevation"))]
+        {
+            tracing::warn!(
+                service = %config.url,
+                "RUSTYFIT_ELEVATION_URL is set but RustyFit was built without the elevation feature"
+            );
+            Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "elevation")]
+        {
+            self.client.is_some()
+        }
+        #[cfg(not(feature = "elevation"))]
+        {
+            false
+        }
+    }
+
+    /// Elevation in metres of each `(latitude, longitude)` position, in degrees.
+    pub async fn lookup(&self, positions: &[(f64, f64)]) -> Result<Vec<f64>, ElevationError> {
+        #[cfg(feature = "elevation")]
+        if let Some(client) = &self.client {
+            return client.lookup(positions).await;
+        }
+        #[cfg(not(feature = "elevation"))]
+        let _ = positions;
+        Err(ElevationError::Unavailable)
+    }
+
+    /// `options` with [`ProcessingOptions::looked_up_altitudes`] filled in for
+    /// every recorded position of `records`.
+    pub async fn options_for(
+        &self,
+        records: &[FitDataRecord],
+        options: &ProcessingOptions,
+    ) -> Result<ProcessingOptions, ElevationError> {
+        let mut altitudes: BTreeMap<(i32, i32), HashableF64> = records
+            .iter()
+            .filter_map(record_semicircles)
+            .map(|position| (position, HashableF64(0.0)))
+            .collect();
+        let positions: Vec<(f64, f64)> = altitudes
+            .keys()
+            .map(|&(lat, long)| {
+                let degrees = |semicircles: i32| f64::from(semicircles) * SEMICIRCLES_TO_DEGREES;
+                (degrees(lat), degrees(long))
+            })
+            .collect();
+        let elevations = self.lookup(&positions).await?;
+        for (altitude, elevation) in altitudes.values_mut().zip(elevations) {
+            *altitude = HashableF64(elevation);
+        }
+        Ok(ProcessingOptions {
+            looked_up_altitudes: altitudes,
+            ..options.clone()
+        })
+    }
+}
+
+#[cfg(feature = "elevation")]
+mod dem {
+    use super::{CONNECT_TIMEOUT, ElevationConfig, ElevationError, REQUEST_TIMEOUT};
+    use moka::future::Cache;
+    use serde::Deserialize;
+    use std::collections::{HashMap, HashSet};
+    use tokio::time::Instant;
+
+    /// Cached positions; a long ride has tens of thousands.
+    const CACHE_CAPACITY: u64 = 1_000_000;
+
+    /// Positions are cached to 1e-5 degrees, about a metre.
+    const CACHE_KEY_SCALE: f64 = 1e5;
+
+    type CacheKey = (i32, i32);
+
+    #[derive(Deserialize)]
+    struct LookupResponse {
+        results: Vec<LookupResult>,
+    }
+
+    #[derive(Deserialize)]
+    struct LookupResult {
+        elevation: f64,
+    }
+
+    #[derive(Clone)]
+    pub(super) struct DemClient {
+        config: ElevationConfig,
+        http: reqwest::Client,
+        cache: Cache<CacheKey, f64>,
+    }
+
+    fn cache_key((lat, long): (f64, f64)) -> CacheKey {
+        (
+            (lat * CACHE_KEY_SCALE).round() as i32,
+            (long * CACHE_KEY_SCALE).round() as i32,
+        )
+    }
+
+    fn request_body(positions: &[(f64, f64)]) -> Vec<u8> {
+        let locations: Vec<serde_json::Value> = positions
+            .iter()
+            .map(|&(latitude, longitude)| {
+                serde_json::json!({ "latitude": latitude, "longitude": longitude })
+            })
+            .collect();
+        serde_json::json!({ "locations": locations })
+            .to_string()
+            .into_bytes()
+    }
+
+    fn parse_response(body: &[u8], expected: usize) -> Result<Vec<f64>, ElevationError> {
+        let response: LookupResponse = serde_json::from_slice(body)
+            .map_err(|err| ElevationError::Response(err.to_string()))?;
+        if response.results.len() != expected {
+            return Err(ElevationError::Response(format!(
+                "{} elevations for {expected} positions",
+                response.results.len()
+            )));
+        }
+        Ok(response
+            .results
+            .into_iter()
+            .map(|result| result.elevation)
+            .collect())
+    }
+
+    impl DemClient {
+        pub(super) fn new(config: ElevationConfig) -> Self {
+            let http = reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("the HTTP client builds with the default TLS backend");
+            Self {
+                config,
+                http,
+                cache: Cache::new(CACHE_CAPACITY),
+            }
+        }
+
+        pub(super) async fn lookup(
+            &self,
+            positions: &[(f64, f64)],
+        ) -> Result<Vec<f64>, ElevationError> {
+            // Kept here as well as in the cache, which may evict them before
+            // they are read back.
+            let mut found: HashMap<CacheKey, f64> = HashMap::new();
+            let mut missing: Vec<(f64, f64)> = Vec::new();
+            let mut seen = HashSet::new();
+            for &position in positions {
+                let key = cache_key(position);
+                if !seen.insert(key) {
+                    continue;
+                }
+                match self.cache.get(&key).await {
+                    Some(elevation) => {
+                        found.insert(key, elevation);
+                    }
+                    None => missing.push(position),
+                }
+            }
+
+            let mut next_request = Instant::now();
+            for batch in missing.chunks(self.config.batch_size) {
+                tokio::time::sleep_until(next_request).await;
+                next_request = Instant::now() + self.config.min_interval;
+                let elevations = self.request(batch).await?;
+                for (&position, elevation) in batch.iter().zip(elevations) {
+                    found.insert(cache_key(position), elevation);
+                    self.cache.insert(cache_key(position), elevation).await;
+                }
+            }
+            Ok(positions
+                .iter()
+                .map(|&position| found[&cache_key(position)])
+                .collect())
+        }
+
+        async fn request(&self, batch: &[(f64, f64)]) -> Result<Vec<f64>, ElevationError> {
+            let response = self
+                .http
+                .post(&self.config.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(request_body(batch))
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|err| ElevationError::Request(err.to_string()))?;
+            let body = response
+                .bytes()
+                .await
+                .map_err(|err| ElevationError::Request(err.to_string()))?;
+            parse_response(&body, batch.len())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn responses_need_one_elevation_per_position() {
+            let body =
+                br#"{"results": [{"latitude": 46.5, "longitude": 7.9, "elevation": 2061.0}]}"#;
+
+            assert_eq!(parse_response(body, 1), Ok(vec![2061.0]));
+            assert!(matches!(
+                parse_response(body, 2),
+                Err(ElevationError::Response(_))
+            ));
+        }
+
+        #[test]
+        fn nearby_positions_share_a_cache_entry() {
+            assert_eq!(
+                cache_key((46.500001, 7.900002)),
+                cache_key((46.500003, 7.899998))
+            );
+            assert_ne!(cache_key((46.5, 7.9)), cache_key((46.50002, 7.9)));
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Notifications for completed processing jobs, for home-automation and
+//! pipeline integrations.
+//!
+//! Each job that produces a result publishes one JSON [`JobCompleted`] message
+//! to an MQTT broker. Publishing needs the `mqtt` feature; without it a
+//! configured broker is reported once at startup and events are dropped.
+
+use crate::processing::annotations::Annotation;
+use crate::processing::{ProcessedFit, ProcessingWarning, WorkoutSummary};
+use serde::Serialize;
+use std::fmt;
+
+/// Topic used when `RUSTYFIT_MQTT_TOPIC` is unset.
+pub const DEFAULT_MQTT_TOPIC: &str = "rustyfit/jobs";
+
+/// Port used when the broker URL has none.
+pub const DEFAULT_MQTT_PORT: u16 = 1883;
+
+/// Broker to publish job events to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl MqttConfig {
+    /// Broker from `RUSTYFIT_MQTT_URL` (`mqtt://host[:port]`), with
+    /// `RUSTYFIT_MQTT_TOPIC`, `RUSTYFIT_MQTT_USERNAME`, and
+    /// `RUSTYFIT_MQTT_PASSWORD`; `None` when no URL is set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        };
+        let (host, port) = parse_broker_url(&var("RUSTYFIT_MQTT_URL")?)?;
+        Some(Self {
+            host,
+            port,
+            topic: var("RUSTYFIT_MQTT_TOPIC").unwrap_or_else(|| DEFAULT_MQTT_TOPIC.to_string()),
+            username: var("RUSTYFIT_MQTT_USERNAME"),
+            password: var("RUSTYFIT_MQTT_PASSWORD"),
+        })
+    }
+}
+
+/// Host and port of an `mqtt://` or `tcp://` URL.
+fn parse_broker_url(url: &str) -> Option<(String, u16)> {
+    let url = url.trim();
+    let authority = url
+        .strip_prefix("mqtt://")
+        .or_else(|| url.strip_prefix("tcp://"))
+        .unwrap_or(url)
+        .trim_end_matches('/');
+    match authority.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() => Some((host.to_string(), port.parse().ok()?)),
+        Some(_) => None,
+        None if !authority.is_empty() => Some((authority.to_string(), DEFAULT_MQTT_PORT)),
+        None => None,
+    }
+}
+
+/// Where a completed job's file came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobSource {
+    Upload,
+    Url,
+    WatchFolder,
+}
+
+/// Message published for each processed file.
+#[derive(Debug, Serialize)]
+pub struct JobCompleted<'a> {
+    pub file_name: &'a str,
+    pub source: JobSource,
+    /// Signed download link; absent for watch-folder jobs, which write files instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<&'a str>,
+    pub summary: &'a WorkoutSummary,
+    pub annotations: &'a [Annotation],
+    pub warnings: &'a [ProcessingWarning],
+}
+
+impl<'a> JobCompleted<'a> {
+    pub fn new(
+        file_name: &'a str,
+        source: JobSource,
+        download_url: Option<&'a str>,
+        processed: &'a ProcessedFit,
+    ) -> Self {
+        Self {
+            file_name,
+            source,
+            download_url,
+            summary: &processed.summary,
+            annotations: &processed.annotations.regions,
+            warnings: &processed.warnings,
+        }
+    }
+}
+
+/// Publisher of [`JobCompleted`] events; does nothing when no broker is configured.
+#[derive(Clone, Default)]
+pub struct JobEvents {
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<mqtt::MqttPublisher>,
+}
+
+impl fmt::Debug for JobEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobEvents")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+impl JobEvents {
+    /// Connect to `config`'s broker. Must be called inside a Tokio runtime, as
+    /// the connection is driven by a background task.
+    pub fn connect(config: Option<&MqttConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        #[cfg(feature = "mqtt")]
+        {
+            Self {
+                mqtt: Some(mqtt::MqttPublisher::connect(config)),
+            }
+        }
+        #[cfg(not(feature = "mqtt"))]
+        {
+            tracing::warn!(
+                broker = %config.host,
+                "RUSTYFIT_MQTT_URL is set but RustyFit was built without the mqtt feature"
+            );
+            Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "mqtt")]
+        {
+            self.mqtt.is_some()
+        }
+        #[cfg(not(feature = "mqtt"))]
+        {
+            false
+        }
+    }
+
+    /// Publish `event` without waiting for the broker; failures are logged
+    /// and never fail or hold up the job.
+    pub fn publish(&self, event: &JobCompleted<'_>) {
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &self.mqtt {
+            match serde_json::to_vec(event) {
+                Ok(payload) => mqtt.publish(payload),
+                Err(err) => tracing::warn!(error = %err, "failed to serialize job event"),
+            }
+        }
+        #[cfg(not(feature = "mqtt"))]
+        let _ = event;
+    }
+}
+
+#[cfg(feature = "mqtt")]
+mod mqtt {
+    use super::MqttConfig;
+    use rumqttc::{AsyncClient, ClientError, MqttOptions, QoS};
+    use std::time::Duration;
+
+    /// Messages buffered while the broker is unreachable; later events are
+    /// dropped until it catches up.
+    const REQUEST_CAPACITY: usize = 64;
+
+    #[derive(Clone)]
+    pub(super) struct MqttPublisher {
+        client: AsyncClient,
+        topic: String,
+    }
+
+    impl MqttPublisher {
+        pub(super) fn connect(config: &MqttConfig) -> Self {
+            // A unique client ID lets the server and watch folder hold separate sessions.
+            let client_id = format!("rustyfit-{}", uuid::Uuid::new_v4().simple());
+            let mut options = MqttOptions::new(client_id, &config.host, config.port);
+            options.set_keep_alive(Duration::from_secs(30));
+            if let (Some(username), Some(password)) = (&config.username, &config.password) {
+                options.set_credentials(username, password);
+            }
+            let (client, mut event_loop) = AsyncClient::new(options, REQUEST_CAPACITY);
+
+            let broker = config.host.clone();
+            tokio::spawn(async move {
+                loop {
+                    if let Err(err) = event_loop.poll().await {
+                        tracing::warn!(%broker, error = %err, "MQTT connection failed; retrying");
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            });
+
+            Self {
+                client,
+                topic: config.topic.clone(),
+            }
+        }
+
+        /// Queue `payload` for the broker, dropping it when the queue is full
+        /// rather than waiting for an unreachable broker.
+        pub(super) fn publish(&self, payload: Vec<u8>) {
+            match self
+                .client
+                .try_publish(&self.topic, QoS::AtLeastOnce, false, payload)
+            {
+                Ok(()) => {}
+                Err(ClientError::TryRequest(_)) => tracing::warn!(
+                    topic = %self.topic,
+                    "MQTT queue is full; dropped job event"
+                ),
+                Err(err) => {
+                    tracing::warn!(topic = %self.topic, error = %err, "failed to publish job event");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broker_urls_default_the_port() {
+        assert_eq!(
+            parse_broker_url("mqtt://broker.local"),
+            Some(("broker.local".to_string(), DEFAULT_MQTT_PORT))
+        );
+        assert_eq!(
+            parse_broker_url("tcp://10.0.0.2:8883/"),
+            Some(("10.0.0.2".to_string(), 8883))
+        );
+        assert_eq!(parse_broker_url("mqtt://:1883"), None);
+        assert_eq!(parse_broker_url("mqtt://host:port"), None);
+    }
+}
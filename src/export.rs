@@ -0,0 +1,109 @@
+//! Bundles of everything stored for one account, so users can take their data
+//! with them before deleting it.
+
+use crate::downloads::StoredDownload;
+use crate::history::HistoryEntry;
+use serde::Serialize;
+use std::io::{self, Cursor, Write};
+use std::time::SystemTime;
+use zip::CompressionMethod;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// One processed file in the bundle's manifest.
+#[derive(Serialize)]
+struct ExportedFile {
+    id: String,
+    /// Path of the file inside the archive.
+    path: String,
+    bytes: usize,
+    processed_at: String,
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    account: &'a str,
+    exported_at: String,
+    activities: usize,
+    files: Vec<ExportedFile>,
+}
+
+/// Zip archive holding `manifest.json`, every remembered activity summary in
+/// `summaries.json`, and the processed files still held under `files/`.
+pub fn account_bundle(
+    account: &str,
+    activities: &[HistoryEntry],
+    downloads: &[(String, StoredDownload)],
+) -> io::Result<Vec<u8>> {
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut files = Vec::with_capacity(downloads.len());
+    for (id, download) in downloads {
+        let path = format!("files/{id}.fit");
+        archive.start_file(path.as_str(), options)?;
+        archive.write_all(&download.bytes)?;
+        files.push(ExportedFile {
+            id: id.clone(),
+            path,
+            bytes: download.bytes.len(),
+            processed_at: rfc3339(download.last_modified),
+        });
+    }
+
+    archive.start_file("summaries.json", options)?;
+    serde_json::to_writer_pretty(&mut archive, activities)?;
+
+    archive.start_file("manifest.json", options)?;
+    let manifest = Manifest {
+        account,
+        exported_at: rfc3339(SystemTime::now()),
+        activities: activities.len(),
+        files,
+    };
+    serde_json::to_writer_pretty(&mut archive, &manifest)?;
+
+    Ok(archive.finish()?.into_inner())
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloads::DownloadStore;
+    use crate::processing::WorkoutSummary;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn bundles_hold_summaries_files_and_a_manifest() {
+        let downloads = DownloadStore::default();
+        let id = downloads.insert(vec![1, 2, 3], Some("alice".into())).await;
+        let activities = vec![HistoryEntry {
+            file_name: "ride.fit".into(),
+            summary: WorkoutSummary::default(),
+            gear: Some("road bike".into()),
+            owner: Some("alice".into()),
+        }];
+
+        let bundle =
+            account_bundle("alice", &activities, &downloads.owned_by("alice").await).unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(bundle)).unwrap();
+        let mut fit = Vec::new();
+        archive
+            .by_name(&format!("files/{id}.fit"))
+            .unwrap()
+            .read_to_end(&mut fit)
+            .unwrap();
+        assert_eq!(fit, [1, 2, 3]);
+        let summaries: serde_json::Value =
+            serde_json::from_reader(archive.by_name("summaries.json").unwrap()).unwrap();
+        assert_eq!(summaries[0]["gear"], "road bike");
+        let manifest: serde_json::Value =
+            serde_json::from_reader(archive.by_name("manifest.json").unwrap()).unwrap();
+        assert_eq!(manifest["account"], "alice");
+        assert_eq!(manifest["files"][0]["bytes"], 3);
+    }
+}
@@ -0,0 +1,282 @@
+//! Download FIT files from user-supplied URLs without letting the URL reach
+//! into the server's own network.
+//!
+//! Only `https` URLs are fetched. Every address the host resolves to must be
+//! publicly routable, and the connection is pinned to the vetted address so
+//! a second DNS answer can't redirect it. Redirects and proxies are never
+//! followed, and the download is cut off at [`FetchLimits::max_bytes`] and
+//! [`FetchLimits::timeout`].
+
+use reqwest::Url;
+use reqwest::redirect::Policy;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+/// Largest download accepted by default; activity files are rarely over a few MB.
+pub const DEFAULT_FETCH_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+/// Default cap on the whole download, connection included.
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Name given to files whose URL path has no usable last segment.
+const FALLBACK_FILE_NAME: &str = "download.fit";
+
+/// Bounds on one URL download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchLimits {
+    pub max_bytes: usize,
+    pub timeout: Duration,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_FETCH_MAX_BYTES,
+            timeout: DEFAULT_FETCH_TIMEOUT,
+        }
+    }
+}
+
+impl FetchLimits {
+    /// Defaults overridden by `RUSTYFIT_FETCH_MAX_BYTES` and
+    /// `RUSTYFIT_FETCH_TIMEOUT_SECONDS`.
+    pub fn from_env() -> Self {
+        let read = |name: &str| -> Option<u64> { std::env::var(name).ok()?.trim().parse().ok() };
+        let defaults = Self::default();
+        Self {
+            max_bytes: read("RUSTYFIT_FETCH_MAX_BYTES")
+                .filter(|&bytes| bytes > 0)
+                .map_or(defaults.max_bytes, |bytes| bytes as usize),
+            timeout: read("RUSTYFIT_FETCH_TIMEOUT_SECONDS")
+                .filter(|&seconds| seconds > 0)
+                .map_or(defaults.timeout, Duration::from_secs),
+        }
+    }
+}
+
+/// A downloaded file.
+#[derive(Debug, Clone)]
+pub struct FetchedFile {
+    /// Last segment of the URL path.
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    /// The URL is not `https`.
+    #[error("Only https URLs can be fetched, not {0}")]
+    InsecureScheme(String),
+    /// The host resolves to a loopback, private, or otherwise internal address.
+    #[error("URL resolves to a non-public address ({0})")]
+    ForbiddenAddress(IpAddr),
+    #[error("Could not resolve host: {0}")]
+    Resolve(String),
+    #[error("Remote file is larger than {limit} bytes")]
+    TooLarge { limit: usize },
+    #[error("Timed out fetching the URL")]
+    TimedOut,
+    /// The server answered with something other than 200, redirects included.
+    #[error("Remote server answered with status {0}")]
+    Status(u16),
+    #[error("Failed to fetch the URL: {0}")]
+    Request(String),
+}
+
+/// Download the FIT file at `url` within `limits`.
+pub async fn fetch_fit(url: &str, limits: &FetchLimits) -> Result<FetchedFile, FetchError> {
+    let url = Url::parse(url.trim()).map_err(|err| FetchError::InvalidUrl(err.to_string()))?;
+    if url.scheme() != "https" {
+        return Err(FetchError::InsecureScheme(url.scheme().to_string()));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| FetchError::InvalidUrl("missing host".to_string()))?
+        .to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let address = resolve_public(&host, port).await?;
+
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .no_proxy()
+        .timeout(limits.timeout)
+        .resolve(&host, address)
+        .build()
+        .map_err(|err| FetchError::Request(err.to_string()))?;
+    let mut response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(request_error)?;
+    if response.status() != reqwest::StatusCode::OK {
+        return Err(FetchError::Status(response.status().as_u16()));
+    }
+    if response
+        .content_length()
+        .is_some_and(|length| length > limits.max_bytes as u64)
+    {
+        return Err(FetchError::TooLarge {
+            limit: limits.max_bytes,
+        });
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(request_error)? {
+        if bytes.len() + chunk.len() > limits.max_bytes {
+            return Err(FetchError::TooLarge {
+                limit: limits.max_bytes,
+            });
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(FetchedFile {
+        file_name: file_name(&url),
+        bytes,
+    })
+}
+
+fn request_error(err: reqwest::Error) -> FetchError {
+    if err.is_timeout() {
+        FetchError::TimedOut
+    } else {
+        FetchError::Request(err.to_string())
+    }
+}
+
+/// The first address of `host`, provided every address it resolves to is public.
+async fn resolve_public(host: &str, port: u16) -> Result<SocketAddr, FetchError> {
+    // IPv6 literals keep their brackets in `host_str`.
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<SocketAddr> = match literal.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|err| FetchError::Resolve(err.to_string()))?
+            .collect(),
+    };
+    if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+        return Err(FetchError::ForbiddenAddress(address.ip()));
+    }
+    addresses
+        .first()
+        .copied()
+        .ok_or_else(|| FetchError::Resolve(format!("no addresses for {host}")))
+}
+
+/// Whether `ip` is globally routable, i.e. not loopback, private, link-local,
+/// shared (CGNAT), documentation, benchmarking, multicast, or reserved space.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match embedded_v4(ip).as_slice() {
+            [] => is_public_v6(ip),
+            embedded => embedded.iter().all(|&v4| is_public_v4(v4)),
+        },
+    }
+}
+
+/// IPv4 addresses an IPv6 address leads to: IPv4-mapped and -compatible
+/// (`::ffff:a.b.c.d`, `::a.b.c.d`), 6to4 (`2002::/16`), and the server and
+/// client of Teredo (`2001::/32`, client bits inverted).
+fn embedded_v4(ip: Ipv6Addr) -> Vec<Ipv4Addr> {
+    let v4 = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+    match ip.segments() {
+        [0, 0, 0, 0, 0, 0 | 0xffff, high, low] => vec![v4(high, low)],
+        [0x2002, high, low, ..] => vec![v4(high, low)],
+        [0x2001, 0, server_hi, server_lo, _, _, client_hi, client_lo] => {
+            vec![v4(server_hi, server_lo), v4(!client_hi, !client_lo)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local (fc00::/7) and link-local (fe80::/10).
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        // Documentation (2001:db8::/32) and NAT64 (64:ff9b::/96).
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+        || (first == 0x0064 && ip.segments()[1] == 0xff9b))
+}
+
+fn file_name(url: &Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .map_or_else(|| FALLBACK_FILE_NAME.to_string(), str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_globally_routable_addresses_are_public() {
+        let public = [
+            "93.184.216.34",
+            "2606:4700:4700::1111",
+            "1.1.1.1",
+            "2002:5db8:d822::1",
+        ];
+        let internal = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::10.0.0.1",
+            "2002:a9fe:a9fe::1",
+            // Teredo via a public server to 127.0.0.1.
+            "2001:0:5db8:d822:0:0:80ff:fffe",
+        ];
+
+        for ip in public {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in internal {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_plain_http_and_internal_hosts_before_connecting() {
+        let limits = FetchLimits::default();
+
+        let insecure = fetch_fit("http://example.com/ride.fit", &limits).await;
+        assert!(matches!(insecure, Err(FetchError::InsecureScheme(_))));
+
+        let metadata = fetch_fit("https://169.254.169.254/latest/meta-data", &limits).await;
+        assert!(matches!(metadata, Err(FetchError::ForbiddenAddress(_))));
+
+        let loopback = fetch_fit("https://[::1]:8443/ride.fit", &limits).await;
+        assert!(matches!(loopback, Err(FetchError::ForbiddenAddress(_))));
+    }
+}
@@ -0,0 +1,240 @@
+//! C ABI for embedding the processing engine in other languages.
+//!
+//! Callers pass a FIT buffer and an optional JSON options string, receive the
+//! re-encoded FIT bytes and a JSON summary in a [`RustyFitOutput`], and hand
+//! that output back to [`rustyfit_output_free`] once done with it.
+//!
+//! Status codes cross the boundary as plain `u32`s, so a code the library
+//! does not know is never read as an enum, and panics are caught before they
+//! can unwind into the caller.
+
+use crate::processing::{self, ProcessingOptions};
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Status codes returned by [`rustyfit_process`] as their `u32` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RustyFitStatus {
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer = 1,
+    /// The options string was not UTF-8 or not a valid options object.
+    InvalidOptions = 2,
+    /// The input could not be decoded or re-encoded as FIT.
+    ProcessingFailed = 3,
+    /// The summary could not be serialized.
+    SummaryFailed = 4,
+    /// The library panicked; the output is left empty.
+    Panicked = 5,
+}
+
+impl RustyFitStatus {
+    const ALL: [RustyFitStatus; 6] = [
+        RustyFitStatus::Ok,
+        RustyFitStatus::NullPointer,
+        RustyFitStatus::InvalidOptions,
+        RustyFitStatus::ProcessingFailed,
+        RustyFitStatus::SummaryFailed,
+        RustyFitStatus::Panicked,
+    ];
+
+    /// The status with code `code`, if there is one.
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| *status as u32 == code)
+    }
+}
+
+/// Buffers produced by a successful [`rustyfit_process`] call.
+///
+/// All pointers are owned by the library and must be released with
+/// [`rustyfit_output_free`].
+#[repr(C)]
+pub struct RustyFitOutput {
+    pub bytes: *mut u8,
+    pub bytes_len: usize,
+    /// NUL-terminated JSON object with `summary`, `devices`, and `warnings`.
+    pub summary_json: *mut c_char,
+}
+
+/// Process `len` bytes at `data` and write the result to `out`, returning a
+/// [`RustyFitStatus`] code.
+///
+/// `options_json` may be null to use the default options. On failure `out`
+/// is left empty and nothing needs to be freed.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, `options_json` must be null or
+/// a NUL-terminated string, and `out` must point to writable memory for one
+/// [`RustyFitOutput`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rustyfit_process(
+    data: *const u8,
+    len: usize,
+    options_json: *const c_char,
+    out: *mut RustyFitOutput,
+) -> u32 {
+    // SAFETY: the caller upholds the contract of `process` for these arguments.
+    let status = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        process(data, len, options_json, out)
+    }));
+    status.unwrap_or(RustyFitStatus::Panicked) as u32
+}
+
+/// [`rustyfit_process`] without the panic guard.
+///
+/// # Safety
+///
+/// As for [`rustyfit_process`].
+unsafe fn process(
+    data: *const u8,
+    len: usize,
+    options_json: *const c_char,
+    out: *mut RustyFitOutput,
+) -> RustyFitStatus {
+    if data.is_null() || out.is_null() {
+        return RustyFitStatus::NullPointer;
+    }
+    // SAFETY: `out` is non-null and writable per the function contract.
+    unsafe {
+        out.write(RustyFitOutput {
+            bytes: ptr::null_mut(),
+            bytes_len: 0,
+            summary_json: ptr::null_mut(),
+        })
+    };
+
+    let options = if options_json.is_null() {
+        ProcessingOptions::default()
+    } else {
+        // SAFETY: non-null `options_json` is NUL-terminated per the function contract.
+        let raw = unsafe { CStr::from_ptr(options_json) };
+        match raw.to_str().ok().map(ProcessingOptions::from_json) {
+            Some(Ok(options)) => options,
+            _ => return RustyFitStatus::InvalidOptions,
+        }
+    };
+
+    // SAFETY: `data` is non-null and points to `len` bytes per the function contract.
+    let input = unsafe { std::slice::from_raw_parts(data, len) };
+    let processed = match processing::process_fit_bytes(input, &options) {
+        Ok(processed) => processed,
+        Err(_) => return RustyFitStatus::ProcessingFailed,
+    };
+    let summary_json = match processed
+        .summary_json()
+        .ok()
+        .and_then(|json| CString::new(json).ok())
+    {
+        Some(json) => json,
+        None => return RustyFitStatus::SummaryFailed,
+    };
+
+    let bytes = Box::into_raw(processed.processed_bytes.into_boxed_slice());
+    // SAFETY: `out` is non-null and writable per the function contract.
+    unsafe {
+        out.write(RustyFitOutput {
+            bytes: bytes.cast::<u8>(),
+            bytes_len: bytes.len(),
+            summary_json: summary_json.into_raw(),
+        })
+    };
+    RustyFitStatus::Ok
+}
+
+/// Release the buffers of an output filled by [`rustyfit_process`].
+///
+/// Safe to call on an empty or already freed output; the fields are reset to
+/// null.
+///
+/// # Safety
+///
+/// `out` must be null or point to an output written by [`rustyfit_process`]
+/// whose buffers have not been freed elsewhere.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rustyfit_output_free(out: *mut RustyFitOutput) {
+    // Freeing does not panic, but nothing may unwind across the boundary.
+    // SAFETY: the caller upholds the contract of `free_output`.
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| unsafe { free_output(out) }));
+}
+
+/// [`rustyfit_output_free`] without the panic guard.
+///
+/// # Safety
+///
+/// As for [`rustyfit_output_free`].
+unsafe fn free_output(out: *mut RustyFitOutput) {
+    // SAFETY: `out` is null or valid per the function contract.
+    let Some(out) = (unsafe { out.as_mut() }) else {
+        return;
+    };
+    if !out.bytes.is_null() {
+        // SAFETY: `bytes`/`bytes_len` came from a boxed slice in `rustyfit_process`.
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(out.bytes, out.bytes_len)) });
+    }
+    if !out.summary_json.is_null() {
+        // SAFETY: `summary_json` came from `CString::into_raw` in `rustyfit_process`.
+        drop(unsafe { CString::from_raw(out.summary_json) });
+    }
+    out.bytes = ptr::null_mut();
+    out.bytes_len = 0;
+    out.summary_json = ptr::null_mut();
+}
+
+/// Static, NUL-terminated description of a status code; codes the library
+/// does not know get a generic message.
+#[unsafe(no_mangle)]
+pub extern "C" fn rustyfit_status_message(status: u32) -> *const c_char {
+    let message: &'static CStr = match RustyFitStatus::from_code(status) {
+        Some(RustyFitStatus::Ok) => c"ok",
+        Some(RustyFitStatus::NullPointer) => c"a required pointer argument was null",
+        Some(RustyFitStatus::InvalidOptions) => c"options are not a valid JSON object",
+        Some(RustyFitStatus::ProcessingFailed) => c"failed to decode or re-encode the FIT data",
+        Some(RustyFitStatus::SummaryFailed) => c"failed to serialize the summary",
+        Some(RustyFitStatus::Panicked) => c"internal error while processing",
+        None => c"unknown status code",
+    };
+    message.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_output() -> RustyFitOutput {
+        RustyFitOutput {
+            bytes: ptr::null_mut(),
+            bytes_len: 0,
+            summary_json: ptr::null_mut(),
+        }
+    }
+
+    #[test]
+    fn rejects_null_input_and_bad_options() {
+        let mut out = empty_output();
+        let status = unsafe { rustyfit_process(ptr::null(), 0, ptr::null(), &mut out) };
+        assert_eq!(status, RustyFitStatus::NullPointer as u32);
+
+        let data = [0u8; 4];
+        let options = c"{\"smooth_speed\": \"yes\"}";
+        let status =
+            unsafe { rustyfit_process(data.as_ptr(), data.len(), options.as_ptr(), &mut out) };
+        assert_eq!(status, RustyFitStatus::InvalidOptions as u32);
+        assert!(out.bytes.is_null() && out.summary_json.is_null());
+
+        unsafe { rustyfit_output_free(&mut out) };
+    }
+
+    #[test]
+    fn unknown_status_codes_get_a_message() {
+        let message = unsafe { CStr::from_ptr(rustyfit_status_message(99)) };
+        assert_eq!(message, c"unknown status code");
+        let message = unsafe { CStr::from_ptr(rustyfit_status_message(5)) };
+        assert_eq!(message, c"internal error while processing");
+        assert_eq!(
+            RustyFitStatus::from_code(3),
+            Some(RustyFitStatus::ProcessingFailed)
+        );
+    }
+}
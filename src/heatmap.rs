@@ -0,0 +1,289 @@
+//! Personal route heatmap built from the GPS tracks in the upload history.
+//!
+//! Tracks are binned into Web Mercator tiles at [`CELL_ZOOM`] (roughly 150 m
+//! across at the equator, less towards the poles), counting each activity at
+//! most once per cell so that a long stop does not outshine a daily commute.
+//! The counts are served at `/heatmap` as a GeoJSON layer of cell squares,
+//! which any web map can style by their `intensity`. Each account has a
+//! layer of its own uploads, and uploads made without one share another.
+
+use crate::history::HistoryStore;
+use crate::processing::track::TrackPoint;
+use axum::body::Bytes;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Zoom level whose tiles are the heatmap cells.
+pub const CELL_ZOOM: u32 = 18;
+
+/// Consecutive points further apart than this many cells are treated as a
+/// gap in the recording rather than a straight line between them.
+const MAX_SEGMENT_CELLS: f64 = 64.0;
+
+/// Activities per cell across a set of tracks.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Heatmap {
+    /// Activities that crossed each cell, keyed by tile `(x, y)`.
+    pub cells: BTreeMap<(u32, u32), u32>,
+    /// Tracks the heatmap was built from.
+    pub activities: usize,
+}
+
+impl Heatmap {
+    pub fn build<'a>(tracks: impl IntoIterator<Item = &'a [TrackPoint]>) -> Self {
+        let mut heatmap = Heatmap::default();
+        for track in tracks {
+            let mut visited = HashSet::new();
+            let positions: Vec<(f64, f64)> =
+                track.iter().map(|&point| tile_position(point)).collect();
+            if let Some(&(x, y)) = positions.first() {
+                visited.insert(cell(x, y));
+            }
+            for pair in positions.windows(2) {
+                let [(x0, y0), (x1, y1)] = [pair[0], pair[1]];
+                let span = (x1 - x0).abs().max((y1 - y0).abs());
+                if span > MAX_SEGMENT_CELLS {
+                    visited.insert(cell(x1, y1));
+                    continue;
+                }
+                // Step at most one cell at a time so no crossed cell is skipped.
+                let steps = span.ceil().max(1.0) as u32;
+                for step in 1..=steps {
+                    let t = f64::from(step) / f64::from(steps);
+                    visited.insert(cell(x0 + (x1 - x0) * t, y0 + (y1 - y0) * t));
+                }
+            }
+            for cell in visited {
+                *heatmap.cells.entry(cell).or_default() += 1;
+            }
+            heatmap.activities += 1;
+        }
+        heatmap
+    }
+
+    /// The cells as a GeoJSON `FeatureCollection` of squares, each with the
+    /// activities that crossed it as `count` and relative to the busiest
+    /// cell as `intensity`.
+    pub fn to_geojson(&self) -> Value {
+        let busiest = self.cells.values().copied().max().unwrap_or(1);
+        let features: Vec<Value> = self
+            .cells
+            .iter()
+            .map(|(&(x, y), &count)| {
+                let (west, north) = tile_corner(x, y);
+                let (east, south) = tile_corner(x + 1, y + 1);
+                json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[
+                            [west, south],
+                            [east, south],
+                            [east, north],
+                            [west, north],
+                            [west, south],
+                        ]],
+                    },
+                    "properties": {
+                        "count": count,
+                        "intensity": f64::from(count) / f64::from(busiest),
+                    },
+                })
+            })
+            .collect();
+        json!({
+            "type": "FeatureCollection",
+            "features": features,
+            "activities": self.activities,
+            "cell_zoom": CELL_ZOOM,
+        })
+    }
+}
+
+/// A built [`Heatmap`] with its GeoJSON, serialized once per rebuild so
+/// requests only copy a handle to it.
+#[derive(Debug, Clone)]
+pub struct HeatmapSnapshot {
+    pub heatmap: Heatmap,
+    pub geojson: Bytes,
+}
+
+impl HeatmapSnapshot {
+    fn new(heatmap: Heatmap) -> Self {
+        let geojson = Bytes::from(heatmap.to_geojson().to_string());
+        Self { heatmap, geojson }
+    }
+}
+
+impl Default for HeatmapSnapshot {
+    fn default() -> Self {
+        Self::new(Heatmap::default())
+    }
+}
+
+/// The latest [`Heatmap`] of one owner's history, rebuilt on a background
+/// task so uploads never wait for it.
+#[derive(Clone, Default)]
+pub struct HeatmapLayer {
+    /// Account whose uploads the layer shows; `None` for those without one.
+    owner: Option<String>,
+    current: Arc<RwLock<Arc<HeatmapSnapshot>>>,
+    rebuilding: Arc<AtomicBool>,
+    stale: Arc<AtomicBool>,
+}
+
+impl HeatmapLayer {
+    pub fn current(&self) -> Arc<HeatmapSnapshot> {
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Rebuild the heatmap from `history` in the background. Requests made
+    /// while a rebuild runs are folded into one more rebuild after it.
+    pub fn refresh(&self, history: HistoryStore) {
+        self.stale.store(true, Ordering::SeqCst);
+        if self.rebuilding.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let layer = self.clone();
+        tokio::spawn(async move {
+            loop {
+                while layer.stale.swap(false, Ordering::SeqCst) {
+                    let tracks = history.tracks(layer.owner.as_deref()).await;
+                    let built = tokio::task::spawn_blocking(move || {
+                        HeatmapSnapshot::new(Heatmap::build(
+                            tracks.iter().map(|track| track.as_slice()),
+                        ))
+                    })
+                    .await;
+                    if let Ok(snapshot) = built {
+                        *layer
+                            .current
+                            .write()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(snapshot);
+                    }
+                }
+                layer.rebuilding.store(false, Ordering::SeqCst);
+                // A refresh between the last check and the store above found
+                // the rebuild still running; pick it up unless another task did.
+                if !layer.stale.load(Ordering::SeqCst)
+                    || layer.rebuilding.swap(true, Ordering::SeqCst)
+                {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// A [`HeatmapLayer`] per owner of uploads, created on first use.
+#[derive(Clone, Default)]
+pub struct HeatmapLayers {
+    layers: Arc<Mutex<HashMap<Option<String>, HeatmapLayer>>>,
+}
+
+impl HeatmapLayers {
+    /// The layer of `owner`'s uploads; `None` for those without an account.
+    pub fn layer(&self, owner: Option<&str>) -> HeatmapLayer {
+        self.layers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry(owner.map(str::to_string))
+            .or_insert_with(|| HeatmapLayer {
+                owner: owner.map(str::to_string),
+                ..HeatmapLayer::default()
+            })
+            .clone()
+    }
+
+    /// [`HeatmapLayer::refresh`] the layer of `owner`'s uploads.
+    pub fn refresh(&self, history: HistoryStore, owner: Option<&str>) {
+        self.layer(owner).refresh(history);
+    }
+}
+
+/// Fractional tile coordinates of `point` at [`CELL_ZOOM`].
+fn tile_position(point: TrackPoint) -> (f64, f64) {
+    let tiles = f64::from(1u32 << CELL_ZOOM);
+    // Web Mercator is undefined at the poles; clamp to its usual bounds.
+    let lat = point.lat.clamp(-85.051_128, 85.051_128).to_radians();
+    let x = (point.lon + 180.0) / 360.0 * tiles;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * tiles;
+    (x, y)
+}
+
+fn cell(x: f64, y: f64) -> (u32, u32) {
+    let last = f64::from((1u32 << CELL_ZOOM) - 1);
+    (x.clamp(0.0, last) as u32, y.clamp(0.0, last) as u32)
+}
+
+/// Longitude and latitude of the north-west corner of tile `(x, y)`.
+fn tile_corner(x: u32, y: u32) -> (f64, f64) {
+    let tiles = f64::from(1u32 << CELL_ZOOM);
+    let lon = f64::from(x) / tiles * 360.0 - 180.0;
+    let lat = (PI * (1.0 - 2.0 * f64::from(y) / tiles))
+        .sinh()
+        .atan()
+        .to_degrees();
+    (lon, lat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(lat: f64, lon: f64) -> TrackPoint {
+        TrackPoint {
+            lat,
+            lon,
+            ..TrackPoint::default()
+        }
+    }
+
+    fn line(from: (f64, f64), to: (f64, f64), points: usize) -> Vec<TrackPoint> {
+        (0..points)
+            .map(|i| {
+                let t = i as f64 / (points - 1) as f64;
+                at(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shared_routes_are_counted_once_per_activity() {
+        // About 1.5 km east along 47°N, sampled sparsely, and a stop.
+        let commute = line((47.0, 8.0), (47.0, 8.02), 5);
+        let stop = vec![at(47.0, 8.0); 100];
+
+        let heatmap = Heatmap::build([commute.as_slice(), commute.as_slice(), stop.as_slice()]);
+
+        assert_eq!(heatmap.activities, 3);
+        let start = cell(tile_position(commute[0]).0, tile_position(commute[0]).1);
+        assert_eq!(heatmap.cells[&start], 3);
+        // Every cell between the sparse points is filled in.
+        let xs: Vec<u32> = heatmap.cells.keys().map(|&(x, _)| x).collect();
+        assert!(xs.windows(2).all(|pair| pair[1] == pair[0] + 1));
+        assert!(heatmap.cells.values().all(|&count| count >= 2));
+    }
+
+    #[test]
+    fn cells_are_served_as_geojson_squares() {
+        let point = at(47.3769, 8.5417);
+        let geojson = Heatmap::build([[point].as_slice()]).to_geojson();
+
+        let feature = &geojson["features"][0];
+        assert_eq!(feature["properties"]["count"], 1);
+        assert_eq!(feature["properties"]["intensity"], 1.0);
+        let ring = feature["geometry"]["coordinates"][0].as_array().unwrap();
+        assert_eq!(ring.len(), 5);
+        let [west, south] = [ring[0][0].as_f64().unwrap(), ring[0][1].as_f64().unwrap()];
+        let [east, north] = [ring[2][0].as_f64().unwrap(), ring[2][1].as_f64().unwrap()];
+        assert!(west <= point.lon && point.lon < east);
+        assert!(south < point.lat && point.lat <= north);
+    }
+}
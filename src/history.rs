@@ -0,0 +1,233 @@
+use crate::processing::WorkoutSummary;
+use crate::processing::metrics::MeanMaxCurve;
+use crate::processing::track::TrackPoint;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// A processed upload remembered for reporting.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub file_name: String,
+    pub summary: WorkoutSummary,
+    /// Bike or shoes the activity was tagged with.
+    pub gear: Option<String>,
+    /// Account the upload was processed for, when accounts are configured.
+    #[serde(skip)]
+    pub owner: Option<String>,
+    /// Recorded positions, for the heatmap; empty for indoor activities.
+    #[serde(skip)]
+    pub track: Arc<Vec<TrackPoint>>,
+}
+
+/// Mean-max curves of the stored activities.
+#[derive(Debug, Clone, Serialize)]
+pub struct CurveHistory {
+    /// Best value at each duration across every activity.
+    pub aggregate: MeanMaxCurve,
+    pub activities: Vec<ActivityCurve>,
+}
+
+/// Accumulated use of one piece of gear, e.g. for retiring worn shoes.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GearUsage {
+    pub gear: String,
+    pub activities: usize,
+    pub distance_meters: f64,
+    pub duration_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityCurve {
+    pub file_name: String,
+    pub start_timestamp: Option<f64>,
+    pub curve: MeanMaxCurve,
+}
+
+/// Uploads remembered unless `RUSTYFIT_HISTORY_MAX_ENTRIES` says otherwise.
+pub const MAX_HISTORY_ENTRIES: usize = 10_000;
+
+/// In-memory store of summaries from the latest processed uploads, up to its
+/// capacity; the oldest are forgotten first.
+#[derive(Clone)]
+pub struct HistoryStore {
+    entries: Arc<Mutex<VecDeque<HistoryEntry>>>,
+    capacity: usize,
+}
+
+impl Default for HistoryStore {
+    fn default() -> Self {
+        Self::with_capacity(MAX_HISTORY_ENTRIES)
+    }
+}
+
+impl HistoryStore {
+    /// Remember at most `capacity` uploads; `0` remembers none.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Arc::default(),
+            capacity,
+        }
+    }
+
+    /// Capacity from `RUSTYFIT_HISTORY_MAX_ENTRIES`.
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("RUSTYFIT_HISTORY_MAX_ENTRIES")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(MAX_HISTORY_ENTRIES);
+        Self::with_capacity(capacity)
+    }
+
+    pub async fn record(&self, file_name: String, summary: WorkoutSummary, gear: Option<String>) {
+        self.record_for(None, file_name, summary, gear, Vec::new())
+            .await;
+    }
+
+    /// [`HistoryStore::record`] for an upload processed for `owner`, with
+    /// its GPS track.
+    pub async fn record_for(
+        &self,
+        owner: Option<String>,
+        file_name: String,
+        summary: WorkoutSummary,
+        gear: Option<String>,
+        track: Vec<TrackPoint>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().await;
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(HistoryEntry {
+            file_name,
+            summary,
+            gear,
+            owner,
+            track: Arc::new(track),
+        });
+    }
+
+    /// GPS tracks of `owner`'s activities; `None` is those processed without
+    /// an account.
+    pub async fn tracks(&self, owner: Option<&str>) -> Vec<Arc<Vec<TrackPoint>>> {
+        self.entries
+            .lock()
+            .await
+            .iter()
+            .filter(|entry| entry.owner.as_deref() == owner && !entry.track.is_empty())
+            .map(|entry| entry.track.clone())
+            .collect()
+    }
+
+    /// Activities processed for `owner`, oldest first.
+    pub async fn owned_by(&self, owner: &str) -> Vec<HistoryEntry> {
+        self.entries
+            .lock()
+            .await
+            .iter()
+            .filter(|entry| entry.owner.as_deref() == Some(owner))
+            .cloned()
+            .collect()
+    }
+
+    /// Forget every activity processed for `owner`, returning how many there were.
+    pub async fn remove_owned(&self, owner: &str) -> usize {
+        let mut entries = self.entries.lock().await;
+        let before = entries.len();
+        entries.retain(|entry| entry.owner.as_deref() != Some(owner));
+        before - entries.len()
+    }
+
+    /// Distance and time per gear tag of `owner`'s activities, in order of
+    /// first use. `None` is the activities processed without an account.
+    pub async fn gear_usage(&self, owner: Option<&str>) -> Vec<GearUsage> {
+        let mut usage: Vec<GearUsage> = Vec::new();
+        let entries = self.entries.lock().await;
+        let owned = entries
+            .iter()
+            .filter(|entry| entry.owner.as_deref() == owner);
+        for entry in owned {
+            let Some(gear) = &entry.gear else {
+                continue;
+            };
+            let index = match usage.iter().position(|total| &total.gear == gear) {
+                Some(index) => index,
+                None => {
+                    usage.push(GearUsage {
+                        gear: gear.clone(),
+                        ..GearUsage::default()
+                    });
+                    usage.len() - 1
+                }
+            };
+            let total = &mut usage[index];
+            total.activities += 1;
+            total.distance_meters += entry.summary.distance_meters.unwrap_or(0.0);
+            total.duration_seconds += entry.summary.duration_seconds.unwrap_or(0.0);
+        }
+        usage
+    }
+
+    /// Each of `owner`'s activities' mean-max curve and the best across all
+    /// of them.
+    pub async fn curves(&self, owner: Option<&str>) -> CurveHistory {
+        let activities: Vec<ActivityCurve> = self
+            .entries
+            .lock()
+            .await
+            .iter()
+            .filter(|entry| entry.owner.as_deref() == owner)
+            .filter_map(|entry| {
+                Some(ActivityCurve {
+                    file_name: entry.file_name.clone(),
+                    start_timestamp: entry.summary.start_timestamp,
+                    curve: entry.summary.mean_max.clone()?,
+                })
+            })
+            .collect();
+        CurveHistory {
+            aggregate: MeanMaxCurve::best_of(activities.iter().map(|activity| &activity.curve)),
+            activities,
+        }
+    }
+
+    /// Summaries of `owner`'s activities, oldest first.
+    pub async fn summaries(&self, owner: Option<&str>) -> Vec<WorkoutSummary> {
+        self.entries
+            .lock()
+            .await
+            .iter()
+            .filter(|entry| entry.owner.as_deref() == owner)
+            .map(|entry| entry.summary.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_oldest_uploads_are_forgotten_at_capacity() {
+        let history = HistoryStore::with_capacity(2);
+        for distance in [1000.0, 2000.0, 3000.0] {
+            let summary = WorkoutSummary {
+                distance_meters: Some(distance),
+                ..WorkoutSummary::default()
+            };
+            history.record("ride.fit".to_string(), summary, None).await;
+        }
+
+        let distances: Vec<Option<f64>> = history
+            .summaries(None)
+            .await
+            .into_iter()
+            .map(|summary| summary.distance_meters)
+            .collect();
+        assert_eq!(distances, [Some(2000.0), Some(3000.0)]);
+    }
+}
@@ -0,0 +1,267 @@
+//! Background processing jobs whose progress can be polled or streamed.
+//!
+//! `POST /api/v1/jobs` returns as soon as the upload is read; the files are
+//! then processed in a spawned task that publishes a [`JobStatus`] after every
+//! progress update. Clients follow it at `GET /api/v1/jobs/:id` or as
+//! server-sent events at `GET /api/v1/jobs/:id/events`, and abandon it with
+//! `DELETE /api/v1/jobs/:id`.
+
+use crate::processing::progress::{CancellationToken, ProcessingObserver, Progress};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// How long a finished job's status stays available.
+pub const JOB_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Where a job is.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running {
+        /// Zero-based index of the file being processed.
+        file_index: usize,
+        file_count: usize,
+        file_name: String,
+        /// `None` until the file's first stage starts.
+        progress: Option<Progress>,
+    },
+    /// The upload's JSON results, as `/api/v1/process` would return them.
+    Completed {
+        result: serde_json::Value,
+    },
+    Failed {
+        error: String,
+    },
+    /// Stopped by `DELETE /api/v1/jobs/:id` before every file was processed.
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job will not change again.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Completed { .. } | JobStatus::Failed { .. } | JobStatus::Cancelled
+        )
+    }
+}
+
+struct JobEntry {
+    status: watch::Sender<JobStatus>,
+    cancellation: CancellationToken,
+    created_at: Instant,
+    /// Account that started the job.
+    owner: Option<String>,
+}
+
+/// Outcome of [`JobRegistry::cancel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelOutcome {
+    /// Processing will stop at its next cancellation check.
+    Requested,
+    AlreadyFinished,
+    NotFound,
+}
+
+/// Jobs started in this process, by ID.
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<HashMap<String, Arc<JobEntry>>>>,
+}
+
+/// Publishes the status of one job.
+#[derive(Clone)]
+pub struct JobHandle {
+    entry: Arc<JobEntry>,
+}
+
+impl JobRegistry {
+    /// Register a queued job for `owner`, dropping jobs older than [`JOB_TTL`].
+    pub fn create(&self, owner: Option<&str>) -> (String, JobHandle) {
+        let id = Uuid::new_v4().to_string();
+        let entry = Arc::new(JobEntry {
+            status: watch::Sender::new(JobStatus::Queued),
+            cancellation: CancellationToken::default(),
+            created_at: Instant::now(),
+            owner: owner.map(str::to_string),
+        });
+        let mut jobs = self.jobs.lock().expect("job registry lock poisoned");
+        jobs.retain(|_, job| job.created_at.elapsed() <= JOB_TTL);
+        jobs.insert(id.clone(), entry.clone());
+        (id, JobHandle { entry })
+    }
+
+    pub fn status(&self, id: &str) -> Option<JobStatus> {
+        self.entry(id).map(|entry| entry.status.borrow().clone())
+    }
+
+    /// Receiver of every status change of a job, starting with the current one.
+    pub fn subscribe(&self, id: &str) -> Option<watch::Receiver<JobStatus>> {
+        self.entry(id).map(|entry| entry.status.subscribe())
+    }
+
+    /// Ask a job to stop; its status becomes [`JobStatus::Cancelled`] once
+    /// the processing task notices.
+    pub fn cancel(&self, id: &str) -> CancelOutcome {
+        match self.entry(id) {
+            None => CancelOutcome::NotFound,
+            Some(entry) if entry.status.borrow().is_finished() => CancelOutcome::AlreadyFinished,
+            Some(entry) => {
+                entry.cancellation.cancel();
+                CancelOutcome::Requested
+            }
+        }
+    }
+
+    /// Cancel and forget every job `owner` started, returning how many there
+    /// were.
+    pub fn remove_owned(&self, owner: &str) -> usize {
+        let mut jobs = self.jobs.lock().expect("job registry lock poisoned");
+        let before = jobs.len();
+        jobs.retain(|_, job| {
+            let owned = job.owner.as_deref() == Some(owner);
+            if owned {
+                job.cancellation.cancel();
+            }
+            !owned
+        });
+        before - jobs.len()
+    }
+
+    fn entry(&self, id: &str) -> Option<Arc<JobEntry>> {
+        self.jobs
+            .lock()
+            .expect("job registry lock poisoned")
+            .get(id)
+            .cloned()
+    }
+}
+
+impl JobHandle {
+    pub fn set(&self, status: JobStatus) {
+        self.entry.status.send_replace(status);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.entry.cancellation.is_cancelled()
+    }
+
+    /// Observer that reports a file's stages as this job's progress.
+    pub fn file_progress<'a>(
+        &'a self,
+        file_index: usize,
+        file_count: usize,
+        file_name: &'a str,
+    ) -> FileProgress<'a> {
+        FileProgress {
+            handle: self,
+            file_index,
+            file_count,
+            file_name,
+        }
+    }
+}
+
+/// [`ProcessingObserver`] publishing one file's progress.
+pub struct FileProgress<'a> {
+    handle: &'a JobHandle,
+    file_index: usize,
+    file_count: usize,
+    file_name: &'a str,
+}
+
+impl FileProgress<'_> {
+    /// Mark the file as started before its first stage reports.
+    pub fn start(&self) {
+        self.publish(None);
+    }
+
+    fn publish(&self, progress: Option<Progress>) {
+        self.handle.set(JobStatus::Running {
+            file_index: self.file_index,
+            file_count: self.file_count,
+            file_name: self.file_name.to_string(),
+            progress,
+        });
+    }
+}
+
+impl ProcessingObserver for FileProgress<'_> {
+    fn progress(&self, progress: Progress) {
+        self.publish(Some(progress));
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.handle.is_cancelled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::progress::Stage;
+
+    #[test]
+    fn progress_reaches_subscribers() {
+        let registry = JobRegistry::default();
+        let (id, handle) = registry.create(None);
+        let mut receiver = registry.subscribe(&id).unwrap();
+        assert!(matches!(*receiver.borrow_and_update(), JobStatus::Queued));
+
+        handle
+            .file_progress(0, 2, "ride.fit")
+            .progress(Progress::new(Stage::Encode, 5, 10));
+
+        assert!(receiver.has_changed().unwrap());
+        assert!(matches!(
+            registry.status(&id),
+            Some(JobStatus::Running {
+                file_index: 0,
+                progress: Some(Progress {
+                    stage: Stage::Encode,
+                    completed: 5,
+                    total: 10
+                }),
+                ..
+            })
+        ));
+        handle.set(JobStatus::Failed {
+            error: "boom".into(),
+        });
+        assert!(registry.status(&id).unwrap().is_finished());
+        assert!(registry.status("missing").is_none());
+    }
+
+    #[test]
+    fn cancellation_reaches_the_processing_observer() {
+        let registry = JobRegistry::default();
+        let (id, handle) = registry.create(None);
+        let progress = handle.file_progress(0, 1, "ride.fit");
+        assert!(!progress.is_cancelled());
+
+        assert_eq!(registry.cancel(&id), CancelOutcome::Requested);
+        assert!(progress.is_cancelled());
+
+        handle.set(JobStatus::Cancelled);
+        assert_eq!(registry.cancel(&id), CancelOutcome::AlreadyFinished);
+        assert_eq!(registry.cancel("missing"), CancelOutcome::NotFound);
+    }
+
+    #[test]
+    fn removing_an_owner_cancels_and_forgets_its_jobs() {
+        let registry = JobRegistry::default();
+        let (owned, handle) = registry.create(Some("alice"));
+        let (other, _) = registry.create(Some("bob"));
+
+        assert_eq!(registry.remove_owned("alice"), 1);
+
+        assert!(handle.is_cancelled());
+        assert!(registry.status(&owned).is_none());
+        assert!(registry.status(&other).is_some());
+    }
+}
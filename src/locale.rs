@@ -0,0 +1,160 @@
+//! Decimal and thousands separators for numbers on rendered pages.
+//!
+//! The separators come from the `number_locale` a user picked, or else from
+//! the browser's `Accept-Language`. Without either, numbers keep the plain
+//! `1234.5` form JSON and CSV exports use.
+
+/// How numbers are written for one reader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberLocale {
+    decimal: char,
+    group: Option<char>,
+}
+
+impl Default for NumberLocale {
+    fn default() -> Self {
+        Self {
+            decimal: '.',
+            group: None,
+        }
+    }
+}
+
+/// Languages writing `1.234,5`.
+const COMMA_DOT: &[&str] = &[
+    "da", "de", "el", "es", "hr", "id", "it", "nl", "pt", "ro", "sl", "sr", "tr",
+];
+
+/// Languages writing `1 234,5`, grouped with a narrow no-break space.
+const COMMA_SPACE: &[&str] = &[
+    "bg", "cs", "et", "fi", "fr", "hu", "lt", "lv", "nb", "nn", "no", "pl", "ru", "sk", "sv", "uk",
+];
+
+/// Languages writing `1,234.5`.
+const DOT_COMMA: &[&str] = &["en", "he", "hi", "ja", "ko", "th", "zh"];
+
+impl NumberLocale {
+    /// Separators for a language tag such as `de`, `en-GB`, or `de-CH`;
+    /// `None` for languages without a known convention.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let tag = tag.trim().to_ascii_lowercase();
+        let mut parts = tag.split(['-', '_']);
+        let language = parts.next()?;
+        let region = parts.next();
+        let (decimal, group) = if region == Some("ch") && matches!(language, "de" | "fr" | "it") {
+            ('.', '\u{2019}')
+        } else if COMMA_DOT.contains(&language) {
+            (',', '.')
+        } else if COMMA_SPACE.contains(&language) {
+            (',', '\u{202f}')
+        } else if DOT_COMMA.contains(&language) {
+            ('.', ',')
+        } else {
+            return None;
+        };
+        Some(Self {
+            decimal,
+            group: Some(group),
+        })
+    }
+
+    /// The first known language of an `Accept-Language` header, by
+    /// preference.
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut languages: Vec<(&str, f64)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut params = entry.split(';');
+                let tag = params.next()?.trim();
+                let quality = params
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+                (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((tag, quality))
+            })
+            .collect();
+        // Stable, so equally preferred languages keep the header's order.
+        languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+        languages
+            .into_iter()
+            .find_map(|(tag, _)| Self::from_tag(tag))
+    }
+
+    /// The user's choice when it names a known language, else the browser's.
+    pub fn negotiate(chosen: Option<&str>, accept_language: Option<&str>) -> Self {
+        chosen
+            .and_then(Self::from_tag)
+            .or_else(|| accept_language.and_then(Self::from_accept_language))
+            .unwrap_or_default()
+    }
+
+    /// `value` with `decimals` fraction digits, grouped in thousands.
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        let plain = format!("{:.*}", decimals, value.abs());
+        let (integer, fraction) = plain.split_once('.').unwrap_or((&plain, ""));
+        let mut text = String::with_capacity(plain.len() + integer.len() / 3);
+        if value.is_sign_negative() && plain.bytes().any(|byte| matches!(byte, b'1'..=b'9')) {
+            text.push('-');
+        }
+        for (index, digit) in integer.chars().enumerate() {
+            let remaining = integer.len() - index;
+            if index > 0 && remaining % 3 == 0 {
+                text.extend(self.group);
+            }
+            text.push(digit);
+        }
+        if !fraction.is_empty() {
+            text.push(self.decimal);
+            text.push_str(fraction);
+        }
+        text
+    }
+
+    /// `text` with the decimal point of every number in it replaced. Numbers
+    /// are not grouped, since decoded values include serial numbers and
+    /// other identifiers.
+    pub fn decimals_in(&self, text: &str) -> String {
+        if self.decimal == '.' {
+            return text.to_string();
+        }
+        text.split(' ')
+            .map(|word| {
+                let numeric = word.parse::<f64>().is_ok()
+                    && word
+                        .bytes()
+                        .all(|byte| byte.is_ascii_digit() || matches!(byte, b'.' | b'-'));
+                if numeric {
+                    word.replace('.', &self.decimal.to_string())
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_follow_the_preferred_language() {
+        let german = NumberLocale::negotiate(None, Some("fr;q=0.5, de-DE, en;q=0.8"));
+        assert_eq!(german.number(12_345.678, 2), "12.345,68");
+        assert_eq!(german.decimals_in("3.25 m/s"), "3,25 m/s");
+        assert_eq!(german.decimals_in("serial 3954432123"), "serial 3954432123");
+
+        let french = NumberLocale::negotiate(Some("fr"), Some("de"));
+        assert_eq!(french.number(-1_234.0, 0), "-1\u{202f}234");
+        assert_eq!(
+            NumberLocale::negotiate(Some("de-CH"), None).number(1_234.5, 1),
+            "1\u{2019}234.5"
+        );
+
+        // Unknown languages and no header keep the plain form.
+        let plain = NumberLocale::negotiate(Some("xx"), Some("*"));
+        assert_eq!(plain, NumberLocale::default());
+        assert_eq!(plain.number(12_345.678, 2), "12345.68");
+        assert_eq!(plain.number(-0.001, 1), "0.0");
+    }
+}
@@ -1,8 +1,88 @@
-use rustyfit::build_app;
+use clap::Parser;
+use rustyfit::accounts::AccountStore;
+use rustyfit::events::{JobEvents, MqttConfig};
+use rustyfit::processing::{ProcessingLimits, ProcessingOptions};
+use rustyfit::telemetry::UsageCounters;
+use rustyfit::watch::{WatchFolder, load_preset};
+use rustyfit::{ServerConfig, build_app_with_config};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-#[tokio::main]
-async fn main() {
+/// Web service for cleaning up and re-encoding FIT activity files.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Address to bind.
+    #[arg(long, env = "RUSTYFIT_HOST", default_value = "0.0.0.0")]
+    host: IpAddr,
+
+    /// Port to listen on; 0 picks a free port and logs it.
+    #[arg(short, long, env = "RUSTYFIT_PORT", default_value_t = 3000)]
+    port: u16,
+
+    /// Tokio worker threads (defaults to one per CPU core).
+    #[arg(long, env = "RUSTYFIT_WORKERS")]
+    workers: Option<usize>,
+
+    /// Directory containing the `wasm-pack` bundle served under `/pkg`.
+    #[arg(long, env = "RUSTYFIT_WASM_PKG_DIR", default_value = "pkg")]
+    wasm_pkg_dir: PathBuf,
+
+    /// Directory containing the stylesheets and scripts served under `/static`.
+    #[arg(long, env = "RUSTYFIT_STATIC_DIR", default_value = "static")]
+    static_dir: PathBuf,
+
+    /// Process every FIT file dropped into this directory.
+    #[arg(long, env = "RUSTYFIT_WATCH_DIR", requires = "watch_output")]
+    watch: Option<PathBuf>,
+
+    /// Directory receiving the processed file and summary JSON of each watched file.
+    #[arg(long, env = "RUSTYFIT_WATCH_OUTPUT_DIR")]
+    watch_output: Option<PathBuf>,
+
+    /// JSON file of processing options applied to watched files (defaults when unset).
+    #[arg(long, env = "RUSTYFIT_WATCH_PRESET")]
+    watch_preset: Option<PathBuf>,
+
+    /// Seconds between scans of the watched directory.
+    #[arg(long, env = "RUSTYFIT_WATCH_INTERVAL_SECONDS", default_value_t = 5)]
+    watch_interval_seconds: u64,
+
+    /// JSON file of accounts and their quotas; processing requires an account when set.
+    #[arg(long, env = "RUSTYFIT_ACCOUNTS_FILE")]
+    accounts: Option<PathBuf>,
+
+    /// Count processed files, option use, and errors for the admin page. The
+    /// anonymous totals are kept in `--telemetry-file` and never sent anywhere.
+    #[arg(long, env = "RUSTYFIT_TELEMETRY")]
+    telemetry: bool,
+
+    /// JSON file the usage totals are saved to, so they survive restarts.
+    #[arg(
+        long,
+        env = "RUSTYFIT_TELEMETRY_FILE",
+        default_value = "rustyfit-usage.json"
+    )]
+    telemetry_file: PathBuf,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(workers) = args.workers {
+        runtime.worker_threads(workers);
+    }
+    runtime
+        .enable_all()
+        .build()
+        .expect("failed to build Tokio runtime")
+        .block_on(serve(args));
+}
+
+async fn serve(args: Args) {
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
@@ -11,14 +91,57 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let app = build_app();
-    let addr: std::net::SocketAddr = "0.0.0.0:3000".parse().expect("valid socket address");
-    tracing::info!("listening on {}", addr);
+    if let (Some(input_dir), Some(output_dir)) = (args.watch.clone(), args.watch_output.clone()) {
+        let options = match &args.watch_preset {
+            Some(path) => load_preset(path).unwrap_or_else(|err| exit_with(err)),
+            None => ProcessingOptions::default(),
+        };
+        let folder = WatchFolder {
+            input_dir,
+            output_dir,
+            options: ProcessingOptions {
+                limits: ProcessingLimits::from_env(),
+                ..options
+            },
+            interval: Duration::from_secs(args.watch_interval_seconds.max(1)),
+            events: JobEvents::connect(MqttConfig::from_env().as_ref()),
+        };
+        folder.prepare().unwrap_or_else(|err| exit_with(err));
+        tokio::spawn(folder.run());
+    }
+
+    let accounts = match &args.accounts {
+        Some(path) => AccountStore::load(path).unwrap_or_else(|err| exit_with(err)),
+        None => AccountStore::default(),
+    };
+
+    let telemetry = args.telemetry.then(|| {
+        UsageCounters::open(args.telemetry_file.clone()).unwrap_or_else(|err| exit_with(err))
+    });
 
-    let listener = tokio::net::TcpListener::bind(addr)
+    let app = build_app_with_config(ServerConfig {
+        wasm_pkg_dir: args.wasm_pkg_dir,
+        static_dir: args.static_dir,
+        accounts,
+        telemetry,
+        ..ServerConfig::from_env()
+    });
+
+    let listener = tokio::net::TcpListener::bind(SocketAddr::new(args.host, args.port))
         .await
         .expect("failed to bind address");
-    axum::serve(listener, app.into_make_service())
-        .await
-        .expect("server crashed");
+    let addr = listener.local_addr().expect("bound socket has an address");
+    tracing::info!("listening on {}", addr);
+
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .expect("server crashed");
+}
+
+fn exit_with(err: impl std::fmt::Display) -> ! {
+    tracing::error!("{err}");
+    std::process::exit(2);
 }
@@ -0,0 +1,209 @@
+//! Content negotiation on the `Accept` and `Accept-Encoding` headers.
+//!
+//! Routes that can answer in several formats list the ones they offer, most
+//! preferred first, and [`negotiate`] picks the one the client rates highest,
+//! honouring `q` values and `type/*` and `*/*` ranges. Formats rated equally
+//! go to the one named most specifically, so `application/json, */*` gets
+//! JSON. Clients that send no `Accept` header, or accept none of the offered
+//! formats, get the first.
+
+use axum::http::{HeaderMap, header};
+
+/// A format a route can answer in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Html,
+    Json,
+    /// The processed FIT file itself.
+    Fit,
+    Csv,
+}
+
+impl ResponseFormat {
+    pub fn media_type(self) -> &'static str {
+        match self {
+            ResponseFormat::Html => "text/html",
+            ResponseFormat::Json => "application/json",
+            ResponseFormat::Fit => "application/vnd.ant.fit",
+            ResponseFormat::Csv => "text/csv",
+        }
+    }
+}
+
+/// The offered format the request's `Accept` header prefers. Equal qualities
+/// go to the format matched by the more specific range (an exact type, then
+/// `type/*`, then `*/*`), and then to the earlier format in `offered`.
+///
+/// # Panics
+///
+/// When `offered` is empty.
+pub fn negotiate(headers: &HeaderMap, offered: &[ResponseFormat]) -> ResponseFormat {
+    let default = offered[0];
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return default;
+    };
+    let ranges: Vec<(String, f32)> = accept.split(',').filter_map(media_range).collect();
+
+    let mut best: Option<(ResponseFormat, f32, u8)> = None;
+    for &format in offered {
+        let Some((specificity, quality)) = quality(&ranges, format.media_type()) else {
+            continue;
+        };
+        let better = best.is_none_or(|(_, best_quality, best_specificity)| {
+            quality > best_quality || (quality == best_quality && specificity > best_specificity)
+        });
+        if quality > 0.0 && better {
+            best = Some((format, quality, specificity));
+        }
+    }
+    best.map_or(default, |(format, _, _)| format)
+}
+
+/// Whether the request's `Accept-Encoding` header allows `coding`, such as
+/// `gzip`. A coding named with `q=0`, or left to a `*;q=0`, is refused; the
+/// named coding wins over `*`.
+pub fn accepts_encoding(headers: &HeaderMap, coding: &str) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let codings: Vec<(String, f32)> = accept.split(',').filter_map(weighted).collect();
+    let rated = |name: &str| {
+        codings
+            .iter()
+            .find(|(entry, _)| entry == name)
+            .map(|&(_, quality)| quality)
+    };
+    rated(coding)
+        .or_else(|| rated("*"))
+        .is_some_and(|quality| quality > 0.0)
+}
+
+/// One `type/subtype;q=…` entry as its lowercased range and quality.
+fn media_range(entry: &str) -> Option<(String, f32)> {
+    weighted(entry).filter(|(range, _)| range.contains('/'))
+}
+
+/// One `value;q=…` entry as its lowercased value and quality, 1 without a
+/// `q` parameter.
+fn weighted(entry: &str) -> Option<(String, f32)> {
+    let mut parts = entry.split(';');
+    let value = parts.next()?.trim().to_ascii_lowercase();
+    if value.is_empty() {
+        return None;
+    }
+    let quality = parts
+        .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+        .find_map(|q| q.trim().parse::<f32>().ok())
+        .unwrap_or(1.0)
+        .clamp(0.0, 1.0);
+    Some((value, quality))
+}
+
+/// Specificity (2 for an exact type, 1 for `type/*`, 0 for `*/*`) and
+/// quality of the most specific range matching `media_type`; `None` when no
+/// range matches it.
+fn quality(ranges: &[(String, f32)], media_type: &str) -> Option<(u8, f32)> {
+    let (kind, _) = media_type.split_once('/')?;
+    let specificity = |range: &str| {
+        if range == media_type {
+            Some(2)
+        } else if range.strip_suffix("/*") == Some(kind) {
+            Some(1)
+        } else if range == "*/*" {
+            Some(0)
+        } else {
+            None
+        }
+    };
+    ranges
+        .iter()
+        .filter_map(|(range, quality)| Some((specificity(range)?, *quality)))
+        .max_by_key(|&(specificity, _)| specificity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const UPLOAD: &[ResponseFormat] = &[
+        ResponseFormat::Html,
+        ResponseFormat::Json,
+        ResponseFormat::Fit,
+    ];
+
+    fn accepting(accept: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+        headers
+    }
+
+    #[test]
+    fn picks_the_highest_rated_offered_format() {
+        let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        assert_eq!(negotiate(&accepting(browser), UPLOAD), ResponseFormat::Html);
+        assert_eq!(
+            negotiate(&accepting("application/json, text/plain, */*"), UPLOAD),
+            ResponseFormat::Json
+        );
+        assert_eq!(
+            negotiate(
+                &accepting("application/vnd.ant.fit, application/json;q=0.5"),
+                UPLOAD
+            ),
+            ResponseFormat::Fit
+        );
+        assert_eq!(
+            negotiate(&accepting("application/*, text/html;q=0.1"), UPLOAD),
+            ResponseFormat::Json
+        );
+        // Equal qualities go to the exact type over `type/*` over `*/*`.
+        assert_eq!(
+            negotiate(&accepting("application/*, text/*"), UPLOAD),
+            ResponseFormat::Html
+        );
+        assert_eq!(
+            negotiate(&accepting("*/*, application/vnd.ant.fit"), UPLOAD),
+            ResponseFormat::Fit
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_first_offered_format() {
+        assert_eq!(negotiate(&HeaderMap::new(), UPLOAD), ResponseFormat::Html);
+        assert_eq!(negotiate(&accepting("*/*"), UPLOAD), ResponseFormat::Html);
+        assert_eq!(
+            negotiate(&accepting("image/png"), UPLOAD),
+            ResponseFormat::Html
+        );
+        assert_eq!(
+            negotiate(&accepting("text/html;q=0, application/json;q=0"), UPLOAD),
+            ResponseFormat::Html
+        );
+    }
+
+    #[test]
+    fn zero_rated_encodings_are_refused() {
+        let encoding = |accept: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(accept));
+            accepts_encoding(&headers, "gzip")
+        };
+
+        assert!(encoding("br;q=1.0, gzip;q=0.8"));
+        assert!(encoding("GZIP"));
+        assert!(encoding("*"));
+        assert!(!encoding("gzip;q=0"));
+        assert!(!encoding("gzip; q=0.000, br"));
+        assert!(!encoding("*;q=0"));
+        assert!(encoding("gzip, *;q=0"));
+        assert!(!encoding("br, deflate"));
+        assert!(!accepts_encoding(&HeaderMap::new(), "gzip"));
+    }
+}
@@ -0,0 +1,567 @@
+//! Printable workout reports, for coaches who archive or share an analysis
+//! offline.
+//!
+//! The PDF is written directly: text uses the standard Helvetica fonts every
+//! reader provides, and charts are drawn by plotters, like the results page's
+//! SVG charts, through a backend that writes them as vector paths onto the
+//! page, so no fonts or images are embedded.
+//!
+//! Reports are built from the summary and annotations kept with the stored
+//! result, so an export never reprocesses the upload.
+
+use crate::charts::{ELEVATION_COLOR, SPEED_COLOR, draw_line_chart};
+use crate::locale::NumberLocale;
+use crate::processing::WorkoutSummary;
+use crate::processing::annotations::{AnnotationKind, Annotations};
+use crate::templates::{
+    format_distance, format_duration, format_elevation, format_heart_rate, format_local_time,
+    format_speed,
+};
+use plotters::prelude::*;
+use plotters_backend::text_anchor::{HPos, VPos};
+use plotters_backend::{
+    BackendColor, BackendCoord, BackendStyle, BackendTextStyle, DrawingErrorKind,
+};
+use std::convert::Infallible;
+
+/// A4, in points.
+const PAGE_WIDTH: f64 = 595.0;
+const PAGE_HEIGHT: f64 = 842.0;
+const MARGIN: f64 = 50.0;
+const CHART_HEIGHT: f64 = 150.0;
+const LINE_HEIGHT: f64 = 16.0;
+const GRAY: (u8, u8, u8) = (153, 153, 153);
+
+/// Summary, speed and elevation charts, and splits of an activity as a PDF.
+pub fn workout_report(
+    summary: &WorkoutSummary,
+    annotations: &Annotations,
+    locale: NumberLocale,
+) -> Vec<u8> {
+    let mut layout = Layout::default();
+
+    let top = layout.reserve(40.0);
+    layout
+        .page()
+        .text(MARGIN, top - 20.0, 20.0, true, "Workout report");
+    let subtitle = format!(
+        "{}, {}",
+        summary.workout_type.as_deref().unwrap_or("Unknown workout"),
+        format_local_time(summary.start_timestamp, summary.utc_offset_seconds)
+    );
+    layout
+        .page()
+        .text(MARGIN, top - 36.0, 10.0, false, &subtitle);
+
+    layout.heading("Summary");
+    let rows = [
+        ("Duration", format_duration(summary.duration_seconds)),
+        ("Moving time", format_duration(summary.moving_time_seconds)),
+        ("Distance", format_distance(summary.distance_meters, locale)),
+        (
+            "Elevation gain",
+            format_elevation(summary.elevation_gain_meters, locale),
+        ),
+        ("Pace (mean)", format_speed(summary.speed_mean)),
+        ("Pace (best)", format_speed(summary.speed_max)),
+        (
+            "Heart rate (mean)",
+            format_heart_rate(summary.heart_rate_mean),
+        ),
+        (
+            "Heart rate (max)",
+            format_heart_rate(summary.heart_rate_max),
+        ),
+    ];
+    for (label, value) in rows {
+        let top = layout.reserve(LINE_HEIGHT);
+        let page = layout.page();
+        page.text(MARGIN, top - 12.0, 10.0, false, label);
+        page.text(MARGIN + 150.0, top - 12.0, 10.0, true, &value);
+    }
+
+    let start = annotations
+        .timeline
+        .first()
+        .map_or(0.0, |point| point.timestamp);
+    let speed: Vec<(f64, f64)> = annotations
+        .timeline
+        .iter()
+        .map(|point| (point.timestamp - start, point.speed * 3.6))
+        .collect();
+    layout.line_chart(
+        "Speed (km/h) over time",
+        &speed,
+        SPEED_COLOR,
+        &|elapsed| format_duration(Some(*elapsed)),
+        locale,
+    );
+
+    let profile: Vec<(f64, f64)> = summary
+        .terrain
+        .iter()
+        .flat_map(|terrain| &terrain.profile)
+        .map(|point| (point.distance_meters, point.altitude_meters))
+        .collect();
+    layout.line_chart(
+        "Elevation (m) over distance",
+        &profile,
+        ELEVATION_COLOR,
+        &|distance| format_distance(Some(*distance), locale),
+        locale,
+    );
+
+    layout.heading("Splits");
+    let laps: Vec<_> = annotations
+        .regions
+        .iter()
+        .filter(|region| region.kind != AnnotationKind::Stop)
+        .collect();
+    if laps.is_empty() {
+        let top = layout.reserve(LINE_HEIGHT);
+        layout
+            .page()
+            .text(MARGIN, top - 12.0, 10.0, false, "No laps were recorded.");
+    } else {
+        layout.table_row(&["Split", "Start", "Duration", "Pace"], true);
+    }
+    let activity_start = summary.start_timestamp.unwrap_or(start);
+    for lap in laps {
+        let speeds: Vec<f64> = annotations
+            .timeline
+            .iter()
+            .filter(|point| (lap.start_timestamp..=lap.end_timestamp).contains(&point.timestamp))
+            .map(|point| point.speed)
+            .collect();
+        let mean_speed =
+            (!speeds.is_empty()).then(|| speeds.iter().sum::<f64>() / speeds.len() as f64);
+        layout.table_row(
+            &[
+                &lap.label,
+                &format_duration(Some(lap.start_timestamp - activity_start)),
+                &format_duration(Some(lap.end_timestamp - lap.start_timestamp)),
+                &format_speed(mean_speed),
+            ],
+            false,
+        );
+    }
+
+    write_document(&layout.pages)
+}
+
+/// Pages filled top to bottom, starting a new page when one is full.
+struct Layout {
+    pages: Vec<Page>,
+    /// Height of the next free line on the last page.
+    y: f64,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            pages: vec![Page::default()],
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+}
+
+impl Layout {
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().expect("layouts start with a page")
+    }
+
+    /// Take `height` points below the cursor, on a new page when the current
+    /// one lacks the room, and return the top of the space taken.
+    fn reserve(&mut self, height: f64) -> f64 {
+        if self.y - height < MARGIN {
+            self.pages.push(Page::default());
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+        let top = self.y;
+        self.y -= height;
+        top
+    }
+
+    fn heading(&mut self, text: &str) {
+        let top = self.reserve(32.0);
+        self.page().text(MARGIN, top - 26.0, 13.0, true, text);
+    }
+
+    fn table_row(&mut self, cells: &[&str], header: bool) {
+        let top = self.reserve(LINE_HEIGHT);
+        let column = (PAGE_WIDTH - 2.0 * MARGIN) / cells.len() as f64;
+        let page = self.page();
+        for (index, cell) in cells.iter().enumerate() {
+            page.text(
+                MARGIN + index as f64 * column,
+                top - 12.0,
+                10.0,
+                header,
+                cell,
+            );
+        }
+        if header {
+            page.stroke(
+                &[
+                    (MARGIN, top - LINE_HEIGHT),
+                    (PAGE_WIDTH - MARGIN, top - LINE_HEIGHT),
+                ],
+                0.5,
+                GRAY,
+            );
+        }
+    }
+
+    /// Line chart of `points` across the page under `caption`, with the x
+    /// values described by `x_label`.
+    fn line_chart(
+        &mut self,
+        caption: &str,
+        points: &[(f64, f64)],
+        color: RGBColor,
+        x_label: &dyn Fn(&f64) -> String,
+        locale: NumberLocale,
+    ) {
+        if points.len() < 2 {
+            return;
+        }
+        let top = self.reserve(CHART_HEIGHT + 52.0);
+        let page = self.page();
+        page.text(MARGIN, top - 20.0, 11.0, true, caption);
+        let area = PageBackend {
+            page,
+            origin: (MARGIN, top - 26.0),
+            size: (
+                (PAGE_WIDTH - 2.0 * MARGIN) as u32,
+                (CHART_HEIGHT + 26.0) as u32,
+            ),
+        }
+        .into_drawing_area();
+        // Drawing onto a page cannot fail.
+        let _ = draw_line_chart(&area, points, color, x_label, locale);
+    }
+}
+
+/// Content stream of one page, in PDF drawing operators.
+#[derive(Default)]
+struct Page {
+    content: Vec<u8>,
+}
+
+impl Page {
+    fn text(&mut self, x: f64, y: f64, size: f64, bold: bool, text: &str) {
+        let font = if bold { "F2" } else { "F1" };
+        self.content
+            .extend(format!("BT /{font} {size} Tf {x:.1} {y:.1} Td (").as_bytes());
+        self.content.extend(encode_text(text));
+        self.content.extend(b") Tj ET\n");
+    }
+
+    /// Open path through `points`, `width` points wide in `color`.
+    fn stroke(&mut self, points: &[(f64, f64)], width: f64, color: (u8, u8, u8)) {
+        let Some(path) = path(points) else {
+            return;
+        };
+        self.content
+            .extend(format!("{} RG {width:.1} w {path} S\n", rgb(color)).as_bytes());
+    }
+
+    /// Closed shape through `points`, filled with `color`.
+    fn fill(&mut self, points: &[(f64, f64)], color: (u8, u8, u8)) {
+        let Some(path) = path(points) else {
+            return;
+        };
+        self.content
+            .extend(format!("{} rg {path} h f\n", rgb(color)).as_bytes());
+    }
+}
+
+/// Path construction operators through `points`; `None` without any.
+fn path(points: &[(f64, f64)]) -> Option<String> {
+    let ((x, y), rest) = points.split_first()?;
+    let mut path = format!("{x:.1} {y:.1} m");
+    for (x, y) in rest {
+        path.push_str(&format!(" {x:.1} {y:.1} l"));
+    }
+    Some(path)
+}
+
+/// Color operands for `color`.
+fn rgb((red, green, blue): (u8, u8, u8)) -> String {
+    let channel = |value: u8| f64::from(value) / 255.0;
+    format!(
+        "{:.3} {:.3} {:.3}",
+        channel(red),
+        channel(green),
+        channel(blue)
+    )
+}
+
+/// Plotters backend drawing onto a page, one backend pixel to a point, with
+/// its top-left corner at `origin`.
+struct PageBackend<'a> {
+    page: &'a mut Page,
+    origin: (f64, f64),
+    size: (u32, u32),
+}
+
+impl PageBackend<'_> {
+    /// Page position of a backend coordinate, whose y axis points down.
+    fn position(&self, (x, y): BackendCoord) -> (f64, f64) {
+        (self.origin.0 + f64::from(x), self.origin.1 - f64::from(y))
+    }
+}
+
+impl DrawingBackend for PageBackend<'_> {
+    type ErrorType = Infallible;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<Infallible>> {
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), DrawingErrorKind<Infallible>> {
+        Ok(())
+    }
+
+    fn draw_pixel(
+        &mut self,
+        point: BackendCoord,
+        color: BackendColor,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        self.draw_rect(point, (point.0 + 1, point.1 + 1), &color, true)
+    }
+
+    fn draw_line<S: BackendStyle>(
+        &mut self,
+        from: BackendCoord,
+        to: BackendCoord,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        self.draw_path([from, to], style)
+    }
+
+    fn draw_rect<S: BackendStyle>(
+        &mut self,
+        upper_left: BackendCoord,
+        bottom_right: BackendCoord,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let (left, top) = self.position(upper_left);
+        let (right, bottom) = self.position(bottom_right);
+        let corners = [
+            (left, top),
+            (right, top),
+            (right, bottom),
+            (left, bottom),
+            (left, top),
+        ];
+        if fill {
+            self.page.fill(&corners, opaque(style.color()));
+        } else {
+            let width = f64::from(style.stroke_width());
+            self.page.stroke(&corners, width, opaque(style.color()));
+        }
+        Ok(())
+    }
+
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        path: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let points: Vec<_> = path.into_iter().map(|point| self.position(point)).collect();
+        let width = f64::from(style.stroke_width());
+        self.page.stroke(&points, width, opaque(style.color()));
+        Ok(())
+    }
+
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        vertices: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        let points: Vec<_> = vertices
+            .into_iter()
+            .map(|point| self.position(point))
+            .collect();
+        self.page.fill(&points, opaque(style.color()));
+        Ok(())
+    }
+
+    fn draw_text<TStyle: BackendTextStyle>(
+        &mut self,
+        text: &str,
+        style: &TStyle,
+        pos: BackendCoord,
+    ) -> Result<(), DrawingErrorKind<Infallible>> {
+        // Plotters sizes text by its line height; like its SVG backend, the
+        // font size is taken as a little less.
+        let size = (style.size() / 1.24).round();
+        let (width, _) = self.estimate_text_size(text, style)?;
+        let (x, y) = self.position(pos);
+        let x = match style.anchor().h_pos {
+            HPos::Left => x,
+            HPos::Center => x - f64::from(width) / 2.0,
+            HPos::Right => x - f64::from(width),
+        };
+        // The page places text by its baseline.
+        let y = match style.anchor().v_pos {
+            VPos::Top => y - 0.75 * size,
+            VPos::Center => y - 0.35 * size,
+            VPos::Bottom => y,
+        };
+        self.page.text(x, y, size, false, text);
+        Ok(())
+    }
+}
+
+/// `color` blended onto the white page, which has no transparency.
+fn opaque(color: BackendColor) -> (u8, u8, u8) {
+    let blend = |channel: u8| (255.0 - color.alpha * (255.0 - f64::from(channel))).round() as u8;
+    (blend(color.rgb.0), blend(color.rgb.1), blend(color.rgb.2))
+}
+
+/// `text` in the WinAnsi encoding of the standard fonts, escaped for a
+/// string literal. Characters the encoding lacks become `?`.
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => bytes.extend([b'\\', c as u8]),
+            '\u{2014}' => bytes.push(0x97),
+            '\u{2019}' => bytes.push(0x92),
+            '\u{202f}' => bytes.push(b' '),
+            ' '..='~' | '\u{a0}'..='\u{ff}' => bytes.push(c as u8),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
+
+/// PDF file of `pages`: the catalog, page tree, and both fonts, then each
+/// page followed by its content stream.
+fn write_document(pages: &[Page]) -> Vec<u8> {
+    let kids: Vec<String> = (0..pages.len())
+        .map(|index| format!("{} 0 R", 5 + 2 * index))
+        .collect();
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+        font("Helvetica"),
+        font("Helvetica-Bold"),
+    ];
+    for (index, page) in pages.iter().enumerate() {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                6 + 2 * index
+            )
+            .into_bytes(),
+        );
+        let mut stream = format!("<< /Length {} >>\nstream\n", page.content.len()).into_bytes();
+        stream.extend(&page.content);
+        stream.extend(b"\nendstream");
+        objects.push(stream);
+    }
+
+    // The binary comment marks the file as binary for transfer tools.
+    let mut pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n", index + 1).as_bytes());
+        pdf.extend(object);
+        pdf.extend(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+    pdf
+}
+
+fn font(name: &str) -> Vec<u8> {
+    format!("<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>")
+        .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_are_indexed_in_the_cross_reference_table() {
+        let mut layout = Layout::default();
+        for index in 0..80 {
+            layout.table_row(&[format!("Lap {index}").as_str(), "0m 00s"], false);
+        }
+        assert_eq!(layout.pages.len(), 2);
+
+        let pdf = write_document(&layout.pages);
+        let tail = |from: usize| String::from_utf8_lossy(&pdf[from..]).into_owned();
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert!(tail(0).contains("/Count 2"));
+
+        // Every object starts where the cross-reference table says it does.
+        let startxref = pdf
+            .windows(10)
+            .rposition(|window| window == b"startxref\n")
+            .unwrap();
+        let xref: usize = tail(startxref + 10)
+            .lines()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        for (index, entry) in tail(xref).lines().skip(3).take(8).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", index + 1).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn charts_are_drawn_as_vector_paths_and_text() {
+        let mut layout = Layout::default();
+        let points: Vec<(f64, f64)> = (0..=10)
+            .map(|minute| (minute as f64 * 60.0, 20.0 + minute as f64))
+            .collect();
+        layout.line_chart(
+            "Speed (km/h) over time",
+            &points,
+            SPEED_COLOR,
+            &|elapsed| format_duration(Some(*elapsed)),
+            NumberLocale::default(),
+        );
+
+        let content = String::from_utf8_lossy(&layout.pages[0].content).into_owned();
+        assert!(content.contains("(Speed \\(km/h\\) over time) Tj"));
+        assert!(content.contains("0.145 0.388 0.922 RG 2.0 w"));
+        assert!(content.contains("(0m 00s) Tj"));
+    }
+
+    #[test]
+    fn text_is_escaped_for_the_standard_fonts() {
+        assert_eq!(encode_text("a (b) \\ —"), b"a \\(b\\) \\\\ \x97".to_vec());
+        assert_eq!(encode_text("1\u{202f}234 €"), b"1 234 ?".to_vec());
+    }
+}
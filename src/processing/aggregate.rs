@@ -0,0 +1,175 @@
+use crate::processing::types::WorkoutSummary;
+use chrono::{DateTime, Datelike, NaiveDate, Weekday};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Calendar granularity used when bucketing activities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportPeriod {
+    Week,
+    Month,
+}
+
+impl ReportPeriod {
+    /// First day of the period containing a Unix timestamp.
+    pub fn start_of(self, timestamp: f64) -> Option<NaiveDate> {
+        match self {
+            ReportPeriod::Week => week_start(timestamp),
+            ReportPeriod::Month => utc_date(timestamp).and_then(|date| date.with_day(1)),
+        }
+    }
+}
+
+/// Totals across every activity in a batch upload.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AggregateSummary {
+    pub activity_count: usize,
+    pub total_distance_meters: f64,
+    pub total_duration_seconds: f64,
+    pub total_elevation_gain_meters: f64,
+    /// Per-week totals ordered by week start; activities without a start time are skipped.
+    pub weeks: Vec<PeriodTotals>,
+}
+
+/// Activity totals for a calendar period starting on `period_start`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PeriodTotals {
+    pub period_start: NaiveDate,
+    pub activity_count: usize,
+    pub distance_meters: f64,
+    pub duration_seconds: f64,
+    pub elevation_gain_meters: f64,
+}
+
+impl PeriodTotals {
+    fn new(period_start: NaiveDate) -> Self {
+        Self {
+            period_start,
+            activity_count: 0,
+            distance_meters: 0.0,
+            duration_seconds: 0.0,
+            elevation_gain_meters: 0.0,
+        }
+    }
+
+    fn add(&mut self, summary: &WorkoutSummary) {
+        self.activity_count += 1;
+        self.distance_meters += summary.distance_meters.unwrap_or(0.0);
+        self.duration_seconds += summary.duration_seconds.unwrap_or(0.0);
+        self.elevation_gain_meters += summary.elevation_gain_meters.unwrap_or(0.0);
+    }
+}
+
+/// Combine per-file summaries into batch totals and a Monday-based weekly breakdown.
+pub fn aggregate_summaries<'a>(
+    summaries: impl IntoIterator<Item = &'a WorkoutSummary>,
+) -> AggregateSummary {
+    let summaries: Vec<&WorkoutSummary> = summaries.into_iter().collect();
+    let mut aggregate = AggregateSummary::default();
+
+    for summary in &summaries {
+        aggregate.activity_count += 1;
+        aggregate.total_distance_meters += summary.distance_meters.unwrap_or(0.0);
+        aggregate.total_duration_seconds += summary.duration_seconds.unwrap_or(0.0);
+        aggregate.total_elevation_gain_meters += summary.elevation_gain_meters.unwrap_or(0.0);
+    }
+
+    aggregate.weeks = period_totals(summaries, ReportPeriod::Week);
+    aggregate
+}
+
+/// Bucket summaries by calendar period, ordered by period start.
+pub fn period_totals<'a>(
+    summaries: impl IntoIterator<Item = &'a WorkoutSummary>,
+    period: ReportPeriod,
+) -> Vec<PeriodTotals> {
+    let mut periods: BTreeMap<NaiveDate, PeriodTotals> = BTreeMap::new();
+
+    for summary in summaries {
+        if let Some(start) = summary.start_timestamp.and_then(|ts| period.start_of(ts)) {
+            periods
+                .entry(start)
+                .or_insert_with(|| PeriodTotals::new(start))
+                .add(summary);
+        }
+    }
+
+    periods.into_values().collect()
+}
+
+/// Calendar date (UTC) of a Unix timestamp in seconds.
+pub(crate) fn utc_date(timestamp: f64) -> Option<NaiveDate> {
+    if !timestamp.is_finite() {
+        return None;
+    }
+    DateTime::from_timestamp(timestamp.floor() as i64, 0).map(|dt| dt.date_naive())
+}
+
+/// Monday of the week containing a Unix timestamp.
+pub(crate) fn week_start(timestamp: f64) -> Option<NaiveDate> {
+    utc_date(timestamp).map(|date| date.week(Weekday::Mon).first_day())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(start: f64, distance: f64, duration: f64) -> WorkoutSummary {
+        WorkoutSummary {
+            start_timestamp: Some(start),
+            distance_meters: Some(distance),
+            duration_seconds: Some(duration),
+            elevation_gain_meters: Some(10.0),
+            ..WorkoutSummary::default()
+        }
+    }
+
+    #[test]
+    fn aggregates_totals_and_weeks() {
+        // 2024-01-01 (Monday), 2024-01-03 (Wednesday) and 2024-01-08 (next Monday).
+        let summaries = [
+            summary(1_704_067_200.0, 5000.0, 1500.0),
+            summary(1_704_240_000.0, 3000.0, 900.0),
+            summary(1_704_672_000.0, 10000.0, 3600.0),
+        ];
+
+        let aggregate = aggregate_summaries(&summaries);
+
+        assert_eq!(aggregate.activity_count, 3);
+        assert_eq!(aggregate.total_distance_meters, 18000.0);
+        assert_eq!(aggregate.total_duration_seconds, 6000.0);
+        assert_eq!(aggregate.total_elevation_gain_meters, 30.0);
+        assert_eq!(aggregate.weeks.len(), 2);
+        assert_eq!(
+            aggregate.weeks[0].period_start,
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+        );
+        assert_eq!(aggregate.weeks[0].activity_count, 2);
+        assert_eq!(aggregate.weeks[1].distance_meters, 10000.0);
+    }
+
+    #[test]
+    fn monthly_periods_start_on_the_first() {
+        // 2024-01-31 and 2024-02-01.
+        let summaries = [
+            summary(1_706_659_200.0, 1000.0, 600.0),
+            summary(1_706_745_600.0, 2000.0, 600.0),
+        ];
+
+        let months = period_totals(&summaries, ReportPeriod::Month);
+
+        assert_eq!(months.len(), 2);
+        assert_eq!(
+            months[1].period_start,
+            NaiveDate::from_ymd_opt(2024, 2, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn activities_without_start_are_excluded_from_weeks() {
+        let aggregate = aggregate_summaries(&[WorkoutSummary::default()]);
+        assert_eq!(aggregate.activity_count, 1);
+        assert!(aggregate.weeks.is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! Time ranges worth marking on the activity's speed chart: stops, laps, and
+//! structured-workout intervals, each found by its own detection pass over
+//! the processed records.
+
+use crate::processing::preprocess::smoothed_speed_series;
+use crate::processing::summary::{DistanceSample, field_value_to_f64};
+use crate::processing::types::{MOVING_SPEED_THRESHOLD, SPEED_SMOOTHING_WINDOW};
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataRecord, Value};
+use serde::Serialize;
+
+/// Shortest stationary stretch reported as a stop.
+pub const MIN_STOP_SECONDS: f64 = 10.0;
+
+/// Most points kept in [`Annotations::timeline`].
+pub const MAX_TIMELINE_POINTS: usize = 600;
+
+/// Regions to shade on the speed chart, and the chart's series.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Annotations {
+    /// Stops first, then laps or intervals, each in time order.
+    pub regions: Vec<Annotation>,
+    /// Smoothed speed over time, downsampled to [`MAX_TIMELINE_POINTS`].
+    pub timeline: Vec<TimelinePoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationKind {
+    Stop,
+    Lap,
+    /// A lap of a structured workout, labelled with its intensity.
+    Interval,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Annotation {
+    pub kind: AnnotationKind,
+    pub start_timestamp: f64,
+    pub end_timestamp: f64,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TimelinePoint {
+    pub timestamp: f64,
+    /// Speed in m/s.
+    pub speed: f64,
+}
+
+/// Run every detection pass over `records`.
+pub fn detect_annotations(records: &[FitDataRecord]) -> Annotations {
+    let Some(series) = smoothed_speed_series(records, SPEED_SMOOTHING_WINDOW) else {
+        return Annotations {
+            regions: detect_laps(records),
+            timeline: Vec::new(),
+        };
+    };
+    let mut regions = detect_stops(&series.samples, &series.smoothed);
+    regions.extend(detect_laps(records));
+
+    let stride = series.smoothed.len().div_ceil(MAX_TIMELINE_POINTS).max(1);
+    let timeline = series
+        .samples
+        .iter()
+        .zip(&series.smoothed)
+        .step_by(stride)
+        .map(|(sample, &speed)| TimelinePoint {
+            timestamp: sample.timestamp,
+            speed,
+        })
+        .collect();
+    Annotations { regions, timeline }
+}
+
+/// Runs of intervals slower than [`MOVING_SPEED_THRESHOLD`] lasting at least
+/// [`MIN_STOP_SECONDS`]; auto-pause gaps count, as no distance is covered.
+fn detect_stops(samples: &[DistanceSample], smoothed: &[f64]) -> Vec<Annotation> {
+    let stop = |start: f64, end: f64| {
+        (end - start >= MIN_STOP_SECONDS).then(|| Annotation {
+            kind: AnnotationKind::Stop,
+            start_timestamp: start,
+            end_timestamp: end,
+            label: "Stop".to_string(),
+        })
+    };
+    let mut stops = Vec::new();
+    let mut started: Option<f64> = None;
+    for (pair, &speed) in samples.windows(2).zip(smoothed) {
+        if speed < MOVING_SPEED_THRESHOLD {
+            started.get_or_insert(pair[0].timestamp);
+        } else if let Some(start) = started.take() {
+            stops.extend(stop(start, pair[0].timestamp));
+        }
+    }
+    if let (Some(start), Some(last)) = (started, samples.last()) {
+        stops.extend(stop(start, last.timestamp));
+    }
+    stops
+}
+
+/// One region per `Lap` message. When any lap carries an intensity other than
+/// `active`, the file is a structured workout and every lap is an interval.
+fn detect_laps(records: &[FitDataRecord]) -> Vec<Annotation> {
+    let laps: Vec<(Option<f64>, Option<f64>, Option<String>)> = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Lap))
+        .map(|record| {
+            let field = |name: &str| record.fields().iter().find(|field| field.name() == name);
+            (
+                field("start_time").and_then(field_value_to_f64),
+                field("timestamp").and_then(field_value_to_f64),
+                field("intensity").and_then(|field| match field.value() {
+                    Value::String(name) => Some(name.clone()),
+                    _ => None,
+                }),
+            )
+        })
+        .collect();
+    let structured = laps
+        .iter()
+        .any(|(_, _, intensity)| intensity.as_deref().is_some_and(|value| value != "active"));
+
+    laps.into_iter()
+        .enumerate()
+        .filter_map(|(index, (start, end, intensity))| {
+            let (start, end) = (start?, end?);
+            let number = index + 1;
+            let (kind, label) = match intensity.filter(|_| structured) {
+                Some(intensity) => (
+                    AnnotationKind::Interval,
+                    format!("Lap {number} · {intensity}"),
+                ),
+                None => (AnnotationKind::Lap, format!("Lap {number}")),
+            };
+            (end > start).then_some(Annotation {
+                kind,
+                start_timestamp: start,
+                end_timestamp: end,
+                label,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+
+    fn sample(second: f64, distance: f64) -> FitDataRecord {
+        record_with(
+            MesgNum::Record,
+            &[
+                ("timestamp", timestamp_value(second).unwrap()),
+                ("distance", Value::Float64(distance)),
+            ],
+        )
+    }
+
+    fn lap(start: f64, end: f64, intensity: &str) -> FitDataRecord {
+        record_with(
+            MesgNum::Lap,
+            &[
+                ("start_time", timestamp_value(start).unwrap()),
+                ("timestamp", timestamp_value(end).unwrap()),
+                ("intensity", Value::String(intensity.to_string())),
+            ],
+        )
+    }
+
+    #[test]
+    fn detects_stops_and_structured_intervals() {
+        // Moving at 5 m/s, a 30 s stop, then moving again.
+        let mut records: Vec<FitDataRecord> = Vec::new();
+        let mut distance = 0.0;
+        for second in 1_000..1_120 {
+            if !(1_040..1_070).contains(&second) {
+                distance += 5.0;
+            }
+            records.push(sample(second as f64, distance));
+        }
+        records.push(lap(1_000.0, 1_060.0, "active"));
+        records.push(lap(1_060.0, 1_119.0, "rest"));
+
+        let annotations = detect_annotations(&records);
+
+        let stop = &annotations.regions[0];
+        assert_eq!(stop.kind, AnnotationKind::Stop);
+        assert!((1_036.0..=1_042.0).contains(&stop.start_timestamp));
+        assert!((1_066.0..=1_072.0).contains(&stop.end_timestamp));
+        let labels: Vec<&str> = annotations.regions[1..]
+            .iter()
+            .map(|region| region.label.as_str())
+            .collect();
+        assert_eq!(labels, vec!["Lap 1 · active", "Lap 2 · rest"]);
+        assert_eq!(annotations.regions[1].kind, AnnotationKind::Interval);
+        assert_eq!(annotations.timeline.len(), 119);
+    }
+
+    #[test]
+    fn plain_laps_are_not_intervals() {
+        let regions = detect_laps(&[lap(0.0, 600.0, "active"), lap(600.0, 900.0, "active")]);
+
+        assert!(
+            regions
+                .iter()
+                .all(|region| region.kind == AnnotationKind::Lap)
+        );
+        assert_eq!(regions[1].label, "Lap 2");
+    }
+}
@@ -0,0 +1,278 @@
+//! Privacy rules for sharing activities, grouped into named profiles.
+//!
+//! Each [`AnonymizeRule`] is an ordinary [`Transform`]; a profile is only the
+//! list of rules it applies. The rules run at the end of the preprocessing
+//! pipeline, so every other transform still sees the original data.
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::indoor::INDOOR_POSITION_FIELDS;
+use crate::processing::transforms::{
+    FieldEdit, RecordContext, Transform, record_timestamp, timestamp_value,
+};
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataRecord, Value};
+use serde::Deserialize;
+
+/// Record positions closer than this to the first or last fix are hidden by
+/// [`AnonymizeRule::FuzzStartEnd`].
+pub const FUZZ_RADIUS_METERS: f64 = 250.0;
+
+/// Heart-rate fields removed by [`AnonymizeRule::StripHeartRate`].
+const HEART_RATE_FIELDS: &[&str] = &[
+    "heart_rate",
+    "avg_heart_rate",
+    "max_heart_rate",
+    "min_heart_rate",
+    "time_in_hr_zone",
+];
+
+pub(crate) const SEMICIRCLES_TO_DEGREES: f64 = 180.0 / 2_147_483_648.0;
+pub(crate) const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+/// Named rule set selected by [`crate::processing::ProcessingOptions::anonymize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnonymizationProfile {
+    /// Keep the route for sharing publicly, but hide where it starts and ends.
+    Public,
+    /// Remove the route entirely.
+    Private,
+}
+
+impl AnonymizationProfile {
+    /// Rules applied by this profile, in order.
+    pub fn rules(self) -> &'static [AnonymizeRule] {
+        match self {
+            AnonymizationProfile::Public => &[
+                AnonymizeRule::FuzzStartEnd,
+                AnonymizeRule::StripSerials,
+                AnonymizeRule::StripHeartRate,
+                AnonymizeRule::RoundTimestamps,
+            ],
+            AnonymizationProfile::Private => &[
+                AnonymizeRule::StripGps,
+                AnonymizeRule::StripSerials,
+                AnonymizeRule::StripHeartRate,
+                AnonymizeRule::RoundTimestamps,
+            ],
+        }
+    }
+}
+
+impl std::str::FromStr for AnonymizationProfile {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "public" => Ok(AnonymizationProfile::Public),
+            "private" => Ok(AnonymizationProfile::Private),
+            _ => Err(()),
+        }
+    }
+}
+
+/// One privacy rewrite; profiles combine several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnonymizeRule {
+    /// Drop every position field from every message.
+    StripGps,
+    /// Drop record positions within [`FUZZ_RADIUS_METERS`] of the first or
+    /// last fix, and the start, end, and bounding-box positions of laps and
+    /// sessions.
+    FuzzStartEnd,
+    /// Drop `serial_number` fields, e.g. from `FileId` and `DeviceInfo`.
+    StripSerials,
+    /// Drop heart-rate fields and HRV messages.
+    StripHeartRate,
+    /// Shift every timestamp so the activity starts on a whole minute; the
+    /// spacing between samples is unchanged.
+    RoundTimestamps,
+}
+
+impl Transform for AnonymizeRule {
+    fn name(&self) -> &'static str {
+        match self {
+            AnonymizeRule::StripGps => "anonymize_strip_gps",
+            AnonymizeRule::FuzzStartEnd => "anonymize_fuzz_start_end",
+            AnonymizeRule::StripSerials => "anonymize_strip_serials",
+            AnonymizeRule::StripHeartRate => "anonymize_strip_heart_rate",
+            AnonymizeRule::RoundTimestamps => "anonymize_round_timestamps",
+        }
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        match self {
+            AnonymizeRule::StripGps => drop_fields(ctx, INDOOR_POSITION_FIELDS),
+            AnonymizeRule::FuzzStartEnd => fuzz_start_end(ctx),
+            AnonymizeRule::StripSerials => drop_fields(ctx, &["serial_number"]),
+            AnonymizeRule::StripHeartRate => {
+                ctx.retain_records(|record| !matches!(record.kind(), MesgNum::Hrv));
+                drop_fields(ctx, HEART_RATE_FIELDS);
+            }
+            AnonymizeRule::RoundTimestamps => round_timestamps(ctx),
+        }
+    }
+}
+
+fn drop_fields(ctx: &mut RecordContext, names: &[&str]) {
+    ctx.rewrite_fields(|_, _, field| {
+        if names.contains(&field.name()) {
+            FieldEdit::Drop
+        } else {
+            FieldEdit::Keep
+        }
+    });
+}
+
+fn fuzz_start_end(ctx: &mut RecordContext) {
+    let positions: Vec<Option<(f64, f64)>> = ctx.records().iter().map(record_position).collect();
+    let (Some(&first), Some(&last)) = (
+        positions.iter().flatten().next(),
+        positions.iter().flatten().next_back(),
+    ) else {
+        return;
+    };
+    let hidden: Vec<bool> = positions
+        .iter()
+        .map(|position| {
+            position.is_some_and(|position| {
+                distance_meters(position, first) < FUZZ_RADIUS_METERS
+                    || distance_meters(position, last) < FUZZ_RADIUS_METERS
+            })
+        })
+        .collect();
+
+    ctx.rewrite_fields(|idx, record, field| {
+        let name = field.name();
+        if !INDOOR_POSITION_FIELDS.contains(&name) {
+            return FieldEdit::Keep;
+        }
+        // Lap and session positions point straight at the start and end.
+        if !matches!(record.kind(), MesgNum::Record) || hidden[idx] {
+            FieldEdit::Drop
+        } else {
+            FieldEdit::Keep
+        }
+    });
+}
+
+fn round_timestamps(ctx: &mut RecordContext) {
+    let Some(start) = ctx.records().iter().find_map(record_timestamp) else {
+        return;
+    };
+    let shift = start.rem_euclid(60.0);
+    if shift == 0.0 {
+        return;
+    }
+
+    ctx.rewrite_fields(|_, _, field| match field.value() {
+        Value::Timestamp(time) => timestamp_value(time.timestamp() as f64 - shift)
+            .map(FieldEdit::Replace)
+            .unwrap_or(FieldEdit::Keep),
+        _ => FieldEdit::Keep,
+    });
+}
+
+/// Latitude and longitude of a `Record` message, in degrees.
+pub(crate) fn record_position(record: &FitDataRecord) -> Option<(f64, f64)> {
+    if !matches!(record.kind(), MesgNum::Record) {
+        return None;
+    }
+    let mut lat = None;
+    let mut long = None;
+    for field in record.fields() {
+        match field.name() {
+            "position_lat" => lat = field_value_to_f64(field),
+            "position_long" => long = field_value_to_f64(field),
+            _ => {}
+        }
+    }
+    lat.zip(long)
+        .map(|(lat, long)| (lat * SEMICIRCLES_TO_DEGREES, long * SEMICIRCLES_TO_DEGREES))
+}
+
+/// Position of a `Record` message as stored, in semicircles.
+pub(crate) fn record_semicircles(record: &FitDataRecord) -> Option<(i32, i32)> {
+    if !matches!(record.kind(), MesgNum::Record) {
+        return None;
+    }
+    let field = |name: &str| {
+        let field = record.fields().iter().find(|field| field.name() == name)?;
+        field_value_to_f64(field).map(|value| value as i32)
+    };
+    field("position_lat").zip(field("position_long"))
+}
+
+/// Great-circle distance between two positions given in degrees.
+pub(crate) fn distance_meters((lat1, long1): (f64, f64), (lat2, long2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlong = (long2 - long1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlong / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::{field_names, record_with};
+
+    /// Semicircles for `degrees`, as stored in FIT position fields.
+    fn semicircles(degrees: f64) -> Value {
+        Value::SInt32((degrees / SEMICIRCLES_TO_DEGREES).round() as i32)
+    }
+
+    #[test]
+    fn fuzz_hides_positions_near_start_and_end_only() {
+        // Roughly 111 m per 0.001° of latitude.
+        let records = [0.0, 0.001, 0.01, 0.02, 0.029, 0.03]
+            .iter()
+            .map(|&lat| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("position_lat", semicircles(lat)),
+                        ("position_long", semicircles(0.0)),
+                        ("heart_rate", Value::UInt8(140)),
+                    ],
+                )
+            })
+            .collect();
+        let mut ctx = RecordContext::new(records);
+
+        AnonymizeRule::FuzzStartEnd.apply(&mut ctx);
+
+        let with_position: Vec<bool> = ctx
+            .records()
+            .iter()
+            .map(|record| field_names(record).contains(&"position_lat"))
+            .collect();
+        assert_eq!(with_position, vec![false, false, true, true, false, false]);
+    }
+
+    #[test]
+    fn round_timestamps_keeps_spacing() {
+        let records = [1_700_000_042.0, 1_700_000_043.0]
+            .iter()
+            .map(|&ts| {
+                record_with(
+                    MesgNum::Record,
+                    &[("timestamp", timestamp_value(ts).unwrap())],
+                )
+            })
+            .collect();
+        let mut ctx = RecordContext::new(records);
+
+        AnonymizeRule::RoundTimestamps.apply(&mut ctx);
+
+        let timestamps: Vec<f64> = ctx.records().iter().filter_map(record_timestamp).collect();
+        assert_eq!(timestamps, vec![1_700_000_040.0, 1_700_000_041.0]);
+    }
+
+    #[test]
+    fn profiles_parse_from_form_values() {
+        assert_eq!("public".parse(), Ok(AnonymizationProfile::Public));
+        assert_eq!(" private ".parse(), Ok(AnonymizationProfile::Private));
+        assert!("everything".parse::<AnonymizationProfile>().is_err());
+    }
+}
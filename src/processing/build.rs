@@ -0,0 +1,817 @@
+//! Activity files built from scratch, for platforms that only export GPX or
+//! CSV.
+//!
+//! Samples are read from the track, then written as the minimal message set
+//! most platforms expect of an activity: `FileId`, a timer start `Event`,
+//! one `Record` per sample, a timer stop `Event`, and a single `Lap`,
+//! `Session`, and `Activity` summarising the whole track. The result goes
+//! through the normal processing pipeline like any uploaded FIT file.
+//!
+//! CSV input needs a header row naming its columns. `timestamp` (or `time`)
+//! is required, as RFC 3339 or Unix seconds. Numbers before
+//! [`PLAUSIBLE_EPOCH_SECONDS`] throughout the file are seconds elapsed since
+//! the activity started instead, and are placed so the activity ends when it
+//! was received. `lat`/`latitude`,
+//! `lon`/`lng`/`longitude` (degrees), `altitude`/`elevation`/`ele` (metres),
+//! `heart_rate`/`hr`, `cadence`, `power`/`watts`, and `distance` (metres) are
+//! optional. Values are split on commas without quoting.
+
+use crate::processing::anonymize::{SEMICIRCLES_TO_DEGREES, distance_meters};
+use crate::processing::transforms::timestamp_value;
+use chrono::{DateTime, NaiveDateTime};
+use fitparser::profile::MesgNum;
+use fitparser::{BaseType, FitDataField, FitDataRecord, TimestampKind, Value, encode_records};
+
+/// Seconds between the Unix epoch and the FIT epoch (1989-12-31T00:00:00Z).
+const FIT_EPOCH_OFFSET: f64 = 631_065_600.0;
+
+/// Unix time of 2000-01-01T00:00:00Z. CSV times before it are read as
+/// seconds elapsed since the start, since no device recorded before it.
+pub const PLAUSIBLE_EPOCH_SECONDS: f64 = 946_684_800.0;
+
+/// `manufacturer` written to `FileId`: the profile's `development` value.
+const DEVELOPMENT_MANUFACTURER: u16 = 255;
+
+/// Input formats an activity can be built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Csv,
+    Gpx,
+}
+
+impl SourceFormat {
+    /// Format named by the file's extension, or `None` for FIT and anything
+    /// else.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+        match extension.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "gpx" => Some(Self::Gpx),
+            _ => None,
+        }
+    }
+}
+
+/// One point of the track being built.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sample {
+    /// Seconds since the Unix epoch.
+    pub timestamp: f64,
+    /// Latitude and longitude in degrees.
+    pub position: Option<(f64, f64)>,
+    pub altitude: Option<f64>,
+    pub heart_rate: Option<u8>,
+    pub cadence: Option<u8>,
+    pub power: Option<u16>,
+    /// Cumulative distance in metres; derived from positions when missing.
+    pub distance: Option<f64>,
+}
+
+/// A track read from GPX or CSV.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    /// Profile sport name, e.g. `running`; `generic` when unknown.
+    pub sport: Option<&'static str>,
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum BuildError {
+    /// The input is not valid UTF-8 text.
+    #[error("File is not UTF-8 text")]
+    NotText,
+    #[error("CSV has no {0} column")]
+    MissingColumn(&'static str),
+    /// A value on a 1-based line could not be read.
+    #[error("Invalid {column} on line {line}")]
+    InvalidValue { line: usize, column: String },
+    #[error("File has no timestamped track points")]
+    NoSamples,
+    /// A field of the manual activity form could not be read.
+    #[error("Invalid {0}")]
+    InvalidField(&'static str),
+    #[error("Failed to write FIT file: {0}")]
+    Encode(String),
+}
+
+/// Longest activity that can be logged by hand: a week, well inside the
+/// millisecond `total_elapsed_time` a FIT session can hold.
+pub const MAX_MANUAL_DURATION_SECONDS: f64 = 7.0 * 24.0 * 3600.0;
+
+/// Sports offered when logging an activity by hand; `generic` is anything
+/// else.
+pub const MANUAL_SPORTS: &[&str] = &[
+    "running", "cycling", "swimming", "walking", "hiking", "generic",
+];
+
+/// A workout logged by hand, for sessions recorded without a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManualActivity {
+    /// One of [`MANUAL_SPORTS`].
+    pub sport: &'static str,
+    /// Seconds since the Unix epoch.
+    pub start: f64,
+    pub duration_seconds: f64,
+    pub distance_meters: Option<f64>,
+}
+
+impl ManualActivity {
+    /// Read the `/create` form: a `datetime-local` start in the user's time
+    /// zone, `utc_offset_minutes` east of UTC, a duration as `h:mm:ss`,
+    /// `mm:ss`, or minutes, and an optional distance in kilometres.
+    pub fn from_form(
+        sport: &str,
+        start: &str,
+        utc_offset_minutes: i32,
+        duration: &str,
+        distance_km: &str,
+    ) -> Result<Self, BuildError> {
+        let sport = MANUAL_SPORTS
+            .iter()
+            .copied()
+            .find(|name| *name == sport)
+            .ok_or(BuildError::InvalidField("sport"))?;
+        let start = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(start.trim(), format).ok())
+            .ok_or(BuildError::InvalidField("start time"))?;
+        let start = start.and_utc().timestamp() as f64 - f64::from(utc_offset_minutes) * 60.0;
+        let duration_seconds = parse_duration(duration)
+            .filter(|seconds| *seconds > 0.0)
+            .ok_or(BuildError::InvalidField("duration"))?;
+        let distance_meters = match distance_km.trim() {
+            "" => None,
+            km => Some(
+                km.parse::<f64>()
+                    .ok()
+                    .filter(|km| km.is_finite() && *km >= 0.0)
+                    .ok_or(BuildError::InvalidField("distance"))?
+                    * 1000.0,
+            ),
+        };
+        Ok(Self {
+            sport,
+            start,
+            duration_seconds,
+            distance_meters,
+        })
+    }
+
+    /// The activity as a two-point track, from its start to its end.
+    pub fn track(&self) -> Track {
+        let point = |timestamp: f64, distance: Option<f64>| Sample {
+            timestamp,
+            distance,
+            ..Sample::default()
+        };
+        Track {
+            sport: Some(self.sport),
+            samples: vec![
+                point(self.start, self.distance_meters.map(|_| 0.0)),
+                point(self.start + self.duration_seconds, self.distance_meters),
+            ],
+        }
+    }
+}
+
+/// `h:mm:ss`, `mm:ss`, or a plain number of minutes, in seconds; `None` for
+/// anything longer than [`MAX_MANUAL_DURATION_SECONDS`].
+fn parse_duration(text: &str) -> Option<f64> {
+    let parts: Vec<f64> = text
+        .trim()
+        .split(':')
+        .map(|part| {
+            part.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite() && *n >= 0.0)
+        })
+        .collect::<Option<_>>()?;
+    let seconds = match parts[..] {
+        [minutes] => minutes * 60.0,
+        [minutes, seconds] => minutes * 60.0 + seconds,
+        [hours, minutes, seconds] => hours * 3600.0 + minutes * 60.0 + seconds,
+        _ => return None,
+    };
+    (seconds <= MAX_MANUAL_DURATION_SECONDS).then_some(seconds)
+}
+
+/// Build FIT bytes for an activity logged by hand.
+pub fn build_manual_fit(activity: &ManualActivity) -> Result<Vec<u8>, BuildError> {
+    let records = build_activity(&activity.track())?;
+    encode_records(&records).map_err(|err| BuildError::Encode(err.to_string()))
+}
+
+/// Build FIT bytes for an activity from a GPX or CSV file received at
+/// `received_at` (Unix seconds).
+pub fn build_fit(
+    bytes: &[u8],
+    format: SourceFormat,
+    received_at: f64,
+) -> Result<Vec<u8>, BuildError> {
+    let text = std::str::from_utf8(bytes).map_err(|_| BuildError::NotText)?;
+    let track = match format {
+        SourceFormat::Csv => parse_csv(text, received_at)?,
+        SourceFormat::Gpx => parse_gpx(text)?,
+    };
+    let records = build_activity(&track)?;
+    encode_records(&records).map_err(|err| BuildError::Encode(err.to_string()))
+}
+
+/// Read samples from CSV with a header row; see the module documentation for
+/// the recognised columns. Elapsed-seconds times are placed so the last
+/// sample falls at `received_at`.
+pub fn parse_csv(text: &str, received_at: f64) -> Result<Track, BuildError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let Some((_, header)) = lines.next() else {
+        return Err(BuildError::NoSamples);
+    };
+    let columns: Vec<String> = header
+        .split(',')
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+    let column = |names: &[&str]| {
+        columns
+            .iter()
+            .position(|name| names.contains(&name.as_str()))
+    };
+    let timestamp = column(&["timestamp", "time"]).ok_or(BuildError::MissingColumn("timestamp"))?;
+    let lat = column(&["lat", "latitude"]);
+    let lon = column(&["lon", "lng", "long", "longitude"]);
+    let altitude = column(&["altitude", "elevation", "ele"]);
+    let heart_rate = column(&["heart_rate", "hr", "heartrate"]);
+    let cadence = column(&["cadence", "cad"]);
+    let power = column(&["power", "watts"]);
+    let distance = column(&["distance"]);
+
+    let mut samples = Vec::new();
+    for (index, line) in lines {
+        let values: Vec<&str> = line.split(',').map(str::trim).collect();
+        let invalid = |column: usize| BuildError::InvalidValue {
+            line: index + 1,
+            column: columns[column].clone(),
+        };
+        // Blank cells are missing values, anything else must parse.
+        let cell = |column: Option<usize>| -> Result<Option<f64>, BuildError> {
+            let Some(column) = column else {
+                return Ok(None);
+            };
+            match values.get(column).copied().unwrap_or_default() {
+                "" => Ok(None),
+                value => value.parse().map(Some).map_err(|_| invalid(column)),
+            }
+        };
+        let time = values.get(timestamp).copied().unwrap_or_default();
+        let time = parse_time(time).ok_or_else(|| invalid(timestamp))?;
+        samples.push(Sample {
+            timestamp: time,
+            position: cell(lat)?.zip(cell(lon)?),
+            altitude: cell(altitude)?,
+            heart_rate: cell(heart_rate)?.map(|bpm| bpm.round() as u8),
+            cadence: cell(cadence)?.map(|rpm| rpm.round() as u8),
+            power: cell(power)?.map(|watts| watts.round() as u16),
+            distance: cell(distance)?,
+        });
+    }
+    let last = samples
+        .iter()
+        .map(|sample| sample.timestamp)
+        .fold(f64::MIN, f64::max);
+    if !samples.is_empty() && last < PLAUSIBLE_EPOCH_SECONDS {
+        let start = received_at - last;
+        for sample in &mut samples {
+            sample.timestamp += start;
+        }
+    }
+    Ok(Track {
+        sport: None,
+        samples,
+    })
+}
+
+/// Read the track points of every track in a GPX file, with heart rate,
+/// cadence, and power from the Garmin `TrackPointExtension` or any other
+/// extension using the same element names.
+pub fn parse_gpx(text: &str) -> Result<Track, BuildError> {
+    let sport = element_text(text, "type").and_then(sport_name);
+    let mut samples = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("<trkpt") {
+        let point = &rest[start..];
+        let end = point
+            .find("</trkpt>")
+            .or_else(|| point.find("/>").map(|end| end + 2))
+            .unwrap_or(point.len());
+        let (point, tail) = point.split_at(end);
+        rest = tail;
+
+        // Points without a time cannot be placed in an activity.
+        let Some(timestamp) = element_text(point, "time").and_then(parse_time) else {
+            continue;
+        };
+        let tag = &point[..point.find('>').unwrap_or(point.len())];
+        let number =
+            |name: &str| element_text(point, name).and_then(|text| text.parse::<f64>().ok());
+        samples.push(Sample {
+            timestamp,
+            position: attribute(tag, "lat").zip(attribute(tag, "lon")),
+            altitude: number("ele"),
+            heart_rate: number("hr").map(|bpm| bpm.round() as u8),
+            cadence: number("cad").map(|rpm| rpm.round() as u8),
+            power: number("power")
+                .or_else(|| number("watts"))
+                .map(|watts| watts.round() as u16),
+            distance: None,
+        });
+    }
+    Ok(Track { sport, samples })
+}
+
+/// RFC 3339 time or Unix seconds.
+fn parse_time(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Ok(seconds) = text.parse::<f64>() {
+        return Some(seconds);
+    }
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|time| time.timestamp_millis() as f64 / 1000.0)
+}
+
+/// Text of the first element named `name` in any namespace, e.g. both `<hr>`
+/// and `<gpxtpx:hr>`.
+fn element_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = xml;
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open + 1..];
+        let tag_end = rest.find('>')?;
+        let tag = &rest[..tag_end];
+        let tag_name = tag.split_whitespace().next().unwrap_or_default();
+        let local = tag_name.rsplit(':').next().unwrap_or_default();
+        if local == name && !tag.ends_with('/') {
+            let content = &rest[tag_end + 1..];
+            return Some(content[..content.find('<')?].trim());
+        }
+    }
+    None
+}
+
+/// Numeric value of attribute `name` in an opening tag.
+fn attribute(tag: &str, name: &str) -> Option<f64> {
+    let mut rest = tag;
+    loop {
+        let at = rest.find(name)?;
+        let preceded_by_space = rest[..at].ends_with(char::is_whitespace);
+        rest = &rest[at + name.len()..];
+        let Some(value) = rest
+            .trim_start()
+            .strip_prefix('=')
+            .filter(|_| preceded_by_space)
+        else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        return value[..value.find(quote)?].parse().ok();
+    }
+}
+
+/// Profile sport for a GPX `<type>`, as written by Strava, Garmin, and others.
+fn sport_name(kind: &str) -> Option<&'static str> {
+    Some(match kind.to_ascii_lowercase().as_str() {
+        "running" | "run" | "9" => "running",
+        "cycling" | "biking" | "ride" | "1" => "cycling",
+        "walking" | "walk" | "10" => "walking",
+        "hiking" | "hike" | "4" => "hiking",
+        "swimming" | "swim" => "swimming",
+        _ => return None,
+    })
+}
+
+/// Profile number of a sport returned by [`sport_name`].
+fn sport_number(sport: &str) -> u8 {
+    match sport {
+        "running" => 1,
+        "cycling" => 2,
+        "swimming" => 5,
+        "walking" => 11,
+        "hiking" => 17,
+        _ => 0,
+    }
+}
+
+/// The messages of an activity holding `track`, in file order.
+pub fn build_activity(track: &Track) -> Result<Vec<FitDataRecord>, BuildError> {
+    let mut samples = track.samples.clone();
+    samples.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return Err(BuildError::NoSamples);
+    };
+    let (start, end) = (first.timestamp, last.timestamp);
+    fill_distance(&mut samples);
+
+    let elapsed = end - start;
+    let distance = samples.iter().rev().find_map(|sample| sample.distance);
+    let start_position = samples.iter().find_map(|sample| sample.position);
+    let heart_rates: Vec<f64> = samples
+        .iter()
+        .filter_map(|sample| sample.heart_rate.map(f64::from))
+        .collect();
+    let powers: Vec<f64> = samples
+        .iter()
+        .filter_map(|sample| sample.power.map(f64::from))
+        .collect();
+    let sport = track.sport.unwrap_or("generic");
+
+    let mut records = Vec::with_capacity(samples.len() + 6);
+
+    let mut file_id = FitDataRecord::new(MesgNum::FileId);
+    file_id.push(enum_field("type", 0, "activity", 4));
+    file_id.push(uint16("manufacturer", 1, "", DEVELOPMENT_MANUFACTURER));
+    file_id.push(uint16("product", 2, "", 0));
+    file_id.push(timestamp_field("time_created", 4, start));
+    records.push(file_id);
+
+    records.push(timer_event(start, ("start", 0)));
+
+    for sample in &samples {
+        let mut record = FitDataRecord::new(MesgNum::Record);
+        record.push(timestamp_field("timestamp", 253, sample.timestamp));
+        if let Some((lat, lon)) = sample.position {
+            record.push(semicircles("position_lat", 0, lat));
+            record.push(semicircles("position_long", 1, lon));
+        }
+        if let Some(altitude) = sample.altitude {
+            record.push(altitude_field(altitude));
+        }
+        if let Some(heart_rate) = sample.heart_rate {
+            record.push(uint8("heart_rate", 3, "bpm", heart_rate));
+        }
+        if let Some(cadence) = sample.cadence {
+            record.push(uint8("cadence", 4, "rpm", cadence));
+        }
+        if let Some(distance) = sample.distance {
+            record.push(scaled_u32("distance", 5, "m", distance, 100.0));
+        }
+        if let Some(power) = sample.power {
+            record.push(uint16("power", 7, "watts", power));
+        }
+        records.push(record);
+    }
+
+    records.push(timer_event(end, ("stop_all", 4)));
+
+    // Lap and Session share field numbers up to `total_distance`.
+    let totals = |kind: MesgNum, event: (&str, u8)| {
+        let mut message = FitDataRecord::new(kind);
+        message.push(timestamp_field("timestamp", 253, end));
+        message.push(enum_field("event", 0, event.0, event.1));
+        message.push(enum_field("event_type", 1, "stop", 1));
+        message.push(timestamp_field("start_time", 2, start));
+        if let Some((lat, lon)) = start_position {
+            message.push(semicircles("start_position_lat", 3, lat));
+            message.push(semicircles("start_position_long", 4, lon));
+        }
+        message.push(scaled_u32("total_elapsed_time", 7, "s", elapsed, 1000.0));
+        message.push(scaled_u32("total_timer_time", 8, "s", elapsed, 1000.0));
+        if let Some(distance) = distance {
+            message.push(scaled_u32("total_distance", 9, "m", distance, 100.0));
+        }
+        message
+    };
+
+    let mut lap = totals(MesgNum::Lap, ("lap", 9));
+    push_heart_rate_and_power(&mut lap, &heart_rates, &powers, [15, 16, 19, 20]);
+    records.push(lap);
+
+    let mut session = totals(MesgNum::Session, ("session", 8));
+    session.push(enum_field("sport", 5, sport, sport_number(sport)));
+    session.push(enum_field("sub_sport", 6, "generic", 0));
+    push_heart_rate_and_power(&mut session, &heart_rates, &powers, [16, 17, 20, 21]);
+    session.push(uint16("first_lap_index", 25, "", 0));
+    session.push(uint16("num_laps", 26, "", 1));
+    records.push(session);
+
+    let mut activity = FitDataRecord::new(MesgNum::Activity);
+    activity.push(timestamp_field("timestamp", 253, end));
+    activity.push(scaled_u32("total_timer_time", 0, "s", elapsed, 1000.0));
+    activity.push(uint16("num_sessions", 1, "", 1));
+    activity.push(enum_field("type", 2, "manual", 0));
+    activity.push(enum_field("event", 3, "activity", 26));
+    activity.push(enum_field("event_type", 4, "stop", 1));
+    records.push(activity);
+
+    Ok(records)
+}
+
+/// Fill in cumulative distance from positions for samples that lack it,
+/// continuing from the last recorded distance.
+fn fill_distance(samples: &mut [Sample]) {
+    if samples.iter().all(|sample| sample.position.is_none()) {
+        return;
+    }
+    let mut total = 0.0;
+    let mut previous: Option<(f64, f64)> = None;
+    for sample in samples {
+        if let Some(position) = sample.position {
+            if let Some(previous) = previous {
+                total += distance_meters(previous, position);
+            }
+            previous = Some(position);
+        }
+        match sample.distance {
+            Some(distance) => total = distance,
+            None => sample.distance = Some(total),
+        }
+    }
+}
+
+/// Average and maximum heart rate and power, at the given field numbers.
+fn push_heart_rate_and_power(
+    message: &mut FitDataRecord,
+    heart_rates: &[f64],
+    powers: &[f64],
+    [avg_hr, max_hr, avg_power, max_power]: [u8; 4],
+) {
+    if let Some((avg, max)) = average_and_max(heart_rates) {
+        message.push(uint8("avg_heart_rate", avg_hr, "bpm", avg.round() as u8));
+        message.push(uint8("max_heart_rate", max_hr, "bpm", max as u8));
+    }
+    if let Some((avg, max)) = average_and_max(powers) {
+        message.push(uint16("avg_power", avg_power, "watts", avg.round() as u16));
+        message.push(uint16("max_power", max_power, "watts", max as u16));
+    }
+}
+
+fn average_and_max(values: &[f64]) -> Option<(f64, f64)> {
+    if values.is_empty() {
+        return None;
+    }
+    let sum: f64 = values.iter().sum();
+    let max = values.iter().copied().fold(f64::MIN, f64::max);
+    Some((sum / values.len() as f64, max))
+}
+
+fn timer_event(at: f64, event_type: (&str, u8)) -> FitDataRecord {
+    let mut event = FitDataRecord::new(MesgNum::Event);
+    event.push(timestamp_field("timestamp", 253, at));
+    event.push(enum_field("event", 0, "timer", 0));
+    event.push(enum_field("event_type", 1, event_type.0, event_type.1));
+    event.push(uint8("event_group", 4, "", 0));
+    event
+}
+
+fn field(
+    name: &str,
+    number: u8,
+    value: Value,
+    raw: Value,
+    units: &str,
+    base_type: BaseType,
+) -> FitDataField {
+    FitDataField::with_meta(
+        name.to_string(),
+        number,
+        None,
+        value,
+        raw,
+        units.to_string(),
+        base_type,
+        1.0,
+        0.0,
+        TimestampKind::None,
+    )
+}
+
+fn uint8(name: &str, number: u8, units: &str, value: u8) -> FitDataField {
+    field(
+        name,
+        number,
+        Value::UInt8(value),
+        Value::UInt8(value),
+        units,
+        BaseType::UInt8,
+    )
+}
+
+fn uint16(name: &str, number: u8, units: &str, value: u16) -> FitDataField {
+    field(
+        name,
+        number,
+        Value::UInt16(value),
+        Value::UInt16(value),
+        units,
+        BaseType::UInt16,
+    )
+}
+
+/// An enum field holding profile value `name`, stored as `number`.
+fn enum_field(field_name: &str, number: u8, name: &str, value: u8) -> FitDataField {
+    field(
+        field_name,
+        number,
+        Value::String(name.to_string()),
+        Value::Enum(value),
+        "",
+        BaseType::Enum,
+    )
+}
+
+/// A `date_time` field, stored as seconds since the FIT epoch.
+fn timestamp_field(name: &str, number: u8, ts: f64) -> FitDataField {
+    let raw = Value::UInt32((ts - FIT_EPOCH_OFFSET).max(0.0) as u32);
+    let value = timestamp_value(ts).unwrap_or_else(|| raw.clone());
+    field(name, number, value, raw, "s", BaseType::UInt32)
+}
+
+fn semicircles(name: &str, number: u8, degrees: f64) -> FitDataField {
+    let semicircles = (degrees / SEMICIRCLES_TO_DEGREES).round() as i32;
+    field(
+        name,
+        number,
+        Value::SInt32(semicircles),
+        Value::SInt32(semicircles),
+        "semicircles",
+        BaseType::SInt32,
+    )
+}
+
+/// A `uint32` field stored as `value * scale`, like FIT times and distances.
+fn scaled_u32(name: &str, number: u8, units: &str, value: f64, scale: f64) -> FitDataField {
+    FitDataField::with_meta(
+        name.to_string(),
+        number,
+        None,
+        Value::Float64(value),
+        Value::UInt32((value.max(0.0) * scale).round() as u32),
+        units.to_string(),
+        BaseType::UInt32,
+        scale,
+        0.0,
+        TimestampKind::None,
+    )
+}
+
+/// `Record.altitude`, stored as `(metres + 500) * 5`.
+fn altitude_field(metres: f64) -> FitDataField {
+    FitDataField::with_meta(
+        "altitude".to_string(),
+        2,
+        None,
+        Value::Float64(metres),
+        Value::UInt16(((metres + 500.0).max(0.0) * 5.0).round() as u16),
+        "m".to_string(),
+        BaseType::UInt16,
+        5.0,
+        500.0,
+        TimestampKind::None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::{ProcessingOptions, process_fit_bytes};
+
+    const GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+  <trk>
+    <type>running</type>
+    <trkseg>
+      <trkpt lat="47.3769" lon="8.5417"><ele>408.0</ele><time>2024-05-01T06:00:00Z</time>
+        <extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>120</gpxtpx:hr><gpxtpx:cad>80</gpxtpx:cad></gpxtpx:TrackPointExtension></extensions>
+      </trkpt>
+      <trkpt lat="47.3778" lon="8.5417"><ele>410.0</ele><time>2024-05-01T06:00:30Z</time>
+        <extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>140</gpxtpx:hr></gpxtpx:TrackPointExtension></extensions>
+      </trkpt>
+      <trkpt lat="47.3787" lon="8.5417"><time>2024-05-01T06:01:00Z</time></trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#;
+
+    #[test]
+    fn gpx_track_points_keep_position_time_and_extensions() {
+        let track = parse_gpx(GPX).unwrap();
+
+        assert_eq!(track.sport, Some("running"));
+        assert_eq!(track.samples.len(), 3);
+        assert_eq!(track.samples[0].position, Some((47.3769, 8.5417)));
+        assert_eq!(track.samples[0].altitude, Some(408.0));
+        assert_eq!(track.samples[0].heart_rate, Some(120));
+        assert_eq!(track.samples[0].cadence, Some(80));
+        assert_eq!(
+            track.samples[1].timestamp - track.samples[0].timestamp,
+            30.0
+        );
+        assert_eq!(track.samples[2].altitude, None);
+    }
+
+    #[test]
+    fn csv_elapsed_seconds_end_when_received() {
+        let received_at = 1_714_543_800.0;
+        let track = parse_csv("time,hr\n0,110\n1.5,112\n600,140\n", received_at).unwrap();
+
+        let times: Vec<f64> = track
+            .samples
+            .iter()
+            .map(|sample| sample.timestamp)
+            .collect();
+        assert_eq!(
+            times,
+            [received_at - 600.0, received_at - 598.5, received_at]
+        );
+    }
+
+    #[test]
+    fn csv_columns_are_matched_by_header() {
+        let csv = "time,HR,power,lat,lon\n\
+                   2024-05-01T06:00:00Z,120,200,47.3769,8.5417\n\
+                   1714543201,,210,,\n";
+        let track = parse_csv(csv, 0.0).unwrap();
+
+        assert_eq!(track.samples[0].heart_rate, Some(120));
+        assert_eq!(track.samples[0].power, Some(200));
+        assert_eq!(track.samples[1].timestamp, 1_714_543_201.0);
+        assert_eq!(track.samples[1].heart_rate, None);
+        assert_eq!(track.samples[1].position, None);
+
+        assert_eq!(
+            parse_csv("hr\n120\n", 0.0),
+            Err(BuildError::MissingColumn("timestamp"))
+        );
+        assert_eq!(
+            parse_csv("time,hr\n1714543200,fast\n", 0.0),
+            Err(BuildError::InvalidValue {
+                line: 2,
+                column: "hr".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn built_activities_decode_with_a_session_summary() {
+        let bytes = build_fit(GPX.as_bytes(), SourceFormat::Gpx, 0.0).unwrap();
+        let processed = process_fit_bytes(&bytes, &ProcessingOptions::default()).unwrap();
+
+        let kinds = message_kinds(&bytes);
+        assert_eq!(kinds.first(), Some(&MesgNum::FileId));
+        assert_eq!(kinds.last(), Some(&MesgNum::Activity));
+        assert_eq!(
+            kinds
+                .iter()
+                .filter(|kind| **kind == MesgNum::Record)
+                .count(),
+            3
+        );
+        let distance = processed.summary.distance_meters.unwrap();
+        assert!((distance - 200.0).abs() < 1.0, "{distance}");
+        assert!(matches!(
+            build_activity(&Track::default()),
+            Err(BuildError::NoSamples)
+        ));
+        assert_eq!(processed.summary.heart_rate_max, Some(140.0));
+    }
+
+    #[test]
+    fn manual_activities_span_their_duration_and_distance() {
+        let activity =
+            ManualActivity::from_form("running", "2024-05-01T07:30", 120, "45:00", "10.5").unwrap();
+        assert_eq!(activity.start, 1_714_541_400.0);
+        assert_eq!(activity.duration_seconds, 2_700.0);
+
+        let bytes = build_manual_fit(&activity).unwrap();
+        let processed = process_fit_bytes(&bytes, &ProcessingOptions::default()).unwrap();
+        assert_eq!(processed.summary.duration_seconds, Some(2_700.0));
+        assert_eq!(processed.summary.distance_meters, Some(10_500.0));
+
+        assert_eq!(parse_duration("1:02:03"), Some(3_723.0));
+        assert_eq!(
+            parse_duration("168:00:00"),
+            Some(MAX_MANUAL_DURATION_SECONDS)
+        );
+        assert_eq!(parse_duration("168:00:01"), None);
+        assert_eq!(parse_duration("1e400"), None);
+        assert_eq!(parse_duration("inf"), None);
+        assert_eq!(parse_duration("NaN"), None);
+        assert_eq!(
+            ManualActivity::from_form("running", "2024-05-01T07:30", 0, "0", ""),
+            Err(BuildError::InvalidField("duration"))
+        );
+        assert_eq!(
+            ManualActivity::from_form("curling", "2024-05-01T07:30", 0, "30", ""),
+            Err(BuildError::InvalidField("sport"))
+        );
+    }
+
+    fn message_kinds(bytes: &[u8]) -> Vec<MesgNum> {
+        crate::processing::parse_fit(bytes)
+            .unwrap()
+            .iter()
+            .map(|record| record.kind())
+            .collect()
+    }
+}
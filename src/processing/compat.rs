@@ -0,0 +1,524 @@
+//! Fixes for known quirks of files written by non-Garmin devices, applied
+//! before any user-selected transform.
+//!
+//! The vendor is read from the `manufacturer` field of the `FileId` message,
+//! so the fixes only ever touch files from the device family they target:
+//!
+//! - Wahoo ELEMNT units pad `Record` messages with zeros instead of the FIT
+//!   invalid value while sensors and GPS are still connecting, which shows up
+//!   as a 0 bpm heart rate and a jump to 0°N 0°E.
+//! - Some COROS firmware writes message timestamps in local time, so records,
+//!   laps, and events start hours away from the session's `start_time`.
+//! - Suunto exports can leave `total_elapsed_time`, `total_timer_time`, and
+//!   `total_distance` out of the `Session` message.
+//!
+//! Polar files are recognised but need no fixes yet. The
+//! `skip_vendor_fixes` option turns all of them off for one upload.
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{
+    FieldEdit, RecordContext, Transform, record_timestamp, timestamp_value,
+};
+use crate::processing::types::{ProcessingWarning, WarningCode};
+use fitparser::profile::MesgNum;
+use fitparser::{BaseType, FitDataField, FitDataRecord, TimestampKind, Value};
+use serde::Serialize;
+
+/// Largest local-time offset a COROS file is corrected for.
+const MAX_CLOCK_OFFSET_SECONDS: f64 = 14.0 * 3600.0;
+
+/// Time zones are offset from UTC by whole quarter hours.
+const CLOCK_OFFSET_STEP_SECONDS: f64 = 900.0;
+
+/// How far the first record may start from the session once the clock offset
+/// is removed, e.g. when the device logged its first sample late.
+const CLOCK_OFFSET_TOLERANCE_SECONDS: f64 = 60.0;
+
+/// Device maker with known quirks, from `FileId.manufacturer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Vendor {
+    Wahoo,
+    Coros,
+    Polar,
+    Suunto,
+}
+
+impl Vendor {
+    /// Vendor for a profile manufacturer name or number.
+    fn from_manufacturer(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(name) => match name.as_str() {
+                "wahoo_fitness" => Some(Self::Wahoo),
+                "coros" => Some(Self::Coros),
+                "polar_electro" => Some(Self::Polar),
+                "suunto" => Some(Self::Suunto),
+                _ => None,
+            },
+            Value::UInt16(32) => Some(Self::Wahoo),
+            Value::UInt16(294) => Some(Self::Coros),
+            Value::UInt16(123) => Some(Self::Polar),
+            Value::UInt16(23) => Some(Self::Suunto),
+            _ => None,
+        }
+    }
+
+    /// Transforms fixing this vendor's quirks, in the order they run.
+    pub fn fixes(self) -> Vec<Box<dyn Transform>> {
+        match self {
+            Self::Wahoo => vec![Box::new(WahooZeroPadding)],
+            Self::Coros => vec![Box::new(CorosClockOffset)],
+            Self::Suunto => vec![Box::new(SuuntoSessionTotals)],
+            Self::Polar => Vec::new(),
+        }
+    }
+}
+
+/// Vendor named by the first `FileId` message, if it is one with quirks.
+pub fn detect_vendor(records: &[FitDataRecord]) -> Option<Vendor> {
+    records
+        .iter()
+        .find(|record| matches!(record.kind(), MesgNum::FileId))?
+        .fields()
+        .iter()
+        .find(|field| field.name() == "manufacturer")
+        .and_then(|field| Vendor::from_manufacturer(field.value()))
+}
+
+/// Drop zero padding from `Record` messages: heart rates of 0 and positions
+/// at exactly 0°N 0°E.
+pub struct WahooZeroPadding;
+
+impl Transform for WahooZeroPadding {
+    fn name(&self) -> &'static str {
+        "wahoo_zero_padding"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let mut dropped = 0;
+        ctx.rewrite_fields(|_, record, field| {
+            if !matches!(record.kind(), MesgNum::Record) {
+                return FieldEdit::Keep;
+            }
+            let padded = match field.name() {
+                "heart_rate" => is_zero(field),
+                "position_lat" | "position_long" => {
+                    ["position_lat", "position_long"].iter().all(|name| {
+                        record
+                            .fields()
+                            .iter()
+                            .find(|field| field.name() == *name)
+                            .is_some_and(is_zero)
+                    })
+                }
+                _ => false,
+            };
+            if padded {
+                dropped += 1;
+                FieldEdit::Drop
+            } else {
+                FieldEdit::Keep
+            }
+        });
+        if dropped > 0 {
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::VendorQuirkFixed,
+                dropped,
+                "zero-padded Wahoo field",
+                "dropped",
+            ));
+        }
+    }
+}
+
+fn is_zero(field: &FitDataField) -> bool {
+    field_value_to_f64(field) == Some(0.0)
+}
+
+/// Shift the `timestamp` of every message written in local time back to UTC,
+/// using the session's `start_time` as the reference, so laps, events, and
+/// the session stay lined up with the records.
+pub struct CorosClockOffset;
+
+impl Transform for CorosClockOffset {
+    fn name(&self) -> &'static str {
+        "coros_clock_offset"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let Some(offset) = clock_offset(ctx.records()) else {
+            return;
+        };
+        let mut shifted = 0;
+        ctx.rewrite_fields(|_, record, field| {
+            if field.name() != "timestamp" {
+                return FieldEdit::Keep;
+            }
+            match field_value_to_f64(field).and_then(|ts| timestamp_value(ts - offset)) {
+                Some(value) => {
+                    shifted += 1;
+                    FieldEdit::Replace(value)
+                }
+                None => FieldEdit::Keep,
+            }
+        });
+        if shifted > 0 {
+            let hours = offset / 3600.0;
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::VendorQuirkFixed,
+                shifted,
+                "COROS message timestamp",
+                &format!("shifted by {hours:+} h from local time to UTC"),
+            ));
+        }
+    }
+}
+
+/// Whole quarter hours between the first record and the session start, when
+/// that is the only reason they differ.
+fn clock_offset(records: &[FitDataRecord]) -> Option<f64> {
+    let session_start = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Session))
+        .flat_map(|record| record.fields())
+        .find(|field| field.name() == "start_time")
+        .and_then(field_value_to_f64)?;
+    let first_record = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .find_map(record_timestamp)?;
+    let difference = first_record - session_start;
+    let offset = (difference / CLOCK_OFFSET_STEP_SECONDS).round() * CLOCK_OFFSET_STEP_SECONDS;
+    (offset != 0.0
+        && offset.abs() <= MAX_CLOCK_OFFSET_SECONDS
+        && (difference - offset).abs() <= CLOCK_OFFSET_TOLERANCE_SECONDS)
+        .then_some(offset)
+}
+
+/// Fill missing `Session` totals from the records: elapsed and timer time
+/// from the first and last timestamps, distance from the last `distance`.
+///
+/// Pauses can't be recovered, so a missing timer time equals elapsed time.
+pub struct SuuntoSessionTotals;
+
+impl Transform for SuuntoSessionTotals {
+    fn name(&self) -> &'static str {
+        "suunto_session_totals"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let samples = ctx
+            .records()
+            .iter()
+            .filter(|record| matches!(record.kind(), MesgNum::Record));
+        let timestamps: Vec<f64> = samples.clone().filter_map(record_timestamp).collect();
+        let elapsed = match (timestamps.first(), timestamps.last()) {
+            (Some(first), Some(last)) if last > first => Some(last - first),
+            _ => None,
+        };
+        let distance = samples
+            .flat_map(|record| record.fields())
+            .filter(|field| field.name() == "distance")
+            .filter_map(field_value_to_f64)
+            .next_back();
+        let totals = [
+            elapsed.map(|seconds| total_time("total_elapsed_time", 7, seconds)),
+            elapsed.map(|seconds| total_time("total_timer_time", 8, seconds)),
+            distance.map(total_distance),
+        ];
+
+        let mut added = 0;
+        ctx.rebuild(|mut records| {
+            for session in records
+                .iter_mut()
+                .filter(|record| matches!(record.kind(), MesgNum::Session))
+            {
+                for total in totals.iter().flatten() {
+                    let present = session
+                        .fields()
+                        .iter()
+                        .any(|field| field.name() == total.name());
+                    if !present {
+                        session.push(total.clone());
+                        added += 1;
+                    }
+                }
+            }
+            records
+        });
+        if added > 0 {
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::VendorQuirkFixed,
+                added,
+                "missing Suunto session total",
+                "filled in from the records",
+            ));
+        }
+    }
+}
+
+/// A `Session` time total, stored in milliseconds as in the FIT profile.
+fn total_time(name: &str, number: u8, seconds: f64) -> FitDataField {
+    FitDataField::with_meta(
+        name.to_string(),
+        number,
+        None,
+        Value::Float64(seconds),
+        Value::UInt32((seconds * 1000.0).round() as u32),
+        "s".to_string(),
+        BaseType::UInt32,
+        1000.0,
+        0.0,
+        TimestampKind::None,
+    )
+}
+
+/// `Session.total_distance`, stored in centimetres as in the FIT profile.
+fn total_distance(metres: f64) -> FitDataField {
+    FitDataField::with_meta(
+        "total_distance".to_string(),
+        9,
+        None,
+        Value::Float64(metres),
+        Value::UInt32((metres * 100.0).round() as u32),
+        "m".to_string(),
+        BaseType::UInt32,
+        100.0,
+        0.0,
+        TimestampKind::None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::preprocess::preprocess_fit;
+    use crate::processing::transforms::tests::{field_names, record_with};
+    use crate::processing::{ProcessingOptions, parse_fit};
+
+    fn file_id(manufacturer: &str) -> FitDataRecord {
+        record_with(
+            MesgNum::FileId,
+            &[("manufacturer", Value::String(manufacturer.to_string()))],
+        )
+    }
+
+    fn timestamp(ts: f64) -> Value {
+        timestamp_value(ts).unwrap()
+    }
+
+    /// Decoded records of a file written by `test/fixtures/vendor/generate.py`.
+    fn fixture(name: &str) -> Vec<FitDataRecord> {
+        let bytes = std::fs::read(format!("test/fixtures/vendor/{name}")).unwrap();
+        parse_fit(&bytes).unwrap()
+    }
+
+    fn field(record: &FitDataRecord, name: &str) -> Option<f64> {
+        record
+            .fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .and_then(field_value_to_f64)
+    }
+
+    #[test]
+    fn wahoo_padding_is_dropped_and_other_vendors_are_untouched() {
+        let records = |manufacturer: &str| {
+            vec![
+                file_id(manufacturer),
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("heart_rate", Value::UInt8(0)),
+                        ("position_lat", Value::SInt32(0)),
+                        ("position_long", Value::SInt32(0)),
+                        ("cadence", Value::UInt8(0)),
+                    ],
+                ),
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("heart_rate", Value::UInt8(120)),
+                        ("position_lat", Value::SInt32(0)),
+                        ("position_long", Value::SInt32(91_000)),
+                    ],
+                ),
+            ]
+        };
+
+        let wahoo = records("wahoo_fitness");
+        let processed = preprocess_fit(&wahoo, &ProcessingOptions::default()).unwrap();
+
+        assert_eq!(field_names(&processed.records[1]), vec!["cadence"]);
+        assert_eq!(processed.records[2].fields().len(), 3);
+        assert_eq!(processed.warnings[0].code, WarningCode::VendorQuirkFixed);
+        assert_eq!(processed.warnings[0].count, 3);
+
+        let garmin = records("garmin");
+        let processed = preprocess_fit(&garmin, &ProcessingOptions::default()).unwrap();
+        assert_eq!(processed.records[1].fields().len(), 4);
+        assert!(processed.warnings.is_empty());
+    }
+
+    #[test]
+    fn coros_local_time_records_are_shifted_to_utc() {
+        let start = 1_700_000_000.0;
+        let local = start + 8.0 * 3600.0 + 2.0;
+        let records = vec![
+            file_id("coros"),
+            record_with(MesgNum::Record, &[("timestamp", timestamp(local))]),
+            record_with(MesgNum::Record, &[("timestamp", timestamp(local + 1.0))]),
+            record_with(MesgNum::Lap, &[("timestamp", timestamp(local + 1.0))]),
+            record_with(
+                MesgNum::Session,
+                &[
+                    ("timestamp", timestamp(local + 1.0)),
+                    ("start_time", timestamp(start)),
+                ],
+            ),
+        ];
+
+        let processed = preprocess_fit(&records, &ProcessingOptions::default()).unwrap();
+
+        let timestamps: Vec<Option<f64>> = processed.records.iter().map(record_timestamp).collect();
+        assert_eq!(
+            timestamps[1..],
+            [
+                Some(start + 2.0),
+                Some(start + 3.0),
+                Some(start + 3.0),
+                Some(start + 3.0)
+            ]
+        );
+        assert_eq!(field(&processed.records[4], "start_time"), Some(start));
+        assert!(processed.warnings[0].message.contains("+8 h"));
+
+        // Records that start a few seconds after the session are already UTC.
+        assert_eq!(clock_offset(&records[..1]), None);
+        let aligned = vec![
+            record_with(MesgNum::Record, &[("timestamp", timestamp(start + 30.0))]),
+            record_with(MesgNum::Session, &[("start_time", timestamp(start))]),
+        ];
+        assert_eq!(clock_offset(&aligned), None);
+    }
+
+    #[test]
+    fn suunto_session_totals_are_filled_from_records() {
+        let records = vec![
+            file_id("suunto"),
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("timestamp", timestamp(1_000.0)),
+                    ("distance", Value::Float64(0.0)),
+                ],
+            ),
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("timestamp", timestamp(1_600.0)),
+                    ("distance", Value::Float64(1_850.5)),
+                ],
+            ),
+            record_with(
+                MesgNum::Session,
+                &[("total_timer_time", Value::Float64(540.0))],
+            ),
+        ];
+
+        let processed = preprocess_fit(&records, &ProcessingOptions::default()).unwrap();
+
+        let session = &processed.records[3];
+        assert_eq!(
+            field_names(session),
+            vec!["total_timer_time", "total_elapsed_time", "total_distance"]
+        );
+        assert_eq!(session.fields()[0].value(), &Value::Float64(540.0));
+        assert_eq!(session.fields()[1].value(), &Value::Float64(600.0));
+        assert_eq!(session.fields()[2].raw_value(), &Value::UInt32(185_050));
+    }
+
+    #[test]
+    fn vendor_fixes_can_be_skipped() {
+        let records = fixture("wahoo_zero_padding.fit");
+        let options = ProcessingOptions {
+            skip_vendor_fixes: true,
+            ..ProcessingOptions::default()
+        };
+
+        let processed = preprocess_fit(&records, &options).unwrap();
+
+        assert!(matches!(processed.records, std::borrow::Cow::Borrowed(_)));
+        assert!(processed.warnings.is_empty());
+    }
+
+    #[test]
+    fn wahoo_fixture_loses_its_padding() {
+        let records = fixture("wahoo_zero_padding.fit");
+        assert_eq!(detect_vendor(&records), Some(Vendor::Wahoo));
+
+        let processed = preprocess_fit(&records, &ProcessingOptions::default()).unwrap();
+
+        let samples: Vec<&FitDataRecord> = processed
+            .records
+            .iter()
+            .filter(|record| matches!(record.kind(), MesgNum::Record))
+            .collect();
+        assert_eq!(samples.len(), 61);
+        assert!(
+            samples
+                .iter()
+                .all(|record| field(record, "heart_rate") != Some(0.0))
+        );
+        assert!(
+            samples
+                .iter()
+                .all(|record| field(record, "position_lat") != Some(0.0))
+        );
+        assert_eq!(processed.warnings[0].count, 9);
+    }
+
+    #[test]
+    fn coros_fixture_lines_up_with_its_session() {
+        let records = fixture("coros_local_time.fit");
+        assert_eq!(detect_vendor(&records), Some(Vendor::Coros));
+        let session_start = records
+            .iter()
+            .find(|record| matches!(record.kind(), MesgNum::Session))
+            .and_then(|session| field(session, "start_time"))
+            .unwrap();
+
+        let processed = preprocess_fit(&records, &ProcessingOptions::default()).unwrap();
+
+        let first = |kind: MesgNum| {
+            processed
+                .records
+                .iter()
+                .find(|record| record.kind() == kind)
+                .and_then(record_timestamp)
+                .unwrap()
+        };
+        assert_eq!(first(MesgNum::Record), session_start);
+        assert_eq!(first(MesgNum::Event), session_start);
+        assert_eq!(first(MesgNum::Lap), session_start + 600.0);
+        assert_eq!(first(MesgNum::Session), session_start + 600.0);
+        assert!(processed.warnings[0].message.contains("+8 h"));
+    }
+
+    #[test]
+    fn suunto_fixture_gets_session_totals() {
+        let records = fixture("suunto_missing_totals.fit");
+        assert_eq!(detect_vendor(&records), Some(Vendor::Suunto));
+
+        let processed = preprocess_fit(&records, &ProcessingOptions::default()).unwrap();
+
+        let session = processed
+            .records
+            .iter()
+            .find(|record| matches!(record.kind(), MesgNum::Session))
+            .unwrap();
+        assert_eq!(field(session, "total_elapsed_time"), Some(600.0));
+        assert_eq!(field(session, "total_timer_time"), Some(600.0));
+        assert_eq!(field(session, "total_distance"), Some(3_000.0));
+    }
+}
@@ -0,0 +1,107 @@
+//! Heat and altitude adjustments, so efforts in different conditions can be
+//! compared as if done at sea level in mild weather.
+//!
+//! Both factors are the share of sea-level, 15 °C performance expected in the
+//! activity's average conditions; adjusted values divide by them.
+
+use crate::processing::summary::field_value_to_f64;
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use serde::Serialize;
+
+/// Temperature with no heat penalty, in °C.
+pub const HEAT_REFERENCE_CELSIUS: f64 = 15.0;
+
+/// Share of performance lost per °C above [`HEAT_REFERENCE_CELSIUS`].
+pub const HEAT_LOSS_PER_DEGREE: f64 = 0.003;
+
+/// Average conditions and the pace/power they are worth.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConditionsAdjustment {
+    pub mean_temperature_celsius: Option<f64>,
+    pub mean_altitude_meters: Option<f64>,
+    /// Share of cool-weather performance expected at the mean temperature.
+    pub heat_factor: f64,
+    /// Share of sea-level performance expected at the mean altitude.
+    pub altitude_factor: f64,
+    /// Mean speed in m/s adjusted to sea level and 15 °C.
+    pub adjusted_speed_mean: Option<f64>,
+    /// Mean power in watts adjusted to sea level and 15 °C.
+    pub adjusted_power_mean: Option<f64>,
+}
+
+/// Adjust `speed_mean` and the mean record power for the average temperature
+/// and altitude of `Record` messages. Returns `None` when the file has
+/// neither temperature nor altitude data.
+pub fn adjust_for_conditions(
+    records: &[FitDataRecord],
+    speed_mean: Option<f64>,
+) -> Option<ConditionsAdjustment> {
+    let mut temperatures = Vec::new();
+    let mut altitudes = Vec::new();
+    let mut powers = Vec::new();
+    for record in records {
+        if !matches!(record.kind(), MesgNum::Record) {
+            continue;
+        }
+        let mut altitude = None;
+        let mut enhanced_altitude = None;
+        for field in record.fields() {
+            match field.name() {
+                "temperature" => temperatures.extend(field_value_to_f64(field)),
+                "power" => powers.extend(field_value_to_f64(field)),
+                "altitude" => altitude = field_value_to_f64(field),
+                "enhanced_altitude" => enhanced_altitude = field_value_to_f64(field),
+                _ => {}
+            }
+        }
+        altitudes.extend(enhanced_altitude.or(altitude));
+    }
+
+    let mean = |values: &[f64]| {
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+    let mean_temperature_celsius = mean(&temperatures);
+    let mean_altitude_meters = mean(&altitudes);
+    if mean_temperature_celsius.is_none() && mean_altitude_meters.is_none() {
+        return None;
+    }
+
+    let heat_factor = mean_temperature_celsius.map_or(1.0, heat_factor);
+    let altitude_factor = mean_altitude_meters.map_or(1.0, altitude_factor);
+    let adjust = |value: f64| value / (heat_factor * altitude_factor);
+    Some(ConditionsAdjustment {
+        mean_temperature_celsius,
+        mean_altitude_meters,
+        heat_factor,
+        altitude_factor,
+        adjusted_speed_mean: speed_mean.map(adjust),
+        adjusted_power_mean: mean(&powers).map(adjust),
+    })
+}
+
+/// Linear loss above [`HEAT_REFERENCE_CELSIUS`], as seen in marathon results
+/// across race-day temperatures; floored at 80%.
+fn heat_factor(celsius: f64) -> f64 {
+    (1.0 - HEAT_LOSS_PER_DEGREE * (celsius - HEAT_REFERENCE_CELSIUS).max(0.0)).max(0.8)
+}
+
+/// Bassett et al. (1999) fit of VO2max at altitude for acclimatised athletes,
+/// with altitude in kilometres.
+fn altitude_factor(meters: f64) -> f64 {
+    let km = meters.max(0.0) / 1000.0;
+    (0.178 * km.powi(3) - 1.43 * km.powi(2) - 4.07 * km + 100.0) / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn factors_are_neutral_in_reference_conditions() {
+        assert_eq!(heat_factor(10.0), 1.0);
+        assert!((heat_factor(25.0) - 0.97).abs() < 1e-9);
+        assert_eq!(altitude_factor(-20.0), 1.0);
+        assert!((altitude_factor(2000.0) - 0.8756).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,123 @@
+//! The CRC-16 FIT files carry after their header and after their data.
+//!
+//! The FIT SDK computes it a nibble at a time; a 256-entry table handles a
+//! whole byte per lookup, which matters once files reach tens of megabytes.
+//!
+//! Files re-encoded by `fitparser` get their CRCs from its encoder; this
+//! module is for the files the crate writes or checks itself.
+
+use std::io::{self, Write};
+
+/// CRC-16/ARC (reflected polynomial `0xA001`) of every byte value.
+const TABLE: [u16; 256] = {
+    let mut table = [0u16; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u16;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+};
+
+/// Continue `crc` over `bytes`, for callers that see a file in pieces.
+pub fn update(crc: u16, bytes: &[u8]) -> u16 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        (crc >> 8) ^ TABLE[usize::from((crc ^ u16::from(byte)) & 0xFF)]
+    })
+}
+
+/// CRC of `bytes`, as stored in a FIT header or after the data section.
+pub fn fit_crc(bytes: &[u8]) -> u16 {
+    update(0, bytes)
+}
+
+/// Writer that keeps the CRC of everything passed through it, so a FIT file
+/// can be written to `inner` and closed with its CRC without a second pass
+/// over the bytes.
+pub struct CrcWriter<W> {
+    inner: W,
+    crc: u16,
+}
+
+impl<W: Write> CrcWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, crc: 0 }
+    }
+
+    /// CRC of the bytes written so far.
+    pub fn crc(&self) -> u16 {
+        self.crc
+    }
+
+    /// Append the CRC of everything written, little endian as FIT stores it,
+    /// and return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&self.crc.to_le_bytes())?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc = update(self.crc, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The FIT SDK's nibble-at-a-time CRC, for comparison.
+    fn sdk_crc(bytes: &[u8]) -> u16 {
+        const NIBBLES: [u16; 16] = [
+            0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+            0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+        ];
+        bytes.iter().fold(0u16, |mut crc, &byte| {
+            let byte = u16::from(byte);
+            let tmp = NIBBLES[usize::from(crc & 0xF)];
+            crc = ((crc >> 4) & 0x0FFF) ^ tmp ^ NIBBLES[usize::from(byte & 0xF)];
+            let tmp = NIBBLES[usize::from(crc & 0xF)];
+            ((crc >> 4) & 0x0FFF) ^ tmp ^ NIBBLES[usize::from((byte >> 4) & 0xF)]
+        })
+    }
+
+    #[test]
+    fn table_matches_the_sdk_algorithm() {
+        assert_eq!(fit_crc(b"123456789"), 0xBB3D);
+        let bytes: Vec<u8> = (0..4_096u32).map(|i| (i * 31 % 251) as u8).collect();
+        assert_eq!(fit_crc(&bytes), sdk_crc(&bytes));
+        let (head, tail) = bytes.split_at(1_000);
+        assert_eq!(update(fit_crc(head), tail), fit_crc(&bytes));
+    }
+
+    #[test]
+    fn written_files_end_with_their_crc() {
+        let mut writer = CrcWriter::new(Vec::new());
+        writer.write_all(&[12, 0x10, 0, 0, 2, 0, 0, 0]).unwrap();
+        writer.write_all(b".FIT").unwrap();
+        writer.write_all(&[0x40, 0]).unwrap();
+        let written_crc = writer.crc();
+        let file = writer.finish().unwrap();
+
+        assert_eq!(file.len(), 16);
+        assert_eq!(written_crc, fit_crc(&file[..14]));
+        assert_eq!(file[14..], fit_crc(&file[..14]).to_le_bytes());
+    }
+}
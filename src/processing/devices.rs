@@ -0,0 +1,118 @@
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use serde::Serialize;
+
+/// Recording device or paired sensor described by `DeviceInfo` messages.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeviceEntry {
+    pub device_index: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub device_type: Option<String>,
+    pub serial_number: Option<String>,
+    pub software_version: Option<String>,
+    pub battery_status: Option<String>,
+}
+
+impl DeviceEntry {
+    /// The device that wrote the file (as opposed to a paired sensor).
+    pub fn is_creator(&self) -> bool {
+        matches!(self.device_index.as_deref(), Some("creator" | "0"))
+    }
+
+    fn merge(&mut self, other: DeviceEntry) {
+        let DeviceEntry {
+            device_index,
+            manufacturer,
+            product,
+            device_type,
+            serial_number,
+            software_version,
+            battery_status,
+        } = other;
+        self.device_index = self.device_index.take().or(device_index);
+        self.manufacturer = self.manufacturer.take().or(manufacturer);
+        self.product = self.product.take().or(product);
+        self.device_type = self.device_type.take().or(device_type);
+        self.serial_number = self.serial_number.take().or(serial_number);
+        self.software_version = self.software_version.take().or(software_version);
+        // Later messages carry the most recent battery reading.
+        self.battery_status = battery_status.or(self.battery_status.take());
+    }
+}
+
+/// Build one inventory entry per device index (or serial number when no index is set).
+///
+/// Devices log `DeviceInfo` repeatedly during an activity, so entries for the
+/// same device are merged rather than listed once per message.
+pub fn collect_devices(records: &[FitDataRecord]) -> Vec<DeviceEntry> {
+    let mut devices: Vec<DeviceEntry> = Vec::new();
+
+    for record in records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::DeviceInfo))
+    {
+        let mut entry = DeviceEntry::default();
+        for field in record.fields() {
+            let value = field.to_string();
+            if value.trim().is_empty() {
+                continue;
+            }
+            match field.name() {
+                "device_index" => entry.device_index = Some(value),
+                "manufacturer" => entry.manufacturer = Some(value),
+                "product_name" | "garmin_product" | "product" if entry.product.is_none() => {
+                    entry.product = Some(value)
+                }
+                "device_type" | "antplus_device_type" | "local_device_type" => {
+                    entry.device_type = Some(value)
+                }
+                "serial_number" => entry.serial_number = Some(value),
+                "software_version" => entry.software_version = Some(value),
+                "battery_status" => entry.battery_status = Some(value),
+                _ => {}
+            }
+        }
+
+        let existing =
+            devices
+                .iter_mut()
+                .find(|device| match (&entry.device_index, &device.device_index) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => {
+                        entry.serial_number.is_some() && entry.serial_number == device.serial_number
+                    }
+                });
+        match existing {
+            Some(device) => device.merge(entry),
+            None => devices.push(entry),
+        }
+    }
+
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_identity_and_latest_battery() {
+        let mut device = DeviceEntry {
+            device_index: Some("1".into()),
+            manufacturer: Some("garmin".into()),
+            battery_status: Some("good".into()),
+            ..DeviceEntry::default()
+        };
+        device.merge(DeviceEntry {
+            device_index: Some("1".into()),
+            manufacturer: Some("other".into()),
+            battery_status: Some("low".into()),
+            ..DeviceEntry::default()
+        });
+
+        assert_eq!(device.manufacturer.as_deref(), Some("garmin"));
+        assert_eq!(device.battery_status.as_deref(), Some("low"));
+        assert!(!device.is_creator());
+    }
+}
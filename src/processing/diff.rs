@@ -0,0 +1,247 @@
+use crate::processing::parse_fit_with_limits;
+use crate::processing::summary::{derive_workout_data, field_value_to_f64};
+use crate::processing::types::{
+    FitProcessError, ProcessingLimits, ProcessingOptions, WorkoutSummary,
+};
+use fitparser::FitDataRecord;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Structural comparison between two FIT files.
+#[derive(Debug, Clone, Serialize)]
+pub struct FitDiff {
+    pub message_counts: Vec<MessageCountDiff>,
+    pub field_presence: Vec<FieldPresenceDiff>,
+    pub summary_deltas: Vec<SummaryDelta>,
+    pub left_coverage: TimestampCoverage,
+    pub right_coverage: TimestampCoverage,
+}
+
+/// Number of messages of one type in each file.
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageCountDiff {
+    pub message_type: String,
+    pub left: usize,
+    pub right: usize,
+}
+
+/// Field names that only appear on one side for a message type.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldPresenceDiff {
+    pub message_type: String,
+    pub only_left: Vec<String>,
+    pub only_right: Vec<String>,
+}
+
+/// A summary metric from both files.
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryDelta {
+    pub metric: &'static str,
+    pub left: Option<f64>,
+    pub right: Option<f64>,
+}
+
+impl SummaryDelta {
+    /// `right - left` when both values are present.
+    pub fn delta(&self) -> Option<f64> {
+        Some(self.right? - self.left?)
+    }
+}
+
+/// First/last timestamps and how many messages carry one.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimestampCoverage {
+    pub first: Option<f64>,
+    pub last: Option<f64>,
+    pub timestamped_messages: usize,
+}
+
+/// Decode both payloads within `limits` and compare them.
+pub fn diff_fit_bytes(
+    left: &[u8],
+    right: &[u8],
+    limits: &ProcessingLimits,
+) -> Result<FitDiff, FitProcessError> {
+    Ok(diff_records(
+        &parse_fit_with_limits(left, limits)?,
+        &parse_fit_with_limits(right, limits)?,
+    ))
+}
+
+pub fn diff_records(left: &[FitDataRecord], right: &[FitDataRecord]) -> FitDiff {
+    let left_counts = message_counts(left);
+    let right_counts = message_counts(right);
+    let message_types: BTreeSet<&String> = left_counts.keys().chain(right_counts.keys()).collect();
+
+    let message_counts = message_types
+        .iter()
+        .map(|message_type| MessageCountDiff {
+            message_type: (*message_type).clone(),
+            left: left_counts.get(*message_type).copied().unwrap_or(0),
+            right: right_counts.get(*message_type).copied().unwrap_or(0),
+        })
+        .collect();
+
+    let left_fields = field_names(left);
+    let right_fields = field_names(right);
+    let empty = BTreeSet::new();
+    let field_presence = message_types
+        .iter()
+        .filter_map(|message_type| {
+            let left_set = left_fields.get(*message_type).unwrap_or(&empty);
+            let right_set = right_fields.get(*message_type).unwrap_or(&empty);
+            let only_left: Vec<String> = left_set.difference(right_set).cloned().collect();
+            let only_right: Vec<String> = right_set.difference(left_set).cloned().collect();
+            if only_left.is_empty() && only_right.is_empty() {
+                None
+            } else {
+                Some(FieldPresenceDiff {
+                    message_type: (*message_type).clone(),
+                    only_left,
+                    only_right,
+                })
+            }
+        })
+        .collect();
+
+    let options = ProcessingOptions::default();
+    let left_summary = derive_workout_data(left, &options).summary;
+    let right_summary = derive_workout_data(right, &options).summary;
+
+    FitDiff {
+        message_counts,
+        field_presence,
+        summary_deltas: summary_deltas(&left_summary, &right_summary),
+        left_coverage: timestamp_coverage(left),
+        right_coverage: timestamp_coverage(right),
+    }
+}
+
+/// Number of data messages of each kind.
+pub(crate) fn message_counts(records: &[FitDataRecord]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for record in records {
+        *counts.entry(format!("{:?}", record.kind())).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn field_names(records: &[FitDataRecord]) -> BTreeMap<String, BTreeSet<String>> {
+    let mut fields: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for record in records {
+        let names = fields.entry(format!("{:?}", record.kind())).or_default();
+        for field in record.fields() {
+            names.insert(field.name().to_string());
+        }
+    }
+    fields
+}
+
+fn summary_deltas(left: &WorkoutSummary, right: &WorkoutSummary) -> Vec<SummaryDelta> {
+    let metrics: [(&'static str, Option<f64>, Option<f64>); 8] = [
+        (
+            "duration_seconds",
+            left.duration_seconds,
+            right.duration_seconds,
+        ),
+        (
+            "moving_time_seconds",
+            left.moving_time_seconds,
+            right.moving_time_seconds,
+        ),
+        (
+            "distance_meters",
+            left.distance_meters,
+            right.distance_meters,
+        ),
+        (
+            "elevation_gain_meters",
+            left.elevation_gain_meters,
+            right.elevation_gain_meters,
+        ),
+        ("speed_mean", left.speed_mean, right.speed_mean),
+        ("speed_max", left.speed_max, right.speed_max),
+        (
+            "heart_rate_mean",
+            left.heart_rate_mean,
+            right.heart_rate_mean,
+        ),
+        ("heart_rate_max", left.heart_rate_max, right.heart_rate_max),
+    ];
+
+    metrics
+        .into_iter()
+        .map(|(metric, left, right)| SummaryDelta {
+            metric,
+            left,
+            right,
+        })
+        .collect()
+}
+
+fn timestamp_coverage(records: &[FitDataRecord]) -> TimestampCoverage {
+    let mut coverage = TimestampCoverage::default();
+    for record in records {
+        let timestamp = record
+            .fields()
+            .iter()
+            .find(|field| field.name() == "timestamp")
+            .and_then(field_value_to_f64);
+        if let Some(ts) = timestamp {
+            coverage.timestamped_messages += 1;
+            coverage.first = Some(coverage.first.map_or(ts, |first| first.min(ts)));
+            coverage.last = Some(coverage.last.map_or(ts, |last| last.max(ts)));
+        }
+    }
+    coverage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fitparser::profile::MesgNum;
+
+    #[test]
+    fn message_counts_include_types_missing_on_one_side() {
+        let left = vec![
+            FitDataRecord::new(MesgNum::FileId),
+            FitDataRecord::new(MesgNum::Record),
+            FitDataRecord::new(MesgNum::Record),
+        ];
+        let right = vec![
+            FitDataRecord::new(MesgNum::FileId),
+            FitDataRecord::new(MesgNum::Record),
+            FitDataRecord::new(MesgNum::Lap),
+        ];
+
+        let diff = diff_records(&left, &right);
+
+        let counts: Vec<(&str, usize, usize)> = diff
+            .message_counts
+            .iter()
+            .map(|count| (count.message_type.as_str(), count.left, count.right))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![("FileId", 1, 1), ("Lap", 0, 1), ("Record", 2, 1)]
+        );
+        assert!(diff.field_presence.is_empty());
+        assert_eq!(diff.left_coverage.timestamped_messages, 0);
+    }
+
+    #[test]
+    fn summary_delta_requires_both_sides() {
+        let delta = SummaryDelta {
+            metric: "distance_meters",
+            left: Some(1000.0),
+            right: Some(1250.0),
+        };
+        assert_eq!(delta.delta(), Some(250.0));
+
+        let missing = SummaryDelta {
+            right: None,
+            ..delta
+        };
+        assert_eq!(missing.delta(), None);
+    }
+}
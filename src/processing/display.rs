@@ -1,20 +1,202 @@
-use crate::processing::types::{DisplayField, DisplayRecord};
-use fitparser::FitDataRecord;
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::types::{DisplayField, DisplayRecord, FieldValueKind};
+use chrono::{DateTime, FixedOffset};
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataField, FitDataRecord, Value};
 
-/// Convert processed records into UI-friendly display records.
-pub fn to_display_records(records: &[FitDataRecord]) -> Vec<DisplayRecord> {
+/// Convert processed records into UI-friendly display records, with
+/// timestamps shown at `utc_offset_seconds` (see [`activity_utc_offset`]).
+pub fn to_display_records(
+    records: &[FitDataRecord],
+    utc_offset_seconds: Option<i32>,
+) -> Vec<DisplayRecord> {
     records
         .iter()
         .map(|record| DisplayRecord {
             message_type: format!("{:?}", record.kind()),
+            message_number: record.kind().as_u16(),
             fields: record
                 .fields()
                 .iter()
                 .map(|field| DisplayField {
                     name: field.name().to_string(),
-                    value: field.to_string(),
+                    number: field.number(),
+                    developer_data_index: field.developer_data_index(),
+                    value: display_value(field, utc_offset_seconds),
+                    units: Some(field.units().to_string()).filter(|units| !units.is_empty()),
+                    raw: field_value_kind(field),
                 })
                 .collect(),
         })
         .collect()
 }
+
+/// The activity's offset from UTC in seconds: the first `local_timestamp`
+/// (on `Activity` messages) less the message's `timestamp`, or else the
+/// `time_zone_offset` of `DeviceSettings`.
+pub fn activity_utc_offset(records: &[FitDataRecord]) -> Option<i32> {
+    let value = |record: &FitDataRecord, name: &str| {
+        record
+            .fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .and_then(field_value_to_f64)
+    };
+    let local = records.iter().find_map(|record| {
+        let utc = value(record, "timestamp")?;
+        let local = value(record, "local_timestamp")?;
+        Some((local - utc).round() as i32)
+    });
+    local.or_else(|| {
+        records
+            .iter()
+            .filter(|record| matches!(record.kind(), MesgNum::DeviceSettings))
+            .find_map(|record| value(record, "time_zone_offset"))
+            .map(|hours| (hours * 3600.0).round() as i32)
+    })
+}
+
+/// `field` as shown to people. Timestamps are given in local time at
+/// `utc_offset_seconds` with the offset as a label, or in UTC when it is
+/// unknown; `local_timestamp` is already a wall-clock time and is labelled
+/// as such.
+pub fn display_value(field: &FitDataField, utc_offset_seconds: Option<i32>) -> String {
+    if !matches!(field.value(), Value::Timestamp(_)) {
+        return field.to_string();
+    }
+    let Some(time) = field_value_to_f64(field)
+        .filter(|seconds| seconds.is_finite())
+        .and_then(|seconds| DateTime::from_timestamp(seconds.floor() as i64, 0))
+    else {
+        return field.to_string();
+    };
+    if field.name() == "local_timestamp" {
+        return time.format("%Y-%m-%d %H:%M:%S (local)").to_string();
+    }
+    match utc_offset_seconds.and_then(FixedOffset::east_opt) {
+        Some(offset) => time
+            .with_timezone(&offset)
+            .format("%Y-%m-%d %H:%M:%S (UTC%:z)")
+            .to_string(),
+        None => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    }
+}
+
+/// Endings of the names of profile fields that hold free text rather than
+/// an enum name, e.g. `product_name` or `descriptor`.
+const TEXT_FIELD_SUFFIXES: &[&str] = &["name", "descriptor", "notes", "text", "url", "units"];
+
+/// `field`'s value by type. The decoder turns enum values the profile names
+/// into strings, so profile strings count as enum names unless the field is
+/// one of the profile's text fields; developer strings are always text.
+pub fn field_value_kind(field: &FitDataField) -> FieldValueKind {
+    match field.value() {
+        Value::Enum(value) => FieldValueKind::Enum(value.to_string()),
+        Value::String(text)
+            if field.developer_data_index().is_none()
+                && !TEXT_FIELD_SUFFIXES
+                    .iter()
+                    .any(|suffix| field.name().ends_with(suffix)) =>
+        {
+            FieldValueKind::Enum(text.clone())
+        }
+        Value::String(text) => FieldValueKind::String(text.clone()),
+        Value::Float32(value) => FieldValueKind::Float(f64::from(*value)),
+        Value::Float64(value) => FieldValueKind::Float(*value),
+        Value::Array(_) => FieldValueKind::String(field.value().to_string()),
+        // Integers, and timestamps as epoch seconds; 2^63 is exact in f64.
+        _ => match field_value_to_f64(field) {
+            Some(value) if value.fract() == 0.0 && value.abs() < 9.223_372_036_854_776e18 => {
+                FieldValueKind::Int(value as i64)
+            }
+            Some(value) => FieldValueKind::Float(value),
+            None => FieldValueKind::String(field.value().to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+
+    // 2024-05-01 08:00:00 UTC.
+    const START: f64 = 1_714_550_400.0;
+
+    #[test]
+    fn timestamps_show_in_the_activity_time_zone() {
+        let records = vec![
+            record_with(
+                MesgNum::Record,
+                &[("timestamp", timestamp_value(START).unwrap())],
+            ),
+            record_with(
+                MesgNum::Activity,
+                &[
+                    ("timestamp", timestamp_value(START).unwrap()),
+                    ("local_timestamp", timestamp_value(START + 7_200.0).unwrap()),
+                ],
+            ),
+        ];
+
+        let offset = activity_utc_offset(&records);
+        assert_eq!(offset, Some(7_200));
+
+        let display = to_display_records(&records, offset);
+        assert_eq!(
+            display[0].fields[0].value,
+            "2024-05-01 10:00:00 (UTC+02:00)"
+        );
+        assert_eq!(display[1].fields[1].value, "2024-05-01 10:00:00 (local)");
+        assert_eq!(
+            to_display_records(&records, None)[0].fields[0].value,
+            "2024-05-01 08:00:00 UTC"
+        );
+    }
+
+    #[test]
+    fn fields_carry_units_and_typed_values() {
+        let field = |name: &str, developer: Option<u8>, value: Value, units: &str| {
+            FitDataField::new(name.to_string(), 0, developer, value, units.to_string())
+        };
+        let mut record = FitDataRecord::new(MesgNum::Record);
+        record.push(field("speed", None, Value::Float64(4.2), "m/s"));
+        record.push(field("heart_rate", None, Value::UInt8(150), "bpm"));
+        record.push(field("sport", None, Value::String("running".into()), ""));
+        record.push(field(
+            "product_name",
+            None,
+            Value::String("Edge".into()),
+            "",
+        ));
+        record.push(field("gear", Some(0), Value::String("Road".into()), ""));
+
+        let display = to_display_records(&[record], None);
+        assert_eq!(display[0].message_number, 20);
+        let fields = &display[0].fields;
+        assert_eq!(fields[0].value, "4.2 m/s");
+        assert_eq!(fields[0].units.as_deref(), Some("m/s"));
+        assert_eq!(fields[0].raw, FieldValueKind::Float(4.2));
+        assert_eq!(fields[1].raw, FieldValueKind::Int(150));
+        assert_eq!(fields[2].units, None);
+        assert_eq!(fields[2].raw, FieldValueKind::Enum("running".to_string()));
+        assert_eq!(fields[3].raw, FieldValueKind::String("Edge".to_string()));
+        assert_eq!(fields[4].raw, FieldValueKind::String("Road".to_string()));
+        assert_eq!(fields[4].developer_data_index, Some(0));
+        assert_eq!(
+            serde_json::to_value(&fields[0].raw).unwrap(),
+            serde_json::json!({"kind": "float", "value": 4.2})
+        );
+    }
+
+    #[test]
+    fn device_settings_time_zone_is_the_fallback() {
+        let records = vec![record_with(
+            MesgNum::DeviceSettings,
+            &[("time_zone_offset", Value::Float64(-4.5))],
+        )];
+
+        assert_eq!(activity_utc_offset(&records), Some(-16_200));
+    }
+}
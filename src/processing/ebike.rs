@@ -0,0 +1,276 @@
+//! Battery, assist-mode, and motor data of eBike rides.
+//!
+//! Recent FIT profiles give `Record` messages `ebike_battery_level`,
+//! `ebike_assist_mode`, and `motor_power` fields; older head units and
+//! manufacturer apps write the same readings as developer fields with names
+//! such as `Battery SOC` or `Assist Mode`. Both are recognised from a fixed
+//! list of names, so the battery fields of watches and sensors are not taken
+//! for the bike's. The bike itself appears in `DeviceInfo` as a light
+//! electric vehicle (`lev`).
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::record_timestamp;
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataField, FitDataRecord};
+use serde::Serialize;
+
+/// Longest gap between records still counted as time in an assist mode.
+const MAX_SAMPLE_GAP_SECONDS: f64 = 30.0;
+
+/// What an eBike field reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EbikeField {
+    /// State of charge, in percent.
+    Battery,
+    AssistMode,
+    /// Power added by the motor, in watts.
+    MotorPower,
+    /// Anything else the bike reports, such as its remaining range.
+    Other,
+}
+
+/// `Record` field names, profile and developer, with spaces and
+/// punctuation as underscores.
+const EBIKE_FIELDS: &[(&str, EbikeField)] = &[
+    ("ebike_battery_level", EbikeField::Battery),
+    ("ebike_assist_mode", EbikeField::AssistMode),
+    ("motor_power", EbikeField::MotorPower),
+    ("ebike_travel_range", EbikeField::Other),
+    ("ebike_assist_level_percent", EbikeField::Other),
+    ("battery_soc", EbikeField::Battery),
+    ("ebike_battery", EbikeField::Battery),
+    ("assist_mode", EbikeField::AssistMode),
+    ("assist_level", EbikeField::AssistMode),
+    ("motor_assist", EbikeField::Other),
+];
+
+/// Classify a `Record` field by name, ignoring case and punctuation; `None`
+/// for fields unrelated to eBikes.
+pub fn ebike_field(name: &str) -> Option<EbikeField> {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    EBIKE_FIELDS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|&(_, field)| field)
+}
+
+/// Device indexes of `DeviceInfo` messages describing the eBike.
+pub fn ebike_device_indexes(records: &[FitDataRecord]) -> Vec<String> {
+    records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::DeviceInfo))
+        .filter(|record| {
+            record.fields().iter().any(|field| {
+                matches!(field.name(), "device_type" | "antplus_device_type")
+                    && field.to_string() == "lev"
+            })
+        })
+        .filter_map(device_index)
+        .collect()
+}
+
+/// The `device_index` of a `DeviceInfo` message.
+pub fn device_index(record: &FitDataRecord) -> Option<String> {
+    record
+        .fields()
+        .iter()
+        .find(|field| field.name() == "device_index")
+        .map(|field| field.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EbikeSummary {
+    pub battery_start_percent: Option<f64>,
+    pub battery_end_percent: Option<f64>,
+    /// Charge used over the ride; `None` without battery readings.
+    pub battery_used_percent: Option<f64>,
+    /// Distance ridden per percent of charge used.
+    pub meters_per_battery_percent: Option<f64>,
+    pub motor_power_mean: Option<f64>,
+    pub motor_power_max: Option<f64>,
+    /// Energy the motor added, in kilojoules.
+    pub motor_energy_kj: Option<f64>,
+    /// Time in each assist mode, in order of first use.
+    pub assist_modes: Vec<AssistModeTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AssistModeTime {
+    pub mode: String,
+    pub seconds: f64,
+}
+
+#[derive(Default)]
+struct Sample {
+    timestamp: Option<f64>,
+    distance: Option<f64>,
+    battery: Option<f64>,
+    assist_mode: Option<String>,
+    motor_power: Option<f64>,
+}
+
+/// Summarise the eBike fields of `Record` messages; `None` when no record
+/// carries one.
+pub fn summarize_ebike(records: &[FitDataRecord]) -> Option<EbikeSummary> {
+    let samples: Vec<Sample> = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .map(|record| {
+            let mut sample = Sample {
+                timestamp: record_timestamp(record),
+                ..Sample::default()
+            };
+            for field in record.fields() {
+                match (field.name(), ebike_field(field.name())) {
+                    ("distance", _) => sample.distance = field_value_to_f64(field),
+                    (_, Some(EbikeField::Battery)) => sample.battery = field_value_to_f64(field),
+                    (_, Some(EbikeField::AssistMode)) => sample.assist_mode = assist_mode(field),
+                    (_, Some(EbikeField::MotorPower)) => {
+                        sample.motor_power = field_value_to_f64(field)
+                    }
+                    _ => {}
+                }
+            }
+            sample
+        })
+        .filter(|sample| {
+            sample.battery.is_some() || sample.assist_mode.is_some() || sample.motor_power.is_some()
+        })
+        .collect();
+    if samples.is_empty() {
+        return None;
+    }
+
+    let battery_start_percent = samples.iter().find_map(|sample| sample.battery);
+    let battery_end_percent = samples.iter().rev().find_map(|sample| sample.battery);
+    let battery_used_percent = battery_start_percent
+        .zip(battery_end_percent)
+        .map(|(start, end)| (start - end).max(0.0));
+    let first_distance = samples.iter().find_map(|sample| sample.distance);
+    let last_distance = samples.iter().rev().find_map(|sample| sample.distance);
+    let meters_per_battery_percent = first_distance
+        .zip(last_distance)
+        .zip(battery_used_percent.filter(|&used| used > 0.0))
+        .map(|((first, last), used)| (last - first).max(0.0) / used);
+
+    let powers: Vec<f64> = samples
+        .iter()
+        .filter_map(|sample| sample.motor_power)
+        .collect();
+    let mut motor_joules = None;
+    let mut assist_modes: Vec<AssistModeTime> = Vec::new();
+    for pair in samples.windows(2) {
+        let Some(seconds) = pair[0]
+            .timestamp
+            .zip(pair[1].timestamp)
+            .map(|(start, end)| end - start)
+            .filter(|&seconds| seconds > 0.0 && seconds <= MAX_SAMPLE_GAP_SECONDS)
+        else {
+            continue;
+        };
+        if let Some(power) = pair[0].motor_power {
+            *motor_joules.get_or_insert(0.0) += power * seconds;
+        }
+        if let Some(mode) = &pair[0].assist_mode {
+            match assist_modes.iter_mut().find(|time| &time.mode == mode) {
+                Some(time) => time.seconds += seconds,
+                None => assist_modes.push(AssistModeTime {
+                    mode: mode.clone(),
+                    seconds,
+                }),
+            }
+        }
+    }
+
+    Some(EbikeSummary {
+        battery_start_percent,
+        battery_end_percent,
+        battery_used_percent,
+        meters_per_battery_percent,
+        motor_power_mean: (!powers.is_empty())
+            .then(|| powers.iter().sum::<f64>() / powers.len() as f64),
+        motor_power_max: powers.iter().copied().reduce(f64::max),
+        motor_energy_kj: motor_joules.map(|joules| joules / 1_000.0),
+        assist_modes,
+    })
+}
+
+/// The assist mode as the file names it, or its number.
+fn assist_mode(field: &FitDataField) -> Option<String> {
+    let mode = field.to_string();
+    (!mode.trim().is_empty()).then(|| mode.trim().to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+    use fitparser::Value;
+
+    pub(crate) fn ride() -> Vec<FitDataRecord> {
+        (0..=60)
+            .map(|second| {
+                let mode = if second < 40 { "eco" } else { "turbo" };
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        (
+                            "timestamp",
+                            timestamp_value(1_000.0 + f64::from(second)).unwrap(),
+                        ),
+                        ("distance", Value::Float64(f64::from(second) * 5.0)),
+                        ("power", Value::UInt16(150)),
+                        (
+                            "ebike_battery_level",
+                            Value::UInt8(80 - (second / 20) as u8),
+                        ),
+                        ("ebike_assist_mode", Value::String(mode.to_string())),
+                        (
+                            "motor_power",
+                            Value::UInt16(if second < 40 { 100 } else { 250 }),
+                        ),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn battery_and_assist_modes_are_summarised() {
+        let summary = summarize_ebike(&ride()).unwrap();
+
+        assert_eq!(summary.battery_start_percent, Some(80.0));
+        assert_eq!(summary.battery_used_percent, Some(3.0));
+        assert_eq!(summary.meters_per_battery_percent, Some(100.0));
+        assert_eq!(summary.motor_power_max, Some(250.0));
+        assert_eq!(summary.motor_energy_kj, Some(9.0));
+        let modes: Vec<(&str, f64)> = summary
+            .assist_modes
+            .iter()
+            .map(|time| (time.mode.as_str(), time.seconds))
+            .collect();
+        assert_eq!(modes, vec![("eco", 40.0), ("turbo", 20.0)]);
+    }
+
+    #[test]
+    fn developer_field_names_are_recognised() {
+        assert_eq!(ebike_field("Battery SOC"), Some(EbikeField::Battery));
+        assert_eq!(ebike_field("Assist Mode"), Some(EbikeField::AssistMode));
+        assert_eq!(ebike_field("motor_power"), Some(EbikeField::MotorPower));
+        assert_eq!(ebike_field("ebike_travel_range"), Some(EbikeField::Other));
+        assert_eq!(ebike_field("power"), None);
+        assert_eq!(ebike_field("battery_voltage"), None);
+        assert_eq!(ebike_field("Motor Temperature"), None);
+        assert_eq!(summarize_ebike(&[]), None);
+    }
+}
@@ -0,0 +1,451 @@
+//! User-defined derived fields such as `watts_per_kg = power / 72`.
+//!
+//! Definitions are written one per line (or separated by `;`) as
+//! `name = expression`. Expressions combine numbers and field names of
+//! `Record` messages with `+`, `-`, `*`, `/`, and parentheses, and may refer
+//! to fields defined earlier in the list. A record missing any referenced
+//! field, or dividing by zero, has no value for that derived field.
+
+use crate::processing::preprocess::preprocess_fit;
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::record_timestamp;
+use crate::processing::types::{FitProcessError, ProcessingOptions};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Most derived fields one upload may define.
+pub const MAX_DERIVED_FIELDS: usize = 16;
+
+/// Longest expression accepted, in bytes.
+pub const MAX_EXPRESSION_LENGTH: usize = 256;
+
+/// Deepest nesting of parentheses and negations accepted, so parsing and
+/// evaluating an expression cannot exhaust the stack.
+pub const MAX_EXPRESSION_DEPTH: usize = 32;
+
+/// A named expression evaluated for every `Record` message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivedField {
+    pub name: String,
+    /// The expression as written, for display.
+    pub expression: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Field(String),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// A definition that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid derived field `{definition}`: {message}")]
+pub struct ExpressionError {
+    pub definition: String,
+    pub message: String,
+}
+
+/// Parse `name = expression` definitions separated by newlines or `;`.
+/// Blank definitions are skipped.
+pub fn parse_definitions(text: &str) -> Result<Vec<DerivedField>, ExpressionError> {
+    let mut fields: Vec<DerivedField> = Vec::new();
+    for definition in text.split(['\n', ';']).map(str::trim) {
+        if definition.is_empty() {
+            continue;
+        }
+        let error = |message: String| ExpressionError {
+            definition: definition.to_string(),
+            message,
+        };
+        let Some((name, expression)) = definition.split_once('=') else {
+            return Err(error("expected `name = expression`".to_string()));
+        };
+        let (name, expression) = (name.trim(), expression.trim());
+        if expression.len() > MAX_EXPRESSION_LENGTH {
+            return Err(error(format!(
+                "expressions are limited to {MAX_EXPRESSION_LENGTH} characters"
+            )));
+        }
+        if !is_identifier(name) {
+            return Err(error(format!("`{name}` is not a valid field name")));
+        }
+        if fields.iter().any(|field| field.name == name) {
+            return Err(error(format!("`{name}` is defined more than once")));
+        }
+        if fields.len() == MAX_DERIVED_FIELDS {
+            return Err(error(format!(
+                "at most {MAX_DERIVED_FIELDS} derived fields are supported"
+            )));
+        }
+        let expr = Parser::new(expression)
+            .map_err(error)?
+            .parse()
+            .map_err(error)?;
+        fields.push(DerivedField {
+            name: name.to_string(),
+            expression: expression.to_string(),
+            expr,
+        });
+    }
+    Ok(fields)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    Open,
+    Close,
+}
+
+/// Recursive-descent parser over a pre-tokenized expression.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Operands currently being parsed, one per open parenthesis or `-`.
+    depth: usize,
+}
+
+impl Parser {
+    fn new(expression: &str) -> Result<Self, String> {
+        let mut tokens = Vec::new();
+        let mut chars = expression.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c.is_ascii_digit() || c == '.' {
+                let mut end = start;
+                while let Some(&(idx, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    end = idx + c.len_utf8();
+                    chars.next();
+                }
+                let literal = &expression[start..end];
+                let number = literal
+                    .parse()
+                    .map_err(|_| format!("`{literal}` is not a number"))?;
+                tokens.push(Token::Number(number));
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let mut end = start;
+                while let Some(&(idx, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    end = idx + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Ident(expression[start..end].to_string()));
+            } else {
+                tokens.push(match c {
+                    '+' | '-' | '*' | '/' => Token::Op(c),
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => return Err(format!("unexpected `{c}`")),
+                });
+                chars.next();
+            }
+        }
+        Ok(Self {
+            tokens,
+            pos: 0,
+            depth: 0,
+        })
+    }
+
+    fn parse(mut self) -> Result<Expr, String> {
+        if self.tokens.is_empty() {
+            return Err("missing expression".to_string());
+        }
+        let expr = self.sum()?;
+        match self.tokens.get(self.pos) {
+            None => Ok(expr),
+            Some(Token::Close) => Err("unmatched `)`".to_string()),
+            Some(_) => Err("expected an operator".to_string()),
+        }
+    }
+
+    fn next_op(&mut self, ops: &[char]) -> Option<Op> {
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Op(c)) if ops.contains(c) => match c {
+                '+' => Op::Add,
+                '-' => Op::Sub,
+                '*' => Op::Mul,
+                _ => Op::Div,
+            },
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(op)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.next_op(&['+', '-']) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(op) = self.next_op(&['*', '/']) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.depth == MAX_EXPRESSION_DEPTH {
+            return Err(format!("nested more than {MAX_EXPRESSION_DEPTH} deep"));
+        }
+        self.depth += 1;
+        let expr = self.operand();
+        self.depth -= 1;
+        expr
+    }
+
+    fn operand(&mut self) -> Result<Expr, String> {
+        if self.next_op(&['-']).is_some() {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Ident(name)) => Ok(Expr::Field(name)),
+            Some(Token::Open) => {
+                let expr = self.sum()?;
+                if self.tokens.get(self.pos) != Some(&Token::Close) {
+                    return Err("missing `)`".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(_) => Err("expected a number, field name, or `(`".to_string()),
+            None => Err("expression ends early".to_string()),
+        }
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, lookup: &impl Fn(&str) -> Option<f64>) -> Option<f64> {
+        let value = match self {
+            Expr::Number(number) => *number,
+            Expr::Field(name) => lookup(name)?,
+            Expr::Neg(inner) => -inner.evaluate(lookup)?,
+            Expr::Binary(left, op, right) => {
+                let (left, right) = (left.evaluate(lookup)?, right.evaluate(lookup)?);
+                match op {
+                    Op::Add => left + right,
+                    Op::Sub => left - right,
+                    Op::Mul => left * right,
+                    Op::Div => left / right,
+                }
+            }
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+/// Values of `fields` for one record, in definition order. Earlier derived
+/// fields take precedence over record fields of the same name.
+pub fn evaluate_record(fields: &[DerivedField], record: &FitDataRecord) -> Vec<Option<f64>> {
+    let mut values: Vec<Option<f64>> = Vec::with_capacity(fields.len());
+    for field in fields {
+        let lookup = |name: &str| match fields[..values.len()]
+            .iter()
+            .position(|earlier| earlier.name == name)
+        {
+            Some(idx) => values[idx],
+            None => record
+                .fields()
+                .iter()
+                .find(|candidate| candidate.name() == name)
+                .and_then(field_value_to_f64),
+        };
+        let value = field.expr.evaluate(&lookup);
+        values.push(value);
+    }
+    values
+}
+
+/// Derived values of every `Record` message.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DerivedFieldTable {
+    pub names: Vec<String>,
+    pub rows: Vec<DerivedFieldRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DerivedFieldRow {
+    /// Seconds since the Unix epoch.
+    pub timestamp: Option<f64>,
+    /// One value per entry of [`DerivedFieldTable::names`].
+    pub values: Vec<Option<f64>>,
+}
+
+/// Range and mean of one derived field over an activity.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DerivedFieldSummary {
+    pub name: String,
+    pub expression: String,
+    /// Records with a value for this field.
+    pub samples: usize,
+    pub min: Option<f64>,
+    pub mean: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl DerivedFieldTable {
+    /// Evaluate `fields` for each `Record` message of `records`.
+    pub fn evaluate(fields: &[DerivedField], records: &[FitDataRecord]) -> Self {
+        Self {
+            names: fields.iter().map(|field| field.name.clone()).collect(),
+            rows: records
+                .iter()
+                .filter(|record| matches!(record.kind(), MesgNum::Record))
+                .map(|record| DerivedFieldRow {
+                    timestamp: record_timestamp(record),
+                    values: evaluate_record(fields, record),
+                })
+                .collect(),
+        }
+    }
+
+    /// One row per record: the timestamp in seconds since the Unix epoch,
+    /// then each derived field; missing values are left empty.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("timestamp");
+        for name in &self.names {
+            let _ = write!(csv, ",{name}");
+        }
+        csv.push('\n');
+        for row in &self.rows {
+            if let Some(timestamp) = row.timestamp {
+                let _ = write!(csv, "{timestamp:.0}");
+            }
+            for value in &row.values {
+                csv.push(',');
+                if let Some(value) = value {
+                    let _ = write!(csv, "{value}");
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn summaries(&self, fields: &[DerivedField]) -> Vec<DerivedFieldSummary> {
+        fields
+            .iter()
+            .enumerate()
+            .map(|(idx, field)| {
+                let values: Vec<f64> = self.rows.iter().filter_map(|row| row.values[idx]).collect();
+                DerivedFieldSummary {
+                    name: field.name.clone(),
+                    expression: field.expression.clone(),
+                    samples: values.len(),
+                    min: values.iter().copied().reduce(f64::min),
+                    mean: (!values.is_empty())
+                        .then(|| values.iter().sum::<f64>() / values.len() as f64),
+                    max: values.iter().copied().reduce(f64::max),
+                }
+            })
+            .collect()
+    }
+}
+
+/// [`DerivedFieldTable`] of decoded records after preprocessing them with
+/// `options`; `None` when the options define no derived fields.
+pub fn processed_derived_fields(
+    records: &[FitDataRecord],
+    options: &ProcessingOptions,
+) -> Result<Option<DerivedFieldTable>, FitProcessError> {
+    let fields = options.derived_field_definitions().unwrap_or_default();
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    let processed = preprocess_fit(records, options)?.records;
+    Ok(Some(DerivedFieldTable::evaluate(&fields, &processed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+
+    #[test]
+    fn evaluates_with_precedence_and_earlier_fields() {
+        let fields = parse_definitions(
+            "watts_per_kg = power / 72\nefficiency = speed / heart_rate; boosted = -(watts_per_kg + 1) * 2",
+        )
+        .expect("valid definitions");
+        let record = record_with(
+            MesgNum::Record,
+            &[
+                ("power", Value::UInt16(288)),
+                ("heart_rate", Value::UInt8(0)),
+                ("speed", Value::Float64(5.0)),
+            ],
+        );
+
+        // A zero heart rate leaves efficiency without a value.
+        assert_eq!(
+            evaluate_record(&fields, &record),
+            vec![Some(4.0), None, Some(-10.0)]
+        );
+        let table = DerivedFieldTable::evaluate(&fields, &[record]);
+        assert_eq!(
+            table.to_csv(),
+            "timestamp,watts_per_kg,efficiency,boosted\n,4,,-10\n"
+        );
+        assert_eq!(table.summaries(&fields)[0].mean, Some(4.0));
+    }
+
+    #[test]
+    fn rejects_malformed_definitions() {
+        for definition in [
+            "power / 72",
+            "2x = power",
+            "a = power *",
+            "a = (power",
+            "a = power)",
+            "a = power % 2",
+            "a = 1; a = 2",
+            &format!("a = {}power{}", "(".repeat(40), ")".repeat(40)),
+            &format!("a = {}1", "-".repeat(40)),
+            &format!("a = {}", vec!["power"; 60].join(" + ")),
+        ] {
+            assert!(
+                parse_definitions(definition).is_err(),
+                "{definition} should not parse"
+            );
+        }
+        assert_eq!(parse_definitions(" ;\n").unwrap(), Vec::new());
+    }
+}
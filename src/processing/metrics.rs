@@ -0,0 +1,309 @@
+//! Models over the per-second power and distance series of an activity.
+
+use crate::processing::preprocess::preprocess_fit;
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::threshold::{estimate_thresholds, per_second};
+use crate::processing::transforms::record_timestamp;
+use crate::processing::types::{FitProcessError, ProcessingOptions};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// W' used when the rider has not entered their own, in joules.
+pub const DEFAULT_W_PRIME_JOULES: f64 = 20_000.0;
+
+/// Durations the mean-max curves are sampled at, in seconds.
+pub const CURVE_DURATIONS_SECONDS: &[usize] =
+    &[1, 5, 10, 30, 60, 120, 300, 600, 1200, 1800, 3600, 7200];
+
+/// Best average value sustained for one duration.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CurvePoint {
+    pub duration_seconds: usize,
+    pub value: f64,
+}
+
+/// Best averages at each of [`CURVE_DURATIONS_SECONDS`] the activity is long
+/// enough for; the basis of critical power and critical speed curves.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MeanMaxCurve {
+    /// Mean-max power in watts.
+    pub power: Vec<CurvePoint>,
+    /// Mean-max speed in m/s.
+    pub speed: Vec<CurvePoint>,
+}
+
+impl MeanMaxCurve {
+    pub fn is_empty(&self) -> bool {
+        self.power.is_empty() && self.speed.is_empty()
+    }
+
+    /// Best value at each duration across `curves`, e.g. every stored activity.
+    pub fn best_of<'a>(curves: impl IntoIterator<Item = &'a MeanMaxCurve>) -> Self {
+        let mut best = Self::default();
+        for curve in curves {
+            merge_best(&mut best.power, &curve.power);
+            merge_best(&mut best.speed, &curve.speed);
+        }
+        best
+    }
+}
+
+fn merge_best(best: &mut Vec<CurvePoint>, points: &[CurvePoint]) {
+    for point in points {
+        match best
+            .iter_mut()
+            .find(|existing| existing.duration_seconds == point.duration_seconds)
+        {
+            Some(existing) => existing.value = existing.value.max(point.value),
+            None => best.push(*point),
+        }
+    }
+    best.sort_by_key(|point| point.duration_seconds);
+}
+
+/// Mean-max power and speed curves of one activity; `None` when it has
+/// neither power nor distance.
+pub fn mean_max_curve(records: &[FitDataRecord]) -> Option<MeanMaxCurve> {
+    let curve = MeanMaxCurve {
+        power: power_curve(&record_series(records, "power")),
+        speed: speed_curve(&record_series(records, "distance")),
+    };
+    (!curve.is_empty()).then_some(curve)
+}
+
+fn power_curve(samples: &[(f64, f64)]) -> Vec<CurvePoint> {
+    let Some(&(origin, _)) = samples.first() else {
+        return Vec::new();
+    };
+    // Time without power readings is time without pedalling.
+    let mut totals = vec![0.0];
+    for watts in per_second(samples, origin, Some(0.0)) {
+        totals.push(totals[totals.len() - 1] + watts.unwrap_or(0.0));
+    }
+    let seconds = totals.len() - 1;
+
+    CURVE_DURATIONS_SECONDS
+        .iter()
+        .filter(|&&duration| duration <= seconds)
+        .map(|&duration| {
+            let best = (duration..=seconds)
+                .map(|end| totals[end] - totals[end - duration])
+                .fold(f64::MIN, f64::max);
+            CurvePoint {
+                duration_seconds: duration,
+                value: best / duration as f64,
+            }
+        })
+        .collect()
+}
+
+fn speed_curve(samples: &[(f64, f64)]) -> Vec<CurvePoint> {
+    let Some(&(origin, _)) = samples.first() else {
+        return Vec::new();
+    };
+    let distance = per_second(samples, origin, None);
+
+    CURVE_DURATIONS_SECONDS
+        .iter()
+        .filter(|&&duration| duration < distance.len())
+        .filter_map(|&duration| {
+            let best = distance
+                .iter()
+                .zip(&distance[duration..])
+                .filter_map(|(start, end)| Some((*end)? - (*start)?))
+                .reduce(f64::max)?;
+            Some(CurvePoint {
+                duration_seconds: duration,
+                value: best.max(0.0) / duration as f64,
+            })
+        })
+        .collect()
+}
+
+/// `(timestamp, value)` pairs of `Record` messages carrying both the
+/// timestamp and `field_name`.
+fn record_series(records: &[FitDataRecord], field_name: &str) -> Vec<(f64, f64)> {
+    records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .filter_map(|record| {
+            let value = record
+                .fields()
+                .iter()
+                .find(|field| field.name() == field_name)
+                .and_then(field_value_to_f64)?;
+            Some((record_timestamp(record)?, value))
+        })
+        .collect()
+}
+
+/// Lowest point of the W' balance over an activity.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WPrimeBalance {
+    pub critical_power_watts: f64,
+    pub w_prime_joules: f64,
+    /// Lowest balance reached, in joules; negative when the effort went
+    /// beyond what the model allows for.
+    pub min_balance_joules: f64,
+    /// Seconds from the first power sample to the lowest balance.
+    pub min_balance_at_seconds: usize,
+}
+
+/// Per-second power and W' balance, starting at the first power sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WPrimeBalanceSeries {
+    pub summary: WPrimeBalance,
+    pub power_watts: Vec<f64>,
+    pub balance_joules: Vec<f64>,
+}
+
+impl WPrimeBalanceSeries {
+    /// One row per second: elapsed seconds, power, and balance.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("elapsed_seconds,power_watts,w_prime_balance_joules\n");
+        for (second, (power, balance)) in self
+            .power_watts
+            .iter()
+            .zip(&self.balance_joules)
+            .enumerate()
+        {
+            let _ = writeln!(csv, "{second},{power:.0},{balance:.0}");
+        }
+        csv
+    }
+}
+
+/// Model W' balance with Skiba's differential form: above critical power the
+/// balance drops by the excess each second, below it the balance recovers
+/// exponentially towards W' at a rate set by how far power is under CP.
+///
+/// Returns `None` without power data or when `critical_power_watts` is not a
+/// positive wattage.
+pub fn w_prime_balance(
+    records: &[FitDataRecord],
+    critical_power_watts: f64,
+    w_prime_joules: f64,
+) -> Option<WPrimeBalanceSeries> {
+    if !(critical_power_watts > 0.0 && w_prime_joules > 0.0) {
+        return None;
+    }
+    let power = record_series(records, "power");
+    let &(origin, _) = power.first()?;
+    // Time without power readings is time without pedalling.
+    let power_watts: Vec<f64> = per_second(&power, origin, Some(0.0))
+        .into_iter()
+        .map(|watts| watts.unwrap_or(0.0))
+        .collect();
+
+    let mut balance = w_prime_joules;
+    let balance_joules: Vec<f64> = power_watts
+        .iter()
+        .map(|&watts| {
+            if watts > critical_power_watts {
+                balance -= watts - critical_power_watts;
+            } else {
+                let recovery = 1.0 - (-(critical_power_watts - watts) / w_prime_joules).exp();
+                balance += (w_prime_joules - balance) * recovery;
+            }
+            balance
+        })
+        .collect();
+
+    let (min_balance_at_seconds, &min_balance_joules) = balance_joules
+        .iter()
+        .enumerate()
+        .min_by(|(_, first), (_, second)| first.total_cmp(second))?;
+    Some(WPrimeBalanceSeries {
+        summary: WPrimeBalance {
+            critical_power_watts,
+            w_prime_joules,
+            min_balance_joules,
+            min_balance_at_seconds,
+        },
+        power_watts,
+        balance_joules,
+    })
+}
+
+/// [`w_prime_balance`] of decoded records after preprocessing them with
+/// `options`, matching the summary shown for the same upload.
+pub fn processed_w_prime_balance(
+    records: &[FitDataRecord],
+    options: &ProcessingOptions,
+) -> Result<Option<WPrimeBalanceSeries>, FitProcessError> {
+    let processed = preprocess_fit(records, options)?.records;
+    let estimated_ftp = estimate_thresholds(&processed).and_then(|estimate| estimate.ftp_watts);
+    Ok(options
+        .w_prime_model(estimated_ftp)
+        .and_then(|(critical_power, w_prime)| w_prime_balance(&processed, critical_power, w_prime)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+    use fitparser::Value;
+
+    fn ride(watts: &[u16]) -> Vec<FitDataRecord> {
+        watts
+            .iter()
+            .enumerate()
+            .map(|(second, &watts)| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        (
+                            "timestamp",
+                            timestamp_value(1_700_000_000.0 + second as f64).unwrap(),
+                        ),
+                        ("power", Value::UInt16(watts)),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn balance_drains_above_cp_and_recovers_below() {
+        let mut watts = vec![400; 60];
+        watts.extend(vec![100; 600]);
+
+        let series = w_prime_balance(&ride(&watts), 250.0, 20_000.0).expect("power present");
+
+        // A minute at 150 W over CP costs 9 kJ.
+        assert_eq!(series.summary.min_balance_joules, 11_000.0);
+        assert_eq!(series.summary.min_balance_at_seconds, 59);
+        let recovered = *series.balance_joules.last().unwrap();
+        assert!(recovered > 19_000.0 && recovered < 20_000.0);
+        assert!(
+            series
+                .to_csv()
+                .starts_with("elapsed_seconds,power_watts,w_prime_balance_joules\n0,400,19850\n")
+        );
+    }
+
+    #[test]
+    fn curves_keep_the_best_average_per_duration() {
+        let mut watts = vec![200; 30];
+        watts[10] = 900;
+        let curve = mean_max_curve(&ride(&watts)).expect("power present");
+
+        assert_eq!(
+            curve.power[0],
+            CurvePoint {
+                duration_seconds: 1,
+                value: 900.0
+            }
+        );
+        assert_eq!(curve.power[1].value, (900.0 + 4.0 * 200.0) / 5.0);
+        assert_eq!(curve.power.len(), 4);
+
+        let steady = mean_max_curve(&ride(&[300; 30])).unwrap();
+        let best = MeanMaxCurve::best_of([&curve, &steady]);
+        assert_eq!(best.power[0].value, 900.0);
+        assert_eq!(best.power[2].value, 300.0);
+    }
+}
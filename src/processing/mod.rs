@@ -1,21 +1,66 @@
+pub mod aggregate;
+pub mod annotations;
+pub mod anonymize;
+pub mod build;
+pub mod compat;
+pub mod conditions;
+pub mod crc;
+pub mod devices;
+pub mod diff;
 pub mod display;
+pub mod ebike;
+pub mod expressions;
+pub mod metrics;
+pub mod plugins;
 pub mod preprocess;
+pub mod preview;
+pub mod profile;
+pub mod progress;
+pub mod query;
+pub mod report;
+pub mod sample;
+pub mod series;
+pub mod ski;
+pub mod spans;
+pub mod spikes;
+pub mod strength;
+pub mod structure;
 pub mod summary;
+pub mod terrain;
+pub mod threshold;
+pub mod track;
+pub mod trainer;
+pub mod transforms;
 pub mod types;
+pub mod wind;
 
+use annotations::detect_annotations;
+use devices::collect_devices;
 use display::to_display_records;
-use fitparser::{from_bytes, encode_records};
-use preprocess::preprocess_fit;
+use fitparser::de::{FitObject, FitStreamProcessor};
+use fitparser::{FitDataRecord, encode_records};
+use preprocess::{Preprocessed, preprocess_fit_observed};
+use progress::{
+    NoObserver, PROGRESS_CHUNK_RECORDS, ProcessingObserver, Progress, Stage, check_cancelled,
+};
+use series::chart_series;
+use spikes::detect_speed_spikes;
+use structure::FileStructure;
 use summary::derive_workout_data;
+use track::gps_track;
 
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
 pub use types::{
-    DisplayField, DisplayRecord, FitProcessError, ProcessedFit, ProcessingOptions, WorkoutSummary,
+    CyclingDynamics, DisplayField, DisplayRecord, ExceededLimit, FieldValueKind, FitProcessError,
+    HashableF64, ParsedFit, ProcessedFit, ProcessingLimits, ProcessingOptions, ProcessingWarning,
+    RecordEdit, SmoothingResiduals, StageTimings, WarningCode, Weather, WorkoutSummary,
 };
 
 /// Decode a FIT payload, preprocess it once, and feed downstream derivation.
 ///
 /// The function performs four stages:
-/// 1. [`from_bytes`] validates FIT framing and decodes `fitparser` records.
+/// 1. [`parse_fit`] validates FIT framing and decodes `fitparser` records.
 /// 2. [`preprocess::preprocess_fit`] removes or overrides values according to
 ///    [`ProcessingOptions`].
 /// 3. [`encode_records`] re-encodes the preprocessed records back into FIT bytes.
@@ -27,26 +72,285 @@ pub fn process_fit_bytes(
     bytes: &[u8],
     options: &ProcessingOptions,
 ) -> Result<ProcessedFit, FitProcessError> {
-    let parsed = from_bytes(bytes).map_err(|err| FitProcessError::ParseError(err.to_string()))?;
-    let processed_records = preprocess_fit(&parsed, options)?;
+    process_fit_bytes_observed(bytes, options, &NoObserver)
+}
 
-    let processed_bytes = encode_records(&processed_records)
-        .map_err(|err| FitProcessError::ParseError(err.to_string()))?;
-    let derived = derive_workout_data(&processed_records);
+/// [`process_fit_bytes`] that reports each stage's progress to `observer` and
+/// stops with [`FitProcessError::Cancelled`] once it is cancelled.
+pub fn process_fit_bytes_observed(
+    bytes: &[u8],
+    options: &ProcessingOptions,
+    observer: &dyn ProcessingObserver,
+) -> Result<ProcessedFit, FitProcessError> {
+    let started = Instant::now();
+    let parsed = parse_fit_with_limits_observed(bytes, &options.limits, observer)?;
+    let parse = started.elapsed();
+    let mut processed = process_parsed_fit_with_source(&parsed, bytes, options, observer)?;
+    processed.timings.parse = parse;
+    processed.structure.measure(bytes);
+    Ok(processed)
+}
 
-    let filtered_records = to_display_records(&processed_records);
+/// Decode FIT bytes into `fitparser` records without any preprocessing.
+///
+/// This is the only place the payload is decoded; every later stage works on
+/// the returned records, so callers that need them again should keep them
+/// rather than re-parse.
+pub fn parse_fit(bytes: &[u8]) -> Result<Vec<FitDataRecord>, FitProcessError> {
+    spans::check_header(bytes, 0)?;
+    decode(bytes, None, &NoObserver, 0)
+}
+
+/// [`parse_fit`] that enforces [`ProcessingLimits`].
+///
+/// Data messages are counted with a cheap framing scan before decoding, so
+/// oversized files are rejected without building their records. The decode
+/// time is checked while decoding, every [`PROGRESS_CHUNK_RECORDS`] messages.
+pub fn parse_fit_with_limits(
+    bytes: &[u8],
+    limits: &ProcessingLimits,
+) -> Result<Vec<FitDataRecord>, FitProcessError> {
+    parse_fit_with_limits_observed(bytes, limits, &NoObserver)
+}
+
+/// [`parse_fit_with_limits`] that reports decoding progress to `observer` and
+/// stops once it is cancelled.
+pub fn parse_fit_with_limits_observed(
+    bytes: &[u8],
+    limits: &ProcessingLimits,
+    observer: &dyn ProcessingObserver,
+) -> Result<Vec<FitDataRecord>, FitProcessError> {
+    let check_count = |found: usize| match limits.max_records {
+        Some(limit) if found > limit => {
+            Err(FitProcessError::LimitExceeded(ExceededLimit::Records {
+                limit,
+                found,
+            }))
+        }
+        _ => Ok(()),
+    };
+
+    // Framing errors are left for `fitparser` to report.
+    let mut expected = 0;
+    if let Ok(spans) = spans::scan_record_spans(bytes) {
+        check_count(spans.len())?;
+        expected = spans.len();
+    }
+
+    check_cancelled(observer)?;
+    observer.progress(Progress::new(Stage::Parse, 0, expected));
+    spans::check_header(bytes, 0)?;
+    let records = decode(bytes, limits.max_decode_time, observer, expected)?;
+    check_count(records.len())?;
+    observer.progress(Progress::new(Stage::Parse, records.len(), records.len()));
+    Ok(records)
+}
+
+/// Decode every data message of `bytes`, including chained files, giving up
+/// once decoding has taken longer than `max_time`.
+fn decode(
+    bytes: &[u8],
+    max_time: Option<Duration>,
+    observer: &dyn ProcessingObserver,
+    expected: usize,
+) -> Result<Vec<FitDataRecord>, FitProcessError> {
+    let parse_error = |err: fitparser::Error| FitProcessError::ParseError(err.to_string());
+    let started = Instant::now();
+    let check_time = || match max_time {
+        Some(limit) if started.elapsed() > limit => {
+            Err(FitProcessError::LimitExceeded(ExceededLimit::DecodeTime {
+                limit,
+            }))
+        }
+        _ => Ok(()),
+    };
+    let mut processor = FitStreamProcessor::new();
+    let mut records = Vec::with_capacity(expected);
+    let mut remaining = bytes;
+    while !remaining.is_empty() {
+        let (rest, object) = processor.deserialize_next(remaining).map_err(parse_error)?;
+        remaining = rest;
+        match object {
+            FitObject::DataMessage(message) => {
+                records.push(processor.decode_message(message).map_err(parse_error)?)
+            }
+            // The next file of a chained FIT starts with fresh definitions.
+            FitObject::Crc(_) => {
+                processor.reset();
+                continue;
+            }
+            FitObject::Header(_) | FitObject::DefinitionMessage(_) => continue,
+        }
+        if records.len() % PROGRESS_CHUNK_RECORDS == 0 {
+            check_time()?;
+            check_cancelled(observer)?;
+            observer.progress(Progress::new(Stage::Parse, records.len(), expected));
+        }
+    }
+    check_time()?;
+    Ok(records)
+}
+
+/// Decode FIT bytes and record the byte range of every data message.
+///
+/// Fails if the framing scan and `fitparser` disagree on the number of data
+/// messages, so `spans` can be indexed alongside `records`.
+pub fn parse_fit_with_spans(bytes: &[u8]) -> Result<ParsedFit, FitProcessError> {
+    let records = parse_fit(bytes)?;
+    let spans = spans::scan_record_spans_with_capacity(bytes, records.len())?;
+    if spans.len() != records.len() {
+        return Err(FitProcessError::ParseError(format!(
+            "found {} data messages but decoded {} records",
+            spans.len(),
+            records.len()
+        )));
+    }
+    Ok(ParsedFit { records, spans })
+}
+
+/// Check the framing of re-encoded bytes before they are handed out, so an
+/// encoder problem fails the upload instead of producing a corrupt download.
+///
+/// The CRCs are left to the encoder: checking them again would be a second
+/// pass over the whole file for every upload.
+fn verify_encoded(bytes: &[u8], expected: usize) -> Result<(), FitProcessError> {
+    let malformed =
+        |msg: &str| FitProcessError::EncodeError(format!("re-encoded file is malformed: {msg}"));
+    let found = match spans::scan_record_spans_with_capacity(bytes, expected) {
+        Ok(spans) => spans.len(),
+        Err(FitProcessError::ParseError(msg) | FitProcessError::InvalidHeader(msg)) => {
+            return Err(malformed(&msg));
+        }
+        Err(err) => return Err(err),
+    };
+    if found != expected {
+        return Err(FitProcessError::EncodeError(format!(
+            "re-encoded file has {found} data messages but {expected} were written"
+        )));
+    }
+    Ok(())
+}
+
+/// Run stages 2–5 of [`process_fit_bytes`] on records that were already decoded.
+///
+/// The returned timings leave `parse` at zero for the caller to fill in.
+pub fn process_parsed_fit(
+    parsed: &[FitDataRecord],
+    options: &ProcessingOptions,
+) -> Result<ProcessedFit, FitProcessError> {
+    process_parsed_fit_observed(parsed, options, &NoObserver)
+}
+
+/// [`process_parsed_fit`] that reports each stage's progress to `observer` and
+/// stops with [`FitProcessError::Cancelled`] once it is cancelled.
+pub fn process_parsed_fit_observed(
+    parsed: &[FitDataRecord],
+    options: &ProcessingOptions,
+    observer: &dyn ProcessingObserver,
+) -> Result<ProcessedFit, FitProcessError> {
+    process_records(parsed, None, options, observer)
+}
+
+/// [`process_parsed_fit_observed`] for records decoded from `source`, which
+/// is returned as the processed file unchanged when
+/// [`ProcessingOptions::preserve_original`] is set and no transform ran.
+pub fn process_parsed_fit_with_source(
+    parsed: &[FitDataRecord],
+    source: &[u8],
+    options: &ProcessingOptions,
+    observer: &dyn ProcessingObserver,
+) -> Result<ProcessedFit, FitProcessError> {
+    process_records(parsed, Some(source), options, observer)
+}
+
+fn process_records(
+    parsed: &[FitDataRecord],
+    source: Option<&[u8]>,
+    options: &ProcessingOptions,
+    observer: &dyn ProcessingObserver,
+) -> Result<ProcessedFit, FitProcessError> {
+    let mut timings = StageTimings::default();
+    let devices = collect_devices(parsed);
+    let structure = FileStructure::from_records(parsed);
+    let speed_spikes = detect_speed_spikes(parsed);
+    let started = Instant::now();
+    let Preprocessed {
+        records: processed_records,
+        warnings,
+        smoothing,
+    } = preprocess_fit_observed(parsed, options, observer)?;
+    timings.preprocess = started.elapsed();
+
+    let total = processed_records.len();
+    check_cancelled(observer)?;
+    let started = Instant::now();
+    observer.progress(Progress::new(Stage::Encode, 0, total));
+    let processed_bytes = match (source, &processed_records) {
+        // Borrowed records mean no transform ran, so the source is the output.
+        (Some(source), Cow::Borrowed(_)) if options.preserve_original => source.to_vec(),
+        _ => {
+            let encoded = encode_records(&processed_records)
+                .map_err(|err| FitProcessError::EncodeError(err.to_string()))?;
+            verify_encoded(&encoded, total)?;
+            encoded
+        }
+    };
+    observer.progress(Progress::new(Stage::Encode, total, total));
+    timings.encode = started.elapsed();
+
+    check_cancelled(observer)?;
+    let started = Instant::now();
+    observer.progress(Progress::new(Stage::Derive, 0, total));
+    let derived = derive_workout_data(&processed_records, options);
+    check_cancelled(observer)?;
+    let annotations = detect_annotations(&processed_records);
+    check_cancelled(observer)?;
+    let charts = chart_series(&processed_records);
+    check_cancelled(observer)?;
+    let track = gps_track(&processed_records);
+    observer.progress(Progress::new(Stage::Derive, total, total));
+    timings.derive = started.elapsed();
+
+    let started = Instant::now();
+    let preview = options
+        .preview_records
+        .map_or(total, |count| total.min(count as usize));
+    let mut filtered_records = Vec::with_capacity(preview);
+    observer.progress(Progress::new(Stage::Display, 0, preview));
+    for chunk in processed_records[..preview].chunks(PROGRESS_CHUNK_RECORDS) {
+        check_cancelled(observer)?;
+        filtered_records.extend(to_display_records(
+            chunk,
+            derived.summary.utc_offset_seconds,
+        ));
+        observer.progress(Progress::new(
+            Stage::Display,
+            filtered_records.len(),
+            preview,
+        ));
+    }
+    timings.display = started.elapsed();
 
     Ok(ProcessedFit {
         records: filtered_records,
+        record_count: total,
         processed_bytes,
         summary: derived.summary,
+        devices,
+        structure,
+        annotations,
+        speed_spikes,
+        charts,
+        track,
+        warnings,
+        smoothing,
+        timings,
     })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::templates::render_processed_records;
 
     fn fixture_bytes() -> Vec<u8> {
         std::fs::read("test/fixtures/activity.fit").expect("fixture should be present")
@@ -59,8 +363,8 @@ mod tests {
         let processed = process_fit_bytes(&bytes, &ProcessingOptions::default())
             .expect("processing should succeed");
 
-        let original = from_bytes(&bytes).expect("fixture should decode");
-        let redecoded = from_bytes(&processed.processed_bytes).expect("processed bytes decode");
+        let original = parse_fit(&bytes).expect("fixture should decode");
+        let redecoded = parse_fit(&processed.processed_bytes).expect("processed bytes decode");
 
         assert_eq!(original.len(), redecoded.len());
         assert!(
@@ -71,6 +375,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn preserve_original_skips_the_rewrite_only_without_transforms() {
+        let bytes = fixture_bytes();
+        let preserve = ProcessingOptions {
+            preserve_original: true,
+            ..ProcessingOptions::default()
+        };
+
+        let processed = process_fit_bytes(&bytes, &preserve).unwrap();
+        assert_eq!(
+            crc::fit_crc(&processed.processed_bytes),
+            crc::fit_crc(&bytes)
+        );
+        assert_eq!(processed.processed_bytes, bytes);
+
+        let smoothed = process_fit_bytes(
+            &bytes,
+            &ProcessingOptions {
+                smooth_speed: true,
+                ..preserve.clone()
+            },
+        )
+        .unwrap();
+        assert_ne!(smoothed.processed_bytes, bytes);
+    }
+
     #[test]
     fn processed_download_remains_decodable_without_speed_fields() {
         let bytes = fixture_bytes();
@@ -80,6 +410,7 @@ mod tests {
             &ProcessingOptions {
                 remove_speed_fields: true,
                 smooth_speed: false,
+                ..ProcessingOptions::default()
             },
         )
         .expect("processing should succeed");
@@ -92,19 +423,191 @@ mod tests {
                 .all(|field| field.name != "speed" && field.name != "enhanced_speed")
         );
 
-        let download = from_bytes(&processed.processed_bytes).expect("download should decode");
+        let download = parse_fit(&processed.processed_bytes).expect("download should decode");
         assert_eq!(download.len(), processed.records.len());
     }
 
+    #[test]
+    fn preview_records_limits_display_records_only() {
+        let bytes = fixture_bytes();
+        let full = process_fit_bytes(&bytes, &ProcessingOptions::default())
+            .expect("processing should succeed");
+
+        for preview in [0, 3] {
+            let processed = process_fit_bytes(
+                &bytes,
+                &ProcessingOptions {
+                    preview_records: Some(preview),
+                    ..ProcessingOptions::default()
+                },
+            )
+            .expect("processing should succeed");
+
+            assert_eq!(processed.records.len(), preview as usize);
+            assert_eq!(processed.record_count, full.records.len());
+            assert_eq!(processed.processed_bytes, full.processed_bytes);
+        }
+    }
+
+    #[cfg(feature = "server")]
     #[test]
     fn rendered_output_includes_summary_and_download_link() {
+        use crate::locale::NumberLocale;
+        use crate::templates::render_processed_records;
+
         let bytes = fixture_bytes();
         let processed = process_fit_bytes(&bytes, &ProcessingOptions::default())
             .expect("processing should succeed");
 
-        let rendered = render_processed_records(&processed, "/download/test");
+        let rendered =
+            render_processed_records(&processed, "/download/test", NumberLocale::default());
 
         assert!(rendered.contains("Workout Overview"));
         assert!(rendered.contains("Download processed FIT"));
     }
+
+    #[test]
+    fn observer_sees_every_stage_finish() {
+        let bytes = fixture_bytes();
+        let updates = std::sync::Mutex::new(Vec::new());
+        let record = |progress: Progress| updates.lock().unwrap().push(progress);
+        let options = ProcessingOptions {
+            smooth_speed: true,
+            ..ProcessingOptions::default()
+        };
+
+        process_fit_bytes_observed(&bytes, &options, &record).expect("processing should succeed");
+
+        let updates = updates.into_inner().unwrap();
+        for stage in [
+            Stage::Parse,
+            Stage::Preprocess,
+            Stage::Encode,
+            Stage::Derive,
+            Stage::Display,
+        ] {
+            assert!(
+                updates
+                    .iter()
+                    .any(|update| update.stage == stage && update.completed == update.total),
+                "{stage:?} should report completion"
+            );
+        }
+    }
+
+    #[test]
+    fn cancelled_processing_stops_before_encoding() {
+        struct CancelOnPreprocess(progress::CancellationToken);
+        impl ProcessingObserver for CancelOnPreprocess {
+            fn progress(&self, progress: Progress) {
+                assert_ne!(progress.stage, Stage::Encode, "encoding should not start");
+                if progress.stage == Stage::Preprocess {
+                    self.0.cancel();
+                }
+            }
+
+            fn is_cancelled(&self) -> bool {
+                self.0.is_cancelled()
+            }
+        }
+
+        let observer = CancelOnPreprocess(Default::default());
+        let result =
+            process_fit_bytes_observed(&fixture_bytes(), &ProcessingOptions::default(), &observer);
+
+        assert!(matches!(result, Err(FitProcessError::Cancelled)));
+    }
+
+    #[test]
+    fn vendor_files_survive_the_rewrite() {
+        for name in [
+            "wahoo_zero_padding.fit",
+            "coros_local_time.fit",
+            "suunto_missing_totals.fit",
+        ] {
+            let bytes = std::fs::read(format!("test/fixtures/vendor/{name}")).unwrap();
+            let original = parse_fit(&bytes).unwrap();
+
+            let processed = process_fit_bytes(&bytes, &ProcessingOptions::default())
+                .unwrap_or_else(|err| panic!("{name}: {err}"));
+
+            let redecoded = parse_fit(&processed.processed_bytes).unwrap();
+            let kinds = |records: &[FitDataRecord]| {
+                records.iter().map(FitDataRecord::kind).collect::<Vec<_>>()
+            };
+            assert_eq!(kinds(&redecoded), kinds(&original), "{name}");
+        }
+    }
+
+    #[test]
+    fn invalid_rules_fail_processing() {
+        let options = ProcessingOptions {
+            replace_values: Some("heart_rate = high".to_string()),
+            ..ProcessingOptions::default()
+        };
+
+        let err = process_fit_bytes(&fixture_bytes(), &options).expect_err("invalid rule");
+
+        assert!(
+            matches!(err, FitProcessError::InvalidOption(ref msg) if msg.contains("heart_rate = high"))
+        );
+    }
+
+    #[test]
+    fn out_of_range_scales_fail_processing() {
+        let options = ProcessingOptions {
+            distance_scale: Some(HashableF64(10.0)),
+            ..ProcessingOptions::default()
+        };
+
+        let err = process_fit_bytes(&fixture_bytes(), &options).expect_err("scale too large");
+
+        assert!(
+            matches!(err, FitProcessError::InvalidOption(ref msg) if msg.contains("distance_scale"))
+        );
+
+        let options = ProcessingOptions {
+            power_scale_percent: Some(HashableF64(f64::NAN)),
+            ..ProcessingOptions::default()
+        };
+        let err = process_fit_bytes(&fixture_bytes(), &options).expect_err("not a percentage");
+        assert!(
+            matches!(err, FitProcessError::InvalidOption(ref msg) if msg.contains("power_scale_percent"))
+        );
+    }
+
+    #[test]
+    fn record_limit_is_enforced_before_decoding() {
+        // Header, a one-field definition for local type 0, then three data messages.
+        let mut bytes = vec![12, 0x10, 0, 0, 15, 0, 0, 0];
+        bytes.extend(b".FIT");
+        bytes.extend([0x40, 0, 0, 20, 0, 1, 3, 1, 2]);
+        bytes.extend([0x00, 1, 0x00, 2, 0x00, 3]);
+        let limits = ProcessingLimits {
+            max_records: Some(2),
+            max_decode_time: None,
+        };
+
+        let err = parse_fit_with_limits(&bytes, &limits).expect_err("too many messages");
+
+        assert!(matches!(
+            err,
+            FitProcessError::LimitExceeded(ExceededLimit::Records { limit: 2, found: 3 })
+        ));
+    }
+
+    #[test]
+    fn decode_time_is_checked_while_decoding() {
+        let limits = ProcessingLimits {
+            max_records: None,
+            max_decode_time: Some(Duration::ZERO),
+        };
+
+        let err = parse_fit_with_limits(&fixture_bytes(), &limits).expect_err("out of time");
+
+        assert!(matches!(
+            err,
+            FitProcessError::LimitExceeded(ExceededLimit::DecodeTime { .. })
+        ));
+    }
 }
@@ -0,0 +1,141 @@
+//! Transforms registered by self-hosters at startup, without forking the crate.
+//!
+//! A plugin is any [`Transform`] that is `Send + Sync`. Register it before
+//! building the app, then select it per upload by name through
+//! [`crate::processing::ProcessingOptions::plugins`]:
+//!
+//! ```ignore
+//! struct CapPower;
+//!
+//! impl Transform for CapPower {
+//!     fn name(&self) -> &'static str {
+//!         "cap_power"
+//!     }
+//!
+//!     fn apply(&self, ctx: &mut RecordContext) {
+//!         ctx.rewrite_fields(|_, _, field| match field.value() {
+//!             Value::UInt16(watts) if field.name() == "power" && *watts > 2000 => {
+//!                 FieldEdit::Replace(Value::UInt16(2000))
+//!             }
+//!             _ => FieldEdit::Keep,
+//!         });
+//!     }
+//! }
+//!
+//! rustyfit::processing::plugins::register_plugin(Arc::new(CapPower));
+//! ```
+//!
+//! Selected plugins run in the order given, after the built-in transforms
+//! and before anonymization, so privacy rules always see their output.
+
+use crate::processing::transforms::Transform;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// A transform shared between every request that selects it.
+pub type Plugin = Arc<dyn Transform + Send + Sync>;
+
+static PLUGINS: RwLock<Vec<Plugin>> = RwLock::new(Vec::new());
+
+/// Make `plugin` selectable by its [`Transform::name`], replacing any plugin
+/// registered under the same name.
+pub fn register_plugin(plugin: Plugin) {
+    let mut plugins = PLUGINS.write().unwrap_or_else(|err| err.into_inner());
+    plugins.retain(|existing| existing.name() != plugin.name());
+    plugins.push(plugin);
+}
+
+/// The plugin registered as `name`.
+pub fn registered_plugin(name: &str) -> Option<Plugin> {
+    PLUGINS
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .iter()
+        .find(|plugin| plugin.name() == name)
+        .cloned()
+}
+
+/// Names of every registered plugin, in registration order.
+pub fn plugin_names() -> Vec<&'static str> {
+    PLUGINS
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .iter()
+        .map(|plugin| plugin.name())
+        .collect()
+}
+
+/// A plugin name in the options that nothing registered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPlugin(pub String);
+
+impl fmt::Display for UnknownPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown plugin `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownPlugin {}
+
+/// Look up each of `names` in order.
+pub fn resolve_plugins(names: &[String]) -> Result<Vec<Plugin>, UnknownPlugin> {
+    names
+        .iter()
+        .map(|name| registered_plugin(name).ok_or_else(|| UnknownPlugin(name.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::ProcessingOptions;
+    use crate::processing::transforms::{RecordContext, build_pipeline};
+    use fitparser::FitDataRecord;
+    use fitparser::profile::MesgNum;
+
+    struct DropEvents;
+
+    impl Transform for DropEvents {
+        fn name(&self) -> &'static str {
+            "test_drop_events"
+        }
+
+        fn apply(&self, ctx: &mut RecordContext) {
+            ctx.retain_records(|record| !matches!(record.kind(), MesgNum::Event));
+        }
+    }
+
+    #[test]
+    fn selected_plugins_run_before_anonymization() {
+        register_plugin(Arc::new(DropEvents));
+        let options = ProcessingOptions {
+            plugins: vec!["test_drop_events".to_string()],
+            strip_device_info: true,
+            anonymize: "private".parse().ok(),
+            ..ProcessingOptions::default()
+        };
+
+        let pipeline = build_pipeline(&options);
+        let names: Vec<&str> = pipeline.iter().map(|transform| transform.name()).collect();
+        assert_eq!(
+            &names[..3],
+            [
+                "strip_device_info",
+                "test_drop_events",
+                "anonymize_strip_gps"
+            ]
+        );
+
+        let mut ctx = RecordContext::new(vec![
+            FitDataRecord::new(MesgNum::Event),
+            FitDataRecord::new(MesgNum::Record),
+        ]);
+        pipeline[1].apply(&mut ctx);
+        assert_eq!(ctx.records().len(), 1);
+
+        assert_eq!(
+            resolve_plugins(&["missing".to_string()]).err(),
+            Some(UnknownPlugin("missing".to_string()))
+        );
+    }
+}
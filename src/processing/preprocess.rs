@@ -1,9 +1,20 @@
+use crate::processing::compat::{Vendor, detect_vendor};
+use crate::processing::progress::{
+    NoObserver, ProcessingObserver, Progress, Stage, check_cancelled,
+};
 use crate::processing::summary::{
     DistanceSample, field_value_to_f64, reconstruct_distance_series, smooth_speed_window,
 };
-use crate::processing::types::{FitProcessError, ProcessingOptions, SPEED_SMOOTHING_WINDOW};
+use crate::processing::transforms::developer_data::DropOrphanedDeveloperFields;
+use crate::processing::transforms::summary_speeds::{RecomputeSummarySpeeds, record_speeds};
+use crate::processing::transforms::timestamps::count_out_of_order;
+use crate::processing::transforms::{RecordContext, Transform, build_pipeline, record_timestamp};
+use crate::processing::types::{
+    FitProcessError, ProcessingOptions, ProcessingWarning, SmoothingResiduals, WarningCode,
+};
 use fitparser::profile::MesgNum;
 use fitparser::{FitDataField, FitDataRecord, Value};
+use std::borrow::Cow;
 
 #[derive(Clone, Debug, Default)]
 pub struct RecordOverrides {
@@ -11,88 +22,146 @@ pub struct RecordOverrides {
     pub distance: Option<f64>,
 }
 
+/// Records after preprocessing, with the warnings raised on the way.
+#[derive(Debug)]
+pub struct Preprocessed<'a> {
+    pub records: Cow<'a, [FitDataRecord]>,
+    pub warnings: Vec<ProcessingWarning>,
+    /// Set when speed smoothing ran.
+    pub smoothing: Option<SmoothingResiduals>,
+}
+
 /// Preprocess FIT data to align with downstream derive/display steps.
-pub fn preprocess_fit(
-    records: &[FitDataRecord],
+///
+/// Runs the transforms enabled by `options` in pipeline order. When no
+/// transform is enabled the input is borrowed instead of copied.
+pub fn preprocess_fit<'a>(
+    records: &'a [FitDataRecord],
     options: &ProcessingOptions,
-) -> Result<Vec<FitDataRecord>, FitProcessError> {
-    let overrides = compute_record_overrides(records, options);
-    Ok(apply_overrides_and_filters(records, &overrides, options))
+) -> Result<Preprocessed<'a>, FitProcessError> {
+    preprocess_fit_observed(records, options, &NoObserver)
 }
 
-fn apply_overrides_and_filters(
-    records: &[FitDataRecord],
-    overrides: &[RecordOverrides],
+/// [`preprocess_fit`] that reports to `observer` after each transform and
+/// stops once it is cancelled, within a transform's record loop if needed.
+pub fn preprocess_fit_observed<'a>(
+    records: &'a [FitDataRecord],
     options: &ProcessingOptions,
-) -> Vec<FitDataRecord> {
-    records
-        .iter()
-        .enumerate()
-        .map(|(idx, record)| {
-            let mut updated = FitDataRecord::new(record.kind());
-            let record_overrides = overrides.get(idx).cloned().unwrap_or_default();
-            let is_record_message = matches!(record.kind(), MesgNum::Record);
-
-            for field in record.fields() {
-                let name = field.name();
-                if options.remove_speed_fields
-                    && is_record_message
-                    && matches!(name, "speed" | "enhanced_speed")
-                {
-                    continue;
-                }
-
-                let mut overridden = false;
-                let value = match name {
-                    "distance" if is_record_message => {
-                        overridden = true;
-                        record_overrides
-                            .distance
-                            .map(Value::Float64)
-                            .unwrap_or_else(|| field.value().clone())
-                    }
-                    "speed" | "enhanced_speed" if is_record_message => {
-                        overridden = true;
-                        record_overrides
-                            .speed
-                            .map(Value::Float64)
-                            .unwrap_or_else(|| field.value().clone())
-                    }
-                    _ => field.value().clone(),
-                };
-
-                if overridden {
-                    let updated_field = FitDataField::with_meta(
-                        field.name().to_string(),
-                        field.number(),
-                        field.developer_data_index(),
-                        value,
-                        field.raw_value().clone(),
-                        field.units().to_string(),
-                        field.base_type(),
-                        field.scale(),
-                        field.offset(),
-                        field.timestamp_kind(),
-                    );
-                    updated.push(updated_field);
-                } else {
-                    updated.push(field.clone());
-                }
-            }
+    observer: &dyn ProcessingObserver,
+) -> Result<Preprocessed<'a>, FitProcessError> {
+    options.validate()?;
+    let mut warnings = input_warnings(records, options);
+    // Vendor fixes run first, so every other transform sees corrected records.
+    let mut pipeline = detect_vendor(records)
+        .filter(|_| !options.skip_vendor_fixes)
+        .map(Vendor::fixes)
+        .unwrap_or_default();
+    pipeline.extend(build_pipeline(options));
+    let total = pipeline.len();
+    observer.progress(Progress::new(Stage::Preprocess, 0, total));
+    if pipeline.is_empty() {
+        return Ok(Preprocessed {
+            records: Cow::Borrowed(records),
+            warnings,
+            smoothing: None,
+        });
+    }
 
-            updated
-        })
-        .collect()
+    let speeds_before = record_speeds(records);
+    let mut ctx = RecordContext::observed(records.to_vec(), observer);
+    for (index, transform) in pipeline.into_iter().enumerate() {
+        check_cancelled(observer)?;
+        transform.apply(&mut ctx);
+        if ctx.is_cancelled() {
+            return Err(FitProcessError::Cancelled);
+        }
+        observer.progress(Progress::new(Stage::Preprocess, index + 1, total));
+    }
+    // Whichever transform changed record speeds, laps and sessions follow them.
+    if record_speeds(ctx.records()) != speeds_before {
+        RecomputeSummarySpeeds.apply(&mut ctx);
+    }
+    // Transforms and plugins may drop declarations; never encode fields without them.
+    DropOrphanedDeveloperFields.apply(&mut ctx);
+    let smoothing = ctx.smoothing_residuals().cloned();
+    let (processed, pipeline_warnings) = ctx.into_parts();
+    warnings.extend(pipeline_warnings);
+    Ok(Preprocessed {
+        records: Cow::Owned(processed),
+        warnings,
+        smoothing,
+    })
 }
 
-pub fn compute_record_overrides(
+/// Warnings about the decoded input itself, independent of the transforms.
+fn input_warnings(
     records: &[FitDataRecord],
     options: &ProcessingOptions,
-) -> Vec<RecordOverrides> {
-    if !options.smooth_speed {
-        return vec![RecordOverrides::default(); records.len()];
+) -> Vec<ProcessingWarning> {
+    let mut warnings = Vec::new();
+
+    // With repair enabled, the repair transform reports what it fixed instead.
+    let out_of_order = count_out_of_order(records);
+    if options.timestamp_repair.is_none() && out_of_order > 0 {
+        warnings.push(ProcessingWarning::new(
+            WarningCode::NonMonotonicTimestamps,
+            out_of_order,
+            "record",
+            "with non-monotonic timestamps; enable timestamp repair to fix them",
+        ));
+    }
+
+    let developer_fields = records
+        .iter()
+        .flat_map(|record| record.fields())
+        .filter(|field| field.developer_data_index().is_some())
+        .count();
+    if developer_fields > 0 {
+        warnings.push(ProcessingWarning::new(
+            WarningCode::DeveloperFieldsPassedThrough,
+            developer_fields,
+            "developer field",
+            "passed through unmodified",
+        ));
     }
 
+    warnings
+}
+
+/// Copy a field's metadata onto a replacement value.
+pub(crate) fn with_value(field: &FitDataField, value: Value) -> FitDataField {
+    FitDataField::with_meta(
+        field.name().to_string(),
+        field.number(),
+        field.developer_data_index(),
+        value,
+        field.raw_value().clone(),
+        field.units().to_string(),
+        field.base_type(),
+        field.scale(),
+        field.offset(),
+        field.timestamp_kind(),
+    )
+}
+
+/// Distance-derived speeds for consecutive samples, before and after smoothing.
+///
+/// `raw[i]`, `smoothed[i]`, and `time_intervals[i]` describe the interval that
+/// starts at `samples[i]`.
+pub(crate) struct SpeedSeries {
+    pub(crate) samples: Vec<DistanceSample>,
+    pub(crate) time_intervals: Vec<f64>,
+    pub(crate) raw: Vec<f64>,
+    pub(crate) smoothed: Vec<f64>,
+}
+
+/// Derive per-interval speeds from timestamp/distance pairs and smooth them
+/// with a moving average of `window` samples. Returns `None` when fewer than
+/// two samples carry both values.
+pub(crate) fn smoothed_speed_series(
+    records: &[FitDataRecord],
+    window: usize,
+) -> Option<SpeedSeries> {
     let mut distance_samples: Vec<DistanceSample> = Vec::new();
 
     for (record_index, record) in records.iter().enumerate() {
@@ -117,7 +186,7 @@ pub fn compute_record_overrides(
     }
 
     if distance_samples.len() < 2 {
-        return vec![RecordOverrides::default(); records.len()];
+        return None;
     }
 
     let time_intervals: Vec<f64> = distance_samples
@@ -141,12 +210,37 @@ pub fn compute_record_overrides(
         }
     }
 
-    let smoothed_speeds = smooth_speed_window(&speeds, SPEED_SMOOTHING_WINDOW);
+    let smoothed = smooth_speed_window(&speeds, window);
+    Some(SpeedSeries {
+        samples: distance_samples,
+        time_intervals,
+        raw: speeds,
+        smoothed,
+    })
+}
+
+/// Per-record smoothed speed and rebuilt distance for a moving average of `window` samples.
+pub fn compute_speed_overrides(records: &[FitDataRecord], window: usize) -> Vec<RecordOverrides> {
+    match smoothed_speed_series(records, window) {
+        Some(series) => speed_overrides(records.len(), series),
+        None => vec![RecordOverrides::default(); records.len()],
+    }
+}
+
+/// Per-record smoothed speed and rebuilt distance from an already smoothed series
+/// over `record_count` records.
+pub(crate) fn speed_overrides(record_count: usize, series: SpeedSeries) -> Vec<RecordOverrides> {
+    let SpeedSeries {
+        samples: distance_samples,
+        time_intervals,
+        smoothed: smoothed_speeds,
+        ..
+    } = series;
     let smoothed_distances =
         reconstruct_distance_series(&distance_samples, &smoothed_speeds, &time_intervals);
 
-    let mut record_speeds: Vec<Option<f64>> = vec![None; records.len()];
-    let mut record_distances: Vec<Option<f64>> = vec![None; records.len()];
+    let mut record_speeds: Vec<Option<f64>> = vec![None; record_count];
+    let mut record_distances: Vec<Option<f64>> = vec![None; record_count];
 
     for (window_idx, (&speed, sample)) in smoothed_speeds
         .iter()
@@ -165,12 +259,89 @@ pub fn compute_record_overrides(
         }
     }
 
-    records
-        .iter()
-        .enumerate()
-        .map(|(idx, _)| RecordOverrides {
+    (0..record_count)
+        .map(|idx| RecordOverrides {
             speed: record_speeds.get(idx).cloned().unwrap_or(None),
             distance: record_distances.get(idx).cloned().unwrap_or(None),
         })
         .collect()
 }
+
+/// Lap or session speeds recomputed from the records the message spans.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SummarySpeedOverrides {
+    pub avg_speed: Option<f64>,
+    pub max_speed: Option<f64>,
+}
+
+/// Per-record average and peak speed for every `Lap` and `Session`, taken from
+/// the `Record` messages between its `start_time` and `timestamp`. The average
+/// is the message's own distance over its timer time, falling back to the
+/// distance the records cover and the elapsed time. Other messages get the
+/// default.
+pub fn summary_speed_overrides(records: &[FitDataRecord]) -> Vec<SummarySpeedOverrides> {
+    let samples: Vec<(f64, Option<f64>, Option<f64>)> = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .filter_map(|record| {
+            let timestamp = record_timestamp(record)?;
+            let value = |names: &[&str]| {
+                names.iter().find_map(|name| {
+                    record
+                        .fields()
+                        .iter()
+                        .find(|field| field.name() == *name)
+                        .and_then(field_value_to_f64)
+                })
+            };
+            Some((
+                timestamp,
+                value(&["enhanced_speed", "speed"]),
+                value(&["distance"]),
+            ))
+        })
+        .collect();
+
+    records
+        .iter()
+        .map(|record| {
+            if !matches!(record.kind(), MesgNum::Lap | MesgNum::Session) {
+                return SummarySpeedOverrides::default();
+            }
+            let value = |name: &str| {
+                record
+                    .fields()
+                    .iter()
+                    .find(|field| field.name() == name)
+                    .and_then(field_value_to_f64)
+            };
+            let (Some(start), Some(end)) = (value("start_time"), record_timestamp(record)) else {
+                return SummarySpeedOverrides::default();
+            };
+            let spanned: Vec<_> = samples
+                .iter()
+                .filter(|(timestamp, _, _)| (start..=end).contains(timestamp))
+                .collect();
+
+            let max_speed = spanned
+                .iter()
+                .filter_map(|(_, speed, _)| *speed)
+                .reduce(f64::max);
+            let covered = || {
+                let mut distances = spanned.iter().filter_map(|(_, _, distance)| *distance);
+                let first = distances.next()?;
+                Some(distances.last().unwrap_or(first) - first)
+            };
+            let distance = value("total_distance").or_else(covered);
+            let time = value("total_timer_time").or_else(|| value("total_elapsed_time"));
+            let avg_speed = match (distance, time) {
+                (Some(distance), Some(time)) if time > 0.0 => Some(distance / time),
+                _ => None,
+            };
+            SummarySpeedOverrides {
+                avg_speed,
+                max_speed,
+            }
+        })
+        .collect()
+}
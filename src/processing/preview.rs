@@ -0,0 +1,74 @@
+use crate::processing::preprocess::smoothed_speed_series;
+use crate::processing::transforms::smooth_speed::smoothing_residuals;
+use crate::processing::types::SmoothingResiduals;
+use fitparser::FitDataRecord;
+use serde::Serialize;
+
+/// Largest moving-average window accepted by the preview endpoint.
+pub const MAX_PREVIEW_WINDOW: usize = 121;
+
+/// Raw and smoothed speed series for charting a candidate smoothing window.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeedPreview {
+    pub window: usize,
+    pub points: Vec<SpeedPreviewPoint>,
+    pub smoothed_max: Option<f64>,
+    /// How far this window moves the raw series; `None` without distance samples.
+    pub residuals: Option<SmoothingResiduals>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeedPreviewPoint {
+    pub timestamp: f64,
+    pub raw_speed: f64,
+    pub smoothed_speed: f64,
+}
+
+/// Recompute the smoothed speed series for `window` without re-encoding.
+pub fn preview_smoothed_speed(records: &[FitDataRecord], window: usize) -> SpeedPreview {
+    let series = smoothed_speed_series(records, window);
+    let residuals = series
+        .as_ref()
+        .map(|series| smoothing_residuals(&series.raw, &series.smoothed, window));
+    let points: Vec<SpeedPreviewPoint> = series
+        .map(|series| {
+            series
+                .samples
+                .iter()
+                .zip(series.raw.iter().zip(&series.smoothed))
+                .map(
+                    |(sample, (&raw_speed, &smoothed_speed))| SpeedPreviewPoint {
+                        timestamp: sample.timestamp,
+                        raw_speed,
+                        smoothed_speed,
+                    },
+                )
+                .collect()
+        })
+        .unwrap_or_default();
+    let smoothed_max = points
+        .iter()
+        .map(|point| point.smoothed_speed)
+        .reduce(f64::max);
+
+    SpeedPreview {
+        window,
+        points,
+        smoothed_max,
+        residuals,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_without_distance_samples_is_empty() {
+        let preview = preview_smoothed_speed(&[], 5);
+        assert_eq!(preview.window, 5);
+        assert!(preview.points.is_empty());
+        assert_eq!(preview.smoothed_max, None);
+        assert_eq!(preview.residuals, None);
+    }
+}
@@ -0,0 +1,259 @@
+//! Catalog of the FIT profile's messages and their fields, for clients that
+//! build field pickers rather than hard-code field numbers.
+//!
+//! Message names and numbers come from `fitparser`'s [`MesgNum`]. The
+//! decoder keeps its field tables private, so the fields are read back from
+//! it instead: every field number of every message is decoded from a probe
+//! file, and those the profile knows come back with their name and units.
+//! Decoding two raw values gives the scale and offset, as scaled fields are
+//! stored as `(value + offset) * scale`. The catalog therefore follows
+//! whichever profile `fitparser` was built with.
+
+use crate::processing::crc::CrcWriter;
+use crate::processing::structure::message_type_name;
+use fitparser::de::{FitObject, FitStreamProcessor};
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataField, Value};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+
+/// First global message number reserved for manufacturers.
+const MANUFACTURER_RANGE_START: u16 = 0xFF00;
+
+/// Raw values every field is decoded with; two points fix a linear scale.
+const PROBES: [u32; 2] = [1_000, 3_000];
+
+/// FIT base type of the probed fields.
+const UINT32: u8 = 0x86;
+
+/// Profile version written in the probe files' headers.
+const PROBE_PROFILE_VERSION: u16 = 2132;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageInfo {
+    /// Name as shown in records, e.g. `Record`.
+    pub name: String,
+    /// Global message number.
+    pub number: u16,
+    /// Fields the profile defines, by number.
+    pub fields: Vec<FieldInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldInfo {
+    pub name: String,
+    /// Field definition number.
+    pub number: u8,
+    pub units: Option<String>,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+/// Every message the profile defines, by number.
+pub fn message_catalog() -> &'static [MessageInfo] {
+    static CATALOG: OnceLock<Vec<MessageInfo>> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        (0..MANUFACTURER_RANGE_START)
+            .map(MesgNum::from_u16)
+            .filter(|kind| !matches!(kind, MesgNum::UnknownVariant(_)))
+            .map(|kind| MessageInfo {
+                name: message_type_name(kind),
+                number: kind.as_u16(),
+                fields: profile_fields(kind),
+            })
+            .collect()
+    })
+}
+
+/// Fields of `kind` as the decoder names, scales, and offsets them.
+fn profile_fields(kind: MesgNum) -> Vec<FieldInfo> {
+    let [low, high] = PROBES.map(|raw| decode_probe(kind.as_u16(), raw));
+    low.into_iter()
+        .map(|(number, field)| {
+            let values = (
+                numeric(field.value()),
+                high.get(&number).and_then(|probed| numeric(probed.value())),
+            );
+            let (scale, offset) = match values {
+                (Some(first), Some(second)) if second != first => {
+                    let scale = f64::from(PROBES[1] - PROBES[0]) / (second - first);
+                    (
+                        rounded(scale),
+                        rounded(f64::from(PROBES[0]) / scale - first),
+                    )
+                }
+                _ => (1.0, 0.0),
+            };
+            FieldInfo {
+                name: field.name().to_string(),
+                number,
+                units: Some(field.units().to_string()).filter(|units| !units.is_empty()),
+                scale,
+                offset,
+            }
+        })
+        .collect()
+}
+
+/// Decode every field number of message `global` holding `raw`, keeping the
+/// fields the profile names.
+fn decode_probe(global: u16, raw: u32) -> BTreeMap<u8, FitDataField> {
+    let bytes = probe_file(global, raw);
+    let mut processor = FitStreamProcessor::new();
+    let mut fields = BTreeMap::new();
+    let mut numbers = 0..u8::MAX;
+    let mut remaining = bytes.as_slice();
+    while !remaining.is_empty() {
+        let Ok((rest, object)) = processor.deserialize_next(remaining) else {
+            break;
+        };
+        remaining = rest;
+        let FitObject::DataMessage(message) = object else {
+            continue;
+        };
+        let Some(number) = numbers.next() else {
+            break;
+        };
+        // Values the decoder cannot convert leave the field out.
+        let Ok(record) = processor.decode_message(message) else {
+            continue;
+        };
+        // Components expand into other fields; only the probed one counts.
+        let probed = record
+            .into_vec()
+            .into_iter()
+            .find(|field| field.number() == number && !field.name().starts_with("unknown_field"));
+        if let Some(field) = probed {
+            fields.insert(number, field);
+        }
+    }
+    fields
+}
+
+/// Bytes each probed field takes: its definition message, then a data
+/// message holding the `uint32`.
+const PROBE_MESSAGE_BYTES: usize = 9 + 5;
+
+/// A FIT file with one data message per field number (255 is the invalid
+/// marker) of message `global`, each a single `uint32` holding `raw`.
+fn probe_file(global: u16, raw: u32) -> Vec<u8> {
+    let data_size = usize::from(u8::MAX) * PROBE_MESSAGE_BYTES;
+    let mut file = CrcWriter::new(Vec::with_capacity(14 + data_size + 2));
+    let written = write_probe(&mut file, global, raw, data_size).and_then(|()| file.finish());
+    written.expect("writing to a Vec cannot fail")
+}
+
+/// The header and data of [`probe_file`]; `file` keeps the CRC as they are
+/// written, so the file is never read back to close it.
+fn write_probe(
+    file: &mut CrcWriter<Vec<u8>>,
+    global: u16,
+    raw: u32,
+    data_size: usize,
+) -> io::Result<()> {
+    file.write_all(&[14, 0x20])?;
+    file.write_all(&PROBE_PROFILE_VERSION.to_le_bytes())?;
+    file.write_all(&(data_size as u32).to_le_bytes())?;
+    file.write_all(b".FIT")?;
+    // The header CRC covers the twelve bytes before it.
+    let header_crc = file.crc();
+    file.write_all(&header_crc.to_le_bytes())?;
+    for number in 0..u8::MAX {
+        // Local message 0, little-endian, redefined for every field.
+        file.write_all(&[0x40, 0, 0])?;
+        file.write_all(&global.to_le_bytes())?;
+        file.write_all(&[1, number, 4, UINT32])?;
+        file.write_all(&[0])?;
+        file.write_all(&raw.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Plain numbers only; timestamps and enum names carry no scale.
+fn numeric(value: &Value) -> Option<f64> {
+    Some(match *value {
+        Value::UInt8(value) => value.into(),
+        Value::UInt16(value) => value.into(),
+        Value::UInt32(value) => value.into(),
+        Value::SInt8(value) => value.into(),
+        Value::SInt16(value) => value.into(),
+        Value::SInt32(value) => value.into(),
+        Value::Float32(value) => value.into(),
+        Value::Float64(value) => value,
+        _ => return None,
+    })
+}
+
+/// Drop the floating-point error of dividing the probes.
+fn rounded(value: f64) -> f64 {
+    (value * 1e6).round() / 1e6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::crc::fit_crc;
+
+    #[test]
+    fn probe_files_carry_valid_crcs() {
+        let file = probe_file(20, PROBES[0]);
+
+        let end = file.len() - 2;
+        assert_eq!(end, 14 + usize::from(u8::MAX) * PROBE_MESSAGE_BYTES);
+        assert_eq!(file[12..14], fit_crc(&file[..12]).to_le_bytes());
+        assert_eq!(file[end..], fit_crc(&file[..end]).to_le_bytes());
+    }
+
+    #[test]
+    fn catalog_lists_profile_messages_with_their_fields() {
+        let catalog = message_catalog();
+        let record = catalog
+            .iter()
+            .find(|message| message.name == "Record")
+            .unwrap();
+
+        assert_eq!(record.number, 20);
+        let speed = record
+            .fields
+            .iter()
+            .find(|field| field.name == "enhanced_speed")
+            .unwrap();
+        assert_eq!((speed.number, speed.scale), (73, 1000.0));
+        assert_eq!(speed.units.as_deref(), Some("m/s"));
+        let altitude = record
+            .fields
+            .iter()
+            .find(|field| field.name == "altitude")
+            .unwrap();
+        assert_eq!(
+            (altitude.number, altitude.scale, altitude.offset),
+            (2, 5.0, 500.0)
+        );
+        let hr_zone = catalog
+            .iter()
+            .find(|message| message.name == "HrZone")
+            .unwrap();
+        assert!(hr_zone.fields.iter().any(|field| field.name == "high_bpm"));
+        assert!(
+            catalog
+                .windows(2)
+                .all(|pair| pair[0].number < pair[1].number)
+        );
+    }
+
+    #[test]
+    fn field_numbers_are_unique_and_sorted() {
+        for message in message_catalog() {
+            assert!(
+                message
+                    .fields
+                    .windows(2)
+                    .all(|pair| pair[0].number < pair[1].number),
+                "{}",
+                message.name
+            );
+        }
+    }
+}
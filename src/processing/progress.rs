@@ -0,0 +1,91 @@
+//! Progress reporting from the processing stages, so callers can show how far
+//! a large file has got.
+
+use crate::processing::FitProcessError;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Records handed to the display stage between two progress reports.
+pub const PROGRESS_CHUNK_RECORDS: usize = 10_000;
+
+/// Stage of [`crate::processing::process_fit_bytes`] that is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Parse,
+    Preprocess,
+    Encode,
+    Derive,
+    Display,
+}
+
+/// How far the current stage has got.
+///
+/// `completed` and `total` count transforms for [`Stage::Preprocess`] and
+/// records for every other stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    pub stage: Stage,
+    pub completed: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn new(stage: Stage, completed: usize, total: usize) -> Self {
+        Self {
+            stage,
+            completed,
+            total,
+        }
+    }
+}
+
+/// Receives [`Progress`] updates while a file is processed, and can stop it.
+pub trait ProcessingObserver: Sync {
+    fn progress(&self, progress: Progress);
+
+    /// Checked between stages, transforms, and record chunks; once it returns
+    /// `true` processing stops with [`FitProcessError::Cancelled`].
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Stop with [`FitProcessError::Cancelled`] if `observer` asks to.
+pub(crate) fn check_cancelled(observer: &dyn ProcessingObserver) -> Result<(), FitProcessError> {
+    if observer.is_cancelled() {
+        Err(FitProcessError::Cancelled)
+    } else {
+        Ok(())
+    }
+}
+
+/// Shared flag asking in-flight processing to stop.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl<F: Fn(Progress) + Sync> ProcessingObserver for F {
+    fn progress(&self, progress: Progress) {
+        self(progress)
+    }
+}
+
+/// Observer for callers that don't track progress.
+pub struct NoObserver;
+
+impl ProcessingObserver for NoObserver {
+    fn progress(&self, _progress: Progress) {}
+}
@@ -0,0 +1,262 @@
+//! Server-side search over the messages of a stored result, for exploring
+//! files too large to send to the browser in one go, and a raw view of any
+//! one message's bytes.
+
+use crate::processing::display::{activity_utc_offset, display_value, field_value_kind};
+use crate::processing::parse_fit_with_spans;
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::types::{FieldValueKind, FitProcessError};
+use fitparser::FitDataRecord;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Bytes shown per line of [`RawMessage::hex_lines`].
+pub const HEX_BYTES_PER_LINE: usize = 16;
+
+/// Page size when the query does not set `limit`.
+pub const DEFAULT_QUERY_LIMIT: usize = 50;
+
+/// Largest page a single query may return.
+pub const MAX_QUERY_LIMIT: usize = 500;
+
+/// Filters for [`query_records`]; every filter that is set must match.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RecordQuery {
+    /// Message type, e.g. `Record` or `Lap`; case-insensitive.
+    pub mesg: Option<String>,
+    /// Only messages carrying this field.
+    pub field: Option<String>,
+    /// Lowest numeric value of `field`, inclusive.
+    pub min: Option<f64>,
+    /// Highest numeric value of `field`, inclusive.
+    pub max: Option<f64>,
+    /// Matches to skip before the returned page.
+    pub offset: usize,
+    /// Matches to return; [`DEFAULT_QUERY_LIMIT`] when unset, capped at
+    /// [`MAX_QUERY_LIMIT`].
+    pub limit: Option<usize>,
+}
+
+/// One page of matching messages.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordPage {
+    /// Matches across the whole file, not just this page.
+    pub total_matches: usize,
+    pub offset: usize,
+    pub records: Vec<InspectedRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InspectedRecord {
+    /// Position of the message in the processed file.
+    pub index: usize,
+    pub message_type: String,
+    /// Global message number, as listed in the FIT profile.
+    pub message_number: u16,
+    pub fields: Vec<InspectedField>,
+}
+
+/// A field with the metadata the results table leaves out.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InspectedField {
+    pub name: String,
+    pub number: u8,
+    /// Set for developer fields.
+    pub developer_data_index: Option<u8>,
+    pub value: String,
+    /// The value as a number, when it is one.
+    pub numeric: Option<f64>,
+    /// The value by type, without formatting.
+    pub raw: FieldValueKind,
+    pub units: String,
+}
+
+/// One data message as stored in the file, next to its decoded fields.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RawMessage {
+    pub index: usize,
+    pub message_type: String,
+    pub message_number: u16,
+    /// Local message type the message was written with.
+    pub local_id: u8,
+    /// Byte offset of the record header within the file.
+    pub offset: usize,
+    /// Length including the record header.
+    pub length: usize,
+    /// `offset  hex bytes  ASCII` lines of [`HEX_BYTES_PER_LINE`] bytes.
+    pub hex_lines: Vec<String>,
+    pub fields: Vec<InspectedField>,
+}
+
+impl RecordQuery {
+    fn matches(&self, record: &FitDataRecord) -> bool {
+        let kind = format!("{:?}", record.kind());
+        if self
+            .mesg
+            .as_ref()
+            .is_some_and(|mesg| !kind.eq_ignore_ascii_case(mesg.trim()))
+        {
+            return false;
+        }
+        let Some(name) = &self.field else {
+            return true;
+        };
+        let Some(field) = record.fields().iter().find(|field| field.name() == name) else {
+            return false;
+        };
+        if self.min.is_none() && self.max.is_none() {
+            return true;
+        }
+        field_value_to_f64(field).is_some_and(|value| {
+            self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+        })
+    }
+}
+
+/// The page of `records` selected by `query`, with timestamps shown at
+/// `utc_offset_seconds`.
+pub fn query_records(
+    records: &[FitDataRecord],
+    query: &RecordQuery,
+    utc_offset_seconds: Option<i32>,
+) -> RecordPage {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_QUERY_LIMIT)
+        .min(MAX_QUERY_LIMIT);
+    let mut total_matches = 0;
+    let mut page = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        if !query.matches(record) {
+            continue;
+        }
+        if total_matches >= query.offset && page.len() < limit {
+            page.push(inspect(index, record, utc_offset_seconds));
+        }
+        total_matches += 1;
+    }
+    RecordPage {
+        total_matches,
+        offset: query.offset,
+        records: page,
+    }
+}
+
+fn inspect(
+    index: usize,
+    record: &FitDataRecord,
+    utc_offset_seconds: Option<i32>,
+) -> InspectedRecord {
+    InspectedRecord {
+        index,
+        message_type: format!("{:?}", record.kind()),
+        message_number: record.kind().as_u16(),
+        fields: record
+            .fields()
+            .iter()
+            .map(|field| InspectedField {
+                name: field.name().to_string(),
+                number: field.number(),
+                developer_data_index: field.developer_data_index(),
+                value: display_value(field, utc_offset_seconds),
+                numeric: field_value_to_f64(field),
+                raw: field_value_kind(field),
+                units: field.units().to_string(),
+            })
+            .collect(),
+    }
+}
+
+/// The data message at `index` of the FIT file `bytes`; `None` when the
+/// file has fewer messages.
+pub fn raw_message(bytes: &[u8], index: usize) -> Result<Option<RawMessage>, FitProcessError> {
+    let parsed = parse_fit_with_spans(bytes)?;
+    let (Some(record), Some(span)) = (parsed.records.get(index), parsed.spans.get(index)) else {
+        return Ok(None);
+    };
+    let range = span.byte_range.clone();
+    let hex_lines = hex_dump(&bytes[range.clone()], range.start);
+    let inspected = inspect(index, record, activity_utc_offset(&parsed.records));
+    Ok(Some(RawMessage {
+        index,
+        message_type: inspected.message_type,
+        message_number: inspected.message_number,
+        local_id: span.local_id,
+        offset: range.start,
+        length: range.len(),
+        hex_lines,
+        fields: inspected.fields,
+    }))
+}
+
+/// `offset  hex bytes  ASCII` lines for `bytes` found at `start` in a file.
+fn hex_dump(bytes: &[u8], start: usize) -> Vec<String> {
+    bytes
+        .chunks(HEX_BYTES_PER_LINE)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let mut text = format!("{:08x} ", start + line * HEX_BYTES_PER_LINE);
+            for byte in chunk {
+                let _ = write!(text, " {byte:02x}");
+            }
+            text.push_str(&"   ".repeat(HEX_BYTES_PER_LINE - chunk.len()));
+            text.push_str("  ");
+            text.extend(chunk.iter().map(|&byte| {
+                if byte.is_ascii_graphic() {
+                    byte as char
+                } else {
+                    '.'
+                }
+            }));
+            text
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+    use fitparser::profile::MesgNum;
+
+    #[test]
+    fn filters_by_message_field_and_range_then_pages() {
+        let mut records: Vec<FitDataRecord> = [150, 172, 180, 165, 175]
+            .iter()
+            .map(|&bpm| record_with(MesgNum::Record, &[("heart_rate", Value::UInt8(bpm))]))
+            .collect();
+        records.insert(
+            2,
+            record_with(MesgNum::Lap, &[("heart_rate", Value::UInt8(190))]),
+        );
+        let query = RecordQuery {
+            mesg: Some("record".to_string()),
+            field: Some("heart_rate".to_string()),
+            min: Some(170.0),
+            offset: 1,
+            limit: Some(1),
+            ..RecordQuery::default()
+        };
+
+        let page = query_records(&records, &query, None);
+
+        assert_eq!(page.total_matches, 3);
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(page.records[0].index, 3);
+        assert_eq!(page.records[0].fields[0].numeric, Some(180.0));
+    }
+
+    #[test]
+    fn hex_dump_pads_short_lines_and_keeps_file_offsets() {
+        let bytes: Vec<u8> = (0x41..0x41 + 18).collect();
+
+        let lines = hex_dump(&bytes, 0x20);
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("00000020  41 42 43"));
+        assert!(lines[0].ends_with("  ABCDEFGHIJKLMNOP"));
+        assert_eq!(lines[1], format!("00000030  51 52{}  QR", "   ".repeat(14)));
+    }
+}
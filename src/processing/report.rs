@@ -0,0 +1,205 @@
+use crate::processing::aggregate::{PeriodTotals, ReportPeriod, period_totals, utc_date};
+use crate::processing::types::WorkoutSummary;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+/// Fraction of the highest recorded heart rate below which an activity counts as easy.
+const EASY_INTENSITY_CEILING: f64 = 0.75;
+/// Fraction of the highest recorded heart rate above which an activity counts as hard.
+const HARD_INTENSITY_FLOOR: f64 = 0.87;
+
+/// Training report built from stored workout summaries.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrainingReport {
+    pub period: ReportPeriod,
+    pub periods: Vec<PeriodReport>,
+    pub personal_records: Vec<PersonalRecord>,
+}
+
+/// Volume and intensity for one calendar period.
+#[derive(Debug, Clone, Serialize)]
+pub struct PeriodReport {
+    #[serde(flatten)]
+    pub totals: PeriodTotals,
+    pub intensity: IntensityDistribution,
+}
+
+/// Training time split by each activity's mean heart rate relative to the
+/// highest heart rate seen across the report.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IntensityDistribution {
+    pub easy_seconds: f64,
+    pub moderate_seconds: f64,
+    pub hard_seconds: f64,
+    /// Time from activities without heart-rate data.
+    pub unknown_seconds: f64,
+}
+
+/// Best value for a metric across all stored activities.
+#[derive(Debug, Clone, Serialize)]
+pub struct PersonalRecord {
+    pub metric: &'static str,
+    pub value: f64,
+    pub achieved_on: Option<NaiveDate>,
+}
+
+/// Group summaries by period and compute intensity buckets and personal records.
+pub fn build_training_report(summaries: &[WorkoutSummary], period: ReportPeriod) -> TrainingReport {
+    let reference_max_hr = summaries
+        .iter()
+        .filter_map(|summary| summary.heart_rate_max)
+        .reduce(f64::max);
+
+    let periods = period_totals(summaries, period)
+        .into_iter()
+        .map(|totals| {
+            let mut intensity = IntensityDistribution::default();
+            for summary in summaries.iter().filter(|summary| {
+                summary.start_timestamp.and_then(|ts| period.start_of(ts))
+                    == Some(totals.period_start)
+            }) {
+                intensity.add(summary, reference_max_hr);
+            }
+            PeriodReport { totals, intensity }
+        })
+        .collect();
+
+    TrainingReport {
+        period,
+        periods,
+        personal_records: personal_records(summaries),
+    }
+}
+
+impl IntensityDistribution {
+    fn add(&mut self, summary: &WorkoutSummary, reference_max_hr: Option<f64>) {
+        let duration = summary.duration_seconds.unwrap_or(0.0);
+        let ratio = match (summary.heart_rate_mean, reference_max_hr) {
+            (Some(mean), Some(max)) if max > 0.0 => mean / max,
+            _ => {
+                self.unknown_seconds += duration;
+                return;
+            }
+        };
+
+        if ratio < EASY_INTENSITY_CEILING {
+            self.easy_seconds += duration;
+        } else if ratio > HARD_INTENSITY_FLOOR {
+            self.hard_seconds += duration;
+        } else {
+            self.moderate_seconds += duration;
+        }
+    }
+}
+
+type SummaryMetric = fn(&WorkoutSummary) -> Option<f64>;
+
+fn personal_records(summaries: &[WorkoutSummary]) -> Vec<PersonalRecord> {
+    let metrics: [(&'static str, SummaryMetric); 4] = [
+        ("longest_distance_meters", |summary| summary.distance_meters),
+        ("longest_duration_seconds", |summary| {
+            summary.duration_seconds
+        }),
+        ("fastest_mean_speed", |summary| summary.speed_mean),
+        ("most_elevation_gain_meters", |summary| {
+            summary.elevation_gain_meters
+        }),
+    ];
+
+    metrics
+        .iter()
+        .filter_map(|(metric, extract)| {
+            summaries
+                .iter()
+                .filter_map(|summary| extract(summary).map(|value| (value, summary)))
+                .filter(|(value, _)| value.is_finite())
+                .max_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(value, summary)| PersonalRecord {
+                    metric,
+                    value,
+                    achieved_on: summary.start_timestamp.and_then(utc_date),
+                })
+        })
+        .collect()
+}
+
+impl TrainingReport {
+    /// Flatten the per-period rows into CSV with a header line.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "period_start,activity_count,distance_meters,duration_seconds,elevation_gain_meters,easy_seconds,moderate_seconds,hard_seconds,unknown_seconds\n",
+        );
+        for row in &self.periods {
+            csv.push_str(&format!(
+                "{},{},{:.1},{:.0},{:.1},{:.0},{:.0},{:.0},{:.0}\n",
+                row.totals.period_start.format("%Y-%m-%d"),
+                row.totals.activity_count,
+                row.totals.distance_meters,
+                row.totals.duration_seconds,
+                row.totals.elevation_gain_meters,
+                row.intensity.easy_seconds,
+                row.intensity.moderate_seconds,
+                row.intensity.hard_seconds,
+                row.intensity.unknown_seconds,
+            ));
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(start: f64, distance: f64, heart_rate_mean: f64) -> WorkoutSummary {
+        WorkoutSummary {
+            start_timestamp: Some(start),
+            duration_seconds: Some(1800.0),
+            distance_meters: Some(distance),
+            heart_rate_mean: Some(heart_rate_mean),
+            heart_rate_max: Some(190.0),
+            ..WorkoutSummary::default()
+        }
+    }
+
+    #[test]
+    fn report_splits_intensity_and_finds_records() {
+        // Both activities fall in the week of 2024-01-01.
+        let summaries = [
+            summary(1_704_067_200.0, 5000.0, 120.0),
+            summary(1_704_240_000.0, 12000.0, 175.0),
+        ];
+
+        let report = build_training_report(&summaries, ReportPeriod::Week);
+
+        assert_eq!(report.periods.len(), 1);
+        let intensity = &report.periods[0].intensity;
+        assert_eq!(intensity.easy_seconds, 1800.0);
+        assert_eq!(intensity.hard_seconds, 1800.0);
+
+        let longest = report
+            .personal_records
+            .iter()
+            .find(|record| record.metric == "longest_distance_meters")
+            .expect("distance record");
+        assert_eq!(longest.value, 12000.0);
+        assert_eq!(longest.achieved_on, NaiveDate::from_ymd_opt(2024, 1, 3));
+    }
+
+    #[test]
+    fn csv_has_header_and_one_row_per_period() {
+        let report = build_training_report(
+            &[summary(1_704_067_200.0, 5000.0, 150.0)],
+            ReportPeriod::Month,
+        );
+        let csv = report.to_csv();
+
+        assert_eq!(csv.lines().count(), 2);
+        assert!(
+            csv.lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("2024-01-01,1,5000.0")
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::processing::parse_fit_with_limits;
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::types::{FitProcessError, ProcessingLimits};
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataField, FitDataRecord, Value, encode_records};
+
+/// Default number of `Record` messages kept in an anonymized sample.
+pub const DEFAULT_SAMPLE_RECORDS: usize = 200;
+
+/// Controls how much of the original activity survives in a bug-report sample.
+#[derive(Debug, Clone)]
+pub struct SampleOptions {
+    /// Maximum number of `Record` messages to keep; other message types are always kept.
+    pub max_records: usize,
+}
+
+impl Default for SampleOptions {
+    fn default() -> Self {
+        Self {
+            max_records: DEFAULT_SAMPLE_RECORDS,
+        }
+    }
+}
+
+/// Decode a FIT payload within `limits`, anonymize it, and re-encode the
+/// reduced sample.
+pub fn extract_sample_bytes(
+    bytes: &[u8],
+    options: &SampleOptions,
+    limits: &ProcessingLimits,
+) -> Result<Vec<u8>, FitProcessError> {
+    let sample = anonymize_sample(&parse_fit_with_limits(bytes, limits)?, options);
+    encode_records(&sample).map_err(|err| FitProcessError::EncodeError(err.to_string()))
+}
+
+/// Produce a minimal reproduction that keeps the message layout of the original.
+///
+/// Positions in every message, including lap and session start, end, and
+/// bounding-box corners, are translated so the first fix sits at 0°/0°;
+/// serial numbers are blanked; and `Record` messages beyond `max_records`
+/// are dropped. Replaced fields lose their raw value too, so the encoder
+/// cannot write the original back. Every message keeps its fields so
+/// definition changes are preserved.
+pub fn anonymize_sample(records: &[FitDataRecord], options: &SampleOptions) -> Vec<FitDataRecord> {
+    let origin = first_position(records);
+    let mut kept_records = 0;
+
+    records
+        .iter()
+        .filter(|record| {
+            if !matches!(record.kind(), MesgNum::Record) {
+                return true;
+            }
+            kept_records += 1;
+            kept_records <= options.max_records
+        })
+        .map(|record| {
+            let mut anonymized = FitDataRecord::new(record.kind());
+            for field in record.fields() {
+                let name = field.name();
+                let replacement = if name == "serial_number" {
+                    Some(Value::UInt32z(0))
+                } else if name.ends_with("_lat") || name.ends_with("_long") {
+                    let is_lat = name.ends_with("_lat");
+                    // Without a fix to translate against, the position becomes 0°.
+                    let translated = origin
+                        .zip(field_value_to_f64(field))
+                        .map(|((lat, long), value)| value - if is_lat { lat } else { long })
+                        .unwrap_or(0.0);
+                    Some(Value::SInt32(translated.round() as i32))
+                } else {
+                    None
+                };
+                match replacement {
+                    Some(value) => anonymized.push(with_raw_value(field, value)),
+                    None => anonymized.push(field.clone()),
+                }
+            }
+            anonymized
+        })
+        .collect()
+}
+
+/// `field` holding `value` as both its value and its raw value.
+fn with_raw_value(field: &FitDataField, value: Value) -> FitDataField {
+    FitDataField::with_meta(
+        field.name().to_string(),
+        field.number(),
+        field.developer_data_index(),
+        value.clone(),
+        value,
+        field.units().to_string(),
+        field.base_type(),
+        field.scale(),
+        field.offset(),
+        field.timestamp_kind(),
+    )
+}
+
+fn first_position(records: &[FitDataRecord]) -> Option<(f64, f64)> {
+    records.iter().find_map(|record| {
+        let mut lat = None;
+        let mut long = None;
+        for field in record.fields() {
+            match field.name() {
+                "position_lat" => lat = field_value_to_f64(field),
+                "position_long" => long = field_value_to_f64(field),
+                _ => {}
+            }
+        }
+        lat.zip(long)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+
+    #[test]
+    fn no_original_coordinate_survives() {
+        let coordinates = [500_000_000, 100_000_000, 500_001_000, 100_002_000];
+        let (lat, long) = (Value::SInt32(coordinates[2]), Value::SInt32(coordinates[3]));
+        let lap = [
+            ("start_position_lat", lat.clone()),
+            ("start_position_long", long.clone()),
+            ("end_position_lat", lat.clone()),
+            ("end_position_long", long.clone()),
+            ("nec_lat", lat.clone()),
+            ("nec_long", long.clone()),
+            ("swc_lat", lat),
+            ("swc_long", long),
+        ];
+        let records = vec![
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("position_lat", Value::SInt32(coordinates[0])),
+                    ("position_long", Value::SInt32(coordinates[1])),
+                ],
+            ),
+            record_with(MesgNum::Lap, &lap),
+            record_with(MesgNum::Session, &lap),
+        ];
+
+        let sample = anonymize_sample(&records, &SampleOptions::default());
+
+        for field in sample.iter().flat_map(|record| record.fields()) {
+            for value in [field.value(), field.raw_value()] {
+                assert!(
+                    !coordinates.iter().any(|&c| *value == Value::SInt32(c)),
+                    "{} kept {value:?}",
+                    field.name()
+                );
+            }
+        }
+        assert_eq!(sample[1].fields()[0].value(), &Value::SInt32(1_000));
+    }
+
+    #[test]
+    fn truncates_only_record_messages() {
+        let mut records = vec![FitDataRecord::new(MesgNum::FileId)];
+        records.extend((0..5).map(|_| FitDataRecord::new(MesgNum::Record)));
+        records.push(FitDataRecord::new(MesgNum::Session));
+
+        let sample = anonymize_sample(&records, &SampleOptions { max_records: 2 });
+
+        let kinds: Vec<MesgNum> = sample.iter().map(|record| record.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                MesgNum::FileId,
+                MesgNum::Record,
+                MesgNum::Record,
+                MesgNum::Session
+            ]
+        );
+    }
+}
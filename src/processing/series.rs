@@ -0,0 +1,100 @@
+//! Speed, heart rate, and elevation over time, thinned for charting.
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::record_timestamp;
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+
+/// Most points kept in each [`ChartSeries`] series.
+pub const MAX_CHART_POINTS: usize = 600;
+
+/// Per-record values of a processed activity, each downsampled to
+/// [`MAX_CHART_POINTS`]; a series is empty when no record carries its field.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChartSeries {
+    /// Speed in km/h.
+    pub speed: Vec<ChartPoint>,
+    /// Heart rate in bpm.
+    pub heart_rate: Vec<ChartPoint>,
+    /// Altitude in metres.
+    pub elevation: Vec<ChartPoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartPoint {
+    /// Seconds since the first timestamped record.
+    pub elapsed_seconds: f64,
+    pub value: f64,
+}
+
+/// Collect the [`ChartSeries`] of timestamped `Record` messages, preferring
+/// the enhanced speed and altitude fields.
+pub fn chart_series(records: &[FitDataRecord]) -> ChartSeries {
+    let samples: Vec<(f64, &FitDataRecord)> = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .filter_map(|record| Some((record_timestamp(record)?, record)))
+        .collect();
+    let Some(&(start, _)) = samples.first() else {
+        return ChartSeries::default();
+    };
+    let series = |names: &[&str], scale: f64| {
+        let points: Vec<ChartPoint> = samples
+            .iter()
+            .filter_map(|&(timestamp, record)| {
+                let value = names.iter().find_map(|name| {
+                    record
+                        .fields()
+                        .iter()
+                        .find(|field| field.name() == *name)
+                        .and_then(field_value_to_f64)
+                })?;
+                value.is_finite().then_some(ChartPoint {
+                    elapsed_seconds: timestamp - start,
+                    value: value * scale,
+                })
+            })
+            .collect();
+        let stride = points.len().div_ceil(MAX_CHART_POINTS).max(1);
+        points.into_iter().step_by(stride).collect()
+    };
+    ChartSeries {
+        speed: series(&["enhanced_speed", "speed"], 3.6),
+        heart_rate: series(&["heart_rate"], 1.0),
+        elevation: series(&["enhanced_altitude", "altitude"], 1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+    use fitparser::Value;
+
+    #[test]
+    fn series_skip_records_without_the_field() {
+        let records: Vec<FitDataRecord> = (0..1_500)
+            .map(|second| {
+                let mut fields = vec![
+                    (
+                        "timestamp",
+                        timestamp_value(1_000.0 + second as f64).unwrap(),
+                    ),
+                    ("enhanced_speed", Value::Float64(5.0)),
+                ];
+                if second % 2 == 0 {
+                    fields.push(("heart_rate", Value::UInt8(140)));
+                }
+                record_with(MesgNum::Record, &fields)
+            })
+            .collect();
+
+        let series = chart_series(&records);
+        assert_eq!(series.speed.len(), 500);
+        assert_eq!(series.speed[1].elapsed_seconds, 3.0);
+        assert_eq!(series.speed[1].value, 18.0);
+        assert_eq!(series.heart_rate.len(), 375);
+        assert!(series.elevation.is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+//! Runs and lift rides of alpine skiing and snowboarding activities.
+//!
+//! A ski day alternates between riding a lift up and skiing down, so speed
+//! and pace averaged over the whole file mean little. The altitude trace is
+//! split at its turning points instead: climbs of at least
+//! [`MIN_VERTICAL_METERS`] are lift rides, and equally deep descents are runs,
+//! each summarised on its own.
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::types::MOVING_SPEED_THRESHOLD;
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use serde::Serialize;
+
+/// `sport` values whose activities are split into runs.
+pub const SNOW_SPORTS: &[&str] = &["alpine_skiing", "snowboarding"];
+
+/// Altitude must move this far against the current direction before a
+/// turning point counts, so GPS and barometer noise cannot split a run.
+pub const REVERSAL_METERS: f64 = 10.0;
+
+/// Smallest vertical a climb or descent needs to count as a lift ride or run.
+pub const MIN_VERTICAL_METERS: f64 = 30.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkiRuns {
+    pub runs: Vec<SkiRun>,
+    pub lift_rides: usize,
+    /// Time spent riding lifts.
+    pub lift_seconds: f64,
+    /// Altitude lost over all runs.
+    pub total_vertical_meters: f64,
+    /// Fastest speed reached on any run.
+    pub max_speed: Option<f64>,
+}
+
+/// One descent, from the first moving sample after the top to the last
+/// moving sample before the bottom.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkiRun {
+    /// Seconds since the Unix epoch.
+    pub start_timestamp: f64,
+    pub duration_seconds: f64,
+    pub vertical_meters: f64,
+    /// `None` when the records carry no distance.
+    pub distance_meters: Option<f64>,
+    pub max_speed: Option<f64>,
+    pub mean_speed: Option<f64>,
+}
+
+struct Sample {
+    timestamp: f64,
+    altitude: f64,
+    distance: Option<f64>,
+    speed: Option<f64>,
+}
+
+/// Split a snow-sport activity into runs; `None` for other sports or when
+/// the records carry no altitude.
+pub fn analyze_ski_runs(records: &[FitDataRecord]) -> Option<SkiRuns> {
+    if !is_snow_sport(records) {
+        return None;
+    }
+    let samples: Vec<Sample> = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .filter_map(|record| {
+            let value = |name: &str| {
+                record
+                    .fields()
+                    .iter()
+                    .find(|field| field.name() == name)
+                    .and_then(field_value_to_f64)
+            };
+            Some(Sample {
+                timestamp: value("timestamp")?,
+                altitude: value("enhanced_altitude").or_else(|| value("altitude"))?,
+                distance: value("distance"),
+                speed: value("enhanced_speed").or_else(|| value("speed")),
+            })
+        })
+        .collect();
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let mut analysis = SkiRuns {
+        runs: Vec::new(),
+        lift_rides: 0,
+        lift_seconds: 0.0,
+        total_vertical_meters: 0.0,
+        max_speed: None,
+    };
+    let turns = turning_points(&samples);
+    for pair in turns.windows(2) {
+        let leg = &samples[pair[0]..=pair[1]];
+        let (top, bottom) = (&leg[0], &leg[leg.len() - 1]);
+        let vertical = top.altitude - bottom.altitude;
+        if -vertical >= MIN_VERTICAL_METERS {
+            analysis.lift_rides += 1;
+            analysis.lift_seconds += bottom.timestamp - top.timestamp;
+        } else if vertical >= MIN_VERTICAL_METERS {
+            let run = summarize_run(leg, vertical);
+            analysis.total_vertical_meters += vertical;
+            analysis.max_speed = match (analysis.max_speed, run.max_speed) {
+                (Some(fastest), Some(speed)) => Some(fastest.max(speed)),
+                (fastest, speed) => fastest.or(speed),
+            };
+            analysis.runs.push(run);
+        }
+    }
+    Some(analysis)
+}
+
+fn is_snow_sport(records: &[FitDataRecord]) -> bool {
+    records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Session | MesgNum::Sport))
+        .flat_map(|record| record.fields())
+        .filter(|field| field.name() == "sport")
+        .any(|field| SNOW_SPORTS.contains(&field.to_string().as_str()))
+}
+
+/// Indexes of the first sample, each confirmed highest or lowest point, and
+/// the last sample.
+fn turning_points(samples: &[Sample]) -> Vec<usize> {
+    let mut turns = vec![0];
+    let mut extreme = 0;
+    // Unknown until altitude first moves by a full reversal.
+    let mut descending: Option<bool> = None;
+    for (index, sample) in samples.iter().enumerate().skip(1) {
+        let change = sample.altitude - samples[extreme].altitude;
+        match descending {
+            Some(true) if change < 0.0 => extreme = index,
+            Some(false) if change > 0.0 => extreme = index,
+            Some(true) if change >= REVERSAL_METERS => {
+                turns.push(extreme);
+                (extreme, descending) = (index, Some(false));
+            }
+            Some(false) if change <= -REVERSAL_METERS => {
+                turns.push(extreme);
+                (extreme, descending) = (index, Some(true));
+            }
+            None if change.abs() >= REVERSAL_METERS => {
+                (extreme, descending) = (index, Some(change < 0.0));
+            }
+            _ => {}
+        }
+    }
+    turns.push(samples.len() - 1);
+    turns.dedup();
+    turns
+}
+
+/// Summarise a descent, leaving out the standing around at either end.
+fn summarize_run(leg: &[Sample], vertical_meters: f64) -> SkiRun {
+    let moving = |sample: &Sample| {
+        sample
+            .speed
+            .is_none_or(|speed| speed >= MOVING_SPEED_THRESHOLD)
+    };
+    let top = leg
+        .iter()
+        .rposition(|sample| sample.altitude >= leg[0].altitude)
+        .unwrap_or(0);
+    let first = leg[top..]
+        .iter()
+        .position(moving)
+        .map_or(top, |offset| top + offset);
+    let last = leg
+        .iter()
+        .rposition(moving)
+        .unwrap_or(leg.len() - 1)
+        .max(first);
+    let (start, end) = (&leg[first], &leg[last]);
+    let duration_seconds = end.timestamp - start.timestamp;
+    let distance_meters = end
+        .distance
+        .zip(start.distance)
+        .map(|(end, start)| (end - start).max(0.0));
+    SkiRun {
+        start_timestamp: start.timestamp,
+        duration_seconds,
+        vertical_meters,
+        distance_meters,
+        max_speed: leg[first..=last]
+            .iter()
+            .filter_map(|sample| sample.speed)
+            .reduce(f64::max),
+        mean_speed: distance_meters
+            .filter(|_| duration_seconds > 0.0)
+            .map(|distance| distance / duration_seconds),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+    use fitparser::Value;
+
+    /// Records every 10 s through `legs` of `(seconds, altitude change,
+    /// speed)`, starting at 1,500 m and 0 m.
+    fn ski_day(sport: &str, legs: &[(u32, f64, f64)]) -> Vec<FitDataRecord> {
+        let mut records = vec![record_with(
+            MesgNum::Session,
+            &[("sport", Value::String(sport.to_string()))],
+        )];
+        let (mut second, mut altitude, mut distance) = (0.0, 1_500.0, 0.0);
+        for &(seconds, climb, speed) in legs {
+            let steps = seconds / 10;
+            for _ in 0..steps {
+                second += 10.0;
+                altitude += climb / f64::from(steps);
+                distance += speed * 10.0;
+                records.push(record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", timestamp_value(1_000_000.0 + second).unwrap()),
+                        ("enhanced_altitude", Value::Float64(altitude)),
+                        ("distance", Value::Float64(distance)),
+                        ("enhanced_speed", Value::Float64(speed)),
+                    ],
+                ));
+            }
+        }
+        records
+    }
+
+    #[test]
+    fn splits_a_ski_day_into_lift_rides_and_runs() {
+        let records = ski_day(
+            "alpine_skiing",
+            &[
+                (400, 300.0, 2.0),   // chairlift
+                (60, 0.0, 0.0),      // standing at the top
+                (120, -300.0, 12.0), // run
+                (300, 250.0, 2.5),   // gondola
+                (60, -5.0, 1.0),     // shuffling to the piste
+                (100, -250.0, 15.0), // run
+                (50, 20.0, 1.0),     // a last lift ride, cut short
+            ],
+        );
+
+        let analysis = analyze_ski_runs(&records).unwrap();
+
+        assert_eq!(analysis.lift_rides, 2);
+        assert_eq!(analysis.lift_seconds, 690.0);
+        assert_eq!(analysis.runs.len(), 2);
+        let first = &analysis.runs[0];
+        assert_eq!(first.vertical_meters, 300.0);
+        assert_eq!(first.duration_seconds, 110.0);
+        assert_eq!(first.max_speed, Some(12.0));
+        assert_eq!(analysis.max_speed, Some(15.0));
+        assert!((analysis.total_vertical_meters - 555.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn other_sports_have_no_runs() {
+        let records = ski_day("hiking", &[(400, 300.0, 1.0), (400, -300.0, 1.0)]);
+
+        assert_eq!(analyze_ski_runs(&records), None);
+    }
+}
@@ -0,0 +1,337 @@
+use crate::processing::types::FitProcessError;
+use fitparser::profile::MesgNum;
+use std::ops::Range;
+
+/// Location of one data message within the original FIT bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordSpan {
+    pub kind: MesgNum,
+    /// Byte range of the message, including its record header.
+    pub byte_range: Range<usize>,
+    /// Local message type the message was written with.
+    pub local_id: u8,
+}
+
+/// Trailing CRC after each file's data section.
+const CRC_SIZE: usize = 2;
+
+/// Field layout registered by a definition message for one local type.
+#[derive(Clone, Copy)]
+struct LocalDefinition {
+    kind: MesgNum,
+    data_size: usize,
+}
+
+/// Walk the FIT framing and return the span of every data message, in file order.
+///
+/// Only record headers and definition messages are interpreted; field values
+/// are left to `fitparser`. Chained files are scanned one after another, each
+/// starting with no local definitions, and byte ranges are offsets into the
+/// whole of `bytes`.
+pub fn scan_record_spans(bytes: &[u8]) -> Result<Vec<RecordSpan>, FitProcessError> {
+    scan_record_spans_with_capacity(bytes, 0)
+}
+
+/// [`scan_record_spans`] for callers that already know about how many data
+/// messages `bytes` holds, e.g. from a message-type histogram of the decoded
+/// records, so the spans are allocated once.
+pub fn scan_record_spans_with_capacity(
+    bytes: &[u8],
+    capacity: usize,
+) -> Result<Vec<RecordSpan>, FitProcessError> {
+    let mut scanner = SpanScanner {
+        definitions: [None; 16],
+        spans: Vec::with_capacity(capacity),
+    };
+    let mut file_start = 0;
+    loop {
+        let data_end = scanner.scan_file(bytes, file_start)?;
+        // Another file follows when a full header with the `.FIT` signature
+        // starts after this file's CRC.
+        file_start = data_end + CRC_SIZE;
+        if bytes.get(file_start + 8..file_start + 12) != Some(b".FIT".as_slice()) {
+            return Ok(scanner.spans);
+        }
+    }
+}
+
+/// Scan state shared by the files of a chain: one definition table, cleared
+/// at each file header, and the spans found so far.
+struct SpanScanner {
+    /// Protocol 2.0 allows all 16 local types in normal headers; compressed
+    /// timestamp headers can only reach the first four.
+    definitions: [Option<LocalDefinition>; 16],
+    spans: Vec<RecordSpan>,
+}
+
+impl SpanScanner {
+    /// Scan the file whose header starts at `file_start`, returning where its
+    /// data section ends.
+    fn scan_file(&mut self, bytes: &[u8], file_start: usize) -> Result<usize, FitProcessError> {
+        let header_size = check_header(bytes, file_start)?;
+        let file = &bytes[file_start..];
+        let data_size = u32::from_le_bytes([file[4], file[5], file[6], file[7]]) as usize;
+        let data_start = file_start + header_size;
+        let data_end = data_start
+            .checked_add(data_size)
+            .filter(|&end| end <= bytes.len())
+            .ok_or_else(|| span_error(data_start, "data section runs past end of file"))?;
+
+        // Definitions never carry over from the previous file.
+        self.definitions = [None; 16];
+        let mut offset = data_start;
+
+        while offset < data_end {
+            let start = offset;
+            let header = bytes[offset];
+            offset += 1;
+
+            if header & 0x80 != 0 {
+                // Compressed timestamp header: bits 5-6 carry the local type.
+                let local_id = (header >> 5) & 0x03;
+                offset = self.push_data_span(start, offset, local_id, data_end)?;
+                continue;
+            }
+
+            // Bit 4 is reserved, and bit 5 only means "developer data" on
+            // definition messages; neither changes the layout of a data message.
+            let local_id = header & 0x0F;
+            if header & 0x40 != 0 {
+                let has_developer_fields = header & 0x20 != 0;
+                let (definition, next) =
+                    read_definition(bytes, offset, data_end, has_developer_fields)?;
+                self.definitions[local_id as usize] = Some(definition);
+                offset = next;
+            } else {
+                offset = self.push_data_span(start, offset, local_id, data_end)?;
+            }
+        }
+
+        Ok(data_end)
+    }
+
+    fn push_data_span(
+        &mut self,
+        start: usize,
+        offset: usize,
+        local_id: u8,
+        data_end: usize,
+    ) -> Result<usize, FitProcessError> {
+        let definition = self.definitions[local_id as usize]
+            .ok_or_else(|| span_error(start, "data message uses an undefined local type"))?;
+        let end = offset + definition.data_size;
+        if end > data_end {
+            return Err(span_error(
+                start,
+                "data message runs past end of data section",
+            ));
+        }
+        self.spans.push(RecordSpan {
+            kind: definition.kind,
+            byte_range: start..end,
+            local_id,
+        });
+        Ok(end)
+    }
+}
+
+/// Check the header of the file starting at `file_start`: its size, the
+/// `.FIT` signature, and a protocol version this scanner understands.
+/// Returns the header size.
+pub fn check_header(bytes: &[u8], file_start: usize) -> Result<usize, FitProcessError> {
+    let invalid =
+        |message: &str| FitProcessError::InvalidHeader(format!("{message} at byte {file_start}"));
+    let file = bytes.get(file_start..).unwrap_or_default();
+    let header_size = usize::from(*file.first().ok_or_else(|| invalid("missing file header"))?);
+    if header_size < 12 || file.len() < header_size {
+        return Err(invalid("truncated file header"));
+    }
+    if &file[8..12] != b".FIT" {
+        return Err(invalid("missing .FIT signature"));
+    }
+    let (major, minor) = (file[1] >> 4, file[1] & 0x0F);
+    if major > 2 {
+        return Err(FitProcessError::UnsupportedFeature(format!(
+            "protocol version {major}.{minor}"
+        )));
+    }
+    Ok(header_size)
+}
+
+fn read_definition(
+    bytes: &[u8],
+    offset: usize,
+    data_end: usize,
+    has_developer_fields: bool,
+) -> Result<(LocalDefinition, usize), FitProcessError> {
+    let fixed = bytes
+        .get(offset..offset + 5)
+        .filter(|_| offset + 5 <= data_end)
+        .ok_or_else(|| span_error(offset, "truncated definition message"))?;
+    let global = match fixed[1] {
+        0 => u16::from_le_bytes([fixed[2], fixed[3]]),
+        1 => u16::from_be_bytes([fixed[2], fixed[3]]),
+        _ => {
+            return Err(span_error(
+                offset + 1,
+                "unknown architecture in definition message",
+            ));
+        }
+    };
+    let mut cursor = offset + 5;
+    let mut data_size = field_sizes(bytes, cursor, fixed[4] as usize, data_end)?;
+    cursor += fixed[4] as usize * 3;
+
+    if has_developer_fields {
+        let count = *bytes
+            .get(cursor)
+            .filter(|_| cursor < data_end)
+            .ok_or_else(|| span_error(cursor, "truncated developer field definitions"))?
+            as usize;
+        cursor += 1;
+        data_size += field_sizes(bytes, cursor, count, data_end)?;
+        cursor += count * 3;
+    }
+
+    Ok((
+        LocalDefinition {
+            kind: MesgNum::from_u16(global),
+            data_size,
+        },
+        cursor,
+    ))
+}
+
+/// Sum the size byte of `count` three-byte field definitions starting at `offset`.
+fn field_sizes(
+    bytes: &[u8],
+    offset: usize,
+    count: usize,
+    data_end: usize,
+) -> Result<usize, FitProcessError> {
+    let end = offset + count * 3;
+    let fields = bytes
+        .get(offset..end)
+        .filter(|_| end <= data_end)
+        .ok_or_else(|| span_error(offset, "truncated field definitions"))?;
+    Ok(fields.chunks_exact(3).map(|field| field[1] as usize).sum())
+}
+
+fn span_error(offset: usize, message: &str) -> FitProcessError {
+    FitProcessError::ParseError(format!("{message} at byte {offset}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 12-byte header followed by `data`, without a trailing CRC.
+    fn fit_file(data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![12, 0x10, 0, 0];
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(b".FIT");
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn spans_cover_normal_and_compressed_data_messages() {
+        // Local type 1: little endian, global 20, two fields of 4 and 1 bytes.
+        let definition = [0x41, 0, 0, 20, 0, 2, 253, 4, 0x86, 3, 1, 2];
+        let normal = [0x01, 1, 2, 3, 4, 5];
+        // Compressed timestamp header for local type 1.
+        let compressed = [0xA3, 1, 2, 3, 4, 5];
+        let bytes = fit_file(&[&definition[..], &normal, &compressed].concat());
+
+        let spans = scan_record_spans(&bytes).expect("spans should scan");
+
+        let ranges: Vec<(Range<usize>, u8)> = spans
+            .iter()
+            .map(|span| (span.byte_range.clone(), span.local_id))
+            .collect();
+        assert_eq!(ranges, vec![(24..30, 1), (30..36, 1)]);
+        assert_eq!(spans[0].kind, MesgNum::from_u16(20));
+    }
+
+    #[test]
+    fn protocol_two_definitions_set_data_sizes() {
+        // Local type 15, big endian, global 20: one 2-byte field plus two
+        // developer fields of 4 and 1 bytes.
+        let definition = [0x6F, 0, 1, 0, 20, 1, 7, 2, 0x84, 2, 0, 4, 0, 1, 1, 0];
+        // Reserved bit 4 set on the data header; the layout is unchanged.
+        let data = [0x1F, 0, 200, 1, 2, 3, 4, 9];
+        let bytes = fit_file(&[&definition[..], &data].concat());
+
+        let spans = scan_record_spans(&bytes).expect("spans should scan");
+
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].byte_range, 28..36);
+        assert_eq!(spans[0].local_id, 15);
+        assert_eq!(spans[0].kind, MesgNum::from_u16(20));
+    }
+
+    #[test]
+    fn chained_files_are_scanned_with_fresh_definitions() {
+        let definition = [0x40, 0, 0, 20, 0, 1, 3, 1, 2];
+        let data = [0x00, 7];
+        let file = [fit_file(&[&definition[..], &data].concat()), vec![0, 0]].concat();
+        let chained = [file.clone(), file].concat();
+
+        let spans = scan_record_spans(&chained).expect("spans should scan");
+
+        let ranges: Vec<Range<usize>> = spans.iter().map(|span| span.byte_range.clone()).collect();
+        assert_eq!(ranges, vec![21..23, 46..48]);
+        assert_eq!(scan_record_spans_with_capacity(&chained, 2).unwrap(), spans);
+
+        // A data message may not reuse a definition from the previous file.
+        let orphan = [
+            fit_file(&[&definition[..], &data].concat()),
+            vec![0, 0],
+            fit_file(&data),
+        ]
+        .concat();
+        assert!(scan_record_spans(&orphan).is_err());
+    }
+
+    #[test]
+    fn headers_are_checked_before_the_data() {
+        let mut bytes = fit_file(&[]);
+        bytes[9] = b'X';
+        assert!(matches!(
+            scan_record_spans(&bytes),
+            Err(FitProcessError::InvalidHeader(msg)) if msg == "missing .FIT signature at byte 0"
+        ));
+
+        let mut bytes = fit_file(&[]);
+        bytes[1] = 0x30;
+        assert!(matches!(
+            check_header(&bytes, 0),
+            Err(FitProcessError::UnsupportedFeature(msg)) if msg == "protocol version 3.0"
+        ));
+        assert!(matches!(
+            check_header(&[12, 0x10], 0),
+            Err(FitProcessError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_architecture_is_rejected() {
+        let bytes = fit_file(&[0x40, 0, 2, 20, 0, 0]);
+
+        let err = scan_record_spans(&bytes).expect_err("architecture 2 is undefined");
+
+        assert!(
+            err.to_string()
+                .ends_with("unknown architecture in definition message at byte 14")
+        );
+    }
+
+    #[test]
+    fn undefined_local_type_reports_offset() {
+        let bytes = fit_file(&[0x02, 0]);
+
+        let err = scan_record_spans(&bytes).expect_err("undefined local type");
+
+        assert!(err.to_string().contains("at byte 12"));
+    }
+}
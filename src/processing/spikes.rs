@@ -0,0 +1,235 @@
+//! Speed spikes in the uploaded records and their likely cause.
+//!
+//! A spike is an interval whose speed, derived from the `distance` counter,
+//! is more than [`SPEED_SPIKE_MIN_DELTA`] above its moving average, the same
+//! test smoothing uses for its `speed_spikes_smoothed` warning. Each one is
+//! put down to a GPS jump when the recorded positions jumped as well, or to a
+//! distance reset when the counter went backwards next to it or ran ahead of
+//! positions that barely moved. GPS jumps are what smoothing is for; a reset
+//! leaves a step in the distance that smoothing only spreads out, so trimming
+//! the affected records is usually the better fix.
+
+use crate::processing::anonymize::{distance_meters, record_position};
+use crate::processing::preprocess::smoothed_speed_series;
+use crate::processing::types::{SPEED_SMOOTHING_WINDOW, SPEED_SPIKE_MIN_DELTA};
+use fitparser::FitDataRecord;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpikeCause {
+    /// The positions jumped along with the distance.
+    GpsJump,
+    /// The distance counter went backwards nearby, or ran ahead of the
+    /// positions.
+    DistanceReset,
+    /// The records carry no positions to tell the two apart.
+    Unknown,
+}
+
+impl SpikeCause {
+    pub fn label(self) -> &'static str {
+        match self {
+            SpikeCause::GpsJump => "GPS jump",
+            SpikeCause::DistanceReset => "Distance reset",
+            SpikeCause::Unknown => "Unknown",
+        }
+    }
+}
+
+/// Most spikes listed for one file; a file with more keeps the largest, so a
+/// recording full of GPS noise does not turn into an endless table.
+pub const MAX_SPEED_SPIKES: usize = 200;
+
+/// One interval whose speed stands out from its neighbours.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpeedSpike {
+    /// End of the interval, in seconds since the Unix epoch.
+    pub timestamp: f64,
+    /// Speed the distance counter implies over the interval.
+    pub speed: f64,
+    /// Moving average of the surrounding intervals.
+    pub expected_speed: f64,
+    /// `speed - expected_speed`, in m/s.
+    pub magnitude: f64,
+    pub cause: SpikeCause,
+}
+
+/// The speed spikes in `records`, at most [`MAX_SPEED_SPIKES`] of the largest,
+/// in time order.
+pub fn detect_speed_spikes(records: &[FitDataRecord]) -> Vec<SpeedSpike> {
+    let Some(series) = smoothed_speed_series(records, SPEED_SMOOTHING_WINDOW) else {
+        return Vec::new();
+    };
+    let samples = &series.samples;
+    let went_backwards = |interval: usize| {
+        samples
+            .get(interval..=interval + 1)
+            .is_some_and(|pair| pair[1].distance < pair[0].distance)
+    };
+
+    let mut spikes: Vec<SpeedSpike> = series
+        .raw
+        .iter()
+        .zip(&series.smoothed)
+        .enumerate()
+        .filter(|&(_, (raw, smoothed))| raw - smoothed > SPEED_SPIKE_MIN_DELTA)
+        .map(|(interval, (&speed, &expected_speed))| {
+            let (start, end) = (&samples[interval], &samples[interval + 1]);
+            let positions = record_position(&records[start.record_index])
+                .zip(record_position(&records[end.record_index]));
+            let seconds = end.timestamp - start.timestamp;
+            let cause =
+                if (interval > 0 && went_backwards(interval - 1)) || went_backwards(interval + 1) {
+                    SpikeCause::DistanceReset
+                } else {
+                    match positions {
+                        Some((from, to))
+                            if seconds > 0.0
+                                && distance_meters(from, to) / seconds - expected_speed
+                                    > SPEED_SPIKE_MIN_DELTA =>
+                        {
+                            SpikeCause::GpsJump
+                        }
+                        Some(_) => SpikeCause::DistanceReset,
+                        None => SpikeCause::Unknown,
+                    }
+                };
+            SpeedSpike {
+                timestamp: end.timestamp,
+                speed,
+                expected_speed,
+                magnitude: speed - expected_speed,
+                cause,
+            }
+        })
+        .collect();
+    if spikes.len() > MAX_SPEED_SPIKES {
+        spikes.sort_by(|a, b| b.magnitude.total_cmp(&a.magnitude));
+        spikes.truncate(MAX_SPEED_SPIKES);
+        spikes.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    }
+    spikes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::anonymize::SEMICIRCLES_TO_DEGREES;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+    use fitparser::profile::MesgNum;
+
+    const STEP_DEGREES: f64 = 0.000027;
+
+    /// One record per second, heading north at about 3 m/s unless
+    /// `distance` and `latitude` (in degrees) say otherwise.
+    fn ride(
+        distance: impl Fn(usize) -> f64,
+        latitude: impl Fn(usize) -> f64,
+    ) -> Vec<FitDataRecord> {
+        (0..10)
+            .map(|second| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", Value::Float64(second as f64)),
+                        ("distance", Value::Float64(distance(second))),
+                        (
+                            "position_lat",
+                            Value::SInt32((latitude(second) / SEMICIRCLES_TO_DEGREES) as i32),
+                        ),
+                        ("position_long", Value::SInt32(0)),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    fn steady(second: usize) -> f64 {
+        second as f64 * 3.0
+    }
+
+    fn heading_north(second: usize) -> f64 {
+        45.0 + second as f64 * STEP_DEGREES
+    }
+
+    #[test]
+    fn jumping_positions_are_gps_jumps() {
+        // One fix lands 60 m ahead, adding 60 m out and 60 m back.
+        let records = ride(
+            |second| match second {
+                5 => steady(5) + 60.0,
+                6.. => steady(second) + 120.0,
+                _ => steady(second),
+            },
+            |second| match second {
+                5 => heading_north(5) + 20.0 * STEP_DEGREES,
+                _ => heading_north(second),
+            },
+        );
+
+        let spikes = detect_speed_spikes(&records);
+
+        assert!(!spikes.is_empty());
+        assert_eq!(spikes[0].timestamp, 5.0);
+        assert!(
+            spikes
+                .iter()
+                .all(|spike| spike.cause == SpikeCause::GpsJump)
+        );
+        assert!(spikes[0].magnitude > SPEED_SPIKE_MIN_DELTA);
+    }
+
+    #[test]
+    fn a_counter_that_falls_back_is_a_distance_reset() {
+        // The counter reads 0 for one sample, then picks up where it was.
+        let records = ride(
+            |second| if second == 5 { 0.0 } else { steady(second) },
+            heading_north,
+        );
+
+        let spikes = detect_speed_spikes(&records);
+
+        assert_eq!(spikes.len(), 1);
+        assert_eq!(spikes[0].timestamp, 6.0);
+        assert_eq!(spikes[0].cause, SpikeCause::DistanceReset);
+    }
+
+    #[test]
+    fn steady_records_have_no_spikes() {
+        assert!(detect_speed_spikes(&ride(steady, heading_north)).is_empty());
+    }
+
+    #[test]
+    fn only_the_largest_spikes_are_listed() {
+        // A spike every 10 s, each a little larger than the one before.
+        let mut distance = 0.0;
+        let records: Vec<FitDataRecord> = (0..3000)
+            .map(|second| {
+                distance += 3.0;
+                if second % 10 == 5 {
+                    distance += 20.0 + second as f64 * 0.01;
+                }
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", Value::Float64(second as f64)),
+                        ("distance", Value::Float64(distance)),
+                    ],
+                )
+            })
+            .collect();
+
+        let spikes = detect_speed_spikes(&records);
+
+        assert_eq!(spikes.len(), MAX_SPEED_SPIKES);
+        assert!(
+            spikes
+                .windows(2)
+                .all(|pair| pair[0].timestamp < pair[1].timestamp)
+        );
+        // Of the 300 spikes, the first 100 are the smallest.
+        assert_eq!(spikes[0].timestamp, 1005.0);
+    }
+}
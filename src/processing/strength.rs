@@ -0,0 +1,132 @@
+//! Sets of strength-training workouts, from the file's `Set` messages.
+//!
+//! Watches write one `Set` per active set and per rest between them, with
+//! the exercise category, repetitions, and weight. Only active sets are
+//! numbered, listed, and counted towards the totals.
+
+use crate::processing::summary::field_value_to_f64;
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataRecord, Value};
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StrengthSets {
+    pub sets: Vec<StrengthSet>,
+    pub total_repetitions: u32,
+    /// Sum of repetitions times weight over all sets, in kilograms.
+    pub volume_load_kg: f64,
+}
+
+/// One active set.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StrengthSet {
+    /// Position among the active sets, from 1; the number set corrections
+    /// refer to.
+    pub number: usize,
+    /// Seconds since the Unix epoch.
+    pub start_timestamp: Option<f64>,
+    pub duration_seconds: Option<f64>,
+    /// Exercise category as the file names it, e.g. `bench_press`.
+    pub category: Option<String>,
+    pub repetitions: Option<u32>,
+    pub weight_kg: Option<f64>,
+}
+
+impl StrengthSet {
+    /// Repetitions times weight; zero when either is missing.
+    pub fn volume_load_kg(&self) -> f64 {
+        f64::from(self.repetitions.unwrap_or(0)) * self.weight_kg.unwrap_or(0.0)
+    }
+}
+
+/// Whether a `Set` message is an active set rather than a rest. Sets
+/// without a `set_type` count as active.
+pub(crate) fn is_active_set(record: &FitDataRecord) -> bool {
+    matches!(record.kind(), MesgNum::Set)
+        && record
+            .fields()
+            .iter()
+            .find(|field| field.name() == "set_type")
+            .is_none_or(|field| field.to_string() != "rest")
+}
+
+/// The active sets of the file; `None` when it has no `Set` messages.
+pub fn analyze_strength_sets(records: &[FitDataRecord]) -> Option<StrengthSets> {
+    if !records
+        .iter()
+        .any(|record| matches!(record.kind(), MesgNum::Set))
+    {
+        return None;
+    }
+    let sets: Vec<StrengthSet> = records
+        .iter()
+        .filter(|record| is_active_set(record))
+        .enumerate()
+        .map(|(index, record)| {
+            let field = |name: &str| record.fields().iter().find(|field| field.name() == name);
+            let value = |name: &str| field(name).and_then(field_value_to_f64);
+            StrengthSet {
+                number: index + 1,
+                start_timestamp: value("start_time").or_else(|| value("timestamp")),
+                duration_seconds: value("duration"),
+                category: field("category").and_then(|field| category_name(field.value())),
+                repetitions: value("repetitions").map(|reps| reps.round() as u32),
+                weight_kg: value("weight"),
+            }
+        })
+        .collect();
+    Some(StrengthSets {
+        total_repetitions: sets.iter().filter_map(|set| set.repetitions).sum(),
+        volume_load_kg: sets.iter().map(StrengthSet::volume_load_kg).sum(),
+        sets,
+    })
+}
+
+/// The first named category; `category` is an array in the profile, as a
+/// set may train several.
+fn category_name(value: &Value) -> Option<String> {
+    let name = match value {
+        Value::Array(values) => values.first()?.to_string(),
+        value => value.to_string(),
+    };
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+
+    pub(crate) fn set(set_type: &str, reps: u16, weight: f64) -> FitDataRecord {
+        record_with(
+            MesgNum::Set,
+            &[
+                ("set_type", Value::String(set_type.to_string())),
+                (
+                    "category",
+                    Value::Array(vec![Value::String("bench_press".to_string())]),
+                ),
+                ("repetitions", Value::UInt16(reps)),
+                ("weight", Value::Float64(weight)),
+            ],
+        )
+    }
+
+    #[test]
+    fn totals_count_active_sets_only() {
+        let records = vec![
+            set("active", 10, 60.0),
+            set("rest", 0, 0.0),
+            set("active", 8, 62.5),
+        ];
+
+        let strength = analyze_strength_sets(&records).unwrap();
+
+        assert_eq!(strength.sets.len(), 2);
+        assert_eq!(strength.sets[1].number, 2);
+        assert_eq!(strength.sets[1].category.as_deref(), Some("bench_press"));
+        assert_eq!(strength.total_repetitions, 18);
+        assert_eq!(strength.volume_load_kg, 1_100.0);
+        assert_eq!(analyze_strength_sets(&[]), None);
+    }
+}
@@ -0,0 +1,298 @@
+//! Layout of an uploaded file: how many messages of each type it holds, how
+//! many bytes they take, and which developer fields it declares.
+
+use crate::processing::spans::scan_record_spans_with_capacity;
+use crate::processing::summary::field_value_to_f64;
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataField, FitDataRecord, Value};
+use serde::Serialize;
+
+/// Message counts and developer field declarations of one file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FileStructure {
+    /// One entry per message type, most frequent first.
+    pub messages: Vec<MessageTypeCount>,
+    /// Fields declared by `FieldDescription` messages.
+    pub developer_fields: Vec<DeveloperFieldDescriptor>,
+    /// Size of the whole file, once [`FileStructure::measure`] has run.
+    pub file_bytes: Option<usize>,
+    /// Bytes taken by data messages, including their record headers; the
+    /// rest is the file header, definition messages, and CRC.
+    pub data_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageTypeCount {
+    /// Profile name, or `unknown#<number>` for messages outside the profile.
+    pub message_type: String,
+    pub count: usize,
+    pub bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeveloperFieldDescriptor {
+    pub developer_data_index: Option<u8>,
+    pub field_definition_number: Option<u8>,
+    pub field_name: Option<String>,
+    pub units: Option<String>,
+    pub fit_base_type_id: Option<u8>,
+    /// Values of this field across all messages.
+    pub values: usize,
+}
+
+impl FileStructure {
+    /// Count the messages of decoded `records`; byte sizes are left unset.
+    pub fn from_records(records: &[FitDataRecord]) -> Self {
+        let mut messages: Vec<MessageTypeCount> = Vec::new();
+        let mut developer_fields = Vec::new();
+        for record in records {
+            let message_type = message_type_name(record.kind());
+            match messages
+                .iter_mut()
+                .find(|entry| entry.message_type == message_type)
+            {
+                Some(entry) => entry.count += 1,
+                None => messages.push(MessageTypeCount {
+                    message_type,
+                    count: 1,
+                    bytes: None,
+                }),
+            }
+            if matches!(record.kind(), MesgNum::FieldDescription) {
+                developer_fields.push(describe(record));
+            }
+        }
+        for descriptor in &mut developer_fields {
+            descriptor.values = records
+                .iter()
+                .flat_map(|record| record.fields())
+                .filter(|field| {
+                    field.developer_data_index().is_some()
+                        && field.developer_data_index() == descriptor.developer_data_index
+                        && Some(field.number()) == descriptor.field_definition_number
+                })
+                .count();
+        }
+        sort_by_count(&mut messages);
+        Self {
+            messages,
+            developer_fields,
+            file_bytes: None,
+            data_bytes: None,
+        }
+    }
+
+    /// Fill in byte sizes from the raw file. Sizes stay unset if the framing
+    /// can't be scanned.
+    pub fn measure(&mut self, bytes: &[u8]) {
+        // The histogram already counts every data message of the file.
+        let messages = self.messages.iter().map(|entry| entry.count).sum();
+        let Ok(spans) = scan_record_spans_with_capacity(bytes, messages) else {
+            return;
+        };
+        for span in &spans {
+            let size = span.byte_range.len();
+            let message_type = message_type_name(span.kind);
+            match self
+                .messages
+                .iter_mut()
+                .find(|entry| entry.message_type == message_type)
+            {
+                Some(entry) => *entry.bytes.get_or_insert(0) += size,
+                None => self.messages.push(MessageTypeCount {
+                    message_type,
+                    count: 0,
+                    bytes: Some(size),
+                }),
+            }
+        }
+        self.file_bytes = Some(bytes.len());
+        self.data_bytes = Some(spans.iter().map(|span| span.byte_range.len()).sum());
+        sort_by_count(&mut self.messages);
+    }
+}
+
+/// Fields that messages of one type carry, for picking fields to remove.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageFields {
+    pub message_type: String,
+    /// Messages of this type in the file.
+    pub count: usize,
+    /// In order of first appearance.
+    pub fields: Vec<PresentField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PresentField {
+    pub name: String,
+    pub number: u8,
+    /// Set for developer fields.
+    pub developer_data_index: Option<u8>,
+    /// Messages carrying the field.
+    pub count: usize,
+}
+
+/// The fields present in `records`, grouped by message type with the most
+/// frequent type first.
+pub fn present_fields(records: &[FitDataRecord]) -> Vec<MessageFields> {
+    let mut messages: Vec<MessageFields> = Vec::new();
+    for record in records {
+        let message_type = message_type_name(record.kind());
+        let index = match messages
+            .iter()
+            .position(|entry| entry.message_type == message_type)
+        {
+            Some(index) => index,
+            None => {
+                messages.push(MessageFields {
+                    message_type,
+                    count: 0,
+                    fields: Vec::new(),
+                });
+                messages.len() - 1
+            }
+        };
+        let entry = &mut messages[index];
+        entry.count += 1;
+        for field in record.fields() {
+            match entry
+                .fields
+                .iter_mut()
+                .find(|present| present.name == field.name())
+            {
+                Some(present) => present.count += 1,
+                None => entry.fields.push(PresentField {
+                    name: field.name().to_string(),
+                    number: field.number(),
+                    developer_data_index: field.developer_data_index(),
+                    count: 1,
+                }),
+            }
+        }
+    }
+    messages.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.message_type.cmp(&b.message_type))
+    });
+    messages
+}
+
+fn sort_by_count(messages: &mut [MessageTypeCount]) {
+    messages.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.message_type.cmp(&b.message_type))
+    });
+}
+
+/// Display name of a message type, keeping the number of unknown ones.
+pub fn message_type_name(kind: MesgNum) -> String {
+    match kind {
+        MesgNum::UnknownVariant(number) => format!("unknown#{number}"),
+        kind => format!("{kind:?}"),
+    }
+}
+
+fn describe(record: &FitDataRecord) -> DeveloperFieldDescriptor {
+    let find = |name: &str| record.fields().iter().find(|field| field.name() == name);
+    let number = |name: &str| find(name).and_then(field_value_to_f64).map(|v| v as u8);
+    let text = |name: &str| find(name).and_then(string_value);
+    DeveloperFieldDescriptor {
+        developer_data_index: number("developer_data_index"),
+        field_definition_number: number("field_definition_number"),
+        field_name: text("field_name"),
+        units: text("units"),
+        fit_base_type_id: number("fit_base_type_id"),
+        values: 0,
+    }
+}
+
+fn string_value(field: &FitDataField) -> Option<String> {
+    match field.value() {
+        Value::String(text) if !text.is_empty() => Some(text.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+
+    #[test]
+    fn counts_types_and_developer_fields() {
+        let mut record = record_with(MesgNum::Record, &[("heart_rate", Value::UInt8(150))]);
+        record.push(FitDataField::new(
+            "stryd_power".to_string(),
+            2,
+            Some(0),
+            Value::UInt16(250),
+            "W".to_string(),
+        ));
+        let records = vec![
+            FitDataRecord::new(MesgNum::FileId),
+            record_with(
+                MesgNum::FieldDescription,
+                &[
+                    ("developer_data_index", Value::UInt8(0)),
+                    ("field_definition_number", Value::UInt8(2)),
+                    ("fit_base_type_id", Value::UInt8(0x84)),
+                    ("field_name", Value::String("stryd_power".to_string())),
+                ],
+            ),
+            record.clone(),
+            record,
+            FitDataRecord::new(MesgNum::UnknownVariant(233)),
+        ];
+
+        let structure = FileStructure::from_records(&records);
+
+        let counts: Vec<(&str, usize)> = structure
+            .messages
+            .iter()
+            .map(|entry| (entry.message_type.as_str(), entry.count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("Record", 2),
+                ("FieldDescription", 1),
+                ("FileId", 1),
+                ("unknown#233", 1)
+            ]
+        );
+        assert_eq!(
+            structure.developer_fields[0].field_name.as_deref(),
+            Some("stryd_power")
+        );
+        assert_eq!(structure.developer_fields[0].values, 2);
+    }
+
+    #[test]
+    fn present_fields_are_grouped_by_message_type() {
+        let records = vec![
+            record_with(MesgNum::Lap, &[("total_distance", Value::Float64(1.0))]),
+            record_with(MesgNum::Record, &[("heart_rate", Value::UInt8(150))]),
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("heart_rate", Value::UInt8(151)),
+                    ("speed", Value::Float64(3.0)),
+                ],
+            ),
+        ];
+
+        let present = present_fields(&records);
+
+        assert_eq!(present[0].message_type, "Record");
+        assert_eq!(present[0].count, 2);
+        let fields: Vec<(&str, usize)> = present[0]
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.count))
+            .collect();
+        assert_eq!(fields, vec![("heart_rate", 2), ("speed", 1)]);
+        assert_eq!(present[1].message_type, "Lap");
+    }
+}
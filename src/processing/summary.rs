@@ -1,4 +1,17 @@
-use crate::processing::types::{DerivedWorkoutData, WorkoutSummary};
+use crate::processing::conditions::adjust_for_conditions;
+use crate::processing::display::activity_utc_offset;
+use crate::processing::ebike::summarize_ebike;
+use crate::processing::expressions::{DerivedFieldSummary, DerivedFieldTable};
+use crate::processing::metrics::{mean_max_curve, w_prime_balance};
+use crate::processing::ski::analyze_ski_runs;
+use crate::processing::strength::analyze_strength_sets;
+use crate::processing::terrain::analyze_terrain;
+use crate::processing::threshold::estimate_thresholds;
+use crate::processing::trainer::analyze_virtual_ride;
+use crate::processing::types::{
+    CyclingDynamics, DerivedWorkoutData, MOVING_SPEED_THRESHOLD, ProcessingOptions, WorkoutSummary,
+};
+use fitparser::profile::MesgNum;
 use fitparser::{FitDataField, FitDataRecord};
 use std::convert::TryInto;
 
@@ -10,15 +23,24 @@ pub(crate) struct DistanceSample {
 }
 
 /// Convert FIT fields into derived metrics and optional smoothed series.
-pub fn derive_workout_data(records: &[FitDataRecord]) -> DerivedWorkoutData {
+pub fn derive_workout_data(
+    records: &[FitDataRecord],
+    options: &ProcessingOptions,
+) -> DerivedWorkoutData {
     let mut timestamps: Vec<f64> = Vec::new();
+    let mut timer_events: Vec<(f64, bool)> = Vec::new();
     let mut workout_type: Option<String> = None;
     let mut distance_samples: Vec<DistanceSample> = Vec::new();
     let mut heart_rates: Vec<f64> = Vec::new();
+    let mut altitudes: Vec<f64> = Vec::new();
 
     for (idx, record) in records.iter().enumerate() {
         let mut timestamp: Option<f64> = None;
         let mut distance: Option<f64> = None;
+        let mut altitude: Option<f64> = None;
+        let mut enhanced_altitude: Option<f64> = None;
+        let mut event: Option<String> = None;
+        let mut event_type: Option<String> = None;
 
         for field in record.fields() {
             match field.name() {
@@ -38,6 +60,10 @@ pub fn derive_workout_data(records: &[FitDataRecord]) -> DerivedWorkoutData {
                         heart_rates.push(value);
                     }
                 }
+                "altitude" => altitude = field_value_to_f64(field),
+                "enhanced_altitude" => enhanced_altitude = field_value_to_f64(field),
+                "event" => event = Some(field.to_string()),
+                "event_type" => event_type = Some(field.to_string()),
                 "sport" | "workout_type" if workout_type.is_none() => {
                     let display = field.to_string();
                     if !display.is_empty() {
@@ -55,9 +81,26 @@ pub fn derive_workout_data(records: &[FitDataRecord]) -> DerivedWorkoutData {
                 distance: dist,
             });
         }
+
+        if let Some(value) = enhanced_altitude.or(altitude) {
+            altitudes.push(value);
+        }
+
+        if let (MesgNum::Event, Some("timer"), Some(ts), Some(kind)) = (
+            record.kind(),
+            event.as_deref(),
+            timestamp,
+            event_type.as_deref(),
+        ) {
+            timer_events.push((ts, kind == "start"));
+        }
     }
 
+    let start_timestamp = timestamps.iter().cloned().reduce(f64::min);
+    let end_timestamp = timestamps.iter().cloned().reduce(f64::max);
+    let utc_offset_seconds = activity_utc_offset(records).or_else(|| options.utc_offset_seconds());
     let duration_seconds = derive_duration(&timestamps);
+    let elevation_gain_meters = derive_elevation_gain(&altitudes);
     let time_intervals: Vec<f64> = distance_samples
         .windows(2)
         .map(|window| match window {
@@ -91,7 +134,19 @@ pub fn derive_workout_data(records: &[FitDataRecord]) -> DerivedWorkoutData {
         .collect();
     let speed_min = positive_speeds.iter().cloned().reduce(f64::min);
     let speed_max = positive_speeds.iter().cloned().reduce(f64::max);
-    let speed_mean = derive_speed_mean(&distance_samples, &distance_series, &speeds);
+    let moving_time_seconds =
+        derive_moving_time(&distance_samples, &speeds, &timer_pauses(&timer_events));
+    let pace_time = if options.pace_from_elapsed_time {
+        duration_seconds
+    } else {
+        moving_time_seconds
+    };
+    let speed_mean = match (distance_meters, distance_samples.first(), pace_time) {
+        (Some(last), Some(first), Some(time)) if time > 0.0 => {
+            Some((last - first.distance).max(0.0) / time)
+        }
+        _ => derive_speed_mean(&distance_samples, &distance_series, &speeds),
+    };
 
     let heart_rate_min = heart_rates.iter().cloned().reduce(f64::min);
     let heart_rate_max = heart_rates.iter().cloned().reduce(f64::max);
@@ -101,21 +156,118 @@ pub fn derive_workout_data(records: &[FitDataRecord]) -> DerivedWorkoutData {
         Some(heart_rates.iter().sum::<f64>() / heart_rates.len() as f64)
     };
 
+    let thresholds = estimate_thresholds(records);
+
     DerivedWorkoutData {
         summary: WorkoutSummary {
+            start_timestamp,
+            end_timestamp,
+            utc_offset_seconds,
             duration_seconds,
+            moving_time_seconds,
             workout_type,
             distance_meters,
+            elevation_gain_meters,
             speed_min,
             speed_mean,
             speed_max,
             heart_rate_min,
             heart_rate_mean,
             heart_rate_max,
+            cycling_dynamics: derive_cycling_dynamics(records),
+            w_prime_balance: options
+                .w_prime_model(thresholds.as_ref().and_then(|estimate| estimate.ftp_watts))
+                .and_then(|(critical_power, w_prime)| {
+                    w_prime_balance(records, critical_power, w_prime)
+                })
+                .map(|series| series.summary),
+            thresholds,
+            mean_max: mean_max_curve(records),
+            conditions: adjust_for_conditions(records, speed_mean),
+            derived_fields: derived_field_summaries(records, options),
+            terrain: analyze_terrain(records, options.classify_surface),
+            ski_runs: analyze_ski_runs(records),
+            strength_sets: analyze_strength_sets(records),
+            ebike: summarize_ebike(records),
+            virtual_ride: analyze_virtual_ride(records),
+            weather: None,
+            wind: None,
         },
     }
 }
 
+/// Summaries of the user's derived fields; definitions that fail to parse
+/// were already rejected with the upload.
+fn derived_field_summaries(
+    records: &[FitDataRecord],
+    options: &ProcessingOptions,
+) -> Vec<DerivedFieldSummary> {
+    let fields = options.derived_field_definitions().unwrap_or_default();
+    if fields.is_empty() {
+        return Vec::new();
+    }
+    DerivedFieldTable::evaluate(&fields, records).summaries(&fields)
+}
+
+/// Average the pedalling fields of `Record` messages.
+fn derive_cycling_dynamics(records: &[FitDataRecord]) -> Option<CyclingDynamics> {
+    let mut left_balance = Vec::new();
+    let mut left_torque = Vec::new();
+    let mut right_torque = Vec::new();
+    let mut left_smoothness = Vec::new();
+    let mut right_smoothness = Vec::new();
+    let mut combined_smoothness = Vec::new();
+
+    for record in records {
+        if !matches!(record.kind(), MesgNum::Record) {
+            continue;
+        }
+        for field in record.fields() {
+            let series = match field.name() {
+                "left_right_balance" => {
+                    if let Some(left) = field_value_to_f64(field).and_then(left_balance_percent) {
+                        left_balance.push(left);
+                    }
+                    continue;
+                }
+                "left_torque_effectiveness" => &mut left_torque,
+                "right_torque_effectiveness" => &mut right_torque,
+                "left_pedal_smoothness" => &mut left_smoothness,
+                "right_pedal_smoothness" => &mut right_smoothness,
+                "combined_pedal_smoothness" => &mut combined_smoothness,
+                _ => continue,
+            };
+            if let Some(value) = field_value_to_f64(field) {
+                series.push(value);
+            }
+        }
+    }
+
+    let mean = |values: &[f64]| {
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    };
+    let dynamics = CyclingDynamics {
+        left_balance: mean(&left_balance),
+        left_torque_effectiveness: mean(&left_torque),
+        right_torque_effectiveness: mean(&right_torque),
+        left_pedal_smoothness: mean(&left_smoothness),
+        right_pedal_smoothness: mean(&right_smoothness),
+        combined_pedal_smoothness: mean(&combined_smoothness),
+    };
+    (dynamics != CyclingDynamics::default()).then_some(dynamics)
+}
+
+/// Left leg's share from a record `left_right_balance` byte.
+///
+/// The low seven bits are a percentage and the high bit says it belongs to
+/// the right leg; without that bit the side is unknown and the sample is
+/// skipped.
+fn left_balance_percent(raw: f64) -> Option<f64> {
+    let raw = raw as u8;
+    let percent = f64::from(raw & 0x7F);
+    (raw & 0x80 != 0 && percent <= 100.0).then_some(100.0 - percent)
+}
+
 fn derive_duration(timestamps: &[f64]) -> Option<f64> {
     if timestamps.is_empty() {
         return None;
@@ -132,6 +284,86 @@ fn derive_duration(timestamps: &[f64]) -> Option<f64> {
     }
 }
 
+/// Altitude changes smaller than this are treated as altimeter noise when
+/// summing climbing.
+pub const CLIMB_HYSTERESIS_METERS: f64 = 3.0;
+
+/// Sum the climbing between consecutive samples, ignoring noise.
+fn derive_elevation_gain(altitudes: &[f64]) -> Option<f64> {
+    if altitudes.len() < 2 {
+        return None;
+    }
+    Some(ascent_and_descent(altitudes).0)
+}
+
+/// Total rise and fall over `altitudes`.
+///
+/// A change only counts once the altitude has moved at least
+/// [`CLIMB_HYSTERESIS_METERS`] from the last counted level, so barometric
+/// jitter does not add up the way summing every delta would.
+pub(crate) fn ascent_and_descent(altitudes: &[f64]) -> (f64, f64) {
+    let Some((&first, rest)) = altitudes.split_first() else {
+        return (0.0, 0.0);
+    };
+    let (mut ascent, mut descent, mut level) = (0.0, 0.0, first);
+    for &altitude in rest {
+        let change = altitude - level;
+        if change >= CLIMB_HYSTERESIS_METERS {
+            ascent += change;
+            level = altitude;
+        } else if change <= -CLIMB_HYSTERESIS_METERS {
+            descent -= change;
+            level = altitude;
+        }
+    }
+    (ascent, descent)
+}
+
+/// Turn timer start/stop events into `(stopped_at, restarted_at)` windows.
+fn timer_pauses(timer_events: &[(f64, bool)]) -> Vec<(f64, f64)> {
+    let mut pauses = Vec::new();
+    let mut stopped_at: Option<f64> = None;
+
+    for &(timestamp, is_start) in timer_events {
+        match (is_start, stopped_at) {
+            (false, None) => stopped_at = Some(timestamp),
+            (true, Some(stop)) => {
+                pauses.push((stop, timestamp));
+                stopped_at = None;
+            }
+            _ => {}
+        }
+    }
+
+    pauses
+}
+
+/// Sum the sample intervals where the athlete was moving and the timer was running.
+fn derive_moving_time(
+    distance_samples: &[DistanceSample],
+    speeds: &[f64],
+    pauses: &[(f64, f64)],
+) -> Option<f64> {
+    if distance_samples.len() < 2 {
+        return None;
+    }
+
+    let moving = distance_samples
+        .windows(2)
+        .zip(speeds)
+        .filter(|(window, speed)| {
+            let start = window[0].timestamp;
+            **speed >= MOVING_SPEED_THRESHOLD
+                && !pauses
+                    .iter()
+                    .any(|(stop, restart)| start >= *stop && start < *restart)
+        })
+        .map(|(window, _)| (window[1].timestamp - window[0].timestamp).max(0.0))
+        .sum();
+
+    Some(moving)
+}
+
 fn derive_speed_mean(
     distance_samples: &[DistanceSample],
     distance_series: &[f64],
@@ -230,6 +462,18 @@ pub(crate) fn smooth_speed_window(speeds: &[f64], window_size: usize) -> Vec<f64
 pub(crate) mod tests {
     use super::*;
 
+    #[test]
+    fn fallback_utc_offsets_are_clamped() {
+        let options = |minutes| ProcessingOptions {
+            utc_offset_minutes: Some(minutes),
+            ..ProcessingOptions::default()
+        };
+
+        assert_eq!(options(90).utc_offset_seconds(), Some(5_400));
+        assert_eq!(options(i32::MAX).utc_offset_seconds(), Some(50_400));
+        assert_eq!(options(i32::MIN).utc_offset_seconds(), Some(-50_400));
+    }
+
     #[test]
     fn smoothing_defaults_to_empty_series() {
         let result = smooth_speed_window(&[], 5);
@@ -253,4 +497,45 @@ pub(crate) mod tests {
         let series = reconstruct_distance_series(&samples, &[1.0], &[1.0]);
         assert_eq!(series, vec![0.0, 1.0]);
     }
+
+    #[test]
+    fn moving_time_skips_slow_and_paused_intervals() {
+        let samples: Vec<DistanceSample> = [0.0, 3.0, 3.0, 6.0, 9.0]
+            .iter()
+            .enumerate()
+            .map(|(idx, &distance)| DistanceSample {
+                record_index: idx,
+                timestamp: idx as f64,
+                distance,
+            })
+            .collect();
+        let speeds = compute_distance_based_speeds(&samples);
+        let pauses = timer_pauses(&[(3.0, false), (4.0, true)]);
+
+        // Interval 1-2 is stationary and interval 3-4 falls inside the timer pause.
+        assert_eq!(derive_moving_time(&samples, &speeds, &pauses), Some(2.0));
+    }
+
+    #[test]
+    fn balance_needs_the_right_leg_flag() {
+        assert_eq!(left_balance_percent(f64::from(0x80 | 52)), Some(48.0));
+        assert_eq!(left_balance_percent(52.0), None);
+    }
+
+    #[test]
+    fn elevation_gain_ignores_descents() {
+        let gain = derive_elevation_gain(&[100.0, 110.0, 105.0, 120.0]);
+        assert_eq!(gain, Some(25.0));
+        assert_eq!(derive_elevation_gain(&[100.0]), None);
+    }
+
+    #[test]
+    fn climbing_ignores_altimeter_jitter() {
+        let jitter = [
+            100.0, 101.0, 100.0, 101.5, 100.5, 102.0, 103.0, 104.0, 103.0,
+        ];
+
+        assert_eq!(ascent_and_descent(&jitter), (3.0, 0.0));
+        assert_eq!(ascent_and_descent(&[]), (0.0, 0.0));
+    }
 }
@@ -0,0 +1,284 @@
+//! Where an activity went up and down: time spent in gradient buckets, an
+//! elevation profile for charting, and an optional guess at the surface.
+//!
+//! Grades are measured over segments of at least [`MIN_SEGMENT_METERS`], as
+//! altitude noise makes sample-to-sample grades meaningless.
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::types::MOVING_SPEED_THRESHOLD;
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use serde::Serialize;
+
+/// Shortest distance a grade is measured over.
+pub const MIN_SEGMENT_METERS: f64 = 50.0;
+
+/// Most points kept in [`TerrainAnalysis::profile`]; longer activities use
+/// longer segments.
+pub const MAX_PROFILE_POINTS: usize = 500;
+
+/// Grade buckets as `(label, upper bound in percent)`; the last bucket takes
+/// everything steeper.
+pub const GRADE_BUCKETS: &[(&str, f64)] = &[
+    ("Steep descent", -8.0),
+    ("Descent", -3.0),
+    ("Flat", 3.0),
+    ("Climb", 8.0),
+    ("Steep climb", f64::INFINITY),
+];
+
+/// Speed variability, beyond what cadence explains, below which the surface
+/// counts as paved.
+pub const PAVED_ROUGHNESS_CEILING: f64 = 0.04;
+
+/// Speed variability, beyond what cadence explains, above which the surface
+/// counts as unpaved.
+pub const UNPAVED_ROUGHNESS_FLOOR: f64 = 0.1;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TerrainAnalysis {
+    /// One entry per [`GRADE_BUCKETS`] entry, in the same order.
+    pub grades: Vec<GradeBucket>,
+    pub profile: Vec<ProfilePoint>,
+    /// Set when surface classification was requested and the file has speed.
+    pub surface: Option<SurfaceEstimate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GradeBucket {
+    pub label: &'static str,
+    pub seconds: f64,
+    pub distance_meters: f64,
+}
+
+/// End of one measured segment.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ProfilePoint {
+    pub distance_meters: f64,
+    pub altitude_meters: f64,
+    /// Grade of the segment ending here, in percent.
+    pub grade_percent: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Surface {
+    Paved,
+    Mixed,
+    Unpaved,
+}
+
+/// Surface guessed from how much speed jitters between samples while the
+/// cadence stays steady; rough ground shakes speed at constant effort.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SurfaceEstimate {
+    pub surface: Surface,
+    /// Mean change in speed between samples, relative to the mean speed.
+    pub speed_variability: f64,
+    /// Mean change in cadence between samples, relative to the mean cadence.
+    pub cadence_variability: Option<f64>,
+}
+
+/// Index of the [`GRADE_BUCKETS`] entry for `grade_percent`.
+pub fn grade_bucket(grade_percent: f64) -> usize {
+    GRADE_BUCKETS
+        .iter()
+        .position(|&(_, upper)| grade_percent < upper)
+        .unwrap_or(GRADE_BUCKETS.len() - 1)
+}
+
+struct Sample {
+    timestamp: f64,
+    distance: f64,
+    altitude: f64,
+}
+
+/// Grade distribution and profile of the `Record` messages that carry
+/// timestamp, distance, and altitude. Returns `None` when there are fewer
+/// than two such records or they cover no distance.
+pub fn analyze_terrain(
+    records: &[FitDataRecord],
+    classify_surface: bool,
+) -> Option<TerrainAnalysis> {
+    let samples: Vec<Sample> = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .filter_map(|record| {
+            let value = |name: &str| {
+                record
+                    .fields()
+                    .iter()
+                    .find(|field| field.name() == name)
+                    .and_then(field_value_to_f64)
+            };
+            Some(Sample {
+                timestamp: value("timestamp")?,
+                distance: value("distance")?,
+                altitude: value("enhanced_altitude").or_else(|| value("altitude"))?,
+            })
+        })
+        .collect();
+    let (first, last) = (samples.first()?, samples.last()?);
+    let total_distance = last.distance - first.distance;
+    if total_distance <= 0.0 {
+        return None;
+    }
+    let segment_meters = MIN_SEGMENT_METERS.max(total_distance / MAX_PROFILE_POINTS as f64);
+
+    let mut grades: Vec<GradeBucket> = GRADE_BUCKETS
+        .iter()
+        .map(|&(label, _)| GradeBucket {
+            label,
+            seconds: 0.0,
+            distance_meters: 0.0,
+        })
+        .collect();
+    let mut profile = Vec::new();
+    let mut start = first;
+    for sample in &samples[1..] {
+        let distance = sample.distance - start.distance;
+        if distance < segment_meters && !std::ptr::eq(sample, last) {
+            continue;
+        }
+        if distance > 0.0 {
+            let grade_percent = (sample.altitude - start.altitude) / distance * 100.0;
+            let bucket = &mut grades[grade_bucket(grade_percent)];
+            bucket.seconds += (sample.timestamp - start.timestamp).max(0.0);
+            bucket.distance_meters += distance;
+            profile.push(ProfilePoint {
+                distance_meters: sample.distance - first.distance,
+                altitude_meters: sample.altitude,
+                grade_percent,
+            });
+        }
+        start = sample;
+    }
+
+    Some(TerrainAnalysis {
+        grades,
+        profile,
+        surface: classify_surface
+            .then(|| estimate_surface(records))
+            .flatten(),
+    })
+}
+
+/// Classify the surface from moving `Record` samples; `None` without speed.
+pub fn estimate_surface(records: &[FitDataRecord]) -> Option<SurfaceEstimate> {
+    let mut speeds = Vec::new();
+    let mut cadences = Vec::new();
+    for record in records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+    {
+        let value = |name: &str| {
+            record
+                .fields()
+                .iter()
+                .find(|field| field.name() == name)
+                .and_then(field_value_to_f64)
+        };
+        let Some(speed) = value("enhanced_speed").or_else(|| value("speed")) else {
+            continue;
+        };
+        // Stops and restarts swing speed for reasons unrelated to the ground.
+        if speed < MOVING_SPEED_THRESHOLD {
+            continue;
+        }
+        speeds.push(speed);
+        cadences.extend(value("cadence").filter(|&cadence| cadence > 0.0));
+    }
+    let speed_variability = variability(&speeds)?;
+    let cadence_variability = variability(&cadences);
+    let roughness = (speed_variability - cadence_variability.unwrap_or(0.0)).max(0.0);
+    let surface = if roughness < PAVED_ROUGHNESS_CEILING {
+        Surface::Paved
+    } else if roughness < UNPAVED_ROUGHNESS_FLOOR {
+        Surface::Mixed
+    } else {
+        Surface::Unpaved
+    };
+    Some(SurfaceEstimate {
+        surface,
+        speed_variability,
+        cadence_variability,
+    })
+}
+
+/// Mean absolute change between consecutive values, relative to their mean.
+fn variability(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    if mean <= 0.0 {
+        return None;
+    }
+    let jitter = values
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .sum::<f64>()
+        / (values.len() - 1) as f64;
+    Some(jitter / mean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+    use fitparser::Value;
+
+    fn sample(second: f64, distance: f64, altitude: f64, speed: f64) -> FitDataRecord {
+        record_with(
+            MesgNum::Record,
+            &[
+                ("timestamp", timestamp_value(1_000.0 + second).unwrap()),
+                ("distance", Value::Float64(distance)),
+                ("altitude", Value::Float64(altitude)),
+                ("speed", Value::Float64(speed)),
+                ("cadence", Value::UInt8(90)),
+            ],
+        )
+    }
+
+    #[test]
+    fn buckets_time_by_segment_grade() {
+        // 100 m flat, 100 m at 10%, then 100 m at -5%, 10 m per second.
+        let mut records = Vec::new();
+        for step in 0..=30 {
+            let distance = step as f64 * 10.0;
+            let altitude = match distance {
+                d if d <= 100.0 => 0.0,
+                d if d <= 200.0 => (d - 100.0) * 0.1,
+                d => 10.0 - (d - 200.0) * 0.05,
+            };
+            records.push(sample(step as f64, distance, altitude, 10.0));
+        }
+
+        let terrain = analyze_terrain(&records, false).unwrap();
+
+        let seconds: Vec<f64> = terrain.grades.iter().map(|bucket| bucket.seconds).collect();
+        assert_eq!(seconds, vec![0.0, 10.0, 10.0, 0.0, 10.0]);
+        assert_eq!(terrain.profile.len(), 6);
+        assert_eq!(terrain.profile.last().unwrap().distance_meters, 300.0);
+        assert_eq!(terrain.surface, None);
+    }
+
+    #[test]
+    fn jittery_speed_at_steady_cadence_reads_as_unpaved() {
+        let steady: Vec<FitDataRecord> = (0..20)
+            .map(|step| sample(step as f64, step as f64 * 8.0, 0.0, 8.0))
+            .collect();
+        let rough: Vec<FitDataRecord> = (0..20)
+            .map(|step| {
+                let speed = if step % 2 == 0 { 7.0 } else { 9.0 };
+                sample(step as f64, step as f64 * 8.0, 0.0, speed)
+            })
+            .collect();
+
+        let surface = |records: &[FitDataRecord]| estimate_surface(records).unwrap().surface;
+        assert_eq!(surface(&steady), Surface::Paved);
+        assert_eq!(surface(&rough), Surface::Unpaved);
+    }
+}
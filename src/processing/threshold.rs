@@ -0,0 +1,326 @@
+//! Functional threshold power and threshold heart-rate estimates for users
+//! without a lab or field test.
+//!
+//! Both estimates come from the best 20 minutes of the activity, so they are
+//! only as good as the effort behind them; [`ThresholdEstimate::notes`] says
+//! what each one was based on.
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::record_timestamp;
+use crate::processing::types::GAP_FILL_MIN_SECONDS;
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use serde::Serialize;
+
+/// Length of the effort both estimates are based on.
+pub const THRESHOLD_WINDOW_SECONDS: usize = 20 * 60;
+
+/// FTP as a fraction of the best 20-minute mean power.
+pub const FTP_FACTOR: f64 = 0.95;
+
+/// A window whose heart rate has a coefficient of variation below this
+/// counts as a sustained effort.
+pub const SUSTAINED_HR_MAX_VARIATION: f64 = 0.05;
+
+/// Gaps between samples longer than this are shortened to it before a
+/// per-second series is built, so a timestamp that jumps by years cannot size
+/// the series. It is longer than any window the series are searched with.
+pub const MAX_SERIES_GAP_SECONDS: f64 = 3.0 * 60.0 * 60.0;
+
+/// Longest per-second series built, a week; later samples are left out.
+pub const MAX_SERIES_SECONDS: usize = 7 * 24 * 60 * 60;
+
+/// Thresholds estimated from one activity.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ThresholdEstimate {
+    /// Functional threshold power in watts.
+    pub ftp_watts: Option<f64>,
+    /// Highest mean power over [`THRESHOLD_WINDOW_SECONDS`], in watts.
+    pub best_20min_power_watts: Option<f64>,
+    /// Lactate threshold heart rate in beats per minute.
+    pub threshold_heart_rate: Option<f64>,
+    /// What the estimates are based on and when not to trust them.
+    pub notes: Vec<String>,
+}
+
+/// Estimate FTP and threshold heart rate from the power and heart-rate fields
+/// of `Record` messages. Returns `None` when the activity has neither.
+pub fn estimate_thresholds(records: &[FitDataRecord]) -> Option<ThresholdEstimate> {
+    let mut power: Vec<(f64, f64)> = Vec::new();
+    let mut heart_rate: Vec<(f64, f64)> = Vec::new();
+    for record in records {
+        if !matches!(record.kind(), MesgNum::Record) {
+            continue;
+        }
+        let Some(timestamp) = record_timestamp(record) else {
+            continue;
+        };
+        for field in record.fields() {
+            let series = match field.name() {
+                "power" => &mut power,
+                "heart_rate" => &mut heart_rate,
+                _ => continue,
+            };
+            if let Some(value) = field_value_to_f64(field) {
+                series.push((timestamp, value));
+            }
+        }
+    }
+    if power.is_empty() && heart_rate.is_empty() {
+        return None;
+    }
+    // Shortened together, so both series keep the same seconds.
+    collapse_gaps(&mut [&mut power, &mut heart_rate]);
+
+    let origin = power
+        .iter()
+        .chain(&heart_rate)
+        .map(|&(timestamp, _)| timestamp)
+        .fold(f64::INFINITY, f64::min);
+    // Time without power readings is time without pedalling.
+    let power_series = per_second(&power, origin, Some(0.0));
+    let heart_rate_series = per_second(&heart_rate, origin, None);
+    let mut notes = Vec::new();
+
+    let best_power = best_window(&power_series, THRESHOLD_WINDOW_SECONDS, None);
+    match best_power {
+        Some((_, watts)) => notes.push(format!(
+            "FTP is 95% of the best 20-minute power ({watts:.0} W) and is only accurate if that effort was close to maximal."
+        )),
+        None if !power.is_empty() => {
+            notes.push("FTP needs at least 20 minutes of power data.".to_string())
+        }
+        None => {}
+    }
+
+    let power_window_heart_rate = best_power.and_then(|(start, _)| {
+        heart_rate_series
+            .get(start..start + THRESHOLD_WINDOW_SECONDS)
+            .and_then(mean_of)
+    });
+    let threshold_heart_rate = match power_window_heart_rate {
+        Some(bpm) => {
+            notes.push(
+                "Threshold heart rate is the average during the best 20-minute power effort."
+                    .to_string(),
+            );
+            Some(bpm)
+        }
+        None => match best_window(
+            &heart_rate_series,
+            THRESHOLD_WINDOW_SECONDS,
+            Some(SUSTAINED_HR_MAX_VARIATION),
+        ) {
+            Some((_, bpm)) => {
+                notes.push(
+                    "Threshold heart rate is the average of the hardest steady 20 minutes and assumes that effort was near threshold."
+                        .to_string(),
+                );
+                Some(bpm)
+            }
+            None if !heart_rate.is_empty() => {
+                notes.push(
+                    "Threshold heart rate needs a steady effort of at least 20 minutes."
+                        .to_string(),
+                );
+                None
+            }
+            None => None,
+        },
+    };
+
+    Some(ThresholdEstimate {
+        ftp_watts: best_power.map(|(_, watts)| watts * FTP_FACTOR),
+        best_20min_power_watts: best_power.map(|(_, watts)| watts),
+        threshold_heart_rate,
+        notes,
+    })
+}
+
+/// One value per second from `origin` to the last sample. Each sample holds
+/// until the next one, for at most [`GAP_FILL_MIN_SECONDS`]; longer gaps are
+/// filled with `gap`. Gaps are shortened to [`MAX_SERIES_GAP_SECONDS`] and
+/// the series to [`MAX_SERIES_SECONDS`].
+pub(crate) fn per_second(
+    samples: &[(f64, f64)],
+    origin: f64,
+    gap: Option<f64>,
+) -> Vec<Option<f64>> {
+    let mut samples = samples.to_vec();
+    collapse_gaps(&mut [&mut samples]);
+    let Some(&(last, _)) = samples.last() else {
+        return Vec::new();
+    };
+    let seconds = ((last - origin).max(0.0) as usize).min(MAX_SERIES_SECONDS);
+    let mut series = vec![gap; seconds + 1];
+    for (idx, &(timestamp, value)) in samples.iter().enumerate() {
+        let start = (timestamp - origin).max(0.0) as usize;
+        let next = samples
+            .get(idx + 1)
+            .map_or(timestamp + 1.0, |&(next, _)| next);
+        let held = (next - timestamp).clamp(1.0, GAP_FILL_MIN_SECONDS) as usize;
+        let end = start.saturating_add(held).min(series.len());
+        if start < end {
+            series[start..end].fill(Some(value));
+        }
+    }
+    series
+}
+
+/// Move the samples of `series`, each in time order, earlier so that no gap
+/// in their combined timeline is longer than [`MAX_SERIES_GAP_SECONDS`].
+pub(crate) fn collapse_gaps(series: &mut [&mut Vec<(f64, f64)>]) {
+    let mut timestamps: Vec<f64> = series
+        .iter()
+        .flat_map(|samples| samples.iter().map(|&(timestamp, _)| timestamp))
+        .collect();
+    timestamps.sort_by(f64::total_cmp);
+    // Each cut is the first timestamp after a long gap and the seconds
+    // removed up to it.
+    let mut cuts: Vec<(f64, f64)> = Vec::new();
+    let mut removed = 0.0;
+    for pair in timestamps.windows(2) {
+        let gap = pair[1] - pair[0];
+        if gap > MAX_SERIES_GAP_SECONDS {
+            removed += gap - MAX_SERIES_GAP_SECONDS;
+            cuts.push((pair[1], removed));
+        }
+    }
+    if cuts.is_empty() {
+        return;
+    }
+    for samples in series.iter_mut() {
+        for (timestamp, _) in samples.iter_mut() {
+            let passed = cuts.partition_point(|&(at, _)| at <= *timestamp);
+            if let Some(&(_, removed)) = passed.checked_sub(1).and_then(|cut| cuts.get(cut)) {
+                *timestamp -= removed;
+            }
+        }
+    }
+}
+
+/// Start and mean of the highest-mean window of `window` seconds with no
+/// missing values. With `max_variation`, only windows whose coefficient of
+/// variation stays below it are considered.
+fn best_window(
+    series: &[Option<f64>],
+    window: usize,
+    max_variation: Option<f64>,
+) -> Option<(usize, f64)> {
+    if window == 0 || series.len() < window {
+        return None;
+    }
+
+    let (mut sum, mut sum_sq, mut missing) = (0.0, 0.0, 0usize);
+    let mut best: Option<(usize, f64)> = None;
+    for (idx, value) in series.iter().enumerate() {
+        match value {
+            Some(value) => {
+                sum += value;
+                sum_sq += value * value;
+            }
+            None => missing += 1,
+        }
+        if idx >= window {
+            match series[idx - window] {
+                Some(value) => {
+                    sum -= value;
+                    sum_sq -= value * value;
+                }
+                None => missing -= 1,
+            }
+        }
+        if idx + 1 < window || missing > 0 {
+            continue;
+        }
+
+        let mean = sum / window as f64;
+        if let Some(max_variation) = max_variation {
+            let deviation = (sum_sq / window as f64 - mean * mean).max(0.0).sqrt();
+            if mean <= 0.0 || deviation / mean > max_variation {
+                continue;
+            }
+        }
+        if best.is_none_or(|(_, best)| mean > best) {
+            best = Some((idx + 1 - window, mean));
+        }
+    }
+    best
+}
+
+/// Mean of a fully covered slice.
+fn mean_of(values: &[Option<f64>]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let values: Option<Vec<f64>> = values.iter().copied().collect();
+    values.map(|values| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+    use fitparser::Value;
+
+    fn ride(seconds: usize, watts: impl Fn(usize) -> u16) -> Vec<FitDataRecord> {
+        (0..seconds)
+            .map(|second| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        (
+                            "timestamp",
+                            timestamp_value(1_700_000_000.0 + second as f64).unwrap(),
+                        ),
+                        ("power", Value::UInt16(watts(second))),
+                        ("heart_rate", Value::UInt8(160)),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn timestamp_jumps_do_not_size_the_series() {
+        // Ten minutes, then the clock jumps ten years ahead for ten more.
+        let jump = 10.0 * 365.0 * 24.0 * 60.0 * 60.0;
+        let samples: Vec<(f64, f64)> = (0..1200)
+            .map(|second| {
+                let timestamp = second as f64 + if second < 600 { 0.0 } else { jump };
+                (timestamp, 200.0)
+            })
+            .collect();
+
+        let series = per_second(&samples, 0.0, Some(0.0));
+
+        // The jump is shortened to the longest gap kept.
+        assert_eq!(
+            series.len(),
+            (599.0 + MAX_SERIES_GAP_SECONDS) as usize + 600
+        );
+        assert_eq!(series.last(), Some(&Some(200.0)));
+    }
+
+    #[test]
+    fn ftp_uses_best_twenty_minutes() {
+        // Ten easy minutes, then a 20-minute effort at 300 W.
+        let records = ride(30 * 60, |second| if second < 600 { 150 } else { 300 });
+
+        let estimate = estimate_thresholds(&records).expect("power and heart rate present");
+
+        assert_eq!(estimate.best_20min_power_watts, Some(300.0));
+        assert_eq!(estimate.ftp_watts, Some(285.0));
+        assert_eq!(estimate.threshold_heart_rate, Some(160.0));
+    }
+
+    #[test]
+    fn short_activity_only_explains_why() {
+        let estimate = estimate_thresholds(&ride(600, |_| 250)).expect("power present");
+
+        assert_eq!(estimate.ftp_watts, None);
+        assert_eq!(estimate.threshold_heart_rate, None);
+        assert_eq!(estimate.notes.len(), 2);
+    }
+}
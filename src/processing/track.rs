@@ -0,0 +1,182 @@
+//! The GPS track of a processed activity, kept with its history entry and
+//! exported as GPX or GeoJSON.
+
+use crate::processing::anonymize::{EARTH_RADIUS_METERS, record_position};
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::record_timestamp;
+use fitparser::FitDataRecord;
+use serde::Serialize;
+
+/// Most points kept in a [`gps_track`].
+pub const MAX_TRACK_POINTS: usize = 5_000;
+
+/// A recorded position in degrees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TrackPoint {
+    pub lat: f64,
+    pub lon: f64,
+    /// Altitude in metres, preferring `enhanced_altitude`.
+    pub elevation: Option<f64>,
+    /// Seconds since the Unix epoch.
+    pub timestamp: Option<f64>,
+}
+
+/// Every position of the `Record` messages in order. Positions outside the
+/// valid range, such as the 0°N 0°E some devices write before a fix, are
+/// skipped.
+pub fn track_points(records: &[FitDataRecord]) -> Vec<TrackPoint> {
+    records
+        .iter()
+        .filter_map(|record| {
+            let (lat, lon) = record_position(record)?;
+            let valid = lat.abs() <= 90.0 && lon.abs() <= 180.0 && (lat, lon) != (0.0, 0.0);
+            let elevation = ["enhanced_altitude", "altitude"].iter().find_map(|name| {
+                record
+                    .fields()
+                    .iter()
+                    .find(|field| field.name() == *name)
+                    .and_then(field_value_to_f64)
+            });
+            valid.then_some(TrackPoint {
+                lat,
+                lon,
+                elevation,
+                timestamp: record_timestamp(record),
+            })
+        })
+        .collect()
+}
+
+/// [`track_points`] downsampled to [`MAX_TRACK_POINTS`].
+pub fn gps_track(records: &[FitDataRecord]) -> Vec<TrackPoint> {
+    let points = track_points(records);
+    let stride = points.len().div_ceil(MAX_TRACK_POINTS).max(1);
+    points.into_iter().step_by(stride).collect()
+}
+
+/// Douglas-Peucker simplification: the fewest points of `points`, always
+/// including the first and last, such that no dropped point lies more than
+/// `tolerance_meters` from the simplified line.
+pub fn simplify(points: &[TrackPoint], tolerance_meters: f64) -> Vec<TrackPoint> {
+    if points.len() < 3 || !(tolerance_meters > 0.0) {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // An explicit stack, as tracks of long rides would recurse too deeply.
+    let mut segments = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = segments.pop() {
+        let farthest = (first + 1..last)
+            .map(|index| {
+                let offset = offset_meters(points[first], points[last], points[index]);
+                (index, offset)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, offset)) = farthest {
+            if offset > tolerance_meters {
+                keep[index] = true;
+                segments.push((first, index));
+                segments.push((index, last));
+            }
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+/// Distance from `point` to the segment `start`–`end`, on a plane tangent at
+/// `start`; accurate well beyond the span of one simplified segment.
+fn offset_meters(start: TrackPoint, end: TrackPoint, point: TrackPoint) -> f64 {
+    let meters_per_degree = EARTH_RADIUS_METERS.to_radians();
+    let scale_lon = start.lat.to_radians().cos();
+    let project = |other: TrackPoint| {
+        (
+            (other.lon - start.lon) * scale_lon * meters_per_degree,
+            (other.lat - start.lat) * meters_per_degree,
+        )
+    };
+    let (ex, ey) = project(end);
+    let (px, py) = project(point);
+    let length_squared = ex * ex + ey * ey;
+    let t = if length_squared > 0.0 {
+        ((px * ex + py * ey) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (px - t * ex).hypot(py - t * ey)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::anonymize::SEMICIRCLES_TO_DEGREES;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+    use fitparser::profile::MesgNum;
+
+    fn at(lat: f64, lon: f64) -> TrackPoint {
+        TrackPoint {
+            lat,
+            lon,
+            ..TrackPoint::default()
+        }
+    }
+
+    #[test]
+    fn tracks_keep_valid_record_positions() {
+        let semicircles = |degrees: f64| Value::SInt32((degrees / SEMICIRCLES_TO_DEGREES) as i32);
+        let records = [
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("position_lat", semicircles(0.0)),
+                    ("position_long", semicircles(0.0)),
+                ],
+            ),
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("position_lat", semicircles(47.5)),
+                    ("position_long", semicircles(8.25)),
+                    ("enhanced_altitude", Value::Float64(412.0)),
+                ],
+            ),
+            record_with(
+                MesgNum::Lap,
+                &[
+                    ("position_lat", semicircles(47.6)),
+                    ("position_long", semicircles(8.3)),
+                ],
+            ),
+        ];
+
+        let track = gps_track(&records);
+        assert_eq!(track.len(), 1);
+        assert!((track[0].lat - 47.5).abs() < 1e-6);
+        assert!((track[0].lon - 8.25).abs() < 1e-6);
+        assert_eq!(track[0].elevation, Some(412.0));
+    }
+
+    #[test]
+    fn simplification_keeps_corners_beyond_the_tolerance() {
+        // East for ~1.1 km in 100 steps with ~1 m of jitter, then north.
+        let mut points: Vec<TrackPoint> = (0..=100)
+            .map(|i| {
+                at(
+                    47.0 + if i % 2 == 0 { 0.0 } else { 0.000_01 },
+                    8.0 + i as f64 * 0.000_15,
+                )
+            })
+            .collect();
+        points.extend((1..=100).map(|i| at(47.0 + i as f64 * 0.000_1, 8.015)));
+
+        let simplified = simplify(&points, 5.0);
+        assert_eq!(simplified, vec![points[0], points[100], points[200]]);
+        assert_eq!(simplify(&points, 0.5).len(), points.len() - 99);
+        assert_eq!(simplify(&points, 0.0).len(), points.len());
+    }
+}
@@ -0,0 +1,291 @@
+//! Rides recorded on an indoor trainer by a virtual-riding app.
+//!
+//! Such files come from apps rather than head units, so they are recognised
+//! by the `FileId` manufacturer or by the `virtual_activity` sub-sport. They
+//! often have no GPS and a speed the app made up, but they record what the
+//! trainer was asked to do: a simulated incline as `Record.grade` and, for
+//! structured workouts, `workout_step` events whose `WorkoutStep` messages
+//! hold the power targets the trainer held in ERG mode.
+
+use crate::processing::anonymize::record_position;
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::record_timestamp;
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataField, FitDataRecord, Value};
+use serde::Serialize;
+
+/// Virtual-riding apps as `(manufacturer number, profile name, display name)`.
+pub const VIRTUAL_PLATFORMS: &[(u16, &str, &str)] = &[
+    (260, "zwift", "Zwift"),
+    (281, "trainer_road", "TrainerRoad"),
+    (282, "the_sufferfest", "The Sufferfest"),
+];
+
+/// `WorkoutStep` power targets above this are watts plus this offset; below
+/// it they are a percentage of FTP.
+const WATTS_TARGET_OFFSET: f64 = 1_000.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VirtualRide {
+    /// App that recorded the ride, when the `FileId` names a known one.
+    pub platform: Option<&'static str>,
+    /// Whether records carry positions, which on some apps are in a
+    /// fictional world.
+    pub has_gps: bool,
+    /// Simulated incline the trainer applied.
+    pub grade_mean_percent: Option<f64>,
+    pub grade_max_percent: Option<f64>,
+    /// Workout steps in time order; empty for free rides.
+    pub segments: Vec<TrainerSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrainerMode {
+    /// The trainer held a power target.
+    Erg,
+    /// The trainer followed the simulated incline.
+    Simulation,
+    Free,
+}
+
+/// One workout step, from its `workout_step` event to the next.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrainerSegment {
+    /// Index of the `WorkoutStep`, when the event names one.
+    pub step: Option<u32>,
+    pub mode: TrainerMode,
+    /// Seconds since the Unix epoch.
+    pub start_timestamp: f64,
+    pub duration_seconds: f64,
+    /// Power target range in watts; `None` for targets set as a share of FTP.
+    pub target_watts: Option<(f64, f64)>,
+    pub mean_power: Option<f64>,
+    pub mean_grade_percent: Option<f64>,
+}
+
+struct Sample {
+    timestamp: f64,
+    power: Option<f64>,
+    grade: Option<f64>,
+}
+
+/// Summarise a virtual ride; `None` for anything else.
+pub fn analyze_virtual_ride(records: &[FitDataRecord]) -> Option<VirtualRide> {
+    let platform = records
+        .iter()
+        .find(|record| matches!(record.kind(), MesgNum::FileId))
+        .and_then(|record| field(record, "manufacturer"))
+        .and_then(|manufacturer| platform_name(manufacturer.value()));
+    if platform.is_none() && !is_virtual_activity(records) {
+        return None;
+    }
+
+    let samples: Vec<Sample> = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .filter_map(|record| {
+            Some(Sample {
+                timestamp: record_timestamp(record)?,
+                power: number(record, "power"),
+                grade: number(record, "grade"),
+            })
+        })
+        .collect();
+    let grades: Vec<f64> = samples.iter().filter_map(|sample| sample.grade).collect();
+
+    Some(VirtualRide {
+        platform,
+        has_gps: records
+            .iter()
+            .any(|record| record_position(record).is_some()),
+        grade_mean_percent: mean(&grades),
+        grade_max_percent: grades.iter().copied().reduce(f64::max),
+        segments: workout_segments(records, &samples),
+    })
+}
+
+fn platform_name(manufacturer: &Value) -> Option<&'static str> {
+    VIRTUAL_PLATFORMS
+        .iter()
+        .find(|&&(code, name, _)| match manufacturer {
+            Value::String(value) => value == name,
+            Value::UInt16(value) => *value == code,
+            _ => false,
+        })
+        .map(|&(_, _, display)| display)
+}
+
+fn is_virtual_activity(records: &[FitDataRecord]) -> bool {
+    records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Session | MesgNum::Sport))
+        .filter_map(|record| field(record, "sub_sport"))
+        .any(|sub_sport| sub_sport.to_string() == "virtual_activity")
+}
+
+/// Split the ride at its `workout_step` events.
+fn workout_segments(records: &[FitDataRecord], samples: &[Sample]) -> Vec<TrainerSegment> {
+    let starts: Vec<(f64, Option<u32>)> = records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Event))
+        .filter(|record| {
+            field(record, "event").is_some_and(|event| event.to_string() == "workout_step")
+        })
+        .filter_map(|record| {
+            let step = number(record, "data").map(|step| step as u32);
+            Some((record_timestamp(record)?, step))
+        })
+        .collect();
+    let Some(end) = samples.last().map(|sample| sample.timestamp) else {
+        return Vec::new();
+    };
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, &(start, step))| {
+            let until = starts.get(index + 1).map_or(end, |&(next, _)| next);
+            let within: Vec<&Sample> = samples
+                .iter()
+                .filter(|sample| {
+                    sample.timestamp >= start && (sample.timestamp < until || until == end)
+                })
+                .collect();
+            let powers: Vec<f64> = within.iter().filter_map(|sample| sample.power).collect();
+            let grades: Vec<f64> = within.iter().filter_map(|sample| sample.grade).collect();
+            let target = step.and_then(|step| power_target(records, step));
+            let mode = match target {
+                Some(_) => TrainerMode::Erg,
+                None if !grades.is_empty() => TrainerMode::Simulation,
+                None => TrainerMode::Free,
+            };
+            TrainerSegment {
+                step,
+                mode,
+                start_timestamp: start,
+                duration_seconds: (until - start).max(0.0),
+                target_watts: target.flatten(),
+                mean_power: mean(&powers),
+                mean_grade_percent: mean(&grades),
+            }
+        })
+        .collect()
+}
+
+/// `Some` when workout step `step` targets power, holding the range in watts
+/// when it is set in watts rather than as a share of FTP.
+fn power_target(records: &[FitDataRecord], step: u32) -> Option<Option<(f64, f64)>> {
+    let workout_step = records.iter().find(|record| {
+        matches!(record.kind(), MesgNum::WorkoutStep)
+            && number(record, "message_index").is_some_and(|index| index as u32 == step)
+    })?;
+    if field(workout_step, "target_type")?.to_string() != "power" {
+        return None;
+    }
+    let bound = |names: [&str; 2]| {
+        names
+            .iter()
+            .find_map(|name| number(workout_step, name))
+            .filter(|&value| value > WATTS_TARGET_OFFSET)
+            .map(|value| value - WATTS_TARGET_OFFSET)
+    };
+    let low = bound(["custom_target_power_low", "custom_target_value_low"]);
+    let high = bound(["custom_target_power_high", "custom_target_value_high"]);
+    Some(low.zip(high))
+}
+
+fn field<'a>(record: &'a FitDataRecord, name: &str) -> Option<&'a FitDataField> {
+    record.fields().iter().find(|field| field.name() == name)
+}
+
+fn number(record: &FitDataRecord, name: &str) -> Option<f64> {
+    field(record, name).and_then(field_value_to_f64)
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+
+    const START: f64 = 1_000_000.0;
+
+    fn at(seconds: f64) -> Value {
+        timestamp_value(START + seconds).unwrap()
+    }
+
+    fn workout_step_event(seconds: f64, step: u32) -> FitDataRecord {
+        record_with(
+            MesgNum::Event,
+            &[
+                ("timestamp", at(seconds)),
+                ("event", Value::String("workout_step".to_string())),
+                ("data", Value::UInt32(step)),
+            ],
+        )
+    }
+
+    fn trainer_road_ride() -> Vec<FitDataRecord> {
+        let mut records = vec![
+            record_with(MesgNum::FileId, &[("manufacturer", Value::UInt16(281))]),
+            record_with(
+                MesgNum::WorkoutStep,
+                &[
+                    ("message_index", Value::UInt16(1)),
+                    ("target_type", Value::String("power".to_string())),
+                    ("custom_target_power_low", Value::UInt32(1_250)),
+                    ("custom_target_power_high", Value::UInt32(1_270)),
+                ],
+            ),
+            workout_step_event(0.0, 0),
+            workout_step_event(60.0, 1),
+        ];
+        records.extend((0..120).map(|second| {
+            let power = if second < 60 { 150 } else { 260 };
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("timestamp", at(f64::from(second))),
+                    ("power", Value::UInt16(power)),
+                ],
+            )
+        }));
+        records
+    }
+
+    #[test]
+    fn erg_steps_are_split_at_workout_step_events() {
+        let ride = analyze_virtual_ride(&trainer_road_ride()).unwrap();
+
+        assert_eq!(ride.platform, Some("TrainerRoad"));
+        assert!(!ride.has_gps);
+        assert_eq!(ride.segments.len(), 2);
+        assert_eq!(ride.segments[0].mode, TrainerMode::Free);
+        assert_eq!(ride.segments[0].mean_power, Some(150.0));
+        let erg = &ride.segments[1];
+        assert_eq!(erg.mode, TrainerMode::Erg);
+        assert_eq!(erg.target_watts, Some((250.0, 270.0)));
+        assert_eq!(erg.duration_seconds, 59.0);
+        assert_eq!(erg.mean_power, Some(260.0));
+    }
+
+    #[test]
+    fn virtual_activities_are_recognised_by_sub_sport() {
+        let session = |sub_sport: &str| {
+            record_with(
+                MesgNum::Session,
+                &[("sub_sport", Value::String(sub_sport.to_string()))],
+            )
+        };
+
+        let ride = analyze_virtual_ride(&[session("virtual_activity")]).unwrap();
+        assert_eq!(ride.platform, None);
+        assert!(ride.segments.is_empty());
+        assert_eq!(analyze_virtual_ride(&[session("road")]), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! Correction of slow barometric altitude drift on loop activities.
+//!
+//! A barometric altimeter reads changes in air pressure, so weather moving
+//! through over a long ride shifts every later altitude. When an activity
+//! ends within [`LOOP_MAX_METERS`] of its start, both ends are at the same
+//! altitude, and any difference between them is drift. It is taken out as a
+//! straight line over time, and lap and session ascent and descent are
+//! recomputed from the corrected altitudes.
+
+use crate::processing::anonymize::{distance_meters, record_position};
+use crate::processing::summary::{ascent_and_descent, field_value_to_f64};
+use crate::processing::transforms::{
+    FieldEdit, RecordContext, Transform, numeric_value_like, record_timestamp,
+};
+use crate::processing::types::{ProcessingWarning, WarningCode};
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataField, FitDataRecord};
+use std::collections::HashMap;
+
+/// Start and end positions closer than this count as the same place.
+pub const LOOP_MAX_METERS: f64 = 100.0;
+
+/// Smaller differences between start and end are within the altimeter's
+/// noise and left alone.
+pub const MIN_DRIFT_METERS: f64 = 1.0;
+
+/// Remove linear altitude drift from activities that end where they started.
+pub struct CorrectAltitudeDrift;
+
+impl Transform for CorrectAltitudeDrift {
+    fn name(&self) -> &'static str {
+        "correct_altitude_drift"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let records = ctx.records();
+        let samples: Vec<(usize, f64, f64)> = records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| matches!(record.kind(), MesgNum::Record))
+            .filter_map(|(index, record)| {
+                let altitude = field(record, "enhanced_altitude").or(field(record, "altitude"))?;
+                Some((index, record_timestamp(record)?, altitude))
+            })
+            .collect();
+        let (Some(&(_, start, first)), Some(&(_, end, last))) = (samples.first(), samples.last())
+        else {
+            return;
+        };
+        let drift = last - first;
+        if end <= start || drift.abs() < MIN_DRIFT_METERS || !is_loop(records) {
+            return;
+        }
+
+        let corrected: HashMap<usize, f64> = samples
+            .iter()
+            .map(|&(index, timestamp, altitude)| {
+                (
+                    index,
+                    altitude - drift * (timestamp - start) / (end - start),
+                )
+            })
+            .collect();
+        let climbs = lap_climbs(records, &corrected);
+        ctx.rewrite_fields(|index, record, field| {
+            altitude_edit(&corrected, &climbs, index, record, field)
+        });
+        ctx.warn(ProcessingWarning::new(
+            WarningCode::AltitudeDriftCorrected,
+            samples.len(),
+            "altitude sample",
+            &format!("corrected for {drift:+.1} m of barometric drift"),
+        ));
+    }
+}
+
+fn field(record: &FitDataRecord, name: &str) -> Option<f64> {
+    record
+        .fields()
+        .iter()
+        .find(|field| field.name() == name)
+        .and_then(field_value_to_f64)
+}
+
+/// Ascent and descent of each lap and session over the `altitudes` of the
+/// records it spans, by message index, with the summary's noise threshold.
+pub(crate) fn lap_climbs(
+    records: &[FitDataRecord],
+    altitudes: &HashMap<usize, f64>,
+) -> HashMap<usize, (f64, f64)> {
+    let mut samples: Vec<(f64, f64)> = altitudes
+        .iter()
+        .filter_map(|(&index, &altitude)| Some((record_timestamp(&records[index])?, altitude)))
+        .collect();
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+    records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| matches!(record.kind(), MesgNum::Lap | MesgNum::Session))
+        .filter_map(|(index, record)| {
+            let (from, to) = (field(record, "start_time")?, record_timestamp(record)?);
+            let spanned: Vec<f64> = samples
+                .iter()
+                .filter(|(timestamp, _)| (from..=to).contains(timestamp))
+                .map(|&(_, altitude)| altitude)
+                .collect();
+            Some((index, ascent_and_descent(&spanned)))
+        })
+        .collect()
+}
+
+/// Record altitudes from `altitudes` and lap and session climbing from
+/// `climbs`, both by message index.
+pub(crate) fn altitude_edit(
+    altitudes: &HashMap<usize, f64>,
+    climbs: &HashMap<usize, (f64, f64)>,
+    index: usize,
+    record: &FitDataRecord,
+    field: &FitDataField,
+) -> FieldEdit {
+    let replacement = match (record.kind(), field.name()) {
+        (MesgNum::Record, "altitude" | "enhanced_altitude") => altitudes.get(&index).copied(),
+        (MesgNum::Lap | MesgNum::Session, "total_ascent") => {
+            climbs.get(&index).map(|&(ascent, _)| ascent)
+        }
+        (MesgNum::Lap | MesgNum::Session, "total_descent") => {
+            climbs.get(&index).map(|&(_, descent)| descent)
+        }
+        _ => None,
+    };
+    replacement
+        .and_then(|value| numeric_value_like(field.value(), value))
+        .map_or(FieldEdit::Keep, FieldEdit::Replace)
+}
+
+/// Whether the first and last recorded positions are the same place.
+fn is_loop(records: &[FitDataRecord]) -> bool {
+    let mut positions = records.iter().filter_map(record_position);
+    let first = positions.next();
+    first
+        .zip(positions.last())
+        .is_some_and(|(first, last)| distance_meters(first, last) <= LOOP_MAX_METERS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::anonymize::SEMICIRCLES_TO_DEGREES;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+
+    /// Out and back over a 50 m hill, with the altimeter gaining 1 m every
+    /// 10 s and the ride ending `end_offset` degrees north of its start.
+    fn ride(end_offset: f64) -> Vec<FitDataRecord> {
+        let mut records: Vec<FitDataRecord> = (0..=10)
+            .map(|step| {
+                let hill = 50.0 - (f64::from(step) - 5.0).abs() * 10.0;
+                let latitude = 45.0 + end_offset * f64::from(step) / 10.0;
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", Value::Float64(f64::from(step) * 10.0)),
+                        (
+                            "position_lat",
+                            Value::SInt32((latitude / SEMICIRCLES_TO_DEGREES) as i32),
+                        ),
+                        ("position_long", Value::SInt32(0)),
+                        (
+                            "enhanced_altitude",
+                            Value::Float64(100.0 + hill + f64::from(step)),
+                        ),
+                    ],
+                )
+            })
+            .collect();
+        records.push(record_with(
+            MesgNum::Session,
+            &[
+                ("timestamp", Value::Float64(100.0)),
+                ("start_time", Value::Float64(0.0)),
+                ("total_ascent", Value::UInt16(55)),
+                ("total_descent", Value::UInt16(45)),
+            ],
+        ));
+        records
+    }
+
+    fn values(ctx: &RecordContext, name: &str) -> Vec<f64> {
+        ctx.records()
+            .iter()
+            .filter_map(|record| field(record, name))
+            .collect()
+    }
+
+    #[test]
+    fn loop_drift_is_removed_and_climbing_recomputed() {
+        let mut ctx = RecordContext::new(ride(0.0));
+
+        CorrectAltitudeDrift.apply(&mut ctx);
+
+        let altitudes = values(&ctx, "enhanced_altitude");
+        assert_eq!(altitudes[0], 100.0);
+        assert!((altitudes[5] - 150.0).abs() < 1e-9);
+        assert!((altitudes[10] - 100.0).abs() < 1e-9);
+        assert_eq!(values(&ctx, "total_ascent"), vec![50.0]);
+        assert_eq!(values(&ctx, "total_descent"), vec![50.0]);
+        let (_, warnings) = ctx.into_parts();
+        assert_eq!(warnings[0].code, WarningCode::AltitudeDriftCorrected);
+    }
+
+    #[test]
+    fn point_to_point_rides_are_left_alone() {
+        // About 1.1 km from start to finish.
+        let mut ctx = RecordContext::new(ride(0.01));
+
+        CorrectAltitudeDrift.apply(&mut ctx);
+
+        assert_eq!(values(&ctx, "enhanced_altitude")[10], 110.0);
+        assert!(ctx.into_parts().1.is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::processing::transforms::title::set_field;
+use crate::processing::transforms::{RecordContext, Transform};
+use fitparser::profile::MesgNum;
+use fitparser::{BaseType, FitDataField, FitDataRecord, TimestampKind, Value};
+
+/// `BikeProfile.custom_wheelsize`, `auto_wheelsize`, and `crank_length`.
+const CUSTOM_WHEELSIZE_FIELD: u8 = 8;
+const AUTO_WHEELSIZE_FIELD: u8 = 9;
+const CRANK_LENGTH_FIELD: u8 = 19;
+
+/// Crank lengths a FIT `crank_length` can hold: `(mm - 110) * 2` in a byte.
+pub const CRANK_LENGTH_RANGE_MM: std::ops::RangeInclusive<f64> = 110.0..=237.0;
+
+/// Correct the bike setup stored with an activity, for platforms that
+/// re-derive speed from wheel revolutions or torque from crank length.
+///
+/// The wheel circumference replaces `custom_wheelsize` (and `auto_wheelsize`,
+/// when recorded) of every `BikeProfile`; the crank length replaces
+/// `crank_length` of every `BikeProfile` and of `DeviceSettings` messages
+/// that carry one. A `BikeProfile` is added after `FileId` when the file has
+/// none.
+pub struct SetBikeProfile {
+    pub wheel_circumference_mm: Option<u16>,
+    pub crank_length_mm: Option<f64>,
+}
+
+impl Transform for SetBikeProfile {
+    fn name(&self) -> &'static str {
+        "set_bike_profile"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let crank_length_mm = self
+            .crank_length_mm
+            .filter(|mm| CRANK_LENGTH_RANGE_MM.contains(mm));
+        let wheel_circumference_mm = self
+            .wheel_circumference_mm
+            .filter(|&mm| mm > 0 && mm < u16::MAX);
+        if wheel_circumference_mm.is_none() && crank_length_mm.is_none() {
+            return;
+        }
+        ctx.rebuild(|records| with_profile(records, wheel_circumference_mm, crank_length_mm));
+    }
+}
+
+fn with_profile(
+    mut records: Vec<FitDataRecord>,
+    wheel_circumference_mm: Option<u16>,
+    crank_length_mm: Option<f64>,
+) -> Vec<FitDataRecord> {
+    let mut profiled = false;
+    for record in &mut records {
+        match record.kind() {
+            MesgNum::BikeProfile => {
+                if let Some(mm) = wheel_circumference_mm {
+                    let auto = record
+                        .fields()
+                        .iter()
+                        .any(|field| field.name() == "auto_wheelsize");
+                    set_field(
+                        record,
+                        wheelsize_field("custom_wheelsize", CUSTOM_WHEELSIZE_FIELD, mm),
+                    );
+                    if auto {
+                        set_field(
+                            record,
+                            wheelsize_field("auto_wheelsize", AUTO_WHEELSIZE_FIELD, mm),
+                        );
+                    }
+                }
+                if let Some(mm) = crank_length_mm {
+                    set_field(record, crank_length_field(CRANK_LENGTH_FIELD, mm));
+                }
+                profiled = true;
+            }
+            MesgNum::DeviceSettings => {
+                let number = record
+                    .fields()
+                    .iter()
+                    .find(|field| field.name() == "crank_length")
+                    .map(|field| field.number());
+                if let Some((number, mm)) = number.zip(crank_length_mm) {
+                    set_field(record, crank_length_field(number, mm));
+                }
+            }
+            _ => {}
+        }
+    }
+    if !profiled {
+        let mut profile = FitDataRecord::new(MesgNum::BikeProfile);
+        if let Some(mm) = wheel_circumference_mm {
+            profile.push(wheelsize_field(
+                "custom_wheelsize",
+                CUSTOM_WHEELSIZE_FIELD,
+                mm,
+            ));
+        }
+        if let Some(mm) = crank_length_mm {
+            profile.push(crank_length_field(CRANK_LENGTH_FIELD, mm));
+        }
+        let at = records
+            .iter()
+            .position(|record| matches!(record.kind(), MesgNum::FileId))
+            .map_or(0, |index| index + 1);
+        records.insert(at, profile);
+    }
+    records
+}
+
+/// A wheel size in metres, stored as millimetres.
+fn wheelsize_field(name: &str, number: u8, mm: u16) -> FitDataField {
+    FitDataField::with_meta(
+        name.to_string(),
+        number,
+        None,
+        Value::Float64(f64::from(mm) / 1000.0),
+        Value::UInt16(mm),
+        "m".to_string(),
+        BaseType::UInt16,
+        1000.0,
+        0.0,
+        TimestampKind::None,
+    )
+}
+
+/// A crank length in millimetres, stored as `(mm - 110) * 2`: the profile's
+/// scale of 2 and offset of -110.
+fn crank_length_field(number: u8, mm: f64) -> FitDataField {
+    FitDataField::with_meta(
+        "crank_length".to_string(),
+        number,
+        None,
+        Value::Float64(mm),
+        Value::UInt8(((mm - 110.0) * 2.0).round() as u8),
+        "mm".to_string(),
+        BaseType::UInt8,
+        2.0,
+        -110.0,
+        TimestampKind::None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+
+    fn value<'a>(record: &'a FitDataRecord, name: &str) -> Option<&'a Value> {
+        record
+            .fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .map(|field| field.value())
+    }
+
+    #[test]
+    fn corrects_existing_profiles_and_device_settings() {
+        let mut ctx = RecordContext::new(vec![
+            FitDataRecord::new(MesgNum::FileId),
+            record_with(
+                MesgNum::DeviceSettings,
+                &[("crank_length", Value::Float64(170.0))],
+            ),
+            record_with(
+                MesgNum::BikeProfile,
+                &[
+                    ("custom_wheelsize", Value::Float64(2.096)),
+                    ("auto_wheelsize", Value::Float64(2.105)),
+                    ("crank_length", Value::Float64(170.0)),
+                ],
+            ),
+        ]);
+
+        SetBikeProfile {
+            wheel_circumference_mm: Some(2_136),
+            crank_length_mm: Some(172.5),
+        }
+        .apply(&mut ctx);
+
+        let records = ctx.records();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            value(&records[1], "crank_length"),
+            Some(&Value::Float64(172.5))
+        );
+        assert_eq!(
+            value(&records[2], "custom_wheelsize"),
+            Some(&Value::Float64(2.136))
+        );
+        assert_eq!(
+            value(&records[2], "auto_wheelsize"),
+            Some(&Value::Float64(2.136))
+        );
+        assert_eq!(
+            value(&records[2], "crank_length"),
+            Some(&Value::Float64(172.5))
+        );
+    }
+
+    #[test]
+    fn adds_a_profile_after_file_id() {
+        let mut ctx = RecordContext::new(vec![
+            FitDataRecord::new(MesgNum::FileId),
+            FitDataRecord::new(MesgNum::Record),
+        ]);
+
+        SetBikeProfile {
+            wheel_circumference_mm: Some(2_105),
+            // Out of range for the field, so left out.
+            crank_length_mm: Some(300.0),
+        }
+        .apply(&mut ctx);
+
+        let profile = &ctx.records()[1];
+        assert!(matches!(profile.kind(), MesgNum::BikeProfile));
+        assert_eq!(
+            value(profile, "custom_wheelsize"),
+            Some(&Value::Float64(2.105))
+        );
+        assert_eq!(value(profile, "crank_length"), None);
+    }
+
+    #[test]
+    fn crank_length_is_stored_with_the_profile_offset() {
+        let field = crank_length_field(CRANK_LENGTH_FIELD, 172.5);
+
+        assert_eq!(field.raw_value(), &Value::UInt8(125));
+        assert_eq!(field.scale(), 2.0);
+        assert_eq!(field.offset(), -110.0);
+    }
+}
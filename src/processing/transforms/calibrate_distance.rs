@@ -0,0 +1,173 @@
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform, numeric_value_like};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+
+/// Distance fields rescaled in `Record`, `Lap`, and `Session` messages.
+const DISTANCE_FIELDS: &[&str] = &["distance", "total_distance"];
+
+/// Speed fields rescaled along with distance, so pace stays consistent.
+const SPEED_FIELDS: &[&str] = &[
+    "speed",
+    "enhanced_speed",
+    "avg_speed",
+    "max_speed",
+    "enhanced_avg_speed",
+    "enhanced_max_speed",
+];
+
+/// How to correct a footpod or treadmill distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceCalibration {
+    /// Multiply every distance and speed by this factor.
+    Scale(f64),
+    /// Rescale so the activity ends at this many metres.
+    TargetMeters(f64),
+}
+
+/// Rescale record distances, lap and session totals, and speeds together,
+/// for indoor runs where the watch and treadmill disagree.
+pub struct CalibrateDistance {
+    pub calibration: DistanceCalibration,
+}
+
+impl Transform for CalibrateDistance {
+    fn name(&self) -> &'static str {
+        "calibrate_distance"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let factor = match self.calibration {
+            DistanceCalibration::Scale(factor) => Some(factor),
+            // Files without distance have nothing to rescale.
+            DistanceCalibration::TargetMeters(target) => {
+                recorded_distance(ctx.records()).map(|recorded| target / recorded)
+            }
+        };
+        let Some(factor) = factor.filter(|factor| factor.is_finite() && *factor > 0.0) else {
+            return;
+        };
+        if factor == 1.0 {
+            return;
+        }
+
+        ctx.rewrite_fields(|_, record, field| {
+            let rescaled = matches!(
+                record.kind(),
+                MesgNum::Record | MesgNum::Lap | MesgNum::Session
+            ) && (DISTANCE_FIELDS.contains(&field.name())
+                || SPEED_FIELDS.contains(&field.name()));
+            if !rescaled {
+                return FieldEdit::Keep;
+            }
+            field_value_to_f64(field)
+                .and_then(|value| numeric_value_like(field.value(), value * factor))
+                .map_or(FieldEdit::Keep, FieldEdit::Replace)
+        });
+    }
+}
+
+/// Distance the file reports: the furthest record distance, or else the
+/// sum of its session totals.
+fn recorded_distance(records: &[FitDataRecord]) -> Option<f64> {
+    let values = |kind: MesgNum, name: &'static str| {
+        records
+            .iter()
+            .filter(move |record| record.kind() == kind)
+            .flat_map(|record| record.fields())
+            .filter(move |field| field.name() == name)
+            .filter_map(field_value_to_f64)
+    };
+    let furthest = values(MesgNum::Record, "distance").fold(None, |max: Option<f64>, value| {
+        Some(max.map_or(value, |max| max.max(value)))
+    });
+    let total = furthest.or_else(|| {
+        let mut sessions = values(MesgNum::Session, "total_distance").peekable();
+        sessions.peek().is_some().then(|| sessions.sum())
+    })?;
+    (total > 0.0).then_some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+
+    fn activity() -> Vec<FitDataRecord> {
+        vec![
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("distance", Value::Float64(0.0)),
+                    ("speed", Value::Float64(3.0)),
+                ],
+            ),
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("distance", Value::Float64(9_400.0)),
+                    ("speed", Value::Float64(3.2)),
+                    ("heart_rate", Value::UInt8(150)),
+                ],
+            ),
+            record_with(
+                MesgNum::Session,
+                &[
+                    ("total_distance", Value::Float64(9_400.0)),
+                    ("avg_speed", Value::Float64(3.1)),
+                ],
+            ),
+        ]
+    }
+
+    fn value(ctx: &RecordContext, index: usize, name: &str) -> Option<f64> {
+        ctx.records()[index]
+            .fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .and_then(field_value_to_f64)
+    }
+
+    #[test]
+    fn target_distance_rescales_records_totals_and_speeds() {
+        let mut ctx = RecordContext::new(activity());
+
+        CalibrateDistance {
+            calibration: DistanceCalibration::TargetMeters(10_000.0),
+        }
+        .apply(&mut ctx);
+
+        let factor = 10_000.0 / 9_400.0;
+        for (index, name) in [(1, "distance"), (2, "total_distance")] {
+            assert!((value(&ctx, index, name).unwrap() - 10_000.0).abs() < 1e-9);
+        }
+        assert_eq!(value(&ctx, 1, "speed"), Some(3.2 * factor));
+        assert_eq!(value(&ctx, 2, "avg_speed"), Some(3.1 * factor));
+        assert_eq!(value(&ctx, 1, "heart_rate"), Some(150.0));
+    }
+
+    #[test]
+    fn scale_factor_applies_as_entered() {
+        let mut ctx = RecordContext::new(activity());
+
+        CalibrateDistance {
+            calibration: DistanceCalibration::Scale(0.5),
+        }
+        .apply(&mut ctx);
+
+        assert_eq!(value(&ctx, 1, "distance"), Some(4_700.0));
+        assert_eq!(value(&ctx, 0, "speed"), Some(1.5));
+
+        // Without any recorded distance there is nothing to aim at.
+        let mut ctx = RecordContext::new(vec![record_with(
+            MesgNum::Record,
+            &[("speed", Value::Float64(3.0))],
+        )]);
+        CalibrateDistance {
+            calibration: DistanceCalibration::TargetMeters(10_000.0),
+        }
+        .apply(&mut ctx);
+        assert_eq!(value(&ctx, 0, "speed"), Some(3.0));
+    }
+}
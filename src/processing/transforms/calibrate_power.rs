@@ -0,0 +1,97 @@
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform, numeric_value_like};
+use fitparser::profile::MesgNum;
+
+/// Power and work fields rescaled in `Record`, `Lap`, and `Session` messages.
+/// Training settings such as `threshold_power` describe the rider, not the
+/// meter, and are left alone.
+const POWER_FIELDS: &[&str] = &[
+    "power",
+    "accumulated_power",
+    "avg_power",
+    "max_power",
+    "normalized_power",
+    "total_work",
+];
+
+/// Scale every power sample and power total by a percentage, for a power
+/// meter known to read high or low.
+pub struct CalibratePower {
+    /// Correction in percent, e.g. `2.5` to raise readings by 2.5%.
+    pub percent: f64,
+}
+
+impl Transform for CalibratePower {
+    fn name(&self) -> &'static str {
+        "calibrate_power"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let factor = 1.0 + self.percent / 100.0;
+        if !factor.is_finite() || factor <= 0.0 || self.percent == 0.0 {
+            return;
+        }
+
+        ctx.rewrite_fields(|_, record, field| {
+            let scaled = matches!(
+                record.kind(),
+                MesgNum::Record | MesgNum::Lap | MesgNum::Session
+            ) && POWER_FIELDS.contains(&field.name());
+            if !scaled {
+                return FieldEdit::Keep;
+            }
+            field_value_to_f64(field)
+                .and_then(|value| numeric_value_like(field.value(), value * factor))
+                .map_or(FieldEdit::Keep, FieldEdit::Replace)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+
+    #[test]
+    fn power_samples_and_session_totals_are_scaled() {
+        let mut ctx = RecordContext::new(vec![
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("power", Value::UInt16(200)),
+                    ("heart_rate", Value::UInt8(140)),
+                ],
+            ),
+            record_with(
+                MesgNum::Session,
+                &[
+                    ("avg_power", Value::UInt16(160)),
+                    ("max_power", Value::UInt16(400)),
+                    ("normalized_power", Value::UInt16(240)),
+                    ("threshold_power", Value::UInt16(250)),
+                ],
+            ),
+        ]);
+
+        CalibratePower { percent: 2.5 }.apply(&mut ctx);
+
+        let values = |index: usize| -> Vec<Value> {
+            ctx.records()[index]
+                .fields()
+                .iter()
+                .map(|field| field.value().clone())
+                .collect()
+        };
+        assert_eq!(values(0), vec![Value::UInt16(205), Value::UInt8(140)]);
+        assert_eq!(
+            values(1),
+            vec![
+                Value::UInt16(164),
+                Value::UInt16(410),
+                Value::UInt16(246),
+                Value::UInt16(250),
+            ]
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Corrections to the repetitions and weight of strength-training sets,
+//! for when the watch miscounted reps or was never told the weight.
+//!
+//! Corrections are written one per line (or separated by `;`) as
+//! `set: field = value, …`, where `set` is the active set's number as shown
+//! in the sets table and `field` is `reps` or `weight` (in kilograms), e.g.
+//! `3: reps = 10, weight = 62.5`. Sets recorded without the corrected field
+//! get it added.
+
+use crate::processing::strength::is_active_set;
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform, numeric_value_like};
+use crate::processing::types::{ProcessingWarning, WarningCode};
+use fitparser::{BaseType, FitDataField, TimestampKind, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Most corrections one upload may define.
+pub const MAX_SET_CORRECTIONS: usize = 64;
+
+/// New values for one active set; `None` keeps the recorded value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetCorrection {
+    /// Position among the active sets, from 1.
+    pub set: usize,
+    pub repetitions: Option<u16>,
+    pub weight_kg: Option<f64>,
+}
+
+/// A correction that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCorrectionError {
+    pub rule: String,
+    pub message: String,
+}
+
+impl fmt::Display for SetCorrectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid set correction `{}`: {}",
+            self.rule, self.message
+        )
+    }
+}
+
+impl std::error::Error for SetCorrectionError {}
+
+/// Parse `set: reps = n, weight = kg` corrections separated by newlines or
+/// `;`. Blank corrections are skipped.
+pub fn parse_set_corrections(text: &str) -> Result<Vec<SetCorrection>, SetCorrectionError> {
+    let mut corrections = Vec::new();
+    for rule in text.split(['\n', ';']).map(str::trim) {
+        if rule.is_empty() {
+            continue;
+        }
+        let error = |message: &str| SetCorrectionError {
+            rule: rule.to_string(),
+            message: message.to_string(),
+        };
+        let Some((set, assignments)) = rule.split_once(':') else {
+            return Err(error("expected `set: reps = n, weight = kg`"));
+        };
+        let set = match set.trim().parse::<usize>() {
+            Ok(set) if set > 0 => set,
+            _ => return Err(error("expected a set number from 1")),
+        };
+        let mut correction = SetCorrection {
+            set,
+            repetitions: None,
+            weight_kg: None,
+        };
+        for assignment in assignments.split(',') {
+            let Some((field, value)) = assignment.split_once('=') else {
+                return Err(error("expected `reps = n` or `weight = kg`"));
+            };
+            let value = value.trim();
+            match field.trim() {
+                "reps" => match value.parse::<u16>() {
+                    Ok(reps) => correction.repetitions = Some(reps),
+                    Err(_) => return Err(error("expected a whole number of reps")),
+                },
+                "weight" => match value.parse::<f64>() {
+                    // The field holds up to 4095.9 kg in 1/16 kg steps.
+                    Ok(kg) if (0.0..4_096.0).contains(&kg) => correction.weight_kg = Some(kg),
+                    _ => return Err(error("expected a weight from 0 to 4095 kg")),
+                },
+                _ => return Err(error("only `reps` and `weight` can be corrected")),
+            }
+        }
+        if corrections.len() == MAX_SET_CORRECTIONS {
+            return Err(error("too many set corrections"));
+        }
+        corrections.push(correction);
+    }
+    Ok(corrections)
+}
+
+/// Apply [`SetCorrection`]s to the file's active `Set` messages.
+pub struct CorrectSets {
+    pub corrections: Vec<SetCorrection>,
+}
+
+impl Transform for CorrectSets {
+    fn name(&self) -> &'static str {
+        "correct_sets"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        // Message index of each active set number; later corrections of the
+        // same set win.
+        let mut corrections: HashMap<usize, &SetCorrection> = HashMap::new();
+        let mut number = 0;
+        for (index, record) in ctx.records().iter().enumerate() {
+            if !is_active_set(record) {
+                continue;
+            }
+            number += 1;
+            if let Some(correction) = self.corrections.iter().rev().find(|c| c.set == number) {
+                corrections.insert(index, correction);
+            }
+        }
+
+        let mut corrected = 0;
+        ctx.rewrite_fields(|index, _, field| {
+            let Some(correction) = corrections.get(&index) else {
+                return FieldEdit::Keep;
+            };
+            let value = match field.name() {
+                "repetitions" => correction.repetitions.map(f64::from),
+                "weight" => correction.weight_kg,
+                _ => None,
+            };
+            match value.and_then(|value| numeric_value_like(field.value(), value)) {
+                Some(value) => {
+                    corrected += 1;
+                    FieldEdit::Replace(value)
+                }
+                None => FieldEdit::Keep,
+            }
+        });
+        // Sets the watch recorded without the field get it added.
+        ctx.rebuild(|mut records| {
+            for (&index, correction) in &corrections {
+                let record = &mut records[index];
+                let has = |name: &str| record.fields().iter().any(|field| field.name() == name);
+                let missing_reps = correction.repetitions.filter(|_| !has("repetitions"));
+                let missing_weight = correction.weight_kg.filter(|_| !has("weight"));
+                if let Some(reps) = missing_reps {
+                    record.push(repetitions_field(reps));
+                    corrected += 1;
+                }
+                if let Some(kg) = missing_weight {
+                    record.push(weight_field(kg));
+                    corrected += 1;
+                }
+            }
+            records
+        });
+
+        let unmatched = self.corrections.iter().filter(|c| c.set > number).count();
+        if corrected > 0 {
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::SetsCorrected,
+                corrected,
+                "set value",
+                "corrected",
+            ));
+        }
+        if unmatched > 0 {
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::SetsCorrected,
+                unmatched,
+                "set correction",
+                "ignored: the file has fewer active sets",
+            ));
+        }
+    }
+}
+
+/// `Set.repetitions` as the FIT profile defines it.
+fn repetitions_field(reps: u16) -> FitDataField {
+    FitDataField::with_meta(
+        "repetitions".to_string(),
+        3,
+        None,
+        Value::UInt16(reps),
+        Value::UInt16(reps),
+        String::new(),
+        BaseType::UInt16,
+        1.0,
+        0.0,
+        TimestampKind::None,
+    )
+}
+
+/// `Set.weight`, stored in 1/16 kg.
+fn weight_field(kg: f64) -> FitDataField {
+    FitDataField::with_meta(
+        "weight".to_string(),
+        4,
+        None,
+        Value::Float64(kg),
+        Value::UInt16((kg * 16.0).round() as u16),
+        "kg".to_string(),
+        BaseType::UInt16,
+        16.0,
+        0.0,
+        TimestampKind::None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::strength::analyze_strength_sets;
+    use crate::processing::strength::tests::set;
+
+    #[test]
+    fn parses_corrections() {
+        assert_eq!(
+            parse_set_corrections("3: reps = 10, weight = 62.5\n; 1: reps=8").unwrap(),
+            vec![
+                SetCorrection {
+                    set: 3,
+                    repetitions: Some(10),
+                    weight_kg: Some(62.5),
+                },
+                SetCorrection {
+                    set: 1,
+                    repetitions: Some(8),
+                    weight_kg: None,
+                },
+            ]
+        );
+        for invalid in ["reps = 10", "0: reps = 10", "1: reps = 9.5", "1: sets = 3"] {
+            assert!(parse_set_corrections(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn corrects_active_sets_by_number() {
+        let mut ctx = RecordContext::new(vec![
+            set("active", 10, 60.0),
+            set("rest", 0, 0.0),
+            set("active", 7, 0.0),
+        ]);
+
+        CorrectSets {
+            corrections: parse_set_corrections("2: reps = 8, weight = 62.5; 5: reps = 1").unwrap(),
+        }
+        .apply(&mut ctx);
+
+        let strength = analyze_strength_sets(ctx.records()).unwrap();
+        assert_eq!(strength.sets[1].repetitions, Some(8));
+        assert_eq!(strength.sets[1].weight_kg, Some(62.5));
+        assert_eq!(strength.volume_load_kg, 1_100.0);
+        let (_, warnings) = ctx.into_parts();
+        assert_eq!(warnings[0].message, "2 set values corrected");
+        assert_eq!(
+            warnings[1].message,
+            "1 set correction ignored: the file has fewer active sets"
+        );
+    }
+
+    #[test]
+    fn adds_fields_the_watch_did_not_record() {
+        use crate::processing::transforms::tests::record_with;
+        use fitparser::profile::MesgNum;
+
+        let mut ctx = RecordContext::new(vec![record_with(
+            MesgNum::Set,
+            &[("set_type", Value::String("active".to_string()))],
+        )]);
+
+        CorrectSets {
+            corrections: parse_set_corrections("1: reps = 12, weight = 40").unwrap(),
+        }
+        .apply(&mut ctx);
+
+        let strength = analyze_strength_sets(ctx.records()).unwrap();
+        assert_eq!(strength.sets[0].repetitions, Some(12));
+        assert_eq!(strength.sets[0].weight_kg, Some(40.0));
+        let (_, warnings) = ctx.into_parts();
+        assert_eq!(warnings[0].message, "2 set values corrected");
+    }
+}
@@ -0,0 +1,197 @@
+use crate::processing::expressions::{DerivedField, evaluate_record};
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{RecordContext, Transform};
+use crate::processing::types::{ProcessingWarning, WarningCode};
+use fitparser::profile::MesgNum;
+use fitparser::{BaseType, FitDataField, FitDataRecord, TimestampKind, Value};
+
+/// FIT base type id of a 64-bit float, as written in `FieldDescription`.
+const FLOAT64_BASE_TYPE_ID: u8 = 0x89;
+
+/// Add user-defined derived fields to `Record` messages as developer fields,
+/// declared by a new `DeveloperDataId` and one `FieldDescription` each ahead
+/// of the first record.
+pub struct InjectDerivedFields {
+    pub fields: Vec<DerivedField>,
+}
+
+impl Transform for InjectDerivedFields {
+    fn name(&self) -> &'static str {
+        "inject_derived_fields"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let values: Vec<Option<Vec<Option<f64>>>> = ctx
+            .records()
+            .iter()
+            .map(|record| {
+                matches!(record.kind(), MesgNum::Record)
+                    .then(|| evaluate_record(&self.fields, record))
+            })
+            .collect();
+        if !values.iter().flatten().flatten().any(Option::is_some) {
+            return;
+        }
+        let Some(developer_index) = next_developer_index(ctx.records()) else {
+            ctx.warn(developer_index_exhausted(self.fields.len()));
+            return;
+        };
+
+        ctx.rebuild(|records| {
+            let mut output = Vec::with_capacity(records.len() + self.fields.len() + 1);
+            let mut declared = false;
+            for (mut record, values) in records.into_iter().zip(values) {
+                let Some(values) = values else {
+                    output.push(record);
+                    continue;
+                };
+                if !declared {
+                    output.extend(self.declarations(developer_index));
+                    declared = true;
+                }
+                for (number, (field, value)) in self.fields.iter().zip(values).enumerate() {
+                    if let Some(value) = value {
+                        record.push(FitDataField::with_meta(
+                            field.name.clone(),
+                            number as u8,
+                            Some(developer_index),
+                            Value::Float64(value),
+                            Value::Float64(value),
+                            String::new(),
+                            BaseType::Float64,
+                            1.0,
+                            0.0,
+                            TimestampKind::None,
+                        ));
+                    }
+                }
+                output.push(record);
+            }
+            output
+        });
+    }
+}
+
+impl InjectDerivedFields {
+    /// The `DeveloperDataId` and `FieldDescription` messages describing the
+    /// derived fields under `developer_index`.
+    fn declarations(&self, developer_index: u8) -> Vec<FitDataRecord> {
+        let field = |name: &str, number: u8, value: Value| {
+            FitDataField::new(name.to_string(), number, None, value, String::new())
+        };
+        let mut developer = FitDataRecord::new(MesgNum::DeveloperDataId);
+        developer.push(field(
+            "developer_data_index",
+            3,
+            Value::UInt8(developer_index),
+        ));
+
+        let mut declarations = vec![developer];
+        for (number, derived) in self.fields.iter().enumerate() {
+            let mut description = FitDataRecord::new(MesgNum::FieldDescription);
+            description.push(field(
+                "developer_data_index",
+                0,
+                Value::UInt8(developer_index),
+            ));
+            description.push(field(
+                "field_definition_number",
+                1,
+                Value::UInt8(number as u8),
+            ));
+            description.push(field(
+                "fit_base_type_id",
+                2,
+                Value::UInt8(FLOAT64_BASE_TYPE_ID),
+            ));
+            description.push(field("field_name", 3, Value::String(derived.name.clone())));
+            declarations.push(description);
+        }
+        declarations
+    }
+}
+
+/// First developer data index not already declared by the file; `None`
+/// when the file has declared the last one.
+pub(crate) fn next_developer_index(records: &[FitDataRecord]) -> Option<u8> {
+    records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::DeveloperDataId))
+        .flat_map(|record| record.fields())
+        .filter(|field| field.name() == "developer_data_index")
+        .filter_map(field_value_to_f64)
+        .try_fold(0u8, |next, index| {
+            let after = u8::try_from(index as u64).ok()?.checked_add(1)?;
+            Some(next.max(after))
+        })
+}
+
+/// Warning for `count` developer fields left out because
+/// [`next_developer_index`] found no free index.
+pub(crate) fn developer_index_exhausted(count: usize) -> ProcessingWarning {
+    ProcessingWarning::new(
+        WarningCode::DeveloperFieldsSkipped,
+        count,
+        "developer field",
+        "not written: the file already declares every developer data index",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::expressions::parse_definitions;
+    use crate::processing::transforms::tests::{field_names, record_with};
+
+    #[test]
+    fn declares_and_appends_developer_fields() {
+        let mut ctx = RecordContext::new(vec![
+            FitDataRecord::new(MesgNum::FileId),
+            record_with(
+                MesgNum::DeveloperDataId,
+                &[("developer_data_index", Value::UInt8(0))],
+            ),
+            record_with(MesgNum::Record, &[("power", Value::UInt16(144))]),
+            record_with(MesgNum::Record, &[("heart_rate", Value::UInt8(120))]),
+        ]);
+        let fields = parse_definitions("watts_per_kg = power / 72").unwrap();
+
+        InjectDerivedFields { fields }.apply(&mut ctx);
+
+        let kinds: Vec<MesgNum> = ctx.records().iter().map(|record| record.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                MesgNum::FileId,
+                MesgNum::DeveloperDataId,
+                MesgNum::DeveloperDataId,
+                MesgNum::FieldDescription,
+                MesgNum::Record,
+                MesgNum::Record,
+            ]
+        );
+        let injected = &ctx.records()[4].fields()[1];
+        assert_eq!(injected.developer_data_index(), Some(1));
+        assert_eq!(injected.value(), &Value::Float64(2.0));
+        // Records the expression can't be evaluated for are left alone.
+        assert_eq!(field_names(&ctx.records()[5]), vec!["heart_rate"]);
+    }
+
+    #[test]
+    fn skips_the_fields_when_no_developer_index_is_free() {
+        let mut ctx = RecordContext::new(vec![
+            record_with(
+                MesgNum::DeveloperDataId,
+                &[("developer_data_index", Value::UInt8(255))],
+            ),
+            record_with(MesgNum::Record, &[("power", Value::UInt16(144))]),
+        ]);
+        let fields = parse_definitions("watts_per_kg = power / 72").unwrap();
+
+        InjectDerivedFields { fields }.apply(&mut ctx);
+
+        assert_eq!(field_names(&ctx.records()[1]), vec!["power"]);
+        let (_, warnings) = ctx.into_parts();
+        assert_eq!(warnings[0].code, WarningCode::DeveloperFieldsSkipped);
+    }
+}
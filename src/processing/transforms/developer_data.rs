@@ -0,0 +1,119 @@
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform};
+use crate::processing::types::{ProcessingWarning, WarningCode};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use std::collections::HashSet;
+
+/// Drop developer fields that no earlier `DeveloperDataId` and
+/// `FieldDescription` declare, e.g. after a transform removed the
+/// declarations. Encoded without them, readers can't size the field and the
+/// rest of the file decodes as garbage.
+pub struct DropOrphanedDeveloperFields;
+
+impl Transform for DropOrphanedDeveloperFields {
+    fn name(&self) -> &'static str {
+        "drop_orphaned_developer_fields"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let mut developers: HashSet<u8> = HashSet::new();
+        let mut descriptions: HashSet<(u8, u8)> = HashSet::new();
+        let mut dropped = 0;
+        let mut declared_upto = None;
+        ctx.rewrite_fields(|idx, record, field| {
+            // A message's own declaration fields are not developer fields,
+            // so declaring before checking them is safe.
+            if declared_upto != Some(idx) {
+                declared_upto = Some(idx);
+                declare(record, &mut developers, &mut descriptions);
+            }
+            let Some(index) = field.developer_data_index() else {
+                return FieldEdit::Keep;
+            };
+            if developers.contains(&index) && descriptions.contains(&(index, field.number())) {
+                FieldEdit::Keep
+            } else {
+                dropped += 1;
+                FieldEdit::Drop
+            }
+        });
+        if dropped > 0 {
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::OrphanedDeveloperFieldsDropped,
+                dropped,
+                "developer field",
+                "dropped because its description was removed",
+            ));
+        }
+    }
+}
+
+fn declare(
+    record: &FitDataRecord,
+    developers: &mut HashSet<u8>,
+    descriptions: &mut HashSet<(u8, u8)>,
+) {
+    let number = |name: &str| {
+        record
+            .fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .and_then(field_value_to_f64)
+            .map(|value| value as u8)
+    };
+    match record.kind() {
+        MesgNum::DeveloperDataId => developers.extend(number("developer_data_index")),
+        MesgNum::FieldDescription => descriptions
+            .extend(number("developer_data_index").zip(number("field_definition_number"))),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::{field_names, record_with};
+    use fitparser::{FitDataField, Value};
+
+    fn record_with_developer_field(index: u8) -> FitDataRecord {
+        let mut record = record_with(MesgNum::Record, &[("heart_rate", Value::UInt8(150))]);
+        record.push(FitDataField::new(
+            "stryd_power".to_string(),
+            0,
+            Some(index),
+            Value::UInt16(250),
+            "W".to_string(),
+        ));
+        record
+    }
+
+    #[test]
+    fn keeps_declared_fields_and_drops_the_rest() {
+        let mut ctx = RecordContext::new(vec![
+            record_with_developer_field(0),
+            record_with(
+                MesgNum::DeveloperDataId,
+                &[("developer_data_index", Value::UInt8(0))],
+            ),
+            record_with(
+                MesgNum::FieldDescription,
+                &[
+                    ("developer_data_index", Value::UInt8(0)),
+                    ("field_definition_number", Value::UInt8(0)),
+                ],
+            ),
+            record_with_developer_field(0),
+            record_with_developer_field(1),
+        ]);
+
+        DropOrphanedDeveloperFields.apply(&mut ctx);
+
+        let names: Vec<Vec<&str>> = ctx.records().iter().map(field_names).collect();
+        assert_eq!(names[0], vec!["heart_rate"]);
+        assert_eq!(names[3], vec!["heart_rate", "stryd_power"]);
+        assert_eq!(names[4], vec!["heart_rate"]);
+        let (_, warnings) = ctx.into_parts();
+        assert_eq!(warnings[0].count, 2);
+    }
+}
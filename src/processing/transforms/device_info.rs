@@ -0,0 +1,39 @@
+use crate::processing::transforms::{RecordContext, Transform};
+use fitparser::profile::MesgNum;
+
+/// Remove `DeviceInfo` messages (device, firmware, and sensor serials).
+pub struct StripDeviceInfo;
+
+impl Transform for StripDeviceInfo {
+    fn name(&self) -> &'static str {
+        "strip_device_info"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        ctx.retain_records(|record| !matches!(record.kind(), MesgNum::DeviceInfo));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fitparser::FitDataRecord;
+
+    #[test]
+    fn drops_device_info_messages() {
+        let mut ctx = RecordContext::new(vec![
+            FitDataRecord::new(MesgNum::FileId),
+            FitDataRecord::new(MesgNum::DeviceInfo),
+            FitDataRecord::new(MesgNum::Record),
+        ]);
+
+        StripDeviceInfo.apply(&mut ctx);
+
+        assert_eq!(ctx.records().len(), 2);
+        assert!(
+            ctx.records()
+                .iter()
+                .all(|record| !matches!(record.kind(), MesgNum::DeviceInfo))
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Stitching of `distance` counters that reset mid-activity.
+//!
+//! Some devices restart the distance from 0 after a crash and resume, so the
+//! series falls back to near zero and climbs again from there. Every later
+//! sample is offset by what was lost, which leaves the distance flat across
+//! the reset and continuing from where it was. A single sample that dips
+//! below its neighbours while the counter carries on, such as 20, 0, 21, is a
+//! glitch rather than a reset and is interpolated from the samples either
+//! side instead. Other backward steps are left as recorded. Lap and session
+//! `total_distance` take the change in distance over their span.
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{
+    FieldEdit, RecordContext, Transform, numeric_value_like, record_timestamp,
+};
+use crate::processing::types::{ProcessingWarning, WarningCode};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use std::collections::HashMap;
+
+/// Highest distance a restarted counter can show at its first sample, in
+/// metres; larger drops are not treated as resets.
+pub const RESET_MAX_METERS: f64 = 100.0;
+
+/// Offset `Record` distances after each point where the counter restarts.
+pub struct StitchDistanceResets;
+
+/// One `Record` distance as recorded.
+struct Sample {
+    index: usize,
+    timestamp: Option<f64>,
+    distance: f64,
+}
+
+impl Transform for StitchDistanceResets {
+    fn name(&self) -> &'static str {
+        "stitch_distance_resets"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let samples: Vec<Sample> = ctx
+            .records()
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| matches!(record.kind(), MesgNum::Record))
+            .filter_map(|(index, record)| {
+                Some(Sample {
+                    index,
+                    timestamp: record_timestamp(record),
+                    distance: field(record, "distance")?,
+                })
+            })
+            .collect();
+
+        let mut stitched: HashMap<usize, f64> = HashMap::new();
+        let (mut resets, mut dips) = (0, 0);
+        let mut offset = 0.0;
+        let mut last: Option<(Option<f64>, f64)> = None;
+        for (position, sample) in samples.iter().enumerate() {
+            let mut distance = sample.distance + offset;
+            let next = samples.get(position + 1);
+            if let Some((last_timestamp, last_distance)) = last.filter(|&(_, last)| distance < last)
+            {
+                let next_distance = next.map(|next| next.distance + offset);
+                if let Some(next_distance) = next_distance.filter(|&next| next >= last_distance) {
+                    dips += 1;
+                    distance = interpolate(
+                        (last_timestamp, last_distance),
+                        (next.and_then(|next| next.timestamp), next_distance),
+                        sample.timestamp,
+                    );
+                    stitched.insert(sample.index, distance);
+                    last = Some((sample.timestamp, distance));
+                    continue;
+                }
+                let keeps_counting = next_distance.is_some_and(|next| next >= distance);
+                if sample.distance <= RESET_MAX_METERS && keeps_counting {
+                    resets += 1;
+                    offset += last_distance - distance;
+                    distance = last_distance;
+                }
+            }
+            if offset != 0.0 {
+                stitched.insert(sample.index, distance);
+            }
+            last = Some((sample.timestamp, distance));
+        }
+        if stitched.is_empty() {
+            return;
+        }
+
+        let totals = span_totals(ctx.records(), &samples, &stitched);
+        ctx.rewrite_fields(|index, record, field| {
+            let replacement = match (record.kind(), field.name()) {
+                (MesgNum::Record, "distance") => stitched.get(&index).copied(),
+                (MesgNum::Lap | MesgNum::Session, "total_distance") => totals.get(&index).copied(),
+                _ => None,
+            };
+            match replacement.and_then(|value| numeric_value_like(field.value(), value)) {
+                Some(value) => FieldEdit::Replace(value),
+                None => FieldEdit::Keep,
+            }
+        });
+        if resets > 0 {
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::DistanceResetsStitched,
+                resets,
+                "distance reset",
+                "stitched by offsetting the distances after it",
+            ));
+        }
+        if dips > 0 {
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::DistanceDipsInterpolated,
+                dips,
+                "distance dip",
+                "interpolated from the samples either side",
+            ));
+        }
+    }
+}
+
+fn field(record: &FitDataRecord, name: &str) -> Option<f64> {
+    record
+        .fields()
+        .iter()
+        .find(|field| field.name() == name)
+        .and_then(field_value_to_f64)
+}
+
+/// Distance at `timestamp` on the line between `before` and `after`, or
+/// halfway between them without timestamps.
+fn interpolate(
+    (before_timestamp, before): (Option<f64>, f64),
+    (after_timestamp, after): (Option<f64>, f64),
+    timestamp: Option<f64>,
+) -> f64 {
+    let share = match (before_timestamp, timestamp, after_timestamp) {
+        (Some(start), Some(at), Some(end)) if end > start => {
+            ((at - start) / (end - start)).clamp(0.0, 1.0)
+        }
+        _ => 0.5,
+    };
+    before + (after - before) * share
+}
+
+/// Lap and session `total_distance` plus what stitching added between the
+/// first and last record distance of their span, by message index.
+fn span_totals(
+    records: &[FitDataRecord],
+    samples: &[Sample],
+    stitched: &HashMap<usize, f64>,
+) -> HashMap<usize, f64> {
+    records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| matches!(record.kind(), MesgNum::Lap | MesgNum::Session))
+        .filter_map(|(index, record)| {
+            let total = field(record, "total_distance")?;
+            let (from, to) = (field(record, "start_time")?, record_timestamp(record)?);
+            let mut spanned = samples
+                .iter()
+                .filter(|sample| sample.timestamp.is_some_and(|at| (from..=to).contains(&at)));
+            let first = spanned.next()?;
+            let last = spanned.last().unwrap_or(first);
+            let corrected = |sample: &Sample| {
+                stitched
+                    .get(&sample.index)
+                    .copied()
+                    .unwrap_or(sample.distance)
+            };
+            let added = (corrected(last) - corrected(first)) - (last.distance - first.distance);
+            (added != 0.0).then_some((index, (total + added).max(0.0)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+
+    fn stitch(distances: &[f64]) -> (Vec<f64>, Vec<ProcessingWarning>) {
+        let records = distances
+            .iter()
+            .enumerate()
+            .map(|(second, &distance)| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("distance", Value::Float64(distance)),
+                        ("timestamp", Value::Float64(second as f64)),
+                    ],
+                )
+            })
+            .collect();
+        let mut ctx = RecordContext::new(records);
+
+        StitchDistanceResets.apply(&mut ctx);
+
+        let (records, warnings) = ctx.into_parts();
+        let distances = records
+            .iter()
+            .filter_map(|record| field_value_to_f64(&record.fields()[0]))
+            .collect();
+        (distances, warnings)
+    }
+
+    #[test]
+    fn distances_continue_across_a_reset() {
+        let (stitched, warnings) = stitch(&[0.0, 10.0, 20.0, 0.0, 10.0, 15.0, 25.0]);
+
+        assert_eq!(stitched, vec![0.0, 10.0, 20.0, 20.0, 30.0, 35.0, 45.0]);
+        assert_eq!(warnings[0].code, WarningCode::DistanceResetsStitched);
+        assert_eq!(warnings[0].count, 1);
+    }
+
+    #[test]
+    fn isolated_dips_are_interpolated_not_stitched() {
+        let (stitched, warnings) = stitch(&[10.0, 20.0, 0.0, 22.0, 30.0]);
+
+        assert_eq!(stitched, vec![10.0, 20.0, 21.0, 22.0, 30.0]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, WarningCode::DistanceDipsInterpolated);
+    }
+
+    #[test]
+    fn other_backward_steps_are_left_as_recorded() {
+        // Too far from zero to be a restart, and the counter does not recover.
+        let (stitched, warnings) = stitch(&[1000.0, 2000.0, 1500.0, 1600.0]);
+
+        assert_eq!(stitched, vec![1000.0, 2000.0, 1500.0, 1600.0]);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn lap_and_session_totals_take_the_stitched_distance() {
+        let mut records: Vec<FitDataRecord> = [0.0, 500.0, 1000.0, 0.0, 400.0]
+            .iter()
+            .enumerate()
+            .map(|(second, &distance)| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("distance", Value::Float64(distance)),
+                        ("timestamp", Value::Float64(second as f64)),
+                    ],
+                )
+            })
+            .collect();
+        for kind in [MesgNum::Lap, MesgNum::Session] {
+            records.push(record_with(
+                kind,
+                &[
+                    ("total_distance", Value::Float64(400.0)),
+                    ("start_time", Value::Float64(0.0)),
+                    ("timestamp", Value::Float64(4.0)),
+                ],
+            ));
+        }
+        let mut ctx = RecordContext::new(records);
+
+        StitchDistanceResets.apply(&mut ctx);
+
+        let totals: Vec<f64> = ctx.records()[5..]
+            .iter()
+            .filter_map(|record| field_value_to_f64(&record.fields()[0]))
+            .collect();
+        assert_eq!(totals, vec![1400.0, 1400.0]);
+    }
+}
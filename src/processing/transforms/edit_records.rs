@@ -0,0 +1,156 @@
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform, numeric_value_like};
+use crate::processing::types::{HashableF64, RecordEdit};
+use fitparser::FitDataRecord;
+use std::fmt;
+
+/// Apply [`RecordEdit`]s to single messages, e.g. a heart-rate spike the
+/// sensor recorded on one record. Runs after every other transform, so
+/// indexes refer to the processed file.
+pub struct EditRecords {
+    pub edits: Vec<RecordEdit>,
+}
+
+impl Transform for EditRecords {
+    fn name(&self) -> &'static str {
+        "edit_records"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        ctx.rewrite_fields(|idx, _, field| {
+            // Later edits of the same field win.
+            let edit = self.edits.iter().rev().find(|edit| {
+                edit.index == idx
+                    && edit.field == field.name()
+                    && field.developer_data_index().is_none()
+            });
+            match edit.map(|edit| edit.value) {
+                None => FieldEdit::Keep,
+                Some(None) => FieldEdit::Drop,
+                Some(Some(HashableF64(value))) => numeric_value_like(field.value(), value)
+                    .map_or(FieldEdit::Keep, FieldEdit::Replace),
+            }
+        });
+    }
+}
+
+/// Why an edit cannot be applied to the processed file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    NoMessage(usize),
+    NoField { index: usize, field: String },
+    NotNumeric(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::NoMessage(index) => write!(f, "No message at index {index}"),
+            EditError::NoField { index, field } => {
+                write!(f, "Message {index} has no {field} field")
+            }
+            EditError::NotNumeric(field) => write!(f, "{field} is not a numeric field"),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// Check that every edit names a numeric field present on its message of
+/// `records`, the processed file. Only existing fields can be edited, as
+/// their definition gives the type the new value is written with.
+pub fn check_edits(records: &[FitDataRecord], edits: &[RecordEdit]) -> Result<(), EditError> {
+    for edit in edits {
+        let record = records
+            .get(edit.index)
+            .ok_or(EditError::NoMessage(edit.index))?;
+        let field = record
+            .fields()
+            .iter()
+            .find(|field| field.name() == edit.field && field.developer_data_index().is_none())
+            .ok_or_else(|| EditError::NoField {
+                index: edit.index,
+                field: edit.field.clone(),
+            })?;
+        let numeric =
+            field_value_to_f64(field).is_some() && numeric_value_like(field.value(), 0.0).is_some();
+        if edit.value.is_some() && !numeric {
+            return Err(EditError::NotNumeric(edit.field.clone()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::{field_names, record_with};
+    use fitparser::Value;
+    use fitparser::profile::MesgNum;
+
+    fn edit(index: usize, field: &str, value: Option<f64>) -> RecordEdit {
+        RecordEdit {
+            index,
+            field: field.to_string(),
+            value: value.map(HashableF64),
+        }
+    }
+
+    #[test]
+    fn edits_set_or_remove_fields_of_one_message() {
+        let records = vec![
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("heart_rate", Value::UInt8(220)),
+                    ("cadence", Value::UInt8(90)),
+                ],
+            ),
+            record_with(MesgNum::Record, &[("heart_rate", Value::UInt8(140))]),
+        ];
+        let edits = vec![
+            edit(0, "heart_rate", Some(150.0)),
+            edit(0, "heart_rate", Some(143.0)),
+            edit(0, "cadence", None),
+        ];
+        assert_eq!(check_edits(&records, &edits), Ok(()));
+
+        let mut ctx = RecordContext::new(records);
+        EditRecords { edits }.apply(&mut ctx);
+
+        assert_eq!(field_names(&ctx.records()[0]), vec!["heart_rate"]);
+        assert_eq!(ctx.records()[0].fields()[0].value(), &Value::UInt8(143));
+        assert_eq!(ctx.records()[1].fields()[0].value(), &Value::UInt8(140));
+    }
+
+    #[test]
+    fn edits_must_name_an_existing_numeric_field() {
+        let records = vec![record_with(
+            MesgNum::FileId,
+            &[
+                ("manufacturer", Value::String("garmin".to_string())),
+                ("product", Value::UInt16(1)),
+            ],
+        )];
+
+        assert_eq!(
+            check_edits(&records, &[edit(1, "product", Some(2.0))]),
+            Err(EditError::NoMessage(1))
+        );
+        assert_eq!(
+            check_edits(&records, &[edit(0, "serial_number", Some(2.0))]),
+            Err(EditError::NoField {
+                index: 0,
+                field: "serial_number".to_string()
+            })
+        );
+        assert_eq!(
+            check_edits(&records, &[edit(0, "manufacturer", Some(2.0))]),
+            Err(EditError::NotNumeric("manufacturer".to_string()))
+        );
+        assert_eq!(
+            check_edits(&records, &[edit(0, "manufacturer", None)]),
+            Ok(())
+        );
+    }
+}
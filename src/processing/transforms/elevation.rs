@@ -0,0 +1,105 @@
+//! Record altitudes replaced with values from an external elevation model.
+//!
+//! The lookup itself is asynchronous and done by the server before
+//! processing; this transform only writes the results, given by recorded
+//! position, into the records' existing `altitude` and `enhanced_altitude`
+//! fields and recomputes lap and session ascent and descent from them.
+
+use crate::processing::anonymize::record_semicircles;
+use crate::processing::transforms::altitude_drift::{altitude_edit, lap_climbs};
+use crate::processing::transforms::{RecordContext, Transform};
+use crate::processing::types::{HashableF64, ProcessingWarning, WarningCode};
+use std::collections::{BTreeMap, HashMap};
+
+/// Write looked-up altitudes into the records they were looked up for.
+pub struct ReplaceAltitude {
+    /// Altitude for each recorded position, in semicircles.
+    pub altitudes: BTreeMap<(i32, i32), HashableF64>,
+}
+
+impl Transform for ReplaceAltitude {
+    fn name(&self) -> &'static str {
+        "replace_altitude"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let altitudes: HashMap<usize, f64> = ctx
+            .records()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, record)| {
+                let altitude = self.altitudes.get(&record_semicircles(record)?)?;
+                Some((index, altitude.0))
+            })
+            .collect();
+        if altitudes.is_empty() {
+            return;
+        }
+        let climbs = lap_climbs(ctx.records(), &altitudes);
+        ctx.rewrite_fields(|index, record, field| {
+            altitude_edit(&altitudes, &climbs, index, record, field)
+        });
+        ctx.warn(ProcessingWarning::new(
+            WarningCode::ElevationReplaced,
+            altitudes.len(),
+            "altitude sample",
+            "replaced from the elevation service",
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::summary::field_value_to_f64;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+    use fitparser::profile::MesgNum;
+
+    #[test]
+    fn altitudes_are_written_by_position() {
+        let mut ctx = RecordContext::new(vec![
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("timestamp", Value::Float64(0.0)),
+                    ("enhanced_altitude", Value::Float64(-20.0)),
+                    ("position_lat", Value::SInt32(100)),
+                    ("position_long", Value::SInt32(200)),
+                ],
+            ),
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("timestamp", Value::Float64(1.0)),
+                    ("enhanced_altitude", Value::Float64(-20.0)),
+                    ("position_lat", Value::SInt32(101)),
+                    ("position_long", Value::SInt32(200)),
+                ],
+            ),
+            record_with(
+                MesgNum::Session,
+                &[
+                    ("timestamp", Value::Float64(1.0)),
+                    ("start_time", Value::Float64(0.0)),
+                    ("total_ascent", Value::UInt16(0)),
+                ],
+            ),
+        ]);
+
+        // Looked up before a vendor fix dropped a message ahead of them.
+        ReplaceAltitude {
+            altitudes: BTreeMap::from([
+                ((101, 200), HashableF64(418.0)),
+                ((100, 200), HashableF64(412.0)),
+                ((0, 0), HashableF64(3.0)),
+            ]),
+        }
+        .apply(&mut ctx);
+
+        let value = |index: usize| field_value_to_f64(&ctx.records()[index].fields()[1]);
+        assert_eq!(value(0), Some(412.0));
+        assert_eq!(value(1), Some(418.0));
+        assert_eq!(value(2), Some(6.0));
+    }
+}
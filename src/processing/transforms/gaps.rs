@@ -0,0 +1,197 @@
+use crate::processing::preprocess::with_value;
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{
+    RecordContext, Transform, numeric_value_like, record_timestamp, timestamp_value,
+};
+use crate::processing::types::GAP_FILL_MAX_SECONDS;
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataField, FitDataRecord};
+
+/// Fields linearly interpolated into the records injected for a gap.
+const INTERPOLATED_FIELDS: &[&str] = &[
+    "distance",
+    "position_lat",
+    "position_long",
+    "heart_rate",
+    "altitude",
+    "enhanced_altitude",
+    "speed",
+    "enhanced_speed",
+    "cadence",
+    "power",
+];
+
+/// Fill gaps between records with one record per second, interpolating
+/// [`INTERPOLATED_FIELDS`] between the records on either side.
+///
+/// With a large `min_gap_seconds` this patches dropouts such as tunnels; with
+/// `1.0` it converts smart (variable-rate) recording to strict 1-second records.
+/// Gaps that contain a timer stop are pauses and are left alone, as are gaps
+/// longer than [`GAP_FILL_MAX_SECONDS`].
+pub struct FillGaps {
+    pub min_gap_seconds: f64,
+}
+
+impl Transform for FillGaps {
+    fn name(&self) -> &'static str {
+        "fill_gaps"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        ctx.rebuild(|records| {
+            let mut filled = Vec::with_capacity(records.len());
+            let mut previous: Option<(usize, f64)> = None;
+            let mut timer_stopped = false;
+
+            for record in records {
+                if is_timer_stop(&record) {
+                    timer_stopped = true;
+                }
+                let timestamp = matches!(record.kind(), MesgNum::Record)
+                    .then(|| record_timestamp(&record))
+                    .flatten();
+
+                if let Some(ts) = timestamp {
+                    if let Some((prev_idx, prev_ts)) = previous {
+                        let gap = ts - prev_ts;
+                        if !timer_stopped
+                            && gap > self.min_gap_seconds
+                            && gap <= GAP_FILL_MAX_SECONDS
+                        {
+                            let before: &FitDataRecord = &filled[prev_idx];
+                            let injected = interpolate_gap(before, prev_ts, &record, ts);
+                            filled.extend(injected);
+                        }
+                    }
+                    previous = Some((filled.len(), ts));
+                    timer_stopped = false;
+                }
+                filled.push(record);
+            }
+
+            filled
+        });
+    }
+}
+
+/// Records at each whole second strictly between `start_ts` and `end_ts`.
+fn interpolate_gap(
+    before: &FitDataRecord,
+    start_ts: f64,
+    after: &FitDataRecord,
+    end_ts: f64,
+) -> Vec<FitDataRecord> {
+    let span = end_ts - start_ts;
+    let timestamp_field = before
+        .fields()
+        .iter()
+        .find(|field| field.name() == "timestamp");
+    let pairs: Vec<(&FitDataField, f64, f64)> = before
+        .fields()
+        .iter()
+        .filter(|field| INTERPOLATED_FIELDS.contains(&field.name()))
+        .filter_map(|field| {
+            let start = field_value_to_f64(field)?;
+            let end = after
+                .fields()
+                .iter()
+                .find(|other| other.name() == field.name())
+                .and_then(field_value_to_f64)?;
+            Some((field, start, end))
+        })
+        .collect();
+
+    let mut injected = Vec::new();
+    let mut ts = start_ts.floor() + 1.0;
+    while ts < end_ts {
+        let fraction = (ts - start_ts) / span;
+        let mut record = FitDataRecord::new(MesgNum::Record);
+        if let Some((field, value)) = timestamp_field.zip(timestamp_value(ts)) {
+            record.push(with_value(field, value));
+        }
+        for &(field, start, end) in &pairs {
+            if let Some(value) = numeric_value_like(field.value(), start + (end - start) * fraction)
+            {
+                record.push(with_value(field, value));
+            }
+        }
+        injected.push(record);
+        ts += 1.0;
+    }
+    injected
+}
+
+fn is_timer_stop(record: &FitDataRecord) -> bool {
+    if !matches!(record.kind(), MesgNum::Event) {
+        return false;
+    }
+    let field_text = |name: &str| {
+        record
+            .fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .map(|field| field.to_string())
+    };
+    field_text("event").as_deref() == Some("timer")
+        && field_text("event_type").is_some_and(|kind| kind != "start")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+
+    #[test]
+    fn interpolates_values_at_each_missing_second() {
+        let before = record_with(
+            MesgNum::Record,
+            &[
+                ("distance", Value::Float64(100.0)),
+                ("heart_rate", Value::UInt8(120)),
+            ],
+        );
+        let after = record_with(
+            MesgNum::Record,
+            &[
+                ("distance", Value::Float64(140.0)),
+                ("heart_rate", Value::UInt8(140)),
+            ],
+        );
+
+        let injected = interpolate_gap(&before, 10.0, &after, 14.0);
+
+        assert_eq!(injected.len(), 3);
+        let middle: Vec<f64> = injected[1]
+            .fields()
+            .iter()
+            .filter_map(field_value_to_f64)
+            .collect();
+        assert_eq!(middle, vec![120.0, 130.0]);
+    }
+
+    #[test]
+    fn one_second_mode_fills_every_smart_recording_interval() {
+        let records = [0.0, 3.0, 4.0]
+            .iter()
+            .map(|&ts| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", Value::Float64(1000.0 + ts)),
+                        ("distance", Value::Float64(ts * 2.0)),
+                    ],
+                )
+            })
+            .collect();
+        let mut ctx = RecordContext::new(records);
+
+        FillGaps {
+            min_gap_seconds: 1.0,
+        }
+        .apply(&mut ctx);
+
+        let timestamps: Vec<f64> = ctx.records().iter().filter_map(record_timestamp).collect();
+        assert_eq!(timestamps, vec![1000.0, 1001.0, 1002.0, 1003.0, 1004.0]);
+    }
+}
@@ -0,0 +1,18 @@
+use crate::processing::transforms::title::{set_session_text, truncate};
+use crate::processing::transforms::{RecordContext, Transform};
+
+/// Tag the activity with the bike or shoes used, as a `gear` developer field
+/// on `Session` messages.
+pub struct TagGear {
+    pub gear: String,
+}
+
+impl Transform for TagGear {
+    fn name(&self) -> &'static str {
+        "tag_gear"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        set_session_text(ctx, "gear", truncate(&self.gear));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform};
+use fitparser::profile::MesgNum;
+use fitparser::{FitDataRecord, Value};
+
+/// Location fields removed from records, laps, and sessions by indoor flattening.
+pub(crate) const INDOOR_POSITION_FIELDS: &[&str] = &[
+    "position_lat",
+    "position_long",
+    "gps_accuracy",
+    "start_position_lat",
+    "start_position_long",
+    "end_position_lat",
+    "end_position_long",
+    "nec_lat",
+    "nec_long",
+    "swc_lat",
+    "swc_long",
+];
+
+/// Altitude fields removed from records, laps, and sessions by indoor flattening.
+const INDOOR_ALTITUDE_FIELDS: &[&str] = &[
+    "altitude",
+    "enhanced_altitude",
+    "grade",
+    "vertical_speed",
+    "total_ascent",
+    "total_descent",
+    "avg_altitude",
+    "max_altitude",
+    "min_altitude",
+    "enhanced_avg_altitude",
+    "enhanced_max_altitude",
+    "enhanced_min_altitude",
+];
+
+/// Strip GPS and altitude data and mark the activity's `sub_sport` as indoor.
+///
+/// Only existing `sub_sport` fields are rewritten; messages without one are
+/// left as they are.
+pub struct FlattenIndoor;
+
+impl Transform for FlattenIndoor {
+    fn name(&self) -> &'static str {
+        "flatten_indoor"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let file_sport = ctx.records().iter().find_map(record_sport);
+
+        ctx.rewrite_fields(|_, record, field| {
+            let name = field.name();
+            let flatten_message = matches!(
+                record.kind(),
+                MesgNum::Record | MesgNum::Lap | MesgNum::Session
+            );
+            if flatten_message
+                && (INDOOR_POSITION_FIELDS.contains(&name)
+                    || INDOOR_ALTITUDE_FIELDS.contains(&name))
+            {
+                return FieldEdit::Drop;
+            }
+
+            if name == "sub_sport" {
+                let sub_sport = record_sport(record)
+                    .or_else(|| file_sport.clone())
+                    .and_then(|sport| indoor_sub_sport(&sport));
+                if let Some(code) = sub_sport {
+                    return FieldEdit::Replace(Value::Enum(code));
+                }
+            }
+
+            FieldEdit::Keep
+        });
+    }
+}
+
+fn record_sport(record: &FitDataRecord) -> Option<String> {
+    record
+        .fields()
+        .iter()
+        .find(|field| field.name() == "sport")
+        .map(|field| field.to_string())
+}
+
+/// FIT `sub_sport` code for the indoor variant of a sport, if one exists.
+fn indoor_sub_sport(sport: &str) -> Option<u8> {
+    match sport {
+        "running" => Some(1),  // treadmill
+        "cycling" => Some(6),  // indoor_cycling
+        "rowing" => Some(14),  // indoor_rowing
+        "walking" => Some(27), // indoor_walking
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::{field_names, record_with};
+
+    #[test]
+    fn indoor_sub_sport_covers_common_sports() {
+        assert_eq!(indoor_sub_sport("running"), Some(1));
+        assert_eq!(indoor_sub_sport("cycling"), Some(6));
+        assert_eq!(indoor_sub_sport("swimming"), None);
+    }
+
+    #[test]
+    fn strips_position_and_altitude_from_records() {
+        let mut ctx = RecordContext::new(vec![record_with(
+            MesgNum::Record,
+            &[
+                ("position_lat", Value::SInt32(1)),
+                ("enhanced_altitude", Value::Float64(120.0)),
+                ("heart_rate", Value::UInt8(150)),
+            ],
+        )]);
+
+        FlattenIndoor.apply(&mut ctx);
+
+        assert_eq!(field_names(&ctx.records()[0]), vec!["heart_rate"]);
+    }
+}
@@ -0,0 +1,440 @@
+//! Ordered record transforms applied by [`crate::processing::preprocess::preprocess_fit`].
+//!
+//! Each transform lives in its own module and only sees a [`RecordContext`], so
+//! new rewrites can be added without touching the others.
+
+pub mod altitude_drift;
+pub mod bike_profile;
+pub mod calibrate_distance;
+pub mod calibrate_power;
+pub mod correct_sets;
+pub mod derived_fields;
+pub mod developer_data;
+pub mod device_info;
+pub mod distance_resets;
+pub mod edit_records;
+pub mod elevation;
+pub mod gaps;
+pub mod gear;
+pub mod indoor;
+pub mod open_water;
+pub mod remove_fields;
+pub mod remove_speed;
+pub mod replace_values;
+pub mod resample;
+pub mod smooth_speed;
+pub mod strip_ebike;
+pub mod summary_speeds;
+pub mod timestamps;
+pub mod title;
+
+use crate::processing::plugins::registered_plugin;
+use crate::processing::preprocess::with_value;
+use crate::processing::progress::{NoObserver, PROGRESS_CHUNK_RECORDS, ProcessingObserver};
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::types::{
+    GAP_FILL_MIN_SECONDS, HashableF64, ProcessingOptions, ProcessingWarning, SmoothingResiduals,
+};
+use chrono::{DateTime, Local};
+use fitparser::{FitDataField, FitDataRecord, Value};
+
+/// A single rewrite step over the full list of decoded records.
+pub trait Transform {
+    /// Short identifier used in logs and diagnostics.
+    fn name(&self) -> &'static str;
+
+    fn apply(&self, ctx: &mut RecordContext);
+}
+
+/// Registered plugins are shared, so the pipeline holds them behind an `Arc`.
+impl<T: Transform + ?Sized> Transform for std::sync::Arc<T> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        (**self).apply(ctx)
+    }
+}
+
+/// Records flowing through the pipeline, plus warnings raised along the way.
+///
+/// The record helpers ask `observer` every [`PROGRESS_CHUNK_RECORDS`] records
+/// whether processing was cancelled, and leave the rest untouched once it is.
+#[derive(Clone)]
+pub struct RecordContext<'a> {
+    records: Vec<FitDataRecord>,
+    warnings: Vec<ProcessingWarning>,
+    smoothing: Option<SmoothingResiduals>,
+    observer: &'a dyn ProcessingObserver,
+    cancelled: bool,
+}
+
+impl std::fmt::Debug for RecordContext<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordContext")
+            .field("records", &self.records)
+            .field("warnings", &self.warnings)
+            .field("smoothing", &self.smoothing)
+            .field("cancelled", &self.cancelled)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for RecordContext<'_> {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+/// What to do with one field while rewriting a record.
+pub enum FieldEdit {
+    Keep,
+    Drop,
+    Replace(Value),
+}
+
+impl<'a> RecordContext<'a> {
+    pub fn new(records: Vec<FitDataRecord>) -> Self {
+        Self::observed(records, &NoObserver)
+    }
+
+    /// A context whose record helpers stop early once `observer` is cancelled.
+    pub fn observed(records: Vec<FitDataRecord>, observer: &'a dyn ProcessingObserver) -> Self {
+        Self {
+            records,
+            warnings: Vec::new(),
+            smoothing: None,
+            observer,
+            cancelled: false,
+        }
+    }
+
+    /// Whether a record helper stopped early because processing was cancelled.
+    ///
+    /// The records are then only partly rewritten and must not be used.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Ask the observer at chunk boundaries, remembering a cancellation.
+    fn poll_cancelled(&mut self, index: usize) -> bool {
+        if !self.cancelled && index % PROGRESS_CHUNK_RECORDS == 0 {
+            self.cancelled = self.observer.is_cancelled();
+        }
+        self.cancelled
+    }
+
+    pub fn records(&self) -> &[FitDataRecord] {
+        &self.records
+    }
+
+    pub fn into_parts(self) -> (Vec<FitDataRecord>, Vec<ProcessingWarning>) {
+        (self.records, self.warnings)
+    }
+
+    /// Report a problem found or fixed by a transform.
+    pub fn warn(&mut self, warning: ProcessingWarning) {
+        self.warnings.push(warning);
+    }
+
+    /// Record how far speed smoothing moved the series.
+    pub fn set_smoothing_residuals(&mut self, residuals: SmoothingResiduals) {
+        self.smoothing = Some(residuals);
+    }
+
+    pub fn smoothing_residuals(&self) -> Option<&SmoothingResiduals> {
+        self.smoothing.as_ref()
+    }
+
+    /// Rebuild every record, keeping, dropping, or replacing each field.
+    ///
+    /// `edit` receives the record index, the record, and the field under
+    /// consideration; replaced values keep the original field metadata.
+    /// Records where every field is kept are left in place, and rebuilt
+    /// records move their kept fields instead of cloning them.
+    pub fn rewrite_fields(
+        &mut self,
+        mut edit: impl FnMut(usize, &FitDataRecord, &FitDataField) -> FieldEdit,
+    ) {
+        let mut edits: Vec<FieldEdit> = Vec::new();
+
+        for idx in 0..self.records.len() {
+            if self.poll_cancelled(idx) {
+                return;
+            }
+            let record = &mut self.records[idx];
+            edits.clear();
+            edits.extend(record.fields().iter().map(|field| edit(idx, record, field)));
+            if edits.iter().all(|edit| matches!(edit, FieldEdit::Keep)) {
+                continue;
+            }
+
+            let original = std::mem::replace(record, FitDataRecord::new(record.kind()));
+            for (field, edit) in original.into_vec().into_iter().zip(edits.drain(..)) {
+                match edit {
+                    FieldEdit::Keep => record.push(field),
+                    FieldEdit::Drop => {}
+                    FieldEdit::Replace(value) => record.push(with_value(&field, value)),
+                }
+            }
+        }
+    }
+
+    /// Replace the record list wholesale, for transforms that insert messages.
+    ///
+    /// `build` is skipped once processing has been cancelled.
+    pub fn rebuild(&mut self, build: impl FnOnce(Vec<FitDataRecord>) -> Vec<FitDataRecord>) {
+        if self.poll_cancelled(0) {
+            return;
+        }
+        self.records = build(std::mem::take(&mut self.records));
+    }
+
+    /// Remove whole messages for which `keep` returns false.
+    pub fn retain_records(&mut self, mut keep: impl FnMut(&FitDataRecord) -> bool) {
+        let mut index = 0;
+        let mut cancelled = self.cancelled;
+        let observer = self.observer;
+        self.records.retain(|record| {
+            if !cancelled && index % PROGRESS_CHUNK_RECORDS == 0 {
+                cancelled = observer.is_cancelled();
+            }
+            index += 1;
+            cancelled || keep(record)
+        });
+        self.cancelled = cancelled;
+    }
+}
+
+/// Build the ordered transform list enabled by `options`.
+pub fn build_pipeline(options: &ProcessingOptions) -> Vec<Box<dyn Transform>> {
+    let mut pipeline: Vec<Box<dyn Transform>> = Vec::new();
+
+    // First, while positions are still the ones they were looked up for.
+    if !options.looked_up_altitudes.is_empty() {
+        pipeline.push(Box::new(elevation::ReplaceAltitude {
+            altitudes: options.looked_up_altitudes.clone(),
+        }));
+    }
+    if let Some(mode) = options.timestamp_repair {
+        pipeline.push(Box::new(timestamps::RepairTimestamps { mode }));
+    }
+    // Stitched before anything interpolates, rescales, or smooths distances.
+    if options.stitch_distance_resets {
+        pipeline.push(Box::new(distance_resets::StitchDistanceResets));
+    }
+    // Cleared before gap filling and resampling interpolate from them. Rules
+    // that do not parse were rejected by `ProcessingOptions::validate`.
+    let replacements = options.value_replacements().unwrap_or_default();
+    if !replacements.is_empty() {
+        pipeline.push(Box::new(replace_values::ReplaceValues { replacements }));
+    }
+    let corrections = options.set_corrections().unwrap_or_default();
+    if !corrections.is_empty() {
+        pipeline.push(Box::new(correct_sets::CorrectSets { corrections }));
+    }
+    if options.one_second_records {
+        pipeline.push(Box::new(gaps::FillGaps {
+            min_gap_seconds: 1.0,
+        }));
+    } else if options.fill_gaps {
+        pipeline.push(Box::new(gaps::FillGaps {
+            min_gap_seconds: GAP_FILL_MIN_SECONDS,
+        }));
+    }
+    if let Some(interval_seconds) = options.resample_interval_seconds.filter(|&s| s > 1) {
+        pipeline.push(Box::new(resample::Resample { interval_seconds }));
+    }
+    // Corrected before calibration, so a target distance rescales the
+    // corrected track rather than the zigzag.
+    if options.correct_open_water {
+        pipeline.push(Box::new(open_water::CorrectOpenWaterSwim));
+    }
+    if options.correct_altitude_drift {
+        pipeline.push(Box::new(altitude_drift::CorrectAltitudeDrift));
+    }
+    // Calibrated before smoothing, so smoothed speeds use corrected distances.
+    if let Some(calibration) = options.distance_calibration() {
+        pipeline.push(Box::new(calibrate_distance::CalibrateDistance {
+            calibration,
+        }));
+    }
+    if let Some(HashableF64(percent)) = options.power_scale_percent {
+        pipeline.push(Box::new(calibrate_power::CalibratePower { percent }));
+    }
+    if options.wheel_circumference_mm.is_some() || options.crank_length_mm.is_some() {
+        pipeline.push(Box::new(bike_profile::SetBikeProfile {
+            wheel_circumference_mm: options.wheel_circumference_mm,
+            crank_length_mm: options.crank_length_mm.map(|HashableF64(mm)| mm),
+        }));
+    }
+    if options.smooth_speed {
+        pipeline.push(Box::new(smooth_speed::SmoothSpeed::default()));
+    }
+    if options.remove_speed_fields {
+        pipeline.push(Box::new(remove_speed::RemoveSpeedFields));
+    }
+    let removed_fields = options.field_removals().unwrap_or_default();
+    if !removed_fields.is_empty() {
+        pipeline.push(Box::new(remove_fields::RemoveFields {
+            fields: removed_fields,
+        }));
+    }
+    if options.flatten_indoor {
+        pipeline.push(Box::new(indoor::FlattenIndoor));
+    }
+    if options.strip_device_info {
+        pipeline.push(Box::new(device_info::StripDeviceInfo));
+    }
+    if options.strip_ebike_data {
+        pipeline.push(Box::new(strip_ebike::StripEbikeData));
+    }
+    // Unknown names were already rejected with the upload.
+    for plugin in options
+        .plugins
+        .iter()
+        .filter_map(|name| registered_plugin(name))
+    {
+        pipeline.push(Box::new(plugin));
+    }
+    if let Some(profile) = options.anonymize {
+        for &rule in profile.rules() {
+            pipeline.push(Box::new(rule));
+        }
+    }
+    if options.title.is_some() || options.notes.is_some() {
+        pipeline.push(Box::new(title::SetActivityTitle {
+            title: options.title.clone(),
+            notes: options.notes.clone(),
+        }));
+    }
+    if let Some(gear) = options.gear_tag() {
+        pipeline.push(Box::new(gear::TagGear {
+            gear: gear.to_string(),
+        }));
+    }
+    // Injected after the other rewrites, so the output's derived values match
+    // what was exported.
+    if options.inject_derived_fields {
+        let fields = options.derived_field_definitions().unwrap_or_default();
+        if !fields.is_empty() {
+            pipeline.push(Box::new(derived_fields::InjectDerivedFields { fields }));
+        }
+    }
+    // Last, so edit indexes match the processed file.
+    if !options.record_edits.is_empty() {
+        pipeline.push(Box::new(edit_records::EditRecords {
+            edits: options.record_edits.clone(),
+        }));
+    }
+
+    pipeline
+}
+
+/// The `timestamp` field of a record, in seconds since the Unix epoch.
+pub(crate) fn record_timestamp(record: &FitDataRecord) -> Option<f64> {
+    record
+        .fields()
+        .iter()
+        .find(|field| field.name() == "timestamp")
+        .and_then(field_value_to_f64)
+}
+
+/// FIT timestamp value for `ts` seconds since the Unix epoch.
+pub(crate) fn timestamp_value(ts: f64) -> Option<Value> {
+    DateTime::from_timestamp(ts as i64, 0).map(|time| Value::Timestamp(time.with_timezone(&Local)))
+}
+
+/// `value` converted to the numeric variant of `template`, so rewritten
+/// fields keep their original base type.
+pub(crate) fn numeric_value_like(template: &Value, value: f64) -> Option<Value> {
+    let rounded = value.round();
+    Some(match template {
+        Value::Float64(_) => Value::Float64(value),
+        Value::Float32(_) => Value::Float32(value as f32),
+        Value::UInt8(_) => Value::UInt8(rounded as u8),
+        Value::UInt16(_) => Value::UInt16(rounded as u16),
+        Value::UInt32(_) => Value::UInt32(rounded as u32),
+        Value::SInt8(_) => Value::SInt8(rounded as i8),
+        Value::SInt16(_) => Value::SInt16(rounded as i16),
+        Value::SInt32(_) => Value::SInt32(rounded as i32),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use fitparser::profile::MesgNum;
+
+    /// Build a record with unit-less fields for transform tests.
+    pub(crate) fn record_with(kind: MesgNum, fields: &[(&str, Value)]) -> FitDataRecord {
+        let mut record = FitDataRecord::new(kind);
+        for (number, (name, value)) in fields.iter().enumerate() {
+            record.push(FitDataField::new(
+                name.to_string(),
+                number as u8,
+                None,
+                value.clone(),
+                String::new(),
+            ));
+        }
+        record
+    }
+
+    pub(crate) fn field_names(record: &FitDataRecord) -> Vec<&str> {
+        record.fields().iter().map(|field| field.name()).collect()
+    }
+
+    #[test]
+    fn pipeline_follows_option_order() {
+        let options = ProcessingOptions {
+            smooth_speed: true,
+            remove_speed_fields: true,
+            strip_device_info: true,
+            ..ProcessingOptions::default()
+        };
+
+        let names: Vec<&str> = build_pipeline(&options)
+            .iter()
+            .map(|transform| transform.name())
+            .collect();
+
+        assert_eq!(
+            names,
+            vec!["smooth_speed", "remove_speed_fields", "strip_device_info"]
+        );
+        assert!(build_pipeline(&ProcessingOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn cancellation_stops_a_rewrite_between_chunks() {
+        use crate::processing::progress::Progress;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Cancelled from the second check on, i.e. after the first chunk.
+        struct CancelAfterFirstChunk(AtomicUsize);
+        impl ProcessingObserver for CancelAfterFirstChunk {
+            fn progress(&self, _progress: Progress) {}
+
+            fn is_cancelled(&self) -> bool {
+                self.0.fetch_add(1, Ordering::Relaxed) > 0
+            }
+        }
+
+        let records = vec![record_with(MesgNum::Record, &[("speed", Value::UInt16(1))]); 25_000];
+        let observer = CancelAfterFirstChunk(AtomicUsize::new(0));
+        let mut ctx = RecordContext::observed(records, &observer);
+        ctx.rewrite_fields(|_, _, _| FieldEdit::Drop);
+
+        assert!(ctx.is_cancelled());
+        let rewritten = ctx
+            .records()
+            .iter()
+            .filter(|record| record.fields().is_empty())
+            .count();
+        assert_eq!(rewritten, PROGRESS_CHUNK_RECORDS);
+        assert_eq!(observer.0.load(Ordering::Relaxed), 2);
+    }
+}
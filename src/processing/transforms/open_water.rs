@@ -0,0 +1,357 @@
+//! GPS correction for open-water swims.
+//!
+//! A swimmer's watch is under water for most of every stroke, so its GPS
+//! fixes scatter around the course actually swum, and lifting the head to
+//! sight swings the track from side to side. Summing the raw fixes overstates
+//! the distance, often by 10–20%. This transform keeps the fixes taken while
+//! strokes are detected, averages each over its neighbours to cancel the
+//! zigzag, holds the position still while the swimmer rests, and recomputes
+//! every distance from the corrected track.
+
+use crate::processing::anonymize::{SEMICIRCLES_TO_DEGREES, distance_meters, record_position};
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{
+    FieldEdit, RecordContext, Transform, numeric_value_like, record_timestamp,
+};
+use crate::processing::types::{ProcessingWarning, WarningCode};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+use std::collections::HashMap;
+
+/// Fixes within this many seconds either side of a fix are averaged with
+/// it; long enough to span a few sighting strokes.
+const SMOOTHING_SECONDS: f64 = 10.0;
+
+/// Speed fields of laps and sessions rescaled with their distance.
+const AVERAGE_SPEED_FIELDS: &[&str] = &["avg_speed", "enhanced_avg_speed"];
+
+/// Correct the GPS track and distances of an open-water swim; other
+/// activities are left untouched.
+pub struct CorrectOpenWaterSwim;
+
+/// A `Record` with a position.
+struct Fix {
+    index: usize,
+    timestamp: f64,
+    lat: f64,
+    long: f64,
+    stroking: bool,
+}
+
+impl Transform for CorrectOpenWaterSwim {
+    fn name(&self) -> &'static str {
+        "correct_open_water_swim"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        if !is_open_water_swim(ctx.records()) {
+            return;
+        }
+        let fixes = collect_fixes(ctx.records());
+        if fixes.len() < 2 {
+            return;
+        }
+        let corrected = corrected_positions(&fixes);
+
+        // Distance swum up to each fix, along the corrected track.
+        let mut along = vec![0.0];
+        for pair in corrected.windows(2) {
+            along.push(along[along.len() - 1] + distance_meters(pair[0], pair[1]));
+        }
+        let total = along[along.len() - 1];
+        let distance_at = |timestamp: f64| {
+            let reached = fixes.partition_point(|fix| fix.timestamp <= timestamp);
+            reached.checked_sub(1).map_or(0.0, |last| along[last])
+        };
+        let recorded = recorded_distance(ctx.records(), &fixes);
+
+        let positions: HashMap<usize, (f64, f64)> = fixes
+            .iter()
+            .zip(&corrected)
+            .map(|(fix, &position)| (fix.index, position))
+            .collect();
+        // New total and the factor it changed by, per lap and session.
+        let totals: HashMap<usize, (f64, f64)> = ctx
+            .records()
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| matches!(record.kind(), MesgNum::Lap | MesgNum::Session))
+            .filter_map(|(index, record)| {
+                let start = field(record, "start_time")?;
+                let end = record_timestamp(record)?;
+                let total = distance_at(end) - distance_at(start);
+                let ratio = field(record, "total_distance")
+                    .filter(|&old| old > 0.0)
+                    .map_or(1.0, |old| total / old);
+                Some((index, (total, ratio)))
+            })
+            .collect();
+
+        ctx.rewrite_fields(|index, record, field| {
+            let replacement = match (record.kind(), field.name()) {
+                (MesgNum::Record, "position_lat") => positions
+                    .get(&index)
+                    .map(|&(lat, _)| lat / SEMICIRCLES_TO_DEGREES),
+                (MesgNum::Record, "position_long") => positions
+                    .get(&index)
+                    .map(|&(_, long)| long / SEMICIRCLES_TO_DEGREES),
+                (MesgNum::Record, "distance") => record_timestamp(record).map(distance_at),
+                (MesgNum::Lap | MesgNum::Session, "total_distance") => {
+                    totals.get(&index).map(|&(total, _)| total)
+                }
+                (MesgNum::Lap | MesgNum::Session, name) if AVERAGE_SPEED_FIELDS.contains(&name) => {
+                    let ratio = totals.get(&index).map(|&(_, ratio)| ratio);
+                    ratio
+                        .zip(field_value_to_f64(field))
+                        .map(|(ratio, speed)| speed * ratio)
+                }
+                _ => None,
+            };
+            replacement
+                .and_then(|value| numeric_value_like(field.value(), value))
+                .map_or(FieldEdit::Keep, FieldEdit::Replace)
+        });
+
+        ctx.warn(ProcessingWarning::new(
+            WarningCode::OpenWaterCorrected,
+            fixes.len(),
+            "GPS fix",
+            &format!(
+                "corrected for open-water swimming; distance {:.0} m → {:.0} m",
+                recorded, total
+            ),
+        ));
+    }
+}
+
+/// Whether the file's sport is swimming in open water, from its `Session`
+/// or `Sport` message.
+fn is_open_water_swim(records: &[FitDataRecord]) -> bool {
+    records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Session | MesgNum::Sport))
+        .any(|record| {
+            let text = |name: &str| {
+                record
+                    .fields()
+                    .iter()
+                    .find(|field| field.name() == name)
+                    .map(|field| field.to_string())
+            };
+            text("sport").as_deref() == Some("swimming")
+                && text("sub_sport").as_deref() == Some("open_water")
+        })
+}
+
+/// Timestamped `Record` positions. A record counts as stroking unless it has
+/// a zero `cadence`, which open-water files use for strokes per minute.
+fn collect_fixes(records: &[FitDataRecord]) -> Vec<Fix> {
+    records
+        .iter()
+        .enumerate()
+        .filter_map(|(index, record)| {
+            let (lat, long) = record_position(record)?;
+            Some(Fix {
+                index,
+                timestamp: record_timestamp(record)?,
+                lat,
+                long,
+                stroking: field(record, "cadence").is_none_or(|cadence| cadence > 0.0),
+            })
+        })
+        .collect()
+}
+
+/// Stroking fixes smoothed within each stretch of strokes; resting fixes
+/// take the position the swimmer stopped at.
+fn corrected_positions(fixes: &[Fix]) -> Vec<(f64, f64)> {
+    let mut corrected = Vec::with_capacity(fixes.len());
+    let mut start = 0;
+    while start < fixes.len() {
+        let stroking = fixes[start].stroking;
+        let end = fixes[start..]
+            .iter()
+            .position(|fix| fix.stroking != stroking)
+            .map_or(fixes.len(), |offset| start + offset);
+        let stretch = &fixes[start..end];
+        if stroking {
+            let timestamps: Vec<f64> = stretch.iter().map(|fix| fix.timestamp).collect();
+            let positions: Vec<(f64, f64)> =
+                stretch.iter().map(|fix| (fix.lat, fix.long)).collect();
+            // Two passes weight near fixes more than far ones; a single
+            // moving average still zigzags when the sighting rhythm does not
+            // divide the window evenly.
+            let once = moving_average(&timestamps, &positions);
+            corrected.extend(moving_average(&timestamps, &once));
+        } else {
+            // Before the first stroke there is nowhere to hold; keep the fix.
+            let held = corrected.last().copied();
+            corrected.extend(
+                stretch
+                    .iter()
+                    .map(|fix| held.unwrap_or((fix.lat, fix.long))),
+            );
+        }
+        start = end;
+    }
+    corrected
+}
+
+/// Each position averaged with those within [`SMOOTHING_SECONDS`] of it.
+fn moving_average(timestamps: &[f64], positions: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let (mut first, mut last) = (0, 0);
+    timestamps
+        .iter()
+        .map(|&timestamp| {
+            while timestamps[first] < timestamp - SMOOTHING_SECONDS {
+                first += 1;
+            }
+            while last + 1 < timestamps.len()
+                && timestamps[last + 1] <= timestamp + SMOOTHING_SECONDS
+            {
+                last += 1;
+            }
+            let window = &positions[first..=last];
+            let count = window.len() as f64;
+            (
+                window.iter().map(|&(lat, _)| lat).sum::<f64>() / count,
+                window.iter().map(|&(_, long)| long).sum::<f64>() / count,
+            )
+        })
+        .collect()
+}
+
+/// Distance the device reported: the furthest record distance, or else the
+/// length of the raw track.
+fn recorded_distance(records: &[FitDataRecord], fixes: &[Fix]) -> f64 {
+    records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .filter_map(|record| field(record, "distance"))
+        .reduce(f64::max)
+        .unwrap_or_else(|| {
+            fixes
+                .windows(2)
+                .map(|pair| {
+                    distance_meters((pair[0].lat, pair[0].long), (pair[1].lat, pair[1].long))
+                })
+                .sum()
+        })
+}
+
+fn field(record: &FitDataRecord, name: &str) -> Option<f64> {
+    record
+        .fields()
+        .iter()
+        .find(|field| field.name() == name)
+        .and_then(field_value_to_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::transforms::timestamp_value;
+    use fitparser::Value;
+
+    const START: f64 = 1_000_000_000.0;
+    /// Degrees of latitude per metre.
+    const LAT_PER_METER: f64 = 1.0 / 111_195.0;
+
+    fn semicircles(degrees: f64) -> Value {
+        Value::SInt32((degrees / SEMICIRCLES_TO_DEGREES).round() as i32)
+    }
+
+    /// 300 s swimming north across a lake at 1 m/s, swinging 30 m east and back every
+    /// 5 s, then 30 s resting with the GPS drifting.
+    fn swim(sub_sport: &str) -> Vec<FitDataRecord> {
+        let mut records: Vec<FitDataRecord> = (0..330)
+            .map(|second| {
+                let swimming = second < 300;
+                let north = f64::from(second.min(299));
+                let east = if swimming && (second / 5) % 2 == 1 {
+                    30.0
+                } else {
+                    0.0
+                };
+                let drift = if swimming {
+                    0.0
+                } else {
+                    f64::from(second - 299) * 3.0
+                };
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        (
+                            "timestamp",
+                            timestamp_value(START + f64::from(second)).unwrap(),
+                        ),
+                        (
+                            "position_lat",
+                            semicircles(47.0 + (north + drift) * LAT_PER_METER),
+                        ),
+                        (
+                            "position_long",
+                            semicircles(8.0 + east * LAT_PER_METER / 47f64.to_radians().cos()),
+                        ),
+                        ("distance", Value::Float64(f64::from(second) * 1.6)),
+                        ("cadence", Value::UInt8(if swimming { 30 } else { 0 })),
+                    ],
+                )
+            })
+            .collect();
+        records.push(record_with(
+            MesgNum::Session,
+            &[
+                ("timestamp", timestamp_value(START + 329.0).unwrap()),
+                ("start_time", timestamp_value(START).unwrap()),
+                ("sport", Value::String("swimming".to_string())),
+                ("sub_sport", Value::String(sub_sport.to_string())),
+                ("total_distance", Value::Float64(527.0)),
+                ("avg_speed", Value::Float64(1.6)),
+            ],
+        ));
+        records
+    }
+
+    fn value(record: &FitDataRecord, name: &str) -> f64 {
+        field(record, name).unwrap()
+    }
+
+    #[test]
+    fn zigzag_and_resting_drift_are_removed_from_the_distance() {
+        let mut ctx = RecordContext::new(swim("open_water"));
+
+        CorrectOpenWaterSwim.apply(&mut ctx);
+
+        let records = ctx.records();
+        let session = records.last().unwrap();
+        let total = value(session, "total_distance");
+        // 299 m swum, less a little where the smoothing window is cut short
+        // at either end; the raw track is over 2 km.
+        assert!((280.0..300.0).contains(&total), "{total}");
+        assert!((value(session, "avg_speed") - 1.6 * total / 527.0).abs() < 1e-9);
+        assert_eq!(value(&records[329], "distance"), total);
+        assert_eq!(
+            value(&records[310], "position_lat"),
+            value(&records[329], "position_lat")
+        );
+        let (_, warnings) = ctx.into_parts();
+        assert_eq!(warnings[0].code, WarningCode::OpenWaterCorrected);
+        assert_eq!(warnings[0].count, 330);
+    }
+
+    #[test]
+    fn other_activities_are_left_alone() {
+        let mut ctx = RecordContext::new(swim("lap_swimming"));
+
+        CorrectOpenWaterSwim.apply(&mut ctx);
+
+        assert_eq!(value(&ctx.records()[5], "distance"), 8.0);
+        assert_eq!(
+            value(ctx.records().last().unwrap(), "total_distance"),
+            527.0
+        );
+        assert!(ctx.into_parts().1.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+//! Removal of fields picked by name, such as those chosen from the field
+//! picker after a dry run of the upload.
+//!
+//! Fields are named `Message.field`, with the message type as shown in the
+//! records table (case-insensitive) and the field as decoded, e.g.
+//! `Record.speed` or `Session.max_heart_rate`. Developer fields are named by
+//! their `field_name`.
+
+use crate::processing::structure::message_type_name;
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform};
+use std::fmt;
+
+/// One field to remove from every message of one type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSelector {
+    pub message_type: String,
+    pub field: String,
+}
+
+/// A field name without its message type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSelectorError {
+    pub selector: String,
+}
+
+impl fmt::Display for FieldSelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid field `{}`: expected `Message.field`, e.g. `Record.speed`",
+            self.selector
+        )
+    }
+}
+
+impl std::error::Error for FieldSelectorError {}
+
+/// Parse `Message.field` names; blank names are skipped.
+pub fn parse_field_selectors(names: &[String]) -> Result<Vec<FieldSelector>, FieldSelectorError> {
+    names
+        .iter()
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| match name.split_once('.') {
+            Some((message_type, field))
+                if !message_type.trim().is_empty() && !field.trim().is_empty() =>
+            {
+                Ok(FieldSelector {
+                    message_type: message_type.trim().to_string(),
+                    field: field.trim().to_string(),
+                })
+            }
+            _ => Err(FieldSelectorError {
+                selector: name.to_string(),
+            }),
+        })
+        .collect()
+}
+
+/// Drop the [`FieldSelector`]s' fields.
+pub struct RemoveFields {
+    pub fields: Vec<FieldSelector>,
+}
+
+impl Transform for RemoveFields {
+    fn name(&self) -> &'static str {
+        "remove_fields"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        ctx.rewrite_fields(|_, record, field| {
+            let message_type = message_type_name(record.kind());
+            if self.fields.iter().any(|selector| {
+                selector.field == field.name()
+                    && selector.message_type.eq_ignore_ascii_case(&message_type)
+            }) {
+                FieldEdit::Drop
+            } else {
+                FieldEdit::Keep
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::{field_names, record_with};
+    use fitparser::Value;
+    use fitparser::profile::MesgNum;
+
+    #[test]
+    fn removes_picked_fields_of_the_named_message_only() {
+        let mut ctx = RecordContext::new(vec![
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("speed", Value::Float64(3.0)),
+                    ("heart_rate", Value::UInt8(140)),
+                ],
+            ),
+            record_with(MesgNum::Lap, &[("heart_rate", Value::UInt8(150))]),
+        ]);
+        let fields = parse_field_selectors(&["record.heart_rate".to_string()]).unwrap();
+
+        RemoveFields { fields }.apply(&mut ctx);
+
+        assert_eq!(field_names(&ctx.records()[0]), vec!["speed"]);
+        assert_eq!(field_names(&ctx.records()[1]), vec!["heart_rate"]);
+    }
+
+    #[test]
+    fn field_names_need_a_message_type() {
+        assert!(parse_field_selectors(&["speed".to_string()]).is_err());
+        assert!(parse_field_selectors(&["Record.".to_string()]).is_err());
+        assert_eq!(parse_field_selectors(&[" ".to_string()]), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform};
+use fitparser::profile::MesgNum;
+
+/// Drop `speed` and `enhanced_speed` from record messages.
+pub struct RemoveSpeedFields;
+
+impl Transform for RemoveSpeedFields {
+    fn name(&self) -> &'static str {
+        "remove_speed_fields"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        ctx.rewrite_fields(|_, record, field| {
+            if matches!(record.kind(), MesgNum::Record)
+                && matches!(field.name(), "speed" | "enhanced_speed")
+            {
+                FieldEdit::Drop
+            } else {
+                FieldEdit::Keep
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::{field_names, record_with};
+    use fitparser::Value;
+
+    #[test]
+    fn removes_speed_only_from_record_messages() {
+        let mut ctx = RecordContext::new(vec![
+            record_with(
+                MesgNum::Record,
+                &[
+                    ("speed", Value::Float64(3.0)),
+                    ("heart_rate", Value::UInt8(140)),
+                ],
+            ),
+            record_with(MesgNum::Lap, &[("enhanced_speed", Value::Float64(3.0))]),
+        ]);
+
+        RemoveSpeedFields.apply(&mut ctx);
+
+        assert_eq!(field_names(&ctx.records()[0]), vec!["heart_rate"]);
+        assert_eq!(field_names(&ctx.records()[1]), vec!["enhanced_speed"]);
+    }
+}
@@ -0,0 +1,272 @@
+//! Find-and-replace for sentinel values sensors write instead of leaving a
+//! field out, such as a heart rate of 0 from a strap that lost contact or a
+//! cadence of 0 while coasting. A field's FIT invalid marker (255 for heart
+//! rate) needs no rule: the decoder already reads it as missing.
+//!
+//! Rules are written one per line (or separated by `;`) as `field = value`,
+//! which removes matching values so they read as missing, or
+//! `field = value -> interpolate`, which replaces them with a value
+//! interpolated over time between the nearest good values on either side.
+
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{
+    FieldEdit, RecordContext, Transform, numeric_value_like, record_timestamp,
+};
+use crate::processing::types::{ProcessingWarning, WarningCode};
+use fitparser::profile::MesgNum;
+use std::fmt;
+
+/// Most rules one upload may define.
+pub const MAX_REPLACEMENTS: usize = 16;
+
+/// What a matching value becomes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replacement {
+    /// Removed from the record, as if the sensor had not reported it.
+    Invalid,
+    /// Interpolated between the surrounding good values; removed when there
+    /// is no good value on one side.
+    Interpolate,
+}
+
+/// Replace every `value` of `field` in `Record` messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueReplacement {
+    pub field: String,
+    pub value: f64,
+    pub replacement: Replacement,
+}
+
+/// A rule that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplacementError {
+    pub rule: String,
+    pub message: String,
+}
+
+impl fmt::Display for ReplacementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid value replacement `{}`: {}",
+            self.rule, self.message
+        )
+    }
+}
+
+impl std::error::Error for ReplacementError {}
+
+/// Parse `field = value [-> invalid|interpolate]` rules separated by
+/// newlines or `;`. Blank rules are skipped.
+pub fn parse_replacements(text: &str) -> Result<Vec<ValueReplacement>, ReplacementError> {
+    let mut replacements = Vec::new();
+    for rule in text.split(['\n', ';']).map(str::trim) {
+        if rule.is_empty() {
+            continue;
+        }
+        let error = |message: &str| ReplacementError {
+            rule: rule.to_string(),
+            message: message.to_string(),
+        };
+        let (matcher, replacement) = match rule.split_once("->") {
+            Some((matcher, replacement)) => (matcher, replacement.trim()),
+            None => (rule, "invalid"),
+        };
+        let replacement = match replacement {
+            "invalid" => Replacement::Invalid,
+            "interpolate" => Replacement::Interpolate,
+            _ => return Err(error("replace with `invalid` or `interpolate`")),
+        };
+        let Some((field, value)) = matcher.split_once('=') else {
+            return Err(error("expected `field = value`"));
+        };
+        let field = field.trim();
+        if field.is_empty() || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(error("expected a field name such as `heart_rate`"));
+        }
+        let Ok(value) = value.trim().parse::<f64>() else {
+            return Err(error("expected a number to replace"));
+        };
+        if replacements.len() == MAX_REPLACEMENTS {
+            return Err(error("too many value replacements"));
+        }
+        replacements.push(ValueReplacement {
+            field: field.to_string(),
+            value,
+            replacement,
+        });
+    }
+    Ok(replacements)
+}
+
+/// Apply [`ValueReplacement`] rules to `Record` messages.
+pub struct ReplaceValues {
+    pub replacements: Vec<ValueReplacement>,
+}
+
+impl Transform for ReplaceValues {
+    fn name(&self) -> &'static str {
+        "replace_values"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        for rule in &self.replacements {
+            replace(ctx, rule);
+        }
+    }
+}
+
+fn replace(ctx: &mut RecordContext, rule: &ValueReplacement) {
+    // Each record's value of the field and its time, or index when the
+    // record has no timestamp.
+    let samples: Vec<Option<(f64, f64)>> = ctx
+        .records()
+        .iter()
+        .enumerate()
+        .map(|(idx, record)| {
+            if !matches!(record.kind(), MesgNum::Record) {
+                return None;
+            }
+            let value = record
+                .fields()
+                .iter()
+                .find(|field| field.name() == rule.field)
+                .and_then(field_value_to_f64)?;
+            let at = record_timestamp(record).unwrap_or(idx as f64);
+            Some((at, value))
+        })
+        .collect();
+    let matches = |sample: &Option<(f64, f64)>| {
+        sample.is_some_and(|(_, value)| (value - rule.value).abs() < 1e-9)
+    };
+    let count = samples.iter().filter(|sample| matches(sample)).count();
+    if count == 0 {
+        return;
+    }
+
+    // The nearest good sample before and after each record.
+    let mut before = vec![None; samples.len()];
+    let mut last = None;
+    for (idx, sample) in samples.iter().enumerate() {
+        before[idx] = last;
+        if sample.is_some() && !matches(sample) {
+            last = *sample;
+        }
+    }
+    let mut after = vec![None; samples.len()];
+    let mut next = None;
+    for (idx, sample) in samples.iter().enumerate().rev() {
+        after[idx] = next;
+        if sample.is_some() && !matches(sample) {
+            next = *sample;
+        }
+    }
+
+    ctx.rewrite_fields(|idx, record, field| {
+        if !matches!(record.kind(), MesgNum::Record)
+            || field.name() != rule.field
+            || !matches(&samples[idx])
+        {
+            return FieldEdit::Keep;
+        }
+        let interpolated = match (rule.replacement, before[idx], after[idx], samples[idx]) {
+            (Replacement::Interpolate, Some((t0, v0)), Some((t1, v1)), Some((t, _))) => {
+                let fraction = if t1 > t0 { (t - t0) / (t1 - t0) } else { 0.5 };
+                numeric_value_like(field.value(), v0 + (v1 - v0) * fraction)
+            }
+            _ => None,
+        };
+        interpolated.map_or(FieldEdit::Drop, FieldEdit::Replace)
+    });
+
+    let detail = match rule.replacement {
+        Replacement::Invalid => "removed",
+        Replacement::Interpolate => "interpolated",
+    };
+    ctx.warn(ProcessingWarning::new(
+        WarningCode::ValuesReplaced,
+        count,
+        &format!("{} value", rule.field),
+        &format!("of {} {detail}", rule.value),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::{field_names, record_with};
+    use crate::processing::transforms::timestamp_value;
+    use fitparser::Value;
+
+    fn record(ts: f64, heart_rate: u8, cadence: u8) -> fitparser::FitDataRecord {
+        record_with(
+            MesgNum::Record,
+            &[
+                ("timestamp", timestamp_value(ts).unwrap()),
+                ("heart_rate", Value::UInt8(heart_rate)),
+                ("cadence", Value::UInt8(cadence)),
+            ],
+        )
+    }
+
+    #[test]
+    fn rules_parse_with_an_optional_replacement() {
+        let rules = parse_replacements("heart_rate = 0 -> interpolate; cadence=0").unwrap();
+
+        assert_eq!(
+            rules,
+            vec![
+                ValueReplacement {
+                    field: "heart_rate".to_string(),
+                    value: 0.0,
+                    replacement: Replacement::Interpolate,
+                },
+                ValueReplacement {
+                    field: "cadence".to_string(),
+                    value: 0.0,
+                    replacement: Replacement::Invalid,
+                },
+            ]
+        );
+        assert!(parse_replacements("heart_rate = high").is_err());
+        assert!(parse_replacements("heart_rate = 255 -> zero").is_err());
+    }
+
+    #[test]
+    fn sentinels_are_interpolated_or_removed() {
+        let mut ctx = RecordContext::new(vec![
+            record(0.0, 120, 80),
+            record(1.0, 0, 0),
+            record(3.0, 0, 0),
+            record(4.0, 140, 82),
+            record(5.0, 0, 84),
+        ]);
+
+        ReplaceValues {
+            replacements: parse_replacements("heart_rate = 0 -> interpolate\ncadence = 0").unwrap(),
+        }
+        .apply(&mut ctx);
+
+        let heart_rate = |idx: usize| {
+            ctx.records()[idx]
+                .fields()
+                .iter()
+                .find(|field| field.name() == "heart_rate")
+                .map(|field| field.value().clone())
+        };
+        assert_eq!(heart_rate(1), Some(Value::UInt8(125)));
+        assert_eq!(heart_rate(2), Some(Value::UInt8(135)));
+        // Nothing good follows the last sample to interpolate towards.
+        assert_eq!(heart_rate(4), None);
+        assert_eq!(
+            field_names(&ctx.records()[1]),
+            vec!["timestamp", "heart_rate"]
+        );
+        assert_eq!(ctx.records()[4].fields().len(), 2);
+
+        let warnings = ctx.into_parts().1;
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].count, 3);
+        assert_eq!(warnings[1].message, "2 cadence values of 0 removed");
+    }
+}
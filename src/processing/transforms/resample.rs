@@ -0,0 +1,149 @@
+use crate::processing::preprocess::with_value;
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{
+    RecordContext, Transform, numeric_value_like, record_timestamp,
+};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+
+/// Fields averaged across a resampling bucket; every other field keeps the
+/// bucket's last value, so cumulative fields like distance stay consistent.
+const MEAN_FIELDS: &[&str] = &[
+    "heart_rate",
+    "cadence",
+    "fractional_cadence",
+    "power",
+    "speed",
+    "enhanced_speed",
+    "temperature",
+    "vertical_oscillation",
+    "stance_time",
+    "step_length",
+];
+
+/// Collapse `Record` messages into one per fixed interval, e.g. 10 Hz power
+/// meter files down to one record every 5 seconds.
+pub struct Resample {
+    pub interval_seconds: u32,
+}
+
+impl Transform for Resample {
+    fn name(&self) -> &'static str {
+        "resample"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let interval = f64::from(self.interval_seconds.max(1));
+
+        ctx.rebuild(|records| {
+            let mut output = Vec::with_capacity(records.len());
+            let mut origin: Option<f64> = None;
+            let mut bucket: Vec<FitDataRecord> = Vec::new();
+            let mut bucket_index: Option<i64> = None;
+
+            for record in records {
+                let timestamp = matches!(record.kind(), MesgNum::Record)
+                    .then(|| record_timestamp(&record))
+                    .flatten();
+                let Some(ts) = timestamp else {
+                    output.push(record);
+                    continue;
+                };
+
+                let start = *origin.get_or_insert(ts);
+                let index = ((ts - start) / interval).floor() as i64;
+                if bucket_index != Some(index) && !bucket.is_empty() {
+                    output.push(aggregate_bucket(std::mem::take(&mut bucket)));
+                }
+                bucket_index = Some(index);
+                bucket.push(record);
+            }
+            if !bucket.is_empty() {
+                output.push(aggregate_bucket(bucket));
+            }
+
+            output
+        });
+    }
+}
+
+/// Merge one bucket into its last record, replacing [`MEAN_FIELDS`] with the
+/// bucket average.
+fn aggregate_bucket(mut bucket: Vec<FitDataRecord>) -> FitDataRecord {
+    let last = bucket.pop().expect("buckets are never empty");
+    if bucket.is_empty() {
+        return last;
+    }
+
+    let mut merged = FitDataRecord::new(last.kind());
+    for field in last.fields() {
+        let name = field.name();
+        if !MEAN_FIELDS.contains(&name) {
+            merged.push(field.clone());
+            continue;
+        }
+
+        let values: Vec<f64> = bucket
+            .iter()
+            .chain(std::iter::once(&last))
+            .filter_map(|record| {
+                record
+                    .fields()
+                    .iter()
+                    .find(|other| other.name() == name)
+                    .and_then(field_value_to_f64)
+            })
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len().max(1) as f64;
+        match numeric_value_like(field.value(), mean) {
+            Some(value) if !values.is_empty() => merged.push(with_value(field, value)),
+            _ => merged.push(field.clone()),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+
+    #[test]
+    fn averages_heart_rate_and_keeps_last_distance() {
+        let records = (0..10)
+            .map(|second| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", Value::Float64(1000.0 + second as f64)),
+                        ("distance", Value::Float64(second as f64 * 3.0)),
+                        ("heart_rate", Value::UInt8(120 + second as u8)),
+                    ],
+                )
+            })
+            .collect();
+        let mut ctx = RecordContext::new(records);
+
+        Resample {
+            interval_seconds: 5,
+        }
+        .apply(&mut ctx);
+
+        let rows: Vec<Vec<f64>> = ctx
+            .records()
+            .iter()
+            .map(|record| {
+                record
+                    .fields()
+                    .iter()
+                    .filter_map(field_value_to_f64)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![vec![1004.0, 12.0, 122.0], vec![1009.0, 27.0, 127.0]]
+        );
+    }
+}
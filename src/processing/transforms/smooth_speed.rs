@@ -0,0 +1,149 @@
+use crate::processing::preprocess::{RecordOverrides, smoothed_speed_series, speed_overrides};
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform};
+use crate::processing::types::{
+    ProcessingWarning, SMOOTHING_RESIDUAL_THRESHOLD, SPEED_SMOOTHING_WINDOW, SPEED_SPIKE_MIN_DELTA,
+    SmoothingResiduals, WarningCode,
+};
+use fitparser::Value;
+use fitparser::profile::MesgNum;
+
+/// Replace record speeds with a moving average and rebuild distances to match.
+///
+/// Lap and session speeds are brought in line afterwards by
+/// [`super::summary_speeds::RecomputeSummarySpeeds`].
+pub struct SmoothSpeed {
+    pub window: usize,
+}
+
+impl Default for SmoothSpeed {
+    fn default() -> Self {
+        Self {
+            window: SPEED_SMOOTHING_WINDOW,
+        }
+    }
+}
+
+impl Transform for SmoothSpeed {
+    fn name(&self) -> &'static str {
+        "smooth_speed"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let Some(series) = smoothed_speed_series(ctx.records(), self.window) else {
+            return;
+        };
+        let spikes = series
+            .raw
+            .iter()
+            .zip(&series.smoothed)
+            .filter(|&(raw, smoothed)| raw - smoothed > SPEED_SPIKE_MIN_DELTA)
+            .count();
+        let residuals = smoothing_residuals(&series.raw, &series.smoothed, self.window);
+        let overrides = speed_overrides(ctx.records().len(), series);
+
+        ctx.rewrite_fields(|idx, record, field| {
+            if !matches!(record.kind(), MesgNum::Record) {
+                return FieldEdit::Keep;
+            }
+            let RecordOverrides { speed, distance } =
+                overrides.get(idx).cloned().unwrap_or_default();
+            let replacement = match field.name() {
+                "distance" => distance,
+                "speed" | "enhanced_speed" => speed,
+                _ => None,
+            };
+            match replacement {
+                Some(value) => FieldEdit::Replace(Value::Float64(value)),
+                None => FieldEdit::Keep,
+            }
+        });
+
+        ctx.set_smoothing_residuals(residuals);
+        if spikes > 0 {
+            ctx.warn(ProcessingWarning::new(
+                WarningCode::SpeedSpikesSmoothed,
+                spikes,
+                "speed spike",
+                "clamped by smoothing",
+            ));
+        }
+    }
+}
+
+/// Compare a raw speed series with its smoothed counterpart.
+pub fn smoothing_residuals(raw: &[f64], smoothed: &[f64], window: usize) -> SmoothingResiduals {
+    let differences: Vec<f64> = raw
+        .iter()
+        .zip(smoothed)
+        .map(|(raw, smoothed)| (raw - smoothed).abs())
+        .collect();
+    let rmse = if differences.is_empty() {
+        0.0
+    } else {
+        (differences.iter().map(|diff| diff * diff).sum::<f64>() / differences.len() as f64).sqrt()
+    };
+    SmoothingResiduals {
+        window,
+        samples: differences.len(),
+        rmse,
+        max_abs_residual: differences.iter().cloned().fold(0.0, f64::max),
+        altered_samples: differences
+            .iter()
+            .filter(|&&diff| diff > SMOOTHING_RESIDUAL_THRESHOLD)
+            .count(),
+        threshold: SMOOTHING_RESIDUAL_THRESHOLD,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::summary::field_value_to_f64;
+    use crate::processing::transforms::tests::record_with;
+
+    #[test]
+    fn spike_is_flattened() {
+        let distances = [0.0, 3.0, 6.0, 30.0, 33.0, 36.0];
+        let records = distances
+            .iter()
+            .enumerate()
+            .map(|(idx, &distance)| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", Value::Float64(idx as f64)),
+                        ("distance", Value::Float64(distance)),
+                        ("speed", Value::Float64(0.0)),
+                    ],
+                )
+            })
+            .collect();
+        let mut ctx = RecordContext::new(records);
+
+        SmoothSpeed { window: 3 }.apply(&mut ctx);
+
+        let speeds: Vec<f64> = ctx
+            .records()
+            .iter()
+            .filter_map(|record| {
+                record
+                    .fields()
+                    .iter()
+                    .find(|field| field.name() == "speed")
+                    .and_then(field_value_to_f64)
+            })
+            .collect();
+        let peak = speeds.iter().cloned().fold(f64::MIN, f64::max);
+        assert!(peak < 24.0, "raw spike of 24 m/s should be averaged down");
+
+        let residuals = ctx.smoothing_residuals().cloned().expect("smoothing ran");
+        assert_eq!((residuals.window, residuals.samples), (3, 5));
+        assert!(residuals.altered_samples >= 1);
+        assert!(residuals.max_abs_residual > residuals.rmse);
+
+        let (_, warnings) = ctx.into_parts();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, WarningCode::SpeedSpikesSmoothed);
+        assert_eq!(warnings[0].count, 1);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::processing::ebike::{device_index, ebike_device_indexes, ebike_field};
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform};
+use fitparser::profile::MesgNum;
+
+/// Remove the battery, assist-mode, and motor fields of `Record` messages,
+/// and the eBike's own `DeviceInfo` messages, so the ride can be shared
+/// without showing it was assisted. Developer fields are matched by name
+/// and their `FieldDescription` messages removed with them; other devices'
+/// battery readings are kept.
+pub struct StripEbikeData;
+
+impl Transform for StripEbikeData {
+    fn name(&self) -> &'static str {
+        "strip_ebike_data"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let ebikes = ebike_device_indexes(ctx.records());
+        ctx.retain_records(|record| match record.kind() {
+            MesgNum::FieldDescription => !record
+                .fields()
+                .iter()
+                .filter(|field| field.name() == "field_name")
+                .any(|field| ebike_field(&field.to_string()).is_some()),
+            MesgNum::DeviceInfo => {
+                device_index(record).is_none_or(|index| !ebikes.contains(&index))
+            }
+            _ => true,
+        });
+        ctx.rewrite_fields(|_, record, field| {
+            if matches!(record.kind(), MesgNum::Record) && ebike_field(field.name()).is_some() {
+                FieldEdit::Drop
+            } else {
+                FieldEdit::Keep
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::ebike::{summarize_ebike, tests::ride};
+    use crate::processing::transforms::tests::{field_names, record_with};
+    use fitparser::Value;
+
+    #[test]
+    fn drops_motor_fields_and_their_descriptions() {
+        let mut records = ride();
+        records.push(record_with(
+            MesgNum::FieldDescription,
+            &[("field_name", Value::String("Battery SOC".to_string()))],
+        ));
+        records.push(record_with(
+            MesgNum::FieldDescription,
+            &[("field_name", Value::String("Core Temperature".to_string()))],
+        ));
+        let mut ctx = RecordContext::new(records);
+
+        StripEbikeData.apply(&mut ctx);
+
+        assert_eq!(summarize_ebike(ctx.records()), None);
+        assert_eq!(
+            field_names(&ctx.records()[0]),
+            vec!["timestamp", "distance", "power"]
+        );
+        assert_eq!(ctx.records().len(), 62);
+    }
+
+    #[test]
+    fn keeps_other_devices_and_their_batteries() {
+        let device = |index: u8, device_type: &str| {
+            record_with(
+                MesgNum::DeviceInfo,
+                &[
+                    ("device_index", Value::UInt8(index)),
+                    (
+                        "antplus_device_type",
+                        Value::String(device_type.to_string()),
+                    ),
+                    ("battery_status", Value::String("ok".to_string())),
+                ],
+            )
+        };
+        let mut ctx = RecordContext::new(vec![
+            device(1, "heart_rate"),
+            device(2, "lev"),
+            record_with(
+                MesgNum::DeviceInfo,
+                &[
+                    ("device_index", Value::UInt8(2)),
+                    ("battery_voltage", Value::Float64(36.5)),
+                ],
+            ),
+            record_with(
+                MesgNum::Session,
+                &[("ebike_battery_level", Value::UInt8(40))],
+            ),
+        ]);
+
+        StripEbikeData.apply(&mut ctx);
+
+        assert_eq!(ctx.records().len(), 2);
+        assert_eq!(
+            field_names(&ctx.records()[0]),
+            vec!["device_index", "antplus_device_type", "battery_status"]
+        );
+        assert_eq!(field_names(&ctx.records()[1]), vec!["ebike_battery_level"]);
+    }
+}
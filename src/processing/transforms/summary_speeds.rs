@@ -0,0 +1,141 @@
+use crate::processing::preprocess::{SummarySpeedOverrides, summary_speed_overrides};
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::{FieldEdit, RecordContext, Transform, numeric_value_like};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+
+/// Recompute lap and session average and peak speeds from the records, so a
+/// speed the transforms removed, such as a smoothed-away spike, does not
+/// linger as `max_speed`.
+///
+/// Runs after every other transform, and only when they changed a record
+/// speed; see [`record_speeds`].
+pub struct RecomputeSummarySpeeds;
+
+impl Transform for RecomputeSummarySpeeds {
+    fn name(&self) -> &'static str {
+        "recompute_summary_speeds"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let summaries = summary_speed_overrides(ctx.records());
+        ctx.rewrite_fields(|idx, record, field| {
+            if !matches!(record.kind(), MesgNum::Lap | MesgNum::Session) {
+                return FieldEdit::Keep;
+            }
+            let SummarySpeedOverrides {
+                avg_speed,
+                max_speed,
+            } = summaries.get(idx).cloned().unwrap_or_default();
+            let replacement = match field.name() {
+                "avg_speed" | "enhanced_avg_speed" => avg_speed,
+                "max_speed" | "enhanced_max_speed" => max_speed,
+                _ => None,
+            };
+            match replacement.and_then(|value| numeric_value_like(field.value(), value)) {
+                Some(value) => FieldEdit::Replace(value),
+                None => FieldEdit::Keep,
+            }
+        });
+    }
+}
+
+/// Speed of every `Record` message in file order, to tell whether the
+/// transforms changed any.
+pub fn record_speeds(records: &[FitDataRecord]) -> Vec<Option<f64>> {
+    records
+        .iter()
+        .filter(|record| matches!(record.kind(), MesgNum::Record))
+        .map(|record| {
+            ["enhanced_speed", "speed"].iter().find_map(|name| {
+                record
+                    .fields()
+                    .iter()
+                    .find(|field| field.name() == *name)
+                    .and_then(field_value_to_f64)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::processing::preprocess::preprocess_fit;
+    use crate::processing::summary::field_value_to_f64;
+    use crate::processing::transforms::tests::record_with;
+    use crate::processing::types::ProcessingOptions;
+    use fitparser::profile::MesgNum;
+    use fitparser::{FitDataRecord, Value};
+
+    /// Six records with a 24 m/s spike, and a lap that recorded it.
+    fn ride_with_spike() -> Vec<FitDataRecord> {
+        let distances = [0.0, 3.0, 6.0, 30.0, 33.0, 36.0];
+        let mut records: Vec<_> = distances
+            .iter()
+            .enumerate()
+            .map(|(idx, &distance)| {
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", Value::Float64(idx as f64)),
+                        ("distance", Value::Float64(distance)),
+                        ("speed", Value::Float64(0.0)),
+                    ],
+                )
+            })
+            .collect();
+        records.push(record_with(
+            MesgNum::Lap,
+            &[
+                ("timestamp", Value::Float64(5.0)),
+                ("start_time", Value::Float64(0.0)),
+                ("total_timer_time", Value::Float64(5.0)),
+                ("total_distance", Value::Float64(36.0)),
+                ("avg_speed", Value::Float64(7.2)),
+                ("max_speed", Value::Float64(24.0)),
+            ],
+        ));
+        records
+    }
+
+    fn speed(record: &FitDataRecord, name: &str) -> Option<f64> {
+        record
+            .fields()
+            .iter()
+            .find(|field| field.name() == name)
+            .and_then(field_value_to_f64)
+    }
+
+    #[test]
+    fn lap_max_speed_follows_the_smoothed_records() {
+        let options = ProcessingOptions {
+            smooth_speed: true,
+            ..ProcessingOptions::default()
+        };
+
+        let records = ride_with_spike();
+        let processed = preprocess_fit(&records, &options).unwrap().records;
+
+        let peak = processed[..6]
+            .iter()
+            .filter_map(|record| speed(record, "speed"))
+            .fold(f64::MIN, f64::max);
+        let lap = &processed[6];
+        assert_eq!(speed(lap, "max_speed"), Some(peak));
+        assert!(peak < 24.0);
+        assert_eq!(speed(lap, "avg_speed"), Some(7.2));
+    }
+
+    #[test]
+    fn unchanged_record_speeds_keep_the_device_totals() {
+        let options = ProcessingOptions {
+            strip_device_info: true,
+            ..ProcessingOptions::default()
+        };
+
+        let records = ride_with_spike();
+        let processed = preprocess_fit(&records, &options).unwrap().records;
+
+        assert_eq!(speed(&processed[6], "max_speed"), Some(24.0));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::processing::transforms::{
+    FieldEdit, RecordContext, Transform, record_timestamp, timestamp_value,
+};
+use crate::processing::types::{ProcessingWarning, TimestampRepair, WarningCode};
+use fitparser::FitDataRecord;
+use fitparser::profile::MesgNum;
+
+/// Fix record timestamps that repeat or step backwards (device clock glitches),
+/// which otherwise yield zero or inflated speeds.
+pub struct RepairTimestamps {
+    pub mode: TimestampRepair,
+}
+
+impl Transform for RepairTimestamps {
+    fn name(&self) -> &'static str {
+        "repair_timestamps"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        let timestamps: Vec<Option<f64>> =
+            ctx.records().iter().map(record_message_timestamp).collect();
+        let out_of_order = out_of_order_flags(&timestamps);
+        let count = out_of_order.iter().filter(|&&flag| flag).count();
+        if count == 0 {
+            return;
+        }
+
+        match self.mode {
+            TimestampRepair::DropRecords => {
+                let mut keep = out_of_order.iter().map(|&flag| !flag);
+                ctx.retain_records(|_| keep.next().unwrap_or(true));
+                ctx.warn(ProcessingWarning::new(
+                    WarningCode::NonMonotonicTimestamps,
+                    count,
+                    "record",
+                    "with non-monotonic timestamps dropped",
+                ));
+            }
+            TimestampRepair::Resequence => {
+                let adjusted = resequence(&timestamps);
+                ctx.rewrite_fields(|idx, _, field| {
+                    match (field.name(), adjusted.get(idx).copied().flatten()) {
+                        ("timestamp", Some(ts)) => timestamp_value(ts)
+                            .map(FieldEdit::Replace)
+                            .unwrap_or(FieldEdit::Keep),
+                        _ => FieldEdit::Keep,
+                    }
+                });
+                ctx.warn(ProcessingWarning::new(
+                    WarningCode::NonMonotonicTimestamps,
+                    count,
+                    "record",
+                    "with non-monotonic timestamps re-sequenced",
+                ));
+            }
+        }
+    }
+}
+
+/// Timestamp of a `Record` message; other messages are not repaired.
+fn record_message_timestamp(record: &FitDataRecord) -> Option<f64> {
+    if !matches!(record.kind(), MesgNum::Record) {
+        return None;
+    }
+    record_timestamp(record)
+}
+
+/// Number of `Record` messages whose timestamp repeats or steps backwards.
+pub(crate) fn count_out_of_order(records: &[FitDataRecord]) -> usize {
+    let timestamps: Vec<Option<f64>> = records.iter().map(record_message_timestamp).collect();
+    out_of_order_flags(&timestamps)
+        .into_iter()
+        .filter(|&flag| flag)
+        .count()
+}
+
+/// Flag timestamps that are not after the last in-order timestamp.
+fn out_of_order_flags(timestamps: &[Option<f64>]) -> Vec<bool> {
+    let mut last: Option<f64> = None;
+    timestamps
+        .iter()
+        .map(|&timestamp| match timestamp {
+            Some(ts) if last.is_some_and(|last| ts <= last) => true,
+            Some(ts) => {
+                last = Some(ts);
+                false
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// Shift each out-of-order timestamp, and everything after it, to one second
+/// past its predecessor so the spacing of later samples is preserved.
+///
+/// Returns the new timestamp only for entries that change.
+fn resequence(timestamps: &[Option<f64>]) -> Vec<Option<f64>> {
+    let mut offset = 0.0;
+    let mut last: Option<f64> = None;
+
+    timestamps
+        .iter()
+        .map(|&timestamp| {
+            let ts = timestamp?;
+            let mut adjusted = ts + offset;
+            if let Some(previous) = last.filter(|&previous| adjusted <= previous) {
+                offset += previous + 1.0 - adjusted;
+                adjusted = previous + 1.0;
+            }
+            last = Some(adjusted);
+            (adjusted != ts).then_some(adjusted)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resequence_shifts_backward_jump_and_following_samples() {
+        let timestamps = [
+            Some(10.0),
+            Some(11.0),
+            Some(11.0),
+            Some(5.0),
+            Some(6.0),
+            None,
+        ];
+
+        let adjusted = resequence(&timestamps);
+
+        assert_eq!(
+            adjusted,
+            vec![None, None, Some(12.0), Some(13.0), Some(14.0), None]
+        );
+    }
+
+    #[test]
+    fn flags_repeats_and_backward_steps_against_last_in_order_timestamp() {
+        let timestamps = [Some(10.0), Some(10.0), Some(5.0), None, Some(11.0)];
+
+        assert_eq!(
+            out_of_order_flags(&timestamps),
+            vec![false, true, true, false, false]
+        );
+    }
+}
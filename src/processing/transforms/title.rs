@@ -0,0 +1,218 @@
+use crate::processing::transforms::derived_fields::{
+    developer_index_exhausted, next_developer_index,
+};
+use crate::processing::transforms::{RecordContext, Transform};
+use fitparser::profile::MesgNum;
+use fitparser::{BaseType, FitDataField, FitDataRecord, TimestampKind, Value};
+
+/// FIT base type id of a null-terminated string, as written in `FieldDescription`.
+const STRING_BASE_TYPE_ID: u8 = 0x07;
+
+/// Longest title or note written, in bytes; a FIT field holds at most 255
+/// bytes including the terminating null.
+pub const MAX_TEXT_BYTES: usize = 254;
+
+/// `Sport.name` and `Workout.wkt_name`.
+const SPORT_NAME_FIELD: u8 = 3;
+const WORKOUT_NAME_FIELD: u8 = 8;
+
+/// Name the activity and attach notes, for platforms that read them back on
+/// import.
+///
+/// The title replaces the `name` of every `Sport` message (adding one after
+/// `FileId` when the file has none) and the `wkt_name` of `Workout` messages.
+/// Notes are written to `Session` messages as a `notes` developer field.
+pub struct SetActivityTitle {
+    pub title: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl Transform for SetActivityTitle {
+    fn name(&self) -> &'static str {
+        "set_activity_title"
+    }
+
+    fn apply(&self, ctx: &mut RecordContext) {
+        if let Some(title) = self.title.as_deref().map(truncate) {
+            ctx.rebuild(|records| with_title(records, title));
+        }
+        if let Some(notes) = self.notes.as_deref().map(truncate) {
+            set_session_text(ctx, "notes", notes);
+        }
+    }
+}
+
+fn with_title(mut records: Vec<FitDataRecord>, title: &str) -> Vec<FitDataRecord> {
+    let mut named = false;
+    for record in &mut records {
+        let (name, number) = match record.kind() {
+            MesgNum::Sport => ("name", SPORT_NAME_FIELD),
+            MesgNum::Workout => ("wkt_name", WORKOUT_NAME_FIELD),
+            _ => continue,
+        };
+        set_field(record, string_field(name, number, None, title));
+        named |= matches!(record.kind(), MesgNum::Sport);
+    }
+    if !named {
+        let mut sport = FitDataRecord::new(MesgNum::Sport);
+        sport.push(string_field("name", SPORT_NAME_FIELD, None, title));
+        let at = records
+            .iter()
+            .position(|record| matches!(record.kind(), MesgNum::FileId))
+            .map_or(0, |index| index + 1);
+        records.insert(at, sport);
+    }
+    records
+}
+
+/// Write `text` to every `Session` message as the string developer field
+/// `name`, declared under a new developer data index.
+pub(crate) fn set_session_text(ctx: &mut RecordContext, name: &str, text: &str) {
+    let Some(developer_index) = next_developer_index(ctx.records()) else {
+        ctx.warn(developer_index_exhausted(1));
+        return;
+    };
+    ctx.rebuild(|records| {
+        let mut output = Vec::with_capacity(records.len() + 2);
+        let mut declared = false;
+        for mut record in records {
+            if matches!(record.kind(), MesgNum::Session) {
+                if !declared {
+                    output.extend(declarations(developer_index, name));
+                    declared = true;
+                }
+                set_field(
+                    &mut record,
+                    string_field(name, 0, Some(developer_index), text),
+                );
+            }
+            output.push(record);
+        }
+        output
+    });
+}
+
+/// The `DeveloperDataId` and `FieldDescription` declaring string field `name`.
+fn declarations(developer_index: u8, name: &str) -> [FitDataRecord; 2] {
+    let field = |name: &str, number: u8, value: Value| {
+        FitDataField::new(name.to_string(), number, None, value, String::new())
+    };
+    let mut developer = FitDataRecord::new(MesgNum::DeveloperDataId);
+    developer.push(field(
+        "developer_data_index",
+        3,
+        Value::UInt8(developer_index),
+    ));
+    let mut description = FitDataRecord::new(MesgNum::FieldDescription);
+    description.push(field(
+        "developer_data_index",
+        0,
+        Value::UInt8(developer_index),
+    ));
+    description.push(field("field_definition_number", 1, Value::UInt8(0)));
+    description.push(field(
+        "fit_base_type_id",
+        2,
+        Value::UInt8(STRING_BASE_TYPE_ID),
+    ));
+    description.push(field("field_name", 3, Value::String(name.to_string())));
+    [developer, description]
+}
+
+/// Replace the field with the same name and developer index, or append it.
+pub(crate) fn set_field(record: &mut FitDataRecord, replacement: FitDataField) {
+    let original = std::mem::replace(record, FitDataRecord::new(record.kind()));
+    let mut replacement = Some(replacement);
+    for field in original.into_vec() {
+        let same = replacement.as_ref().is_some_and(|new| {
+            new.name() == field.name() && new.developer_data_index() == field.developer_data_index()
+        });
+        match replacement.take_if(|_| same) {
+            Some(new) => record.push(new),
+            None => record.push(field),
+        }
+    }
+    if let Some(new) = replacement {
+        record.push(new);
+    }
+}
+
+fn string_field(name: &str, number: u8, developer_index: Option<u8>, text: &str) -> FitDataField {
+    FitDataField::with_meta(
+        name.to_string(),
+        number,
+        developer_index,
+        Value::String(text.to_string()),
+        Value::String(text.to_string()),
+        String::new(),
+        BaseType::String,
+        1.0,
+        0.0,
+        TimestampKind::None,
+    )
+}
+
+/// `text` trimmed and cut to [`MAX_TEXT_BYTES`] on a character boundary.
+pub(crate) fn truncate(text: &str) -> &str {
+    let text = text.trim();
+    let mut end = text.len().min(MAX_TEXT_BYTES);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::transforms::tests::{field_names, record_with};
+
+    #[test]
+    fn names_the_activity_and_declares_notes_before_the_session() {
+        let mut ctx = RecordContext::new(vec![
+            FitDataRecord::new(MesgNum::FileId),
+            record_with(
+                MesgNum::Workout,
+                &[("wkt_name", Value::String("Z2".into()))],
+            ),
+            FitDataRecord::new(MesgNum::Record),
+            record_with(
+                MesgNum::Session,
+                &[("sport", Value::String("cycling".into()))],
+            ),
+        ]);
+
+        SetActivityTitle {
+            title: Some("  Morning ride ".to_string()),
+            notes: Some("Felt strong".to_string()),
+        }
+        .apply(&mut ctx);
+
+        let kinds: Vec<MesgNum> = ctx.records().iter().map(|record| record.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                MesgNum::FileId,
+                MesgNum::Sport,
+                MesgNum::Workout,
+                MesgNum::Record,
+                MesgNum::DeveloperDataId,
+                MesgNum::FieldDescription,
+                MesgNum::Session,
+            ]
+        );
+        let value = |index: usize, field: usize| ctx.records()[index].fields()[field].value();
+        assert_eq!(value(1, 0), &Value::String("Morning ride".into()));
+        assert_eq!(value(2, 0), &Value::String("Morning ride".into()));
+        assert_eq!(field_names(&ctx.records()[6]), vec!["sport", "notes"]);
+        assert_eq!(ctx.records()[6].fields()[1].developer_data_index(), Some(0));
+    }
+
+    #[test]
+    fn truncates_on_a_character_boundary() {
+        let text = "é".repeat(MAX_TEXT_BYTES);
+
+        assert_eq!(truncate(&text).len(), MAX_TEXT_BYTES);
+        assert_eq!(truncate(&format!("a{text}")).len(), MAX_TEXT_BYTES - 1);
+    }
+}
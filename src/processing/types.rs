@@ -1,72 +1,818 @@
+use crate::processing::annotations::{Annotation, Annotations};
+use crate::processing::anonymize::AnonymizationProfile;
+use crate::processing::conditions::ConditionsAdjustment;
+use crate::processing::devices::DeviceEntry;
+use crate::processing::ebike::EbikeSummary;
+use crate::processing::expressions::{
+    DerivedField, DerivedFieldSummary, ExpressionError, parse_definitions,
+};
+use crate::processing::metrics::{DEFAULT_W_PRIME_JOULES, MeanMaxCurve, WPrimeBalance};
+use crate::processing::series::ChartSeries;
+use crate::processing::ski::SkiRuns;
+use crate::processing::spans::RecordSpan;
+use crate::processing::spikes::SpeedSpike;
+use crate::processing::strength::StrengthSets;
+use crate::processing::structure::FileStructure;
+use crate::processing::terrain::TerrainAnalysis;
+use crate::processing::threshold::ThresholdEstimate;
+use crate::processing::track::TrackPoint;
+use crate::processing::trainer::VirtualRide;
+use crate::processing::transforms::calibrate_distance::DistanceCalibration;
+use crate::processing::transforms::correct_sets::{
+    SetCorrection, SetCorrectionError, parse_set_corrections,
+};
+use crate::processing::transforms::remove_fields::{
+    FieldSelector, FieldSelectorError, parse_field_selectors,
+};
+use crate::processing::transforms::replace_values::{
+    ReplacementError, ValueReplacement, parse_replacements,
+};
+use crate::processing::wind::WindAdjustment;
+use fitparser::FitDataRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::RangeInclusive;
+use std::time::Duration;
 
 /// Simplified representation of a FIT field for display in the UI.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DisplayField {
     pub name: String,
+    /// Field definition number, as listed in the FIT profile or, for
+    /// developer fields, in their `FieldDescription`.
+    pub number: u8,
+    /// Set for developer fields.
+    pub developer_data_index: Option<u8>,
+    /// The value with its units, e.g. `4.2 m/s`.
     pub value: String,
+    /// Units from the profile or the developer field description.
+    pub units: Option<String>,
+    /// The value without formatting or units.
+    pub raw: FieldValueKind,
+}
+
+/// A field value by type, so clients need not parse [`DisplayField::value`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum FieldValueKind {
+    /// Whole numbers, and timestamps as seconds since the Unix epoch.
+    Int(i64),
+    /// Scaled and floating-point values.
+    Float(f64),
+    /// Free text, and arrays as they are displayed.
+    String(String),
+    /// A value of one of the profile's enumerated types, by name when the
+    /// profile names it.
+    Enum(String),
 }
 
 /// Human-readable wrapper around a parsed FIT data record.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DisplayRecord {
     pub message_type: String,
+    /// Global message number, as listed in the FIT profile.
+    pub message_number: u16,
     pub fields: Vec<DisplayField>,
 }
 
+/// Decoded records paired with where each one sits in the original bytes.
+#[derive(Debug, Clone)]
+pub struct ParsedFit {
+    pub records: Vec<FitDataRecord>,
+    /// `spans[i]` is the byte location of `records[i]`.
+    pub spans: Vec<RecordSpan>,
+}
+
 /// Processed FIT output returned to the web handler.
 #[derive(Debug, Clone)]
 pub struct ProcessedFit {
-    /// Fields formatted for rendering.
+    /// Fields formatted for rendering, for the first
+    /// [`ProcessingOptions::preview_records`] messages.
     pub records: Vec<DisplayRecord>,
+    /// Messages in the processed file, including those left out of `records`.
+    pub record_count: usize,
     /// Re-encoded FIT payload, optionally with filtered data fields.
     pub processed_bytes: Vec<u8>,
     /// Summary metrics extracted from the FIT payload.
     pub summary: WorkoutSummary,
+    /// Recording device and paired sensors found in the original upload.
+    pub devices: Vec<DeviceEntry>,
+    /// Message counts, sizes, and developer fields of the original upload.
+    pub structure: FileStructure,
+    /// Stops, laps, and intervals to mark on the speed chart.
+    pub annotations: Annotations,
+    /// Speed spikes in the original upload, with their likely cause.
+    pub speed_spikes: Vec<SpeedSpike>,
+    /// Speed, heart rate, and elevation over time for the results charts.
+    pub charts: ChartSeries,
+    /// Recorded positions after every transform, e.g. for the history heatmap.
+    pub track: Vec<TrackPoint>,
+    /// Problems found or fixed while preprocessing.
+    pub warnings: Vec<ProcessingWarning>,
+    /// How far smoothing moved the speed series; `None` when smoothing was off
+    /// or the file has no distance samples.
+    pub smoothing: Option<SmoothingResiduals>,
+    /// Time spent in each stage that produced this output.
+    pub timings: StageTimings,
 }
 
-/// User-facing toggles that adjust how FIT bytes are rewritten.
+/// Wall-clock time spent in each processing stage.
 #[derive(Debug, Clone, Default)]
+pub struct StageTimings {
+    pub parse: Duration,
+    pub preprocess: Duration,
+    pub encode: Duration,
+    pub derive: Duration,
+    pub display: Duration,
+}
+
+impl StageTimings {
+    pub fn total(&self) -> Duration {
+        self.parse + self.preprocess + self.encode + self.derive + self.display
+    }
+}
+
+impl ProcessedFit {
+    /// Summary metrics, device inventory, annotations, speed spikes, warnings,
+    /// and smoothing residuals as a JSON object, for callers outside the web
+    /// UI.
+    pub fn summary_json(&self) -> serde_json::Result<String> {
+        #[derive(Serialize)]
+        struct SummaryExport<'a> {
+            summary: &'a WorkoutSummary,
+            devices: &'a [DeviceEntry],
+            annotations: &'a [Annotation],
+            speed_spikes: &'a [SpeedSpike],
+            warnings: &'a [ProcessingWarning],
+            #[serde(skip_serializing_if = "Option::is_none")]
+            smoothing: Option<&'a SmoothingResiduals>,
+        }
+
+        serde_json::to_string(&SummaryExport {
+            summary: &self.summary,
+            devices: &self.devices,
+            annotations: &self.annotations.regions,
+            speed_spikes: &self.speed_spikes,
+            warnings: &self.warnings,
+            smoothing: self.smoothing.as_ref(),
+        })
+    }
+}
+
+/// Stable identifier of a [`ProcessingWarning`] for API clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCode {
+    SpeedSpikesSmoothed,
+    NonMonotonicTimestamps,
+    DeveloperFieldsPassedThrough,
+    OrphanedDeveloperFieldsDropped,
+    VendorQuirkFixed,
+    ValuesReplaced,
+    SetsCorrected,
+    OpenWaterCorrected,
+    DistanceResetsStitched,
+    DistanceDipsInterpolated,
+    AltitudeDriftCorrected,
+    ElevationReplaced,
+    ElevationLookupFailed,
+    WeatherLookupFailed,
+    DeveloperFieldsSkipped,
+}
+
+/// Something preprocessing noticed or changed that the user should know about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProcessingWarning {
+    pub code: WarningCode,
+    /// Number of records or fields affected.
+    pub count: usize,
+    pub message: String,
+}
+
+impl ProcessingWarning {
+    /// Warning whose message starts with `count` and `noun`, pluralised.
+    pub fn new(code: WarningCode, count: usize, noun: &str, detail: &str) -> Self {
+        let plural = if count == 1 { "" } else { "s" };
+        Self {
+            code,
+            count,
+            message: format!("{count} {noun}{plural} {detail}"),
+        }
+    }
+}
+
+/// User-facing toggles that adjust how FIT bytes are rewritten.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
 pub struct ProcessingOptions {
     /// Drop `speed` and `enhanced_speed` fields from record messages.
     pub remove_speed_fields: bool,
+    /// Fields to drop as `Message.field`, e.g. `Record.speed`; see
+    /// [`crate::processing::transforms::remove_fields`].
+    pub remove_fields: Vec<String>,
     /// Smooth derived speed values using a sliding window before presenting them.
     pub smooth_speed: bool,
+    /// Strip GPS and altitude data and mark the activity as indoor, for
+    /// treadmill or trainer sessions recorded with GPS switched on.
+    pub flatten_indoor: bool,
+    /// Base the mean pace on elapsed time instead of moving time.
+    pub pace_from_elapsed_time: bool,
+    /// Fallback UTC offset (minutes) for local times when the file has no time zone.
+    pub utc_offset_minutes: Option<i32>,
+    /// Drop `DeviceInfo` messages (device, firmware, and sensor serials) from the output.
+    pub strip_device_info: bool,
+    /// Drop eBike battery, assist-mode, and motor fields, including
+    /// developer fields named like them.
+    pub strip_ebike_data: bool,
+    /// Fix repeated or backwards record timestamps before anything else runs.
+    pub timestamp_repair: Option<TimestampRepair>,
+    /// Insert interpolated records into recording gaps while the timer runs.
+    pub fill_gaps: bool,
+    /// Interpolate smart-recording files to one record per second; implies
+    /// gap filling for every gap.
+    pub one_second_records: bool,
+    /// Collapse record messages to one per this many seconds.
+    pub resample_interval_seconds: Option<u32>,
+    /// Privacy profile applied after every other transform, e.g. before
+    /// sharing an activity publicly.
+    pub anonymize: Option<AnonymizationProfile>,
+    /// Critical power for the W' balance model; the estimated FTP when unset.
+    pub critical_power_watts: Option<u32>,
+    /// Anaerobic work capacity for the W' balance model; 20 kJ when unset.
+    pub w_prime_joules: Option<u32>,
+    /// User-defined `name = expression` fields, one per line or separated by
+    /// `;`; see [`crate::processing::expressions`].
+    pub derived_fields: Option<String>,
+    /// Also write the derived fields into the output FIT as developer fields.
+    pub inject_derived_fields: bool,
+    /// Names of [`crate::processing::plugins`] to run, in order.
+    pub plugins: Vec<String>,
+    /// Activity name written into the output's `Sport` and `Workout` messages.
+    pub title: Option<String>,
+    /// Free-text notes written to the session as a `notes` developer field.
+    pub notes: Option<String>,
+    /// Guess the surface (paved, mixed, unpaved) from speed and cadence variability.
+    pub classify_surface: bool,
+    /// Bike or shoes used, written to the session as a `gear` developer field
+    /// and kept with the upload's history entry.
+    pub gear: Option<String>,
+    /// Multiply distances and speeds by this factor, e.g. `1.064` when a
+    /// treadmill showed 10.0 km but the watch recorded 9.4 km.
+    pub distance_scale: Option<HashableF64>,
+    /// Rescale distances and speeds so the activity totals this many metres;
+    /// takes precedence over `distance_scale`.
+    pub target_distance_meters: Option<u32>,
+    /// Scale power samples and lap/session power totals by this percentage,
+    /// e.g. `2.5` for a meter known to read 2.5% low.
+    pub power_scale_percent: Option<HashableF64>,
+    /// Wheel circumference written to the `BikeProfile`, e.g. `2105` for a
+    /// 700x25c tyre.
+    pub wheel_circumference_mm: Option<u16>,
+    /// Crank length written to the `BikeProfile` and `DeviceSettings`, e.g.
+    /// `172.5`.
+    pub crank_length_mm: Option<HashableF64>,
+    /// Sentinel values to clear from records, as `field = value` rules with
+    /// an optional `-> interpolate`, one per line or separated by `;`; see
+    /// [`crate::processing::transforms::replace_values`].
+    pub replace_values: Option<String>,
+    /// Repetitions and weights of strength-training sets to correct, as
+    /// `set: reps = n, weight = kg`, one set per line or separated by `;`;
+    /// see [`crate::processing::transforms::correct_sets`].
+    pub correct_sets: Option<String>,
+    /// Hand back the uploaded bytes instead of a re-encoded copy when no
+    /// transform rewrites the records, so the download is bit-identical.
+    pub preserve_original: bool,
+    /// Messages formatted for display, from the start of the file; `0`
+    /// builds none, for callers that only want the download and summary.
+    /// Every message when unset.
+    pub preview_records: Option<u32>,
+    /// Smooth the GPS track of open-water swims while strokes are detected,
+    /// hold it still during rests, and recompute distances from it.
+    pub correct_open_water: bool,
+    /// Offset record distances after each point where the counter restarts
+    /// near 0, e.g. after a device crashed, and interpolate single samples
+    /// that dip below their neighbours.
+    pub stitch_distance_resets: bool,
+    /// Skip the fixes for known quirks of the device vendor's files; see
+    /// [`crate::processing::compat`].
+    pub skip_vendor_fixes: bool,
+    /// Remove barometric altitude drift from activities that end where they
+    /// started, and recompute lap and session ascent and descent.
+    pub correct_altitude_drift: bool,
+    /// Replace record altitudes with the server's elevation service, for
+    /// devices with a broken barometer; needs a server built with the
+    /// `elevation` feature and `RUSTYFIT_ELEVATION_URL`.
+    pub replace_elevation: bool,
+    /// Look up the weather at the activity's start from the server's weather
+    /// provider; needs a server built with the `weather` feature and
+    /// `RUSTYFIT_WEATHER_URL`.
+    pub enrich_weather: bool,
+    /// Field overrides for single messages of the processed file, added with
+    /// `PATCH /api/v1/results/:id/records/:index` rather than with the upload,
+    /// since indexes only make sense once a file has been processed.
+    #[serde(skip)]
+    pub record_edits: Vec<RecordEdit>,
+    /// Altitude the elevation service gave each recorded position of the
+    /// upload, keyed by `(position_lat, position_long)` in semicircles so
+    /// transforms that add or drop messages cannot misplace them; filled in
+    /// by the server before processing when `replace_elevation` is set, as
+    /// the lookup is asynchronous.
+    #[serde(skip)]
+    pub looked_up_altitudes: BTreeMap<(i32, i32), HashableF64>,
+    /// Server-side resource limits; never taken from client-supplied options.
+    #[serde(skip)]
+    pub limits: ProcessingLimits,
+}
+
+/// An `f64` compared bit for bit, so options holding one, such as a scale
+/// factor, a record edit value, a crank length, or a looked-up altitude, can
+/// still be hashed for the upload cache.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(transparent)]
+pub struct HashableF64(pub f64);
+
+impl PartialEq for HashableF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for HashableF64 {}
+
+impl Hash for HashableF64 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+/// One field of one processed message set to a new value, or removed when
+/// `value` is `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecordEdit {
+    /// Position of the message in the processed file.
+    pub index: usize,
+    pub field: String,
+    pub value: Option<HashableF64>,
+}
+
+/// Upper bounds that protect the server from pathological uploads.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProcessingLimits {
+    /// Maximum number of data messages in one file.
+    pub max_records: Option<usize>,
+    /// Maximum wall-clock time spent decoding one file.
+    pub max_decode_time: Option<Duration>,
+}
+
+/// Default cap on data messages per file; a 24-hour 1 Hz recording is ~90k.
+pub const DEFAULT_MAX_RECORDS: usize = 2_000_000;
+
+/// Furthest a time zone is from UTC, in minutes (UTC+14:00).
+pub const MAX_UTC_OFFSET_MINUTES: i32 = 14 * 60;
+
+/// Accepted `distance_scale` factors; anything further off is a typo rather
+/// than a miscalibrated footpod or treadmill.
+pub const DISTANCE_SCALE_RANGE: RangeInclusive<f64> = 0.5..=1.5;
+
+/// Accepted `power_scale_percent` corrections; power meters are specified to
+/// a few percent, so anything larger is not a calibration.
+pub const POWER_SCALE_PERCENT_RANGE: RangeInclusive<f64> = -20.0..=20.0;
+
+/// Default cap on decode time per file.
+pub const DEFAULT_MAX_DECODE_TIME: Duration = Duration::from_secs(30);
+
+impl Default for ProcessingLimits {
+    fn default() -> Self {
+        Self {
+            max_records: Some(DEFAULT_MAX_RECORDS),
+            max_decode_time: Some(DEFAULT_MAX_DECODE_TIME),
+        }
+    }
+}
+
+impl ProcessingLimits {
+    /// Defaults overridden by `RUSTYFIT_MAX_RECORDS` and
+    /// `RUSTYFIT_MAX_DECODE_SECONDS`; a value of `0` disables that limit.
+    pub fn from_env() -> Self {
+        let read = |name: &str| -> Option<u64> { std::env::var(name).ok()?.trim().parse().ok() };
+        let defaults = Self::default();
+        Self {
+            max_records: match read("RUSTYFIT_MAX_RECORDS") {
+                Some(0) => None,
+                Some(limit) => Some(limit as usize),
+                None => defaults.max_records,
+            },
+            max_decode_time: match read("RUSTYFIT_MAX_DECODE_SECONDS") {
+                Some(0) => None,
+                Some(seconds) => Some(Duration::from_secs(seconds)),
+                None => defaults.max_decode_time,
+            },
+        }
+    }
+}
+
+/// How [`ProcessingOptions::timestamp_repair`] handles out-of-order records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampRepair {
+    /// Drop records whose timestamp is not after the previous record's.
+    DropRecords,
+    /// Move offending records one second past their predecessor, shifting
+    /// later records by the same amount.
+    Resequence,
+}
+
+impl std::str::FromStr for TimestampRepair {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "drop_records" | "drop" => Ok(TimestampRepair::DropRecords),
+            "resequence" => Ok(TimestampRepair::Resequence),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ProcessingOptions {
+    /// Critical power and W' for the W' balance model, falling back to
+    /// `estimated_ftp` and [`DEFAULT_W_PRIME_JOULES`].
+    pub fn w_prime_model(&self, estimated_ftp: Option<f64>) -> Option<(f64, f64)> {
+        let critical_power = self.critical_power_watts.map(f64::from).or(estimated_ftp)?;
+        let w_prime = self
+            .w_prime_joules
+            .map_or(DEFAULT_W_PRIME_JOULES, f64::from);
+        Some((critical_power, w_prime))
+    }
+
+    /// Parsed [`ProcessingOptions::derived_fields`]; empty when unset.
+    pub fn derived_field_definitions(&self) -> Result<Vec<DerivedField>, ExpressionError> {
+        self.derived_fields
+            .as_deref()
+            .map_or(Ok(Vec::new()), parse_definitions)
+    }
+
+    /// Parsed [`ProcessingOptions::replace_values`]; empty when unset.
+    pub fn value_replacements(&self) -> Result<Vec<ValueReplacement>, ReplacementError> {
+        self.replace_values
+            .as_deref()
+            .map_or(Ok(Vec::new()), parse_replacements)
+    }
+
+    /// Parsed [`ProcessingOptions::remove_fields`].
+    pub fn field_removals(&self) -> Result<Vec<FieldSelector>, FieldSelectorError> {
+        parse_field_selectors(&self.remove_fields)
+    }
+
+    /// Parsed [`ProcessingOptions::correct_sets`]; empty when unset.
+    pub fn set_corrections(&self) -> Result<Vec<SetCorrection>, SetCorrectionError> {
+        self.correct_sets
+            .as_deref()
+            .map_or(Ok(Vec::new()), parse_set_corrections)
+    }
+
+    /// Check every option that can hold an invalid value, such as a rule that
+    /// does not parse or a scale outside its range, so processing fails
+    /// rather than skipping or applying it.
+    pub fn validate(&self) -> Result<(), FitProcessError> {
+        let invalid = |err: &dyn fmt::Display| FitProcessError::InvalidOption(err.to_string());
+        self.derived_field_definitions()
+            .map_err(|err| invalid(&err))?;
+        self.value_replacements().map_err(|err| invalid(&err))?;
+        self.set_corrections().map_err(|err| invalid(&err))?;
+        self.field_removals().map_err(|err| invalid(&err))?;
+        let out_of_range =
+            |range: &RangeInclusive<f64>, HashableF64(value): HashableF64| !range.contains(&value);
+        if self
+            .distance_scale
+            .is_some_and(|factor| out_of_range(&DISTANCE_SCALE_RANGE, factor))
+        {
+            return Err(FitProcessError::InvalidOption(format!(
+                "distance_scale must be between {} and {}",
+                DISTANCE_SCALE_RANGE.start(),
+                DISTANCE_SCALE_RANGE.end()
+            )));
+        }
+        if self
+            .power_scale_percent
+            .is_some_and(|percent| out_of_range(&POWER_SCALE_PERCENT_RANGE, percent))
+        {
+            return Err(FitProcessError::InvalidOption(format!(
+                "power_scale_percent must be between {} and {}",
+                POWER_SCALE_PERCENT_RANGE.start(),
+                POWER_SCALE_PERCENT_RANGE.end()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Names of the options set to anything but their default, for usage
+    /// counts that must not reveal the values.
+    pub fn names_in_use(&self) -> Vec<&'static str> {
+        // Destructured so that a new option cannot be left out.
+        let Self {
+            remove_speed_fields,
+            remove_fields,
+            smooth_speed,
+            flatten_indoor,
+            pace_from_elapsed_time,
+            utc_offset_minutes,
+            strip_device_info,
+            strip_ebike_data,
+            timestamp_repair,
+            fill_gaps,
+            one_second_records,
+            resample_interval_seconds,
+            anonymize,
+            critical_power_watts,
+            w_prime_joules,
+            derived_fields,
+            inject_derived_fields,
+            plugins,
+            title,
+            notes,
+            classify_surface,
+            gear,
+            distance_scale,
+            target_distance_meters,
+            power_scale_percent,
+            wheel_circumference_mm,
+            crank_length_mm,
+            replace_values,
+            correct_sets,
+            preserve_original,
+            preview_records,
+            correct_open_water,
+            stitch_distance_resets,
+            skip_vendor_fixes,
+            correct_altitude_drift,
+            replace_elevation,
+            enrich_weather,
+            record_edits,
+            looked_up_altitudes: _,
+            limits: _,
+        } = self;
+        [
+            ("remove_speed_fields", *remove_speed_fields),
+            ("remove_fields", !remove_fields.is_empty()),
+            ("smooth_speed", *smooth_speed),
+            ("flatten_indoor", *flatten_indoor),
+            ("pace_from_elapsed_time", *pace_from_elapsed_time),
+            ("utc_offset_minutes", utc_offset_minutes.is_some()),
+            ("strip_device_info", *strip_device_info),
+            ("strip_ebike_data", *strip_ebike_data),
+            ("timestamp_repair", timestamp_repair.is_some()),
+            ("fill_gaps", *fill_gaps),
+            ("one_second_records", *one_second_records),
+            (
+                "resample_interval_seconds",
+                resample_interval_seconds.is_some(),
+            ),
+            ("anonymize", anonymize.is_some()),
+            ("critical_power_watts", critical_power_watts.is_some()),
+            ("w_prime_joules", w_prime_joules.is_some()),
+            ("derived_fields", derived_fields.is_some()),
+            ("inject_derived_fields", *inject_derived_fields),
+            ("plugins", !plugins.is_empty()),
+            ("title", title.is_some()),
+            ("notes", notes.is_some()),
+            ("classify_surface", *classify_surface),
+            ("gear", gear.is_some()),
+            ("distance_scale", distance_scale.is_some()),
+            ("target_distance_meters", target_distance_meters.is_some()),
+            ("power_scale_percent", power_scale_percent.is_some()),
+            ("wheel_circumference_mm", wheel_circumference_mm.is_some()),
+            ("crank_length_mm", crank_length_mm.is_some()),
+            ("replace_values", replace_values.is_some()),
+            ("correct_sets", correct_sets.is_some()),
+            ("preserve_original", *preserve_original),
+            ("preview_records", preview_records.is_some()),
+            ("correct_open_water", *correct_open_water),
+            ("stitch_distance_resets", *stitch_distance_resets),
+            ("skip_vendor_fixes", *skip_vendor_fixes),
+            ("correct_altitude_drift", *correct_altitude_drift),
+            ("replace_elevation", *replace_elevation),
+            ("enrich_weather", *enrich_weather),
+            ("record_edits", !record_edits.is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect()
+    }
+
+    /// Equivalent options with settings that cannot affect the output
+    /// normalised, so caches treat e.g. a one-second resample interval and no
+    /// resampling as the same request.
+    pub fn canonical(&self) -> Self {
+        let derived_fields = self
+            .derived_fields
+            .as_deref()
+            .map(str::trim)
+            .filter(|fields| !fields.is_empty())
+            .map(str::to_string);
+        Self {
+            fill_gaps: self.fill_gaps && !self.one_second_records,
+            resample_interval_seconds: self.resample_interval_seconds.filter(|&s| s > 1),
+            inject_derived_fields: self.inject_derived_fields && derived_fields.is_some(),
+            derived_fields,
+            gear: self.gear_tag().map(str::to_string),
+            ..self.clone()
+        }
+    }
+
+    /// Distance correction requested by `target_distance_meters` or
+    /// `distance_scale`, in that order of precedence.
+    pub fn distance_calibration(&self) -> Option<DistanceCalibration> {
+        match (self.target_distance_meters, self.distance_scale) {
+            (Some(meters), _) if meters > 0 => {
+                Some(DistanceCalibration::TargetMeters(f64::from(meters)))
+            }
+            (_, Some(HashableF64(factor))) => Some(DistanceCalibration::Scale(factor)),
+            _ => None,
+        }
+    }
+
+    /// [`ProcessingOptions::gear`] trimmed; `None` when blank.
+    pub fn gear_tag(&self) -> Option<&str> {
+        self.gear
+            .as_deref()
+            .map(str::trim)
+            .filter(|gear| !gear.is_empty())
+    }
+
+    /// `utc_offset_minutes` in seconds, clamped to the ±14 hours real time
+    /// zones span.
+    pub fn utc_offset_seconds(&self) -> Option<i32> {
+        self.utc_offset_minutes
+            .map(|minutes| minutes.clamp(-MAX_UTC_OFFSET_MINUTES, MAX_UTC_OFFSET_MINUTES) * 60)
+    }
+
+    /// Parse options from a JSON object using the field names above; missing
+    /// fields and empty input fall back to the defaults.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        if json.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(json)
+    }
 }
 
 /// Derived overview metrics from the FIT records.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct WorkoutSummary {
+    /// Earliest timestamp in the file, in seconds since the Unix epoch.
+    pub start_timestamp: Option<f64>,
+    /// Latest timestamp in the file, in seconds since the Unix epoch.
+    pub end_timestamp: Option<f64>,
+    /// Offset from UTC used for local start/end times, from the file's
+    /// `local_timestamp` or the user's fallback.
+    pub utc_offset_seconds: Option<i32>,
     pub duration_seconds: Option<f64>,
+    /// Time spent moving with the timer running, excluding stops and pauses.
+    pub moving_time_seconds: Option<f64>,
     pub workout_type: Option<String>,
     pub distance_meters: Option<f64>,
+    pub elevation_gain_meters: Option<f64>,
     pub speed_min: Option<f64>,
     pub speed_mean: Option<f64>,
     pub speed_max: Option<f64>,
     pub heart_rate_min: Option<f64>,
     pub heart_rate_mean: Option<f64>,
     pub heart_rate_max: Option<f64>,
+    /// Pedalling metrics from power meters that report them; `None` when no
+    /// record carries any of them.
+    pub cycling_dynamics: Option<CyclingDynamics>,
+    /// FTP and threshold heart rate estimated from the activity's best efforts.
+    pub thresholds: Option<ThresholdEstimate>,
+    /// Lowest W' balance, modelled against the rider's critical power or
+    /// else the estimated FTP.
+    pub w_prime_balance: Option<WPrimeBalance>,
+    /// Best power and speed sustained for a range of durations.
+    pub mean_max: Option<MeanMaxCurve>,
+    /// Pace and power adjusted for the activity's heat and altitude.
+    pub conditions: Option<ConditionsAdjustment>,
+    /// Range and mean of each user-defined derived field.
+    pub derived_fields: Vec<DerivedFieldSummary>,
+    /// Time at each gradient and the elevation profile.
+    pub terrain: Option<TerrainAnalysis>,
+    /// Runs and lift rides of alpine skiing and snowboarding activities.
+    pub ski_runs: Option<SkiRuns>,
+    /// Active sets of strength-training workouts, from their `Set` messages.
+    pub strength_sets: Option<StrengthSets>,
+    /// Battery use, assist modes, and motor power of eBike rides.
+    pub ebike: Option<EbikeSummary>,
+    /// Incline and workout steps of rides recorded by virtual-riding apps.
+    pub virtual_ride: Option<VirtualRide>,
+    /// Weather at the start, looked up by the server when
+    /// [`ProcessingOptions::enrich_weather`] is set.
+    pub weather: Option<Weather>,
+    /// Headwind along the route and wind-adjusted pace and power, from the
+    /// start's `weather`.
+    pub wind: Option<WindAdjustment>,
+}
+
+/// Historical weather at a place and hour, from the weather provider.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Weather {
+    /// Start of the hour observed, in seconds since the Unix epoch.
+    pub observed_at: f64,
+    pub temperature_celsius: Option<f64>,
+    pub relative_humidity_percent: Option<f64>,
+    pub wind_speed: Option<f64>,
+    /// Direction the wind blows from, in degrees clockwise from north.
+    pub wind_direction_degrees: Option<f64>,
+}
+
+/// Record-level cycling dynamics averaged over the activity, in percent.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CyclingDynamics {
+    /// Left leg's share of power; the right leg's is `100 - left_balance`.
+    pub left_balance: Option<f64>,
+    pub left_torque_effectiveness: Option<f64>,
+    pub right_torque_effectiveness: Option<f64>,
+    pub left_pedal_smoothness: Option<f64>,
+    pub right_pedal_smoothness: Option<f64>,
+    /// Pedal smoothness from single-sided or combined sensors.
+    pub combined_pedal_smoothness: Option<f64>,
+}
+
+/// Differences between the raw and smoothed speed series, for judging how
+/// aggressive a smoothing window was.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SmoothingResiduals {
+    /// Moving-average window, in samples.
+    pub window: usize,
+    /// Number of speed intervals compared.
+    pub samples: usize,
+    /// Root-mean-square difference between raw and smoothed speed, in m/s.
+    pub rmse: f64,
+    /// Largest absolute difference, in m/s.
+    pub max_abs_residual: f64,
+    /// Intervals whose speed moved by more than `threshold`.
+    pub altered_samples: usize,
+    /// Change in m/s above which an interval counts as altered.
+    pub threshold: f64,
 }
 
 /// Default window size (in samples) for moving-average speed smoothing.
 pub const SPEED_SMOOTHING_WINDOW: usize = 5;
 
+/// Recording gaps longer than this many seconds are filled when gap filling is on;
+/// shorter ones are normal for smart (variable-rate) recording.
+pub const GAP_FILL_MIN_SECONDS: f64 = 10.0;
+
+/// Longer gaps are treated as a stop rather than a dropout and left unfilled.
+pub const GAP_FILL_MAX_SECONDS: f64 = 600.0;
+
+/// Smoothing that moves an interval's speed by more than this many m/s counts
+/// as altering it in [`SmoothingResiduals`].
+pub const SMOOTHING_RESIDUAL_THRESHOLD: f64 = 1.0;
+
+/// A raw interval speed this many m/s above its smoothed value counts as a spike.
+pub const SPEED_SPIKE_MIN_DELTA: f64 = 5.0;
+
+/// Speed (m/s) below which an interval counts as stopped for moving time.
+pub const MOVING_SPEED_THRESHOLD: f64 = 0.5;
+
 #[derive(Debug, Default)]
 pub struct DerivedWorkoutData {
     pub summary: WorkoutSummary,
 }
 
-#[derive(Debug)]
+/// Why a file could not be parsed, preprocessed, or encoded.
+#[derive(Debug, thiserror::Error)]
 pub enum FitProcessError {
+    /// The data section does not decode as FIT messages.
+    #[error("Failed to decode FIT file: {0}")]
     ParseError(String),
+    /// The file header is missing, truncated, or lacks the `.FIT` signature.
+    #[error("Invalid FIT file header: {0}")]
+    InvalidHeader(String),
+    /// The processed records could not be written back as a valid FIT file.
+    #[error("Failed to encode FIT file: {0}")]
+    EncodeError(String),
+    /// The file uses something this version cannot process, such as a newer
+    /// protocol version.
+    #[error("Unsupported FIT feature: {0}")]
+    UnsupportedFeature(String),
+    #[error("{0}")]
+    LimitExceeded(ExceededLimit),
+    /// An option written as rules, such as `replace_values`, does not parse;
+    /// the message names the rule.
+    #[error("{0}")]
+    InvalidOption(String),
+    /// Stopped at the caller's request; see [`crate::processing::progress::CancellationToken`].
+    #[error("Processing was cancelled")]
+    Cancelled,
 }
 
-impl fmt::Display for FitProcessError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            FitProcessError::ParseError(msg) => write!(f, "Failed to decode FIT file: {msg}"),
-        }
-    }
+/// Which [`ProcessingLimits`] entry a file ran into.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ExceededLimit {
+    #[error("FIT file has {found} messages, more than the limit of {limit}")]
+    Records { limit: usize, found: usize },
+    #[error("FIT file took longer than {} seconds to decode", limit.as_secs_f64())]
+    DecodeTime { limit: Duration },
 }
-
-impl std::error::Error for FitProcessError {}
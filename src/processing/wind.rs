@@ -0,0 +1,277 @@
+//! Headwind and tailwind along the route, from the weather at the start.
+//!
+//! The track is cut into stretches of at least [`WIND_SEGMENT_METERS`], and
+//! the wind's component along each one's heading is taken as the headwind
+//! (negative for a tailwind). Air resistance grows with the square of the
+//! airspeed, so each stretch is also given the speed the same aerodynamic
+//! power would have held in still air, and the power its speed would have
+//! needed there, for a road cyclist of [`DRAG_AREA`] at sea-level air
+//! density. Rolling resistance and gradient do not depend on the wind and are
+//! left out, so these are estimates for comparing rides, not measurements.
+
+use crate::processing::anonymize::{distance_meters, record_position};
+use crate::processing::summary::field_value_to_f64;
+use crate::processing::transforms::record_timestamp;
+use crate::processing::types::Weather;
+use fitparser::FitDataRecord;
+use serde::Serialize;
+
+/// Shortest stretch of track given its own heading.
+pub const WIND_SEGMENT_METERS: f64 = 1000.0;
+
+/// Drag coefficient times frontal area of a cyclist on the hoods, in m².
+pub const DRAG_AREA: f64 = 0.32;
+
+/// Air density at sea level and 15 °C, in kg/m³.
+pub const AIR_DENSITY: f64 = 1.225;
+
+/// One stretch of track and the wind along it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WindSegment {
+    /// Seconds since the Unix epoch.
+    pub start_timestamp: f64,
+    pub distance_meters: f64,
+    /// Direction of travel from start to end, in degrees clockwise from north.
+    pub heading_degrees: f64,
+    /// Wind component against the direction of travel, in m/s.
+    pub headwind: f64,
+    pub speed: f64,
+    /// Speed the same aerodynamic power would have held in still air; zero
+    /// when a tailwind faster than the rider did all the aerodynamic work.
+    pub adjusted_speed: f64,
+    /// Mean record power, in watts.
+    pub power: Option<f64>,
+    /// `power` less what the wind added to or took from the air resistance.
+    pub adjusted_power: Option<f64>,
+}
+
+/// The start's wind resolved along the route.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WindAdjustment {
+    /// Distance-weighted mean headwind, in m/s.
+    pub mean_headwind: f64,
+    /// Share of the distance ridden into the wind, from 0 to 1.
+    pub headwind_share: f64,
+    /// Time-weighted mean of the segments' adjusted speeds.
+    pub adjusted_speed_mean: Option<f64>,
+    /// Time-weighted mean of the segments' adjusted power.
+    pub adjusted_power_mean: Option<f64>,
+    pub segments: Vec<WindSegment>,
+}
+
+/// Resolve `weather`'s wind along the positions of `records`. Returns `None`
+/// without a wind speed and direction or a track of at least one segment.
+pub fn adjust_for_wind(records: &[FitDataRecord], weather: &Weather) -> Option<WindAdjustment> {
+    let wind_speed = weather.wind_speed?;
+    let wind_from = weather.wind_direction_degrees?;
+    let segments: Vec<WindSegment> = track_segments(records)
+        .into_iter()
+        .map(|segment| segment.with_wind(wind_speed, wind_from))
+        .collect();
+    if segments.is_empty() {
+        return None;
+    }
+
+    let distance: f64 = segments.iter().map(|segment| segment.distance_meters).sum();
+    let seconds = |segment: &WindSegment| segment.distance_meters / segment.speed;
+    let time_weighted = |value: fn(&WindSegment) -> Option<f64>| {
+        let weighted: Vec<(f64, f64)> = segments
+            .iter()
+            .filter_map(|segment| Some((value(segment)?, seconds(segment))))
+            .collect();
+        let total: f64 = weighted.iter().map(|&(_, seconds)| seconds).sum();
+        (total > 0.0).then(|| {
+            weighted
+                .iter()
+                .map(|&(value, seconds)| value * seconds)
+                .sum::<f64>()
+                / total
+        })
+    };
+    Some(WindAdjustment {
+        mean_headwind: segments
+            .iter()
+            .map(|segment| segment.headwind * segment.distance_meters)
+            .sum::<f64>()
+            / distance,
+        headwind_share: segments
+            .iter()
+            .filter(|segment| segment.headwind > 0.0)
+            .map(|segment| segment.distance_meters)
+            .sum::<f64>()
+            / distance,
+        adjusted_speed_mean: time_weighted(|segment| Some(segment.adjusted_speed)),
+        adjusted_power_mean: time_weighted(|segment| segment.adjusted_power),
+        segments,
+    })
+}
+
+/// A segment before the wind is applied.
+struct TrackSegment {
+    start_timestamp: f64,
+    seconds: f64,
+    distance_meters: f64,
+    heading_degrees: f64,
+    power: Option<f64>,
+}
+
+impl TrackSegment {
+    fn with_wind(self, wind_speed: f64, wind_from: f64) -> WindSegment {
+        let headwind = wind_speed * (wind_from - self.heading_degrees).to_radians().cos();
+        let speed = self.distance_meters / self.seconds;
+        let airspeed = speed + headwind;
+        let drag = airspeed * airspeed.abs();
+        let aero_power = |drag: f64| 0.5 * AIR_DENSITY * DRAG_AREA * drag * speed;
+        let wind_power = aero_power(drag) - aero_power(speed * speed);
+        WindSegment {
+            start_timestamp: self.start_timestamp,
+            distance_meters: self.distance_meters,
+            heading_degrees: self.heading_degrees,
+            headwind,
+            speed,
+            // A tailwind faster than the rider pushes them along: no
+            // aerodynamic power was spent, so there is no still-air speed.
+            adjusted_speed: (drag * speed).cbrt().max(0.0),
+            power: self.power,
+            adjusted_power: self.power.map(|power| (power - wind_power).max(0.0)),
+        }
+    }
+}
+
+/// Consecutive stretches of at least [`WIND_SEGMENT_METERS`] between
+/// positioned records; a shorter remainder at the end is left out.
+fn track_segments(records: &[FitDataRecord]) -> Vec<TrackSegment> {
+    let mut segments = Vec::new();
+    let mut start: Option<((f64, f64), f64)> = None;
+    let mut last = None;
+    let mut distance = 0.0;
+    let mut powers = Vec::new();
+    for record in records {
+        let (Some(position), Some(timestamp)) = (record_position(record), record_timestamp(record))
+        else {
+            continue;
+        };
+        let Some((from, started)) = start else {
+            start = Some((position, timestamp));
+            last = Some(position);
+            continue;
+        };
+        if let Some(last) = last {
+            distance += distance_meters(last, position);
+        }
+        last = Some(position);
+        powers.extend(
+            record
+                .fields()
+                .iter()
+                .find(|field| field.name() == "power")
+                .and_then(field_value_to_f64),
+        );
+        if distance < WIND_SEGMENT_METERS || timestamp <= started {
+            continue;
+        }
+        segments.push(TrackSegment {
+            start_timestamp: started,
+            seconds: timestamp - started,
+            distance_meters: distance,
+            heading_degrees: bearing_degrees(from, position),
+            power: (!powers.is_empty()).then(|| powers.iter().sum::<f64>() / powers.len() as f64),
+        });
+        start = Some((position, timestamp));
+        distance = 0.0;
+        powers.clear();
+    }
+    segments
+}
+
+/// Initial great-circle bearing from `from` to `to`, in degrees clockwise
+/// from north.
+fn bearing_degrees((lat1, long1): (f64, f64), (lat2, long2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlong = (long2 - long1).to_radians();
+    let y = dlong.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlong.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::anonymize::SEMICIRCLES_TO_DEGREES;
+    use crate::processing::transforms::tests::record_with;
+    use fitparser::Value;
+    use fitparser::profile::MesgNum;
+
+    /// About 2 km north then back south at 10 m/s and 200 W, one record per
+    /// 100.5 m.
+    fn out_and_back() -> Vec<FitDataRecord> {
+        let step_degrees = 100.5 / 111_195.0;
+        (0..=40)
+            .map(|step| {
+                let along = if step <= 20 { step } else { 40 - step };
+                let latitude = 45.0 + f64::from(along) * step_degrees;
+                record_with(
+                    MesgNum::Record,
+                    &[
+                        ("timestamp", Value::Float64(f64::from(step) * 10.0)),
+                        (
+                            "position_lat",
+                            Value::SInt32((latitude / SEMICIRCLES_TO_DEGREES) as i32),
+                        ),
+                        ("position_long", Value::SInt32(0)),
+                        ("power", Value::UInt16(200)),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    fn northerly(speed: f64) -> Weather {
+        Weather {
+            wind_speed: Some(speed),
+            wind_direction_degrees: Some(0.0),
+            ..Weather::default()
+        }
+    }
+
+    #[test]
+    fn headwind_legs_are_adjusted_up_and_tailwind_legs_down() {
+        let wind = adjust_for_wind(&out_and_back(), &northerly(4.0)).unwrap();
+
+        assert_eq!(wind.segments.len(), 4);
+        let (out, back) = (&wind.segments[0], &wind.segments[3]);
+        assert!(out.heading_degrees < 1.0 || out.heading_degrees > 359.0);
+        assert!((out.headwind - 4.0).abs() < 1e-6);
+        assert!((back.headwind + 4.0).abs() < 1e-6);
+        assert!(out.adjusted_speed > out.speed && back.adjusted_speed < back.speed);
+        assert!(out.adjusted_power.unwrap() < 200.0);
+        assert!(back.adjusted_power.unwrap() > 200.0);
+        assert!(wind.mean_headwind.abs() < 1e-6);
+        assert!((wind.headwind_share - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn still_air_leaves_the_ride_as_it_was() {
+        let wind = adjust_for_wind(&out_and_back(), &northerly(0.0)).unwrap();
+
+        let segment = &wind.segments[0];
+        assert!((segment.adjusted_speed - segment.speed).abs() < 1e-9);
+        assert_eq!(segment.adjusted_power, Some(200.0));
+        assert!(adjust_for_wind(&out_and_back(), &Weather::default()).is_none());
+    }
+
+    #[test]
+    fn a_tailwind_faster_than_the_rider_adjusts_to_a_standstill() {
+        let wind = adjust_for_wind(&out_and_back(), &northerly(12.0)).unwrap();
+
+        let back = &wind.segments[3];
+        assert!((back.headwind + 12.0).abs() < 1e-6);
+        assert_eq!(back.adjusted_speed, 0.0);
+        assert!(
+            wind.segments
+                .iter()
+                .all(|segment| segment.adjusted_speed >= 0.0)
+        );
+        assert!(wind.adjusted_speed_mean.unwrap() > 0.0);
+    }
+}
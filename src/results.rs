@@ -0,0 +1,391 @@
+use crate::downloads::DOWNLOAD_TTL;
+use crate::processing::annotations::Annotations;
+use crate::processing::preprocess::preprocess_fit;
+use crate::processing::{FitProcessError, ProcessedFit, ProcessingOptions, WorkoutSummary};
+use axum::body::Bytes;
+use axum::http::{HeaderValue, StatusCode};
+use fitparser::FitDataRecord;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// How long an `Idempotency-Key` is remembered; matches the lifetime of the
+/// download links in the stored responses.
+pub const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Most parsed uploads kept at once; the oldest is dropped to make room.
+pub const MAX_STORED_RESULTS: usize = 256;
+
+/// Parsed upload kept around so follow-up requests can recompute without re-uploading.
+#[derive(Clone)]
+pub struct StoredResult {
+    pub records: Arc<Vec<FitDataRecord>>,
+    pub options: ProcessingOptions,
+    /// What the exports need from the processed file, so they don't reprocess it.
+    pub report: Arc<StoredReport>,
+}
+
+impl StoredResult {
+    /// Messages of the processed file, preprocessed by the first search,
+    /// edit, or route export and reused by the rest.
+    pub fn processed_records(&self) -> Result<Arc<Vec<FitDataRecord>>, FitProcessError> {
+        if let Some(records) = self.report.records.get() {
+            return Ok(records.clone());
+        }
+        let processed = preprocess_fit(&self.records, &self.options)?;
+        let records = Arc::new(processed.records.into_owned());
+        Ok(self.report.records.get_or_init(|| records).clone())
+    }
+}
+
+/// Summary and annotations of a processed upload, as the PDF report uses them.
+#[derive(Debug, Default)]
+pub struct StoredReport {
+    pub summary: WorkoutSummary,
+    pub annotations: Annotations,
+    /// See [`StoredResult::processed_records`].
+    records: OnceLock<Arc<Vec<FitDataRecord>>>,
+}
+
+impl StoredReport {
+    pub fn from_processed(processed: &ProcessedFit) -> Self {
+        Self {
+            summary: processed.summary.clone(),
+            annotations: processed.annotations.clone(),
+            records: OnceLock::new(),
+        }
+    }
+}
+
+struct ResultEntry {
+    result: StoredResult,
+    stored_at: Instant,
+    /// Insertion order, which breaks ties between equal `stored_at`s.
+    order: u64,
+    /// Held for the whole of a read-modify-write, such as a record edit.
+    edit_lock: Arc<Mutex<()>>,
+}
+
+/// Response first sent for an `Idempotency-Key`, replayed for retries.
+#[derive(Clone, Debug)]
+pub struct IdempotentResponse {
+    pub status: StatusCode,
+    pub content_type: Option<HeaderValue>,
+    pub body: Bytes,
+}
+
+enum IdempotencyState {
+    InFlight,
+    Done(IdempotentResponse),
+}
+
+struct IdempotencyEntry {
+    /// Content hash of the request body the key was first used with.
+    fingerprint: [u8; 32],
+    state: IdempotencyState,
+    created_at: Instant,
+}
+
+/// Outcome of presenting an `Idempotency-Key`.
+#[derive(Debug)]
+pub enum IdempotencyClaim {
+    /// The key is new; the caller processes the request and then calls
+    /// [`ResultStore::complete_idempotent`] or [`ResultStore::release_idempotent`].
+    Claimed,
+    Replay(IdempotentResponse),
+    /// A request with this key is still being processed.
+    InProgress,
+    /// The key was used with a different request body.
+    Mismatch,
+}
+
+/// In-memory map from result ID to the parsed records behind it. Results
+/// expire with their download after [`DOWNLOAD_TTL`], and at most
+/// [`MAX_STORED_RESULTS`] are kept.
+#[derive(Clone, Default)]
+pub struct ResultStore {
+    results: Arc<Mutex<HashMap<String, ResultEntry>>>,
+    next_order: Arc<AtomicU64>,
+    idempotency: Arc<Mutex<HashMap<String, IdempotencyEntry>>>,
+}
+
+impl ResultStore {
+    /// Store `result` under `id`. Replacing a live result, e.g. after a
+    /// record edit, keeps its expiry.
+    pub async fn insert(&self, id: String, result: StoredResult) {
+        let mut results = self.results.lock().await;
+        results.retain(|_, entry| entry.stored_at.elapsed() <= DOWNLOAD_TTL);
+        if let Some(entry) = results.get_mut(&id) {
+            entry.result = result;
+            return;
+        }
+        while results.len() >= MAX_STORED_RESULTS {
+            let Some(oldest) = results
+                .iter()
+                .min_by_key(|(_, entry)| (entry.stored_at, entry.order))
+                .map(|(id, _)| id.clone())
+            else {
+                break;
+            };
+            results.remove(&oldest);
+        }
+        results.insert(
+            id,
+            ResultEntry {
+                result,
+                stored_at: Instant::now(),
+                order: self.next_order.fetch_add(1, Ordering::Relaxed),
+                edit_lock: Arc::default(),
+            },
+        );
+    }
+
+    pub async fn get(&self, id: &str) -> Option<StoredResult> {
+        self.results
+            .lock()
+            .await
+            .get(id)
+            .filter(|entry| entry.stored_at.elapsed() <= DOWNLOAD_TTL)
+            .map(|entry| entry.result.clone())
+    }
+
+    /// Wait for exclusive use of the result under `id`, so an edit reads and
+    /// writes it back without another edit in between. `None` when the
+    /// result is gone.
+    pub async fn lock_for_edit(&self, id: &str) -> Option<OwnedMutexGuard<()>> {
+        let lock = self
+            .results
+            .lock()
+            .await
+            .get(id)
+            .filter(|entry| entry.stored_at.elapsed() <= DOWNLOAD_TTL)
+            .map(|entry| entry.edit_lock.clone())?;
+        Some(lock.lock_owned().await)
+    }
+
+    pub async fn remove(&self, ids: &[String]) {
+        let mut results = self.results.lock().await;
+        for id in ids {
+            results.remove(id);
+        }
+    }
+
+    /// Claim `key` for a request whose body hashes to `fingerprint`, or find
+    /// what an earlier request with the same key produced.
+    pub async fn claim_idempotent(&self, key: &str, fingerprint: [u8; 32]) -> IdempotencyClaim {
+        let mut entries = self.idempotency.lock().await;
+        entries.retain(|_, entry| entry.created_at.elapsed() <= IDEMPOTENCY_KEY_TTL);
+        match entries.get(key) {
+            Some(entry) if entry.fingerprint != fingerprint => IdempotencyClaim::Mismatch,
+            Some(IdempotencyEntry {
+                state: IdempotencyState::InFlight,
+                ..
+            }) => IdempotencyClaim::InProgress,
+            Some(IdempotencyEntry {
+                state: IdempotencyState::Done(response),
+                ..
+            }) => IdempotencyClaim::Replay(response.clone()),
+            None => {
+                entries.insert(
+                    key.to_string(),
+                    IdempotencyEntry {
+                        fingerprint,
+                        state: IdempotencyState::InFlight,
+                        created_at: Instant::now(),
+                    },
+                );
+                IdempotencyClaim::Claimed
+            }
+        }
+    }
+
+    /// Remember the response to a claimed key.
+    pub async fn complete_idempotent(&self, key: &str, response: IdempotentResponse) {
+        if let Some(entry) = self.idempotency.lock().await.get_mut(key) {
+            entry.state = IdempotencyState::Done(response);
+        }
+    }
+
+    /// Forget every key claimed within `scope`, such as an account whose data
+    /// is deleted.
+    pub async fn remove_idempotency_scope(&self, scope: &str) {
+        let prefix = format!("{scope}\n");
+        self.idempotency
+            .lock()
+            .await
+            .retain(|key, _| !key.starts_with(&prefix));
+    }
+
+    /// Forget a claimed key that has no response yet, so a retry is processed
+    /// again.
+    pub async fn release_idempotent(&self, key: &str) {
+        let mut entries = self.idempotency.lock().await;
+        if matches!(
+            entries.get(key),
+            Some(IdempotencyEntry {
+                state: IdempotencyState::InFlight,
+                ..
+            })
+        ) {
+            entries.remove(key);
+        }
+    }
+}
+
+/// Releases a claimed `Idempotency-Key` when dropped before
+/// [`IdempotencyGuard::complete`], such as when the client disconnects and
+/// the request handler is cancelled.
+pub struct IdempotencyGuard {
+    store: ResultStore,
+    key: Option<String>,
+}
+
+impl IdempotencyGuard {
+    pub fn new(store: ResultStore, key: String) -> Self {
+        Self {
+            store,
+            key: Some(key),
+        }
+    }
+
+    /// Remember `response` for the key.
+    pub async fn complete(mut self, response: IdempotentResponse) {
+        if let Some(key) = self.key.take() {
+            self.store.complete_idempotent(&key, response).await;
+        }
+    }
+
+    /// Forget the key now, so a retry is processed again.
+    pub async fn release(mut self) {
+        if let Some(key) = self.key.take() {
+            self.store.release_idempotent(&key).await;
+        }
+    }
+}
+
+impl Drop for IdempotencyGuard {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let store = self.store.clone();
+            runtime.spawn(async move { store.release_idempotent(&key).await });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_oldest_results_make_room() {
+        let store = ResultStore::default();
+        let result = StoredResult {
+            records: Arc::new(Vec::new()),
+            options: ProcessingOptions::default(),
+            report: Arc::default(),
+        };
+        for index in 0..=MAX_STORED_RESULTS {
+            store.insert(index.to_string(), result.clone()).await;
+        }
+
+        assert_eq!(store.results.lock().await.len(), MAX_STORED_RESULTS);
+        assert!(store.get("0").await.is_none());
+        assert!(store.get(&MAX_STORED_RESULTS.to_string()).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn edits_of_one_result_wait_for_each_other() {
+        let store = ResultStore::default();
+        let result = StoredResult {
+            records: Arc::new(Vec::new()),
+            options: ProcessingOptions::default(),
+            report: Arc::default(),
+        };
+        store.insert("edited".to_string(), result.clone()).await;
+        store.insert("other".to_string(), result).await;
+
+        let first = store.lock_for_edit("edited").await.unwrap();
+        let second = tokio::time::timeout(Duration::from_millis(20), store.lock_for_edit("edited"));
+        assert!(second.await.is_err());
+        assert!(store.lock_for_edit("other").await.is_some());
+
+        drop(first);
+        assert!(store.lock_for_edit("edited").await.is_some());
+        assert!(store.lock_for_edit("missing").await.is_none());
+    }
+
+    #[test]
+    fn processed_records_are_preprocessed_once() {
+        let bytes = std::fs::read("test/fixtures/activity.fit").unwrap();
+        let result = StoredResult {
+            records: Arc::new(crate::processing::parse_fit(&bytes).unwrap()),
+            options: ProcessingOptions::default(),
+            report: Arc::default(),
+        };
+
+        let first = result.processed_records().unwrap();
+        let again = result.clone().processed_records().unwrap();
+
+        assert!(!first.is_empty());
+        assert!(Arc::ptr_eq(&first, &again));
+    }
+
+    #[tokio::test]
+    async fn dropped_claims_are_released() {
+        let store = ResultStore::default();
+        assert!(matches!(
+            store.claim_idempotent("abandoned", [1; 32]).await,
+            IdempotencyClaim::Claimed
+        ));
+
+        drop(IdempotencyGuard::new(
+            store.clone(),
+            "abandoned".to_string(),
+        ));
+        tokio::task::yield_now().await;
+
+        assert!(matches!(
+            store.claim_idempotent("abandoned", [1; 32]).await,
+            IdempotencyClaim::Claimed
+        ));
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_replay_matching_requests_only() {
+        let store = ResultStore::default();
+        let key = "retry-1";
+
+        assert!(matches!(
+            store.claim_idempotent(key, [1; 32]).await,
+            IdempotencyClaim::Claimed
+        ));
+        assert!(matches!(
+            store.claim_idempotent(key, [1; 32]).await,
+            IdempotencyClaim::InProgress
+        ));
+        store
+            .complete_idempotent(
+                key,
+                IdempotentResponse {
+                    status: StatusCode::OK,
+                    content_type: None,
+                    body: Bytes::from_static(b"{}"),
+                },
+            )
+            .await;
+
+        assert!(matches!(
+            store.claim_idempotent(key, [1; 32]).await,
+            IdempotencyClaim::Replay(response) if response.body == "{}"
+        ));
+        assert!(matches!(
+            store.claim_idempotent(key, [2; 32]).await,
+            IdempotencyClaim::Mismatch
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+//! GPX and GeoJSON copies of a processed activity's route, for importing
+//! into mapping tools or embedding in web pages.
+
+use crate::processing::track::TrackPoint;
+use crate::templates::escape_html;
+use chrono::{DateTime, SecondsFormat};
+use serde_json::{Value, json};
+
+/// A GPX 1.1 document with `track` as a single track segment.
+pub fn to_gpx(track: &[TrackPoint], name: &str) -> String {
+    let mut gpx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"RustyFit\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    );
+    gpx.push_str(&format!(
+        "  <trk>\n    <name>{}</name>\n    <trkseg>\n",
+        escape_html(name)
+    ));
+    for point in track {
+        gpx.push_str(&format!(
+            "      <trkpt lat=\"{:.7}\" lon=\"{:.7}\">",
+            point.lat, point.lon
+        ));
+        if let Some(elevation) = point.elevation {
+            gpx.push_str(&format!("<ele>{elevation:.1}</ele>"));
+        }
+        if let Some(time) = point
+            .timestamp
+            .and_then(|ts| DateTime::from_timestamp(ts.floor() as i64, 0))
+        {
+            gpx.push_str(&format!(
+                "<time>{}</time>",
+                time.to_rfc3339_opts(SecondsFormat::Secs, true)
+            ));
+        }
+        gpx.push_str("</trkpt>\n");
+    }
+    gpx.push_str("    </trkseg>\n  </trk>\n</gpx>\n");
+    gpx
+}
+
+/// A GeoJSON `Feature` with `track` as a `LineString`; positions carry the
+/// elevation as a third coordinate when every point has one.
+pub fn to_geojson(track: &[TrackPoint], name: &str) -> Value {
+    let with_elevation = track.iter().all(|point| point.elevation.is_some());
+    let coordinates: Vec<Vec<f64>> = track
+        .iter()
+        .map(|point| {
+            let mut position = vec![round7(point.lon), round7(point.lat)];
+            if let (true, Some(elevation)) = (with_elevation, point.elevation) {
+                position.push((elevation * 10.0).round() / 10.0);
+            }
+            position
+        })
+        .collect();
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": coordinates,
+        },
+        "properties": {
+            "name": name,
+            "points": track.len(),
+        },
+    })
+}
+
+/// Seven decimal places, about a centimetre, which keeps exports compact.
+fn round7(degrees: f64) -> f64 {
+    (degrees * 1e7).round() / 1e7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_export_as_gpx_and_geojson() {
+        let track = [
+            TrackPoint {
+                lat: 47.376_912_345,
+                lon: 8.541_7,
+                elevation: Some(408.04),
+                timestamp: Some(1_714_557_600.0),
+            },
+            TrackPoint {
+                lat: 47.377,
+                lon: 8.542,
+                elevation: None,
+                timestamp: None,
+            },
+        ];
+
+        let gpx = to_gpx(&track, "Lunch <ride>");
+        assert!(gpx.contains("<name>Lunch &lt;ride&gt;</name>"));
+        assert!(gpx.contains(
+            "<trkpt lat=\"47.3769123\" lon=\"8.5417000\"><ele>408.0</ele>\
+             <time>2024-05-01T10:00:00Z</time></trkpt>"
+        ));
+        assert!(gpx.contains("<trkpt lat=\"47.3770000\" lon=\"8.5420000\"></trkpt>"));
+
+        let geojson = to_geojson(&track, "Lunch");
+        assert_eq!(
+            geojson["geometry"]["coordinates"][0],
+            json!([8.5417, 47.3769123])
+        );
+        assert_eq!(geojson["properties"]["points"], 2);
+    }
+}
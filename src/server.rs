@@ -0,0 +1,3790 @@
+use crate::accounts::{Account, AccountStore, QuotaExceeded};
+use crate::admin::{AdminOverview, AdminToken, ErrorLog, JobTracker};
+use crate::assets::{
+    STATIC_PREFIX, fingerprint_static_dir, is_current_fingerprint, strip_fingerprint,
+};
+use crate::cache::{CachedUpload, UploadCache, content_hash};
+use crate::compression::{DownloadCompression, compressed_body};
+use crate::config::ServerConfig;
+use crate::csrf::{CsrfToken, issue_csrf_cookie, require_csrf_token};
+use crate::downloads::{DownloadStore, RangeRequest, parse_range};
+use crate::elevation::ElevationService;
+use crate::events::{JobCompleted, JobEvents, JobSource};
+use crate::export::account_bundle;
+use crate::fetch::{FetchError, fetch_fit};
+use crate::heatmap::HeatmapLayers;
+use crate::history::{CurveHistory, GearUsage, HistoryStore};
+use crate::jobs::{CancelOutcome, JobHandle, JobRegistry, JobStatus};
+use crate::locale::NumberLocale;
+use crate::negotiate::{ResponseFormat, accepts_encoding, negotiate};
+use crate::pdf::workout_report;
+use crate::processing::aggregate::{AggregateSummary, ReportPeriod, aggregate_summaries};
+use crate::processing::annotations::Annotation;
+use crate::processing::anonymize::record_position;
+use crate::processing::build::{ManualActivity, SourceFormat, build_fit, build_manual_fit};
+use crate::processing::diff::{FitDiff, diff_fit_bytes};
+use crate::processing::expressions::{DerivedFieldTable, processed_derived_fields};
+use crate::processing::metrics::processed_w_prime_balance;
+use crate::processing::plugins::{plugin_names, resolve_plugins};
+use crate::processing::preview::{MAX_PREVIEW_WINDOW, preview_smoothed_speed};
+use crate::processing::profile::{MessageInfo, message_catalog};
+use crate::processing::progress::{NoObserver, ProcessingObserver};
+use crate::processing::query::{RecordQuery, query_records, raw_message};
+use crate::processing::report::build_training_report;
+use crate::processing::sample::{SampleOptions, extract_sample_bytes};
+use crate::processing::spikes::SpeedSpike;
+use crate::processing::structure::{MessageFields, present_fields};
+use crate::processing::track::{TrackPoint, simplify, track_points};
+use crate::processing::transforms::edit_records::check_edits;
+use crate::processing::wind::adjust_for_wind;
+use crate::processing::{
+    ExceededLimit, FitProcessError, HashableF64, ProcessedFit, ProcessingLimits, ProcessingOptions,
+    ProcessingWarning, RecordEdit, SmoothingResiduals, StageTimings, WarningCode, Weather,
+    WorkoutSummary, parse_fit_with_limits, parse_fit_with_limits_observed, process_parsed_fit,
+    process_parsed_fit_with_source,
+};
+use crate::results::{
+    IdempotencyClaim, IdempotencyGuard, IdempotentResponse, ResultStore, StoredReport, StoredResult,
+};
+use crate::route_export::{to_geojson, to_gpx};
+use crate::signing::{SignatureError, UrlSigner};
+use crate::spool::{MAX_UPLOAD_BYTES, SPOOL_THRESHOLD_BYTES, SpoolError, UploadBytes, spool};
+use crate::stats::FileStats;
+use crate::telemetry::UsageCounters;
+use crate::templates::{
+    render_account_usage, render_admin_dashboard, render_aggregate_dashboard, render_batch_results,
+    render_create_form, render_curves_page, render_diff_form, render_diff_result,
+    render_landing_page, render_processed_records, render_training_report,
+};
+use crate::weather::{WeatherError, WeatherService};
+use axum::{
+    Router,
+    body::Body,
+    extract::{
+        ConnectInfo, DefaultBodyLimit, Extension, FromRequest, Multipart, Path, Query, Request,
+        State,
+    },
+    http::{HeaderMap, HeaderValue, StatusCode, Uri, header},
+    middleware::{self, Next},
+    response::{
+        Html, IntoResponse, Redirect, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{delete, get, patch, post},
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower::ServiceBuilder;
+use tower_http::services::ServeDir;
+use tower_http::set_header::SetResponseHeaderLayer;
+
+#[derive(Clone, Default)]
+struct AppState {
+    downloads: DownloadStore,
+    history: HistoryStore,
+    /// Route heatmaps of the history per account, rebuilt after uploads with
+    /// a GPS track.
+    heatmap: HeatmapLayers,
+    results: ResultStore,
+    limits: ProcessingLimits,
+    cache: UploadCache,
+    signer: UrlSigner,
+    jobs: JobTracker,
+    /// Uploads processed in the background, by job ID.
+    background_jobs: JobRegistry,
+    errors: ErrorLog,
+    /// `/admin` is only served when a token is configured.
+    admin_token: Option<AdminToken>,
+    /// Empty unless accounts are configured, in which case uploads need one.
+    accounts: AccountStore,
+    events: JobEvents,
+    /// Altitude lookups for `replace_elevation`.
+    elevation: ElevationService,
+    /// Start weather for `enrich_weather`.
+    weather: WeatherService,
+    /// Anonymous usage totals, when the operator enabled them.
+    usage: Option<UsageCounters>,
+    config: Arc<ServerConfig>,
+}
+
+impl AppState {
+    async fn insert_download(&self, bytes: Vec<u8>) -> String {
+        self.downloads.insert(bytes, None).await
+    }
+}
+
+pub fn build_app() -> Router {
+    build_app_with_config(ServerConfig::from_env())
+}
+
+pub fn build_app_with_config(config: ServerConfig) -> Router {
+    fingerprint_static_dir(&config.static_dir);
+    router_with_state(AppState {
+        limits: ProcessingLimits::from_env(),
+        cache: UploadCache::from_env(),
+        history: HistoryStore::from_env(),
+        signer: UrlSigner::from_env(),
+        admin_token: AdminToken::from_env(),
+        events: JobEvents::connect(config.job_events.as_ref()),
+        elevation: ElevationService::connect(config.elevation.as_ref()),
+        weather: WeatherService::connect(config.weather.as_ref()),
+        accounts: config.accounts.clone(),
+        usage: config.telemetry.clone(),
+        config: Arc::new(config),
+        ..AppState::default()
+    })
+}
+
+fn router_with_state(state: AppState) -> Router {
+    let static_files = ServiceBuilder::new()
+        .layer(middleware::from_fn(fingerprinted_assets))
+        .service(ServeDir::new(&state.config.static_dir));
+
+    // With accounts configured, these routes need one; processing also counts
+    // against its quota, and stored activities are only shown to their account.
+    let metered = || middleware::from_fn_with_state(state.clone(), require_account);
+
+    // Browser forms that change server state must echo the CSRF cookie.
+    let forms = Router::new()
+        .route(
+            "/upload",
+            post(handle_upload)
+                .layer(metered())
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+        )
+        .route("/diff", post(diff_page).layer(metered()))
+        .route("/create", post(create_activity))
+        .route(
+            "/api/v1/results/:id/preview",
+            post(preview_smoothing).layer(metered()),
+        )
+        .route("/admin/purge", post(admin_purge))
+        .route_layer(middleware::from_fn(require_csrf_token));
+
+    let mut router = Router::new()
+        .route("/", get(landing_page))
+        .route("/download/:id", get(download_processed))
+        .route("/download/:id/processed", get(download_processed))
+        .route("/download/:id/original", get(download_original))
+        .route(
+            "/download/:id/w-prime-balance.csv",
+            get(download_w_prime_balance),
+        )
+        .route(
+            "/download/:id/derived-fields.csv",
+            get(download_derived_fields_csv),
+        )
+        .route(
+            "/download/:id/derived-fields.json",
+            get(download_derived_fields_json),
+        )
+        .route("/export/pdf/:id", get(export_pdf))
+        .route("/export/gpx/:id", get(export_gpx))
+        .route("/export/geojson/:id", get(export_geojson))
+        .route("/reports/:period", get(training_report).layer(metered()))
+        .route("/curves", get(curves_page).layer(metered()))
+        .route("/api/v1/curves", get(curves_api).layer(metered()))
+        .route("/heatmap", get(heatmap_layer))
+        .route("/api/v1/plugins", get(plugins_api))
+        .route("/api/v1/profile/messages", get(profile_messages_api))
+        .route("/api/v1/gear", get(gear_api).layer(metered()))
+        .route("/api/v1/results/:id/records", get(search_records))
+        .route("/api/v1/results/:id/records/:index", patch(edit_record))
+        .route("/api/v1/results/:id/records/:index/raw", get(raw_record))
+        .route("/diff", get(diff_form))
+        .route("/create", get(create_form))
+        .route("/api/v1/diff", post(diff_api).layer(metered()))
+        .route("/api/v1/fields", post(fields_api).layer(metered()))
+        .route("/api/v1/process", post(process_api).layer(metered()))
+        .route(
+            "/api/v1/jobs",
+            post(create_job)
+                .layer(metered())
+                .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES)),
+        )
+        .route("/api/v1/jobs/:id", get(job_status).delete(cancel_job))
+        .route("/api/v1/jobs/:id/events", get(job_events))
+        .route("/api/v1/process-url", post(process_url).layer(metered()))
+        .route("/account", get(account_page).layer(metered()))
+        .route(
+            "/api/v1/account/usage",
+            get(account_usage_api).layer(metered()),
+        )
+        .route(
+            "/api/v1/account/export",
+            get(export_account).layer(metered()),
+        )
+        .route(
+            "/api/v1/account/data",
+            delete(delete_account_data).layer(metered()),
+        )
+        .route("/api/v1/sample", post(anonymized_sample).layer(metered()))
+        .route("/api/v1/cache/metrics", get(cache_metrics))
+        .route("/pkg/:file", get(wasm_asset))
+        .route("/admin", get(admin_dashboard))
+        .merge(forms)
+        .nest_service(STATIC_PREFIX, static_files)
+        .layer(middleware::from_fn(issue_csrf_cookie));
+    // Handlers that set one of these headers themselves keep their own value.
+    for (name, value) in state.config.security_headers.headers() {
+        router = router.layer(SetResponseHeaderLayer::if_not_present(name, value));
+    }
+    router.with_state(state)
+}
+
+async fn landing_page() -> Html<String> {
+    Html(render_landing_page())
+}
+
+/// One file's outcome in an upload answered as JSON.
+#[derive(Serialize)]
+struct FileResult {
+    file_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<WorkoutSummary>,
+    /// Stops, laps, and intervals found in the activity.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+    /// Speed spikes in the upload and their likely cause.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    speed_spikes: Vec<SpeedSpike>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<ProcessingWarning>,
+    /// How far speed smoothing moved the series, when it ran.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    smoothing: Option<SmoothingResiduals>,
+    /// Rendered results fragment, as returned for single-file HTML uploads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Multi-file upload response: every file's outcome plus batch totals over
+/// the files that processed successfully.
+#[derive(Serialize)]
+struct UploadResults {
+    aggregate: AggregateSummary,
+    aggregate_html: String,
+    files: Vec<FileResult>,
+}
+
+/// The request's `Accept-Language` header, when it is readable.
+fn accept_language(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Files and options read from an upload form.
+struct UploadForm {
+    /// Name and contents of each file; large ones are spooled to disk.
+    files: Vec<(String, UploadBytes)>,
+    /// Options for the file at the same index, with the server's limits.
+    file_options: Vec<ProcessingOptions>,
+    bypass_cache: bool,
+    /// Separators for numbers in rendered results.
+    locale: NumberLocale,
+}
+
+/// Read an upload form. Shared options come from the individual form
+/// fields; a `file_options` JSON object sent for the n-th file replaces them
+/// for that file, and an empty one keeps the shared options. A
+/// `number_locale` field picks the number format, falling back to the
+/// request's `Accept-Language`.
+async fn read_upload_form(
+    state: &AppState,
+    headers: &HeaderMap,
+    mut multipart: Multipart,
+) -> Result<UploadForm, Response> {
+    let mut uploaded: Vec<(String, UploadBytes)> = Vec::new();
+    let mut file_options: Vec<Option<ProcessingOptions>> = Vec::new();
+    let mut bypass_cache = false;
+    let mut number_locale: Option<String> = None;
+    let mut options = ProcessingOptions {
+        limits: state.limits.clone(),
+        ..ProcessingOptions::default()
+    };
+
+    while let Ok(Some(field)) = multipart.next_field().await {
+        match field.name() {
+            Some("file") => {
+                let file_name = field
+                    .file_name()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("file-{}", uploaded.len() + 1));
+                match spool(field, SPOOL_THRESHOLD_BYTES).await {
+                    Ok(bytes) => {
+                        uploaded.push((file_name, bytes));
+                    }
+                    Err(err @ SpoolError::Read(_)) => {
+                        return Err((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+                    }
+                    Err(err) => {
+                        return Err(
+                            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+                        );
+                    }
+                }
+            }
+            Some("bypass_cache") => {
+                if let Ok(value) = field.text().await {
+                    bypass_cache = value == "true" || value == "on";
+                }
+            }
+            Some("number_locale") => {
+                number_locale = field.text().await.ok();
+            }
+            Some("remove_speed_fields") => {
+                if let Ok(value) = field.text().await {
+                    options.remove_speed_fields = value == "true" || value == "on";
+                }
+            }
+            Some("smooth_speed") => {
+                if let Ok(value) = field.text().await {
+                    options.smooth_speed = value == "true" || value == "on";
+                }
+            }
+            Some("flatten_indoor") => {
+                if let Ok(value) = field.text().await {
+                    options.flatten_indoor = value == "true" || value == "on";
+                }
+            }
+            Some("pace_from_elapsed_time") => {
+                if let Ok(value) = field.text().await {
+                    options.pace_from_elapsed_time = value == "true" || value == "on";
+                }
+            }
+            Some("strip_device_info") => {
+                if let Ok(value) = field.text().await {
+                    options.strip_device_info = value == "true" || value == "on";
+                }
+            }
+            Some("strip_ebike_data") => {
+                if let Ok(value) = field.text().await {
+                    options.strip_ebike_data = value == "true" || value == "on";
+                }
+            }
+            Some("fill_gaps") => {
+                if let Ok(value) = field.text().await {
+                    options.fill_gaps = value == "true" || value == "on";
+                }
+            }
+            Some("one_second_records") => {
+                if let Ok(value) = field.text().await {
+                    options.one_second_records = value == "true" || value == "on";
+                }
+            }
+            Some("resample_interval_seconds") => {
+                if let Ok(value) = field.text().await {
+                    options.resample_interval_seconds = value.trim().parse().ok();
+                }
+            }
+            Some("preview_records") => {
+                if let Ok(value) = field.text().await {
+                    options.preview_records = value.trim().parse().ok();
+                }
+            }
+            Some("critical_power_watts") => {
+                if let Ok(value) = field.text().await {
+                    options.critical_power_watts = value.trim().parse().ok();
+                }
+            }
+            Some("w_prime_joules") => {
+                if let Ok(value) = field.text().await {
+                    options.w_prime_joules = value.trim().parse().ok();
+                }
+            }
+            Some("timestamp_repair") => {
+                if let Ok(value) = field.text().await {
+                    options.timestamp_repair = value.parse().ok();
+                }
+            }
+            Some("anonymize") => {
+                if let Ok(value) = field.text().await {
+                    options.anonymize = value.parse().ok();
+                }
+            }
+            Some("derived_fields") => {
+                if let Ok(value) = field.text().await {
+                    options.derived_fields = (!value.trim().is_empty()).then_some(value);
+                }
+            }
+            Some("replace_values") => {
+                if let Ok(value) = field.text().await {
+                    options.replace_values = (!value.trim().is_empty()).then_some(value);
+                }
+            }
+            Some("correct_sets") => {
+                if let Ok(value) = field.text().await {
+                    options.correct_sets = (!value.trim().is_empty()).then_some(value);
+                }
+            }
+            Some("remove_fields") => {
+                if let Ok(value) = field.text().await {
+                    options.remove_fields = value
+                        .split([',', '\n'])
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+            }
+            Some("plugins") => {
+                if let Ok(value) = field.text().await {
+                    options.plugins = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+            }
+            Some("title") => {
+                if let Ok(value) = field.text().await {
+                    options.title = (!value.trim().is_empty()).then_some(value);
+                }
+            }
+            Some("preserve_original") => {
+                if let Ok(value) = field.text().await {
+                    options.preserve_original = value == "true" || value == "on";
+                }
+            }
+            Some("correct_open_water") => {
+                if let Ok(value) = field.text().await {
+                    options.correct_open_water = value == "true" || value == "on";
+                }
+            }
+            Some("stitch_distance_resets") => {
+                if let Ok(value) = field.text().await {
+                    options.stitch_distance_resets = value == "true" || value == "on";
+                }
+            }
+            Some("skip_vendor_fixes") => {
+                if let Ok(value) = field.text().await {
+                    options.skip_vendor_fixes = value == "true" || value == "on";
+                }
+            }
+            Some("correct_altitude_drift") => {
+                if let Ok(value) = field.text().await {
+                    options.correct_altitude_drift = value == "true" || value == "on";
+                }
+            }
+            Some("replace_elevation") => {
+                if let Ok(value) = field.text().await {
+                    options.replace_elevation = value == "true" || value == "on";
+                }
+            }
+            Some("enrich_weather") => {
+                if let Ok(value) = field.text().await {
+                    options.enrich_weather = value == "true" || value == "on";
+                }
+            }
+            Some("classify_surface") => {
+                if let Ok(value) = field.text().await {
+                    options.classify_surface = value == "true" || value == "on";
+                }
+            }
+            Some("gear") => {
+                if let Ok(value) = field.text().await {
+                    options.gear = (!value.trim().is_empty()).then_some(value);
+                }
+            }
+            Some("notes") => {
+                if let Ok(value) = field.text().await {
+                    options.notes = (!value.trim().is_empty()).then_some(value);
+                }
+            }
+            Some("inject_derived_fields") => {
+                if let Ok(value) = field.text().await {
+                    options.inject_derived_fields = value == "true" || value == "on";
+                }
+            }
+            Some("distance_scale") => {
+                if let Ok(value) = field.text().await {
+                    options.distance_scale = value.trim().parse().ok().map(HashableF64);
+                }
+            }
+            Some("target_distance_meters") => {
+                if let Ok(value) = field.text().await {
+                    options.target_distance_meters = value.trim().parse().ok();
+                }
+            }
+            Some("power_scale_percent") => {
+                if let Ok(value) = field.text().await {
+                    options.power_scale_percent = value.trim().parse().ok().map(HashableF64);
+                }
+            }
+            Some("wheel_circumference_mm") => {
+                if let Ok(value) = field.text().await {
+                    options.wheel_circumference_mm = value.trim().parse().ok();
+                }
+            }
+            Some("crank_length_mm") => {
+                if let Ok(value) = field.text().await {
+                    options.crank_length_mm = value.trim().parse().ok().map(HashableF64);
+                }
+            }
+            Some("utc_offset_minutes") => {
+                if let Ok(value) = field.text().await {
+                    options.utc_offset_minutes = value.trim().parse().ok();
+                }
+            }
+            Some("file_options") => {
+                let value = field.text().await.unwrap_or_default();
+                if value.trim().is_empty() {
+                    file_options.push(None);
+                    continue;
+                }
+                match ProcessingOptions::from_json(&value) {
+                    Ok(parsed) => file_options.push(Some(parsed)),
+                    Err(err) => {
+                        return Err((
+                            StatusCode::BAD_REQUEST,
+                            format!("Invalid file_options: {err}"),
+                        )
+                            .into_response());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if uploaded.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No file provided").into_response());
+    }
+    // GPX and CSV tracks are turned into FIT activities up front, so caching
+    // and processing only ever see FIT bytes.
+    for (file_name, bytes) in &mut uploaded {
+        let Some(format) = SourceFormat::from_file_name(file_name) else {
+            continue;
+        };
+        match build_fit(bytes, format, Utc::now().timestamp() as f64) {
+            Ok(fit) => *bytes = fit,
+            Err(err) => {
+                return Err(
+                    (StatusCode::BAD_REQUEST, format!("{file_name}: {err}")).into_response()
+                );
+            }
+        }
+    }
+    for candidate in std::iter::once(&options).chain(file_options.iter().flatten()) {
+        if let Err(err) = candidate.validate() {
+            return Err((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+        }
+        if let Err(err) = resolve_plugins(&candidate.plugins) {
+            return Err((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+        }
+    }
+
+    let file_options = (0..uploaded.len())
+        .map(|index| match file_options.get(index).cloned().flatten() {
+            Some(custom) => ProcessingOptions {
+                limits: state.limits.clone(),
+                ..custom
+            },
+            None => options.clone(),
+        })
+        .collect();
+    Ok(UploadForm {
+        files: uploaded,
+        file_options,
+        bypass_cache,
+        locale: NumberLocale::negotiate(number_locale.as_deref(), accept_language(headers)),
+    })
+}
+
+/// Formats `/upload` answers in, negotiated on `Accept`.
+const UPLOAD_FORMATS: &[ResponseFormat] = &[
+    ResponseFormat::Html,
+    ResponseFormat::Json,
+    ResponseFormat::Fit,
+];
+
+/// Process uploaded files read by [`read_upload_form`].
+///
+/// With `Accept: application/json` every file is reported in an
+/// [`UploadResults`], failures included. `Accept: application/vnd.ant.fit`
+/// answers a single file with the processed file itself, its download link
+/// in `Content-Location`. Otherwise the first failure aborts the upload and
+/// results are rendered as HTML.
+async fn handle_upload(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    account: Option<Extension<Account>>,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    let form = match read_upload_form(&state, &headers, multipart).await {
+        Ok(form) => form,
+        Err(response) => return response,
+    };
+    let format = negotiate(&headers, UPLOAD_FORMATS);
+    if format == ResponseFormat::Fit && form.files.len() > 1 {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            "A FIT response holds one file; upload files one at a time or accept JSON",
+        )
+            .into_response();
+    }
+    let account = account.map(|Extension(account)| account);
+    if let Err(response) = reserve_quota(&state, account.as_ref(), form.files.len()).await {
+        return response;
+    }
+
+    let _job = state.jobs.start();
+    if format == ResponseFormat::Json {
+        let results = process_upload_form(&state, form, account.as_ref(), None).await;
+        return axum::Json(results).into_response();
+    }
+
+    let file_count = form.files.len();
+    let batch_upload = file_count > 1;
+    let locale = form.locale;
+    let mut results = Vec::with_capacity(file_count);
+    for ((file_name, file_bytes), options) in form.files.into_iter().zip(&form.file_options) {
+        let upload = UploadContext {
+            source: JobSource::Upload,
+            bypass_cache: form.bypass_cache,
+            account: account.as_ref(),
+            observer: &NoObserver,
+        };
+        match process_upload(&state, &file_name, &file_bytes, options, &upload).await {
+            Ok((processed, download_url)) => results.push((file_name, processed, download_url)),
+            Err(err) => {
+                // Neither this file nor the ones after it were processed.
+                refund_quota(&state, account.as_ref(), file_count - results.len()).await;
+                return render_upload_error(err, batch_upload.then_some(file_name.as_str()));
+            }
+        }
+    }
+
+    if format == ResponseFormat::Fit {
+        let (file_name, processed, download_url) = results.swap_remove(0);
+        let stem = std::path::Path::new(&file_name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| stem.chars().all(|c| c.is_ascii_graphic() && c != '"'))
+            .unwrap_or("processed");
+        return (
+            [
+                (header::CONTENT_TYPE, format.media_type().to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{stem}.fit\""),
+                ),
+                (header::CONTENT_LOCATION, download_url),
+            ],
+            processed.processed_bytes,
+        )
+            .into_response();
+    }
+
+    let aggregate = aggregate_summaries(results.iter().map(|(_, processed, _)| &processed.summary));
+    if batch_upload {
+        Html(render_batch_results(&aggregate, &results, locale)).into_response()
+    } else {
+        let (_, processed, download_url) = &results[0];
+        Html(render_processed_records(processed, download_url, locale)).into_response()
+    }
+}
+
+/// Process every file of `form`, reporting each outcome, failures included.
+/// Progress is published to `job` when the upload runs in the background, and
+/// the remaining files are skipped once that job is cancelled.
+async fn process_upload_form(
+    state: &AppState,
+    form: UploadForm,
+    account: Option<&Account>,
+    job: Option<&JobHandle>,
+) -> UploadResults {
+    let file_count = form.files.len();
+    let mut summaries = Vec::with_capacity(file_count);
+    let mut files = Vec::with_capacity(file_count);
+    let uploads = form.files.into_iter().zip(&form.file_options);
+    for (index, ((file_name, file_bytes), options)) in uploads.enumerate() {
+        let progress = job.map(|job| job.file_progress(index, file_count, &file_name));
+        let observer: &dyn ProcessingObserver = match &progress {
+            Some(progress) => {
+                progress.start();
+                progress
+            }
+            None => &NoObserver,
+        };
+        let upload = UploadContext {
+            source: JobSource::Upload,
+            bypass_cache: form.bypass_cache,
+            account,
+            observer,
+        };
+        let outcome = process_upload(state, &file_name, &file_bytes, options, &upload).await;
+        if job.is_some_and(JobHandle::is_cancelled) {
+            break;
+        }
+        files.push(match outcome {
+            Ok((processed, download_url)) => {
+                summaries.push(processed.summary.clone());
+                FileResult {
+                    file_name,
+                    summary: Some(processed.summary.clone()),
+                    annotations: processed.annotations.regions.clone(),
+                    speed_spikes: processed.speed_spikes.clone(),
+                    warnings: processed.warnings.clone(),
+                    smoothing: processed.smoothing.clone(),
+                    html: Some(render_processed_records(
+                        &processed,
+                        &download_url,
+                        form.locale,
+                    )),
+                    download_url: Some(download_url),
+                    error: None,
+                }
+            }
+            Err(err) => FileResult {
+                file_name,
+                download_url: None,
+                summary: None,
+                annotations: Vec::new(),
+                speed_spikes: Vec::new(),
+                warnings: Vec::new(),
+                smoothing: None,
+                html: None,
+                error: Some(err.to_string()),
+            },
+        });
+    }
+
+    // Failed files and those skipped after a cancellation do not count.
+    refund_quota(state, account, file_count - summaries.len()).await;
+    let aggregate = aggregate_summaries(&summaries);
+    UploadResults {
+        aggregate_html: render_aggregate_dashboard(&aggregate, form.locale),
+        aggregate,
+        files,
+    }
+}
+
+/// Where to follow a background job.
+#[derive(Serialize)]
+struct JobCreated {
+    id: String,
+    status_url: String,
+    events_url: String,
+}
+
+/// Read an upload like `/api/v1/process` and process it in the background,
+/// answering `202 Accepted` with the job's URLs straight away.
+async fn create_job(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    account: Option<Extension<Account>>,
+    multipart: Multipart,
+) -> Response {
+    let form = match read_upload_form(&state, &headers, multipart).await {
+        Ok(form) => form,
+        Err(response) => return response,
+    };
+    let account = account.map(|Extension(account)| account);
+    if let Err(response) = reserve_quota(&state, account.as_ref(), form.files.len()).await {
+        return response;
+    }
+
+    let owner = account.as_ref().map(|account| account.id.as_str());
+    let (id, handle) = state.background_jobs.create(owner);
+    let task_state = state.clone();
+    tokio::spawn(async move {
+        let _job = task_state.jobs.start();
+        let results = process_upload_form(&task_state, form, account.as_ref(), Some(&handle)).await;
+        if handle.is_cancelled() {
+            handle.set(JobStatus::Cancelled);
+            return;
+        }
+        handle.set(match serde_json::to_value(&results) {
+            Ok(result) => JobStatus::Completed { result },
+            Err(err) => JobStatus::Failed {
+                error: err.to_string(),
+            },
+        });
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        axum::Json(JobCreated {
+            status_url: format!("/api/v1/jobs/{id}"),
+            events_url: format!("/api/v1/jobs/{id}/events"),
+            id,
+        }),
+    )
+        .into_response()
+}
+
+/// Current status of a background job.
+async fn job_status(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    match state.background_jobs.status(&id) {
+        Some(status) => axum::Json(status).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Stop a background job. Files already processed stay downloadable, but the
+/// job reports no results; answers `409 Conflict` once the job has finished.
+async fn cancel_job(State(state): State<AppState>, Path(id): Path<String>) -> StatusCode {
+    match state.background_jobs.cancel(&id) {
+        CancelOutcome::Requested => StatusCode::ACCEPTED,
+        CancelOutcome::AlreadyFinished => StatusCode::CONFLICT,
+        CancelOutcome::NotFound => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Stream a job's status as server-sent events: the current status first,
+/// then every change, ending once the job completes, fails, or is cancelled.
+async fn job_events(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let Some(receiver) = state.background_jobs.subscribe(&id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let events = stream::unfold(
+        (receiver, true, false),
+        |(mut receiver, first, finished)| async move {
+            if finished || (!first && receiver.changed().await.is_err()) {
+                return None;
+            }
+            let status = receiver.borrow_and_update().clone();
+            let event = Event::default()
+                .json_data(&status)
+                .unwrap_or_else(|_| Event::default().comment("unserializable status"));
+            Some((
+                Ok::<_, Infallible>(event),
+                (receiver, false, status.is_finished()),
+            ))
+        },
+    );
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Header identifying retries of the same processing request.
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// Longest accepted `Idempotency-Key`.
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// Largest multipart body read by `/api/v1/process`, the same limit axum
+/// applies to `/upload`.
+const PROCESS_API_BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Run `process` once per `Idempotency-Key` within `scope`. Retries with the
+/// same key and body get the first response again, marked with
+/// `Idempotent-Replayed`; reusing a key for a different body is rejected.
+/// Without the header every request is processed. Server errors are not
+/// remembered, and neither are requests abandoned by the client, so they can
+/// be retried.
+async fn idempotent<Fut>(
+    state: &AppState,
+    scope: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+    process: impl FnOnce() -> Fut,
+) -> Response
+where
+    Fut: Future<Output = Response>,
+{
+    let Some(key) = headers
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+    else {
+        return process().await;
+    };
+    if key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Idempotency-Key must be at most {MAX_IDEMPOTENCY_KEY_LEN} characters"),
+        )
+            .into_response();
+    }
+
+    // Keys are only unique per client, so clients cannot replay each other's.
+    let key = format!("{scope}\n{key}");
+    let guard = match state
+        .results
+        .claim_idempotent(&key, content_hash(body))
+        .await
+    {
+        IdempotencyClaim::Claimed => IdempotencyGuard::new(state.results.clone(), key),
+        IdempotencyClaim::Replay(stored) => {
+            let mut response = (stored.status, stored.body).into_response();
+            if let Some(content_type) = stored.content_type {
+                response
+                    .headers_mut()
+                    .insert(header::CONTENT_TYPE, content_type);
+            }
+            response
+                .headers_mut()
+                .insert("idempotent-replayed", HeaderValue::from_static("true"));
+            return response;
+        }
+        IdempotencyClaim::InProgress => {
+            return (
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still being processed",
+            )
+                .into_response();
+        }
+        IdempotencyClaim::Mismatch => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key was already used with a different request",
+            )
+                .into_response();
+        }
+    };
+
+    let (parts, body) = process().await.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) if !parts.status.is_server_error() => bytes,
+        outcome => {
+            guard.release().await;
+            let body = outcome.map(Body::from).unwrap_or_else(|_| Body::empty());
+            return Response::from_parts(parts, body);
+        }
+    };
+    guard
+        .complete(IdempotentResponse {
+            status: parts.status,
+            content_type: parts.headers.get(header::CONTENT_TYPE).cloned(),
+            body: bytes.clone(),
+        })
+        .await;
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Whose `Idempotency-Key`s a request's key is kept apart from: its account,
+/// or else the address it came from.
+fn idempotency_scope(parts: &axum::http::request::Parts) -> String {
+    if let Some(account) = parts.extensions.get::<Account>() {
+        return account_scope(&account.id);
+    }
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or_else(
+            || "anonymous".to_string(),
+            |ConnectInfo(address)| format!("address:{}", address.ip()),
+        )
+}
+
+/// Idempotency scope of an account's requests.
+fn account_scope(id: &str) -> String {
+    format!("account:{id}")
+}
+
+/// `/upload` for API clients: the same multipart fields, always answered as
+/// JSON, without the CSRF token, and honouring `Idempotency-Key`.
+async fn process_api(State(state): State<AppState>, request: Request) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, PROCESS_API_BODY_LIMIT).await {
+        Ok(body) => body,
+        Err(_) => {
+            return (StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large").into_response();
+        }
+    };
+    parts
+        .headers
+        .insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+    let headers = parts.headers.clone();
+    let account = parts.extensions.get::<Account>().cloned().map(Extension);
+    let scope = idempotency_scope(&parts);
+
+    idempotent(&state, &scope, &headers, &body, || async {
+        let request = Request::from_parts(parts, Body::from(body.clone()));
+        match Multipart::from_request(request, &state).await {
+            Ok(multipart) => handle_upload(
+                State(state.clone()),
+                headers.clone(),
+                account.clone(),
+                multipart,
+            )
+            .await
+            .into_response(),
+            Err(rejection) => rejection.into_response(),
+        }
+    })
+    .await
+}
+
+/// Where a file being processed came from and who it is processed for.
+struct UploadContext<'a> {
+    source: JobSource,
+    bypass_cache: bool,
+    /// Owner of the stored download, when accounts are configured.
+    account: Option<&'a Account>,
+    observer: &'a dyn ProcessingObserver,
+}
+
+/// Why [`process_upload`] produced no download.
+#[derive(Debug)]
+enum UploadError {
+    Processing(FitProcessError),
+    /// The processed file would not fit in the account's storage quota.
+    Quota(QuotaExceeded),
+}
+
+impl From<FitProcessError> for UploadError {
+    fn from(error: FitProcessError) -> Self {
+        UploadError::Processing(error)
+    }
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::Processing(error) => error.fmt(f),
+            UploadError::Quota(exceeded) => exceeded.fmt(f),
+        }
+    }
+}
+
+fn render_upload_error(error: UploadError, file_name: Option<&str>) -> Response {
+    match error {
+        UploadError::Processing(error) => render_processing_error(error, file_name),
+        UploadError::Quota(exceeded) => quota_exceeded_response(&exceeded),
+    }
+}
+
+/// Options for processing an upload, with anything looked up for it first.
+struct LookedUp<'a> {
+    options: Cow<'a, ProcessingOptions>,
+    warning: Option<ProcessingWarning>,
+    /// The upload's records and how long decoding took, when the lookup
+    /// needed them, so processing does not decode the file again.
+    parsed: Option<(Vec<FitDataRecord>, Duration)>,
+}
+
+/// `options` with the elevation service's altitudes filled in when
+/// `replace_elevation` is set, or a warning saying why they are missing.
+/// Files that fail to parse are left for processing to report.
+async fn with_looked_up_altitudes<'a>(
+    state: &AppState,
+    file_bytes: &[u8],
+    options: &'a ProcessingOptions,
+    observer: &dyn ProcessingObserver,
+) -> LookedUp<'a> {
+    let unchanged = LookedUp {
+        options: Cow::Borrowed(options),
+        warning: None,
+        parsed: None,
+    };
+    if !options.replace_elevation {
+        return unchanged;
+    }
+    let started = Instant::now();
+    let Ok(records) = parse_fit_with_limits_observed(file_bytes, &options.limits, observer) else {
+        return unchanged;
+    };
+    let parse = started.elapsed();
+    match state.elevation.options_for(&records, options).await {
+        Ok(options) => LookedUp {
+            options: Cow::Owned(options),
+            warning: None,
+            parsed: Some((records, parse)),
+        },
+        Err(err) => {
+            tracing::warn!(error = %err, "elevation lookup failed");
+            let positions = records
+                .iter()
+                .filter(|record| record_position(record).is_some())
+                .count();
+            let warning = ProcessingWarning::new(
+                WarningCode::ElevationLookupFailed,
+                positions,
+                "altitude sample",
+                &format!("kept as recorded: {err}"),
+            );
+            LookedUp {
+                options: Cow::Borrowed(options),
+                warning: Some(warning),
+                parsed: Some((records, parse)),
+            }
+        }
+    }
+}
+
+/// Weather at the activity's first recorded position and start time; `None`
+/// when the file has neither.
+async fn start_weather(
+    state: &AppState,
+    records: &[FitDataRecord],
+    summary: &WorkoutSummary,
+) -> Result<Option<Weather>, WeatherError> {
+    let start = records.iter().find_map(record_position);
+    let (Some(position), Some(timestamp)) = (start, summary.start_timestamp) else {
+        return Ok(None);
+    };
+    state.weather.at(position, timestamp).await.map(Some)
+}
+
+/// Process one uploaded file, reusing a cached result for repeat uploads, and
+/// store it for download. Returns the result and its signed download URL.
+async fn process_upload(
+    state: &AppState,
+    file_name: &str,
+    file_bytes: &[u8],
+    options: &ProcessingOptions,
+    upload: &UploadContext<'_>,
+) -> Result<(ProcessedFit, String), UploadError> {
+    let UploadContext {
+        source,
+        bypass_cache,
+        account,
+        observer,
+    } = *upload;
+    // Looked up before the cache, as its key includes the altitudes.
+    let LookedUp {
+        options,
+        warning: elevation_warning,
+        parsed,
+    } = with_looked_up_altitudes(state, file_bytes, options, observer).await;
+    let options = options.as_ref();
+    let hash = content_hash(file_bytes);
+    let cached = if bypass_cache {
+        state.cache.record_bypass();
+        None
+    } else {
+        let owner = account.map(|account| account.id.as_str());
+        state.cache.get(hash, options, owner).await
+    };
+    let (records, mut processed, repeat_upload) = match cached {
+        Some(CachedUpload {
+            records,
+            mut processed,
+            ..
+        }) => {
+            processed.timings = StageTimings::default();
+            (records, processed, true)
+        }
+        None => {
+            let started = Instant::now();
+            let parsed = match parsed {
+                Some(parsed) => Ok(parsed),
+                None => parse_fit_with_limits_observed(file_bytes, &options.limits, observer)
+                    .map(|parsed| (parsed, started.elapsed())),
+            };
+            let outcome = parsed.and_then(|(parsed, parse)| {
+                let mut processed =
+                    process_parsed_fit_with_source(&parsed, file_bytes, options, observer)?;
+                processed.timings.parse = parse;
+                processed.structure.measure(file_bytes);
+                Ok((Arc::new(parsed), processed))
+            });
+            let (records, processed) = match outcome {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    // Abandoned uploads are not a problem with the file.
+                    if !matches!(err, FitProcessError::Cancelled) {
+                        state.errors.record(&err).await;
+                        if let Some(usage) = &state.usage {
+                            usage.record_error(&err).await;
+                        }
+                    }
+                    return Err(err.into());
+                }
+            };
+            state
+                .cache
+                .insert(
+                    hash,
+                    options,
+                    CachedUpload {
+                        records: records.clone(),
+                        processed: processed.clone(),
+                        owner: account.map(|account| account.id.clone()),
+                    },
+                )
+                .await;
+            (records, processed, false)
+        }
+    };
+    processed.warnings.extend(elevation_warning);
+    if options.enrich_weather {
+        match start_weather(state, &records, &processed.summary).await {
+            Ok(weather) => {
+                processed.summary.wind = weather
+                    .as_ref()
+                    .and_then(|weather| adjust_for_wind(&records, weather));
+                processed.summary.weather = weather;
+            }
+            Err(err) => {
+                tracing::warn!(error = %err, "weather lookup failed");
+                processed.warnings.push(ProcessingWarning::new(
+                    WarningCode::WeatherLookupFailed,
+                    1,
+                    "weather lookup",
+                    &format!("failed: {err}"),
+                ));
+            }
+        }
+    }
+    FileStats {
+        file_name,
+        records: &records,
+        options,
+        processed: &processed,
+        input_bytes: file_bytes.len(),
+        cache_hit: repeat_upload,
+    }
+    .log(state.config.processing_log);
+    let max_stored_bytes = account.and_then(|account| account.quota.max_stored_bytes);
+    let download_id = state
+        .downloads
+        .insert_with_original(
+            processed.processed_bytes.clone(),
+            file_bytes.to_vec(),
+            account.map(|account| account.id.clone()),
+            max_stored_bytes,
+        )
+        .await
+        .map_err(|used| {
+            UploadError::Quota(QuotaExceeded::Storage {
+                limit: max_stored_bytes.unwrap_or_default(),
+                used,
+            })
+        })?;
+    if let Some(usage) = &state.usage {
+        usage.record_file(options).await;
+    }
+    state
+        .results
+        .insert(
+            download_id.clone(),
+            StoredResult {
+                records,
+                options: options.clone(),
+                report: Arc::new(StoredReport::from_processed(&processed)),
+            },
+        )
+        .await;
+    // A repeat of a recent upload is the same activity; keep reports from counting it twice.
+    if !repeat_upload {
+        state
+            .history
+            .record_for(
+                account.map(|account| account.id.clone()),
+                file_name.to_string(),
+                processed.summary.clone(),
+                options.gear_tag().map(str::to_string),
+                processed.track.clone(),
+            )
+            .await;
+        if !processed.track.is_empty() {
+            let owner = account.map(|account| account.id.as_str());
+            state.heatmap.refresh(state.history.clone(), owner);
+        }
+    }
+    let download_url = state.signer.download_url(&download_id);
+    state.events.publish(&JobCompleted::new(
+        file_name,
+        source,
+        Some(&download_url),
+        &processed,
+    ));
+    Ok((processed, download_url))
+}
+
+#[derive(Deserialize)]
+struct ProcessUrlRequest {
+    url: String,
+    #[serde(default)]
+    options: ProcessingOptions,
+}
+
+/// Fetch a FIT file from an https URL and process it as if it were uploaded.
+async fn process_url(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+    axum::Json(request): axum::Json<ProcessUrlRequest>,
+) -> impl IntoResponse {
+    let options = ProcessingOptions {
+        limits: state.limits.clone(),
+        ..request.options
+    };
+    if let Err(err) = options.validate() {
+        return (StatusCode::BAD_REQUEST, err.to_string()).into_response();
+    }
+    if let Err(err) = resolve_plugins(&options.plugins) {
+        return (StatusCode::BAD_REQUEST, err.to_string()).into_response();
+    }
+    let account = account.map(|Extension(account)| account);
+    if let Err(response) = reserve_quota(&state, account.as_ref(), 1).await {
+        return response;
+    }
+
+    let _job = state.jobs.start();
+    let fetched = match fetch_fit(&request.url, &state.config.url_fetch).await {
+        Ok(fetched) => fetched,
+        Err(err) => {
+            let status = match err {
+                FetchError::InvalidUrl(_)
+                | FetchError::InsecureScheme(_)
+                | FetchError::ForbiddenAddress(_) => StatusCode::BAD_REQUEST,
+                FetchError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+                FetchError::TimedOut => StatusCode::GATEWAY_TIMEOUT,
+                FetchError::Resolve(_) | FetchError::Status(_) | FetchError::Request(_) => {
+                    StatusCode::BAD_GATEWAY
+                }
+            };
+            refund_quota(&state, account.as_ref(), 1).await;
+            return (status, err.to_string()).into_response();
+        }
+    };
+    let upload = UploadContext {
+        source: JobSource::Url,
+        bypass_cache: false,
+        account: account.as_ref(),
+        observer: &NoObserver,
+    };
+    match process_upload(
+        &state,
+        &fetched.file_name,
+        &fetched.bytes,
+        &options,
+        &upload,
+    )
+    .await
+    {
+        Ok((processed, download_url)) => axum::Json(FileResult {
+            file_name: fetched.file_name,
+            summary: Some(processed.summary.clone()),
+            annotations: processed.annotations.regions.clone(),
+            speed_spikes: processed.speed_spikes.clone(),
+            warnings: processed.warnings.clone(),
+            smoothing: processed.smoothing.clone(),
+            html: None,
+            download_url: Some(download_url),
+            error: None,
+        })
+        .into_response(),
+        Err(err) => {
+            refund_quota(&state, account.as_ref(), 1).await;
+            render_upload_error(err, None)
+        }
+    }
+}
+
+fn render_processing_error(
+    error: FitProcessError,
+    file_name: Option<&str>,
+) -> axum::response::Response {
+    let message = match file_name {
+        Some(name) => format!("{name}: {error}"),
+        None => error.to_string(),
+    };
+    let status = match error {
+        FitProcessError::LimitExceeded(ExceededLimit::Records { .. }) => {
+            StatusCode::PAYLOAD_TOO_LARGE
+        }
+        FitProcessError::LimitExceeded(ExceededLimit::DecodeTime { .. }) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        FitProcessError::ParseError(_)
+        | FitProcessError::InvalidHeader(_)
+        | FitProcessError::InvalidOption(_) => StatusCode::BAD_REQUEST,
+        FitProcessError::UnsupportedFeature(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        // The upload was fine; writing it back out failed.
+        FitProcessError::EncodeError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        // Only background jobs are cancelled, and they report it in their status.
+        FitProcessError::Cancelled => StatusCode::CONFLICT,
+    };
+    (status, message).into_response()
+}
+
+#[derive(Deserialize)]
+struct DownloadQuery {
+    expires: Option<u64>,
+    signature: Option<String>,
+    /// Download a compressed copy instead of the FIT file itself.
+    compression: Option<DownloadCompression>,
+}
+
+/// Error response for a download link that is unsigned, tampered with, or expired.
+fn signature_rejection(
+    state: &AppState,
+    id: &str,
+    query: &DownloadQuery,
+) -> Option<axum::response::Response> {
+    match state
+        .signer
+        .verify(id, query.expires, query.signature.as_deref())
+    {
+        Ok(()) => None,
+        Err(SignatureError::Expired) => {
+            Some((StatusCode::GONE, "Download link has expired").into_response())
+        }
+        Err(SignatureError::Missing | SignatureError::Invalid) => {
+            Some((StatusCode::FORBIDDEN, "Invalid download link").into_response())
+        }
+    }
+}
+
+/// Per-second power and W' balance of a stored result as CSV, signed like
+/// the processed file it belongs to.
+async fn download_w_prime_balance(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+) -> axum::response::Response {
+    if let Some(rejection) = signature_rejection(&state, &id, &query) {
+        return rejection;
+    }
+    let Some(result) = state.results.get(&id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let _job = state.jobs.start();
+    match processed_w_prime_balance(&result.records, &result.options) {
+        Ok(Some(series)) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"w-prime-balance.csv\"",
+                ),
+            ],
+            series.to_csv(),
+        )
+            .into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            "No power data or critical power to model W' balance",
+        )
+            .into_response(),
+        Err(err) => {
+            state.errors.record(&err).await;
+            render_processing_error(err, None)
+        }
+    }
+}
+
+/// Derived-field values of a stored result, or the response explaining why
+/// there are none.
+async fn derived_field_table(
+    state: &AppState,
+    id: &str,
+    query: &DownloadQuery,
+) -> Result<DerivedFieldTable, axum::response::Response> {
+    if let Some(rejection) = signature_rejection(state, id, query) {
+        return Err(rejection);
+    }
+    let Some(result) = state.results.get(id).await else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let _job = state.jobs.start();
+    match processed_derived_fields(&result.records, &result.options) {
+        Ok(Some(table)) => Ok(table),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "No derived fields were defined").into_response()),
+        Err(err) => {
+            state.errors.record(&err).await;
+            Err(render_processing_error(err, None))
+        }
+    }
+}
+
+/// Per-record derived-field values as CSV, signed like the processed file.
+async fn download_derived_fields_csv(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+) -> axum::response::Response {
+    match derived_field_table(&state, &id, &query).await {
+        Ok(table) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"derived-fields.csv\"",
+                ),
+            ],
+            table.to_csv(),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Per-record derived-field values as JSON, signed like the processed file.
+async fn download_derived_fields_json(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+) -> axum::response::Response {
+    match derived_field_table(&state, &id, &query).await {
+        Ok(table) => axum::Json(table).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Printable report of a stored result as a PDF, signed like the processed
+/// file, with numbers written for the browser's language.
+async fn export_pdf(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    if let Some(rejection) = signature_rejection(&state, &id, &query) {
+        return rejection;
+    }
+    let Some(result) = state.results.get(&id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let locale = NumberLocale::negotiate(None, accept_language(&headers));
+    let disposition = format!("attachment; filename=\"rustyfit-{id}.pdf\"");
+    let report = &result.report;
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        workout_report(&report.summary, &report.annotations, locale),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct RouteQuery {
+    /// Douglas-Peucker tolerance in metres; unset or 0 keeps every point.
+    tolerance_meters: Option<f64>,
+}
+
+/// Name and simplified GPS track of a stored result, or the response
+/// explaining why there is none.
+async fn exported_route(
+    state: &AppState,
+    id: &str,
+    query: &DownloadQuery,
+    route: &RouteQuery,
+) -> Result<(String, Vec<TrackPoint>), axum::response::Response> {
+    if let Some(rejection) = signature_rejection(state, id, query) {
+        return Err(rejection);
+    }
+    let tolerance = route.tolerance_meters.unwrap_or(0.0);
+    if !(tolerance.is_finite() && tolerance >= 0.0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "tolerance_meters must be a distance in metres",
+        )
+            .into_response());
+    }
+    let Some(result) = state.results.get(id).await else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let _job = state.jobs.start();
+    match result.processed_records() {
+        Ok(records) => {
+            let points = track_points(&records);
+            if points.is_empty() {
+                let missing = (StatusCode::NOT_FOUND, "The activity has no GPS track");
+                return Err(missing.into_response());
+            }
+            let name = result
+                .options
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("rustyfit-{id}"));
+            Ok((name, simplify(&points, tolerance)))
+        }
+        Err(err) => {
+            state.errors.record(&err).await;
+            Err(render_processing_error(err, None))
+        }
+    }
+}
+
+/// Route of a stored result as GPX, signed like the processed file.
+async fn export_gpx(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    Query(route): Query<RouteQuery>,
+) -> axum::response::Response {
+    match exported_route(&state, &id, &query, &route).await {
+        Ok((name, track)) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/gpx+xml".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"rustyfit-{id}.gpx\""),
+                ),
+            ],
+            to_gpx(&track, &name),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Route of a stored result as a GeoJSON `LineString` feature, signed like
+/// the processed file.
+async fn export_geojson(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    Query(route): Query<RouteQuery>,
+) -> axum::response::Response {
+    match exported_route(&state, &id, &query, &route).await {
+        Ok((name, track)) => (
+            [(header::CONTENT_TYPE, "application/geo+json")],
+            axum::Json(to_geojson(&track, &name)),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+/// File of a result served by a download link.
+#[derive(Debug, Clone, Copy)]
+enum DownloadedFile {
+    Processed,
+    /// The upload exactly as it was received.
+    Original,
+}
+
+impl DownloadedFile {
+    fn stem(self) -> &'static str {
+        match self {
+            DownloadedFile::Processed => "processed",
+            DownloadedFile::Original => "original",
+        }
+    }
+}
+
+/// Serve a processed file with validators for caching proxies and `Range`
+/// support for resumable downloads. `HEAD` is answered by the same route.
+///
+/// Only links signed by [`UrlSigner::download_url`] that have not yet expired
+/// are honoured.
+async fn download_processed(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    serve_download(&state, &id, &query, &headers, DownloadedFile::Processed).await
+}
+
+/// Serve the uploaded file a result was processed from, under the same
+/// signed link and for as long as the processed file.
+async fn download_original(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    serve_download(&state, &id, &query, &headers, DownloadedFile::Original).await
+}
+
+async fn serve_download(
+    state: &AppState,
+    id: &str,
+    query: &DownloadQuery,
+    headers: &HeaderMap,
+    file: DownloadedFile,
+) -> axum::response::Response {
+    if let Some(rejection) = signature_rejection(state, id, query) {
+        return rejection;
+    }
+    let Some(download) = state.downloads.get(id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let download = match file {
+        DownloadedFile::Processed => download,
+        DownloadedFile::Original => match download.original() {
+            Some(original) => original.clone(),
+            None => {
+                return (StatusCode::NOT_FOUND, "The original upload was not kept").into_response();
+            }
+        },
+    };
+    let header_text = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let accepts_gzip = accepts_encoding(headers, "gzip");
+    // Ranges address the uncompressed bytes, so range requests are served as-is.
+    let compression = query.compression.or_else(|| {
+        (accepts_gzip && header_text(header::RANGE).is_none()).then_some(DownloadCompression::Gzip)
+    });
+    let etag = match compression {
+        Some(compression) => compression.etag(&download.etag),
+        None => download.etag.clone(),
+    };
+    let accept_ranges = if compression.is_some() {
+        "none"
+    } else {
+        "bytes"
+    };
+    let modified = DateTime::<Utc>::from(download.last_modified);
+    let validators = [
+        (header::ETAG, etag.clone()),
+        (
+            header::LAST_MODIFIED,
+            modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        ),
+        (header::ACCEPT_RANGES, accept_ranges.to_string()),
+        (header::VARY, header::ACCEPT_ENCODING.to_string()),
+    ];
+
+    let not_modified = match header_text(header::IF_NONE_MATCH) {
+        Some(tags) => tags
+            .split(',')
+            .any(|tag| tag.trim() == etag || tag.trim() == "*"),
+        None => header_text(header::IF_MODIFIED_SINCE)
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| since.timestamp() >= modified.timestamp()),
+    };
+    if not_modified {
+        return (StatusCode::NOT_MODIFIED, validators).into_response();
+    }
+
+    let content = [
+        (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.fit\"", file.stem()),
+        ),
+    ];
+    match (compression, query.compression) {
+        (Some(requested), Some(_)) => {
+            let content = [
+                (header::CONTENT_TYPE, requested.content_type().to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"{}\"",
+                        requested.file_name(file.stem())
+                    ),
+                ),
+            ];
+            let body = compressed_body(download.bytes.clone(), requested, file.stem());
+            return (StatusCode::OK, validators, content, body).into_response();
+        }
+        (Some(negotiated), None) => {
+            let body = compressed_body(download.bytes.clone(), negotiated, file.stem());
+            return (
+                StatusCode::OK,
+                validators,
+                content,
+                [(header::CONTENT_ENCODING, "gzip")],
+                body,
+            )
+                .into_response();
+        }
+        (None, _) => {}
+    }
+    let len = download.bytes.len();
+    match parse_range(header_text(header::RANGE), len) {
+        RangeRequest::Full => {
+            (StatusCode::OK, validators, content, download.bytes.to_vec()).into_response()
+        }
+        RangeRequest::Partial(range) => (
+            StatusCode::PARTIAL_CONTENT,
+            validators,
+            content,
+            [(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{len}", range.start, range.end - 1),
+            )],
+            download.bytes[range].to_vec(),
+        )
+            .into_response(),
+        RangeRequest::Unsatisfiable => (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{len}"))],
+        )
+            .into_response(),
+    }
+}
+
+/// Aggregate mean-max curves of the stored activities as a chart.
+async fn curves_page(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+) -> Html<String> {
+    Html(render_curves_page(
+        &state.history.curves(owner_id(&account)).await,
+    ))
+}
+
+/// Names accepted by the `plugins` upload field.
+async fn plugins_api() -> axum::Json<Vec<&'static str>> {
+    axum::Json(plugin_names())
+}
+
+/// The FIT profile's messages with their known fields, for building field
+/// pickers.
+async fn profile_messages_api() -> axum::Json<&'static [MessageInfo]> {
+    axum::Json(message_catalog())
+}
+
+/// Per-activity and aggregate mean-max curves of the stored activities.
+async fn curves_api(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+) -> axum::Json<CurveHistory> {
+    axum::Json(state.history.curves(owner_id(&account)).await)
+}
+
+/// Density of the stored GPS tracks as a GeoJSON layer; see [`crate::heatmap`].
+/// An account sees its own uploads, and the admin those made without one.
+async fn heatmap_layer(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let owner = match state.accounts.authenticate(&headers) {
+        Some(account) => Some(account.id),
+        None => {
+            if let Some(rejection) = admin_rejection(&state, &headers) {
+                return rejection;
+            }
+            None
+        }
+    };
+    let snapshot = state.heatmap.layer(owner.as_deref()).current();
+    (
+        [(header::CONTENT_TYPE, "application/geo+json")],
+        snapshot.geojson.clone(),
+    )
+        .into_response()
+}
+
+/// Distance and time per gear tag across the stored activities.
+async fn gear_api(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+) -> axum::Json<Vec<GearUsage>> {
+    axum::Json(state.history.gear_usage(owner_id(&account)).await)
+}
+
+#[derive(Deserialize)]
+struct ReportQuery {
+    /// `html`, `csv`, or `json`; negotiated on `Accept` when unset.
+    format: Option<String>,
+    /// Number format of the HTML report, e.g. `de`; `Accept-Language` when unset.
+    locale: Option<String>,
+}
+
+async fn training_report(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+    Path(period): Path<String>,
+    headers: HeaderMap,
+    Query(query): Query<ReportQuery>,
+) -> impl IntoResponse {
+    let period = match period.as_str() {
+        "weekly" => ReportPeriod::Week,
+        "monthly" => ReportPeriod::Month,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let summaries = state.history.summaries(owner_id(&account)).await;
+    let report = build_training_report(&summaries, period);
+
+    let negotiated = [
+        ResponseFormat::Html,
+        ResponseFormat::Json,
+        ResponseFormat::Csv,
+    ];
+    let format =
+        query
+            .format
+            .as_deref()
+            .unwrap_or_else(|| match negotiate(&headers, &negotiated) {
+                ResponseFormat::Json => "json",
+                ResponseFormat::Csv => "csv",
+                _ => "html",
+            });
+    match format {
+        "html" => {
+            let locale =
+                NumberLocale::negotiate(query.locale.as_deref(), accept_language(&headers));
+            Html(render_training_report(&report, locale)).into_response()
+        }
+        "csv" => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"training-report.csv\"",
+                ),
+            ],
+            report.to_csv(),
+        )
+            .into_response(),
+        "json" => axum::Json(report).into_response(),
+        other => (
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported report format: {other}"),
+        )
+            .into_response(),
+    }
+}
+
+async fn diff_form(Extension(CsrfToken(csrf_token)): Extension<CsrfToken>) -> Html<String> {
+    Html(render_diff_form(&csrf_token))
+}
+
+async fn diff_page(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    match diff_uploads(&state, account, multipart).await {
+        Ok(diff) => Html(render_diff_result(&diff)).into_response(),
+        Err(response) => response,
+    }
+}
+
+async fn diff_api(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    match diff_uploads(&state, account, multipart).await {
+        Ok(diff) => axum::Json(diff).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// The fields of a `file` upload grouped by message type, without processing
+/// it, for picking fields to remove before the real upload.
+async fn fields_api(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<axum::Json<Vec<MessageFields>>, Response> {
+    let mut bytes = None;
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() != Some("file") {
+            continue;
+        }
+        match field.bytes().await {
+            Ok(data) => bytes = Some(data),
+            Err(err) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Failed to read uploaded file: {err}"),
+                )
+                    .into_response());
+            }
+        }
+    }
+    let Some(bytes) = bytes else {
+        return Err((StatusCode::BAD_REQUEST, "A file is required").into_response());
+    };
+
+    let _job = state.jobs.start();
+    match parse_fit_with_limits(&bytes, &state.limits) {
+        Ok(records) => Ok(axum::Json(present_fields(&records))),
+        Err(err) => {
+            state.errors.record(&err).await;
+            Err(render_processing_error(err, None))
+        }
+    }
+}
+
+async fn create_form(Extension(CsrfToken(csrf_token)): Extension<CsrfToken>) -> Html<String> {
+    Html(render_create_form(&csrf_token))
+}
+
+/// Fields of the `/create` form, read by [`ManualActivity::from_form`].
+#[derive(Deserialize)]
+struct ManualActivityForm {
+    sport: String,
+    start: String,
+    #[serde(default)]
+    utc_offset_minutes: String,
+    duration: String,
+    #[serde(default)]
+    distance_km: String,
+}
+
+/// FIT file for a workout entered by hand on `/create`.
+async fn create_activity(axum::Form(form): axum::Form<ManualActivityForm>) -> Response {
+    let utc_offset_minutes = form.utc_offset_minutes.trim().parse().unwrap_or(0);
+    let built = ManualActivity::from_form(
+        &form.sport,
+        &form.start,
+        utc_offset_minutes,
+        &form.duration,
+        &form.distance_km,
+    )
+    .and_then(|activity| build_manual_fit(&activity).map(|bytes| (activity.sport, bytes)));
+    match built {
+        Ok((sport, bytes)) => (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{sport}.fit\""),
+                ),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    }
+}
+
+/// Read the `left` and `right` files from a multipart body and diff them.
+async fn diff_uploads(
+    state: &AppState,
+    account: Option<Extension<Account>>,
+    mut multipart: Multipart,
+) -> Result<FitDiff, axum::response::Response> {
+    let mut left: Option<Vec<u8>> = None;
+    let mut right: Option<Vec<u8>> = None;
+
+    while let Ok(Some(field)) = multipart.next_field().await {
+        let slot = match field.name() {
+            Some("left") => &mut left,
+            Some("right") => &mut right,
+            _ => continue,
+        };
+        match field.bytes().await {
+            Ok(bytes) => *slot = Some(bytes.to_vec()),
+            Err(err) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Failed to read uploaded file: {err}"),
+                )
+                    .into_response());
+            }
+        }
+    }
+
+    let (Some(left), Some(right)) = (left, right) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Two files are required: left and right",
+        )
+            .into_response());
+    };
+
+    let account = account.map(|Extension(account)| account);
+    reserve_quota(state, account.as_ref(), 2).await?;
+
+    let _job = state.jobs.start();
+    match diff_fit_bytes(&left, &right, &state.limits) {
+        Ok(diff) => Ok(diff),
+        Err(err) => {
+            refund_quota(state, account.as_ref(), 2).await;
+            state.errors.record(&err).await;
+            Err(render_processing_error(err, None))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PreviewQuery {
+    window: usize,
+}
+
+/// Recompute the smoothed speed series of a stored result for a new window size.
+async fn preview_smoothing(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+    Path(id): Path<String>,
+    Query(query): Query<PreviewQuery>,
+) -> impl IntoResponse {
+    if query.window == 0 || query.window > MAX_PREVIEW_WINDOW {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("window must be between 1 and {MAX_PREVIEW_WINDOW}"),
+        )
+            .into_response();
+    }
+
+    let account = account.map(|Extension(account)| account);
+    // Other accounts' results are as good as missing.
+    let owner = state
+        .downloads
+        .get(&id)
+        .await
+        .and_then(|download| download.owner);
+    if owner.as_deref() != account.as_ref().map(|account| account.id.as_str()) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Some(result) = state.results.get(&id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(response) = reserve_quota(&state, account.as_ref(), 1).await {
+        return response;
+    }
+    let _job = state.jobs.start();
+    axum::Json(preview_smoothed_speed(&result.records, query.window)).into_response()
+}
+
+/// Search the processed messages of a stored result. Takes the same
+/// `expires` and `signature` parameters as the result's download link.
+async fn search_records(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(signed): Query<DownloadQuery>,
+    Query(query): Query<RecordQuery>,
+) -> axum::response::Response {
+    if let Some(rejection) = signature_rejection(&state, &id, &signed) {
+        return rejection;
+    }
+    let Some(result) = state.results.get(&id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let _job = state.jobs.start();
+    match result.processed_records() {
+        Ok(records) => {
+            let utc_offset_seconds = result.report.summary.utc_offset_seconds;
+            axum::Json(query_records(&records, &query, utc_offset_seconds)).into_response()
+        }
+        Err(err) => {
+            state.errors.record(&err).await;
+            render_processing_error(err, None)
+        }
+    }
+}
+
+/// Field overrides for one message, e.g. `{"fields": {"heart_rate": 143}}`;
+/// `null` removes the field.
+#[derive(Deserialize)]
+struct RecordPatch {
+    fields: BTreeMap<String, Option<f64>>,
+}
+
+/// Edit one message of a processed file and re-encode its download in place.
+///
+/// Edits are kept with the result's options, so later previews, searches,
+/// and edits see them too.
+async fn edit_record(
+    State(state): State<AppState>,
+    Path((id, index)): Path<(String, usize)>,
+    Query(signed): Query<DownloadQuery>,
+    axum::Json(patch): axum::Json<RecordPatch>,
+) -> axum::response::Response {
+    if let Some(rejection) = signature_rejection(&state, &id, &signed) {
+        return rejection;
+    }
+    // Held until the edited result is stored, so concurrent edits apply one
+    // after the other instead of overwriting each other.
+    let Some(_edit) = state.results.lock_for_edit(&id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(mut result) = state.results.get(&id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let edits: Vec<RecordEdit> = patch
+        .fields
+        .into_iter()
+        .map(|(field, value)| RecordEdit {
+            index,
+            field,
+            value: value.map(HashableF64),
+        })
+        .collect();
+    if edits.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No fields to edit").into_response();
+    }
+
+    let _job = state.jobs.start();
+    let checked = result
+        .processed_records()
+        .map(|current| check_edits(&current, &edits));
+    match checked {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => {
+            return (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response();
+        }
+        Err(err) => return render_processing_error(err, None),
+    }
+    result.options.record_edits.extend(edits);
+    let processed = match process_parsed_fit(&result.records, &result.options) {
+        Ok(processed) => processed,
+        Err(err) => {
+            state.errors.record(&err).await;
+            return render_processing_error(err, None);
+        }
+    };
+    result.report = Arc::new(StoredReport::from_processed(&processed));
+    if !state
+        .downloads
+        .replace(&id, processed.processed_bytes)
+        .await
+    {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let query = RecordQuery {
+        offset: index,
+        limit: Some(1),
+        ..RecordQuery::default()
+    };
+    let utc_offset_seconds = result.report.summary.utc_offset_seconds;
+    let page = result
+        .processed_records()
+        .map(|records| query_records(&records, &query, utc_offset_seconds));
+    state.results.insert(id, result).await;
+    match page {
+        Ok(page) => axum::Json(&page.records[0]).into_response(),
+        Err(err) => render_processing_error(err, None),
+    }
+}
+
+/// Bytes and decoded fields of one message of the processed file, signed like
+/// its download link.
+async fn raw_record(
+    State(state): State<AppState>,
+    Path((id, index)): Path<(String, usize)>,
+    Query(signed): Query<DownloadQuery>,
+) -> axum::response::Response {
+    if let Some(rejection) = signature_rejection(&state, &id, &signed) {
+        return rejection;
+    }
+    let Some(download) = state.downloads.get(&id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let _job = state.jobs.start();
+    match raw_message(&download.bytes, index) {
+        Ok(Some(message)) => axum::Json(message).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "No message at that index").into_response(),
+        Err(err) => {
+            state.errors.record(&err).await;
+            render_processing_error(err, None)
+        }
+    }
+}
+
+/// Return an anonymized, truncated copy of the uploaded file for bug reports.
+async fn anonymized_sample(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let mut uploaded: Option<Vec<u8>> = None;
+    let mut options = SampleOptions::default();
+
+    while let Ok(Some(field)) = multipart.next_field().await {
+        match field.name() {
+            Some("file") => match field.bytes().await {
+                Ok(bytes) => uploaded = Some(bytes.to_vec()),
+                Err(err) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        format!("Failed to read uploaded file: {err}"),
+                    )
+                        .into_response();
+                }
+            },
+            Some("max_records") => {
+                if let Some(value) = field.text().await.ok().and_then(|v| v.parse().ok()) {
+                    options.max_records = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let Some(file_bytes) = uploaded else {
+        return (StatusCode::BAD_REQUEST, "No file provided").into_response();
+    };
+    let account = account.map(|Extension(account)| account);
+    if let Err(response) = reserve_quota(&state, account.as_ref(), 1).await {
+        return response;
+    }
+
+    let _job = state.jobs.start();
+    match extract_sample_bytes(&file_bytes, &options, &state.limits) {
+        Ok(bytes) => (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, "application/octet-stream"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"sample.fit\"",
+                ),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(err) => {
+            refund_quota(&state, account.as_ref(), 1).await;
+            state.errors.record(&err).await;
+            render_processing_error(err, None)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AdminQuery {
+    purged: Option<usize>,
+}
+
+/// Response for requests without the admin token; the page is hidden entirely
+/// when no token is configured.
+fn admin_rejection(state: &AppState, headers: &HeaderMap) -> Option<axum::response::Response> {
+    match &state.admin_token {
+        None => Some(StatusCode::NOT_FOUND.into_response()),
+        Some(token) if token.authorizes(headers) => None,
+        Some(_) => Some(
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Basic realm=\"RustyFit admin\"")],
+            )
+                .into_response(),
+        ),
+    }
+}
+
+/// Identify the account behind a metered request, refusing anonymous ones
+/// once accounts are configured. The account is passed on as an extension.
+async fn require_account(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if state.accounts.is_enabled() {
+        let Some(account) = state.accounts.authenticate(request.headers()) else {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Basic realm=\"RustyFit\"")],
+                "Sign in with your account token",
+            )
+                .into_response();
+        };
+        request.extensions_mut().insert(account);
+    }
+    next.run(request).await
+}
+
+/// Count `files` new uploads against `account`'s quota; anonymous uploads are
+/// only possible without accounts and are not metered.
+async fn reserve_quota(
+    state: &AppState,
+    account: Option<&Account>,
+    files: usize,
+) -> Result<(), Response> {
+    let Some(account) = account else {
+        return Ok(());
+    };
+    let stored_bytes = state.downloads.stored_bytes(&account.id).await;
+    state
+        .accounts
+        .reserve(account, files, stored_bytes)
+        .await
+        .map_err(|exceeded| quota_exceeded_response(&exceeded))
+}
+
+/// ID of the signed-in account, whose stored activities a request may see;
+/// `None` without accounts.
+fn owner_id(account: &Option<Extension<Account>>) -> Option<&str> {
+    account
+        .as_ref()
+        .map(|Extension(account)| account.id.as_str())
+}
+
+/// Hand back `files` reserved by [`reserve_quota`] that were not processed.
+async fn refund_quota(state: &AppState, account: Option<&Account>, files: usize) {
+    if let Some(account) = account.filter(|_| files > 0) {
+        state.accounts.refund(account, files).await;
+    }
+}
+
+fn quota_exceeded_response(exceeded: &QuotaExceeded) -> Response {
+    match exceeded {
+        QuotaExceeded::FilesPerDay { retry_after, .. } => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(
+                header::RETRY_AFTER,
+                retry_after.as_secs().max(1).to_string(),
+            )],
+            exceeded.to_string(),
+        )
+            .into_response(),
+        QuotaExceeded::Storage { .. } => {
+            (StatusCode::INSUFFICIENT_STORAGE, exceeded.to_string()).into_response()
+        }
+    }
+}
+
+/// Usage of the signed-in account against its quota; hidden without accounts.
+async fn account_page(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+) -> Response {
+    let Some(Extension(account)) = account else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let stored_bytes = state.downloads.stored_bytes(&account.id).await;
+    Html(render_account_usage(
+        &state.accounts.usage(&account, stored_bytes).await,
+    ))
+    .into_response()
+}
+
+async fn account_usage_api(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+) -> Response {
+    let Some(Extension(account)) = account else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let stored_bytes = state.downloads.stored_bytes(&account.id).await;
+    axum::Json(state.accounts.usage(&account, stored_bytes).await).into_response()
+}
+
+/// Zip of everything stored for the signed-in account: its activity
+/// summaries and the processed files not yet expired.
+async fn export_account(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+) -> Response {
+    let Some(Extension(account)) = account else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let activities = state.history.owned_by(&account.id).await;
+    let downloads = state.downloads.owned_by(&account.id).await;
+    let bundle = tokio::task::spawn_blocking(move || {
+        account_bundle(&account.id, &activities, &downloads).map(|bytes| (account.id, bytes))
+    })
+    .await;
+    match bundle {
+        Ok(Ok((id, bytes))) => (
+            [
+                (header::CONTENT_TYPE, "application/zip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"rustyfit-{id}.zip\""),
+                ),
+                (header::CACHE_CONTROL, "no-store".to_string()),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Ok(Err(err)) => {
+            tracing::error!(error = %err, "failed to build account export");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(err) => {
+            tracing::error!(error = %err, "account export task failed");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DeletedAccountData {
+    deleted_activities: usize,
+    deleted_files: usize,
+}
+
+/// Forget the signed-in account's activity history, processed files, cached
+/// uploads, idempotent responses, and background jobs. Its token and quota
+/// stay, since they come from the accounts file.
+async fn delete_account_data(
+    State(state): State<AppState>,
+    account: Option<Extension<Account>>,
+) -> Response {
+    let Some(Extension(account)) = account else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let deleted_activities = state.history.remove_owned(&account.id).await;
+    state
+        .heatmap
+        .refresh(state.history.clone(), Some(&account.id));
+    let ids = state.downloads.remove_owned(&account.id).await;
+    state.results.remove(&ids).await;
+    state.cache.remove_owned(&account.id).await;
+    state
+        .results
+        .remove_idempotency_scope(&account_scope(&account.id))
+        .await;
+    state.background_jobs.remove_owned(&account.id);
+    tracing::info!(
+        account = %account.id,
+        deleted_activities,
+        deleted_files = ids.len(),
+        "deleted account data"
+    );
+    axum::Json(DeletedAccountData {
+        deleted_activities,
+        deleted_files: ids.len(),
+    })
+    .into_response()
+}
+
+/// Operator view of downloads, active jobs, cache, and recent errors.
+async fn admin_dashboard(
+    State(state): State<AppState>,
+    Extension(CsrfToken(csrf_token)): Extension<CsrfToken>,
+    headers: HeaderMap,
+    Query(query): Query<AdminQuery>,
+) -> axum::response::Response {
+    if let Some(response) = admin_rejection(&state, &headers) {
+        return response;
+    }
+    let (error_counts, recent_errors) = state.errors.snapshot().await;
+    let overview = AdminOverview {
+        downloads: state.downloads.list().await,
+        active_jobs: state.jobs.active(),
+        cache: state.cache.metrics().await,
+        error_counts,
+        recent_errors,
+        purged: query.purged,
+        usage: match &state.usage {
+            Some(usage) => Some(usage.snapshot().await),
+            None => None,
+        },
+    };
+    Html(render_admin_dashboard(&overview, &csrf_token)).into_response()
+}
+
+/// Drop expired downloads and cache entries, then return to the dashboard.
+async fn admin_purge(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> axum::response::Response {
+    if let Some(response) = admin_rejection(&state, &headers) {
+        return response;
+    }
+    let purged = state.downloads.purge_expired().await + state.cache.purge_expired().await;
+    Redirect::to(&format!("/admin?purged={purged}")).into_response()
+}
+
+async fn cache_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    axum::Json(state.cache.metrics().await)
+}
+
+/// Map fingerprinted asset names back to the file on disk. Responses under
+/// the file's current fingerprint never change and are cached for a year;
+/// plain names and stale fingerprints revalidate.
+async fn fingerprinted_assets(mut request: Request, next: Next) -> Response {
+    let fingerprinted = is_current_fingerprint(request.uri().path());
+    let stripped =
+        strip_fingerprint(request.uri().path()).and_then(|path| path.parse::<Uri>().ok());
+    if let Some(uri) = stripped {
+        *request.uri_mut() = uri;
+    }
+
+    let mut response = next.run(request).await;
+    if response.status().is_success() {
+        let cache_control = if fingerprinted {
+            "public, max-age=31536000, immutable"
+        } else {
+            "no-cache"
+        };
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(cache_control),
+        );
+    }
+    response
+}
+
+/// Serve the optional in-browser processing bundle when it has been built.
+async fn wasm_asset(State(state): State<AppState>, Path(file): Path<String>) -> impl IntoResponse {
+    let content_type = match file.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("js") => "text/javascript",
+        Some("wasm") => "application/wasm",
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    if file.contains("..") || file.contains('/') || file.contains('\\') {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    match tokio::fs::read(state.config.wasm_pkg_dir.join(&file)).await {
+        Ok(bytes) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, content_type)],
+            bytes,
+        )
+            .into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn landing_page_responds() {
+        let app = build_app();
+        let response = app
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn upload_without_file_is_rejected() {
+        let app = build_app();
+        let req = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header("content-type", "multipart/form-data; boundary=--boundary")
+            .header(header::COOKIE, "rustyfit_csrf=test-token")
+            .header("x-csrf-token", "test-token")
+            .body(Body::from("----boundary--"))
+            .unwrap();
+
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn upload_rejects_invalid_derived_fields() {
+        let body = "--b\r\n\
+Content-Disposition: form-data; name=\"derived_fields\"\r\n\r\n\
+watts_per_kg = power /\r\n\
+--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"ride.fit\"\r\n\r\n\
+not a fit file\r\n\
+--b--\r\n";
+        let response = build_app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/upload")
+                    .header("content-type", "multipart/form-data; boundary=b")
+                    .header(header::COOKIE, "rustyfit_csrf=test-token")
+                    .header("x-csrf-token", "test-token")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("watts_per_kg"));
+    }
+
+    #[tokio::test]
+    async fn upload_answers_with_the_fit_file_when_asked_for_it() {
+        let mut body = b"--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"ride.fit\"\r\n\r\n"
+            .to_vec();
+        body.extend(std::fs::read("test/fixtures/activity.fit").unwrap());
+        body.extend(b"\r\n--b--\r\n");
+        let response = build_app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/upload")
+                    .header("content-type", "multipart/form-data; boundary=b")
+                    .header(header::ACCEPT, "application/vnd.ant.fit, text/html;q=0.5")
+                    .header(header::COOKIE, "rustyfit_csrf=test-token")
+                    .header("x-csrf-token", "test-token")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/vnd.ant.fit"
+        );
+        assert!(response.headers().contains_key(header::CONTENT_LOCATION));
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[8..12], b".FIT");
+    }
+
+    #[tokio::test]
+    async fn json_upload_reports_each_file_outcome() {
+        let body = "--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"broken.fit\"\r\n\r\n\
+not a fit file\r\n\
+--b\r\n\
+Content-Disposition: form-data; name=\"file_options\"\r\n\r\n\
+{\"smooth_speed\": true}\r\n\
+--b--\r\n";
+        let response = build_app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/upload")
+                    .header("content-type", "multipart/form-data; boundary=b")
+                    .header(header::ACCEPT, "application/json")
+                    .header(header::COOKIE, "rustyfit_csrf=test-token")
+                    .header("x-csrf-token", "test-token")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let results: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(results["files"][0]["file_name"], "broken.fit");
+        assert!(results["files"][0]["error"].is_string());
+        assert_eq!(results["aggregate"]["activity_count"], 0);
+    }
+
+    #[tokio::test]
+    async fn process_api_replays_requests_with_the_same_idempotency_key() {
+        let mut body = b"--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"ride.fit\"\r\n\r\n"
+            .to_vec();
+        body.extend(std::fs::read("test/fixtures/activity.fit").unwrap());
+        body.extend(b"\r\n--b--\r\n");
+        let app = build_app();
+        let request = |body: Vec<u8>| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/process")
+                .header("content-type", "multipart/form-data; boundary=b")
+                .header("idempotency-key", "ride-upload-1")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let first = app.clone().oneshot(request(body.clone())).await.unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let first = first.into_body().collect().await.unwrap().to_bytes();
+
+        let retry = app.clone().oneshot(request(body.clone())).await.unwrap();
+        assert_eq!(retry.headers()["idempotent-replayed"], "true");
+        let retry = retry.into_body().collect().await.unwrap().to_bytes();
+        let first: serde_json::Value = serde_json::from_slice(&first).unwrap();
+        let retry: serde_json::Value = serde_json::from_slice(&retry).unwrap();
+        assert!(first["files"][0]["download_url"].is_string());
+        assert_eq!(
+            first["files"][0]["download_url"],
+            retry["files"][0]["download_url"]
+        );
+
+        body.extend(b"changed");
+        let reused = app.oneshot(request(body)).await.unwrap();
+        assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn accounts_are_required_and_held_to_their_daily_quota() {
+        let mut body = b"--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"ride.fit\"\r\n\r\n"
+            .to_vec();
+        body.extend(std::fs::read("test/fixtures/activity.fit").unwrap());
+        body.extend(b"\r\n--b--\r\n");
+        let app = router_with_state(AppState {
+            accounts: AccountStore::from_json(
+                r#"[{ "id": "alice", "token": "alice-token", "files_per_day": 1 }]"#,
+            )
+            .unwrap(),
+            ..AppState::default()
+        });
+        let request = |token: Option<&str>| {
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/api/v1/process")
+                .header("content-type", "multipart/form-data; boundary=b");
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            request.body(Body::from(body.clone())).unwrap()
+        };
+
+        let anonymous = app.clone().oneshot(request(None)).await.unwrap();
+        assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+        let first = app
+            .clone()
+            .oneshot(request(Some("alice-token")))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let second = app
+            .clone()
+            .oneshot(request(Some("alice-token")))
+            .await
+            .unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(second.headers().contains_key(header::RETRY_AFTER));
+
+        let usage = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v1/account/usage")
+                    .header(header::AUTHORIZATION, "Bearer alice-token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let usage = usage.into_body().collect().await.unwrap().to_bytes();
+        let usage: serde_json::Value = serde_json::from_slice(&usage).unwrap();
+        assert_eq!(usage["files_today"], 1);
+        assert!(usage["stored_bytes"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn the_same_file_from_two_accounts_is_recorded_for_both() {
+        let mut body = b"--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"ride.fit\"\r\n\r\n"
+            .to_vec();
+        body.extend(std::fs::read("test/fixtures/activity.fit").unwrap());
+        body.extend(b"\r\n--b--\r\n");
+        let state = AppState {
+            accounts: AccountStore::from_json(
+                r#"[
+                    { "id": "alice", "token": "alice-token" },
+                    { "id": "bob", "token": "bob-token" }
+                ]"#,
+            )
+            .unwrap(),
+            ..AppState::default()
+        };
+        let app = router_with_state(state.clone());
+
+        for token in ["alice-token", "bob-token"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/v1/process")
+                        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+                        .header("content-type", "multipart/form-data; boundary=b")
+                        .body(Body::from(body.clone()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        assert_eq!(state.history.owned_by("alice").await.len(), 1);
+        assert_eq!(state.history.owned_by("bob").await.len(), 1);
+        assert_eq!(state.cache.metrics().await.hits, 0);
+    }
+
+    #[tokio::test]
+    async fn field_listing_requires_an_account() {
+        let app = router_with_state(AppState {
+            accounts: AccountStore::from_json(r#"[{ "id": "alice", "token": "alice-token" }]"#)
+                .unwrap(),
+            ..AppState::default()
+        });
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/fields")
+                    .header("content-type", "multipart/form-data; boundary=b")
+                    .body(Body::from("--b--\r\n"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn failed_files_do_not_count_against_the_quota() {
+        let body = b"--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"broken.fit\"\r\n\r\n\
+not a fit file\r\n--b--\r\n";
+        let app = router_with_state(AppState {
+            accounts: AccountStore::from_json(
+                r#"[{ "id": "alice", "token": "alice-token", "files_per_day": 1 }]"#,
+            )
+            .unwrap(),
+            ..AppState::default()
+        });
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/v1/process")
+                        .header(header::AUTHORIZATION, "Bearer alice-token")
+                        .header("content-type", "multipart/form-data; boundary=b")
+                        .body(Body::from(body.to_vec()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let usage = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v1/account/usage")
+                    .header(header::AUTHORIZATION, "Bearer alice-token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let usage = usage.into_body().collect().await.unwrap().to_bytes();
+        let usage: serde_json::Value = serde_json::from_slice(&usage).unwrap();
+        assert_eq!(usage["files_today"], 0);
+    }
+
+    #[tokio::test]
+    async fn account_data_can_be_exported_and_deleted() {
+        let mut body = b"--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"ride.fit\"\r\n\r\n"
+            .to_vec();
+        body.extend(std::fs::read("test/fixtures/activity.fit").unwrap());
+        body.extend(b"\r\n--b--\r\n");
+        let state = AppState {
+            accounts: AccountStore::from_json(
+                r#"[{ "id": "alice", "token": "alice-token" }, { "id": "bob", "token": "bob-token" }]"#,
+            )
+            .unwrap(),
+            ..AppState::default()
+        };
+        let app = router_with_state(state.clone());
+        let request = |method: &str, uri: &str, token: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        };
+        let processed = app
+            .clone()
+            .oneshot(
+                request("POST", "/api/v1/process", "alice-token")
+                    .header("content-type", "multipart/form-data; boundary=b")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(processed.status(), StatusCode::OK);
+
+        // Reports only count the signed-in account's activities.
+        for (token, periods) in [("alice-token", 1), ("bob-token", 0)] {
+            let report = app
+                .clone()
+                .oneshot(
+                    request("GET", "/reports/weekly?format=json", token)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let report = report.into_body().collect().await.unwrap().to_bytes();
+            let report: serde_json::Value = serde_json::from_slice(&report).unwrap();
+            assert_eq!(report["periods"].as_array().unwrap().len(), periods);
+        }
+
+        let export = |token: &'static str| {
+            app.clone().oneshot(
+                request("GET", "/api/v1/account/export", token)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        let response = export("alice-token").await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/zip");
+        let bundle = response.into_body().collect().await.unwrap().to_bytes();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bundle.to_vec())).unwrap();
+        assert!(archive.file_names().any(|name| name.starts_with("files/")));
+        let other = export("bob-token").await.unwrap();
+        let other = other.into_body().collect().await.unwrap().to_bytes();
+        let other = zip::ZipArchive::new(std::io::Cursor::new(other.to_vec())).unwrap();
+        assert!(!other.file_names().any(|name| name.starts_with("files/")));
+
+        let deleted = app
+            .clone()
+            .oneshot(
+                request("DELETE", "/api/v1/account/data", "alice-token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let deleted = deleted.into_body().collect().await.unwrap().to_bytes();
+        let deleted: serde_json::Value = serde_json::from_slice(&deleted).unwrap();
+        assert_eq!(deleted["deleted_activities"], 1);
+        assert_eq!(deleted["deleted_files"], 1);
+        assert_eq!(state.cache.metrics().await.entries, 0);
+        let bundle = export("alice-token").await.unwrap();
+        let bundle = bundle.into_body().collect().await.unwrap().to_bytes();
+        let archive = zip::ZipArchive::new(std::io::Cursor::new(bundle.to_vec())).unwrap();
+        assert!(!archive.file_names().any(|name| name.starts_with("files/")));
+    }
+
+    #[tokio::test]
+    async fn background_jobs_finish_with_the_process_api_result() {
+        let mut body = b"--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"ride.fit\"\r\n\r\n"
+            .to_vec();
+        body.extend(std::fs::read("test/fixtures/activity.fit").unwrap());
+        body.extend(b"\r\n--b--\r\n");
+        let app = build_app();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/jobs")
+                    .header("content-type", "multipart/form-data; boundary=b")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let job: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let status_url = job["status_url"].as_str().unwrap().to_string();
+
+        let mut status = serde_json::Value::Null;
+        for _ in 0..100 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(&status_url)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            status = serde_json::from_slice(&body).unwrap();
+            if status["state"] == "completed" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(status["state"], "completed");
+        assert!(status["result"]["files"][0]["download_url"].is_string());
+
+        let missing = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v1/jobs/unknown/events")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn form_posts_require_matching_csrf_token() {
+        let app = build_app();
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/diff").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("rustyfit_csrf="));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/upload?csrf_token=forged")
+                    .header(header::COOKIE, "rustyfit_csrf=real")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn processed_download_can_be_retrieved() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+
+        let download_id = state.insert_download(vec![1, 2, 3]).await;
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(state.signer.download_url(&download_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let collected = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(collected.as_ref(), &[1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn downloads_are_gzipped_when_accepted_or_requested() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+        let download_id = state.insert_download(vec![1, 2, 3]).await;
+        let url = state.signer.download_url(&download_id);
+
+        let negotiated = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(&url)
+                    .header(header::ACCEPT_ENCODING, "br;q=1.0, gzip;q=0.8")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(negotiated.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(negotiated.headers()[header::ACCEPT_RANGES], "none");
+        let body = negotiated.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..2], &[0x1f, 0x8b]);
+
+        let requested = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("{url}&compression=zip"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(requested.status(), StatusCode::OK);
+        assert!(requested.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(
+            requested.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"processed.zip\""
+        );
+        let body = requested.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..4], b"PK\x03\x04");
+    }
+
+    /// Process `fit` as a fresh upload, skipping the result cache.
+    async fn process_fresh(
+        state: &AppState,
+        fit: &[u8],
+        options: ProcessingOptions,
+    ) -> (ProcessedFit, String) {
+        let upload = UploadContext {
+            source: JobSource::Upload,
+            bypass_cache: true,
+            account: None,
+            observer: &NoObserver,
+        };
+        process_upload(state, "ride.fit", fit, &options, &upload)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn record_edits_rewrite_the_download() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+        let fit = std::fs::read("test/fixtures/activity.fit").unwrap();
+        let (processed, download_url) =
+            process_fresh(&state, &fit, ProcessingOptions::default()).await;
+        let (path, signature) = download_url.split_once('?').unwrap();
+        let results = path.replace("/download/", "/api/v1/results/") + "/records";
+        let json = |response: Response| async move {
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+
+        let search = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("{results}?{signature}&mesg=Record&limit=1"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let record = json(search).await["records"][0].clone();
+        let index = record["index"].as_u64().unwrap();
+        let field = record["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|field| field["name"] != "timestamp" && field["numeric"].is_number())
+            .unwrap()["name"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let patch = |body: String| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method("PATCH")
+                    .uri(format!("{results}/{index}?{signature}"))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+
+        let edited = patch(format!(r#"{{"fields": {{"{field}": 1}}}}"#))
+            .await
+            .unwrap();
+        assert_eq!(edited.status(), StatusCode::OK);
+        let edited = json(edited).await;
+        let value = edited["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|edited| edited["name"] == field.as_str())
+            .unwrap()["numeric"]
+            .clone();
+        assert_eq!(value, 1.0);
+        let download = state
+            .downloads
+            .get(&path["/download/".len()..])
+            .await
+            .unwrap();
+        assert_ne!(*download.bytes, processed.processed_bytes);
+
+        let missing = patch(r#"{"fields": {"no_such_field": 1}}"#.to_string())
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn stored_results_export_as_a_pdf_report() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+        let fit = std::fs::read("test/fixtures/activity.fit").unwrap();
+        let (_, download_url) = process_fresh(&state, &fit, ProcessingOptions::default()).await;
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(download_url.replacen("/download/", "/export/pdf/", 1))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/pdf");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.starts_with(b"%PDF-"));
+        assert!(body.ends_with(b"%%EOF\n"));
+    }
+
+    #[tokio::test]
+    async fn preserved_originals_download_bit_identical() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+        let fit = std::fs::read("test/fixtures/activity.fit").unwrap();
+        let options = ProcessingOptions {
+            preserve_original: true,
+            ..ProcessingOptions::default()
+        };
+        let (processed, download_url) = process_fresh(&state, &fit, options).await;
+        assert_eq!(content_hash(&processed.processed_bytes), content_hash(&fit));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(download_url)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(content_hash(&body), content_hash(&fit));
+    }
+
+    #[tokio::test]
+    async fn originals_download_beside_the_processed_file() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+        let fit = std::fs::read("test/fixtures/activity.fit").unwrap();
+        let (processed, download_url) = process_fresh(
+            &state,
+            &fit,
+            ProcessingOptions {
+                smooth_speed: true,
+                ..ProcessingOptions::default()
+            },
+        )
+        .await;
+
+        for (file, expected) in [
+            ("original", &fit),
+            ("processed", &processed.processed_bytes),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(download_url.replacen('?', &format!("/{file}?"), 1))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers()[header::CONTENT_DISPOSITION],
+                format!("attachment; filename=\"{file}.fit\"")
+            );
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(&body[..], &expected[..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_export_simplified_to_the_requested_tolerance() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+        let fit = std::fs::read("test/fixtures/activity.fit").unwrap();
+        let (_, download_url) = process_fresh(&state, &fit, ProcessingOptions::default()).await;
+        let get = |uri: String| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+
+        let response = get(download_url.replacen("/download/", "/export/gpx/", 1))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/gpx+xml"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let full = String::from_utf8_lossy(&body).matches("<trkpt ").count();
+        assert!(full > 2);
+
+        let geojson_url = download_url.replacen("/download/", "/export/geojson/", 1);
+        let response = get(format!("{geojson_url}&tolerance_meters=1000"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let route: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            route["geometry"]["coordinates"].as_array().unwrap().len(),
+            2
+        );
+
+        let response = get(format!("{geojson_url}&tolerance_meters=-1"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn unsigned_or_tampered_download_links_are_rejected() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+        let download_id = state.insert_download(vec![1, 2, 3]).await;
+        let tampered = state
+            .signer
+            .download_url(&download_id)
+            .replace(&download_id, &uuid::Uuid::new_v4().to_string());
+
+        for uri in [
+            format!("/download/{download_id}"),
+            format!("/download/{download_id}/original"),
+            format!("/download/{download_id}/w-prime-balance.csv"),
+            format!("/download/{download_id}/derived-fields.json"),
+            format!("/export/pdf/{download_id}"),
+            format!("/export/gpx/{download_id}"),
+            format!("/api/v1/results/{download_id}/records?mesg=Record"),
+            format!("/api/v1/results/{download_id}/records/0/raw"),
+            tampered,
+        ] {
+            let response = app
+                .clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[tokio::test]
+    async fn download_supports_ranges_and_etags() {
+        let state = AppState::default();
+        let app = router_with_state(state.clone());
+        let download_id = state.insert_download(vec![1, 2, 3, 4, 5]).await;
+        let uri = state.signer.download_url(&download_id);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(&uri)
+                    .header(header::RANGE, "bytes=1-2")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 1-2/5");
+        let etag = response.headers()[header::ETAG].clone();
+        let collected = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(collected.as_ref(), &[2, 3]);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("HEAD")
+                    .uri(&uri)
+                    .header(header::IF_NONE_MATCH, etag)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn weekly_report_renders_stored_history() {
+        let state = AppState::default();
+        state
+            .history
+            .record(
+                "morning.fit".into(),
+                crate::processing::WorkoutSummary {
+                    start_timestamp: Some(1_704_067_200.0),
+                    distance_meters: Some(5000.0),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await;
+        let app = router_with_state(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/reports/weekly?format=csv")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let collected = response.into_body().collect().await.unwrap().to_bytes();
+        let csv = String::from_utf8(collected.to_vec()).unwrap();
+        assert!(csv.contains("2024-01-01,1,5000.0"));
+    }
+
+    #[tokio::test]
+    async fn uploaded_tracks_build_the_heatmap_in_the_background() {
+        let state = AppState {
+            admin_token: Some(AdminToken::new("s3cret")),
+            ..AppState::default()
+        };
+        let app = router_with_state(state.clone());
+        let fit = std::fs::read("test/fixtures/activity.fit").unwrap();
+        process_fresh(&state, &fit, ProcessingOptions::default()).await;
+
+        for _ in 0..100 {
+            if state.heatmap.layer(None).current().heatmap.activities > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let anonymous = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/heatmap")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/heatmap")
+                    .header(header::AUTHORIZATION, "Bearer s3cret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/geo+json"
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let layer: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(layer["activities"], 1);
+        assert!(!layer["features"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn curves_api_combines_stored_activities() {
+        use crate::processing::metrics::{CurvePoint, MeanMaxCurve};
+
+        let state = AppState::default();
+        for watts in [300.0, 250.0] {
+            let curve = MeanMaxCurve {
+                power: vec![CurvePoint {
+                    duration_seconds: 60,
+                    value: watts,
+                }],
+                speed: Vec::new(),
+            };
+            state
+                .history
+                .record(
+                    format!("{watts}.fit"),
+                    WorkoutSummary {
+                        mean_max: Some(curve),
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .await;
+        }
+        let app = router_with_state(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v1/curves")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let collected = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&collected).unwrap();
+        assert_eq!(json["activities"].as_array().unwrap().len(), 2);
+        assert_eq!(json["aggregate"]["power"][0]["value"], 300.0);
+    }
+
+    #[tokio::test]
+    async fn gear_api_totals_tagged_activities() {
+        let state = AppState::default();
+        let activities = [
+            (Some("Trail shoes"), 8000.0),
+            (None, 3000.0),
+            (Some("Trail shoes"), 12000.0),
+        ];
+        for (gear, meters) in activities {
+            state
+                .history
+                .record(
+                    "run.fit".into(),
+                    WorkoutSummary {
+                        distance_meters: Some(meters),
+                        ..Default::default()
+                    },
+                    gear.map(str::to_string),
+                )
+                .await;
+        }
+        let app = router_with_state(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/v1/gear")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let collected = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&collected).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["gear"], "Trail shoes");
+        assert_eq!(json[0]["activities"], 2);
+        assert_eq!(json[0]["distance_meters"], 20000.0);
+    }
+
+    #[tokio::test]
+    async fn process_url_refuses_internal_addresses() {
+        let app = build_app();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/process-url")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(
+                        r#"{"url": "https://127.0.0.1/ride.fit", "options": {"smooth_speed": true}}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let collected = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&collected).contains("non-public address"));
+    }
+
+    #[tokio::test]
+    async fn diff_without_both_files_is_rejected() {
+        let app = build_app();
+        let req = Request::builder()
+            .method("POST")
+            .uri("/api/v1/diff")
+            .header("content-type", "multipart/form-data; boundary=--boundary")
+            .body(Body::from("----boundary--"))
+            .unwrap();
+
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn preview_for_unknown_result_is_not_found() {
+        let app = build_app();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/v1/results/missing/preview?window=7")
+                    .header(header::COOKIE, "rustyfit_csrf=test-token")
+                    .header("x-csrf-token", "test-token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn wasm_asset_rejects_other_files() {
+        let app = build_app();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/pkg/secret.txt")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn fingerprinted_assets_are_cached_and_pages_carry_csp() {
+        let app = build_app();
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(
+            response.headers()[header::CONTENT_SECURITY_POLICY]
+                .to_str()
+                .unwrap()
+                .contains("script-src 'self'")
+        );
+        assert_eq!(
+            response.headers()[header::X_CONTENT_TYPE_OPTIONS],
+            "nosniff"
+        );
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(crate::assets::asset_url("app.css"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=31536000, immutable"
+        );
+    }
+
+    #[tokio::test]
+    async fn admin_dashboard_requires_token() {
+        let state = AppState {
+            admin_token: Some(AdminToken::new("s3cret")),
+            ..AppState::default()
+        };
+        let app = router_with_state(state.clone());
+        state.insert_download(vec![0; 2048]).await;
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/admin")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/admin")
+                    .header(header::AUTHORIZATION, "Bearer s3cret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("2.0 KiB"));
+    }
+
+    #[test]
+    fn record_limit_maps_to_payload_too_large() {
+        let error = FitProcessError::LimitExceeded(ExceededLimit::Records {
+            limit: 10,
+            found: 11,
+        });
+
+        let response = render_processing_error(error, Some("huge.fit"));
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::downloads::DOWNLOAD_TTL;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Default lifetime of a signed download link.
+pub const DEFAULT_DOWNLOAD_URL_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Context string separating download-link keys from any other use of the secret.
+const KEY_CONTEXT: &str = "rustyfit 2024 signed download url";
+
+/// Why a download link was rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    Missing,
+    Invalid,
+    Expired,
+}
+
+/// Signs download paths with an expiry so links can be shared for a limited time.
+///
+/// Signatures are keyed BLAKE3 MACs over the download ID and expiry.
+#[derive(Clone)]
+pub struct UrlSigner {
+    key: [u8; 32],
+    ttl: Duration,
+}
+
+impl Default for UrlSigner {
+    /// A signer with a random per-process key; links do not survive restarts.
+    fn default() -> Self {
+        let mut seed = Vec::with_capacity(32);
+        seed.extend_from_slice(Uuid::new_v4().as_bytes());
+        seed.extend_from_slice(Uuid::new_v4().as_bytes());
+        Self::new(&seed, DEFAULT_DOWNLOAD_URL_TTL)
+    }
+}
+
+impl UrlSigner {
+    /// A signer whose links last `ttl`, at most as long as the download
+    /// store keeps the file.
+    pub fn new(secret: &[u8], ttl: Duration) -> Self {
+        Self {
+            key: blake3::derive_key(KEY_CONTEXT, secret),
+            ttl: ttl.min(DOWNLOAD_TTL),
+        }
+    }
+
+    /// Key from `RUSTYFIT_DOWNLOAD_SECRET` (random when unset) and lifetime
+    /// from `RUSTYFIT_DOWNLOAD_URL_TTL_SECONDS`.
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("RUSTYFIT_DOWNLOAD_URL_TTL_SECONDS")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_DOWNLOAD_URL_TTL);
+        match std::env::var("RUSTYFIT_DOWNLOAD_SECRET") {
+            Ok(secret) if !secret.is_empty() => Self::new(secret.as_bytes(), ttl),
+            _ => Self {
+                ttl: ttl.min(DOWNLOAD_TTL),
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Signed `/download/:id` path that expires after the configured lifetime.
+    pub fn download_url(&self, id: &str) -> String {
+        let expires = unix_now().saturating_add(self.ttl.as_secs());
+        format!(
+            "/download/{id}?expires={expires}&signature={}",
+            self.signature(id, expires).to_hex()
+        )
+    }
+
+    /// Check a link's signature and expiry.
+    pub fn verify(
+        &self,
+        id: &str,
+        expires: Option<u64>,
+        signature: Option<&str>,
+    ) -> Result<(), SignatureError> {
+        let (Some(expires), Some(signature)) = (expires, signature) else {
+            return Err(SignatureError::Missing);
+        };
+        let signature = blake3::Hash::from_hex(signature).map_err(|_| SignatureError::Invalid)?;
+        // `blake3::Hash` equality is constant time.
+        if signature != self.signature(id, expires) {
+            return Err(SignatureError::Invalid);
+        }
+        if expires < unix_now() {
+            return Err(SignatureError::Expired);
+        }
+        Ok(())
+    }
+
+    fn signature(&self, id: &str, expires: u64) -> blake3::Hash {
+        let mut mac = blake3::Hasher::new_keyed(&self.key);
+        mac.update(id.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac.finalize()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_value<'a>(url: &'a str, name: &str) -> &'a str {
+        url.split(['?', '&'])
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            .expect("query parameter present")
+    }
+
+    #[test]
+    fn signed_url_verifies_only_for_its_id_and_key() {
+        let signer = UrlSigner::new(b"secret", DEFAULT_DOWNLOAD_URL_TTL);
+        let url = signer.download_url("abc");
+        let expires = query_value(&url, "expires").parse().ok();
+        let signature = Some(query_value(&url, "signature"));
+
+        assert_eq!(signer.verify("abc", expires, signature), Ok(()));
+        assert_eq!(
+            signer.verify("abd", expires, signature),
+            Err(SignatureError::Invalid)
+        );
+        assert_eq!(
+            UrlSigner::new(b"other", DEFAULT_DOWNLOAD_URL_TTL).verify("abc", expires, signature),
+            Err(SignatureError::Invalid)
+        );
+        assert_eq!(
+            signer.verify("abc", None, signature),
+            Err(SignatureError::Missing)
+        );
+    }
+
+    #[test]
+    fn links_do_not_outlive_the_download() {
+        let signer = UrlSigner::new(b"secret", Duration::from_secs(u64::MAX));
+        let url = signer.download_url("abc");
+        let expires: u64 = query_value(&url, "expires").parse().unwrap();
+
+        assert!(expires <= unix_now() + DOWNLOAD_TTL.as_secs());
+    }
+
+    #[test]
+    fn expired_links_are_rejected() {
+        let signer = UrlSigner::new(b"secret", DEFAULT_DOWNLOAD_URL_TTL);
+        let expires = unix_now() - 10;
+        let signature = signer.signature("abc", expires).to_hex();
+
+        assert_eq!(
+            signer.verify("abc", Some(expires), Some(signature.as_str())),
+            Err(SignatureError::Expired)
+        );
+    }
+}
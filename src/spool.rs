@@ -0,0 +1,176 @@
+//! Uploaded files too large to buffer, spooled to disk and parsed through a
+//! memory map.
+//!
+//! A file field is buffered in memory until it reaches
+//! [`SPOOL_THRESHOLD_BYTES`]; the rest is streamed to a temporary file that
+//! is then mapped and handed to the parser as `&[u8]`, so pages are loaded as
+//! the decoder reaches them and nothing is copied until the decoded records
+//! are rewritten.
+
+use axum::body::Bytes;
+use futures_util::{Stream, StreamExt};
+use memmap2::Mmap;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::pin::Pin;
+use tokio::io::AsyncWriteExt;
+
+/// Uploaded files at least this large are spooled to disk.
+pub const SPOOL_THRESHOLD_BYTES: usize = 8 * 1024 * 1024;
+
+/// Largest request body accepted by the multipart upload routes.
+pub const MAX_UPLOAD_BYTES: usize = 256 * 1024 * 1024;
+
+/// Contents of one uploaded file, in memory or spooled depending on its size.
+pub enum UploadBytes {
+    Memory(Vec<u8>),
+    Spooled(SpooledFile),
+}
+
+impl UploadBytes {
+    pub fn is_spooled(&self) -> bool {
+        matches!(self, Self::Spooled(_))
+    }
+}
+
+impl Deref for UploadBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Memory(bytes) => bytes,
+            Self::Spooled(file) => file,
+        }
+    }
+}
+
+/// A temporary file holding an upload, mapped read-only and deleted on drop.
+pub struct SpooledFile {
+    path: PathBuf,
+    /// `None` until the whole upload is written.
+    map: Option<Mmap>,
+}
+
+impl Deref for SpooledFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or_default()
+    }
+}
+
+impl Drop for SpooledFile {
+    fn drop(&mut self) {
+        // Unmapped first, since Windows refuses to delete a mapped file.
+        self.map.take();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpoolError {
+    /// The client's upload could not be read.
+    #[error("Failed to read uploaded file: {0}")]
+    Read(String),
+    #[error("Failed to spool uploaded file: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Collect an uploaded file from `chunks`, spooling it to disk once it
+/// reaches `threshold` bytes.
+pub async fn spool<S, E>(chunks: S, threshold: usize) -> Result<UploadBytes, SpoolError>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: fmt::Display,
+{
+    let mut chunks = std::pin::pin!(chunks);
+    let mut buffered = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|err| SpoolError::Read(err.to_string()))?;
+        buffered.extend_from_slice(&chunk);
+        if buffered.len() >= threshold {
+            let file = spool_to_disk(buffered, chunks).await?;
+            return Ok(UploadBytes::Spooled(file));
+        }
+    }
+    Ok(UploadBytes::Memory(buffered))
+}
+
+/// Write `head` and the rest of `chunks` to a new temporary file and map it.
+async fn spool_to_disk<S, E>(
+    head: Vec<u8>,
+    mut chunks: Pin<&mut S>,
+) -> Result<SpooledFile, SpoolError>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: fmt::Display,
+{
+    let path = std::env::temp_dir().join(format!("rustyfit-upload-{}.fit", uuid::Uuid::new_v4()));
+    let mut file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .await?;
+    // Deleted again however spooling ends.
+    let mut spooled = SpooledFile { path, map: None };
+    file.write_all(&head).await?;
+    drop(head);
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|err| SpoolError::Read(err.to_string()))?;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    let file = file.into_std().await;
+    // SAFETY: the file was created here under a random name and is only read
+    // from now on, so nothing truncates or rewrites it while it is mapped.
+    spooled.map = Some(unsafe { Mmap::map(&file)? });
+    Ok(spooled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::parse_fit;
+    use futures_util::stream;
+
+    fn chunked(bytes: &[u8]) -> impl Stream<Item = Result<Bytes, io::Error>> {
+        let chunks: Vec<_> = bytes
+            .chunks(100)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        stream::iter(chunks)
+    }
+
+    #[tokio::test]
+    async fn large_uploads_are_spooled_and_parse_like_the_original() {
+        let bytes = std::fs::read("test/fixtures/activity.fit").unwrap();
+
+        let upload = spool(chunked(&bytes), 256).await.unwrap();
+
+        let UploadBytes::Spooled(file) = &upload else {
+            panic!("the upload was not spooled");
+        };
+        let path = file.path.clone();
+        assert!(path.exists());
+        assert_eq!(&upload[..], &bytes[..]);
+        assert_eq!(
+            parse_fit(&upload).unwrap().len(),
+            parse_fit(&bytes).unwrap().len()
+        );
+        drop(upload);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn small_uploads_stay_in_memory() {
+        let bytes = std::fs::read("test/fixtures/activity.fit").unwrap();
+
+        let upload = spool(chunked(&bytes), SPOOL_THRESHOLD_BYTES).await.unwrap();
+
+        assert!(!upload.is_spooled());
+        assert_eq!(&upload[..], &bytes[..]);
+    }
+}
@@ -0,0 +1,70 @@
+//! Per-file processing statistics, emitted as one structured tracing event so
+//! operators can analyse real-world workloads.
+
+use crate::config::ProcessingLogDetail;
+use crate::processing::diff::message_counts;
+use crate::processing::{ProcessedFit, ProcessingOptions};
+use fitparser::FitDataRecord;
+use std::time::Duration;
+
+/// Tracing target of the statistics event, for filtering it separately.
+pub const PROCESSING_LOG_TARGET: &str = "rustyfit::processing";
+
+/// What is known about one processed upload.
+pub struct FileStats<'a> {
+    pub file_name: &'a str,
+    /// Records decoded from the upload, before preprocessing.
+    pub records: &'a [FitDataRecord],
+    pub options: &'a ProcessingOptions,
+    pub processed: &'a ProcessedFit,
+    pub input_bytes: usize,
+    /// Whether the result came from the upload cache, in which case no stage
+    /// ran and every duration is zero.
+    pub cache_hit: bool,
+}
+
+impl FileStats<'_> {
+    /// Emit the statistics event at the given detail level.
+    pub fn log(&self, detail: ProcessingLogDetail) {
+        let timings = &self.processed.timings;
+        let output_bytes = self.processed.processed_bytes.len();
+        let size_delta = output_bytes as i64 - self.input_bytes as i64;
+        match detail {
+            ProcessingLogDetail::Off => {}
+            ProcessingLogDetail::Summary => tracing::info!(
+                target: PROCESSING_LOG_TARGET,
+                file_name = self.file_name,
+                record_count = self.records.len(),
+                input_bytes = self.input_bytes,
+                output_bytes,
+                size_delta,
+                cache_hit = self.cache_hit,
+                total_ms = millis(timings.total()),
+                "processed file"
+            ),
+            ProcessingLogDetail::Full => tracing::info!(
+                target: PROCESSING_LOG_TARGET,
+                file_name = self.file_name,
+                record_count = self.records.len(),
+                input_bytes = self.input_bytes,
+                output_bytes,
+                size_delta,
+                cache_hit = self.cache_hit,
+                total_ms = millis(timings.total()),
+                message_types = ?message_counts(self.records),
+                options = ?self.options,
+                warnings = self.processed.warnings.len(),
+                parse_ms = millis(timings.parse),
+                preprocess_ms = millis(timings.preprocess),
+                encode_ms = millis(timings.encode),
+                derive_ms = millis(timings.derive),
+                display_ms = millis(timings.display),
+                "processed file"
+            ),
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,181 @@
+//! Opt-in usage counters that help operators of a self-hosted server see
+//! how it is used.
+//!
+//! Only totals are kept: files processed, how often each processing option
+//! was set, and failed uploads by error kind. Option values, file names, and
+//! accounts are never recorded. The totals are shown on `/admin` and saved to
+//! a local JSON file after every change, so they survive restarts; they are
+//! never sent anywhere.
+
+use crate::admin::error_kind;
+use crate::processing::{FitProcessError, ProcessingOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Counters shared by every request; only created when the operator enables
+/// them with `--telemetry`.
+#[derive(Debug, Clone, Default)]
+pub struct UsageCounters {
+    totals: Arc<Mutex<UsageTotals>>,
+    /// Where the totals are saved; `None` keeps them in memory.
+    file: Option<Arc<PathBuf>>,
+}
+
+/// Everything counted since counting was enabled.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub files_processed: u64,
+    /// Processed files that set each option, by option name.
+    pub options: BTreeMap<String, u64>,
+    /// Failed uploads by error kind.
+    pub errors: BTreeMap<String, u64>,
+}
+
+#[derive(Debug)]
+pub enum TelemetryError {
+    Io(PathBuf, io::Error),
+    Invalid(PathBuf, serde_json::Error),
+}
+
+impl fmt::Display for TelemetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelemetryError::Io(path, err) => {
+                write!(f, "failed to read usage totals {}: {err}", path.display())
+            }
+            TelemetryError::Invalid(path, err) => {
+                write!(f, "invalid usage totals file {}: {err}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TelemetryError {}
+
+impl UsageCounters {
+    /// Counters continuing from the totals saved in `file`, which is created
+    /// on the first change if it does not exist yet.
+    pub fn open(file: PathBuf) -> Result<Self, TelemetryError> {
+        let totals = match std::fs::read_to_string(&file) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|err| TelemetryError::Invalid(file.clone(), err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => UsageTotals::default(),
+            Err(err) => return Err(TelemetryError::Io(file, err)),
+        };
+        Ok(Self {
+            totals: Arc::new(Mutex::new(totals)),
+            file: Some(Arc::new(file)),
+        })
+    }
+
+    pub async fn record_file(&self, options: &ProcessingOptions) {
+        let mut totals = self.totals.lock().await;
+        totals.files_processed += 1;
+        for name in options.names_in_use() {
+            *totals.options.entry(name.to_string()).or_default() += 1;
+        }
+        self.save(&totals).await;
+    }
+
+    pub async fn record_error(&self, error: &FitProcessError) {
+        let mut totals = self.totals.lock().await;
+        *totals
+            .errors
+            .entry(error_kind(error).to_string())
+            .or_default() += 1;
+        self.save(&totals).await;
+    }
+
+    /// Write `totals` to the file, while the caller still holds the lock so
+    /// saves land in order.
+    async fn save(&self, totals: &UsageTotals) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let json = serde_json::to_vec_pretty(totals).expect("usage totals serialize");
+        // Written beside the file and renamed over it, so a crash mid-write
+        // never leaves a truncated file that would fail the next start.
+        let mut partial = file.as_os_str().to_owned();
+        partial.push(".partial");
+        let saved = async {
+            tokio::fs::write(&partial, json).await?;
+            tokio::fs::rename(&partial, file.as_path()).await
+        };
+        if let Err(err) = saved.await {
+            tracing::warn!(error = %err, path = %file.display(), "failed to save usage totals");
+        }
+    }
+
+    pub async fn snapshot(&self) -> UsageTotals {
+        self.totals.lock().await.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_option_names_are_counted() {
+        let counters = UsageCounters::default();
+        let options = ProcessingOptions {
+            smooth_speed: true,
+            title: Some("Morning ride with Sam".to_string()),
+            ..ProcessingOptions::default()
+        };
+        counters.record_file(&options).await;
+        counters
+            .record_file(&ProcessingOptions {
+                smooth_speed: true,
+                ..ProcessingOptions::default()
+            })
+            .await;
+        counters
+            .record_error(&FitProcessError::ParseError("bad header".into()))
+            .await;
+
+        let totals = counters.snapshot().await;
+        assert_eq!(totals.files_processed, 2);
+        assert_eq!(
+            totals.options.into_iter().collect::<Vec<_>>(),
+            vec![("smooth_speed".to_string(), 2), ("title".to_string(), 1)]
+        );
+        assert_eq!(
+            totals.errors.into_iter().collect::<Vec<_>>(),
+            vec![("parse_error".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn totals_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("rustyfit-usage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("usage.json");
+        let _ = std::fs::remove_file(&file);
+
+        let counters = UsageCounters::open(file.clone()).unwrap();
+        counters.record_file(&ProcessingOptions::default()).await;
+        counters
+            .record_error(&FitProcessError::ParseError("bad header".into()))
+            .await;
+        drop(counters);
+
+        let restarted = UsageCounters::open(file.clone()).unwrap();
+        restarted.record_file(&ProcessingOptions::default()).await;
+        let totals = restarted.snapshot().await;
+        assert_eq!(totals.files_processed, 2);
+        assert_eq!(totals.errors["parse_error"], 1);
+
+        std::fs::write(&file, "not json").unwrap();
+        assert!(matches!(
+            UsageCounters::open(file),
+            Err(TelemetryError::Invalid(..))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
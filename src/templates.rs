@@ -1,6 +1,40 @@
-use crate::processing::ProcessedFit;
+use crate::accounts::AccountUsage;
+use crate::admin::AdminOverview;
+use crate::assets::asset_url;
+use crate::charts::render_series_charts;
+use crate::history::CurveHistory;
+use crate::locale::NumberLocale;
+use crate::processing::aggregate::{AggregateSummary, ReportPeriod};
+use crate::processing::annotations::{AnnotationKind, Annotations};
+use crate::processing::build::MANUAL_SPORTS;
+use crate::processing::conditions::ConditionsAdjustment;
+use crate::processing::diff::FitDiff;
+use crate::processing::ebike::EbikeSummary;
+use crate::processing::expressions::DerivedFieldSummary;
+use crate::processing::metrics::{
+    CURVE_DURATIONS_SECONDS, CurvePoint, MeanMaxCurve, WPrimeBalance,
+};
+use crate::processing::preview::MAX_PREVIEW_WINDOW;
+use crate::processing::report::TrainingReport;
+use crate::processing::ski::SkiRuns;
+use crate::processing::spikes::{MAX_SPEED_SPIKES, SpeedSpike, SpikeCause};
+use crate::processing::strength::StrengthSets;
+use crate::processing::structure::FileStructure;
+use crate::processing::terrain::{Surface, TerrainAnalysis, grade_bucket};
+use crate::processing::threshold::ThresholdEstimate;
+use crate::processing::trainer::{TrainerMode, VirtualRide};
+use crate::processing::types::SPEED_SMOOTHING_WINDOW;
+use crate::processing::wind::WindAdjustment;
+use crate::processing::{CyclingDynamics, ProcessedFit, Weather};
 
-fn format_duration(seconds: Option<f64>) -> String {
+pub(crate) fn escape_html(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub(crate) fn format_duration(seconds: Option<f64>) -> String {
     match seconds {
         Some(total) => {
             let rounded = total.round().max(0.0) as u64;
@@ -18,15 +52,50 @@ fn format_duration(seconds: Option<f64>) -> String {
     }
 }
 
-fn format_distance(meters: Option<f64>) -> String {
+pub(crate) fn format_distance(meters: Option<f64>, locale: NumberLocale) -> String {
     match meters {
-        Some(distance) if distance >= 1000.0 => format!("{:.2} km", distance / 1000.0),
-        Some(distance) => format!("{:.0} m", distance),
+        Some(distance) if distance >= 1000.0 => {
+            format!("{} km", locale.number(distance / 1000.0, 2))
+        }
+        Some(distance) => format!("{} m", locale.number(distance, 0)),
         None => "—".to_string(),
     }
 }
 
-fn format_speed(speed: Option<f64>) -> String {
+fn format_timestamp(timestamp: Option<f64>) -> String {
+    timestamp
+        .filter(|ts| ts.is_finite())
+        .and_then(|ts| chrono::DateTime::from_timestamp(ts.floor() as i64, 0))
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| "—".to_string())
+}
+
+/// Format a Unix timestamp in the activity's local time, labelled with its UTC offset.
+pub(crate) fn format_local_time(timestamp: Option<f64>, utc_offset_seconds: Option<i32>) -> String {
+    let Some(offset_seconds) = utc_offset_seconds else {
+        return format_timestamp(timestamp);
+    };
+    let offset = chrono::FixedOffset::east_opt(offset_seconds);
+    timestamp
+        .filter(|ts| ts.is_finite())
+        .and_then(|ts| chrono::DateTime::from_timestamp(ts.floor() as i64, 0))
+        .zip(offset)
+        .map(|(dt, offset)| {
+            dt.with_timezone(&offset)
+                .format("%Y-%m-%d %H:%M (UTC%:z)")
+                .to_string()
+        })
+        .unwrap_or_else(|| "—".to_string())
+}
+
+pub(crate) fn format_elevation(meters: Option<f64>, locale: NumberLocale) -> String {
+    match meters {
+        Some(gain) if gain.is_finite() => format!("{} m", locale.number(gain, 0)),
+        _ => "—".to_string(),
+    }
+}
+
+pub(crate) fn format_speed(speed: Option<f64>) -> String {
     match speed {
         Some(value) if value > 0.0 => {
             let total_minutes = 1000.0 / (value * 60.0);
@@ -46,105 +115,1722 @@ fn format_speed(speed: Option<f64>) -> String {
     }
 }
 
-fn format_heart_rate(value: Option<f64>) -> String {
+pub(crate) fn format_heart_rate(value: Option<f64>) -> String {
     match value {
         Some(hr) if hr.is_finite() && hr > 0.0 => format!("{:.0} bpm", hr.round()),
         _ => "—".to_string(),
     }
 }
 
+fn format_percent(value: Option<f64>) -> String {
+    match value {
+        Some(percent) if percent.is_finite() => format!("{percent:.0}%"),
+        _ => "—".to_string(),
+    }
+}
+
 pub fn render_landing_page() -> String {
-    include_str!("../templates/landing.html").to_string()
+    include_str!("../templates/landing.html")
+        .replace("{{stylesheet}}", &asset_url("app.css"))
+        .replace("{{script}}", &asset_url("landing.js"))
 }
 
-pub fn render_processed_records(processed: &ProcessedFit, download_url: &str) -> String {
-    let mut body = String::new();
+/// Wrap a body fragment in the shared standalone page shell.
+pub fn render_page(title: &str, body: &str) -> String {
+    include_str!("../templates/page.html")
+        .replace("{{stylesheet}}", &asset_url("app.css"))
+        .replace("{{title}}", &escape_html(title))
+        .replace("{{body}}", body)
+}
 
+/// Render one processed file's results, with numbers written for `locale`.
+///
+/// Each section is a region labelled by its eyebrow, so screen reader users
+/// can list and jump between them; sections a file has no data for are left
+/// out.
+pub fn render_processed_records(
+    processed: &ProcessedFit,
+    download_url: &str,
+    locale: NumberLocale,
+) -> String {
     let summary = &processed.summary;
-    let (min_speed, mean_speed, max_speed) = (
-        format_speed(summary.speed_min),
-        format_speed(summary.speed_mean),
-        format_speed(summary.speed_max),
-    );
-    let (min_hr, mean_hr, max_hr) = (
-        format_heart_rate(summary.heart_rate_min),
-        format_heart_rate(summary.heart_rate_mean),
-        format_heart_rate(summary.heart_rate_max),
-    );
+    let sections = [
+        Some(render_overview(processed, download_url, locale)),
+        summary
+            .ski_runs
+            .as_ref()
+            .map(|runs| render_ski_runs(runs, summary.utc_offset_seconds, locale)),
+        summary
+            .strength_sets
+            .as_ref()
+            .map(|strength| render_strength_sets(strength, locale)),
+        (processed.annotations.timeline.len() >= 2)
+            .then(|| render_annotated_timeline(&processed.annotations)),
+        render_series_charts(&processed.charts, locale)
+            .map(|charts| results_section("Charts", "Speed, heart rate, and elevation", &charts)),
+        (processed.charts.speed.len() >= 2).then(|| render_smoothing_preview(download_url)),
+        summary
+            .cycling_dynamics
+            .as_ref()
+            .map(render_cycling_dynamics),
+        summary
+            .conditions
+            .as_ref()
+            .map(|conditions| render_conditions(conditions, locale)),
+        summary
+            .weather
+            .as_ref()
+            .map(|weather| render_weather(weather, summary.utc_offset_seconds)),
+        summary
+            .wind
+            .as_ref()
+            .map(|wind| render_wind(wind, summary.utc_offset_seconds, locale)),
+        summary
+            .terrain
+            .as_ref()
+            .map(|terrain| render_terrain(terrain, locale)),
+        summary
+            .ebike
+            .as_ref()
+            .map(|ebike| render_ebike(ebike, locale)),
+        summary
+            .virtual_ride
+            .as_ref()
+            .map(|ride| render_virtual_ride(ride, summary.utc_offset_seconds)),
+        summary.thresholds.as_ref().map(render_threshold_estimate),
+        summary
+            .w_prime_balance
+            .as_ref()
+            .map(|balance| render_w_prime_balance(balance, download_url)),
+        (!summary.derived_fields.is_empty())
+            .then(|| render_derived_fields(&summary.derived_fields, download_url)),
+        summary.mean_max.as_ref().map(|curve| {
+            results_section(
+                "Mean-Max Curves",
+                "Best efforts by duration",
+                &render_mean_max_charts(curve),
+            )
+        }),
+        (!processed.speed_spikes.is_empty())
+            .then(|| render_speed_spikes(&processed.speed_spikes, summary.utc_offset_seconds)),
+        (!processed.warnings.is_empty()).then(|| render_warnings(processed)),
+        (!processed.devices.is_empty()).then(|| render_device_inventory(processed)),
+        (!processed.structure.messages.is_empty())
+            .then(|| render_file_structure(&processed.structure)),
+        Some(render_record_table(processed, download_url, locale)),
+    ];
+    sections.into_iter().flatten().collect()
+}
+
+/// A results card whose region is labelled by `eyebrow`.
+fn results_section(eyebrow: &str, heading: &str, content: &str) -> String {
+    format!(
+        "<section class=\"results-card\" aria-label=\"{eyebrow}\"><div class=\"results-header\"><div><p class=\"eyebrow\">{eyebrow}</p><h2>{heading}</h2></div></div>{content}</section>"
+    )
+}
+
+/// One labelled value of a summary grid, announced as a named group.
+fn summary_card(label: &str, value: &str) -> String {
+    format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"{label}\"><p class=\"label\">{label}</p><p class=\"value\">{value}</p></div>"
+    )
+}
+
+/// Opening of a table, through its header row, whose `caption` names it for
+/// assistive technology.
+fn table_head(caption: &str, columns: &[&str]) -> String {
+    let headers: String = columns
+        .iter()
+        .map(|column| format!("<th scope=\"col\">{column}</th>"))
+        .collect();
+    format!(
+        "<div class=\"table-wrapper\"><table><caption class=\"visually-hidden\">{caption}</caption><thead><tr>{headers}</tr></thead>"
+    )
+}
+
+fn render_overview(processed: &ProcessedFit, download_url: &str, locale: NumberLocale) -> String {
+    let summary = &processed.summary;
+    let report_url = download_url.replacen("/download/", "/export/pdf/", 1);
+    let original_url = download_url.replacen('?', "/original?", 1);
+    // Routes are simplified to 2 m, which is invisible on a map.
+    let route_links = if processed.track.is_empty() {
+        String::new()
+    } else {
+        let gpx_url = download_url.replacen("/download/", "/export/gpx/", 1);
+        let geojson_url = download_url.replacen("/download/", "/export/geojson/", 1);
+        format!(
+            "<p class=\"download-variants\">Route as <a download=route.gpx href={gpx_url}&tolerance_meters=2>GPX</a> \
+             &middot; <a download=route.geojson href={geojson_url}&tolerance_meters=2>GeoJSON</a></p>"
+        )
+    };
+    let mut body = String::new();
 
-    body.push_str("<section class=\"results-card\">");
+    body.push_str("<section class=\"results-card\" aria-label=\"Workout Overview\">");
     body.push_str(
         "<div class=\"results-header\"><div><p class=\"eyebrow\">Workout Overview</p><h2>Freshly parsed FIT file</h2></div>",
     );
     body.push_str(&format!(
-        "<a class=\"cta\" download=processed.fit href={download_url}>Download processed FIT</a>"
+        "<div class=\"download-links\"><a class=\"cta\" download=processed.fit href={download_url}>Download processed FIT</a>\
+         <p class=\"download-variants\">or as <a download=processed.fit.gz href={download_url}&compression=gzip aria-label=\"Download processed FIT as .fit.gz\">.fit.gz</a> \
+         &middot; <a download=processed.zip href={download_url}&compression=zip aria-label=\"Download processed FIT as .zip\">.zip</a></p>\
+         <p class=\"download-variants\"><a download=workout-report.pdf href={report_url}>Printable report (PDF)</a> \
+         &middot; <a download=original.fit href={original_url}>Original upload</a></p>{route_links}</div>"
     ));
     body.push_str("</div>");
 
+    let workout_type = summary
+        .workout_type
+        .as_deref()
+        .map_or_else(|| "Unknown".to_string(), escape_html);
+    let cards = [
+        (
+            "Start Time",
+            format_local_time(summary.start_timestamp, summary.utc_offset_seconds),
+        ),
+        (
+            "End Time",
+            format_local_time(summary.end_timestamp, summary.utc_offset_seconds),
+        ),
+        (
+            "Workout Duration",
+            format_duration(summary.duration_seconds),
+        ),
+        ("Moving Time", format_duration(summary.moving_time_seconds)),
+        ("Workout Type", workout_type),
+        (
+            "Workout Distance",
+            format_distance(summary.distance_meters, locale),
+        ),
+        (
+            "Elevation Gain",
+            format_elevation(summary.elevation_gain_meters, locale),
+        ),
+        ("Speed (min)", format_speed(summary.speed_min)),
+        ("Speed (mean)", format_speed(summary.speed_mean)),
+        ("Speed (max)", format_speed(summary.speed_max)),
+        (
+            "Heart Rate (min)",
+            format_heart_rate(summary.heart_rate_min),
+        ),
+        (
+            "Heart Rate (mean)",
+            format_heart_rate(summary.heart_rate_mean),
+        ),
+        (
+            "Heart Rate (max)",
+            format_heart_rate(summary.heart_rate_max),
+        ),
+    ];
+    // Trainer apps often record neither speed nor distance; a row of dashes
+    // says nothing the Virtual Ride card doesn't.
+    let hide_speed = summary.virtual_ride.is_some() && summary.speed_max.is_none();
     body.push_str("<div class=\"summary-grid\">");
+    for (label, value) in cards {
+        if hide_speed && (label.starts_with("Speed") || label == "Workout Distance") {
+            continue;
+        }
+        body.push_str(&summary_card(label, &value));
+    }
+    body.push_str("</div>");
+    body.push_str("</section>");
+    body
+}
+
+/// The first records of the file, with the explorer for searching the rest.
+fn render_record_table(
+    processed: &ProcessedFit,
+    download_url: &str,
+    locale: NumberLocale,
+) -> String {
+    let mut body = String::new();
+
+    // The explorer queries the stored result with the download link's signature.
+    let records_url = download_url
+        .replacen("/download/", "/api/v1/results/", 1)
+        .replacen('?', "/records?", 1);
+    body.push_str("<section class=\"results-card\" aria-label=\"Data records\">");
+    let shown = processed.records.len().min(25);
+    // Summary-only uploads skip the preview; the explorer still searches.
+    let heading = if shown == 0 {
+        format!("{} records", processed.record_count)
+    } else {
+        format!(
+            "Showing the first {shown} of {} records",
+            processed.record_count
+        )
+    };
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Workout Duration</p><p class=\"value\">{}</p></div>",
-        format_duration(summary.duration_seconds)
+        "<div class=\"results-header\"><div><p class=\"eyebrow\">Data records</p><h2 class=\"record-count\" aria-live=\"polite\">{heading}</h2></div></div>"
     ));
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Workout Type</p><p class=\"value\">{}</p></div>",
-        summary
-            .workout_type
-            .as_ref()
-            .map(|val| val.clone())
-            .unwrap_or_else(|| "Unknown".into())
+        "<form class=\"record-explorer\" role=\"search\" aria-label=\"Search records\" data-records-url=\"{}\">\
+<label>Message <input name=\"mesg\" placeholder=\"Record\" /></label>\
+<label>Field <input name=\"field\" placeholder=\"heart_rate\" /></label>\
+<label>Min <input name=\"min\" type=\"number\" step=\"any\" /></label>\
+<label>Max <input name=\"max\" type=\"number\" step=\"any\" /></label>\
+<button type=\"submit\">Search</button>\
+<button type=\"button\" class=\"secondary record-more\" hidden>Next page</button></form>",
+        escape_html(&records_url)
+    ));
+    body.push_str(&table_head(
+        "Decoded messages and their fields",
+        &["Message", "Fields"],
+    ));
+    body.push_str("<tbody class=\"record-rows\">");
+
+    for (index, record) in processed.records.iter().take(shown).enumerate() {
+        body.push_str(&format!(
+            "<tr><td>{} <span class=\"label\">(global {})</span></td><td>",
+            record.message_type, record.message_number
+        ));
+        body.push_str("<ul>");
+        for field in &record.fields {
+            body.push_str(&format!(
+                "<li><strong>{}</strong> <span class=\"label\">#{}</span>: {}</li>",
+                field.name,
+                field.number,
+                locale.decimals_in(&field.value)
+            ));
+        }
+        body.push_str("</ul>");
+        body.push_str(&format!(
+            "<details class=\"raw-message\" data-index=\"{index}\"><summary>Raw bytes</summary><pre></pre></details>"
+        ));
+        body.push_str("</td></tr>");
+    }
+
+    body.push_str("</tbody></table></div>");
+    body.push_str("</section>");
+    body
+}
+
+fn render_cycling_dynamics(dynamics: &CyclingDynamics) -> String {
+    let mut body = String::new();
+    let pair = |left: Option<f64>, right: Option<f64>| match (left, right) {
+        (None, None) => "—".to_string(),
+        (left, right) => format!("L {} / R {}", format_percent(left), format_percent(right)),
+    };
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Cycling Dynamics\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Cycling Dynamics</p><h2>Pedalling balance and efficiency</h2></div></div>");
+    body.push_str("<div class=\"summary-grid\">");
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"L/R Balance\"><p class=\"label\">L/R Balance</p><p class=\"value\">{}</p></div>",
+        pair(
+            dynamics.left_balance,
+            dynamics.left_balance.map(|left| 100.0 - left)
+        )
     ));
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Workout Distance</p><p class=\"value\">{}</p></div>",
-        format_distance(summary.distance_meters)
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Torque Effectiveness\"><p class=\"label\">Torque Effectiveness</p><p class=\"value\">{}</p></div>",
+        pair(
+            dynamics.left_torque_effectiveness,
+            dynamics.right_torque_effectiveness
+        )
     ));
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Speed (min)</p><p class=\"value\">{}</p></div>",
-        min_speed
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Pedal Smoothness\"><p class=\"label\">Pedal Smoothness</p><p class=\"value\">{}</p></div>",
+        match dynamics.combined_pedal_smoothness {
+            Some(combined) => format_percent(Some(combined)),
+            None => pair(
+                dynamics.left_pedal_smoothness,
+                dynamics.right_pedal_smoothness
+            ),
+        }
     ));
+    body.push_str("</div></section>");
+    body
+}
+
+fn render_conditions(conditions: &ConditionsAdjustment, locale: NumberLocale) -> String {
+    let mut body = String::new();
+    let factor = |value: f64| format!("{:.1}%", value * 100.0);
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Conditions\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Conditions</p><h2>Adjusted to sea level and 15 °C</h2></div></div>");
+    body.push_str("<div class=\"summary-grid\">");
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Speed (mean)</p><p class=\"value\">{}</p></div>",
-        mean_speed
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Mean Temperature\"><p class=\"label\">Mean Temperature</p><p class=\"value\">{}</p></div>",
+        conditions
+            .mean_temperature_celsius
+            .map(|celsius| format!("{celsius:.1} °C"))
+            .unwrap_or_else(|| "—".to_string())
     ));
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Speed (max)</p><p class=\"value\">{}</p></div>",
-        max_speed
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Mean Altitude\"><p class=\"label\">Mean Altitude</p><p class=\"value\">{}</p></div>",
+        format_elevation(conditions.mean_altitude_meters, locale)
     ));
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Heart Rate (min)</p><p class=\"value\">{}</p></div>",
-        min_hr
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Heat Factor\"><p class=\"label\">Heat Factor</p><p class=\"value\">{}</p></div>",
+        factor(conditions.heat_factor)
     ));
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Heart Rate (mean)</p><p class=\"value\">{}</p></div>",
-        mean_hr
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Altitude Factor\"><p class=\"label\">Altitude Factor</p><p class=\"value\">{}</p></div>",
+        factor(conditions.altitude_factor)
     ));
     body.push_str(&format!(
-        "<div class=\"summary-card\"><p class=\"label\">Heart Rate (max)</p><p class=\"value\">{}</p></div>",
-        max_hr
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Adjusted Speed (mean)\"><p class=\"label\">Adjusted Speed (mean)</p><p class=\"value\">{}</p></div>",
+        format_speed(conditions.adjusted_speed_mean)
     ));
+    if let Some(watts) = conditions.adjusted_power_mean {
+        body.push_str(&format!(
+            "<div class=\"summary-card\" role=\"group\" aria-label=\"Adjusted Power (mean)\"><p class=\"label\">Adjusted Power (mean)</p><p class=\"value\">{watts:.0} W</p></div>"
+        ));
+    }
+    body.push_str("</div></section>");
+    body
+}
+
+/// The provider's weather for the hour the activity started in.
+fn render_weather(weather: &Weather, utc_offset_seconds: Option<i32>) -> String {
+    let reading = |value: Option<f64>, unit: &str| {
+        value
+            .map(|value| format!("{value:.1} {unit}"))
+            .unwrap_or_else(|| "—".to_string())
+    };
+    let wind = match (weather.wind_speed, weather.wind_direction_degrees) {
+        (Some(speed), Some(direction)) => format!("{speed:.1} m/s from {direction:.0}°"),
+        (speed, _) => reading(speed, "m/s"),
+    };
+    let mut body = String::from("<div class=\"summary-grid\">");
+    body.push_str(&summary_card(
+        "Observed",
+        &format_local_time(Some(weather.observed_at), utc_offset_seconds),
+    ));
+    body.push_str(&summary_card(
+        "Temperature",
+        &reading(weather.temperature_celsius, "°C"),
+    ));
+    body.push_str(&summary_card(
+        "Humidity",
+        &reading(weather.relative_humidity_percent, "%"),
+    ));
+    body.push_str(&summary_card("Wind", &wind));
     body.push_str("</div>");
-    body.push_str("</section>");
+    results_section("Weather", "At the start", &body)
+}
 
-    body.push_str("<section class=\"results-card\">");
+/// Headwind along the route, and the pace and power each stretch is worth
+/// in still air.
+fn render_wind(
+    wind: &WindAdjustment,
+    utc_offset_seconds: Option<i32>,
+    locale: NumberLocale,
+) -> String {
+    let power = |watts: Option<f64>| {
+        watts
+            .map(|watts| format!("{watts:.0} W"))
+            .unwrap_or_else(|| "—".to_string())
+    };
+    let mut body = String::from("<div class=\"summary-grid\">");
+    body.push_str(&summary_card(
+        "Mean Headwind",
+        &format!("{:+.1} m/s", wind.mean_headwind),
+    ));
+    body.push_str(&summary_card(
+        "Into the Wind",
+        &format_percent(Some(wind.headwind_share * 100.0)),
+    ));
+    body.push_str(&summary_card(
+        "Adjusted Speed (mean)",
+        &format_speed(wind.adjusted_speed_mean),
+    ));
+    if wind.adjusted_power_mean.is_some() {
+        body.push_str(&summary_card(
+            "Adjusted Power (mean)",
+            &power(wind.adjusted_power_mean),
+        ));
+    }
+    body.push_str("</div>");
+    body.push_str("<p>Estimates for a road cyclist, from the wind at the start; negative headwinds are tailwinds.</p>");
+    body.push_str(&table_head(
+        "Headwind and wind-adjusted pace and power of each stretch of the route",
+        &[
+            "Start",
+            "Distance",
+            "Heading",
+            "Headwind",
+            "Pace",
+            "Adjusted Pace",
+            "Power",
+            "Adjusted Power",
+        ],
+    ));
+    body.push_str("<tbody>");
+    for segment in &wind.segments {
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{:.0}°</td><td>{:+.1} m/s</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            format_local_time(Some(segment.start_timestamp), utc_offset_seconds),
+            format_distance(Some(segment.distance_meters), locale),
+            segment.heading_degrees,
+            segment.headwind,
+            format_speed(Some(segment.speed)),
+            format_speed(Some(segment.adjusted_speed)),
+            power(segment.power),
+            power(segment.adjusted_power)
+        ));
+    }
+    body.push_str("</tbody></table></div>");
+    results_section("Wind", "Headwind and wind-adjusted effort", &body)
+}
+
+/// Speed over time with laps and intervals shaded behind it and stops on top.
+fn render_annotated_timeline(annotations: &Annotations) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 200.0;
+    const MARGIN: f64 = 30.0;
+    let timeline = &annotations.timeline;
+    let (start, end) = match (timeline.first(), timeline.last()) {
+        (Some(first), Some(last)) if last.timestamp > first.timestamp => {
+            (first.timestamp, last.timestamp)
+        }
+        _ => return String::new(),
+    };
+    let max_speed = timeline
+        .iter()
+        .map(|point| point.speed * 3.6)
+        .fold(0.0, f64::max)
+        .max(1.0);
+    let x = |timestamp: f64| {
+        MARGIN + (timestamp.clamp(start, end) - start) / (end - start) * (WIDTH - 2.0 * MARGIN)
+    };
+    let y = |kmh: f64| HEIGHT - MARGIN - kmh / max_speed * (HEIGHT - 2.0 * MARGIN);
+
+    let label = "Speed (km/h) with stops, laps, and intervals";
+    let mut body = String::new();
+    body.push_str("<section class=\"results-card\" aria-label=\"Timeline\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Timeline</p><h2>Stops, laps, and intervals</h2></div></div>");
+    body.push_str(&format!(
+        "<figure class=\"curve-chart\"><figcaption>{label}</figcaption><svg viewBox=\"0 0 {WIDTH} {HEIGHT}\" role=\"img\" aria-label=\"{label}\">"
+    ));
+    // Laps and intervals first, so stops drawn afterwards stay visible.
+    let mut regions: Vec<_> = annotations.regions.iter().collect();
+    regions.sort_by_key(|region| region.kind == AnnotationKind::Stop);
+    for (index, region) in regions.into_iter().enumerate() {
+        let fill = match region.kind {
+            AnnotationKind::Stop => "#64748b",
+            AnnotationKind::Lap if index % 2 == 0 => "#bfdbfe",
+            AnnotationKind::Lap => "#e0f2fe",
+            AnnotationKind::Interval if region.label.contains("rest") => "#bbf7d0",
+            AnnotationKind::Interval if region.label.contains("recovery") => "#bbf7d0",
+            AnnotationKind::Interval => "#fecaca",
+        };
+        let (left, right) = (x(region.start_timestamp), x(region.end_timestamp));
+        body.push_str(&format!(
+            "<rect class=\"annotation\" x=\"{left:.1}\" y=\"{MARGIN:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{fill}\" fill-opacity=\"0.45\"><title>{} ({})</title></rect>",
+            (right - left).max(1.0),
+            HEIGHT - 2.0 * MARGIN,
+            escape_html(&region.label),
+            format_duration(Some(region.end_timestamp - region.start_timestamp))
+        ));
+    }
+    let line: Vec<String> = timeline
+        .iter()
+        .map(|point| format!("{:.1},{:.1}", x(point.timestamp), y(point.speed * 3.6)))
+        .collect();
     body.push_str(&format!(
-        "<div class=\"results-header\"><div><p class=\"eyebrow\">Data records</p><h2>Showing the first 25 of {} records</h2></div></div>",
-        processed.records.len()
+        "<polyline fill=\"none\" stroke=\"#2563eb\" stroke-width=\"1.5\" points=\"{}\" />",
+        line.join(" ")
     ));
-    body.push_str("<div class=\"table-wrapper\"><table><thead><tr><th>Message</th><th>Fields</th></tr></thead><tbody>");
+    body.push_str(&format!(
+        "<text x=\"4\" y=\"{MARGIN:.1}\" font-size=\"10\">{max_speed:.0}</text><text x=\"{:.1}\" y=\"{:.1}\" font-size=\"10\" text-anchor=\"end\">{}</text>",
+        WIDTH - MARGIN,
+        HEIGHT - 10.0,
+        format_duration(Some(end - start))
+    ));
+    body.push_str("</svg></figure></section>");
+    body
+}
 
-    for record in processed.records.iter().take(25) {
-        body.push_str(&format!("<tr><td>{}</td><td>", record.message_type));
-        body.push_str("<ul>");
-        for field in &record.fields {
+/// Totals and one row per run, since whole-day pace mixes in the lifts.
+fn render_ski_runs(
+    analysis: &SkiRuns,
+    utc_offset_seconds: Option<i32>,
+    locale: NumberLocale,
+) -> String {
+    let mut body = String::from("<div class=\"summary-grid\">");
+    let cards = [
+        ("Runs", analysis.runs.len().to_string()),
+        ("Lift Rides", analysis.lift_rides.to_string()),
+        (
+            "Time on Lifts",
+            format_duration(Some(analysis.lift_seconds)),
+        ),
+        (
+            "Total Vertical",
+            format_elevation(Some(analysis.total_vertical_meters), locale),
+        ),
+        ("Top Speed", format_speed(analysis.max_speed)),
+    ];
+    for (label, value) in cards {
+        body.push_str(&summary_card(label, &value));
+    }
+    body.push_str("</div>");
+    if !analysis.runs.is_empty() {
+        body.push_str(&table_head(
+            "Time, vertical, distance, and speed of each run",
+            &[
+                "Run",
+                "Start",
+                "Time",
+                "Vertical",
+                "Distance",
+                "Max Speed",
+                "Mean Speed",
+            ],
+        ));
+        body.push_str("<tbody>");
+        for (number, run) in analysis.runs.iter().enumerate() {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                number + 1,
+                format_local_time(Some(run.start_timestamp), utc_offset_seconds),
+                format_duration(Some(run.duration_seconds)),
+                format_elevation(Some(run.vertical_meters), locale),
+                format_distance(run.distance_meters, locale),
+                format_speed(run.max_speed),
+                format_speed(run.mean_speed)
+            ));
+        }
+        body.push_str("</tbody></table></div>");
+    }
+    results_section("Runs", "Runs and lift rides", &body)
+}
+
+/// Totals and one row per active set, numbered as set corrections expect.
+fn render_strength_sets(strength: &StrengthSets, locale: NumberLocale) -> String {
+    let kilograms = |kg: f64| format!("{} kg", locale.number(kg, 1));
+    let mut body = String::from("<div class=\"summary-grid\">");
+    let cards = [
+        ("Sets", strength.sets.len().to_string()),
+        ("Total Reps", strength.total_repetitions.to_string()),
+        ("Volume Load", kilograms(strength.volume_load_kg)),
+    ];
+    for (label, value) in cards {
+        body.push_str(&summary_card(label, &value));
+    }
+    body.push_str("</div>");
+    body.push_str(&table_head(
+        "Exercise, repetitions, and weight of each set",
+        &["Set", "Exercise", "Reps", "Weight", "Volume", "Time"],
+    ));
+    body.push_str("<tbody>");
+    for set in &strength.sets {
+        let exercise = set.category.as_deref().map_or_else(
+            || "—".to_string(),
+            |name| escape_html(&name.replace('_', " ")),
+        );
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{exercise}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            set.number,
+            set.repetitions
+                .map_or("—".to_string(), |reps| reps.to_string()),
+            set.weight_kg.map_or("—".to_string(), kilograms),
+            kilograms(set.volume_load_kg()),
+            format_duration(set.duration_seconds)
+        ));
+    }
+    body.push_str("</tbody></table></div>");
+    results_section("Strength", "Sets and volume load", &body)
+}
+
+fn render_ebike(ebike: &EbikeSummary, locale: NumberLocale) -> String {
+    let percent =
+        |value: Option<f64>| value.map_or("—".to_string(), |value| format!("{value:.0}%"));
+    let watts =
+        |value: Option<f64>| value.map_or("—".to_string(), |value| format!("{value:.0} W"));
+    let cards = [
+        ("Battery at Start", percent(ebike.battery_start_percent)),
+        ("Battery at End", percent(ebike.battery_end_percent)),
+        ("Battery Used", percent(ebike.battery_used_percent)),
+        (
+            "Distance per 1% Battery",
+            format_distance(ebike.meters_per_battery_percent, locale),
+        ),
+        ("Motor Power (mean)", watts(ebike.motor_power_mean)),
+        ("Motor Power (max)", watts(ebike.motor_power_max)),
+        (
+            "Motor Energy",
+            ebike.motor_energy_kj.map_or("—".to_string(), |kj| {
+                format!("{} kJ", locale.number(kj, 0))
+            }),
+        ),
+    ];
+    let mut body = String::from("<div class=\"summary-grid\">");
+    for (label, value) in cards {
+        body.push_str(&summary_card(label, &value));
+    }
+    body.push_str("</div>");
+    let total_seconds: f64 = ebike.assist_modes.iter().map(|time| time.seconds).sum();
+    if total_seconds > 0.0 {
+        body.push_str(&table_head(
+            "Time in each assist mode",
+            &["Assist Mode", "Time", "Share"],
+        ));
+        body.push_str("<tbody>");
+        for time in &ebike.assist_modes {
             body.push_str(&format!(
-                "<li><strong>{}</strong>: {}</li>",
-                field.name, field.value
+                "<tr><td>{}</td><td>{}</td><td>{:.0}%</td></tr>",
+                escape_html(&time.mode),
+                format_duration(Some(time.seconds)),
+                time.seconds / total_seconds * 100.0
             ));
         }
-        body.push_str("</ul></td></tr>");
+        body.push_str("</tbody></table></div>");
     }
+    results_section("eBike", "Battery and assist", &body)
+}
 
+fn render_virtual_ride(ride: &VirtualRide, utc_offset_seconds: Option<i32>) -> String {
+    let percent =
+        |value: Option<f64>| value.map_or("—".to_string(), |value| format!("{value:.1}%"));
+    let watts =
+        |value: Option<f64>| value.map_or("—".to_string(), |value| format!("{value:.0} W"));
+    let cards = [
+        (
+            "Platform",
+            ride.platform.unwrap_or("Virtual ride").to_string(),
+        ),
+        (
+            "GPS",
+            if ride.has_gps {
+                "Recorded"
+            } else {
+                "Not recorded"
+            }
+            .to_string(),
+        ),
+        ("Incline (mean)", percent(ride.grade_mean_percent)),
+        ("Incline (max)", percent(ride.grade_max_percent)),
+    ];
+    let mut body = String::from("<div class=\"summary-grid\">");
+    for (label, value) in cards {
+        body.push_str(&summary_card(label, &value));
+    }
+    body.push_str("</div>");
+    if !ride.segments.is_empty() {
+        body.push_str(&table_head(
+            "Trainer mode, target, and power of each workout step",
+            &[
+                "Step", "Mode", "Start", "Time", "Target", "Power", "Incline",
+            ],
+        ));
+        body.push_str("<tbody>");
+        for segment in &ride.segments {
+            let mode = match segment.mode {
+                TrainerMode::Erg => "ERG",
+                TrainerMode::Simulation => "Simulation",
+                TrainerMode::Free => "Free",
+            };
+            let target = match segment.target_watts {
+                Some((low, high)) if low == high => format!("{low:.0} W"),
+                Some((low, high)) => format!("{low:.0}–{high:.0} W"),
+                None => "—".to_string(),
+            };
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{mode}</td><td>{}</td><td>{}</td><td>{target}</td><td>{}</td><td>{}</td></tr>",
+                segment
+                    .step
+                    .map_or("—".to_string(), |step| (step + 1).to_string()),
+                format_local_time(Some(segment.start_timestamp), utc_offset_seconds),
+                format_duration(Some(segment.duration_seconds)),
+                watts(segment.mean_power),
+                percent(segment.mean_grade_percent)
+            ));
+        }
+        body.push_str("</tbody></table></div>");
+    }
+    results_section("Virtual Ride", "Trainer and workout steps", &body)
+}
+
+/// Line colour of each [`crate::processing::terrain::GRADE_BUCKETS`] entry, from steep descent to steep climb.
+const GRADE_COLORS: &[&str] = &["#1d4ed8", "#60a5fa", "#94a3b8", "#f59e0b", "#dc2626"];
+
+fn render_terrain(terrain: &TerrainAnalysis, locale: NumberLocale) -> String {
+    let mut body = String::new();
+    let total_seconds: f64 = terrain.grades.iter().map(|bucket| bucket.seconds).sum();
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Terrain\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Terrain</p><h2>Time in grade</h2></div></div>");
+    if let Some(estimate) = &terrain.surface {
+        let surface = match estimate.surface {
+            Surface::Paved => "Paved",
+            Surface::Mixed => "Mixed",
+            Surface::Unpaved => "Unpaved",
+        };
+        body.push_str(&format!(
+            "<div class=\"summary-grid\"><div class=\"summary-card\" role=\"group\" aria-label=\"Estimated Surface\"><p class=\"label\">Estimated Surface</p><p class=\"value\">{surface}</p></div></div>"
+        ));
+    }
+    body.push_str(&table_head(
+        "Time and distance in each grade bucket",
+        &["Grade", "Time", "Share", "Distance"],
+    ));
+    body.push_str("<tbody>");
+    for (bucket, color) in terrain.grades.iter().zip(GRADE_COLORS) {
+        let share = if total_seconds > 0.0 {
+            bucket.seconds / total_seconds * 100.0
+        } else {
+            0.0
+        };
+        body.push_str(&format!(
+            "<tr><td><svg class=\"grade-swatch\" viewBox=\"0 0 1 1\" aria-hidden=\"true\"><rect width=\"1\" height=\"1\" fill=\"{color}\"/></svg>{}</td><td>{}</td><td>{share:.0}%</td><td>{}</td></tr>",
+            escape_html(bucket.label),
+            format_duration(Some(bucket.seconds)),
+            format_distance(Some(bucket.distance_meters), locale)
+        ));
+    }
     body.push_str("</tbody></table></div>");
+    if terrain.profile.len() >= 2 {
+        body.push_str(&render_terrain_profile(terrain, locale));
+    }
     body.push_str("</section>");
     body
 }
+
+/// Elevation against distance, each segment coloured by its grade bucket.
+fn render_terrain_profile(terrain: &TerrainAnalysis, locale: NumberLocale) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 200.0;
+    const MARGIN: f64 = 30.0;
+    let profile = &terrain.profile;
+    let max_distance = profile
+        .last()
+        .map_or(1.0, |point| point.distance_meters.max(1.0));
+    let (low, high) = profile
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(low, high), point| {
+            (
+                low.min(point.altitude_meters),
+                high.max(point.altitude_meters),
+            )
+        });
+    let range = (high - low).max(1.0);
+    let x = |distance: f64| MARGIN + distance / max_distance * (WIDTH - 2.0 * MARGIN);
+    let y = |altitude: f64| HEIGHT - MARGIN - (altitude - low) / range * (HEIGHT - 2.0 * MARGIN);
+
+    let label = "Elevation (m) by grade";
+    let mut body = format!(
+        "<figure class=\"curve-chart\"><figcaption>{label}</figcaption><svg viewBox=\"0 0 {WIDTH} {HEIGHT}\" role=\"img\" aria-label=\"{label}\">"
+    );
+    for pair in profile.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let color = GRADE_COLORS[grade_bucket(to.grade_percent)];
+        body.push_str(&format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{color}\" stroke-width=\"2\"><title>{:.1}%</title></line>",
+            x(from.distance_meters),
+            y(from.altitude_meters),
+            x(to.distance_meters),
+            y(to.altitude_meters),
+            to.grade_percent
+        ));
+    }
+    body.push_str(&format!(
+        "<text x=\"4\" y=\"{MARGIN:.1}\" font-size=\"10\">{high:.0}</text><text x=\"4\" y=\"{:.1}\" font-size=\"10\">{low:.0}</text><text x=\"{:.1}\" y=\"{:.1}\" font-size=\"10\" text-anchor=\"end\">{}</text>",
+        HEIGHT - MARGIN,
+        WIDTH - MARGIN,
+        HEIGHT - 10.0,
+        format_distance(Some(max_distance), locale)
+    ));
+    body.push_str("</svg></figure>");
+    body
+}
+
+fn render_threshold_estimate(estimate: &ThresholdEstimate) -> String {
+    let mut body = String::new();
+    let watts = |value: Option<f64>| match value {
+        Some(watts) if watts.is_finite() => format!("{watts:.0} W"),
+        _ => "—".to_string(),
+    };
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Threshold Estimate\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Threshold Estimate</p><h2>FTP and threshold heart rate</h2></div></div>");
+    body.push_str("<div class=\"summary-grid\">");
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Estimated FTP\"><p class=\"label\">Estimated FTP</p><p class=\"value\">{}</p></div>",
+        watts(estimate.ftp_watts)
+    ));
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Best 20 min Power\"><p class=\"label\">Best 20 min Power</p><p class=\"value\">{}</p></div>",
+        watts(estimate.best_20min_power_watts)
+    ));
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Threshold Heart Rate\"><p class=\"label\">Threshold Heart Rate</p><p class=\"value\">{}</p></div>",
+        format_heart_rate(estimate.threshold_heart_rate)
+    ));
+    body.push_str("</div><ul>");
+    for note in &estimate.notes {
+        body.push_str(&format!("<li>{}</li>", escape_html(note)));
+    }
+    body.push_str("</ul></section>");
+    body
+}
+
+fn render_derived_fields(fields: &[DerivedFieldSummary], download_url: &str) -> String {
+    let mut body = String::new();
+    let csv_url = download_url.replacen('?', "/derived-fields.csv?", 1);
+    let json_url = download_url.replacen('?', "/derived-fields.json?", 1);
+    let format_value = |value: Option<f64>| value.map_or("—".to_string(), |v| format!("{v:.2}"));
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Derived Fields\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Derived Fields</p><h2>Your expressions</h2></div>");
+    body.push_str(&format!(
+        "<a class=\"cta\" download=derived-fields.csv href={csv_url} aria-label=\"Download derived fields as CSV\">Download CSV</a><a class=\"cta\" download=derived-fields.json href={json_url} aria-label=\"Download derived fields as JSON\">Download JSON</a>"
+    ));
+    body.push_str("</div><div class=\"summary-grid\">");
+    for field in fields {
+        let name = escape_html(&field.name);
+        body.push_str(&format!(
+            "<div class=\"summary-card\" role=\"group\" aria-label=\"{name}\"><p class=\"label\">{name} = {}</p><p class=\"value\">{}</p><p class=\"label\">mean of {} records; {} to {}</p></div>",
+            escape_html(&field.expression),
+            format_value(field.mean),
+            field.samples,
+            format_value(field.min),
+            format_value(field.max)
+        ));
+    }
+    body.push_str("</div></section>");
+    body
+}
+
+fn render_w_prime_balance(balance: &WPrimeBalance, download_url: &str) -> String {
+    let mut body = String::new();
+    // The series sits under the processed file's signed path and shares its signature.
+    let series_url = download_url.replacen('?', "/w-prime-balance.csv?", 1);
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Anaerobic Capacity\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Anaerobic Capacity</p><h2>W' balance</h2></div>");
+    body.push_str(&format!(
+        "<a class=\"cta\" download=w-prime-balance.csv href={series_url}>Download per-second W' balance</a>"
+    ));
+    body.push_str("</div><div class=\"summary-grid\">");
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Critical Power\"><p class=\"label\">Critical Power</p><p class=\"value\">{:.0} W</p></div>",
+        balance.critical_power_watts
+    ));
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"W'\"><p class=\"label\">W'</p><p class=\"value\">{:.1} kJ</p></div>",
+        balance.w_prime_joules / 1000.0
+    ));
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Lowest W' balance\"><p class=\"label\">Lowest W' balance</p><p class=\"value\">{:.1} kJ ({:.0}%)</p></div>",
+        balance.min_balance_joules / 1000.0,
+        balance.min_balance_joules / balance.w_prime_joules * 100.0
+    ));
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Reached After\"><p class=\"label\">Reached After</p><p class=\"value\">{}</p></div>",
+        format_duration(Some(balance.min_balance_at_seconds as f64))
+    ));
+    body.push_str("</div></section>");
+    body
+}
+
+/// Power and speed curve charts for whichever of the two have data.
+fn render_mean_max_charts(curve: &MeanMaxCurve) -> String {
+    let mut body = String::new();
+    if !curve.power.is_empty() {
+        body.push_str(&render_curve_chart("Power (W)", &curve.power, 1.0));
+    }
+    if !curve.speed.is_empty() {
+        body.push_str(&render_curve_chart("Speed (km/h)", &curve.speed, 3.6));
+    }
+    body
+}
+
+/// Inline SVG line chart of one curve on a logarithmic duration axis;
+/// `scale` converts the stored values into the unit in `label`.
+fn render_curve_chart(label: &str, points: &[CurvePoint], scale: f64) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 200.0;
+    const MARGIN: f64 = 30.0;
+    let max_duration = (*CURVE_DURATIONS_SECONDS.last().unwrap_or(&1) as f64).ln();
+    let max_value = points
+        .iter()
+        .map(|point| point.value * scale)
+        .fold(0.0, f64::max)
+        .max(1.0);
+    let x =
+        |duration: usize| MARGIN + (duration as f64).ln() / max_duration * (WIDTH - 2.0 * MARGIN);
+    let y = |value: f64| HEIGHT - MARGIN - value / max_value * (HEIGHT - 2.0 * MARGIN);
+
+    let mut body = format!(
+        "<figure class=\"curve-chart\"><figcaption>{}</figcaption><svg viewBox=\"0 0 {WIDTH} {HEIGHT}\" role=\"img\" aria-label=\"{}\">",
+        escape_html(label),
+        escape_html(label)
+    );
+    let line: Vec<String> = points
+        .iter()
+        .map(|point| {
+            format!(
+                "{:.1},{:.1}",
+                x(point.duration_seconds),
+                y(point.value * scale)
+            )
+        })
+        .collect();
+    body.push_str(&format!(
+        "<polyline fill=\"none\" stroke=\"#2563eb\" stroke-width=\"2\" points=\"{}\" />",
+        line.join(" ")
+    ));
+    for point in points {
+        let (px, py) = (x(point.duration_seconds), y(point.value * scale));
+        body.push_str(&format!(
+            "<circle cx=\"{px:.1}\" cy=\"{py:.1}\" r=\"3\" fill=\"#2563eb\"><title>{}: {:.1}</title></circle>",
+            format_curve_duration(point.duration_seconds),
+            point.value * scale
+        ));
+    }
+    for &duration in CURVE_DURATIONS_SECONDS {
+        body.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"10\" text-anchor=\"middle\">{}</text>",
+            x(duration),
+            HEIGHT - 10.0,
+            format_curve_duration(duration)
+        ));
+    }
+    body.push_str(&format!(
+        "<text x=\"4\" y=\"{:.1}\" font-size=\"10\">{max_value:.0}</text>",
+        MARGIN
+    ));
+    body.push_str("</svg></figure>");
+    body
+}
+
+fn format_curve_duration(seconds: usize) -> String {
+    match seconds {
+        s if s >= 3600 && s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s >= 60 && s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+/// Aggregate mean-max curves across every stored activity.
+pub fn render_curves_page(history: &CurveHistory) -> String {
+    let mut body = String::new();
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Critical Power &amp; Speed\">");
+    body.push_str(&format!(
+        "<div class=\"results-header\"><div><p class=\"eyebrow\">Critical Power &amp; Speed</p><h2>Best efforts across {} activities</h2></div><div><a class=\"cta\" href=\"/api/v1/curves\">Download JSON</a></div></div>",
+        history.activities.len()
+    ));
+    if history.aggregate.is_empty() {
+        body.push_str("<p>No processed activities with power or distance yet. Upload FIT files to build the curves.</p>");
+    } else {
+        body.push_str(&render_mean_max_charts(&history.aggregate));
+    }
+    body.push_str("</section>");
+
+    render_page("Mean-max curves", &body)
+}
+
+/// A slider that previews the speed series smoothed with another window,
+/// redrawn by the landing page script from the preview endpoint.
+fn render_smoothing_preview(download_url: &str) -> String {
+    let preview_url = download_url
+        .replacen("/download/", "/api/v1/results/", 1)
+        .replacen('?', "/preview?", 1);
+    results_section(
+        "Smoothing Preview",
+        "Raw and smoothed speed",
+        &format!(
+            "<form class=\"smoothing-preview\" data-preview-url=\"{preview_url}\">\
+             <label>Window: <output class=\"smoothing-window-value\">{SPEED_SMOOTHING_WINDOW}</output> samples \
+             <input class=\"smoothing-window\" type=\"range\" name=\"window\" min=\"1\" max=\"{MAX_PREVIEW_WINDOW}\" step=\"2\" value=\"{SPEED_SMOOTHING_WINDOW}\"></label>\
+             <figure class=\"curve-chart\"><figcaption>Speed: raw in grey, smoothed in blue</figcaption>\
+             <svg viewBox=\"0 0 600 200\" role=\"img\" aria-label=\"Raw and smoothed speed\">\
+             <polyline class=\"raw-speed\" fill=\"none\" stroke=\"#9ca3af\" stroke-width=\"1\" points=\"\" />\
+             <polyline class=\"smoothed-speed\" fill=\"none\" stroke=\"#2563eb\" stroke-width=\"1.5\" points=\"\" /></svg></figure>\
+             <p class=\"smoothing-residuals\" aria-live=\"polite\">Move the slider to preview a window.</p></form>"
+        ),
+    )
+}
+
+/// Each spike with its likely cause, and whether smoothing or trimming suits
+/// the file better.
+fn render_speed_spikes(spikes: &[SpeedSpike], utc_offset_seconds: Option<i32>) -> String {
+    let count = |cause: SpikeCause| spikes.iter().filter(|spike| spike.cause == cause).count();
+    let resets = count(SpikeCause::DistanceReset);
+    let mut body = String::from("<div class=\"summary-grid\">");
+    let cards = [
+        ("Spikes", spikes.len()),
+        ("GPS Jumps", count(SpikeCause::GpsJump)),
+        ("Distance Resets", resets),
+    ];
+    for (label, value) in cards {
+        body.push_str(&summary_card(label, &value.to_string()));
+    }
+    body.push_str("</div>");
+    body.push_str(if resets > 0 {
+        "<p>Distance resets leave a step in the distance that smoothing only spreads out; stitch them with the distance reset option or trim the records around them instead.</p>"
+    } else {
+        "<p>Smoothing speed clamps spikes like these, and rebuilds the distance from the smoothed speeds.</p>"
+    });
+    if spikes.len() == MAX_SPEED_SPIKES {
+        body.push_str(&format!(
+            "<p>Only the {MAX_SPEED_SPIKES} largest spikes are listed.</p>"
+        ));
+    }
+    body.push_str(&table_head(
+        "Time, speed, and likely cause of each speed spike",
+        &["Time", "Speed", "Expected", "Likely Cause"],
+    ));
+    body.push_str("<tbody>");
+    for spike in spikes {
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            format_local_time(Some(spike.timestamp), utc_offset_seconds),
+            format_speed(Some(spike.speed)),
+            format_speed(Some(spike.expected_speed)),
+            spike.cause.label()
+        ));
+    }
+    body.push_str("</tbody></table></div>");
+    results_section("Speed Spikes", "Spikes and their likely cause", &body)
+}
+
+fn render_warnings(processed: &ProcessedFit) -> String {
+    let mut body = String::new();
+    body.push_str("<section class=\"results-card warnings\" aria-label=\"Warnings\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Warnings</p><h2>Things to check in this file</h2></div></div>");
+    body.push_str("<ul>");
+    for warning in &processed.warnings {
+        body.push_str(&format!("<li>{}</li>", escape_html(&warning.message)));
+    }
+    body.push_str("</ul></section>");
+    body
+}
+
+fn render_device_inventory(processed: &ProcessedFit) -> String {
+    let mut body = String::new();
+    let text = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(escape_html)
+            .unwrap_or_else(|| "—".to_string())
+    };
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Devices &amp; Sensors\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Devices &amp; Sensors</p><h2>Recording inventory</h2></div></div>");
+    body.push_str(&table_head(
+        "Devices and sensors recorded in the file",
+        &[
+            "Role",
+            "Manufacturer",
+            "Product",
+            "Type",
+            "Firmware",
+            "Serial",
+            "Battery",
+        ],
+    ));
+    body.push_str("<tbody>");
+    for device in &processed.devices {
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            if device.is_creator() { "Recording device" } else { "Sensor" },
+            text(&device.manufacturer),
+            text(&device.product),
+            text(&device.device_type),
+            text(&device.software_version),
+            text(&device.serial_number),
+            text(&device.battery_status),
+        ));
+    }
+    body.push_str("</tbody></table></div></section>");
+    body
+}
+
+fn render_file_structure(structure: &FileStructure) -> String {
+    let mut body = String::new();
+    let size = |bytes: Option<usize>| bytes.map_or("—".to_string(), format_bytes);
+    let text = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(escape_html)
+            .unwrap_or_else(|| "—".to_string())
+    };
+    let number = |value: Option<u8>| value.map_or("—".to_string(), |v| v.to_string());
+
+    body.push_str("<section class=\"results-card\" aria-label=\"File Structure\">");
+    body.push_str(&format!(
+        "<div class=\"results-header\"><div><p class=\"eyebrow\">File Structure</p><h2>{} message types</h2></div></div>",
+        structure.messages.len()
+    ));
+    if let (Some(file_bytes), Some(data_bytes)) = (structure.file_bytes, structure.data_bytes) {
+        body.push_str(&format!(
+            "<p class=\"label\">{} in total; {} of data messages, {} of header, definitions, and CRC.</p>",
+            format_bytes(file_bytes),
+            format_bytes(data_bytes),
+            format_bytes(file_bytes.saturating_sub(data_bytes))
+        ));
+    }
+    body.push_str(&table_head(
+        "Messages by type",
+        &["Message", "Count", "Size"],
+    ));
+    body.push_str("<tbody>");
+    for entry in &structure.messages {
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&entry.message_type),
+            entry.count,
+            size(entry.bytes)
+        ));
+    }
+    body.push_str("</tbody></table></div>");
+
+    if !structure.developer_fields.is_empty() {
+        body.push_str(&table_head(
+            "Developer fields",
+            &[
+                "Developer field",
+                "Developer index",
+                "Field number",
+                "Units",
+                "Base type",
+                "Values",
+            ],
+        ));
+        body.push_str("<tbody>");
+        for field in &structure.developer_fields {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                text(&field.field_name),
+                number(field.developer_data_index),
+                number(field.field_definition_number),
+                text(&field.units),
+                field
+                    .fit_base_type_id
+                    .map_or("—".to_string(), |id| format!("{id:#04x}")),
+                field.values
+            ));
+        }
+        body.push_str("</tbody></table></div>");
+    }
+    body.push_str("</section>");
+    body
+}
+
+pub fn render_aggregate_dashboard(aggregate: &AggregateSummary, locale: NumberLocale) -> String {
+    let mut body = String::new();
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Batch Overview\">");
+    body.push_str(&format!(
+        "<div class=\"results-header\"><div><p class=\"eyebrow\">Batch Overview</p><h2>{} activities combined</h2></div></div>",
+        aggregate.activity_count
+    ));
+    body.push_str("<div class=\"summary-grid\">");
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Total Distance\"><p class=\"label\">Total Distance</p><p class=\"value\">{}</p></div>",
+        format_distance(Some(aggregate.total_distance_meters), locale)
+    ));
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Total Time\"><p class=\"label\">Total Time</p><p class=\"value\">{}</p></div>",
+        format_duration(Some(aggregate.total_duration_seconds))
+    ));
+    body.push_str(&format!(
+        "<div class=\"summary-card\" role=\"group\" aria-label=\"Total Elevation Gain\"><p class=\"label\">Total Elevation Gain</p><p class=\"value\">{}</p></div>",
+        format_elevation(Some(aggregate.total_elevation_gain_meters), locale)
+    ));
+    body.push_str("</div>");
+
+    if !aggregate.weeks.is_empty() {
+        body.push_str(&table_head(
+            "Totals per week",
+            &[
+                "Week of",
+                "Activities",
+                "Distance",
+                "Time",
+                "Elevation Gain",
+            ],
+        ));
+        body.push_str("<tbody>");
+        for week in &aggregate.weeks {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                week.period_start.format("%Y-%m-%d"),
+                week.activity_count,
+                format_distance(Some(week.distance_meters), locale),
+                format_duration(Some(week.duration_seconds)),
+                format_elevation(Some(week.elevation_gain_meters), locale),
+            ));
+        }
+        body.push_str("</tbody></table></div>");
+    }
+
+    body.push_str("</section>");
+    body
+}
+
+/// Render the aggregate dashboard followed by each file's individual results.
+pub fn render_batch_results(
+    aggregate: &AggregateSummary,
+    files: &[(String, ProcessedFit, String)],
+    locale: NumberLocale,
+) -> String {
+    let mut body = render_aggregate_dashboard(aggregate, locale);
+
+    for (file_name, processed, download_url) in files {
+        body.push_str(&format!(
+            "<h3 class=\"file-heading\">{}</h3>",
+            escape_html(file_name)
+        ));
+        body.push_str(&render_processed_records(processed, download_url, locale));
+    }
+
+    body
+}
+
+pub fn render_training_report(report: &TrainingReport, locale: NumberLocale) -> String {
+    let (title, slug) = match report.period {
+        ReportPeriod::Week => ("Weekly training report", "weekly"),
+        ReportPeriod::Month => ("Monthly training report", "monthly"),
+    };
+    let mut body = String::new();
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Training Report\">");
+    body.push_str(&format!(
+        "<div class=\"results-header\"><div><p class=\"eyebrow\">Training Report</p><h2>{title}</h2></div><div><a class=\"cta\" href=\"/reports/{slug}?format=csv\">Download CSV</a> <a class=\"cta\" href=\"/reports/{slug}?format=json\">Download JSON</a></div></div>"
+    ));
+
+    if report.periods.is_empty() {
+        body.push_str("<p>No processed activities yet. Upload FIT files to build a report.</p>");
+    } else {
+        body.push_str(&table_head(
+            "Totals and heart-rate intensity per period",
+            &[
+                "Period",
+                "Activities",
+                "Distance",
+                "Time",
+                "Elevation Gain",
+                "Easy",
+                "Moderate",
+                "Hard",
+            ],
+        ));
+        body.push_str("<tbody>");
+        for row in &report.periods {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                row.totals.period_start.format("%Y-%m-%d"),
+                row.totals.activity_count,
+                format_distance(Some(row.totals.distance_meters), locale),
+                format_duration(Some(row.totals.duration_seconds)),
+                format_elevation(Some(row.totals.elevation_gain_meters), locale),
+                format_duration(Some(row.intensity.easy_seconds)),
+                format_duration(Some(row.intensity.moderate_seconds)),
+                format_duration(Some(row.intensity.hard_seconds)),
+            ));
+        }
+        body.push_str("</tbody></table></div>");
+    }
+    body.push_str("</section>");
+
+    if !report.personal_records.is_empty() {
+        body.push_str("<section class=\"results-card\" aria-label=\"Personal Records\">");
+        body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">Personal Records</p><h2>All-time bests</h2></div></div>");
+        body.push_str("<div class=\"summary-grid\">");
+        for record in &report.personal_records {
+            let (label, value) = match record.metric {
+                "longest_distance_meters" => (
+                    "Longest Distance",
+                    format_distance(Some(record.value), locale),
+                ),
+                "longest_duration_seconds" => {
+                    ("Longest Duration", format_duration(Some(record.value)))
+                }
+                "fastest_mean_speed" => ("Fastest Mean Pace", format_speed(Some(record.value))),
+                "most_elevation_gain_meters" => (
+                    "Most Elevation Gain",
+                    format_elevation(Some(record.value), locale),
+                ),
+                other => (other, format!("{:.1}", record.value)),
+            };
+            let achieved = record
+                .achieved_on
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| "—".into());
+            body.push_str(&format!(
+                "<div class=\"summary-card\" role=\"group\" aria-label=\"{label}\"><p class=\"label\">{label}</p><p class=\"value\">{value}</p><p class=\"label\">{achieved}</p></div>"
+            ));
+        }
+        body.push_str("</div></section>");
+    }
+
+    render_page(title, &body)
+}
+
+pub fn render_diff_form(csrf_token: &str) -> String {
+    let csrf_token = escape_html(csrf_token);
+    let body = format!(
+        "<section class=\"results-card\" aria-label=\"FIT Diff\"><div class=\"results-header\"><div><p class=\"eyebrow\">FIT Diff</p><h2>Compare two FIT files</h2></div></div>\
+<form method=\"post\" action=\"/diff?csrf_token={csrf_token}\" enctype=\"multipart/form-data\">\
+<p><label>Original file <input type=\"file\" name=\"left\" accept=\".fit\" required /></label></p>\
+<p><label>Modified file <input type=\"file\" name=\"right\" accept=\".fit\" required /></label></p>\
+<p><button class=\"cta\" type=\"submit\">Compare</button></p></form></section>"
+    );
+    render_page("Compare FIT files", &body)
+}
+
+/// Form logging a workout recorded without a device; posts to `/create`.
+pub fn render_create_form(csrf_token: &str) -> String {
+    let csrf_token = escape_html(csrf_token);
+    let sports: String = MANUAL_SPORTS
+        .iter()
+        .map(|sport| {
+            let label = match *sport {
+                "generic" => "Other".to_string(),
+                sport => sport[..1].to_ascii_uppercase() + &sport[1..],
+            };
+            format!("<option value=\"{sport}\">{label}</option>")
+        })
+        .collect();
+    let body = format!(
+        "<section class=\"results-card\" aria-label=\"Create\"><div class=\"results-header\"><div><p class=\"eyebrow\">Create</p><h2>Log a workout without a device</h2></div></div>\
+<form method=\"post\" action=\"/create?csrf_token={csrf_token}\">\
+<p><label>Sport <select name=\"sport\">{sports}</select></label></p>\
+<p><label>Start <input type=\"datetime-local\" name=\"start\" required /></label> \
+<label>UTC offset (minutes) <input type=\"number\" name=\"utc_offset_minutes\" value=\"0\" step=\"15\" /></label></p>\
+<p><label>Duration <input type=\"text\" name=\"duration\" placeholder=\"h:mm:ss\" required /></label> \
+<label>Distance (km) <input type=\"number\" name=\"distance_km\" min=\"0\" step=\"0.01\" /></label></p>\
+<p><button class=\"cta\" type=\"submit\">Download FIT file</button></p></form></section>"
+    );
+    render_page("Create activity", &body)
+}
+
+pub fn render_diff_result(diff: &FitDiff) -> String {
+    let mut body = String::new();
+
+    body.push_str("<section class=\"results-card\" aria-label=\"FIT Diff\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">FIT Diff</p><h2>Message counts</h2></div><a class=\"cta\" href=\"/diff\">Compare other files</a></div>");
+    body.push_str(&table_head(
+        "Message counts in each file",
+        &["Message", "Original", "Modified", "Change"],
+    ));
+    body.push_str("<tbody>");
+    for count in &diff.message_counts {
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:+}</td></tr>",
+            escape_html(&count.message_type),
+            count.left,
+            count.right,
+            count.right as i64 - count.left as i64
+        ));
+    }
+    body.push_str("</tbody></table></div></section>");
+
+    body.push_str("<section class=\"results-card\" aria-label=\"FIT Diff\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">FIT Diff</p><h2>Field presence</h2></div></div>");
+    if diff.field_presence.is_empty() {
+        body.push_str("<p>Both files use the same fields for every message type.</p>");
+    } else {
+        body.push_str(&table_head(
+            "Fields present in only one file",
+            &["Message", "Only in original", "Only in modified"],
+        ));
+        body.push_str("<tbody>");
+        for presence in &diff.field_presence {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&presence.message_type),
+                escape_html(&presence.only_left.join(", ")),
+                escape_html(&presence.only_right.join(", "))
+            ));
+        }
+        body.push_str("</tbody></table></div>");
+    }
+    body.push_str("</section>");
+
+    body.push_str("<section class=\"results-card\" aria-label=\"FIT Diff\">");
+    body.push_str("<div class=\"results-header\"><div><p class=\"eyebrow\">FIT Diff</p><h2>Summary and coverage</h2></div></div>");
+    body.push_str(&table_head(
+        "Summary metrics of each file",
+        &["Metric", "Original", "Modified", "Change"],
+    ));
+    body.push_str("<tbody>");
+    for delta in &diff.summary_deltas {
+        let format_value = |value: Option<f64>| {
+            value
+                .map(|v| format!("{v:.2}"))
+                .unwrap_or_else(|| "—".to_string())
+        };
+        body.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(delta.metric),
+            format_value(delta.left),
+            format_value(delta.right),
+            delta
+                .delta()
+                .map(|d| format!("{d:+.2}"))
+                .unwrap_or_else(|| "—".to_string())
+        ));
+    }
+    body.push_str(&format!(
+        "<tr><td>first_timestamp</td><td>{}</td><td>{}</td><td></td></tr>",
+        format_timestamp(diff.left_coverage.first),
+        format_timestamp(diff.right_coverage.first)
+    ));
+    body.push_str(&format!(
+        "<tr><td>last_timestamp</td><td>{}</td><td>{}</td><td></td></tr>",
+        format_timestamp(diff.left_coverage.last),
+        format_timestamp(diff.right_coverage.last)
+    ));
+    body.push_str(&format!(
+        "<tr><td>timestamped_messages</td><td>{}</td><td>{}</td><td>{:+}</td></tr>",
+        diff.left_coverage.timestamped_messages,
+        diff.right_coverage.timestamped_messages,
+        diff.right_coverage.timestamped_messages as i64
+            - diff.left_coverage.timestamped_messages as i64
+    ));
+    body.push_str("</tbody></table></div></section>");
+
+    render_page("FIT diff", &body)
+}
+
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+pub fn render_admin_dashboard(overview: &AdminOverview, csrf_token: &str) -> String {
+    let mut body = String::new();
+    let total_bytes: usize = overview
+        .downloads
+        .iter()
+        .map(|download| download.size)
+        .sum();
+
+    if let Some(purged) = overview.purged {
+        body.push_str(&format!(
+            "<section class=\"results-card\" role=\"status\"><p>Purged {purged} expired entries.</p></section>"
+        ));
+    }
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Admin\">");
+    body.push_str(&format!(
+        "<div class=\"results-header\"><div><p class=\"eyebrow\">Admin</p><h2>Server state</h2></div><form method=\"post\" action=\"/admin/purge?csrf_token={}\"><button class=\"cta\" type=\"submit\">Purge expired entries</button></form></div>",
+        escape_html(csrf_token)
+    ));
+    body.push_str("<div class=\"summary-grid\">");
+    for (label, value) in [
+        ("Downloads", overview.downloads.len().to_string()),
+        ("Download Memory", format_bytes(total_bytes)),
+        ("Active Jobs", overview.active_jobs.to_string()),
+        ("Cached Uploads", overview.cache.entries.to_string()),
+        ("Cache Hits", overview.cache.hits.to_string()),
+        ("Cache Misses", overview.cache.misses.to_string()),
+    ] {
+        body.push_str(&format!(
+            "<div class=\"summary-card\" role=\"group\" aria-label=\"{label}\"><p class=\"label\">{label}</p><p class=\"value\">{value}</p></div>"
+        ));
+    }
+    body.push_str("</div></section>");
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Downloads\"><div class=\"results-header\"><div><p class=\"eyebrow\">Downloads</p><h2>Stored files</h2></div></div>");
+    if overview.downloads.is_empty() {
+        body.push_str("<p>No downloads in memory.</p>");
+    } else {
+        body.push_str(&table_head(
+            "Stored downloads",
+            &["ID", "Size", "Age", "Status"],
+        ));
+        body.push_str("<tbody>");
+        for download in &overview.downloads {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&download.id),
+                format_bytes(download.size),
+                format_duration(Some(download.age.as_secs_f64())),
+                if download.expired {
+                    "Expired"
+                } else {
+                    "Active"
+                },
+            ));
+        }
+        body.push_str("</tbody></table></div>");
+    }
+    body.push_str("</section>");
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Usage\"><div class=\"results-header\"><div><p class=\"eyebrow\">Usage</p><h2>All time</h2></div></div>");
+    match &overview.usage {
+        None => body.push_str(
+            "<p>Usage counters are off. Start the server with <code>--telemetry</code> (or <code>RUSTYFIT_TELEMETRY=true</code>) to count files, options, and errors.</p>",
+        ),
+        Some(usage) => {
+            body.push_str("<div class=\"summary-grid\">");
+            body.push_str(&summary_card(
+                "Files Processed",
+                &usage.files_processed.to_string(),
+            ));
+            for (kind, count) in &usage.errors {
+                body.push_str(&summary_card(&escape_html(kind), &count.to_string()));
+            }
+            body.push_str("</div>");
+            if !usage.options.is_empty() {
+                let mut options: Vec<_> = usage.options.iter().collect();
+                options.sort_by(|a, b| b.1.cmp(a.1));
+                body.push_str(&table_head("Option use", &["Option", "Files"]));
+                body.push_str("<tbody>");
+                for (name, count) in options {
+                    body.push_str(&format!(
+                        "<tr><td>{}</td><td>{count}</td></tr>",
+                        escape_html(name)
+                    ));
+                }
+                body.push_str("</tbody></table></div>");
+            }
+        }
+    }
+    body.push_str("</section>");
+
+    body.push_str("<section class=\"results-card\" aria-label=\"Errors\"><div class=\"results-header\"><div><p class=\"eyebrow\">Errors</p><h2>Recent failures</h2></div></div>");
+    if overview.error_counts.is_empty() {
+        body.push_str("<p>No errors since startup.</p>");
+    } else {
+        body.push_str("<div class=\"summary-grid\">");
+        for (kind, count) in &overview.error_counts {
+            body.push_str(&format!(
+                "<div class=\"summary-card\" role=\"group\" aria-label=\"{kind}\"><p class=\"label\">{kind}</p><p class=\"value\">{count}</p></div>"
+            ));
+        }
+        body.push_str("</div>");
+        body.push_str(&table_head("Recent failures", &["When", "Kind", "Message"]));
+        body.push_str("<tbody>");
+        for error in &overview.recent_errors {
+            body.push_str(&format!(
+                "<tr><td>{} ago</td><td>{}</td><td>{}</td></tr>",
+                format_duration(Some(error.at.elapsed().as_secs_f64())),
+                error.kind,
+                escape_html(&error.message),
+            ));
+        }
+        body.push_str("</tbody></table></div>");
+    }
+    body.push_str("</section>");
+
+    render_page("Admin", &body)
+}
+
+/// Files processed in the last day and bytes held in downloads, against the
+/// account's quota.
+pub fn render_account_usage(usage: &AccountUsage) -> String {
+    let limit = |limit: Option<String>| limit.unwrap_or_else(|| "unlimited".to_string());
+    let mut body = String::new();
+    body.push_str("<section class=\"results-card\" aria-label=\"Account\">");
+    body.push_str(&format!(
+        "<div class=\"results-header\"><div><p class=\"eyebrow\">Account</p><h2>{}</h2></div></div>",
+        escape_html(&usage.account)
+    ));
+    body.push_str("<div class=\"summary-grid\">");
+    for (label, value, quota) in [
+        (
+            "Files in the Last Day",
+            usage.files_today.to_string(),
+            limit(usage.quota.files_per_day.map(|files| files.to_string())),
+        ),
+        (
+            "Stored Downloads",
+            format_bytes(usage.stored_bytes as usize),
+            limit(
+                usage
+                    .quota
+                    .max_stored_bytes
+                    .map(|bytes| format_bytes(bytes as usize)),
+            ),
+        ),
+    ] {
+        body.push_str(&format!(
+            "<div class=\"summary-card\" role=\"group\" aria-label=\"{label}\"><p class=\"label\">{label}</p><p class=\"value\">{value}</p><p class=\"label\">of {quota}</p></div>"
+        ));
+    }
+    body.push_str("</div><p>Downloads count against storage until they expire, an hour after processing.</p><p><a href=\"/api/v1/account/export\">Download all your data</a> as a zip of activity summaries and processed files.</p></section>");
+
+    render_page("Account", &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_time_applies_offset() {
+        // 2024-01-01 06:30:00 UTC shown in UTC+01:00.
+        let formatted = format_local_time(Some(1_704_090_600.0), Some(3600));
+        assert_eq!(formatted, "2024-01-01 07:30 (UTC+01:00)");
+        assert_eq!(
+            format_local_time(Some(1_704_090_600.0), None),
+            "2024-01-01 06:30:00 UTC"
+        );
+    }
+
+    #[test]
+    fn tables_and_cards_are_labelled() {
+        assert_eq!(
+            table_head("Messages by type", &["Message", "Count"]),
+            "<div class=\"table-wrapper\"><table><caption class=\"visually-hidden\">Messages by type</caption>\
+<thead><tr><th scope=\"col\">Message</th><th scope=\"col\">Count</th></tr></thead>"
+        );
+        assert!(
+            summary_card("Moving Time", "1h 02m 03s").starts_with(
+                "<div class=\"summary-card\" role=\"group\" aria-label=\"Moving Time\">"
+            )
+        );
+    }
+
+    #[test]
+    fn diff_field_names_are_escaped() {
+        use crate::processing::diff::{FieldPresenceDiff, TimestampCoverage};
+
+        let diff = FitDiff {
+            message_counts: Vec::new(),
+            field_presence: vec![FieldPresenceDiff {
+                message_type: "record".to_string(),
+                only_left: vec!["<script>alert(1)</script>".to_string()],
+                only_right: Vec::new(),
+            }],
+            summary_deltas: Vec::new(),
+            left_coverage: TimestampCoverage::default(),
+            right_coverage: TimestampCoverage::default(),
+        };
+
+        let page = render_diff_result(&diff);
+        assert!(!page.contains("<script>alert"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    }
+
+    #[test]
+    fn smoothing_preview_posts_to_the_result_with_its_signature() {
+        let section = render_smoothing_preview("/download/abc?expires=1&signature=xyz");
+        assert!(
+            section.contains(
+                "data-preview-url=\"/api/v1/results/abc/preview?expires=1&signature=xyz\""
+            )
+        );
+        assert!(section.contains(&format!("max=\"{MAX_PREVIEW_WINDOW}\"")));
+    }
+
+    #[test]
+    fn grade_colours_need_no_inline_styles() {
+        use crate::processing::terrain::GradeBucket;
+
+        let terrain = TerrainAnalysis {
+            grades: vec![GradeBucket {
+                label: "Flat",
+                seconds: 60.0,
+                distance_meters: 500.0,
+            }],
+            profile: Vec::new(),
+            surface: None,
+        };
+        let html = render_terrain(&terrain, NumberLocale::default());
+        // The default Content-Security-Policy blocks `style` attributes.
+        assert!(!html.contains("style="));
+        assert!(html.contains(&format!("fill=\"{}\"", GRADE_COLORS[0])));
+    }
+}
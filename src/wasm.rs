@@ -0,0 +1,41 @@
+//! Browser bindings for the processing core, so files can be processed
+//! client-side without being uploaded.
+
+use crate::processing::{self, ProcessingOptions};
+use wasm_bindgen::prelude::*;
+
+/// Result of processing one file in the browser.
+#[wasm_bindgen]
+pub struct WasmProcessedFit {
+    processed_bytes: Vec<u8>,
+    summary_json: String,
+}
+
+#[wasm_bindgen]
+impl WasmProcessedFit {
+    /// Re-encoded FIT payload, ready to be offered as a download.
+    #[wasm_bindgen(getter, js_name = processedBytes)]
+    pub fn processed_bytes(&self) -> Vec<u8> {
+        self.processed_bytes.clone()
+    }
+
+    /// Workout summary and device inventory as a JSON object string.
+    #[wasm_bindgen(getter, js_name = summaryJson)]
+    pub fn summary_json(&self) -> String {
+        self.summary_json.clone()
+    }
+}
+
+/// Process FIT bytes with options given as a JSON object using the
+/// [`ProcessingOptions`] field names; missing fields fall back to defaults.
+#[wasm_bindgen(js_name = processFitBytes)]
+pub fn process_fit_bytes(bytes: &[u8], options_json: &str) -> Result<WasmProcessedFit, JsError> {
+    let options = ProcessingOptions::from_json(options_json)?;
+    let processed = processing::process_fit_bytes(bytes, &options)?;
+    let summary_json = processed.summary_json()?;
+
+    Ok(WasmProcessedFit {
+        processed_bytes: processed.processed_bytes,
+        summary_json,
+    })
+}
@@ -0,0 +1,334 @@
+//! Watch-folder mode for self-hosters: every FIT file dropped into a directory
+//! is processed with a fixed preset and written to an output directory.
+//!
+//! The directory is polled rather than watched through OS notifications, so it
+//! also works on network shares and bind mounts. A file is only picked up once
+//! its size and modification time are unchanged between two polls, so files
+//! that are still being copied in are left alone.
+
+use crate::events::{JobCompleted, JobEvents, JobSource};
+use crate::processing::{ProcessingOptions, process_fit_bytes};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Default time between two scans of the input directory.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Tracing target of watch-folder events, for filtering them separately.
+pub const WATCH_LOG_TARGET: &str = "rustyfit::watch";
+
+/// Directories and preset for the watch-folder mode.
+#[derive(Debug, Clone)]
+pub struct WatchFolder {
+    pub input_dir: PathBuf,
+    /// Receives the processed bytes under the input's file name and the
+    /// summary JSON under that name plus `.json`, so inputs that differ only
+    /// in their extension's case keep separate outputs.
+    pub output_dir: PathBuf,
+    /// Options applied to every file, limits included.
+    pub options: ProcessingOptions,
+    pub interval: Duration,
+    /// Notified of every processed file.
+    pub events: JobEvents,
+}
+
+#[derive(Debug)]
+pub enum WatchError {
+    /// The input and output directories are the same, so outputs would be
+    /// picked up as new inputs.
+    SameDirectory(PathBuf),
+    Io(PathBuf, std::io::Error),
+    /// The preset file is not a valid options object.
+    InvalidPreset(PathBuf, serde_json::Error),
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchError::SameDirectory(dir) => write!(
+                f,
+                "watch output directory must differ from the input directory ({})",
+                dir.display()
+            ),
+            WatchError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            WatchError::InvalidPreset(path, err) => {
+                write!(f, "invalid watch preset {}: {err}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WatchError {}
+
+/// Read a preset from a JSON file using the [`ProcessingOptions`] field names.
+pub fn load_preset(path: &Path) -> Result<ProcessingOptions, WatchError> {
+    let json =
+        std::fs::read_to_string(path).map_err(|err| WatchError::Io(path.to_path_buf(), err))?;
+    ProcessingOptions::from_json(&json)
+        .map_err(|err| WatchError::InvalidPreset(path.to_path_buf(), err))
+}
+
+/// Size and modification time of an input file as of the last poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileVersion {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SeenFile {
+    version: FileVersion,
+    /// Whether this version has been processed (or failed); a new version is
+    /// processed again.
+    handled: bool,
+}
+
+/// Outcome of one poll of the input directory.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PollReport {
+    pub processed: Vec<PathBuf>,
+    pub failed: Vec<PathBuf>,
+}
+
+impl WatchFolder {
+    /// Check the directories, creating the output directory when missing.
+    pub fn prepare(&self) -> Result<(), WatchError> {
+        let input = self
+            .input_dir
+            .canonicalize()
+            .map_err(|err| WatchError::Io(self.input_dir.clone(), err))?;
+        std::fs::create_dir_all(&self.output_dir)
+            .map_err(|err| WatchError::Io(self.output_dir.clone(), err))?;
+        let output = self
+            .output_dir
+            .canonicalize()
+            .map_err(|err| WatchError::Io(self.output_dir.clone(), err))?;
+        if input == output {
+            return Err(WatchError::SameDirectory(input));
+        }
+        Ok(())
+    }
+
+    /// Poll the input directory forever.
+    pub async fn run(self) {
+        tracing::info!(
+            target: WATCH_LOG_TARGET,
+            input_dir = %self.input_dir.display(),
+            output_dir = %self.output_dir.display(),
+            "watching for FIT files"
+        );
+        let mut watcher = Watcher::default();
+        loop {
+            watcher.poll(&self).await;
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
+
+/// Files seen in earlier polls.
+#[derive(Debug, Default)]
+pub struct Watcher {
+    seen: HashMap<PathBuf, SeenFile>,
+}
+
+impl Watcher {
+    /// Process every `.fit` file whose size and modification time have not
+    /// changed since the previous poll and that has not been handled yet.
+    pub async fn poll(&mut self, folder: &WatchFolder) -> PollReport {
+        let mut report = PollReport::default();
+        let mut entries = match tokio::fs::read_dir(&folder.input_dir).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!(
+                    target: WATCH_LOG_TARGET,
+                    input_dir = %folder.input_dir.display(),
+                    error = %err,
+                    "failed to list watch directory"
+                );
+                return report;
+            }
+        };
+
+        let mut present = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if !is_fit_file(&path) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata().await else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            let version = FileVersion {
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            };
+            present.push(path.clone());
+
+            let ready = match self.seen.get(&path) {
+                Some(seen) if seen.version == version => !seen.handled,
+                _ => {
+                    self.seen.insert(
+                        path,
+                        SeenFile {
+                            version,
+                            handled: false,
+                        },
+                    );
+                    continue;
+                }
+            };
+            if !ready {
+                continue;
+            }
+
+            match process_file(&path, folder).await {
+                Ok(()) => report.processed.push(path.clone()),
+                Err(message) => {
+                    tracing::warn!(
+                        target: WATCH_LOG_TARGET,
+                        file = %path.display(),
+                        error = %message,
+                        "failed to process watched file"
+                    );
+                    report.failed.push(path.clone());
+                }
+            }
+            if let Some(seen) = self.seen.get_mut(&path) {
+                seen.handled = true;
+            }
+        }
+
+        // Forget removed files, so one dropped in again under the same name is processed.
+        self.seen.retain(|path, _| present.contains(path));
+        report
+    }
+}
+
+/// Process one input file and write its outputs.
+async fn process_file(path: &Path, folder: &WatchFolder) -> Result<(), String> {
+    // Read rather than mapped: another program may still truncate or rewrite
+    // the file, which would fault a mapping mid-parse.
+    let bytes = tokio::fs::read(path).await.map_err(|err| err.to_string())?;
+    // Processing is CPU-bound; keep it off the async worker threads.
+    let options = folder.options.clone();
+    let processed = tokio::task::spawn_blocking(move || process_fit_bytes(&bytes, &options))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    let summary = processed.summary_json().map_err(|err| err.to_string())?;
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "activity.fit".to_string());
+    let fit_path = folder.output_dir.join(&file_name);
+    let json_path = folder.output_dir.join(format!("{file_name}.json"));
+    tokio::fs::write(&fit_path, &processed.processed_bytes)
+        .await
+        .map_err(|err| format!("{}: {err}", fit_path.display()))?;
+    tokio::fs::write(&json_path, summary)
+        .await
+        .map_err(|err| format!("{}: {err}", json_path.display()))?;
+
+    tracing::info!(
+        target: WATCH_LOG_TARGET,
+        file = %path.display(),
+        output = %fit_path.display(),
+        records = processed.record_count,
+        warnings = processed.warnings.len(),
+        "processed watched file"
+    );
+    folder.events.publish(&JobCompleted::new(
+        &file_name,
+        JobSource::WatchFolder,
+        None,
+        &processed,
+    ));
+    Ok(())
+}
+
+fn is_fit_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("fit"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_folder() -> WatchFolder {
+        let root = std::env::temp_dir().join(format!("rustyfit-watch-{}", uuid::Uuid::new_v4()));
+        let folder = WatchFolder {
+            input_dir: root.join("incoming"),
+            output_dir: root.join("processed"),
+            options: ProcessingOptions::default(),
+            interval: DEFAULT_WATCH_INTERVAL,
+            events: JobEvents::default(),
+        };
+        std::fs::create_dir_all(&folder.input_dir).unwrap();
+        folder.prepare().unwrap();
+        folder
+    }
+
+    #[tokio::test]
+    async fn stable_files_are_processed_once() {
+        let folder = temp_folder();
+        std::fs::copy(
+            "test/fixtures/activity.fit",
+            folder.input_dir.join("ride.fit"),
+        )
+        .unwrap();
+        std::fs::write(folder.input_dir.join("broken.FIT"), b"not a fit file").unwrap();
+        std::fs::write(folder.input_dir.join("notes.txt"), b"ignored").unwrap();
+        let mut watcher = Watcher::default();
+
+        // The first poll only records sizes; files still being copied would change.
+        assert_eq!(watcher.poll(&folder).await, PollReport::default());
+        let report = watcher.poll(&folder).await;
+        assert_eq!(report.processed, vec![folder.input_dir.join("ride.fit")]);
+        assert_eq!(report.failed, vec![folder.input_dir.join("broken.FIT")]);
+        assert!(folder.output_dir.join("ride.fit").exists());
+        assert!(folder.output_dir.join("ride.fit.json").exists());
+        assert_eq!(watcher.poll(&folder).await, PollReport::default());
+
+        std::fs::remove_dir_all(folder.input_dir.parent().unwrap()).unwrap();
+    }
+
+    // Needs a case-sensitive file system to hold both inputs.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn inputs_with_the_same_stem_keep_separate_outputs() {
+        let folder = temp_folder();
+        for name in ["ride.fit", "ride.FIT"] {
+            std::fs::copy("test/fixtures/activity.fit", folder.input_dir.join(name)).unwrap();
+        }
+        let mut watcher = Watcher::default();
+
+        watcher.poll(&folder).await;
+        let report = watcher.poll(&folder).await;
+
+        assert_eq!(report.processed.len(), 2);
+        assert!(folder.output_dir.join("ride.fit.json").exists());
+        assert!(folder.output_dir.join("ride.FIT.json").exists());
+
+        std::fs::remove_dir_all(folder.input_dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn output_directory_must_differ_from_input() {
+        let folder = temp_folder();
+        let same = WatchFolder {
+            output_dir: folder.input_dir.clone(),
+            ..folder.clone()
+        };
+
+        assert!(matches!(same.prepare(), Err(WatchError::SameDirectory(_))));
+
+        std::fs::remove_dir_all(folder.input_dir.parent().unwrap()).unwrap();
+    }
+}
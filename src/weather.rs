@@ -0,0 +1,306 @@
+//! Historical weather at an activity's start, for the `enrich_weather`
+//! option.
+//!
+//! The provider is any Open-Meteo compatible archive API at
+//! `RUSTYFIT_WEATHER_URL`: it is asked for the hourly temperature, relative
+//! humidity, and 10 m wind of the start's day in UTC, and the hour the
+//! activity started in is kept. The start is rounded to about a kilometre
+//! before it is sent, so the provider never sees the exact position, and
+//! answers are cached by that place and hour, so re-uploads and group rides
+//! cost one request. Lookups time out after a few seconds rather than hold
+//! up the upload.
+//! Lookups need the `weather` feature; without it a configured provider is
+//! reported once at startup and lookups fail.
+
+use crate::processing::Weather;
+use std::fmt;
+
+/// Historical weather API to look the start's weather up from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeatherConfig {
+    /// Archive endpoint, e.g. `https://archive-api.open-meteo.com/v1/archive`.
+    pub url: String,
+}
+
+impl WeatherConfig {
+    /// Provider from `RUSTYFIT_WEATHER_URL`; `None` when it is unset.
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("RUSTYFIT_WEATHER_URL").ok()?;
+        let url = url.trim();
+        (!url.is_empty()).then(|| Self {
+            url: url.to_string(),
+        })
+    }
+}
+
+/// Why no weather was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WeatherError {
+    /// No provider is configured, or the server was built without the
+    /// `weather` feature.
+    Unavailable,
+    Request(String),
+    /// The provider's answer could not be read or had no data for the hour.
+    Response(String),
+}
+
+impl fmt::Display for WeatherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeatherError::Unavailable => write!(f, "no weather provider is configured"),
+            WeatherError::Request(msg) => write!(f, "weather request failed: {msg}"),
+            WeatherError::Response(msg) => write!(f, "unexpected weather response: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for WeatherError {}
+
+/// Client for the configured weather provider; every lookup fails with
+/// [`WeatherError::Unavailable`] when none is configured.
+#[derive(Clone, Default)]
+pub struct WeatherService {
+    #[cfg(feature = "weather")]
+    client: Option<provider::WeatherClient>,
+}
+
+impl fmt::Debug for WeatherService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeatherService")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+impl WeatherService {
+    pub fn connect(config: Option<&WeatherConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        #[cfg(feature = "weather")]
+        {
+            Self {
+                client: Some(provider::WeatherClient::new(config.clone())),
+            }
+        }
+        #[cfg(not(feature = "weather"))]
+        {
+            tracing::warn!(
+                provider = %config.url,
+                "RUSTYFIT_WEATHER_URL is set but RustyFit was built without the weather feature"
+            );
+            Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "weather")]
+        {
+            self.client.is_some()
+        }
+        #[cfg(not(feature = "weather"))]
+        {
+            false
+        }
+    }
+
+    /// Weather in the hour containing `timestamp` (seconds since the Unix
+    /// epoch) at `(latitude, longitude)`, in degrees.
+    pub async fn at(&self, position: (f64, f64), timestamp: f64) -> Result<Weather, WeatherError> {
+        #[cfg(feature = "weather")]
+        if let Some(client) = &self.client {
+            return client.at(position, timestamp).await;
+        }
+        #[cfg(not(feature = "weather"))]
+        let _ = (position, timestamp);
+        Err(WeatherError::Unavailable)
+    }
+}
+
+#[cfg(feature = "weather")]
+mod provider {
+    use super::{WeatherConfig, WeatherError};
+    use crate::processing::Weather;
+    use chrono::{DateTime, NaiveDateTime};
+    use moka::future::Cache;
+    use serde::Deserialize;
+    use std::time::Duration;
+
+    /// Places and hours kept; each is one small [`Weather`].
+    const CACHE_CAPACITY: u64 = 100_000;
+
+    /// Historical weather does not change, but providers revise recent hours.
+    const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Places are cached, and sent to the provider, to 0.01 degrees, about a
+    /// kilometre.
+    const CACHE_KEY_SCALE: f64 = 100.0;
+
+    /// Longest wait for the provider to accept a connection.
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Longest wait for a whole lookup, so a stalled provider cannot hold up
+    /// an upload.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    const HOURLY_VARIABLES: &str =
+        "temperature_2m,relative_humidity_2m,wind_speed_10m,wind_direction_10m";
+
+    /// Latitude and longitude in hundredths of a degree, and the hour.
+    type CacheKey = (i32, i32, i64);
+
+    #[derive(Deserialize)]
+    struct ArchiveResponse {
+        hourly: Hourly,
+    }
+
+    #[derive(Deserialize)]
+    struct Hourly {
+        /// UTC hours as `YYYY-MM-DDTHH:MM`.
+        time: Vec<String>,
+        #[serde(default)]
+        temperature_2m: Vec<Option<f64>>,
+        #[serde(default)]
+        relative_humidity_2m: Vec<Option<f64>>,
+        #[serde(default)]
+        wind_speed_10m: Vec<Option<f64>>,
+        #[serde(default)]
+        wind_direction_10m: Vec<Option<f64>>,
+    }
+
+    #[derive(Clone)]
+    pub(super) struct WeatherClient {
+        config: WeatherConfig,
+        http: reqwest::Client,
+        cache: Cache<CacheKey, Weather>,
+    }
+
+    fn cache_key((lat, long): (f64, f64), hour: i64) -> CacheKey {
+        (
+            (lat * CACHE_KEY_SCALE).round() as i32,
+            (long * CACHE_KEY_SCALE).round() as i32,
+            hour,
+        )
+    }
+
+    /// The `hour`th hour since the Unix epoch from an archive answer.
+    fn parse_response(body: &[u8], hour: i64) -> Result<Weather, WeatherError> {
+        let response: ArchiveResponse =
+            serde_json::from_slice(body).map_err(|err| WeatherError::Response(err.to_string()))?;
+        let hourly = response.hourly;
+        let index = hourly
+            .time
+            .iter()
+            .position(|time| {
+                NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M")
+                    .is_ok_and(|time| time.and_utc().timestamp() == hour * 3600)
+            })
+            .ok_or_else(|| WeatherError::Response("no data for the start hour".to_string()))?;
+        let value = |series: &[Option<f64>]| series.get(index).copied().flatten();
+        Ok(Weather {
+            observed_at: (hour * 3600) as f64,
+            temperature_celsius: value(&hourly.temperature_2m),
+            relative_humidity_percent: value(&hourly.relative_humidity_2m),
+            wind_speed: value(&hourly.wind_speed_10m),
+            wind_direction_degrees: value(&hourly.wind_direction_10m),
+        })
+    }
+
+    impl WeatherClient {
+        pub(super) fn new(config: WeatherConfig) -> Self {
+            Self {
+                config,
+                http: reqwest::Client::builder()
+                    .connect_timeout(CONNECT_TIMEOUT)
+                    .timeout(REQUEST_TIMEOUT)
+                    .build()
+                    .expect("the HTTP client builds with the default TLS backend"),
+                cache: Cache::builder()
+                    .max_capacity(CACHE_CAPACITY)
+                    .time_to_live(CACHE_TTL)
+                    .build(),
+            }
+        }
+
+        pub(super) async fn at(
+            &self,
+            (lat, long): (f64, f64),
+            timestamp: f64,
+        ) -> Result<Weather, WeatherError> {
+            let hour = (timestamp / 3600.0).floor() as i64;
+            let key = cache_key((lat, long), hour);
+            if let Some(weather) = self.cache.get(&key).await {
+                return Ok(weather);
+            }
+            let day = DateTime::from_timestamp(hour * 3600, 0)
+                .ok_or_else(|| WeatherError::Request("start time out of range".to_string()))?
+                .date_naive()
+                .to_string();
+            // Only the cached place is sent, never the exact start.
+            let (lat, long) = (
+                format!("{:.2}", f64::from(key.0) / CACHE_KEY_SCALE),
+                format!("{:.2}", f64::from(key.1) / CACHE_KEY_SCALE),
+            );
+            let response = self
+                .http
+                .get(&self.config.url)
+                .query(&[
+                    ("latitude", lat.as_str()),
+                    ("longitude", long.as_str()),
+                    ("start_date", day.as_str()),
+                    ("end_date", day.as_str()),
+                    ("hourly", HOURLY_VARIABLES),
+                    ("timezone", "GMT"),
+                    ("wind_speed_unit", "ms"),
+                ])
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|err| WeatherError::Request(err.to_string()))?;
+            let body = response
+                .bytes()
+                .await
+                .map_err(|err| WeatherError::Request(err.to_string()))?;
+            let weather = parse_response(&body, hour)?;
+            self.cache.insert(key, weather.clone()).await;
+            Ok(weather)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn nearby_starts_share_a_rounded_place() {
+            let hour = 1_714_550_400 / 3600;
+            assert_eq!(cache_key((46.50412, 7.90104), hour), (4650, 790, hour));
+            assert_eq!(
+                cache_key((46.50412, 7.90104), hour),
+                cache_key((46.49781, 7.89660), hour)
+            );
+        }
+
+        #[test]
+        fn picks_the_start_hour_from_the_day() {
+            let body = br#"{"hourly": {
+                "time": ["2024-05-01T07:00", "2024-05-01T08:00"],
+                "temperature_2m": [9.5, 11.2],
+                "relative_humidity_2m": [80, 74],
+                "wind_speed_10m": [3.1, null],
+                "wind_direction_10m": [220, 230]
+            }}"#;
+            // 2024-05-01 08:00 UTC.
+            let hour = 1_714_550_400 / 3600;
+
+            let weather = parse_response(body, hour).unwrap();
+
+            assert_eq!(weather.observed_at, 1_714_550_400.0);
+            assert_eq!(weather.temperature_celsius, Some(11.2));
+            assert_eq!(weather.relative_humidity_percent, Some(74.0));
+            assert_eq!(weather.wind_speed, None);
+            assert_eq!(weather.wind_direction_degrees, Some(230.0));
+            assert!(parse_response(body, hour + 1).is_err());
+        }
+    }
+}
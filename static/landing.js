@@ -0,0 +1,516 @@
+const dropZone = document.getElementById('drop-zone');
+const fileInput = document.getElementById('file-input');
+const processBtn = document.getElementById('process-btn');
+const clearBtn = document.getElementById('clear-btn');
+const fileListEl = document.getElementById('file-list');
+const statusEl = document.getElementById('status');
+const tabsEl = document.getElementById('result-tabs');
+const panelsEl = document.getElementById('result-panels');
+const sharedOptionsEl = document.getElementById('shared-options');
+const bypassCacheCheckbox = document.getElementById('bypass-cache');
+const numberLocaleSelect = document.getElementById('number-locale');
+const inBrowserCheckbox = document.getElementById('in-browser');
+
+// Files waiting to be processed: { file, item, statusEl, optionsEl, customCheckbox, pickerEl }.
+let queue = [];
+
+// Echo the double-submit cookie set by the server with every form post.
+const csrfToken = () => (document.cookie.split('; ').find(c => c.startsWith('rustyfit_csrf=')) || '').split('=')[1] || '';
+
+const escapeHtml = (text) => String(text).replace(/[&<>"']/g, c => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;' }[c]));
+
+const formatSize = (bytes) => bytes >= 1048576 ? (bytes / 1048576).toFixed(1) + ' MiB' : Math.max(1, Math.round(bytes / 1024)) + ' KiB';
+
+const preventDefaults = (e) => { e.preventDefault(); e.stopPropagation(); };
+['dragenter', 'dragover', 'dragleave', 'drop'].forEach(eventName => {
+  dropZone.addEventListener(eventName, preventDefaults, false);
+  document.body.addEventListener(eventName, preventDefaults, false);
+});
+
+['dragenter', 'dragover'].forEach(eventName => {
+  dropZone.addEventListener(eventName, () => dropZone.classList.add('drag'), false);
+});
+['dragleave', 'drop'].forEach(eventName => {
+  dropZone.addEventListener(eventName, () => dropZone.classList.remove('drag'), false);
+});
+
+dropZone.addEventListener('click', () => fileInput.click());
+// The drop zone is a button for keyboard users too.
+dropZone.addEventListener('keydown', (e) => {
+  if (e.key === 'Enter' || e.key === ' ') {
+    e.preventDefault();
+    fileInput.click();
+  }
+});
+
+dropZone.addEventListener('drop', (e) => addFiles(e.dataTransfer.files));
+fileInput.addEventListener('change', (e) => { addFiles(e.target.files); fileInput.value = ''; });
+processBtn.addEventListener('click', processQueue);
+clearBtn.addEventListener('click', () => {
+  queue = [];
+  fileListEl.innerHTML = '';
+  updateButtons();
+});
+
+// Options object using the `ProcessingOptions` field names, read from one options panel.
+function readOptions(container) {
+  const options = { utc_offset_minutes: -new Date().getTimezoneOffset() };
+  container.querySelectorAll('[data-option]').forEach(control => {
+    const name = control.dataset.option;
+    if (control.type === 'checkbox' && control.hasAttribute('value')) {
+      // A checkbox with a value selects that named option, e.g. a profile.
+      options[name] = control.checked ? control.value : null;
+    } else if (control.type === 'checkbox') {
+      options[name] = control.checked;
+    } else if (name === 'utc_offset_minutes') {
+      // Files without a time zone of their own fall back to this browser's.
+      if (control.value) options[name] = Number(control.value);
+    } else if (control.type === 'number' || name === 'resample_interval_seconds') {
+      options[name] = control.value ? Number(control.value) : null;
+    } else {
+      options[name] = control.value || null;
+    }
+  });
+  return options;
+}
+
+function addFiles(files) {
+  Array.from(files || []).forEach(file => {
+    const item = document.createElement('li');
+    item.className = 'file-item';
+    item.innerHTML = '<div class="file-row"><span class="file-name">' + escapeHtml(file.name) + '</span>'
+      + '<span class="label">' + formatSize(file.size) + '</span>'
+      + '<span class="file-status">Queued</span>'
+      + '<label><input type="checkbox" class="custom-toggle" /> Custom options</label>'
+      + '<button type="button" class="secondary fields-btn" aria-expanded="false">Remove fields</button>'
+      + '<button type="button" class="secondary remove-btn">Remove</button></div>'
+      + '<div class="field-picker" hidden></div>';
+    // Start from the shared options so only the differences need changing.
+    const optionsEl = sharedOptionsEl.cloneNode(true);
+    optionsEl.removeAttribute('id');
+    optionsEl.hidden = true;
+    sharedOptionsEl.querySelectorAll('[data-option]').forEach(control => {
+      const copy = optionsEl.querySelector('[data-option="' + control.dataset.option + '"]');
+      if (control.type === 'checkbox') {
+        copy.checked = control.checked;
+      } else {
+        copy.value = control.value;
+      }
+    });
+    item.appendChild(optionsEl);
+    fileListEl.appendChild(item);
+
+    const entry = {
+      file,
+      item,
+      optionsEl,
+      statusEl: item.querySelector('.file-status'),
+      customCheckbox: item.querySelector('.custom-toggle'),
+      pickerEl: item.querySelector('.field-picker'),
+    };
+    entry.customCheckbox.addEventListener('change', () => { optionsEl.hidden = !entry.customCheckbox.checked; });
+    const fieldsBtn = item.querySelector('.fields-btn');
+    fieldsBtn.addEventListener('click', () => {
+      entry.pickerEl.hidden = !entry.pickerEl.hidden;
+      fieldsBtn.setAttribute('aria-expanded', String(!entry.pickerEl.hidden));
+      if (!entry.pickerEl.hidden && !entry.pickerEl.dataset.loaded) {
+        loadFieldPicker(entry);
+      }
+    });
+    item.querySelector('.remove-btn').addEventListener('click', () => {
+      queue = queue.filter(queued => queued !== entry);
+      item.remove();
+      updateButtons();
+    });
+    queue.push(entry);
+  });
+  updateButtons();
+}
+
+function updateButtons() {
+  processBtn.disabled = queue.length === 0;
+  clearBtn.disabled = queue.length === 0;
+}
+
+// Read the file's fields with a dry run on the server and list them as
+// checkboxes by message type; the ticked ones are removed when it is processed.
+async function loadFieldPicker(entry) {
+  entry.pickerEl.dataset.loaded = 'true';
+  entry.pickerEl.textContent = 'Reading fields...';
+  const formData = new FormData();
+  formData.append('file', entry.file);
+  const response = await fetch('/api/v1/fields', { method: 'POST', body: formData });
+  if (!response.ok) {
+    delete entry.pickerEl.dataset.loaded;
+    entry.pickerEl.innerHTML = '<p class="error">Could not read fields: ' + escapeHtml(await response.text()) + '</p>';
+    return;
+  }
+  const messages = await response.json();
+  entry.pickerEl.innerHTML = '<p class="label">Tick the fields to remove from the processed file.</p>'
+    + messages.map(message => '<fieldset><legend>' + escapeHtml(message.message_type) + ' <span class="label">(' + message.count + ')</span></legend>'
+      + message.fields.map(field => '<label><input type="checkbox" class="remove-field" value="' + escapeHtml(message.message_type + '.' + field.name) + '" /> '
+        + escapeHtml(field.name) + (field.developer_data_index === null ? '' : ' <span class="label">(developer field)</span>') + '</label>').join('')
+      + '</fieldset>').join('');
+}
+
+const pickedFields = (entry) => Array.from(entry.pickerEl.querySelectorAll('.remove-field:checked')).map(box => box.value);
+
+// Fields picked for a file are sent with its own options, even when it otherwise uses the shared ones.
+const hasOwnOptions = (entry) => entry.customCheckbox.checked || pickedFields(entry).length > 0;
+
+const optionsFor = (entry) => ({
+  ...readOptions(entry.customCheckbox.checked ? entry.optionsEl : sharedOptionsEl),
+  remove_fields: pickedFields(entry),
+});
+
+function setStatus(entry, text, failed) {
+  entry.statusEl.textContent = text;
+  entry.statusEl.classList.toggle('error', Boolean(failed));
+}
+
+// Show one tab per result; `tabs` is a list of { title, html, failed }.
+// Focus moves to the first tab, so keyboard and screen reader users land on
+// the results once they arrive.
+function showResults(tabs) {
+  tabsEl.innerHTML = '';
+  panelsEl.innerHTML = '';
+  tabs.forEach((tab, index) => {
+    const button = document.createElement('button');
+    button.type = 'button';
+    button.id = 'result-tab-' + index;
+    button.className = 'tab' + (tab.failed ? ' failed' : '');
+    button.setAttribute('role', 'tab');
+    button.setAttribute('aria-controls', 'result-panel-' + index);
+    button.textContent = tab.title;
+    const panel = document.createElement('div');
+    panel.id = 'result-panel-' + index;
+    panel.className = 'tab-panel';
+    panel.tabIndex = 0;
+    panel.setAttribute('role', 'tabpanel');
+    panel.setAttribute('aria-labelledby', button.id);
+    panel.innerHTML = tab.html;
+    button.addEventListener('click', () => selectTab(index));
+    button.addEventListener('keydown', (e) => {
+      const step = { ArrowRight: 1, ArrowLeft: -1 }[e.key];
+      if (step) {
+        const next = (index + step + tabs.length) % tabs.length;
+        selectTab(next);
+        tabsEl.children[next].focus();
+      }
+    });
+    tabsEl.appendChild(button);
+    panelsEl.appendChild(panel);
+  });
+  if (tabs.length > 0) {
+    selectTab(0);
+    tabsEl.children[0].focus();
+  }
+}
+
+function selectTab(selected) {
+  Array.from(tabsEl.children).forEach((tab, index) => {
+    tab.classList.toggle('active', index === selected);
+    tab.setAttribute('aria-selected', String(index === selected));
+    tab.tabIndex = index === selected ? 0 : -1;
+  });
+  Array.from(panelsEl.children).forEach((panel, index) => { panel.hidden = index !== selected; });
+}
+
+async function processQueue() {
+  const entries = queue;
+  if (entries.length === 0) {
+    return;
+  }
+  queue = [];
+  updateButtons();
+  if (inBrowserCheckbox.checked) {
+    await processInBrowser(entries);
+  } else {
+    await uploadEntries(entries);
+  }
+}
+
+function uploadEntries(entries) {
+  const formData = new FormData();
+  const shared = readOptions(sharedOptionsEl);
+  Object.entries(shared).forEach(([name, value]) => formData.append(name, value === null ? '' : String(value)));
+  formData.append('bypass_cache', bypassCacheCheckbox.checked ? 'true' : 'false');
+  formData.append('number_locale', numberLocaleSelect.value);
+  // `file_options` pairs with the file in the same position; empty means shared options.
+  entries.forEach(entry => {
+    formData.append('file', entry.file);
+    formData.append('file_options', hasOwnOptions(entry) ? JSON.stringify(optionsFor(entry)) : '');
+    setStatus(entry, 'Waiting to upload');
+  });
+
+  statusEl.textContent = 'Uploading...';
+  showResults([]);
+  return new Promise(resolve => {
+    const request = new XMLHttpRequest();
+    request.open('POST', '/api/v1/jobs');
+    request.setRequestHeader('X-CSRF-Token', csrfToken());
+    request.upload.addEventListener('progress', (e) => {
+      if (!e.lengthComputable) {
+        return;
+      }
+      const percent = Math.round((e.loaded / e.total) * 100);
+      entries.forEach(entry => setStatus(entry, percent < 100 ? 'Uploading ' + percent + '%' : 'Processing...'));
+    });
+    request.addEventListener('load', () => {
+      if (request.status !== 202) {
+        entries.forEach(entry => setStatus(entry, 'Failed', true));
+        statusEl.innerHTML = '<span class="error">Upload failed: ' + escapeHtml(request.responseText) + '</span>';
+        resolve();
+        return;
+      }
+      const job = JSON.parse(request.responseText);
+      statusEl.textContent = 'Processing...';
+      followJob(entries, job, resolve);
+    });
+    request.addEventListener('error', () => {
+      entries.forEach(entry => setStatus(entry, 'Failed', true));
+      statusEl.innerHTML = '<span class="error">Upload failed: network error</span>';
+      resolve();
+    });
+    request.send(formData);
+  });
+}
+
+const STAGE_LABELS = {
+  parse: 'Parsing',
+  preprocess: 'Applying options',
+  encode: 'Encoding',
+  derive: 'Summarising',
+  display: 'Rendering',
+};
+
+let activeJob = null;
+
+// Leaving the page abandons the upload, so stop processing it on the server.
+window.addEventListener('pagehide', () => {
+  if (activeJob) {
+    fetch(activeJob.status_url, { method: 'DELETE', keepalive: true });
+  }
+});
+
+// Follow a background job's server-sent events until it finishes.
+function followJob(entries, job, done) {
+  activeJob = job;
+  const events = new EventSource(job.events_url);
+  const finish = () => {
+    activeJob = null;
+    events.close();
+    done();
+  };
+  events.addEventListener('message', (e) => {
+    const status = JSON.parse(e.data);
+    if (status.state === 'running') {
+      entries.slice(0, status.file_index).forEach(entry => setStatus(entry, 'Processed'));
+      const progress = status.progress;
+      const percent = progress && progress.total > 0 ? Math.round((progress.completed / progress.total) * 100) : 0;
+      setStatus(entries[status.file_index], progress ? STAGE_LABELS[progress.stage] + ' ' + percent + '%' : 'Processing...');
+      return;
+    }
+    if (status.state === 'completed') {
+      showUploadResults(entries, status.result);
+      finish();
+    } else if (status.state === 'failed') {
+      entries.forEach(entry => setStatus(entry, 'Failed', true));
+      statusEl.innerHTML = '<span class="error">Processing failed: ' + escapeHtml(status.error) + '</span>';
+      finish();
+    } else if (status.state === 'cancelled') {
+      entries.forEach(entry => setStatus(entry, 'Cancelled', true));
+      statusEl.textContent = 'Processing was cancelled';
+      finish();
+    }
+  });
+  events.addEventListener('error', () => {
+    if (events.readyState === EventSource.CLOSED) {
+      entries.forEach(entry => setStatus(entry, 'Failed', true));
+      statusEl.innerHTML = '<span class="error">Lost track of processing; check the job at ' + escapeHtml(job.status_url) + '</span>';
+      activeJob = null;
+      done();
+    }
+  });
+}
+
+function showUploadResults(entries, results) {
+  const tabs = results.files.map((result, index) => {
+    setStatus(entries[index], result.error ? 'Failed' : 'Done', Boolean(result.error));
+    return result.error
+      ? { title: result.file_name, html: '<p class="error">' + escapeHtml(result.error) + '</p>', failed: true }
+      : { title: result.file_name, html: result.html };
+  });
+  if (results.files.length > 1) {
+    tabs.unshift({ title: 'Overview', html: results.aggregate_html });
+  }
+  const failed = results.files.filter(result => result.error).length;
+  statusEl.textContent = failed === 0 ? 'Processed successfully' : (results.files.length - failed) + ' processed, ' + failed + ' failed';
+  showResults(tabs);
+}
+
+let wasmModule = null;
+async function loadWasm() {
+  if (!wasmModule) {
+    const module = await import('/pkg/rustyfit.js');
+    await module.default();
+    wasmModule = module;
+  }
+  return wasmModule;
+}
+
+async function processInBrowser(entries) {
+  statusEl.textContent = 'Processing locally...';
+  showResults([]);
+  let wasm;
+  try {
+    wasm = await loadWasm();
+  } catch (err) {
+    entries.forEach(entry => setStatus(entry, 'Failed', true));
+    statusEl.innerHTML = '<span class="error">In-browser processing is unavailable on this server.</span>';
+    return;
+  }
+  const tabs = [];
+  for (const entry of entries) {
+    setStatus(entry, 'Processing...');
+    try {
+      const bytes = new Uint8Array(await entry.file.arrayBuffer());
+      const result = wasm.processFitBytes(bytes, JSON.stringify(optionsFor(entry)));
+      const { summary, warnings } = JSON.parse(result.summaryJson);
+      const url = URL.createObjectURL(new Blob([result.processedBytes], { type: 'application/octet-stream' }));
+      const cards = Object.entries(summary)
+        .filter(([, value]) => value !== null && typeof value !== 'object')
+        .map(([label, value]) => '<div class="summary-card"><p class="label">' + escapeHtml(label) + '</p><p class="value">' + escapeHtml(typeof value === 'number' ? Math.round(value * 100) / 100 : value) + '</p></div>')
+        .join('');
+      const warningList = warnings.length === 0 ? '' : '<section class="results-card warnings" aria-label="Warnings"><div class="results-header"><div><p class="eyebrow">Warnings</p><h2>Things to check in this file</h2></div></div><ul>'
+        + warnings.map(warning => '<li>' + escapeHtml(warning.message) + '</li>').join('') + '</ul></section>';
+      tabs.push({
+        title: entry.file.name,
+        html: '<section class="results-card" aria-label="Processed locally"><div class="results-header"><div><p class="eyebrow">Processed locally</p><h2>' + escapeHtml(entry.file.name) + '</h2></div>'
+          + '<a class="cta" download="processed.fit" href="' + url + '">Download processed FIT</a></div>'
+          + '<div class="summary-grid">' + cards + '</div></section>' + warningList,
+      });
+      setStatus(entry, 'Done');
+    } catch (err) {
+      tabs.push({ title: entry.file.name, html: '<p class="error">' + escapeHtml(err.message || err) + '</p>', failed: true });
+      setStatus(entry, 'Failed', true);
+    }
+  }
+  showResults(tabs);
+  statusEl.textContent = 'Processed in your browser; nothing was uploaded.';
+}
+
+const rawDetails = (index) => '<details class="raw-message" data-index="' + index + '"><summary>Raw bytes</summary><pre></pre></details>';
+
+// Record explorer: search the stored result server-side and page through matches.
+async function searchRecords(form, offset) {
+  const params = new URLSearchParams();
+  new FormData(form).forEach((value, name) => {
+    if (String(value).trim() !== '') {
+      params.append(name, String(value).trim());
+    }
+  });
+  params.append('offset', String(offset));
+  const section = form.closest('.results-card');
+  const rows = section.querySelector('.record-rows');
+  const heading = section.querySelector('.record-count');
+  const more = form.querySelector('.record-more');
+  const response = await fetch(form.dataset.recordsUrl + '&' + params.toString());
+  if (!response.ok) {
+    heading.textContent = 'Search failed: ' + await response.text();
+    return;
+  }
+  const page = await response.json();
+  rows.innerHTML = page.records.map(record => '<tr><td>' + escapeHtml(record.message_type)
+    + ' #' + record.index + ' <span class="label">(global ' + record.message_number + ')</span></td><td><ul>'
+    + record.fields.map(field => '<li><strong>' + escapeHtml(field.name) + '</strong> <span class="label">#' + field.number + '</span>: ' + escapeHtml(field.value)
+      + (field.developer_data_index === null ? '' : ' <span class="label">(developer field)</span>') + '</li>').join('')
+    + '</ul>' + rawDetails(record.index) + '</td></tr>').join('');
+  const shown = page.offset + page.records.length;
+  heading.textContent = page.total_matches === 0
+    ? 'No matching records'
+    : 'Showing ' + (page.offset + 1) + '–' + shown + ' of ' + page.total_matches + ' matching records';
+  form.dataset.offset = String(shown);
+  more.hidden = shown >= page.total_matches;
+}
+
+panelsEl.addEventListener('submit', (e) => {
+  if (e.target.matches('.record-explorer')) {
+    e.preventDefault();
+    searchRecords(e.target, 0);
+  }
+});
+
+panelsEl.addEventListener('click', (e) => {
+  if (e.target.matches('.record-more')) {
+    const form = e.target.closest('.record-explorer');
+    searchRecords(form, Number(form.dataset.offset || 0));
+  }
+});
+
+// Load a message's bytes the first time its inspector is opened; `toggle` doesn't bubble.
+panelsEl.addEventListener('toggle', async (e) => {
+  const details = e.target;
+  if (!details.matches('.raw-message') || !details.open || details.dataset.loaded) {
+    return;
+  }
+  details.dataset.loaded = 'true';
+  const pre = details.querySelector('pre');
+  const form = details.closest('.results-card').querySelector('.record-explorer');
+  const url = form.dataset.recordsUrl.replace('/records?', '/records/' + details.dataset.index + '/raw?');
+  const response = await fetch(url);
+  if (!response.ok) {
+    pre.textContent = 'Could not load bytes: ' + await response.text();
+    return;
+  }
+  const message = await response.json();
+  pre.textContent = 'Global message ' + message.message_number + ', local type ' + message.local_id + ', ' + message.length + ' bytes at offset ' + message.offset + '\n\n'
+    + message.hex_lines.join('\n') + '\n\n'
+    + message.fields.map(field => field.name + ' (#' + field.number + ') = ' + field.value + (field.units ? ' ' + field.units : '')).join('\n');
+}, true);
+
+// Smoothing preview: redraw raw and smoothed speed for the slider's window.
+async function previewSmoothing(form) {
+  const slider = form.querySelector('.smoothing-window');
+  const note = form.querySelector('.smoothing-residuals');
+  const response = await fetch(form.dataset.previewUrl + '&window=' + slider.value, {
+    method: 'POST',
+    headers: { 'X-CSRF-Token': csrfToken() },
+  });
+  if (!response.ok) {
+    note.textContent = 'Preview failed: ' + await response.text();
+    return;
+  }
+  const preview = await response.json();
+  const points = preview.points;
+  if (points.length < 2) {
+    note.textContent = 'Not enough speed samples to preview.';
+    return;
+  }
+  const start = points[0].timestamp;
+  const end = Math.max(points[points.length - 1].timestamp - start, 1);
+  const high = Math.max(...points.map(point => Math.max(point.raw_speed, point.smoothed_speed)), 0.1);
+  const line = (key) => points.map(point => (30 + (point.timestamp - start) / end * 540).toFixed(1)
+    + ',' + (170 - point[key] / high * 140).toFixed(1)).join(' ');
+  form.querySelector('.raw-speed').setAttribute('points', line('raw_speed'));
+  form.querySelector('.smoothed-speed').setAttribute('points', line('smoothed_speed'));
+  const residuals = preview.residuals;
+  note.textContent = residuals
+    ? 'Window ' + preview.window + ': ' + residuals.altered_samples + ' of ' + residuals.samples
+      + ' intervals moved by more than ' + (residuals.threshold * 3.6).toFixed(1) + ' km/h (RMS '
+      + (residuals.rmse * 3.6).toFixed(2) + ' km/h).'
+    : 'Window ' + preview.window + '.';
+}
+
+panelsEl.addEventListener('input', (e) => {
+  if (e.target.matches('.smoothing-window')) {
+    e.target.closest('.smoothing-preview').querySelector('.smoothing-window-value').textContent = e.target.value;
+  }
+});
+
+// `change` fires once the slider is released, so dragging sends one request.
+panelsEl.addEventListener('change', (e) => {
+  if (e.target.matches('.smoothing-window')) {
+    previewSmoothing(e.target.closest('.smoothing-preview'));
+  }
+});